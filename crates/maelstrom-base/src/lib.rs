@@ -20,6 +20,7 @@ use std::{
     error::Error,
     fmt::{self, Debug, Formatter},
     hash::Hash,
+    net::IpAddr,
     num::NonZeroU32,
     result::Result,
     str::{self, FromStr},
@@ -159,37 +160,6 @@ impl TryFrom<Utf8PathBuf> for NonRootUtf8PathBuf {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "kebab-case")]
-#[serde(deny_unknown_fields)]
-pub enum JobMountForTomlAndJson {
-    Bind {
-        mount_point: NonRootUtf8PathBuf,
-        local_path: Utf8PathBuf,
-        #[serde(default)]
-        read_only: bool,
-    },
-    Devices {
-        devices: EnumSet<JobDeviceForTomlAndJson>,
-    },
-    Devpts {
-        mount_point: NonRootUtf8PathBuf,
-    },
-    Mqueue {
-        mount_point: NonRootUtf8PathBuf,
-    },
-    Proc {
-        mount_point: NonRootUtf8PathBuf,
-    },
-    Sys {
-        mount_point: NonRootUtf8PathBuf,
-    },
-    Tmp {
-        mount_point: NonRootUtf8PathBuf,
-    },
-}
-
 #[pocket_definition(export)]
 #[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub enum JobMount {
@@ -198,12 +168,20 @@ pub enum JobMount {
         local_path: Utf8PathBuf,
         read_only: bool,
     },
+    Device {
+        path: Utf8PathBuf,
+    },
     Devices {
         devices: EnumSet<JobDevice>,
     },
     Devpts {
         mount_point: Utf8PathBuf,
     },
+    Layer {
+        mount_point: Utf8PathBuf,
+        layer: (Sha256Digest, ArtifactType),
+        read_only: bool,
+    },
     Mqueue {
         mount_point: Utf8PathBuf,
     },
@@ -215,41 +193,32 @@ pub enum JobMount {
     },
     Tmp {
         mount_point: Utf8PathBuf,
+        size: Option<u64>,
+        mode: Option<u32>,
+        uid: Option<UserId>,
+        gid: Option<GroupId>,
+        nr_inodes: Option<u64>,
+    },
+    Volume {
+        name: String,
+        mount_point: Utf8PathBuf,
+        size: u64,
+        mode: JobVolumeMode,
     },
 }
 
-impl From<JobMountForTomlAndJson> for JobMount {
-    fn from(job_mount: JobMountForTomlAndJson) -> JobMount {
-        match job_mount {
-            JobMountForTomlAndJson::Bind {
-                mount_point,
-                local_path,
-                read_only,
-            } => JobMount::Bind {
-                mount_point: mount_point.into(),
-                local_path,
-                read_only,
-            },
-            JobMountForTomlAndJson::Devices { devices } => JobMount::Devices {
-                devices: devices.into_iter().map(JobDevice::from).collect(),
-            },
-            JobMountForTomlAndJson::Devpts { mount_point } => JobMount::Devpts {
-                mount_point: mount_point.into(),
-            },
-            JobMountForTomlAndJson::Mqueue { mount_point } => JobMount::Mqueue {
-                mount_point: mount_point.into(),
-            },
-            JobMountForTomlAndJson::Proc { mount_point } => JobMount::Proc {
-                mount_point: mount_point.into(),
-            },
-            JobMountForTomlAndJson::Sys { mount_point } => JobMount::Sys {
-                mount_point: mount_point.into(),
-            },
-            JobMountForTomlAndJson::Tmp { mount_point } => JobMount::Tmp {
-                mount_point: mount_point.into(),
-            },
-        }
-    }
+/// How a [`JobMount::Volume`] may be shared between jobs running concurrently on the same worker.
+#[pocket_definition(export)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobVolumeMode {
+    /// Any number of jobs may use the volume at the same time.
+    #[default]
+    Shared,
+
+    /// Only one job at a time may use the volume. Other jobs that want the volume wait until the
+    /// job using it completes.
+    Exclusive,
 }
 
 #[pocket_definition(export)]
@@ -268,13 +237,215 @@ pub enum JobNetwork {
 pub enum JobRootOverlay {
     #[default]
     None,
-    Tmp,
+    Tmp {
+        size: Option<u64>,
+    },
     Local {
         upper: Utf8PathBuf,
         work: Utf8PathBuf,
     },
 }
 
+/// An extra entry for the `/etc/hosts` file synthesized for a job.
+#[pocket_definition(export)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobHostsEntry {
+    pub address: IpAddr,
+    pub names: Vec<String>,
+}
+
+impl JobHostsEntry {
+    pub fn new<I, T>(address: impl Into<IpAddr>, names: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            address: address.into(),
+            names: names.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A Linux capability, as described in capabilities(7).
+#[pocket_definition(export)]
+#[derive(Debug, Deserialize, EnumIter, EnumSetType, Serialize)]
+pub enum JobCapability {
+    AuditControl,
+    AuditRead,
+    AuditWrite,
+    BlockSuspend,
+    Bpf,
+    CheckpointRestore,
+    Chown,
+    DacOverride,
+    DacReadSearch,
+    Fowner,
+    Fsetid,
+    IpcLock,
+    IpcOwner,
+    Kill,
+    Lease,
+    LinuxImmutable,
+    MacAdmin,
+    MacOverride,
+    Mknod,
+    NetAdmin,
+    NetBindService,
+    NetBroadcast,
+    NetRaw,
+    Perfmon,
+    Setfcap,
+    Setgid,
+    Setpcap,
+    Setuid,
+    Syslog,
+    SysAdmin,
+    SysBoot,
+    SysChroot,
+    SysModule,
+    SysNice,
+    SysPacct,
+    SysPtrace,
+    SysRawio,
+    SysResource,
+    SysTime,
+    SysTtyConfig,
+    WakeAlarm,
+}
+
+#[derive(Debug, Deserialize, EnumSetType, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[enumset(serialize_repr = "list")]
+pub enum JobCapabilityForTomlAndJson {
+    AuditControl,
+    AuditRead,
+    AuditWrite,
+    BlockSuspend,
+    Bpf,
+    CheckpointRestore,
+    Chown,
+    DacOverride,
+    DacReadSearch,
+    Fowner,
+    Fsetid,
+    IpcLock,
+    IpcOwner,
+    Kill,
+    Lease,
+    LinuxImmutable,
+    MacAdmin,
+    MacOverride,
+    Mknod,
+    NetAdmin,
+    NetBindService,
+    NetBroadcast,
+    NetRaw,
+    Perfmon,
+    Setfcap,
+    Setgid,
+    Setpcap,
+    Setuid,
+    Syslog,
+    SysAdmin,
+    SysBoot,
+    SysChroot,
+    SysModule,
+    SysNice,
+    SysPacct,
+    SysPtrace,
+    SysRawio,
+    SysResource,
+    SysTime,
+    SysTtyConfig,
+    WakeAlarm,
+}
+
+impl From<JobCapabilityForTomlAndJson> for JobCapability {
+    fn from(value: JobCapabilityForTomlAndJson) -> JobCapability {
+        match value {
+            JobCapabilityForTomlAndJson::AuditControl => JobCapability::AuditControl,
+            JobCapabilityForTomlAndJson::AuditRead => JobCapability::AuditRead,
+            JobCapabilityForTomlAndJson::AuditWrite => JobCapability::AuditWrite,
+            JobCapabilityForTomlAndJson::BlockSuspend => JobCapability::BlockSuspend,
+            JobCapabilityForTomlAndJson::Bpf => JobCapability::Bpf,
+            JobCapabilityForTomlAndJson::CheckpointRestore => JobCapability::CheckpointRestore,
+            JobCapabilityForTomlAndJson::Chown => JobCapability::Chown,
+            JobCapabilityForTomlAndJson::DacOverride => JobCapability::DacOverride,
+            JobCapabilityForTomlAndJson::DacReadSearch => JobCapability::DacReadSearch,
+            JobCapabilityForTomlAndJson::Fowner => JobCapability::Fowner,
+            JobCapabilityForTomlAndJson::Fsetid => JobCapability::Fsetid,
+            JobCapabilityForTomlAndJson::IpcLock => JobCapability::IpcLock,
+            JobCapabilityForTomlAndJson::IpcOwner => JobCapability::IpcOwner,
+            JobCapabilityForTomlAndJson::Kill => JobCapability::Kill,
+            JobCapabilityForTomlAndJson::Lease => JobCapability::Lease,
+            JobCapabilityForTomlAndJson::LinuxImmutable => JobCapability::LinuxImmutable,
+            JobCapabilityForTomlAndJson::MacAdmin => JobCapability::MacAdmin,
+            JobCapabilityForTomlAndJson::MacOverride => JobCapability::MacOverride,
+            JobCapabilityForTomlAndJson::Mknod => JobCapability::Mknod,
+            JobCapabilityForTomlAndJson::NetAdmin => JobCapability::NetAdmin,
+            JobCapabilityForTomlAndJson::NetBindService => JobCapability::NetBindService,
+            JobCapabilityForTomlAndJson::NetBroadcast => JobCapability::NetBroadcast,
+            JobCapabilityForTomlAndJson::NetRaw => JobCapability::NetRaw,
+            JobCapabilityForTomlAndJson::Perfmon => JobCapability::Perfmon,
+            JobCapabilityForTomlAndJson::Setfcap => JobCapability::Setfcap,
+            JobCapabilityForTomlAndJson::Setgid => JobCapability::Setgid,
+            JobCapabilityForTomlAndJson::Setpcap => JobCapability::Setpcap,
+            JobCapabilityForTomlAndJson::Setuid => JobCapability::Setuid,
+            JobCapabilityForTomlAndJson::Syslog => JobCapability::Syslog,
+            JobCapabilityForTomlAndJson::SysAdmin => JobCapability::SysAdmin,
+            JobCapabilityForTomlAndJson::SysBoot => JobCapability::SysBoot,
+            JobCapabilityForTomlAndJson::SysChroot => JobCapability::SysChroot,
+            JobCapabilityForTomlAndJson::SysModule => JobCapability::SysModule,
+            JobCapabilityForTomlAndJson::SysNice => JobCapability::SysNice,
+            JobCapabilityForTomlAndJson::SysPacct => JobCapability::SysPacct,
+            JobCapabilityForTomlAndJson::SysPtrace => JobCapability::SysPtrace,
+            JobCapabilityForTomlAndJson::SysRawio => JobCapability::SysRawio,
+            JobCapabilityForTomlAndJson::SysResource => JobCapability::SysResource,
+            JobCapabilityForTomlAndJson::SysTime => JobCapability::SysTime,
+            JobCapabilityForTomlAndJson::SysTtyConfig => JobCapability::SysTtyConfig,
+            JobCapabilityForTomlAndJson::WakeAlarm => JobCapability::WakeAlarm,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobCapabilitiesForTomlAndJson {
+    #[serde(default)]
+    pub add: EnumSet<JobCapabilityForTomlAndJson>,
+    #[serde(default)]
+    pub drop: EnumSet<JobCapabilityForTomlAndJson>,
+}
+
+/// Capabilities to add to or drop from the default set for a job. By default, a job running as
+/// root in its user namespace gets all capabilities, and a job running as any other user gets none.
+/// A capability that is both added and dropped is dropped.
+#[pocket_definition(export)]
+#[derive(
+    Copy, Clone, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct JobCapabilities {
+    pub add: EnumSet<JobCapability>,
+    pub drop: EnumSet<JobCapability>,
+}
+
+impl JobCapabilities {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.drop.is_empty()
+    }
+}
+
+impl From<JobCapabilitiesForTomlAndJson> for JobCapabilities {
+    fn from(value: JobCapabilitiesForTomlAndJson) -> JobCapabilities {
+        JobCapabilities {
+            add: value.add.into_iter().map(JobCapability::from).collect(),
+            drop: value.drop.into_iter().map(JobCapability::from).collect(),
+        }
+    }
+}
+
 /// ID of a user. This should be compatible with uid_t.
 #[pocket_definition(export)]
 #[derive(
@@ -367,13 +538,27 @@ impl WindowSize {
     }
 }
 
+/// Where the worker should connect a job's TTY.
+#[pocket_definition(export)]
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum JobTtyAddress {
+    /// A Unix domain socket abstract address. We use exactly 6 bytes because that's how many bytes
+    /// the autobind feature in Linux uses. The first byte will always be 0. Jobs with this kind of
+    /// TTY must be run on the local worker.
+    Local([u8; 6]),
+
+    /// A random 128-bit key identifying a TTY relay on the broker. The worker connects to the
+    /// broker and presents this key, and the broker pairs the connection with the client's
+    /// connection using the same key.
+    Relay([u8; 16]),
+}
+
 /// The parameters for a TTY for a job.
 #[pocket_definition(export)]
 #[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct JobTty {
-    /// A Unix domain socket abstract address. We use exactly 6 bytes because that's how many bytes
-    /// the autobind feature in Linux uses. The first byte will always be 0.
-    pub socket_address: [u8; 6],
+    /// Where to connect the TTY.
+    pub address: JobTtyAddress,
 
     /// The initial window size of the TTY. Window size updates may follow.
     pub window_size: WindowSize,
@@ -381,14 +566,183 @@ pub struct JobTty {
 
 impl JobTty {
     pub fn new(socket_address: &[u8; 6], window_size: WindowSize) -> Self {
-        let socket_address = *socket_address;
         Self {
-            socket_address,
+            address: JobTtyAddress::Local(*socket_address),
+            window_size,
+        }
+    }
+
+    pub fn relay(key: [u8; 16], window_size: WindowSize) -> Self {
+        Self {
+            address: JobTtyAddress::Relay(key),
             window_size,
         }
     }
 }
 
+/// A helper process that runs alongside a job's main program, in the same network namespace but
+/// in its own root file system. Sidecars are started before the main program and are killed when
+/// it exits.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct JobSidecar {
+    pub program: Utf8PathBuf,
+    pub arguments: Vec<String>,
+    pub environment: Vec<String>,
+    pub layers: NonEmpty<(Sha256Digest, ArtifactType)>,
+    pub working_directory: Option<Utf8PathBuf>,
+
+    /// If set, the main program isn't started until something accepts TCP connections on this
+    /// port on the loopback interface.
+    pub ready_port: Option<u16>,
+
+    /// How long to wait for the sidecar to accept connections on `ready_port` before failing the
+    /// job. If not set, [`JobSidecar::DEFAULT_READY_TIMEOUT`] is used.
+    pub ready_timeout: Option<Timeout>,
+}
+
+impl JobSidecar {
+    /// The time allowed for a sidecar to become ready if it doesn't have a `ready_timeout`. It's
+    /// never unlimited, since the worker doesn't start timing the job until the sidecars are ready.
+    pub const DEFAULT_READY_TIMEOUT: Timeout = match NonZeroU32::new(60) {
+        Some(timeout) => Timeout(timeout),
+        None => unreachable!(),
+    };
+
+    pub fn new(
+        program: impl Into<String>,
+        layers: impl Into<NonEmpty<(Sha256Digest, ArtifactType)>>,
+    ) -> Self {
+        JobSidecar {
+            program: program.into().into(),
+            layers: layers.into(),
+            arguments: Default::default(),
+            environment: Default::default(),
+            working_directory: Default::default(),
+            ready_port: Default::default(),
+            ready_timeout: Default::default(),
+        }
+    }
+
+    pub fn arguments<I, T>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.arguments = arguments.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn environment<I, T>(mut self, environment: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.environment = environment.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn working_directory(mut self, working_directory: Option<impl Into<Utf8PathBuf>>) -> Self {
+        self.working_directory = working_directory.map(Into::into);
+        self
+    }
+
+    pub fn ready_port(mut self, ready_port: Option<u16>) -> Self {
+        self.ready_port = ready_port;
+        self
+    }
+
+    pub fn ready_timeout(mut self, ready_timeout: Option<impl Into<Timeout>>) -> Self {
+        self.ready_timeout = ready_timeout.map(Into::into);
+        self
+    }
+}
+
+/// The value of a secret. Its [`Debug`] implementation doesn't show the value, so that secrets
+/// don't end up in log messages.
+#[derive(Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct JobSecretValue(Vec<u8>);
+
+impl JobSecretValue {
+    pub fn new(value: impl Into<Vec<u8>>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Debug for JobSecretValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// How a secret is exposed to a job.
+#[pocket_definition(export)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum JobSecretDestination {
+    /// An environment variable with the given name.
+    Environment(String),
+
+    /// A file at the given path. The file's contents live in a tmpfs, and are bind mounted over a
+    /// file that must already exist in the container.
+    File(Utf8PathBuf),
+}
+
+/// Where the worker fetches a job's secret values from when the job starts. The values never
+/// travel in the [`JobSpec`], and so never pass through the broker's scheduler or its logs.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum JobSecretsAddress {
+    /// A Unix domain socket abstract address that the client is listening on. Like
+    /// [`JobTtyAddress::Local`], it's exactly 6 bytes. Jobs with this kind of address must be run
+    /// on the local worker.
+    Local([u8; 6]),
+
+    /// A random 128-bit key identifying a relay on the broker. The worker connects to the broker
+    /// and presents this key, and the broker pairs the connection with the client's connection
+    /// using the same key.
+    Relay([u8; 16]),
+}
+
+/// The secrets for a job. Only the destinations are given here. The values are sent by the client,
+/// in the same order, as a `Vec<JobSecretValue>` over a connection to `address`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct JobSecrets {
+    pub address: JobSecretsAddress,
+    pub destinations: Vec<JobSecretDestination>,
+}
+
+impl JobSecrets {
+    pub fn new(
+        address: JobSecretsAddress,
+        destinations: impl IntoIterator<Item = JobSecretDestination>,
+    ) -> Self {
+        Self {
+            address,
+            destinations: destinations.into_iter().collect(),
+        }
+    }
+}
+
+/// A secret for a job, along with its value. These are only put together by the worker, once it
+/// has fetched the values.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct JobSecret {
+    pub destination: JobSecretDestination,
+    pub value: JobSecretValue,
+}
+
+impl JobSecret {
+    pub fn new(destination: JobSecretDestination, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            destination,
+            value: JobSecretValue::new(value),
+        }
+    }
+}
+
 /// All necessary information for the worker to execute a job.
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub struct JobSpec {
@@ -402,10 +756,18 @@ pub struct JobSpec {
     pub working_directory: Option<Utf8PathBuf>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
+    pub groups: Vec<GroupId>,
+    pub synthesize_user_files: bool,
+    pub hostname: Option<String>,
+    pub hosts: Vec<JobHostsEntry>,
+    pub capabilities: JobCapabilities,
+    pub init: bool,
     pub timeout: Option<Timeout>,
     pub estimated_duration: Option<Duration>,
     pub allocate_tty: Option<JobTty>,
     pub priority: i8,
+    pub sidecars: Vec<JobSidecar>,
+    pub secrets: Option<JobSecrets>,
 }
 
 impl JobSpec {
@@ -424,10 +786,18 @@ impl JobSpec {
             working_directory: Default::default(),
             user: Default::default(),
             group: Default::default(),
+            groups: Default::default(),
+            synthesize_user_files: Default::default(),
+            hostname: Default::default(),
+            hosts: Default::default(),
+            capabilities: Default::default(),
+            init: Default::default(),
             timeout: Default::default(),
             estimated_duration: Default::default(),
             allocate_tty: Default::default(),
             priority: Default::default(),
+            sidecars: Default::default(),
+            secrets: Default::default(),
         }
     }
 
@@ -479,6 +849,40 @@ impl JobSpec {
         self
     }
 
+    pub fn groups<I, T>(mut self, groups: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<GroupId>,
+    {
+        self.groups = groups.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn synthesize_user_files(mut self, synthesize_user_files: bool) -> Self {
+        self.synthesize_user_files = synthesize_user_files;
+        self
+    }
+
+    pub fn hostname(mut self, hostname: Option<impl Into<String>>) -> Self {
+        self.hostname = hostname.map(Into::into);
+        self
+    }
+
+    pub fn hosts(mut self, hosts: impl IntoIterator<Item = JobHostsEntry>) -> Self {
+        self.hosts = hosts.into_iter().collect();
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: JobCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn init(mut self, init: bool) -> Self {
+        self.init = init;
+        self
+    }
+
     pub fn timeout(mut self, timeout: Option<impl Into<Timeout>>) -> Self {
         self.timeout = timeout.map(Into::into);
         self
@@ -499,6 +903,16 @@ impl JobSpec {
         self
     }
 
+    pub fn sidecars(mut self, sidecars: impl IntoIterator<Item = JobSidecar>) -> Self {
+        self.sidecars = sidecars.into_iter().collect();
+        self
+    }
+
+    pub fn secrets(mut self, secrets: Option<JobSecrets>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
     pub fn must_be_run_locally(&self) -> bool {
         self.network == JobNetwork::Local
             || self
@@ -506,7 +920,71 @@ impl JobSpec {
                 .iter()
                 .any(|mount| matches!(mount, JobMount::Bind { .. }))
             || matches!(&self.root_overlay, JobRootOverlay::Local { .. })
-            || self.allocate_tty.is_some()
+            || matches!(
+                &self.allocate_tty,
+                Some(JobTty {
+                    address: JobTtyAddress::Local(_),
+                    ..
+                })
+            )
+            || matches!(
+                &self.secrets,
+                Some(JobSecrets {
+                    address: JobSecretsAddress::Local(_),
+                    ..
+                })
+            )
+    }
+
+    /// The keys of the relays on the broker that the job's worker will connect to, if any.
+    pub fn relay_keys(&self) -> impl Iterator<Item = [u8; 16]> + '_ {
+        let tty = self.allocate_tty.and_then(|tty| match tty.address {
+            JobTtyAddress::Relay(key) => Some(key),
+            JobTtyAddress::Local(_) => None,
+        });
+        let secrets = self
+            .secrets
+            .as_ref()
+            .and_then(|secrets| match secrets.address {
+                JobSecretsAddress::Relay(key) => Some(key),
+                JobSecretsAddress::Local(_) => None,
+            });
+        tty.into_iter().chain(secrets)
+    }
+
+    /// The layers that need to be mounted separately from the root file system, in mount order:
+    /// first the layer for each [`JobMount::Layer`] mount, then the layers of each sidecar.
+    pub fn mount_layers(&self) -> impl Iterator<Item = &(Sha256Digest, ArtifactType)> {
+        self.mounts
+            .iter()
+            .filter_map(|mount| match mount {
+                JobMount::Layer { layer, .. } => Some(layer),
+                _ => None,
+            })
+            .chain(
+                self.sidecars
+                    .iter()
+                    .flat_map(|sidecar| sidecar.layers.iter()),
+            )
+    }
+
+    /// The host device paths of the [`JobMount::Device`] mounts. A job with any of these can only
+    /// be run on a worker that allows all of them.
+    pub fn host_devices(&self) -> impl Iterator<Item = &Utf8PathBuf> {
+        self.mounts.iter().filter_map(|mount| match mount {
+            JobMount::Device { path } => Some(path),
+            _ => None,
+        })
+    }
+
+    /// The [`JobMount::Volume`] mounts, in mount order.
+    pub fn volumes(&self) -> impl Iterator<Item = (&str, u64, JobVolumeMode)> {
+        self.mounts.iter().filter_map(|mount| match mount {
+            JobMount::Volume {
+                name, size, mode, ..
+            } => Some((name.as_str(), *size, *mode)),
+            _ => None,
+        })
     }
 }
 
@@ -795,7 +1273,7 @@ impl Error for Sha256DigestVerificationError {}
 mod tests {
     use super::*;
     use enumset::enum_set;
-    use heck::ToKebabCase;
+    use heck::{ToKebabCase, ToShoutySnakeCase};
     use strum::IntoEnumIterator as _;
 
     #[test]
@@ -1005,7 +1483,7 @@ mod tests {
         let spec = spec.root_overlay(JobRootOverlay::None);
         assert_eq!(spec.must_be_run_locally(), false);
 
-        let spec = spec.root_overlay(JobRootOverlay::Tmp);
+        let spec = spec.root_overlay(JobRootOverlay::Tmp { size: None });
         assert_eq!(spec.must_be_run_locally(), false);
 
         let spec = spec.root_overlay(JobRootOverlay::Local {
@@ -1026,10 +1504,91 @@ mod tests {
         let spec = spec.allocate_tty(Some(JobTty::new(b"\0abcde", WindowSize::new(20, 80))));
         assert_eq!(spec.must_be_run_locally(), true);
 
+        let spec = spec.allocate_tty(Some(JobTty::relay([42; 16], WindowSize::new(20, 80))));
+        assert_eq!(spec.must_be_run_locally(), false);
+
         let spec = spec.allocate_tty(None::<JobTty>);
         assert_eq!(spec.must_be_run_locally(), false);
     }
 
+    #[test]
+    fn job_spec_must_be_run_locally_secrets() {
+        let destinations = || [JobSecretDestination::Environment("TOKEN".into())];
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.must_be_run_locally(), false);
+
+        let address = JobSecretsAddress::Local(*b"\0abcde");
+        let spec = spec.secrets(Some(JobSecrets::new(address, destinations())));
+        assert_eq!(spec.must_be_run_locally(), true);
+
+        let address = JobSecretsAddress::Relay([42; 16]);
+        let spec = spec.secrets(Some(JobSecrets::new(address, destinations())));
+        assert_eq!(spec.must_be_run_locally(), false);
+    }
+
+    #[test]
+    fn job_spec_relay_keys() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(Vec::from_iter(spec.relay_keys()), Vec::<[u8; 16]>::new());
+
+        let spec = spec
+            .allocate_tty(Some(JobTty::relay([1; 16], WindowSize::new(20, 80))))
+            .secrets(Some(JobSecrets::new(JobSecretsAddress::Relay([2; 16]), [])));
+        assert_eq!(Vec::from_iter(spec.relay_keys()), vec![[1; 16], [2; 16]]);
+
+        let spec = spec.allocate_tty(Some(JobTty::new(b"\0abcde", WindowSize::new(20, 80))));
+        assert_eq!(Vec::from_iter(spec.relay_keys()), vec![[2; 16]]);
+    }
+
+    #[test]
+    fn job_spec_mount_layers_includes_sidecar_layers() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([JobMount::Layer {
+            mount_point: Utf8PathBuf::from("/layer"),
+            layer: (Sha256Digest::from(1u32), ArtifactType::Tar),
+            read_only: true,
+        }])
+        .sidecars([
+            JobSidecar::new(
+                "bar",
+                nonempty![
+                    (Sha256Digest::from(2u32), ArtifactType::Tar),
+                    (Sha256Digest::from(3u32), ArtifactType::Manifest)
+                ],
+            ),
+            JobSidecar::new(
+                "baz",
+                nonempty![(Sha256Digest::from(4u32), ArtifactType::Tar)],
+            ),
+        ]);
+        assert_eq!(
+            Vec::from_iter(spec.mount_layers().cloned()),
+            vec![
+                (Sha256Digest::from(1u32), ArtifactType::Tar),
+                (Sha256Digest::from(2u32), ArtifactType::Tar),
+                (Sha256Digest::from(3u32), ArtifactType::Manifest),
+                (Sha256Digest::from(4u32), ArtifactType::Tar),
+            ]
+        );
+    }
+
+    #[test]
+    fn job_secret_value_debug_is_redacted() {
+        let secret = JobSecret::new(JobSecretDestination::Environment("TOKEN".into()), "hunter2");
+        let debug = format!("{secret:?}");
+        assert!(!debug.contains("hunter2"), "{debug}");
+        assert!(debug.contains("TOKEN"), "{debug}");
+    }
+
     trait AssertError {
         fn assert_error(&self, expected: &str);
     }
@@ -1077,17 +1636,48 @@ mod tests {
     }
 
     #[test]
-    fn bind_mount_no_read_only() {
-        let job_mount: JobMountForTomlAndJson =
-            deserialize_value(r#"{ type = "bind", mount_point = "/mnt", local_path = "/a" }"#);
-        let job_mount: JobMount = job_mount.into();
+    fn job_capabilities_for_toml_and_json() {
+        let capabilities: JobCapabilitiesForTomlAndJson =
+            deserialize_value(r#"{ add = ["NET_RAW"], drop = ["SYS_ADMIN", "MKNOD"] }"#);
         assert_eq!(
-            job_mount,
-            JobMount::Bind {
-                mount_point: Utf8PathBuf::from("/mnt"),
-                local_path: Utf8PathBuf::from("/a"),
-                read_only: false,
+            JobCapabilities::from(capabilities),
+            JobCapabilities {
+                add: enum_set!(JobCapability::NetRaw),
+                drop: enum_set!(JobCapability::SysAdmin | JobCapability::Mknod),
             }
         );
     }
+
+    #[test]
+    fn job_capabilities_for_toml_and_json_defaults() {
+        let capabilities: JobCapabilitiesForTomlAndJson =
+            deserialize_value(r#"{ add = ["KILL"] }"#);
+        assert_eq!(
+            JobCapabilities::from(capabilities),
+            JobCapabilities {
+                add: enum_set!(JobCapability::Kill),
+                drop: EnumSet::empty(),
+            }
+        );
+    }
+
+    #[test]
+    fn job_capabilities_for_toml_and_json_unknown_capability() {
+        deserialize_value_error::<JobCapabilitiesForTomlAndJson>(r#"{ add = ["CAP_NET_RAW"] }"#)
+            .assert_error("unknown variant `CAP_NET_RAW`");
+    }
+
+    #[test]
+    fn job_capability_for_toml_and_json_and_job_capability_match() {
+        for job_capability in JobCapability::iter() {
+            let repr = format!(
+                r#""{}""#,
+                format!("{job_capability:?}").to_shouty_snake_case()
+            );
+            assert_eq!(
+                JobCapability::from(deserialize_value::<JobCapabilityForTomlAndJson>(&repr)),
+                job_capability
+            );
+        }
+    }
 }
@@ -1,7 +1,7 @@
-use crate::{Sha256Digest, Utf8PathBuf};
+use crate::{GroupId, Sha256Digest, UserId, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
 struct OctalFmt<T>(T);
 
@@ -23,6 +23,14 @@ impl fmt::Debug for Mode {
     }
 }
 
+impl Mode {
+    /// This mode with its permission bits, including the setuid, setgid, and sticky bits, replaced
+    /// by those of `permissions`.
+    pub fn with_permissions(self, permissions: Mode) -> Self {
+        Self(self.0 & !0o7777 | permissions.0 & 0o7777)
+    }
+}
+
 impl From<Mode> for u32 {
     fn from(m: Mode) -> u32 {
         m.0
@@ -34,6 +42,18 @@ fn mode_fmt() {
     assert_eq!(format!("{:?}", Mode(0o1755)), "Mode(1755)");
 }
 
+#[test]
+fn mode_with_permissions() {
+    assert_eq!(
+        Mode(0o100644).with_permissions(Mode(0o4755)),
+        Mode(0o104755)
+    );
+    assert_eq!(
+        Mode(0o100644).with_permissions(Mode(0o170600)),
+        Mode(0o100600)
+    );
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct UnixTimestamp(pub i64);
 
@@ -57,11 +77,32 @@ impl From<UnixTimestamp> for std::time::SystemTime {
     }
 }
 
+/// Extended attributes of a file, keyed by their full name (like `user.comment` or
+/// `security.capability`).
+pub type Xattrs = BTreeMap<String, Vec<u8>>;
+
+/// The user and group that own a file, as ids in the job's user namespace.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Owner {
+    pub user: UserId,
+    pub group: GroupId,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ManifestEntryMetadata {
     pub size: u64,
     pub mode: Mode,
     pub mtime: UnixTimestamp,
+    pub xattrs: Xattrs,
+    /// Files without an owner are owned by the job's user and group.
+    pub owner: Option<Owner>,
+}
+
+/// One piece of a file whose contents are split into content-defined chunks.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ManifestChunk {
+    pub digest: Sha256Digest,
+    pub size: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -69,6 +110,23 @@ pub enum ManifestFileData {
     Digest(Sha256Digest),
     Inline(Vec<u8>),
     Empty,
+    /// The file's contents are the concatenation of the given chunks, each stored as its own
+    /// artifact. Files that only differ in part of their contents share most of their chunks.
+    Chunked(Vec<ManifestChunk>),
+}
+
+impl ManifestFileData {
+    /// The digests of the artifacts holding the file's contents.
+    pub fn digests(&self) -> impl Iterator<Item = &Sha256Digest> {
+        let (digest, chunks) = match self {
+            Self::Digest(digest) => (Some(digest), &[][..]),
+            Self::Chunked(chunks) => (None, &chunks[..]),
+            Self::Inline(_) | Self::Empty => (None, &[][..]),
+        };
+        digest
+            .into_iter()
+            .chain(chunks.iter().map(|chunk| &chunk.digest))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -91,6 +149,12 @@ pub struct ManifestEntry {
 #[repr(u32)]
 pub enum ManifestVersion {
     V0 = 0,
-    #[default]
     V1 = 1,
+    /// Entries carry extended attributes.
+    V2 = 2,
+    /// Files may be split into chunks.
+    V3 = 3,
+    /// Entries may have an owner.
+    #[default]
+    V4 = 4,
 }
@@ -2,20 +2,37 @@
 
 use crate::{
     stats::BrokerStatistics, ClientJobId, JobBrokerStatus, JobId, JobOutcomeResult, JobSpec,
-    JobWorkerStatus, Sha256Digest,
+    JobWorkerStatus, Sha256Digest, Utf8PathBuf,
 };
 use bincode::Options;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
 /// The first message sent by a connector to the broker. It identifies what the connector is, and
 /// provides any relevant information.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Hello {
     Client,
-    Worker { slots: u32 },
+    Worker {
+        slots: u32,
+        devices: BTreeSet<Utf8PathBuf>,
+    },
     Monitor,
     ArtifactPusher,
     ArtifactFetcher,
+    Relay {
+        key: [u8; 16],
+        side: RelaySide,
+    },
+}
+
+/// Which end of a relay a [`Hello::Relay`] connection is. The broker pairs a job connection with
+/// the client connection that has the same key, then copies bytes between the two. Relays carry
+/// jobs' TTYs and deliver their secrets.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum RelaySide {
+    Job,
+    Client,
 }
 
 /// Message sent from the broker to a worker. The broker won't send a message until it has received
@@ -67,6 +84,13 @@ pub enum MonitorToBroker {
     StatisticsRequest,
 }
 
+/// Message sent from the broker to the client side of a relay once the job side has connected.
+/// After this message, the connection just carries whatever the two sides send each other.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum BrokerToRelay {
+    Connected,
+}
+
 /// Message sent from the broker to an artifact fetcher. This will be in response to an
 /// [`ArtifactFetcherToBroker`] message. On failure to get the artifact, the result contains
 /// details about what went wrong. After a failure, the broker will close the artifact fetcher
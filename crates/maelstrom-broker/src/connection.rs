@@ -1,11 +1,12 @@
 use crate::{
     artifact_fetcher, artifact_pusher,
+    relay::{self, Relay},
     scheduler_task::{SchedulerMessage, SchedulerSender},
     IdVendor,
 };
 use anyhow::Result;
 use maelstrom_base::{
-    proto::{ClientToBroker, Hello},
+    proto::{BrokerToWorker, ClientToBroker, Hello, WorkerToBroker},
     ClientId, MonitorId, WorkerId,
 };
 use maelstrom_util::{
@@ -14,7 +15,7 @@ use maelstrom_util::{
 };
 use serde::Serialize;
 use slog::{debug, error, info, o, warn, Logger};
-use std::{future::Future, sync::Arc, thread};
+use std::{future::Future, net::SocketAddr, sync::Arc, thread};
 use tokio::{
     io::BufReader,
     net::{TcpListener, TcpStream},
@@ -104,8 +105,10 @@ pub async fn connection_main<IdT, FromSchedulerMessageT, ReaderFutureT, WriterFu
 
 async fn unassigned_connection_main(
     socket: TcpStream,
+    peer_addr: SocketAddr,
     scheduler_sender: SchedulerSender,
     id_vendor: Arc<IdVendor>,
+    relay: Arc<Relay>,
     temp_file_factory: TempFileFactory<Fs>,
     log: Logger,
 ) {
@@ -125,6 +128,7 @@ async fn unassigned_connection_main(
             let log_clone = log.clone();
             let log_clone2 = log.clone();
             debug!(log, "client connected");
+            let relay_clone = relay.clone();
             connection_main(
                 scheduler_sender,
                 id,
@@ -136,7 +140,13 @@ async fn unassigned_connection_main(
                         scheduler_sender,
                         |msg| {
                             assert!(!matches!(&msg, ClientToBroker::JobRequest(_, spec) if spec.must_be_run_locally()));
-                            SchedulerMessage::FromClient(id, msg)
+                            let ClientToBroker::JobRequest(_, spec) = &msg;
+                            for key in spec.relay_keys() {
+                                if let Err(err) = relay_clone.bind_client(key, id, peer_addr.ip()) {
+                                    warn!(log_clone, "error binding relay"; "error" => %err);
+                                }
+                            }
+                            SchedulerMessage::FromClient(id, Box::new(msg))
                         },
                         &log_clone
                     )
@@ -147,9 +157,10 @@ async fn unassigned_connection_main(
                 },
             )
             .await;
+            relay.client_disconnected(id);
             debug!(log, "client disconnected");
         }
-        Ok(Hello::Worker { slots }) => {
+        Ok(Hello::Worker { slots, devices }) => {
             let (read_stream, write_stream) = socket.into_split();
             let read_stream = BufReader::new(read_stream);
             let id: WorkerId = id_vendor.vend();
@@ -157,23 +168,42 @@ async fn unassigned_connection_main(
             info!(log, "worker connected");
             let log_clone = log.clone();
             let log_clone2 = log.clone();
+            let relay_clone = relay.clone();
             connection_main(
                 scheduler_sender,
                 id,
-                |id, sender| SchedulerMessage::WorkerConnected(id, slots as usize, sender),
+                |id, sender| SchedulerMessage::WorkerConnected(id, slots as usize, devices, sender),
                 SchedulerMessage::WorkerDisconnected,
                 |scheduler_sender| async move {
                     let _ = net::async_socket_reader(
                         read_stream,
                         scheduler_sender,
-                        |msg| SchedulerMessage::FromWorker(id, msg),
+                        |msg| {
+                            if let WorkerToBroker::JobResponse(jid, _) = &msg {
+                                relay_clone.job_completed(*jid);
+                            }
+                            SchedulerMessage::FromWorker(id, msg)
+                        },
                         &log_clone,
                     )
                     .await;
                 },
-                |scheduler_receiver| async move {
-                    let _ = net::async_socket_writer(scheduler_receiver, write_stream, &log_clone2)
-                        .await;
+                |mut scheduler_receiver| async move {
+                    let mut write_stream = write_stream;
+                    // The job side of a relay must come from the worker the job is sent to.
+                    while let Some(msg) = scheduler_receiver.recv().await {
+                        if let BrokerToWorker::EnqueueJob(jid, spec) = &msg {
+                            for key in spec.relay_keys() {
+                                relay.bind_worker(key, *jid, peer_addr.ip());
+                            }
+                        }
+                        if net::write_message_to_async_socket(&mut write_stream, msg, &log_clone2)
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
                 },
             )
             .await;
@@ -225,6 +255,15 @@ async fn unassigned_connection_main(
                 artifact_pusher::connection_main(socket, scheduler_sender, temp_file_factory, log)
             });
         }
+        Ok(Hello::Relay { key, side }) => {
+            let log = log.new(o!("rid" => id_vendor.vend::<u32>().to_string()));
+            debug!(log, "relay connected"; "side" => ?side);
+            if let Err(err) =
+                relay::connection_main(&relay, key, side, peer_addr.ip(), socket, log.clone()).await
+            {
+                debug!(log, "relay error"; "error" => %err);
+            }
+        }
         Err(err) => {
             warn!(log, "error reading hello message"; "error" => %err);
         }
@@ -238,6 +277,7 @@ pub async fn listener_main(
     listener: TcpListener,
     scheduler_sender: SchedulerSender,
     id_vendor: Arc<IdVendor>,
+    relay: Arc<Relay>,
     temp_file_factory: TempFileFactory<Fs>,
     log: Logger,
 ) {
@@ -248,8 +288,10 @@ pub async fn listener_main(
                 debug!(log, "new connection");
                 task::spawn(unassigned_connection_main(
                     socket,
+                    peer_addr,
                     scheduler_sender.clone(),
                     id_vendor.clone(),
+                    relay.clone(),
                     temp_file_factory.clone(),
                     log,
                 ));
@@ -5,12 +5,14 @@ mod artifact_pusher;
 pub mod config;
 mod connection;
 mod http;
+mod relay;
 mod scheduler_task;
 
 use anyhow::{Context as _, Result};
 use config::Config;
 use maelstrom_base::stats::BROKER_STATISTICS_INTERVAL;
 use maelstrom_util::{config::common::CacheSize, root::RootBuf};
+use relay::Relay;
 use scheduler_task::{CacheDir, SchedulerMessage, SchedulerSender, SchedulerTask};
 use slog::{error, info, Logger};
 use std::{
@@ -70,6 +72,8 @@ async fn main_inner_inner(
         id: AtomicU32::new(0),
     });
 
+    let relay = Arc::new(Relay::default());
+
     let mut join_set = JoinSet::new();
 
     join_set.spawn(http::listener_main(
@@ -82,6 +86,7 @@ async fn main_inner_inner(
         listener,
         scheduler_task.scheduler_sender().clone(),
         id_vendor,
+        relay,
         scheduler_task.temp_file_factory().clone(),
         log.clone(),
     ));
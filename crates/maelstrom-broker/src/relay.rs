@@ -0,0 +1,442 @@
+//! Relay traffic between a job running on a worker and the client that submitted it. This carries
+//! the job's TTY, if it has one, and delivers its secrets, so that neither has to go through the
+//! scheduler.
+//!
+//! Both sides connect to the broker and say hello with the same key. The first side to arrive
+//! waits for the second. When the second arrives, it hands its socket over to the first, which
+//! tells the client side that the job has connected and then copies bytes in both directions
+//! until both sides have shut down.
+//!
+//! A key is bound to the client connection that submits a job using it, and then to the worker
+//! that the job is sent to. The client side must connect from the client's address, and the job
+//! side from the address of the worker that owns the job. Knowing the key alone isn't enough.
+
+use anyhow::{anyhow, Result};
+use maelstrom_base::{
+    proto::{BrokerToRelay, RelaySide},
+    ClientId, JobId,
+};
+use maelstrom_util::net;
+use slog::{debug, Logger};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    future,
+    net::IpAddr,
+    sync::Mutex,
+};
+use tokio::{io, net::TcpStream, sync::oneshot};
+
+type Key = [u8; 16];
+
+struct Waiting {
+    side: RelaySide,
+    addr: IpAddr,
+    sender: oneshot::Sender<TcpStream>,
+}
+
+/// Who may connect to each side of a relay.
+struct Binding {
+    client: ClientId,
+    client_addr: IpAddr,
+    job: Option<(JobId, IpAddr)>,
+}
+
+impl Binding {
+    fn allows(&self, side: RelaySide, addr: IpAddr) -> bool {
+        match side {
+            RelaySide::Client => addr == self.client_addr,
+            RelaySide::Job => self.job.is_some_and(|(_, job)| addr == job),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    waiting: HashMap<Key, Waiting>,
+    bindings: HashMap<Key, Binding>,
+}
+
+/// The connections that are waiting for their other side to show up, and who is allowed to be on
+/// each side, keyed by relay key.
+#[derive(Default)]
+pub struct Relay {
+    inner: Mutex<Inner>,
+}
+
+enum Pairing {
+    HandedOff,
+    Wait(oneshot::Receiver<TcpStream>, TcpStream),
+}
+
+impl Relay {
+    /// Bind `key` to the client that submitted a job using it. It's an error if a different client
+    /// has already bound the key.
+    pub fn bind_client(&self, key: Key, client: ClientId, addr: IpAddr) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.bindings.entry(key) {
+            Entry::Occupied(entry) if entry.get().client != client => Err(anyhow!(
+                "relay key already bound to client {}",
+                entry.get().client
+            )),
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(entry) => {
+                entry.insert(Binding {
+                    client,
+                    client_addr: addr,
+                    job: None,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Bind the job side of `key` to the worker that `jid` was just sent to. This is ignored unless
+    /// the job's client has bound the key.
+    pub fn bind_worker(&self, key: Key, jid: JobId, addr: IpAddr) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(binding) = inner.bindings.get_mut(&key) {
+            if binding.client == jid.cid {
+                binding.job = Some((jid, addr));
+            }
+        }
+    }
+
+    /// Forget the keys bound by a client that has disconnected.
+    pub fn client_disconnected(&self, client: ClientId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.bindings.retain(|_, binding| binding.client != client);
+    }
+
+    /// Forget the key for a job that has completed.
+    pub fn job_completed(&self, jid: JobId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .bindings
+            .retain(|_, binding| !binding.job.is_some_and(|(job, _)| job == jid));
+    }
+
+    fn pair(
+        &self,
+        key: Key,
+        side: RelaySide,
+        addr: IpAddr,
+        mut socket: TcpStream,
+    ) -> Result<Pairing> {
+        let inner = &mut *self.inner.lock().unwrap();
+        match inner.bindings.get(&key) {
+            Some(binding) if !binding.allows(side, addr) => {
+                return Err(anyhow!(
+                    "{side:?} side of relay connected from unexpected address {addr}"
+                ));
+            }
+            // The client side connects before the client submits the job, but the job side can
+            // only connect once the job has been sent to a worker.
+            None if side == RelaySide::Job => {
+                return Err(anyhow!("no job with a relay was sent to {addr}"));
+            }
+            _ => {}
+        }
+        loop {
+            match inner.waiting.remove(&key) {
+                Some(Waiting {
+                    side: waiting_side,
+                    addr: waiting_addr,
+                    sender,
+                }) if waiting_side != side => {
+                    // The waiting side may have connected before the key was bound. If it
+                    // doesn't belong, drop it, and wait in its place.
+                    if !inner
+                        .bindings
+                        .get(&key)
+                        .is_some_and(|binding| binding.allows(waiting_side, waiting_addr))
+                    {
+                        continue;
+                    }
+                    match sender.send(socket) {
+                        Ok(()) => {
+                            return Ok(Pairing::HandedOff);
+                        }
+                        Err(returned) => {
+                            // The other side gave up waiting. Try again, which will result in us
+                            // waiting.
+                            socket = returned;
+                        }
+                    }
+                }
+                Some(other) => {
+                    inner.waiting.insert(key, other);
+                    return Err(anyhow!("{side:?} side of relay already connected"));
+                }
+                None => {
+                    let (sender, receiver) = oneshot::channel();
+                    inner.waiting.insert(key, Waiting { side, addr, sender });
+                    return Ok(Pairing::Wait(receiver, socket));
+                }
+            }
+        }
+    }
+
+    /// Remove our entry if nobody has taken it. We know it's ours if its sender is closed, since
+    /// we've already dropped the corresponding receiver.
+    fn abandon(&self, key: Key) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .waiting
+            .get(&key)
+            .is_some_and(|waiting| waiting.sender.is_closed())
+        {
+            inner.waiting.remove(&key);
+        }
+    }
+}
+
+/// Wait until the peer closes the socket. If the peer sends data instead, just wait forever: we'll
+/// notice it going away once we start relaying.
+async fn wait_for_close(socket: &TcpStream) {
+    let mut buf = [0u8; 1];
+    if let Ok(1..) = socket.peek(&mut buf).await {
+        future::pending::<()>().await;
+    }
+}
+
+/// Main loop for a relay connection. This returns once the socket has either been handed off
+/// to the other side of the relay, or once the relay has finished.
+pub async fn connection_main(
+    relay: &Relay,
+    key: Key,
+    side: RelaySide,
+    addr: IpAddr,
+    socket: TcpStream,
+    log: Logger,
+) -> Result<()> {
+    let (receiver, mut socket) = match relay.pair(key, side, addr, socket)? {
+        Pairing::HandedOff => {
+            debug!(log, "handed off relay connection");
+            return Ok(());
+        }
+        Pairing::Wait(receiver, socket) => (receiver, socket),
+    };
+
+    debug!(log, "waiting for other side of relay");
+    let peer = tokio::select! {
+        peer = receiver => peer,
+        _ = wait_for_close(&socket) => {
+            relay.abandon(key);
+            return Err(anyhow!("relay closed before other side connected"));
+        }
+    };
+    let Ok(mut peer) = peer else {
+        return Err(anyhow!("relay connection isn't bound to the job"));
+    };
+
+    debug!(log, "relay connected");
+    let (job, client) = match side {
+        RelaySide::Job => (&mut socket, &mut peer),
+        RelaySide::Client => (&mut peer, &mut socket),
+    };
+    net::write_message_to_async_socket(client, BrokerToRelay::Connected, &log).await?;
+    io::copy_bidirectional(job, client).await?;
+    debug!(log, "relay finished");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::ClientJobId;
+    use std::{net::Ipv4Addr, sync::Arc};
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+        task::{self, JoinHandle},
+    };
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const ELSEWHERE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    fn log() -> Logger {
+        Logger::root(slog::Discard, slog::o!())
+    }
+
+    fn jid(cid: u32) -> JobId {
+        JobId {
+            cid: ClientId::from(cid),
+            cjid: ClientJobId::from(1),
+        }
+    }
+
+    struct Fixture {
+        relay: Arc<Relay>,
+        listener: TcpListener,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            Self {
+                relay: Default::default(),
+                listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            }
+        }
+
+        fn bind(&self, key: Key, client: IpAddr, job: IpAddr) {
+            self.relay.bind_client(key, jid(1).cid, client).unwrap();
+            self.relay.bind_worker(key, jid(1), job);
+        }
+
+        async fn connect(&self) -> (TcpStream, TcpStream) {
+            let addr = self.listener.local_addr().unwrap();
+            let (client, server) = tokio::join!(TcpStream::connect(addr), self.listener.accept());
+            (client.unwrap(), server.unwrap().0)
+        }
+
+        async fn spawn(&self, key: Key, side: RelaySide) -> (TcpStream, JoinHandle<Result<()>>) {
+            let (client, server) = self.connect().await;
+            let relay = self.relay.clone();
+            let handle = task::spawn(async move {
+                connection_main(&relay, key, side, LOCALHOST, server, log()).await
+            });
+            (client, handle)
+        }
+
+        async fn spawn_waiting(
+            &self,
+            key: Key,
+            side: RelaySide,
+        ) -> (TcpStream, JoinHandle<Result<()>>) {
+            let (client, handle) = self.spawn(key, side).await;
+            while !self.relay.inner.lock().unwrap().waiting.contains_key(&key) {
+                task::yield_now().await;
+            }
+            (client, handle)
+        }
+
+        async fn connect_job(&self, key: Key) -> (TcpStream, Result<()>) {
+            let (job, server) = self.connect().await;
+            let result =
+                connection_main(&self.relay, key, RelaySide::Job, LOCALHOST, server, log()).await;
+            (job, result)
+        }
+
+        fn is_empty(&self) -> bool {
+            self.relay.inner.lock().unwrap().waiting.is_empty()
+        }
+    }
+
+    #[tokio::test]
+    async fn relays_in_both_directions() {
+        let fixture = Fixture::new().await;
+        let (mut client, handle) = fixture.spawn_waiting([1; 16], RelaySide::Client).await;
+
+        fixture.bind([1; 16], LOCALHOST, LOCALHOST);
+        let (mut job, result) = fixture.connect_job([1; 16]).await;
+        result.unwrap();
+        assert!(fixture.is_empty());
+
+        let connected: BrokerToRelay = net::read_message_from_async_socket(&mut client, &log())
+            .await
+            .unwrap();
+        assert_eq!(connected, BrokerToRelay::Connected);
+
+        job.write_all(b"output").await.unwrap();
+        let mut buf = [0u8; 6];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"output");
+
+        client.write_all(b"input").await.unwrap();
+        let mut buf = [0u8; 5];
+        job.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"input");
+
+        job.shutdown().await.unwrap();
+        client.shutdown().await.unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn same_side_twice_is_an_error() {
+        let fixture = Fixture::new().await;
+        let (_client, _handle) = fixture.spawn_waiting([2; 16], RelaySide::Client).await;
+
+        let (_client2, server) = fixture.connect().await;
+        connection_main(
+            &fixture.relay,
+            [2; 16],
+            RelaySide::Client,
+            LOCALHOST,
+            server,
+            log(),
+        )
+        .await
+        .unwrap_err();
+        assert!(!fixture.is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiting_side_closing_is_cleaned_up() {
+        let fixture = Fixture::new().await;
+        let (client, handle) = fixture.spawn_waiting([3; 16], RelaySide::Client).await;
+
+        drop(client);
+        handle.await.unwrap().unwrap_err();
+        assert!(fixture.is_empty());
+    }
+
+    #[tokio::test]
+    async fn job_side_without_binding_is_an_error() {
+        let fixture = Fixture::new().await;
+        let (_client, _handle) = fixture.spawn_waiting([4; 16], RelaySide::Client).await;
+
+        fixture.connect_job([4; 16]).await.1.unwrap_err();
+        fixture
+            .relay
+            .bind_client([4; 16], jid(1).cid, LOCALHOST)
+            .unwrap();
+        fixture.connect_job([4; 16]).await.1.unwrap_err();
+        assert!(!fixture.is_empty());
+    }
+
+    #[tokio::test]
+    async fn job_side_from_other_worker_is_an_error() {
+        let fixture = Fixture::new().await;
+        let (_client, _handle) = fixture.spawn_waiting([5; 16], RelaySide::Client).await;
+
+        fixture.bind([5; 16], LOCALHOST, ELSEWHERE);
+        fixture.connect_job([5; 16]).await.1.unwrap_err();
+        assert!(!fixture.is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_side_from_other_client_is_dropped() {
+        let fixture = Fixture::new().await;
+        let (_client, handle) = fixture.spawn_waiting([6; 16], RelaySide::Client).await;
+
+        fixture.bind([6; 16], ELSEWHERE, LOCALHOST);
+        let (_job, _job_handle) = fixture.spawn([6; 16], RelaySide::Job).await;
+        handle.await.unwrap().unwrap_err();
+        let inner = fixture.relay.inner.lock().unwrap();
+        assert_eq!(inner.waiting[&[6; 16]].side, RelaySide::Job);
+    }
+
+    #[test]
+    fn bindings() {
+        let relay = Relay::default();
+        relay.bind_client([7; 16], jid(1).cid, LOCALHOST).unwrap();
+        relay.bind_client([7; 16], jid(1).cid, LOCALHOST).unwrap();
+        relay
+            .bind_client([7; 16], jid(2).cid, LOCALHOST)
+            .unwrap_err();
+
+        // A job from another client can't claim the job side.
+        relay.bind_worker([7; 16], jid(2), LOCALHOST);
+        assert!(!relay.inner.lock().unwrap().bindings[&[7; 16]].allows(RelaySide::Job, LOCALHOST));
+        relay.bind_worker([7; 16], jid(1), LOCALHOST);
+        assert!(relay.inner.lock().unwrap().bindings[&[7; 16]].allows(RelaySide::Job, LOCALHOST));
+
+        relay.job_completed(jid(1));
+        assert!(relay.inner.lock().unwrap().bindings.is_empty());
+
+        relay.bind_client([7; 16], jid(1).cid, LOCALHOST).unwrap();
+        relay.client_disconnected(jid(1).cid);
+        assert!(relay.inner.lock().unwrap().bindings.is_empty());
+    }
+}
@@ -3,7 +3,7 @@
 
 use anyhow::Result;
 use maelstrom_base::{
-    manifest::{ManifestEntry, ManifestEntryData, ManifestFileData},
+    manifest::{ManifestEntry, ManifestEntryData},
     proto::{
         BrokerToClient, BrokerToMonitor, BrokerToWorker, ClientToBroker, MonitorToBroker,
         WorkerToBroker,
@@ -12,8 +12,8 @@ use maelstrom_base::{
         BrokerStatistics, JobState, JobStateCounts, JobStatisticsSample, JobStatisticsTimeSeries,
         WorkerStatistics,
     },
-    ArtifactType, ClientId, ClientJobId, JobBrokerStatus, JobId, JobOutcomeResult, JobSpec,
-    JobWorkerStatus, MonitorId, Sha256Digest, WorkerId,
+    ArtifactType, ClientId, ClientJobId, JobBrokerStatus, JobError, JobId, JobOutcomeResult,
+    JobSpec, JobWorkerStatus, MonitorId, Sha256Digest, Utf8PathBuf, WorkerId,
 };
 use maelstrom_util::{
     cache::{self, Cache, GetArtifact, GotArtifact},
@@ -23,7 +23,7 @@ use maelstrom_util::{
 };
 use std::{
     cmp::Ordering,
-    collections::{BinaryHeap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
     error,
     fmt::{self, Debug, Formatter},
     path::{Path, PathBuf},
@@ -160,11 +160,11 @@ pub enum Message<DepsT: SchedulerDeps, TempFileT: cache::fs::TempFile> {
     ClientDisconnected(ClientId),
 
     /// The given client has sent us the given message.
-    FromClient(ClientId, ClientToBroker),
+    FromClient(ClientId, Box<ClientToBroker>),
 
-    /// The given worker connected. It has the given number of slots and messages can be sent to it
-    /// on the given sender.
-    WorkerConnected(WorkerId, usize, DepsT::WorkerSender),
+    /// The given worker connected. It has the given number of slots, allows jobs to mount the given
+    /// host devices, and messages can be sent to it on the given sender.
+    WorkerConnected(WorkerId, usize, BTreeSet<Utf8PathBuf>, DepsT::WorkerSender),
 
     /// The given worker disconnected.
     WorkerDisconnected(WorkerId),
@@ -210,10 +210,11 @@ impl<DepsT: SchedulerDeps, TempFileT: cache::fs::TempFile> Debug for Message<Dep
             Message::FromClient(cid, msg) => {
                 f.debug_tuple("FromClient").field(cid).field(msg).finish()
             }
-            Message::WorkerConnected(wid, slots, _sender) => f
+            Message::WorkerConnected(wid, slots, devices, _sender) => f
                 .debug_tuple("WorkerConnected")
                 .field(wid)
                 .field(slots)
+                .field(devices)
                 .finish(),
             Message::WorkerDisconnected(wid) => {
                 f.debug_tuple("WorkerDisconnected").field(wid).finish()
@@ -256,6 +257,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             workers: WorkerMap(HashMap::default()),
             monitors: HashMap::default(),
             queued_jobs: BinaryHeap::default(),
+            queued_device_jobs: BTreeMap::default(),
             worker_heap: Heap::default(),
             job_statistics: JobStatisticsTimeSeries::default(),
         }
@@ -267,11 +269,13 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         match msg {
             Message::ClientConnected(id, sender) => self.receive_client_connected(id, sender),
             Message::ClientDisconnected(id) => self.receive_client_disconnected(deps, id),
-            Message::FromClient(cid, ClientToBroker::JobRequest(cjid, spec)) => {
-                self.receive_client_job_request(deps, cid, cjid, spec)
-            }
-            Message::WorkerConnected(id, slots, sender) => {
-                self.receive_worker_connected(deps, id, slots, sender)
+            Message::FromClient(cid, msg) => match *msg {
+                ClientToBroker::JobRequest(cjid, spec) => {
+                    self.receive_client_job_request(deps, cid, cjid, spec)
+                }
+            },
+            Message::WorkerConnected(id, slots, devices, sender) => {
+                self.receive_worker_connected(deps, id, slots, devices, sender)
             }
             Message::WorkerDisconnected(id) => self.receive_worker_disconnected(deps, id),
             Message::FromWorker(wid, WorkerToBroker::JobResponse(jid, result)) => {
@@ -368,20 +372,30 @@ impl<DepsT: SchedulerDeps> ClientMap<DepsT> {
 
 struct Worker<DepsT: SchedulerDeps> {
     slots: usize,
+    devices: BTreeSet<Utf8PathBuf>,
     pending: HashSet<JobId>,
     heap_index: HeapIndex,
     sender: DepsT::WorkerSender,
 }
 
 impl<DepsT: SchedulerDeps> Worker<DepsT> {
-    fn new(slots: usize, sender: DepsT::WorkerSender) -> Self {
+    fn new(slots: usize, devices: BTreeSet<Utf8PathBuf>, sender: DepsT::WorkerSender) -> Self {
         Worker {
             slots,
+            devices,
             sender,
             pending: HashSet::default(),
             heap_index: HeapIndex::default(),
         }
     }
+
+    fn is_full(&self) -> bool {
+        self.pending.len() == 2 * self.slots
+    }
+
+    fn allows_host_devices(&self, devices: &BTreeSet<Utf8PathBuf>) -> bool {
+        devices.is_subset(&self.devices)
+    }
 }
 
 struct WorkerMap<DepsT: SchedulerDeps>(HashMap<WorkerId, Worker<DepsT>>);
@@ -446,21 +460,158 @@ pub struct Scheduler<CacheT, DepsT: SchedulerDeps> {
     workers: WorkerMap<DepsT>,
     monitors: HashMap<MonitorId, DepsT::MonitorSender>,
     queued_jobs: BinaryHeap<QueuedJob>,
+    /// Jobs that mount host devices, grouped by the set of devices they mount, highest priority
+    /// first. These can only be run on workers that allow all of their devices, so they can't be
+    /// handed to whichever worker is least loaded like the jobs in `queued_jobs`.
+    queued_device_jobs: BTreeMap<BTreeSet<Utf8PathBuf>, VecDeque<QueuedJob>>,
     worker_heap: Heap<WorkerMap<DepsT>>,
     job_statistics: JobStatisticsTimeSeries,
 }
 
 impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
-    fn possibly_start_jobs(&mut self, deps: &mut DepsT, mut just_enqueued: HashSet<JobId>) {
-        while !self.queued_jobs.is_empty() && !self.workers.0.is_empty() {
-            let wid = self.worker_heap.peek().unwrap();
-            let worker = self.workers.0.get_mut(wid).unwrap();
+    /// Put a job that has all of its artifacts into the appropriate queue.
+    fn enqueue_job(&mut self, jid: JobId) {
+        let spec = &self.clients.job_from_jid(jid).spec;
+        let queued_job = QueuedJob::new(jid, spec.priority, spec.estimated_duration);
+        let devices: BTreeSet<_> = spec.host_devices().cloned().collect();
+        if devices.is_empty() {
+            self.queued_jobs.push(queued_job);
+        } else {
+            // Keep the queue sorted, putting the new job after any others with the same priority.
+            let queue = self.queued_device_jobs.entry(devices).or_default();
+            let index = queue.partition_point(|other| *other >= queued_job);
+            queue.insert(index, queued_job);
+        }
+    }
 
-            if worker.pending.len() == 2 * worker.slots {
-                break;
+    /// Fail the queued jobs that mount host devices that no connected worker allows. If there are
+    /// no workers at all, the jobs keep waiting, just like any other job would.
+    fn fail_device_jobs_without_workers(
+        &mut self,
+        deps: &mut DepsT,
+        just_enqueued: &mut HashSet<JobId>,
+    ) {
+        if self.workers.0.is_empty() {
+            return;
+        }
+        let workers = &self.workers;
+        let unsatisfiable: Vec<_> = self
+            .queued_device_jobs
+            .keys()
+            .filter(|devices| {
+                !workers
+                    .0
+                    .values()
+                    .any(|worker| worker.allows_host_devices(devices))
+            })
+            .cloned()
+            .collect();
+        for devices in unsatisfiable {
+            let error = JobError::Execution(format!(
+                "no connected worker allows host devices {}",
+                devices
+                    .iter()
+                    .map(|device| device.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+            for QueuedJob { jid, .. } in self.queued_device_jobs.remove(&devices).unwrap() {
+                just_enqueued.remove(&jid);
+                let client = self.clients.0.get_mut(&jid.cid).unwrap();
+                deps.send_message_to_client(
+                    &mut client.sender,
+                    BrokerToClient::JobResponse(jid.cjid, Err(error.clone())),
+                );
+                let job = client.jobs.remove(&jid.cjid).unwrap();
+                for artifact in job.acquired_artifacts {
+                    self.cache.decrement_refcount(artifact);
+                }
+                client.num_completed_jobs += 1;
             }
+        }
+    }
+
+    /// The device set of the queued jobs that mount host devices that should be started before
+    /// any other job: the one with the most important job, as long as that job is at least as
+    /// important as the most important job in `queued_jobs`. Jobs that mount host devices win
+    /// ties, since they can only go to some workers. Only device sets that `filter` accepts are
+    /// considered.
+    fn next_device_jobs(
+        &self,
+        filter: impl Fn(&BTreeSet<Utf8PathBuf>) -> bool,
+    ) -> Option<BTreeSet<Utf8PathBuf>> {
+        self.queued_device_jobs
+            .iter()
+            .filter(|(devices, _)| filter(devices))
+            .max_by(|(_, lhs), (_, rhs)| lhs[0].cmp(&rhs[0]))
+            .filter(|(_, queue)| {
+                self.queued_jobs
+                    .peek()
+                    .map_or(true, |queued_job| queue[0] >= *queued_job)
+            })
+            .map(|(devices, _)| devices.clone())
+    }
+
+    /// Pop the most important job from the queue of jobs that mount the given host devices.
+    fn pop_device_job(&mut self, devices: &BTreeSet<Utf8PathBuf>) -> Option<QueuedJob> {
+        let queue = self.queued_device_jobs.get_mut(devices).unwrap();
+        let queued_job = queue.pop_front();
+        if queue.is_empty() {
+            self.queued_device_jobs.remove(devices);
+        }
+        queued_job
+    }
+
+    fn possibly_start_jobs(&mut self, deps: &mut DepsT, mut just_enqueued: HashSet<JobId>) {
+        self.fail_device_jobs_without_workers(deps, &mut just_enqueued);
+
+        // Jobs are started in priority order, whether or not they mount host devices. A device set
+        // is skipped once none of the workers that allow it has a free slot.
+        let mut blocked_device_sets = HashSet::new();
+        loop {
+            let devices = self.next_device_jobs(|devices| !blocked_device_sets.contains(devices));
+            let wid = match &devices {
+                Some(devices) => {
+                    let wid = self
+                        .workers
+                        .0
+                        .iter()
+                        .filter(|(_, worker)| {
+                            !worker.is_full() && worker.allows_host_devices(devices)
+                        })
+                        .map(|(wid, _)| *wid)
+                        .reduce(|lhs, rhs| {
+                            if self.workers.is_element_less_than(&rhs, &lhs) {
+                                rhs
+                            } else {
+                                lhs
+                            }
+                        });
+                    let Some(wid) = wid else {
+                        blocked_device_sets.insert(devices.clone());
+                        continue;
+                    };
+                    wid
+                }
+                None => {
+                    if self.queued_jobs.is_empty() || self.workers.0.is_empty() {
+                        break;
+                    }
+                    let wid = *self.worker_heap.peek().unwrap();
+                    if self.workers.0[&wid].is_full() {
+                        break;
+                    }
+                    wid
+                }
+            };
 
-            let jid = self.queued_jobs.pop().unwrap().jid;
+            let jid = match &devices {
+                Some(devices) => self.pop_device_job(devices),
+                None => self.queued_jobs.pop(),
+            }
+            .unwrap()
+            .jid;
+            let worker = self.workers.0.get_mut(&wid).unwrap();
             let job = self.clients.job_from_jid(jid);
             deps.send_message_to_worker(
                 &mut worker.sender,
@@ -499,6 +650,10 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         }
 
         self.queued_jobs.retain(|qj| qj.jid.cid != id);
+        self.queued_device_jobs.retain(|_, queue| {
+            queue.retain(|qj| qj.jid.cid != id);
+            !queue.is_empty()
+        });
         for worker in self.workers.0.values_mut() {
             worker.pending.retain(|jid| {
                 jid.cid != id || {
@@ -562,9 +717,12 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
     ) {
         let jid = JobId { cid, cjid };
         let client = self.clients.0.get_mut(&cid).unwrap();
-        let layers = spec.layers.clone();
-        let priority = spec.priority;
-        let estimated_duration = spec.estimated_duration;
+        let layers: Vec<_> = spec
+            .layers
+            .iter()
+            .chain(spec.mount_layers())
+            .cloned()
+            .collect();
         client.jobs.insert(cjid, Job::new(spec)).assert_is_none();
 
         for (digest, type_) in layers {
@@ -576,8 +734,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         let job = client.jobs.get(&jid.cjid).unwrap();
         let have_all_artifacts = job.missing_artifacts.is_empty();
         if have_all_artifacts {
-            self.queued_jobs
-                .push(QueuedJob::new(jid, priority, estimated_duration));
+            self.enqueue_job(jid);
             self.possibly_start_jobs(deps, HashSet::from_iter([jid]));
         } else {
             deps.send_message_to_client(
@@ -592,11 +749,12 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         deps: &mut DepsT,
         id: WorkerId,
         slots: usize,
+        devices: BTreeSet<Utf8PathBuf>,
         sender: DepsT::WorkerSender,
     ) {
         self.workers
             .0
-            .insert(id, Worker::new(slots, sender))
+            .insert(id, Worker::new(slots, devices, sender))
             .assert_is_none();
         self.worker_heap.push(&mut self.workers, id);
         self.possibly_start_jobs(deps, HashSet::default());
@@ -609,12 +767,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
 
         let mut just_enqueued = HashSet::new();
         for jid in worker.pending.drain() {
-            let job = self.clients.job_from_jid(jid);
-            self.queued_jobs.push(QueuedJob::new(
-                jid,
-                job.spec.priority,
-                job.spec.estimated_duration,
-            ));
+            self.enqueue_job(jid);
             just_enqueued.insert(jid);
         }
 
@@ -648,7 +801,16 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         }
         client.num_completed_jobs += 1;
 
-        if let Some(QueuedJob { jid, .. }) = self.queued_jobs.pop() {
+        // Give this worker the most important job it can run. If that's a job that mounts host
+        // devices, it's taken from the queue for its device set.
+        let worker = &self.workers.0[&wid];
+        let devices = self.next_device_jobs(|devices| worker.allows_host_devices(devices));
+        let queued_job = match devices {
+            Some(devices) => self.pop_device_job(&devices),
+            None => self.queued_jobs.pop(),
+        };
+        let worker = self.workers.0.get_mut(&wid).unwrap();
+        if let Some(QueuedJob { jid, .. }) = queued_job {
             // If there are any queued_requests, we can just pop one off of the front of
             // the queue and not have to update the worker's used slot count or position in the
             // workers list.
@@ -711,8 +873,15 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
     ) -> Result<()> {
         for entry in deps.read_manifest(&self.cache.cache_path(&digest))? {
             let entry = entry?;
-            if let ManifestEntryData::File(ManifestFileData::Digest(digest)) = entry.data {
-                self.ensure_artifact_for_job(deps, digest, jid, IsManifest::NotManifest);
+            if let ManifestEntryData::File(data) = entry.data {
+                for digest in data.digests() {
+                    self.ensure_artifact_for_job(
+                        deps,
+                        digest.clone(),
+                        jid,
+                        IsManifest::NotManifest,
+                    );
+                }
             }
         }
         Ok(())
@@ -740,11 +909,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
 
             let job = self.clients.job_from_jid(jid);
             if job.missing_artifacts.is_empty() {
-                self.queued_jobs.push(QueuedJob::new(
-                    jid,
-                    job.spec.priority,
-                    job.spec.estimated_duration,
-                ));
+                self.enqueue_job(jid);
                 just_enqueued.insert(jid);
             }
         }
@@ -780,6 +945,7 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         counts[JobState::Pending] = self
             .queued_jobs
             .iter()
+            .chain(self.queued_device_jobs.values().flatten())
             .filter(|QueuedJob { jid, .. }| jid.cid == cid)
             .count() as u64;
 
@@ -823,8 +989,9 @@ mod tests {
     use enum_map::enum_map;
     use itertools::Itertools;
     use maelstrom_base::{
-        manifest::{ManifestEntry, ManifestEntryMetadata, Mode, UnixTimestamp},
+        manifest::{ManifestChunk, ManifestEntryMetadata, ManifestFileData, Mode, UnixTimestamp},
         proto::BrokerToWorker::{self, *},
+        JobMount,
     };
     use maelstrom_test::*;
     use maplit::hashmap;
@@ -834,7 +1001,7 @@ mod tests {
     #[derive(Clone, Debug, PartialEq)]
     enum TestMessage {
         ToClient(ClientId, BrokerToClient),
-        ToWorker(WorkerId, BrokerToWorker),
+        ToWorker(WorkerId, Box<BrokerToWorker>),
         ToMonitor(MonitorId, BrokerToMonitor),
         ToWorkerArtifactFetcher(u32, Option<(PathBuf, u64)>),
         CacheGetArtifact(JobId, Sha256Digest),
@@ -940,7 +1107,9 @@ mod tests {
             sender: &mut TestWorkerSender,
             message: BrokerToWorker,
         ) {
-            self.borrow_mut().messages.push(ToWorker(sender.0, message));
+            self.borrow_mut()
+                .messages
+                .push(ToWorker(sender.0, Box::new(message)));
         }
 
         fn send_message_to_monitor(
@@ -1080,7 +1249,7 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1092,7 +1261,7 @@ mod tests {
         let mut fixture = Fixture::default();
         fixture.receive_message(FromClient(
             cid![1],
-            ClientToBroker::JobRequest(cjid![1], spec![1, Tar]),
+            Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar])),
         ));
     }
 
@@ -1135,13 +1304,23 @@ mod tests {
     #[should_panic]
     fn connect_from_duplicate_worker_panics() {
         let mut fixture = Fixture::default();
-        fixture.receive_message(WorkerConnected(wid![1], 2, worker_sender![1]));
-        fixture.receive_message(WorkerConnected(wid![1], 2, worker_sender![1]));
+        fixture.receive_message(WorkerConnected(
+            wid![1],
+            2,
+            BTreeSet::new(),
+            worker_sender![1],
+        ));
+        fixture.receive_message(WorkerConnected(
+            wid![1],
+            2,
+            BTreeSet::new(),
+            worker_sender![1],
+        ));
     }
 
     script_test! {
         response_from_known_worker_for_unknown_job_ignored,
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1], Ok(outcome![1]))) => {};
     }
 
@@ -1153,10 +1332,10 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1], spec![1, Tar]))),
         };
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
@@ -1164,9 +1343,196 @@ mod tests {
         };
     }
 
+    macro_rules! device_spec {
+        [$n:literal, $device:literal] => {
+            spec![$n, Tar].mounts([JobMount::Device {
+                path: $device.into(),
+            }])
+        };
+    }
+
+    script_test! {
+        device_jobs_only_go_to_workers_that_allow_their_devices,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::from(["/dev/fuse".into()]), worker_sender![2]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], device_spec![1, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 1], device_spec![1, "/dev/fuse"]))),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], device_spec![2, "/dev/net/tun"]))) => {
+            CacheGetArtifact(jid![1, 2], digest![2]),
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Err(JobError::Execution(
+                "no connected worker allows host devices /dev/net/tun".into()
+            )))),
+            CacheDecrementRefcount(digest![2]),
+        };
+    }
+
+    script_test! {
+        device_jobs_wait_when_there_are_no_workers,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], device_spec![1, "/dev/net/tun"]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
+        };
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/net/tun".into()]), worker_sender![1]) => {
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], device_spec![1, "/dev/net/tun"]))),
+        };
+    }
+
+    script_test! {
+        device_jobs_fail_when_last_worker_allowing_them_disconnects,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/fuse".into()]), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], device_spec![1, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], device_spec![1, "/dev/fuse"]))),
+        };
+        WorkerDisconnected(wid![1]) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Err(JobError::Execution(
+                "no connected worker allows host devices /dev/fuse".into()
+            )))),
+            CacheDecrementRefcount(digest![1]),
+        };
+    }
+
+    script_test! {
+        device_jobs_with_different_devices_start_in_priority_order,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], device_spec![1, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], device_spec![2, "/dev/net/tun"].priority(1)))) => {
+            CacheGetArtifact(jid![1, 2], digest![2]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForWorker)),
+        };
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/fuse".into(), "/dev/net/tun".into()]), worker_sender![1]) => {
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], device_spec![2, "/dev/net/tun"].priority(1)))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], device_spec![1, "/dev/fuse"]))),
+        };
+    }
+
+    script_test! {
+        worker_prefers_device_jobs_it_allows,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+                ((jid![1, 3], digest![3]), vec![GetArtifact::Success]),
+                ((jid![1, 4], digest![4]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/fuse".into()]), worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
+            CacheGetArtifact(jid![1, 2], digest![2]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
+            CacheGetArtifact(jid![1, 3], digest![3]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], device_spec![4, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 4], digest![4]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+            CacheDecrementRefcount(digest![1]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 4], device_spec![4, "/dev/fuse"]))),
+        };
+    }
+
+    script_test! {
+        device_jobs_and_other_jobs_start_in_priority_order,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], device_spec![1, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar].priority(1)))) => {
+            CacheGetArtifact(jid![1, 2], digest![2]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForWorker)),
+        };
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/fuse".into()]), worker_sender![1]) => {
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar].priority(1)))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], device_spec![1, "/dev/fuse"]))),
+        };
+    }
+
+    script_test! {
+        worker_prefers_more_important_jobs_to_device_jobs,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![2]), vec![GetArtifact::Success]),
+                ((jid![1, 3], digest![3]), vec![GetArtifact::Success]),
+                ((jid![1, 4], digest![4]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::from(["/dev/fuse".into()]), worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
+            CacheGetArtifact(jid![1, 2], digest![2]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar].priority(1)))) => {
+            CacheGetArtifact(jid![1, 3], digest![3]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], device_spec![4, "/dev/fuse"]))) => {
+            CacheGetArtifact(jid![1, 4], digest![4]),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+            CacheDecrementRefcount(digest![1]),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar].priority(1)))),
+        };
+    }
+
     script_test! {
         response_from_worker_for_disconnected_client_ignored,
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1], Ok(outcome![1]))) => {};
     }
 
@@ -1186,78 +1552,78 @@ mod tests {
                 ((jid![1, 10], digest![10]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
-        WorkerConnected(wid![2], 2, worker_sender![2]) => {};
-        WorkerConnected(wid![3], 3, worker_sender![3]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 2, BTreeSet::new(), worker_sender![2]) => {};
+        WorkerConnected(wid![3], 3, BTreeSet::new(), worker_sender![3]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         // 0/2 0/2 0/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
         // 1/2 0/2 0/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
         // 1/2 1/2 0/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
             CacheGetArtifact(jid![1, 3], digest![3]),
-            ToWorker(wid![3], EnqueueJob(jid![1, 3], spec![3, Tar])),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar]))),
         };
 
         // 1/2 1/2 1/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar]))) => {
             CacheGetArtifact(jid![1, 4], digest![4]),
-            ToWorker(wid![3], EnqueueJob(jid![1, 4], spec![4, Tar])),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar]))),
         };
 
         // 1/2 1/2 2/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![5], spec![5, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![5], spec![5, Tar]))) => {
             CacheGetArtifact(jid![1, 5], digest![5]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 5], spec![5, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 5], spec![5, Tar]))),
         };
 
         // 2/2 1/2 2/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![6], spec![6, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![6], spec![6, Tar]))) => {
             CacheGetArtifact(jid![1, 6], digest![6]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 6], spec![6, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 6], spec![6, Tar]))),
         };
 
         // 2/2 2/2 2/3
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![7], spec![7, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![7], spec![7, Tar]))) => {
             CacheGetArtifact(jid![1, 7], digest![7]),
-            ToWorker(wid![3], EnqueueJob(jid![1, 7], spec![7, Tar])),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 7], spec![7, Tar]))),
         };
 
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
             CacheDecrementRefcount(digest![1]),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![8], spec![8, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![8], spec![8, Tar]))) => {
             CacheGetArtifact(jid![1, 8], digest![8]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 8], spec![8, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 8], spec![8, Tar]))),
         };
 
         FromWorker(wid![2], WorkerToBroker::JobResponse(jid![1, 2], Ok(outcome![2]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
             CacheDecrementRefcount(digest![2]),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![9], spec![9, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![9], spec![9, Tar]))) => {
             CacheGetArtifact(jid![1, 9], digest![9]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 9], spec![9, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 9], spec![9, Tar]))),
         };
 
         FromWorker(wid![3], WorkerToBroker::JobResponse(jid![1, 3], Ok(outcome![3]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![3], Ok(outcome![3]))),
             CacheDecrementRefcount(digest![3]),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![10], spec![10, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![10], spec![10, Tar]))) => {
             CacheGetArtifact(jid![1, 10], digest![10]),
-            ToWorker(wid![3], EnqueueJob(jid![1, 10], spec![10, Tar])),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 10], spec![10, Tar]))),
         };
     }
 
@@ -1273,40 +1639,40 @@ mod tests {
                 ((jid![1, 6], digest![6]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         // 0/1 0/1
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
         // 1/1 0/1
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
         // 1/1 1/1
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
             CacheGetArtifact(jid![1, 3], digest![3]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar]))),
         };
 
         // 2/1 1/1
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar]))) => {
             CacheGetArtifact(jid![1, 4], digest![4]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 4], spec![4, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar]))),
         };
 
         // 2/1 2/1
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![5], spec![5, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![5], spec![5, Tar]))) => {
             CacheGetArtifact(jid![1, 5], digest![5]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![5], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![6], spec![6, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![6], spec![6, Tar]))) => {
             CacheGetArtifact(jid![1, 6], digest![6]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![6], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1315,14 +1681,14 @@ mod tests {
         FromWorker(wid![2], WorkerToBroker::JobResponse(jid![1, 2], Ok(outcome![2]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
             CacheDecrementRefcount(digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 5], spec![5, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 5], spec![5, Tar]))),
         };
 
         // 1/2 2/2
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
             CacheDecrementRefcount(digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 6], spec![6, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 6], spec![6, Tar]))),
         };
     }
 
@@ -1340,41 +1706,41 @@ mod tests {
         },
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest!(1)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar].priority(-1).estimated_duration(Some(millis!(6))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar].priority(-1).estimated_duration(Some(millis!(6)))))) => {
             CacheGetArtifact(jid![1, 2], digest!(2)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar].estimated_duration(Some(millis!(5))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar].estimated_duration(Some(millis!(5)))))) => {
             CacheGetArtifact(jid![1, 3], digest!(3)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(4))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(4)))))) => {
             CacheGetArtifact(jid![1, 4], digest!(4)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![5], spec![5, Tar].estimated_duration(Some(millis!(3))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![5], spec![5, Tar].estimated_duration(Some(millis!(3)))))) => {
             CacheGetArtifact(jid![1, 5], digest!(5)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![5], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![6], spec![6, Tar].priority(1).estimated_duration(Some(millis!(2))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![6], spec![6, Tar].priority(1).estimated_duration(Some(millis!(2)))))) => {
             CacheGetArtifact(jid![1, 6], digest!(6)),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![6], JobBrokerStatus::WaitingForWorker)),
         };
 
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {
-            ToWorker(wid![1], EnqueueJob(jid![1, 6], spec![6, Tar].priority(1).estimated_duration(Some(millis!(2))))),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
-            ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(5))))),
-            ToWorker(wid![1], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(4))))),
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 6], spec![6, Tar].priority(1).estimated_duration(Some(millis!(2)))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(5)))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(4)))))),
         };
 
-        WorkerConnected(wid![2], 2, worker_sender![2]) => {
-            ToWorker(wid![2], EnqueueJob(jid![1, 5], spec![5, Tar].estimated_duration(Some(millis!(3))))),
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar].priority(-1).estimated_duration(Some(millis!(6))))),
+        WorkerConnected(wid![2], 2, BTreeSet::new(), worker_sender![2]) => {
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 5], spec![5, Tar].estimated_duration(Some(millis!(3)))))),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar].priority(-1).estimated_duration(Some(millis!(6)))))),
         };
     }
 
@@ -1389,45 +1755,45 @@ mod tests {
                 ((jid![1, 5], digest![5]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
-        WorkerConnected(wid![3], 1, worker_sender![3]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
+        WorkerConnected(wid![3], 1, BTreeSet::new(), worker_sender![3]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50)))))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50)))))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
             CacheGetArtifact(jid![1, 3], digest![3]),
-            ToWorker(wid![3], EnqueueJob(jid![1, 3], spec![3, Tar])),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40)))))) => {
             CacheGetArtifact(jid![1, 4], digest![4]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40)))))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![5], spec![5, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![5], spec![5, Tar]))) => {
             CacheGetArtifact(jid![1, 5], digest![5]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 5], spec![5, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 5], spec![5, Tar]))),
         };
 
         WorkerDisconnected(wid![1]) => {
-            ToWorker(wid![3], EnqueueJob(jid![1, 1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50))))),
+            ToWorker(wid![3], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar].priority(1).estimated_duration(Some(millis!(50)))))),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
         };
 
         FromWorker(wid![2], WorkerToBroker::JobResponse(jid![1, 2], Ok(outcome![2]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
             CacheDecrementRefcount(digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40))))),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40)))))),
         };
     }
 
@@ -1441,24 +1807,24 @@ mod tests {
                 ((jid![1, 4], digest![4]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar].estimated_duration(Some(millis!(300))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar].estimated_duration(Some(millis!(300)))))) => {
             CacheGetArtifact(jid![1, 3], digest![3]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40)))))) => {
             CacheGetArtifact(jid![1, 4], digest![4]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1466,22 +1832,22 @@ mod tests {
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
             CacheDecrementRefcount(digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300)))))),
         };
 
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {
-            ToWorker(wid![2], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40))))),
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40)))))),
         };
 
         WorkerDisconnected(wid![1]) => {
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
         };
 
         FromWorker(wid![2], WorkerToBroker::JobResponse(jid![1, 2], Ok(outcome![2]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
             CacheDecrementRefcount(digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300))))),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300)))))),
         };
     }
 
@@ -1495,24 +1861,24 @@ mod tests {
                 ((jid![1, 4], digest![4]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar].estimated_duration(Some(millis!(10))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar].estimated_duration(Some(millis!(10)))))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar].estimated_duration(Some(millis!(10))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar].estimated_duration(Some(millis!(10)))))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar].estimated_duration(Some(millis!(20))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar].estimated_duration(Some(millis!(20)))))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![2, Tar].estimated_duration(Some(millis!(20))))),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar].estimated_duration(Some(millis!(20)))))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar].priority(1).estimated_duration(Some(millis!(1))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar].priority(1).estimated_duration(Some(millis!(1)))))) => {
             CacheGetArtifact(jid![1, 3], digest![3]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40))))) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar].estimated_duration(Some(millis!(40)))))) => {
             CacheGetArtifact(jid![1, 4], digest![4]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1522,9 +1888,9 @@ mod tests {
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForWorker)),
         };
 
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {
-            ToWorker(wid![2], EnqueueJob(jid![1, 3], spec![3, Tar].priority(1).estimated_duration(Some(millis!(1))))),
-            ToWorker(wid![2], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40))))),
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar].priority(1).estimated_duration(Some(millis!(1)))))),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40)))))),
         };
     }
 
@@ -1537,17 +1903,17 @@ mod tests {
             ], [], [], [])
         },
 
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid![1, 2], digest![2]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
@@ -1561,7 +1927,7 @@ mod tests {
         };
 
         WorkerDisconnected(wid![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
     }
 
     script_test! {
@@ -1571,16 +1937,16 @@ mod tests {
                 ((jid!(1, 1), digest![1]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(1, 1), digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 1])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 1]))),
             CacheDecrementRefcount(digest![1]),
             CacheClientDisconnected(cid![1]),
         };
@@ -1595,30 +1961,30 @@ mod tests {
                 ((jid!(1, 2), digest![3]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
         ClientConnected(cid![2], client_sender![2]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(1, 1), digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![1], spec![2, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![1], spec![2, Tar]))) => {
             CacheGetArtifact(jid!(2, 1), digest![2]),
-            ToWorker(wid![2], EnqueueJob(jid![2, 1], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![2, 1], spec![2, Tar]))),
         };
 
         ClientDisconnected(cid![2]) => {
-            ToWorker(wid![2], CancelJob(jid![2, 1])),
+            ToWorker(wid![2], Box::new(CancelJob(jid![2, 1]))),
             CacheDecrementRefcount(digest![2]),
             CacheClientDisconnected(cid![2]),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![3, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![3, Tar]))) => {
             CacheGetArtifact(jid!(1, 2), digest![3]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![3, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![3, Tar]))),
         };
     }
 
@@ -1632,25 +1998,25 @@ mod tests {
                 ((jid!(2, 1), digest![1]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(1, 1), digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid!(1, 2), digest![2]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
         };
 
         ClientConnected(cid![2], client_sender![2]) => {};
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(2, 1), digest![1]),
             ToClient(cid![2], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![3, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
             CacheGetArtifact(jid!(1, 3), digest![3]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![3], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1663,7 +2029,7 @@ mod tests {
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
             CacheDecrementRefcount(digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 3], spec![3, Tar]))),
         };
     }
 
@@ -1679,36 +2045,36 @@ mod tests {
                 ((jid!(2, 4), digest![4]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
         ClientConnected(cid![2], client_sender![2]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(1, 1), digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid!(2, 1), digest![1]),
-            ToWorker(wid![2], EnqueueJob(jid![2, 1], spec![1, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![2, 1], spec![1, Tar]))),
         };
 
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid!(2, 2), digest![2]),
-            ToWorker(wid![1], EnqueueJob(jid![2, 2], spec![2, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![2, 2], spec![2, Tar]))),
         };
 
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![3], spec![3, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![3], spec![3, Tar]))) => {
             CacheGetArtifact(jid!(2, 3), digest![3]),
-            ToWorker(wid![2], EnqueueJob(jid![2, 3], spec![3, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![2, 3], spec![3, Tar]))),
         };
 
-        FromClient(cid![2], ClientToBroker::JobRequest(cjid![4], spec![4, Tar])) => {
+        FromClient(cid![2], Box::new(ClientToBroker::JobRequest(cjid![4], spec![4, Tar]))) => {
             CacheGetArtifact(jid!(2, 4), digest![4]),
             ToClient(cid![2], BrokerToClient::JobStatusUpdate(cjid![4], JobBrokerStatus::WaitingForWorker)),
         };
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![2, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![2, Tar]))) => {
             CacheGetArtifact(jid!(1, 2), digest![2]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForWorker)),
         };
@@ -1719,11 +2085,11 @@ mod tests {
             CacheDecrementRefcount(digest![3]),
             CacheDecrementRefcount(digest![4]),
 
-            ToWorker(wid![2], CancelJob(jid![2, 1])),
-            ToWorker(wid![1], CancelJob(jid![2, 2])),
-            ToWorker(wid![2], CancelJob(jid![2, 3])),
+            ToWorker(wid![2], Box::new(CancelJob(jid![2, 1]))),
+            ToWorker(wid![1], Box::new(CancelJob(jid![2, 2]))),
+            ToWorker(wid![2], Box::new(CancelJob(jid![2, 3]))),
 
-            ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 2], spec![2, Tar]))),
 
             CacheClientDisconnected(cid![2]),
         };
@@ -1738,12 +2104,12 @@ mod tests {
                 ((jid![1, 2], digest![44]), vec![GetArtifact::Get]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         FromClient(
             cid![1],
-            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])
+            Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]]))
         ) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
@@ -1758,6 +2124,39 @@ mod tests {
         }
     }
 
+    script_test! {
+        request_with_mount_layers,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Success]),
+                ((jid![1, 2], digest![43]), vec![GetArtifact::Get]),
+            ], [], [], [])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(
+            cid![1],
+            Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar)]].mounts([
+                JobMount::Layer {
+                    mount_point: "/mnt".into(),
+                    layer: (digest![43], ArtifactType::Tar),
+                    read_only: false,
+                },
+            ])))
+        ) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            CacheGetArtifact(jid![1, 2], digest![43]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![43])),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForLayers)),
+        };
+
+        ClientDisconnected(cid![1]) => {
+            CacheClientDisconnected(cid![1]),
+            CacheDecrementRefcount(digest![42]),
+        }
+    }
+
     script_test! {
         request_with_layers_2,
         {
@@ -1767,17 +2166,17 @@ mod tests {
                 ((jid![1, 2], digest![44]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         FromClient(
             cid![1],
-            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])
+            Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]]))
         ) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
             CacheGetArtifact(jid![1, 2], digest![44]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]]))),
         };
 
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 2], Ok(outcome![1]))) => {
@@ -1800,12 +2199,12 @@ mod tests {
                 (digest![44], vec![vec![jid![1, 2]]]),
             ], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         FromClient(
             cid![1],
-            ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])
+            Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]]))
         ) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
@@ -1819,11 +2218,11 @@ mod tests {
         };
         GotArtifact(digest![44], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![44], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -1838,18 +2237,18 @@ mod tests {
                 ((jid![1, 2], digest![42]), vec![GetArtifact::Success]),
             ], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         FromClient(
-            cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (42, Tar)]])
+            cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (42, Tar)]]))
         ) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (42, Tar)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (42, Tar)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
         }
@@ -1864,11 +2263,11 @@ mod tests {
                 (digest![42], vec![vec![jid![1, 2]]]),
             ], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
         FromClient(
-            cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (42, Tar)]])
+            cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Tar), (42, Tar)]]))
         ) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
@@ -1877,11 +2276,11 @@ mod tests {
 
         GotArtifact(digest![42], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![42], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (42, Tar)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (42, Tar)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
         }
@@ -1903,15 +2302,17 @@ mod tests {
                         size: 11,
                         mode: Mode(0o0555),
                         mtime: UnixTimestamp(1705538554),
+                        xattrs: Default::default(),
+                        owner: None,
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                 }])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForLayers)),
@@ -1925,17 +2326,80 @@ mod tests {
 
         GotArtifact(digest![43], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![43], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
         }
     }
 
+    script_test! {
+        request_with_chunked_manifest_nothing_in_cache,
+        {
+            Fixture::new([
+                ((jid![1, 2], digest![42]), vec![GetArtifact::Get]),
+                ((jid![1, 2], digest![43]), vec![GetArtifact::Get]),
+                ((jid![1, 2], digest![44]), vec![GetArtifact::Get]),
+            ], [
+                (digest![42], vec![vec![jid![1, 2]]]),
+                (digest![43], vec![vec![jid![1, 2]]]),
+                (digest![44], vec![vec![jid![1, 2]]]),
+            ], [], [
+                (digest![42], vec![ManifestEntry {
+                    path: "foobar.bin".into(),
+                    metadata: ManifestEntryMetadata {
+                        size: 11,
+                        mode: Mode(0o0555),
+                        mtime: UnixTimestamp(1705538554),
+                        xattrs: Default::default(),
+                        owner: None,
+                    },
+                    data: ManifestEntryData::File(ManifestFileData::Chunked(vec![
+                        ManifestChunk { digest: digest![43], size: 5 },
+                        ManifestChunk { digest: digest![44], size: 6 },
+                    ])),
+                }])
+            ])
+        },
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
+        ClientConnected(cid![1], client_sender![1]) => {};
+
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
+            CacheGetArtifact(jid![1, 2], digest![42]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
+            ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForLayers)),
+        };
+
+        GotArtifact(digest![42], "/z/tmp/foo".into()) => {
+            CacheGotArtifact(digest![42], "/z/tmp/foo".into()),
+            CacheGetArtifact(jid![1, 2], digest![43]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![43])),
+            CacheGetArtifact(jid![1, 2], digest![44]),
+            ToClient(cid![1], BrokerToClient::TransferArtifact(digest![44])),
+        };
+
+        GotArtifact(digest![43], "/z/tmp/bar".into()) => {
+            CacheGotArtifact(digest![43], "/z/tmp/bar".into()),
+        };
+
+        GotArtifact(digest![44], "/z/tmp/baz".into()) => {
+            CacheGotArtifact(digest![44], "/z/tmp/baz".into()),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
+        };
+
+        ClientDisconnected(cid![1]) => {
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
+            CacheClientDisconnected(cid![1]),
+            CacheDecrementRefcount(digest![42]),
+            CacheDecrementRefcount(digest![43]),
+            CacheDecrementRefcount(digest![44]),
+        }
+    }
+
     script_test! {
         request_with_manifest_with_duplicate_digests_with_nothing_in_cache,
         {
@@ -1953,6 +2417,8 @@ mod tests {
                             size: 11,
                             mode: Mode(0o0555),
                             mtime: UnixTimestamp(1705538554),
+                            xattrs: Default::default(),
+                            owner: None,
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                     },
@@ -1962,16 +2428,18 @@ mod tests {
                             size: 11,
                             mode: Mode(0o0555),
                             mtime: UnixTimestamp(1705538554),
+                            xattrs: Default::default(),
+                            owner: None,
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                     }
                 ])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             ToClient(cid![1], BrokerToClient::TransferArtifact(digest![42])),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![2], JobBrokerStatus::WaitingForLayers)),
@@ -1985,11 +2453,11 @@ mod tests {
 
         GotArtifact(digest![43], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![43], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -2012,15 +2480,17 @@ mod tests {
                         size: 11,
                         mode: Mode(0o0555),
                         mtime: UnixTimestamp(1705538554),
+                        xattrs: Default::default(),
+                        owner: None,
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                 }])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
             ToClient(cid![1], BrokerToClient::TransferArtifact(digest![43])),
@@ -2029,11 +2499,11 @@ mod tests {
 
         GotArtifact(digest![43], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![43], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -2056,15 +2526,17 @@ mod tests {
                         size: 11,
                         mode: Mode(0o0555),
                         mtime: UnixTimestamp(1705538554),
+                        xattrs: Default::default(),
+                        owner: None,
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                 }])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
             ToClient(cid![1], BrokerToClient::TransferArtifact(digest![43])),
@@ -2073,11 +2545,11 @@ mod tests {
 
         GotArtifact(digest![43], "/z/tmp/bar".into()) => {
             CacheGotArtifact(digest![43], "/z/tmp/bar".into()),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -2100,22 +2572,24 @@ mod tests {
                         size: 11,
                         mode: Mode(0o0555),
                         mtime: UnixTimestamp(1705538554),
+                        xattrs: Default::default(),
+                        owner: None,
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                 }])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -2139,6 +2613,8 @@ mod tests {
                             size: 11,
                             mode: Mode(0o0555),
                             mtime: UnixTimestamp(1705538554),
+                            xattrs: Default::default(),
+                            owner: None,
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                     },
@@ -2148,23 +2624,25 @@ mod tests {
                             size: 11,
                             mode: Mode(0o0555),
                             mtime: UnixTimestamp(1705538554),
+                            xattrs: Default::default(),
+                            owner: None,
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
                     }
                 ])
             ])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         ClientConnected(cid![1], client_sender![1]) => {};
 
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![2], spec![1, [(42, Manifest)]]))) => {
             CacheGetArtifact(jid![1, 2], digest![42]),
             CacheGetArtifact(jid![1, 2], digest![43]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 2], spec![1, [(42, Manifest)]]))),
         };
 
         ClientDisconnected(cid![1]) => {
-            ToWorker(wid![1], CancelJob(jid![1, 2])),
+            ToWorker(wid![1], Box::new(CancelJob(jid![1, 2]))),
             CacheClientDisconnected(cid![1]),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
@@ -2218,9 +2696,9 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
         MonitorConnected(mid![1], monitor_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, [(42, Tar)]])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, [(42, Tar)]]))) => {
             CacheGetArtifact(jid![1, 1], digest![42]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForLayers)),
         };
@@ -2253,7 +2731,7 @@ mod tests {
         },
         ClientConnected(cid![1], client_sender![1]) => {};
         MonitorConnected(mid![1], monitor_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
             ToClient(cid![1], BrokerToClient::JobStatusUpdate(cjid![1], JobBrokerStatus::WaitingForWorker)),
         };
@@ -2283,11 +2761,11 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
         MonitorConnected(mid![1], monitor_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
         StatisticsHeartbeat => {};
         FromMonitor(mid![1], MonitorToBroker::StatisticsRequest) => {
@@ -2317,11 +2795,11 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 2, BTreeSet::new(), worker_sender![1]) => {};
         MonitorConnected(mid![1], monitor_sender![1]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![1], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 1], digest![1]),
-            ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
+            ToWorker(wid![1], Box::new(EnqueueJob(jid![1, 1], spec![1, Tar]))),
         };
         FromWorker(wid![1], WorkerToBroker::JobResponse(jid![1, 1], Ok(outcome![1]))) => {
             ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
@@ -2355,10 +2833,10 @@ mod tests {
             ], [], [], [])
         },
         ClientConnected(cid![1], client_sender![1]) => {};
-        WorkerConnected(wid![2], 1, worker_sender![2]) => {};
-        FromClient(cid![1], ClientToBroker::JobRequest(cjid![3], spec![1, Tar])) => {
+        WorkerConnected(wid![2], 1, BTreeSet::new(), worker_sender![2]) => {};
+        FromClient(cid![1], Box::new(ClientToBroker::JobRequest(cjid![3], spec![1, Tar]))) => {
             CacheGetArtifact(jid![1, 3], digest![1]),
-            ToWorker(wid![2], EnqueueJob(jid![1, 3], spec![1, Tar])),
+            ToWorker(wid![2], Box::new(EnqueueJob(jid![1, 3], spec![1, Tar]))),
         };
         FromWorker(
             wid![2],
@@ -2403,7 +2881,7 @@ mod tests {
         {
             Fixture::new([], [], [], [])
         },
-        WorkerConnected(wid![1], 1, worker_sender![1]) => {};
+        WorkerConnected(wid![1], 1, BTreeSet::new(), worker_sender![1]) => {};
         FromWorker(
             wid![1],
             WorkerToBroker::JobStatusUpdate(jid![2, 3], JobWorkerStatus::WaitingForLayers)
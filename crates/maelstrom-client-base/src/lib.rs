@@ -50,6 +50,11 @@ pub struct CacheDir;
 pub const MANIFEST_DIR: &str = "manifests";
 pub const STUB_MANIFEST_DIR: &str = "manifests/stubs";
 pub const SYMLINK_MANIFEST_DIR: &str = "manifests/symlinks";
+pub const FILES_MANIFEST_DIR: &str = "manifests/files";
+pub const CHUNK_DIR: &str = "chunks";
+pub const ARCHIVE_BLOB_DIR: &str = "archive-blobs";
+pub const GIT_BLOB_DIR: &str = "git-blobs";
+pub const COMMAND_LAYER_DIR: &str = "command-layers";
 pub const SO_LISTINGS_DIR: &str = "so-listings";
 
 impl From<proto::Error> for anyhow::Error {
@@ -3,16 +3,20 @@ use anyhow::{anyhow, Result};
 use enumset::{EnumSet, EnumSetType};
 use maelstrom_base::{
     client_job_id_pocket_definition, group_id_pocket_definition,
-    job_broker_status_pocket_definition, job_completed_pocket_definition,
-    job_device_pocket_definition, job_effects_pocket_definition, job_mount_pocket_definition,
+    job_broker_status_pocket_definition, job_capabilities_pocket_definition,
+    job_capability_pocket_definition, job_completed_pocket_definition,
+    job_device_pocket_definition, job_effects_pocket_definition, job_hosts_entry_pocket_definition,
     job_network_pocket_definition, job_outcome_pocket_definition,
     job_output_result_pocket_definition, job_root_overlay_pocket_definition,
-    job_termination_status_pocket_definition, job_tty_pocket_definition,
-    job_worker_status_pocket_definition, timeout_pocket_definition, user_id_pocket_definition,
-    window_size_pocket_definition, worker_id_pocket_definition, ClientJobId, GroupId,
-    JobBrokerStatus, JobCompleted, JobDevice, JobEffects, JobMount, JobNetwork, JobOutcome,
-    JobOutputResult, JobRootOverlay, JobTerminationStatus, JobTty, JobWorkerStatus, Timeout,
-    UserId, Utf8PathBuf, WindowSize, WorkerId,
+    job_secret_destination_pocket_definition, job_termination_status_pocket_definition,
+    job_tty_address_pocket_definition, job_tty_pocket_definition,
+    job_volume_mode_pocket_definition, job_worker_status_pocket_definition,
+    timeout_pocket_definition, user_id_pocket_definition, window_size_pocket_definition,
+    worker_id_pocket_definition, ClientJobId, GroupId, JobBrokerStatus, JobCapabilities,
+    JobCapability, JobCompleted, JobDevice, JobEffects, JobHostsEntry, JobNetwork, JobOutcome,
+    JobOutputResult, JobRootOverlay, JobSecretDestination, JobTerminationStatus, JobTty,
+    JobTtyAddress, JobVolumeMode, JobWorkerStatus, Timeout, UserId, Utf8PathBuf, WindowSize,
+    WorkerId,
 };
 use maelstrom_macro::{
     into_proto_buf_remote_derive, remote_derive, try_from_proto_buf_remote_derive,
@@ -25,6 +29,7 @@ use maelstrom_util::{
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
 use std::hash::Hash;
+use std::net::IpAddr;
 use std::os::unix::ffi::OsStringExt as _;
 use std::path::{Path, PathBuf};
 
@@ -175,6 +180,22 @@ impl<const LEN: usize> TryFromProtoBuf for [u8; LEN] {
     }
 }
 
+impl<V: IntoProtoBuf> IntoProtoBuf for Box<V> {
+    type ProtoBufType = V::ProtoBufType;
+
+    fn into_proto_buf(self) -> Self::ProtoBufType {
+        (*self).into_proto_buf()
+    }
+}
+
+impl<V: TryFromProtoBuf> TryFromProtoBuf for Box<V> {
+    type ProtoBufType = V::ProtoBufType;
+
+    fn try_from_proto_buf(v: Self::ProtoBufType) -> Result<Self> {
+        Ok(Box::new(V::try_from_proto_buf(v)?))
+    }
+}
+
 impl IntoProtoBuf for Box<[u8]> {
     type ProtoBufType = Vec<u8>;
 
@@ -214,6 +235,22 @@ impl TryFromProtoBuf for String {
     }
 }
 
+impl IntoProtoBuf for IpAddr {
+    type ProtoBufType = String;
+
+    fn into_proto_buf(self) -> String {
+        self.to_string()
+    }
+}
+
+impl TryFromProtoBuf for IpAddr {
+    type ProtoBufType = String;
+
+    fn try_from_proto_buf(v: String) -> Result<Self> {
+        Ok(v.parse()?)
+    }
+}
+
 impl<'a> IntoProtoBuf for &'a Path {
     type ProtoBufType = Vec<u8>;
 
@@ -491,6 +528,18 @@ remote_derive!(
 // | |_| | (_) | |_) /_  _\
 //  \___/ \___/|_.__/  \/
 
+remote_derive!(
+    JobCapability,
+    (IntoProtoBuf, TryFromProtoBuf),
+    proto(proto_buf_type = "proto::JobCapability")
+);
+
+remote_derive!(
+    JobCapabilities,
+    (IntoProtoBuf, TryFromProtoBuf),
+    proto(proto_buf_type = "proto::JobCapabilities")
+);
+
 remote_derive!(
     JobDevice,
     (IntoProtoBuf, TryFromProtoBuf),
@@ -509,30 +558,48 @@ remote_derive!(
     proto(proto_buf_type = "proto::JobNetwork")
 );
 
+remote_derive!(
+    JobVolumeMode,
+    (IntoProtoBuf, TryFromProtoBuf),
+    proto(proto_buf_type = "proto::JobVolumeMode")
+);
+
 remote_derive!(
     JobEffects,
     (IntoProtoBuf, TryFromProtoBuf),
     proto(proto_buf_type = "proto::JobEffects", option_all)
 );
 
+remote_derive!(
+    JobTtyAddress,
+    (IntoProtoBuf, TryFromProtoBuf),
+    proto(
+        proto_buf_type = "proto::JobTtyAddress",
+        enum_type = "proto::job_tty_address::Address"
+    ),
+);
+
 remote_derive!(
     JobTty,
     (IntoProtoBuf, TryFromProtoBuf),
     proto(proto_buf_type = "proto::JobTty"),
+    @address: proto(option),
     @window_size: proto(option)
 );
 
 remote_derive!(
-    JobMount,
+    JobSecretDestination,
+    (IntoProtoBuf, TryFromProtoBuf),
+    proto(
+        proto_buf_type = "proto::JobSecretDestination",
+        enum_type = "proto::job_secret_destination::Destination"
+    ),
+);
+
+remote_derive!(
+    JobHostsEntry,
     (IntoProtoBuf, TryFromProtoBuf),
-    proto(proto_buf_type = "proto::JobMount", enum_type = "proto::job_mount::Mount"),
-    @Bind: proto(proto_buf_type = "proto::BindMount"),
-    @Devices: proto(proto_buf_type = "proto::DevicesMount"),
-    @Devpts: proto(proto_buf_type = "proto::DevptsMount"),
-    @Mqueue: proto(proto_buf_type = "proto::MqueueMount"),
-    @Proc: proto(proto_buf_type = "proto::ProcMount"),
-    @Sys: proto(proto_buf_type = "proto::SysMount"),
-    @Tmp: proto(proto_buf_type = "proto::TmpMount"),
+    proto(proto_buf_type = "proto::JobHostsEntry")
 );
 
 remote_derive!(
@@ -542,6 +609,7 @@ remote_derive!(
         proto_buf_type = "proto::JobRootOverlay",
         enum_type = "proto::job_root_overlay::Overlay"
     ),
+    @Tmp: proto(proto_buf_type = "proto::TmpJobRootOverlay"),
     @Local: proto(proto_buf_type = "proto::LocalJobRootOverlay"),
 );
 
@@ -8,10 +8,13 @@ pub mod substitute;
 
 use crate::{proto, IntoProtoBuf, TryFromProtoBuf};
 use anyhow::{anyhow, bail, Error, Result};
+use bytesize::ByteSize;
 use derive_more::From;
 use enumset::{EnumSet, EnumSetType};
 use maelstrom_base::{
-    enum_set, GroupId, JobMount, JobNetwork, JobRootOverlay, JobTty, Timeout, UserId, Utf8PathBuf,
+    enum_set, ArtifactType, GroupId, JobCapabilities, JobDevice, JobDeviceForTomlAndJson,
+    JobHostsEntry, JobNetwork, JobRootOverlay, JobSecretDestination, JobTty, JobVolumeMode,
+    NonRootUtf8PathBuf, Sha256Digest, Timeout, UserId, Utf8PathBuf,
 };
 use maelstrom_util::template::{replace_template_vars, TemplateVars};
 use serde::{de, Deserialize, Deserializer, Serialize};
@@ -19,8 +22,10 @@ use std::time::Duration;
 use std::{
     collections::{BTreeMap, HashMap},
     env::{self, VarError},
+    fmt,
     path::PathBuf,
     result,
+    str::FromStr,
 };
 use tuple::Map as _;
 
@@ -181,6 +186,13 @@ pub struct ContainerSpec {
     pub network: JobNetwork,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
+    pub groups: Vec<GroupId>,
+    pub synthesize_user_files: bool,
+    pub hostname: Option<String>,
+    pub hosts: Vec<JobHostsEntry>,
+    #[proto(default)]
+    pub capabilities: JobCapabilities,
+    pub init: bool,
 }
 
 impl ContainerSpec {
@@ -198,6 +210,52 @@ impl ContainerSpec {
         }
         Ok(())
     }
+
+    /// Sidecars only get their layers, environment, and working directory from their container.
+    /// They share everything else with the job they run alongside, so it's an error for their
+    /// container to set anything else.
+    pub fn check_for_sidecar(&self) -> Result<()> {
+        let Self {
+            image: _,
+            layers: _,
+            environment: _,
+            working_directory: _,
+            root_overlay,
+            mounts,
+            network,
+            user,
+            group,
+            groups,
+            synthesize_user_files,
+            hostname,
+            hosts,
+            capabilities,
+            init,
+        } = self;
+        let unsupported = [
+            ("root_overlay", *root_overlay != JobRootOverlay::None),
+            ("mounts", !mounts.is_empty()),
+            ("network", *network != JobNetwork::Disabled),
+            ("user", user.is_some()),
+            ("group", group.is_some()),
+            ("groups", !groups.is_empty()),
+            ("synthesize_user_files", *synthesize_user_files),
+            ("hostname", hostname.is_some()),
+            ("hosts", !hosts.is_empty()),
+            ("capabilities", !capabilities.is_empty()),
+            ("init", *init),
+        ]
+        .into_iter()
+        .filter_map(|(field, set)| set.then_some(format!("`{field}`")))
+        .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            bail!(
+                "sidecar containers can't set {}; sidecars share them with the job",
+                unsupported.join(", ")
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(IntoProtoBuf, TryFromProtoBuf, From, Clone, Debug, PartialEq, Eq)]
@@ -207,7 +265,13 @@ impl ContainerSpec {
 )]
 pub enum ContainerRef {
     Name(String),
-    Inline(ContainerSpec),
+    Inline(Box<ContainerSpec>),
+}
+
+impl From<ContainerSpec> for ContainerRef {
+    fn from(container: ContainerSpec) -> Self {
+        Self::Inline(Box::new(container))
+    }
 }
 
 impl ContainerRef {
@@ -228,6 +292,182 @@ impl ContainerRef {
     }
 }
 
+/// A helper process that runs alongside a job, in its own container but sharing the job's network
+/// namespace.
+#[derive(IntoProtoBuf, TryFromProtoBuf, Clone, Debug, PartialEq, Eq)]
+#[proto(proto_buf_type = "proto::JobSidecarSpec")]
+pub struct JobSidecarSpec {
+    #[proto(option)]
+    pub container: ContainerRef,
+    pub program: Utf8PathBuf,
+    pub arguments: Vec<String>,
+    pub ready_port: Option<u16>,
+    pub ready_timeout: Option<Timeout>,
+}
+
+impl JobSidecarSpec {
+    pub fn new(program: impl Into<String>, container: impl Into<ContainerRef>) -> Self {
+        JobSidecarSpec {
+            container: container.into(),
+            program: program.into().into(),
+            arguments: Default::default(),
+            ready_port: Default::default(),
+            ready_timeout: Default::default(),
+        }
+    }
+
+    pub fn arguments<I, T>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.arguments = arguments.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn ready_port(mut self, ready_port: Option<u16>) -> Self {
+        self.ready_port = ready_port;
+        self
+    }
+
+    pub fn ready_timeout(mut self, ready_timeout: Option<impl Into<Timeout>>) -> Self {
+        self.ready_timeout = ready_timeout.map(Into::into);
+        self
+    }
+}
+
+/// A struct used for deserializing sidecars in JSON, TOML, or other similar formats. A sidecar's
+/// container is given inline, and only has layers, an environment, and a working directory.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobSidecarForTomlAndJson {
+    pub program: Utf8PathBuf,
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    #[serde(default)]
+    pub layers: Vec<LayerSpec>,
+    pub image: Option<Image>,
+    pub environment: Option<BTreeMap<String, String>>,
+    pub working_directory: Option<Utf8PathBuf>,
+    pub ready_port: Option<u16>,
+    pub ready_timeout: Option<Timeout>,
+}
+
+impl TryFrom<JobSidecarForTomlAndJson> for JobSidecarSpec {
+    type Error = Error;
+
+    fn try_from(sidecar: JobSidecarForTomlAndJson) -> Result<Self> {
+        let image = sidecar.image.map(|image| ImageSpec {
+            name: image.name,
+            use_layers: image.use_.contains(ImageUse::Layers),
+            use_environment: image.use_.contains(ImageUse::Environment),
+            use_working_directory: image.use_.contains(ImageUse::WorkingDirectory),
+        });
+        if sidecar.layers.is_empty() && !image.as_ref().is_some_and(|image| image.use_layers) {
+            bail!("sidecar must have `layers` or an `image` with a `use` of `layers`");
+        }
+        if sidecar.working_directory.is_some()
+            && image
+                .as_ref()
+                .is_some_and(|image| image.use_working_directory)
+        {
+            bail!(concat!(
+                "sidecar cannot set `working_directory` if `image` with a `use` of ",
+                "`working_directory` is also set"
+            ));
+        }
+        let container = ContainerSpec {
+            image,
+            layers: sidecar.layers,
+            root_overlay: Default::default(),
+            environment: sidecar
+                .environment
+                .map(IntoEnvironment::into_environment)
+                .unwrap_or_default(),
+            working_directory: sidecar.working_directory,
+            mounts: Default::default(),
+            network: Default::default(),
+            user: Default::default(),
+            group: Default::default(),
+            groups: Default::default(),
+            synthesize_user_files: Default::default(),
+            hostname: Default::default(),
+            hosts: Default::default(),
+            capabilities: Default::default(),
+            init: Default::default(),
+        };
+        Ok(JobSidecarSpec::new(sidecar.program, container)
+            .arguments(sidecar.arguments)
+            .ready_port(sidecar.ready_port)
+            .ready_timeout(sidecar.ready_timeout))
+    }
+}
+
+/// Where the client gets a secret's value from. The value is read by the client when the job is
+/// submitted.
+#[derive(IntoProtoBuf, TryFromProtoBuf, Clone, Debug, PartialEq, Eq)]
+#[proto(
+    proto_buf_type = "proto::JobSecretSource",
+    enum_type = "proto::job_secret_source::Source"
+)]
+pub enum JobSecretSource {
+    /// The client's environment variable with the given name.
+    Environment(String),
+
+    /// The client's file at the given path, which is relative to the project directory.
+    File(Utf8PathBuf),
+}
+
+/// A secret for a job. Only the secret's source is recorded here. The client reads the value when
+/// it submits the job.
+#[derive(IntoProtoBuf, TryFromProtoBuf, Clone, Debug, PartialEq, Eq)]
+#[proto(proto_buf_type = "proto::JobSecretSpec")]
+pub struct JobSecretSpec {
+    #[proto(option)]
+    pub source: JobSecretSource,
+    #[proto(option)]
+    pub destination: JobSecretDestination,
+}
+
+impl JobSecretSpec {
+    pub fn new(source: JobSecretSource, destination: JobSecretDestination) -> Self {
+        Self {
+            source,
+            destination,
+        }
+    }
+}
+
+/// A struct used for deserializing secrets in JSON, TOML, or other similar formats. Exactly one of
+/// `variable` and `file` gives the destination, and exactly one of `from_env` and `from_file` gives
+/// the source.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobSecretForTomlAndJson {
+    pub variable: Option<String>,
+    pub file: Option<NonRootUtf8PathBuf>,
+    pub from_env: Option<String>,
+    pub from_file: Option<Utf8PathBuf>,
+}
+
+impl TryFrom<JobSecretForTomlAndJson> for JobSecretSpec {
+    type Error = Error;
+
+    fn try_from(secret: JobSecretForTomlAndJson) -> Result<Self> {
+        let destination = match (secret.variable, secret.file) {
+            (Some(variable), None) => JobSecretDestination::Environment(variable),
+            (None, Some(file)) => JobSecretDestination::File(file.into()),
+            _ => bail!("secret must have exactly one of `variable` or `file`"),
+        };
+        let source = match (secret.from_env, secret.from_file) {
+            (Some(var), None) => JobSecretSource::Environment(var),
+            (None, Some(path)) => JobSecretSource::File(path),
+            _ => bail!("secret must have exactly one of `from_env` or `from_file`"),
+        };
+        Ok(JobSecretSpec::new(source, destination))
+    }
+}
+
 #[derive(IntoProtoBuf, TryFromProtoBuf, Clone, Debug, PartialEq, Eq)]
 #[proto(proto_buf_type = "proto::JobSpec")]
 pub struct JobSpec {
@@ -239,6 +479,8 @@ pub struct JobSpec {
     pub estimated_duration: Option<Duration>,
     pub allocate_tty: Option<JobTty>,
     pub priority: i8,
+    pub sidecars: Vec<JobSidecarSpec>,
+    pub secrets: Vec<JobSecretSpec>,
 }
 
 impl JobSpec {
@@ -254,6 +496,12 @@ impl JobSpec {
                 working_directory: Default::default(),
                 user: Default::default(),
                 group: Default::default(),
+                groups: Default::default(),
+                synthesize_user_files: Default::default(),
+                hostname: Default::default(),
+                hosts: Default::default(),
+                capabilities: Default::default(),
+                init: Default::default(),
             }
             .into(),
             program: program.into().into(),
@@ -262,6 +510,8 @@ impl JobSpec {
             estimated_duration: Default::default(),
             allocate_tty: Default::default(),
             priority: Default::default(),
+            sidecars: Default::default(),
+            secrets: Default::default(),
         }
     }
 
@@ -315,6 +565,44 @@ impl JobSpec {
         self
     }
 
+    pub fn groups<I, T>(mut self, groups: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<GroupId>,
+    {
+        self.container.as_inline_mut().unwrap().groups =
+            groups.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn synthesize_user_files(mut self, synthesize_user_files: bool) -> Self {
+        self.container
+            .as_inline_mut()
+            .unwrap()
+            .synthesize_user_files = synthesize_user_files;
+        self
+    }
+
+    pub fn hostname(mut self, hostname: Option<impl Into<String>>) -> Self {
+        self.container.as_inline_mut().unwrap().hostname = hostname.map(Into::into);
+        self
+    }
+
+    pub fn hosts(mut self, hosts: impl IntoIterator<Item = JobHostsEntry>) -> Self {
+        self.container.as_inline_mut().unwrap().hosts = hosts.into_iter().collect();
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: JobCapabilities) -> Self {
+        self.container.as_inline_mut().unwrap().capabilities = capabilities;
+        self
+    }
+
+    pub fn init(mut self, init: bool) -> Self {
+        self.container.as_inline_mut().unwrap().init = init;
+        self
+    }
+
     pub fn timeout(mut self, timeout: Option<impl Into<Timeout>>) -> Self {
         self.timeout = timeout.map(Into::into);
         self
@@ -324,6 +612,16 @@ impl JobSpec {
         self.priority = priority;
         self
     }
+
+    pub fn sidecars(mut self, sidecars: impl IntoIterator<Item = JobSidecarSpec>) -> Self {
+        self.sidecars = sidecars.into_iter().collect();
+        self
+    }
+
+    pub fn secrets(mut self, secrets: impl IntoIterator<Item = JobSecretSpec>) -> Self {
+        self.secrets = secrets.into_iter().collect();
+        self
+    }
 }
 
 #[derive(
@@ -346,6 +644,65 @@ pub struct PrefixOptions {
     pub canonicalize: bool,
     #[serde(default)]
     pub follow_symlinks: bool,
+    /// Replace the permission bits of every regular file in the layer with these.
+    pub chmod: Option<u32>,
+    /// Make every file in the layer owned by this user and group.
+    pub chown: Option<FileOwner>,
+}
+
+/// The numeric user and group ids given to a layer's files with the `chown` prefix option. It's
+/// written as `"UID:GID"` in TOML and JSON.
+#[derive(
+    IntoProtoBuf, TryFromProtoBuf, Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize,
+)]
+#[proto(proto_buf_type = String, try_from_into)]
+#[serde(try_from = "String", into = "String")]
+pub struct FileOwner {
+    pub user: UserId,
+    pub group: GroupId,
+}
+
+impl FromStr for FileOwner {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (user, group) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("owner {s:?} must be of the form \"UID:GID\""))?;
+        Ok(Self {
+            user: UserId::new(user.parse()?),
+            group: GroupId::new(group.parse()?),
+        })
+    }
+}
+
+impl TryFrom<String> for FileOwner {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for FileOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.user, self.group)
+    }
+}
+
+impl From<FileOwner> for String {
+    fn from(owner: FileOwner) -> Self {
+        owner.to_string()
+    }
+}
+
+impl From<FileOwner> for maelstrom_base::manifest::Owner {
+    fn from(owner: FileOwner) -> Self {
+        Self {
+            user: owner.user,
+            group: owner.group,
+        }
+    }
 }
 
 #[derive(
@@ -366,6 +723,36 @@ pub struct SymlinkSpec {
     pub target: Utf8PathBuf,
 }
 
+/// The contents of a file in a `files` layer.
+#[derive(
+    Clone, Debug, Deserialize, Eq, Hash, IntoProtoBuf, PartialEq, Serialize, TryFromProtoBuf,
+)]
+#[proto(
+    proto_buf_type = "proto::FileContents",
+    enum_type = "proto::file_contents::Data"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FileContents {
+    /// The contents as text.
+    Contents(String),
+    /// The contents encoded as base64, for files that aren't text.
+    Base64(String),
+}
+
+/// A file in a `files` layer.
+#[derive(
+    Clone, Debug, Deserialize, Eq, Hash, IntoProtoBuf, PartialEq, Serialize, TryFromProtoBuf,
+)]
+#[proto(proto_buf_type = "proto::FileSpec")]
+pub struct FileSpec {
+    pub path: Utf8PathBuf,
+    #[serde(flatten)]
+    #[proto(option)]
+    pub contents: FileContents,
+    /// The file's permission bits. The default is `0o644`.
+    pub mode: Option<u32>,
+}
+
 #[derive(
     Clone, Debug, Deserialize, Eq, Hash, IntoProtoBuf, PartialEq, Serialize, TryFromProtoBuf,
 )]
@@ -380,6 +767,14 @@ pub enum LayerSpec {
         #[serde(rename = "tar")]
         path: Utf8PathBuf,
     },
+    #[proto(proto_buf_type = proto::ArchiveLayer)]
+    Archive {
+        #[serde(rename = "archive")]
+        path: Utf8PathBuf,
+        #[serde(flatten)]
+        #[proto(option)]
+        prefix_options: PrefixOptions,
+    },
     #[proto(proto_buf_type = proto::GlobLayer)]
     Glob {
         glob: String,
@@ -398,6 +793,8 @@ pub enum LayerSpec {
     Stubs { stubs: Vec<String> },
     #[proto(proto_buf_type = proto::SymlinksLayer)]
     Symlinks { symlinks: Vec<SymlinkSpec> },
+    #[proto(proto_buf_type = proto::FilesLayer)]
+    Files { files: Vec<FileSpec> },
     #[proto(proto_buf_type = proto::SharedLibraryDependenciesLayer)]
     SharedLibraryDependencies {
         #[serde(rename = "shared-library-dependencies")]
@@ -406,12 +803,41 @@ pub enum LayerSpec {
         #[proto(option)]
         prefix_options: PrefixOptions,
     },
+    #[proto(proto_buf_type = proto::GitLayer)]
+    Git {
+        #[serde(rename = "git")]
+        path: Utf8PathBuf,
+        #[serde(default)]
+        revision: Option<String>,
+        #[serde(default)]
+        untracked: bool,
+        #[serde(flatten)]
+        #[proto(option)]
+        prefix_options: PrefixOptions,
+    },
+    #[proto(proto_buf_type = proto::CommandLayer)]
+    Command {
+        command: Vec<String>,
+        output: Utf8PathBuf,
+        #[serde(default)]
+        inputs: Vec<Utf8PathBuf>,
+        #[serde(default)]
+        image: Option<String>,
+        #[serde(default)]
+        layers: Vec<LayerSpec>,
+        #[serde(flatten)]
+        #[proto(option)]
+        prefix_options: PrefixOptions,
+    },
 }
 
 impl LayerSpec {
     pub fn replace_template_vars(&mut self, vars: &TemplateVars) -> Result<()> {
         match self {
             Self::Tar { path } => *path = replace_template_vars(path.as_str(), vars)?.into(),
+            Self::Archive { path, .. } => {
+                *path = replace_template_vars(path.as_str(), vars)?.into()
+            }
             Self::Glob { glob, .. } => *glob = replace_template_vars(glob, vars)?,
             Self::Paths { paths, .. } => {
                 for path in paths {
@@ -429,16 +855,301 @@ impl LayerSpec {
                     *target = replace_template_vars(target.as_str(), vars)?.into();
                 }
             }
+            Self::Files { files } => {
+                for FileSpec { path, .. } in files {
+                    *path = replace_template_vars(path.as_str(), vars)?.into();
+                }
+            }
             Self::SharedLibraryDependencies { binary_paths, .. } => {
                 for path in binary_paths {
                     *path = replace_template_vars(path.as_str(), vars)?.into();
                 }
             }
+            Self::Git { path, .. } => *path = replace_template_vars(path.as_str(), vars)?.into(),
+            Self::Command {
+                output,
+                inputs,
+                layers,
+                ..
+            } => {
+                *output = replace_template_vars(output.as_str(), vars)?.into();
+                for input in inputs {
+                    *input = replace_template_vars(input.as_str(), vars)?.into();
+                }
+                for layer in layers {
+                    layer.replace_template_vars(vars)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub enum JobMountForTomlAndJson {
+    Bind {
+        mount_point: NonRootUtf8PathBuf,
+        local_path: Utf8PathBuf,
+        #[serde(default)]
+        read_only: bool,
+    },
+    Device {
+        path: NonRootUtf8PathBuf,
+    },
+    Devices {
+        devices: EnumSet<JobDeviceForTomlAndJson>,
+    },
+    Devpts {
+        mount_point: NonRootUtf8PathBuf,
+    },
+    Layer {
+        mount_point: NonRootUtf8PathBuf,
+        layer: LayerSpec,
+        #[serde(default)]
+        read_only: bool,
+    },
+    Mqueue {
+        mount_point: NonRootUtf8PathBuf,
+    },
+    Proc {
+        mount_point: NonRootUtf8PathBuf,
+    },
+    Sys {
+        mount_point: NonRootUtf8PathBuf,
+    },
+    Tmp {
+        mount_point: NonRootUtf8PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<ByteSizeForTomlAndJson>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uid: Option<UserId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gid: Option<GroupId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nr_inodes: Option<u64>,
+    },
+    Volume {
+        name: String,
+        mount_point: NonRootUtf8PathBuf,
+        size: ByteSizeForTomlAndJson,
+        #[serde(default)]
+        mode: JobVolumeMode,
+    },
+}
+
+/// A size in bytes, as given in TOML or JSON. Sizes may be given either as a number of bytes or as
+/// a string like `"64 MiB"`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ByteSizeForTomlAndJson(#[serde(with = "bytesize_serde")] ByteSize);
+
+impl From<u64> for ByteSizeForTomlAndJson {
+    fn from(bytes: u64) -> Self {
+        Self(ByteSize::b(bytes))
+    }
+}
+
+impl From<ByteSizeForTomlAndJson> for u64 {
+    fn from(size: ByteSizeForTomlAndJson) -> Self {
+        size.0.as_u64()
+    }
+}
+
+/// A mount in a [`ContainerSpec`]. This mirrors [`maelstrom_base::JobMount`], except that a layer
+/// mount is given as a [`LayerSpec`]. The client builds the layer before converting the mount into
+/// a [`maelstrom_base::JobMount`].
+#[derive(IntoProtoBuf, TryFromProtoBuf, Clone, Debug, PartialEq, Eq)]
+#[proto(
+    proto_buf_type = "proto::JobMount",
+    enum_type = "proto::job_mount::Mount"
+)]
+pub enum JobMount {
+    #[proto(proto_buf_type = proto::BindMount)]
+    Bind {
+        mount_point: Utf8PathBuf,
+        local_path: Utf8PathBuf,
+        read_only: bool,
+    },
+    #[proto(proto_buf_type = proto::DeviceMount)]
+    Device { path: Utf8PathBuf },
+    #[proto(proto_buf_type = proto::DevicesMount)]
+    Devices { devices: EnumSet<JobDevice> },
+    #[proto(proto_buf_type = proto::DevptsMount)]
+    Devpts { mount_point: Utf8PathBuf },
+    #[proto(proto_buf_type = proto::LayerMount)]
+    Layer {
+        mount_point: Utf8PathBuf,
+        #[proto(option)]
+        layer: LayerSpec,
+        read_only: bool,
+    },
+    #[proto(proto_buf_type = proto::MqueueMount)]
+    Mqueue { mount_point: Utf8PathBuf },
+    #[proto(proto_buf_type = proto::ProcMount)]
+    Proc { mount_point: Utf8PathBuf },
+    #[proto(proto_buf_type = proto::SysMount)]
+    Sys { mount_point: Utf8PathBuf },
+    #[proto(proto_buf_type = proto::TmpMount)]
+    Tmp {
+        mount_point: Utf8PathBuf,
+        size: Option<u64>,
+        mode: Option<u32>,
+        uid: Option<UserId>,
+        gid: Option<GroupId>,
+        nr_inodes: Option<u64>,
+    },
+    #[proto(proto_buf_type = proto::VolumeMount)]
+    Volume {
+        name: String,
+        mount_point: Utf8PathBuf,
+        size: u64,
+        mode: JobVolumeMode,
+    },
+}
+
+impl From<JobMountForTomlAndJson> for JobMount {
+    fn from(job_mount: JobMountForTomlAndJson) -> JobMount {
+        match job_mount {
+            JobMountForTomlAndJson::Bind {
+                mount_point,
+                local_path,
+                read_only,
+            } => JobMount::Bind {
+                mount_point: mount_point.into(),
+                local_path,
+                read_only,
+            },
+            JobMountForTomlAndJson::Device { path } => JobMount::Device { path: path.into() },
+            JobMountForTomlAndJson::Devices { devices } => JobMount::Devices {
+                devices: devices.into_iter().map(JobDevice::from).collect(),
+            },
+            JobMountForTomlAndJson::Devpts { mount_point } => JobMount::Devpts {
+                mount_point: mount_point.into(),
+            },
+            JobMountForTomlAndJson::Layer {
+                mount_point,
+                layer,
+                read_only,
+            } => JobMount::Layer {
+                mount_point: mount_point.into(),
+                layer,
+                read_only,
+            },
+            JobMountForTomlAndJson::Mqueue { mount_point } => JobMount::Mqueue {
+                mount_point: mount_point.into(),
+            },
+            JobMountForTomlAndJson::Proc { mount_point } => JobMount::Proc {
+                mount_point: mount_point.into(),
+            },
+            JobMountForTomlAndJson::Sys { mount_point } => JobMount::Sys {
+                mount_point: mount_point.into(),
+            },
+            JobMountForTomlAndJson::Tmp {
+                mount_point,
+                size,
+                mode,
+                uid,
+                gid,
+                nr_inodes,
+            } => JobMount::Tmp {
+                mount_point: mount_point.into(),
+                size: size.map(Into::into),
+                mode,
+                uid,
+                gid,
+                nr_inodes,
+            },
+            JobMountForTomlAndJson::Volume {
+                name,
+                mount_point,
+                size,
+                mode,
+            } => JobMount::Volume {
+                name,
+                mount_point: mount_point.into(),
+                size: size.into(),
+                mode,
+            },
+        }
+    }
+}
+
+impl JobMountForTomlAndJson {
+    pub fn replace_template_vars(&mut self, vars: &TemplateVars) -> Result<()> {
+        if let Self::Layer { layer, .. } = self {
+            layer.replace_template_vars(vars)?;
         }
         Ok(())
     }
 }
 
+impl JobMount {
+    /// Convert into a [`maelstrom_base::JobMount`]. The `layer` argument must be the built layer
+    /// if this is a layer mount, and is ignored otherwise.
+    pub fn into_base(
+        self,
+        layer: Option<(Sha256Digest, ArtifactType)>,
+    ) -> maelstrom_base::JobMount {
+        match self {
+            Self::Bind {
+                mount_point,
+                local_path,
+                read_only,
+            } => maelstrom_base::JobMount::Bind {
+                mount_point,
+                local_path,
+                read_only,
+            },
+            Self::Device { path } => maelstrom_base::JobMount::Device { path },
+            Self::Devices { devices } => maelstrom_base::JobMount::Devices { devices },
+            Self::Devpts { mount_point } => maelstrom_base::JobMount::Devpts { mount_point },
+            Self::Layer {
+                mount_point,
+                read_only,
+                ..
+            } => maelstrom_base::JobMount::Layer {
+                mount_point,
+                layer: layer.expect("layer mount must be given a layer"),
+                read_only,
+            },
+            Self::Mqueue { mount_point } => maelstrom_base::JobMount::Mqueue { mount_point },
+            Self::Proc { mount_point } => maelstrom_base::JobMount::Proc { mount_point },
+            Self::Sys { mount_point } => maelstrom_base::JobMount::Sys { mount_point },
+            Self::Tmp {
+                mount_point,
+                size,
+                mode,
+                uid,
+                gid,
+                nr_inodes,
+            } => maelstrom_base::JobMount::Tmp {
+                mount_point,
+                size,
+                mode,
+                uid,
+                gid,
+                nr_inodes,
+            },
+            Self::Volume {
+                name,
+                mount_point,
+                size,
+                mode,
+            } => maelstrom_base::JobMount::Volume {
+                name,
+                mount_point,
+                size,
+                mode,
+            },
+        }
+    }
+}
+
 /// An enum and struct (`EnumSet<ImageUse>`) used for deserializing "image use" statements in JSON,
 /// TOML, or other similar formats. This allows users to specify things like
 /// `use = ["layers", "environment"]` in TOML, or the equivalent in JSON.
@@ -595,6 +1306,164 @@ mod tests {
     use maplit::btreemap;
     use std::{ffi::OsStr, os::unix::ffi::OsStrExt as _};
 
+    fn deserialize_value<T: for<'a> Deserialize<'a>>(file: &str) -> T {
+        T::deserialize(toml::de::ValueDeserializer::new(file)).unwrap()
+    }
+
+    #[test]
+    fn bind_mount_no_read_only() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "bind", mount_point = "/mnt", local_path = "/a" }"#);
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Bind {
+                mount_point: Utf8PathBuf::from("/mnt"),
+                local_path: Utf8PathBuf::from("/a"),
+                read_only: false,
+            }
+        );
+    }
+
+    #[test]
+    fn layer_mount() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "layer", mount_point = "/data", layer = { paths = ["a", "b"], strip_prefix = "a" } }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Layer {
+                mount_point: Utf8PathBuf::from("/data"),
+                layer: LayerSpec::Paths {
+                    paths: vec![Utf8PathBuf::from("a"), Utf8PathBuf::from("b")],
+                    prefix_options: PrefixOptions {
+                        strip_prefix: Some("a".into()),
+                        ..Default::default()
+                    },
+                },
+                read_only: false,
+            }
+        );
+    }
+
+    #[test]
+    fn layer_mount_read_only() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "layer", mount_point = "/data", layer = { tar = "foo.tar" }, read_only = true }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Layer {
+                mount_point: Utf8PathBuf::from("/data"),
+                layer: tar_layer!("foo.tar"),
+                read_only: true,
+            }
+        );
+    }
+
+    #[test]
+    fn tmp_mount_no_options() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "tmp", mount_point = "/tmp" }"#);
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Tmp {
+                mount_point: Utf8PathBuf::from("/tmp"),
+                size: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                nr_inodes: None,
+            }
+        );
+    }
+
+    #[test]
+    fn tmp_mount_options() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "tmp", mount_point = "/tmp", size = "64 MiB", mode = 0o1777, uid = 1, gid = 2, nr_inodes = 1000 }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Tmp {
+                mount_point: Utf8PathBuf::from("/tmp"),
+                size: Some(64 * 1024 * 1024),
+                mode: Some(0o1777),
+                uid: Some(UserId::from(1)),
+                gid: Some(GroupId::from(2)),
+                nr_inodes: Some(1000),
+            }
+        );
+    }
+
+    #[test]
+    fn tmp_mount_size_in_bytes() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "tmp", mount_point = "/tmp", size = 4096 }"#);
+        assert_eq!(
+            job_mount,
+            JobMountForTomlAndJson::Tmp {
+                mount_point: NonRootUtf8PathBuf::try_from(Utf8PathBuf::from("/tmp")).unwrap(),
+                size: Some(ByteSizeForTomlAndJson::from(4096)),
+                mode: None,
+                uid: None,
+                gid: None,
+                nr_inodes: None,
+            }
+        );
+    }
+
+    #[test]
+    fn device_mount() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "device", path = "/dev/net/tun" }"#);
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Device {
+                path: Utf8PathBuf::from("/dev/net/tun"),
+            }
+        );
+    }
+
+    #[test]
+    fn volume_mount() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "volume", name = "pip", mount_point = "/root/.cache/pip", size = "1 GiB" }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Volume {
+                name: "pip".into(),
+                mount_point: Utf8PathBuf::from("/root/.cache/pip"),
+                size: 1024 * 1024 * 1024,
+                mode: JobVolumeMode::Shared,
+            }
+        );
+    }
+
+    #[test]
+    fn volume_mount_exclusive() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "volume", name = "pip", mount_point = "/cache", size = 4096, mode = "exclusive" }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Volume {
+                name: "pip".into(),
+                mount_point: Utf8PathBuf::from("/cache"),
+                size: 4096,
+                mode: JobVolumeMode::Exclusive,
+            }
+        );
+    }
+
     #[test]
     fn std_env_lookup_good() {
         let var = "AN_ENVIRONMENT_VARIABLE_1";
@@ -863,4 +1732,64 @@ mod tests {
             })
         );
     }
+
+    fn sidecar_container() -> ContainerSpec {
+        JobSidecarSpec::try_from(JobSidecarForTomlAndJson {
+            program: "redis-server".into(),
+            arguments: vec![],
+            layers: vec![tar_layer!("redis.tar")],
+            image: None,
+            environment: None,
+            working_directory: None,
+            ready_port: None,
+            ready_timeout: None,
+        })
+        .unwrap()
+        .container
+        .as_inline()
+        .unwrap()
+        .clone()
+    }
+
+    #[test]
+    fn check_for_sidecar_accepts_defaults() {
+        sidecar_container().check_for_sidecar().unwrap();
+    }
+
+    #[test]
+    fn check_for_sidecar_rejects_fields_shared_with_job() {
+        let container = ContainerSpec {
+            user: Some(UserId::new(1000)),
+            network: JobNetwork::Loopback,
+            init: true,
+            ..sidecar_container()
+        };
+        assert_error(
+            container.check_for_sidecar().unwrap_err(),
+            "sidecar containers can't set `network`, `user`, `init`; sidecars share them with the job",
+        );
+    }
+
+    #[test]
+    fn file_owner_from_str() {
+        assert_eq!(
+            "1000:2000".parse::<FileOwner>().unwrap(),
+            FileOwner {
+                user: UserId::new(1000),
+                group: GroupId::new(2000),
+            }
+        );
+        assert_eq!(
+            "1000".parse::<FileOwner>().unwrap_err().to_string(),
+            r#"owner "1000" must be of the form "UID:GID""#
+        );
+        "1000:staff".parse::<FileOwner>().unwrap_err();
+    }
+
+    #[test]
+    fn file_owner_display_round_trips() {
+        let owner: FileOwner = "0:100".parse().unwrap();
+        assert_eq!(owner.to_string(), "0:100");
+        assert_eq!(owner.to_string().parse::<FileOwner>().unwrap(), owner);
+    }
 }
@@ -11,23 +11,29 @@ use crate::{
 use anyhow::{anyhow, bail, Context as _, Result};
 use assert_matches::assert_matches;
 use async_trait::async_trait;
-use layer_builder::LayerBuilder;
+use futures::{future::BoxFuture, FutureExt as _, StreamExt as _};
+pub use layer_builder::LayerBuilder;
 use layer_cache::{CacheResult, LayerCache};
 use maelstrom_base::{
-    proto::{Hello, WorkerToBroker},
-    ArtifactType, Sha256Digest,
+    manifest::ManifestChunk,
+    proto::{BrokerToRelay, Hello, RelaySide, WorkerToBroker},
+    ArtifactType, JobNetwork, JobOutcome, JobRootOverlay, JobSecretDestination, JobSecretValue,
+    JobSecrets, JobSecretsAddress, JobTerminationStatus, NonEmpty, Sha256Digest, Utf8PathBuf,
 };
 use maelstrom_client_base::{
     spec::{
-        environment_eval, std_env_lookup, ContainerRef, ContainerSpec, ConvertedImage, ImageConfig,
-        JobSpec, LayerSpec,
+        environment_eval, std_env_lookup, ContainerRef, ContainerSpec, ConvertedImage,
+        EnvironmentSpec, ImageConfig, ImageSpec, JobMount, JobSecretSource, JobSecretSpec, JobSpec,
+        LayerSpec, PrefixOptions,
     },
     AcceptInvalidRemoteContainerTlsCerts, CacheDir, IntrospectResponse, JobStatus, ProjectDir,
-    StateDir, MANIFEST_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
+    StateDir, ARCHIVE_BLOB_DIR, CHUNK_DIR, COMMAND_LAYER_DIR, FILES_MANIFEST_DIR, GIT_BLOB_DIR,
+    MANIFEST_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
 };
 use maelstrom_container::{
     self as container, ContainerImage, ContainerImageDepot, ContainerImageDepotDir,
 };
+use maelstrom_linux::{self as linux, SocketType};
 use maelstrom_util::{
     async_fs,
     config::common::{BrokerAddr, CacheSize, InlineLimit, Slots},
@@ -43,11 +49,12 @@ use std::pin::Pin;
 use std::{
     collections::{HashMap, HashSet},
     mem,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::SystemTime,
 };
 use tokio::{
-    net::TcpStream,
+    net::{TcpStream, UnixListener},
     sync::{mpsc, Mutex},
     task::{self, JoinSet},
 };
@@ -58,12 +65,15 @@ pub struct Client {
     clean_up: Arc<CleanUpWork>,
 }
 
+#[derive(Clone)]
 struct ClientState {
+    broker_addr: Option<BrokerAddr>,
     local_broker_sender: router::Sender,
+    project_dir: RootBuf<ProjectDir>,
     layer_builder: Arc<LayerBuilder>,
     artifact_upload_tracker: ProgressTracker,
     image_download_tracker: ProgressTracker,
-    container_image_depot: ContainerImageDepot,
+    container_image_depot: Arc<ContainerImageDepot>,
     log: Logger,
     locked: Arc<Mutex<ClientStateLocked>>,
     layer_building_semaphore: Arc<tokio::sync::Semaphore>,
@@ -120,12 +130,51 @@ impl Uploader {
                 .await?;
             digest
         };
-        if !locked.processed_artifact_digests.contains(&digest) {
+        self.add_artifact(&mut locked, path, &digest)?;
+        Ok(digest)
+    }
+
+    fn add_artifact(
+        &self,
+        locked: &mut ClientStateLocked,
+        path: PathBuf,
+        digest: &Sha256Digest,
+    ) -> Result<()> {
+        if !locked.processed_artifact_digests.contains(digest) {
             locked.processed_artifact_digests.insert(digest.clone());
             self.local_broker_sender
                 .send(router::Message::AddArtifact(path, digest.clone()))?;
         }
-        Ok(digest)
+        Ok(())
+    }
+
+    /// Chunks are named after their digests, so they don't go through the digest repository.
+    async fn upload_chunk(&self, path: &Path, digest: &Sha256Digest) -> Result<()> {
+        debug!(self.log, "add_artifact"; "path" => ?path);
+
+        let mut locked = self.locked.lock().await;
+        self.add_artifact(&mut locked, path.to_owned(), digest)
+    }
+
+    async fn cached_chunks(&self, path: &Path) -> Result<Option<Vec<ManifestChunk>>> {
+        let path = async_fs::Fs::new().canonicalize(path).await?;
+        self.locked.lock().await.digest_repo.get_chunks(&path).await
+    }
+
+    async fn cache_chunks(
+        &self,
+        path: &Path,
+        mtime: SystemTime,
+        size: u64,
+        chunks: &[ManifestChunk],
+    ) -> Result<()> {
+        let path = async_fs::Fs::new().canonicalize(path).await?;
+        self.locked
+            .lock()
+            .await
+            .digest_repo
+            .add_chunks(path, mtime, size, chunks)
+            .await
     }
 }
 
@@ -134,6 +183,24 @@ impl<'a> maelstrom_util::manifest::DataUpload for &'a Uploader {
     async fn upload(&mut self, path: &Path) -> Result<Sha256Digest> {
         Uploader::upload(self, path).await
     }
+
+    async fn upload_chunk(&mut self, path: &Path, digest: &Sha256Digest) -> Result<()> {
+        Uploader::upload_chunk(self, path, digest).await
+    }
+
+    async fn cached_chunks(&mut self, path: &Path) -> Result<Option<Vec<ManifestChunk>>> {
+        Uploader::cached_chunks(self, path).await
+    }
+
+    async fn cache_chunks(
+        &mut self,
+        path: &Path,
+        mtime: SystemTime,
+        size: u64,
+        chunks: &[ManifestChunk],
+    ) -> Result<()> {
+        Uploader::cache_chunks(self, path, mtime, size, chunks).await
+    }
 }
 
 impl ClientState {
@@ -143,22 +210,125 @@ impl ClientState {
             local_broker_sender: self.local_broker_sender.clone(),
             locked: self.locked.clone(),
         };
-        let layer_builder = self.layer_builder.clone();
-        let sem = self.layer_building_semaphore.clone();
-        let locked = self.locked.clone();
+        let state = self.clone();
         tokio::task::spawn(async move {
             let build_fn = async {
-                let _permit = sem.acquire().await.unwrap();
-                let (artifact_path, artifact_type) =
-                    layer_builder.build_layer(layer.clone(), &uploader).await?;
+                let (artifact_path, artifact_type) = match &layer {
+                    LayerSpec::Command { image, layers, .. }
+                        if image.is_some() || !layers.is_empty() =>
+                    {
+                        state.build_command_layer(layer.clone(), &uploader).await?
+                    }
+                    _ => {
+                        let _permit = state.layer_building_semaphore.acquire().await.unwrap();
+                        state
+                            .layer_builder
+                            .build_layer(layer.clone(), &uploader)
+                            .await?
+                    }
+                };
                 let artifact_digest = uploader.upload(&artifact_path).await?;
                 Result::<_>::Ok((artifact_digest, artifact_type))
             };
             let res = build_fn.await;
-            locked.lock().await.cached_layers.fill(&layer, res);
+            state.locked.lock().await.cached_layers.fill(&layer, res);
         });
     }
 
+    /// Build a [`LayerSpec::Command`] layer by running its command as a job, in a container made
+    /// from the layer's image and layers. The job's changes to its root file system are kept in
+    /// the cache directory, so the job is only run again when the command, the base layers, or the
+    /// inputs change.
+    ///
+    /// The base layers are resolved before taking a layer-building permit, since building them
+    /// may need permits of its own.
+    async fn build_command_layer(
+        &self,
+        layer: LayerSpec,
+        uploader: &Uploader,
+    ) -> Result<(PathBuf, ArtifactType)> {
+        let LayerSpec::Command {
+            command,
+            output,
+            inputs,
+            image,
+            layers,
+            prefix_options,
+        } = layer
+        else {
+            panic!("expected a command layer");
+        };
+        let Some((program, arguments)) = command.split_first() else {
+            bail!("a command layer's command can't be empty");
+        };
+        if output.is_relative() {
+            bail!("the output of a command layer run as a job must be an absolute path");
+        }
+        let image = image.map(|name| ImageSpec {
+            name,
+            use_layers: true,
+            use_environment: true,
+            use_working_directory: true,
+        });
+        let base_layers: Vec<_> = self
+            .resolve_container(image.clone(), layers.clone(), vec![], None)
+            .await?
+            .layers
+            .into();
+        let key = self
+            .layer_builder
+            .command_layer_key(&command, &output, &inputs, &base_layers)
+            .await?;
+
+        let command = &command;
+        let upper = self
+            .layer_builder
+            .command_layer_upper_dir(&key, |upper, work| async move {
+                let mut spec = JobSpec::new(program.clone(), layers)
+                    .arguments(arguments.iter().cloned())
+                    .network(JobNetwork::Local)
+                    .root_overlay(JobRootOverlay::Local { upper, work });
+                if let Some(image) = image {
+                    spec = spec.image(image);
+                }
+                let mut statuses = self.run_job(spec).await?;
+                let result = loop {
+                    match statuses.next().await {
+                        Some(JobStatus::Completed { result, .. }) => break result,
+                        Some(JobStatus::Running(_)) => continue,
+                        None => bail!("client shut down while running command layer job"),
+                    }
+                };
+                match result.map_err(|err| anyhow!("error running command layer job: {err:?}"))? {
+                    JobOutcome::Completed(completed) => {
+                        if completed.status != JobTerminationStatus::Exited(0) {
+                            bail!(
+                                "command `{}` failed:\nstderr: {}\nstdout: {}",
+                                command.join(" "),
+                                completed.effects.stderr,
+                                completed.effects.stdout
+                            );
+                        }
+                    }
+                    JobOutcome::TimedOut(_) => bail!("command `{}` timed out", command.join(" ")),
+                }
+                Ok(())
+            })
+            .await?;
+
+        // The output is inside of the container, so there is nothing to canonicalize.
+        let prefix_options = PrefixOptions {
+            canonicalize: false,
+            ..prefix_options
+        };
+        let _permit = self.layer_building_semaphore.acquire().await.unwrap();
+        let manifest_path = self
+            .layer_builder
+            .build_command_manifest(&upper, &output, &key, prefix_options, uploader)
+            .await?;
+        Ok((manifest_path, ArtifactType::Manifest))
+    }
+
     async fn get_layers(
         &self,
         layers: Vec<LayerSpec>,
@@ -195,6 +365,270 @@ impl ClientState {
             .collect())
     }
 
+    /// Look up the container, resolving it by name if necessary.
+    async fn get_container(&self, container: ContainerRef) -> Result<ContainerSpec> {
+        match container {
+            ContainerRef::Name(n) => {
+                let locked = self.locked.lock().await;
+                Ok(locked
+                    .containers
+                    .get(&n)
+                    .ok_or_else(|| anyhow!("container {n:?} unknown"))?
+                    .clone())
+            }
+            ContainerRef::Inline(c) => {
+                c.check_for_local_network_and_sys_mount()?;
+                Ok(*c)
+            }
+        }
+    }
+
+    /// Combine a container's image with its own layers, environment, and working directory, and
+    /// get all of the resulting layers.
+    async fn resolve_container(
+        &self,
+        image: Option<ImageSpec>,
+        mut layers: Vec<LayerSpec>,
+        environment: Vec<EnvironmentSpec>,
+        working_directory: Option<Utf8PathBuf>,
+    ) -> Result<ResolvedContainer> {
+        let mut initial_env = Default::default();
+        let mut image_working_directory = None;
+        if let Some(image_spec) = image {
+            let image = self.get_container_image(&image_spec.name).await?;
+            let image_config = ImageConfig {
+                layers: image.layers.clone(),
+                environment: image.env().cloned(),
+                working_directory: image.working_dir().map(From::from),
+            };
+            let image = ConvertedImage::new(&image_spec.name, image_config);
+            if image_spec.use_layers {
+                let end = mem::replace(&mut layers, image.layers()?);
+                layers.extend(end);
+            }
+            if image_spec.use_environment {
+                initial_env = image.environment()?;
+            }
+            if image_spec.use_working_directory {
+                image_working_directory = Some(image.working_directory()?);
+            }
+        }
+        if image_working_directory.is_some() && working_directory.is_some() {
+            bail!("can't provide both `working_directory` and `image.use_working_directory`");
+        }
+
+        Ok(ResolvedContainer {
+            layers: self
+                .get_layers(layers)
+                .await?
+                .try_into()
+                .map_err(|_| anyhow!("missing layers"))?,
+            environment: environment_eval(initial_env, environment, std_env_lookup)?,
+            working_directory: image_working_directory.or(working_directory),
+        })
+    }
+
+    /// Read the values of a job's secrets. This is done as late as possible, and the values are
+    /// only ever kept in [`JobSecretValue`]s, so that they don't show up in any log messages.
+    async fn resolve_secrets(
+        &self,
+        secrets: Vec<JobSecretSpec>,
+    ) -> Result<(Vec<JobSecretDestination>, Vec<JobSecretValue>)> {
+        let fs = async_fs::Fs::new();
+        let mut destinations = vec![];
+        let mut values = vec![];
+        for JobSecretSpec {
+            source,
+            destination,
+        } in secrets
+        {
+            let value = match source {
+                JobSecretSource::Environment(var) => std_env_lookup(&var)?
+                    .ok_or_else(|| anyhow!("secret environment variable {var:?} isn't set"))?
+                    .into_bytes(),
+                JobSecretSource::File(path) => fs.read(self.project_dir.join::<()>(path)).await?,
+            };
+            destinations.push(destination);
+            values.push(JobSecretValue::new(value));
+        }
+        Ok((destinations, values))
+    }
+
+    /// Get ready to hand a job's secret values to the worker that runs it, so that they never go
+    /// through the broker's scheduler. Returns the address to give the worker, and a future that
+    /// sends the values each time a worker connects.
+    ///
+    /// A job may be started more than once, like when its worker disconnects and the broker gives
+    /// it to another one, so the values are handed out once per attempt. The future never finishes
+    /// on its own: it's aborted once the job completes.
+    ///
+    /// Jobs that go to the broker get the values through a relay on the broker, just like a TTY.
+    /// Jobs that run on the local worker connect to us directly.
+    async fn serve_secrets(
+        &self,
+        spec: &maelstrom_base::JobSpec,
+        values: Vec<JobSecretValue>,
+    ) -> Result<(JobSecretsAddress, BoxFuture<'static, Result<()>>)> {
+        let log = self.log.clone();
+        match self.broker_addr {
+            Some(broker_addr) if !spec.must_be_run_locally() => {
+                let key: [u8; 16] = rand::random();
+                let mut socket = connect_secrets_relay(broker_addr, key, &log).await?;
+                let server = async move {
+                    // The broker pairs each of our connections with one job connection, so we
+                    // need a new one waiting for the next attempt.
+                    loop {
+                        let BrokerToRelay::Connected =
+                            net::read_message_from_async_socket(&mut socket, &log).await?;
+                        net::write_message_to_async_socket(&mut socket, &values, &log).await?;
+                        socket = connect_secrets_relay(broker_addr, key, &log).await?;
+                    }
+                };
+                Ok((JobSecretsAddress::Relay(key), server.boxed()))
+            }
+            _ => {
+                let (listener, address) = linux::autobound_unix_listener(SocketType::NONBLOCK, 1)?;
+                let listener = UnixListener::from_std(listener.into())?;
+                let server = async move {
+                    // Anybody on the machine can connect to an abstract socket, so we only give
+                    // the values to our own user, which the local worker runs as.
+                    loop {
+                        let (mut socket, _) = listener.accept().await?;
+                        if socket.peer_cred()?.uid() == linux::getuid().as_u32() {
+                            net::write_message_to_async_socket(&mut socket, &values, &log).await?;
+                        }
+                    }
+                };
+                Ok((JobSecretsAddress::Local(address), server.boxed()))
+            }
+        }
+    }
+
+    async fn run_job(
+        &self,
+        spec: JobSpec,
+    ) -> Result<futures::channel::mpsc::UnboundedReceiver<JobStatus>> {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        debug!(self.log, "run_job"; "spec" => ?spec);
+
+        let mut container = self.get_container(spec.container).await?;
+        if !spec.sidecars.is_empty() && container.network == JobNetwork::Disabled {
+            bail!("jobs with sidecars need a `network` of `loopback` or `local`");
+        }
+        let ResolvedContainer {
+            layers,
+            environment,
+            working_directory,
+        } = self
+            .resolve_container(
+                container.image.take(),
+                mem::take(&mut container.layers),
+                mem::take(&mut container.environment),
+                container.working_directory.take(),
+            )
+            .await?;
+
+        let mount_layers = container
+            .mounts
+            .iter()
+            .filter_map(|mount| match mount {
+                JobMount::Layer { layer, .. } => Some(layer.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut converted_mount_layers = self.get_layers(mount_layers).await?.into_iter();
+        let mounts = container
+            .mounts
+            .into_iter()
+            .map(|mount| {
+                let layer = matches!(mount, JobMount::Layer { .. })
+                    .then(|| converted_mount_layers.next().unwrap());
+                mount.into_base(layer)
+            })
+            .collect();
+
+        let mut sidecars = vec![];
+        for sidecar in spec.sidecars {
+            let container = self.get_container(sidecar.container).await?;
+            container.check_for_sidecar()?;
+            let ResolvedContainer {
+                layers,
+                environment,
+                working_directory,
+            } = self
+                .resolve_container(
+                    container.image,
+                    container.layers,
+                    container.environment,
+                    container.working_directory,
+                )
+                .await?;
+            sidecars.push(maelstrom_base::JobSidecar {
+                program: sidecar.program,
+                arguments: sidecar.arguments,
+                environment,
+                layers,
+                working_directory,
+                ready_port: sidecar.ready_port,
+                ready_timeout: sidecar.ready_timeout,
+            });
+        }
+
+        let (secret_destinations, secret_values) = self.resolve_secrets(spec.secrets).await?;
+
+        let mut spec = maelstrom_base::JobSpec {
+            program: spec.program,
+            arguments: spec.arguments,
+            environment,
+            layers,
+            mounts,
+            network: container.network,
+            root_overlay: container.root_overlay,
+            working_directory,
+            user: container.user,
+            group: container.group,
+            groups: container.groups,
+            synthesize_user_files: container.synthesize_user_files,
+            hostname: container.hostname,
+            hosts: container.hosts,
+            capabilities: container.capabilities,
+            init: container.init,
+            timeout: spec.timeout,
+            estimated_duration: spec.estimated_duration,
+            allocate_tty: spec.allocate_tty,
+            priority: spec.priority,
+            sidecars,
+            secrets: None,
+        };
+
+        // The secrets are served until the job completes, whether or not a worker ever asks for
+        // them, and no longer.
+        let sender = if secret_destinations.is_empty() {
+            sender
+        } else {
+            let (address, server) = self.serve_secrets(&spec, secret_values).await?;
+            spec.secrets = Some(JobSecrets::new(address, secret_destinations));
+            let log = self.log.clone();
+            let server = task::spawn(async move {
+                if let Err(err) = server.await {
+                    warn!(log, "error sending secrets to job"; "error" => %err);
+                }
+            });
+            let (job_sender, mut job_receiver) = futures::channel::mpsc::unbounded();
+            task::spawn(async move {
+                while let Some(status) = job_receiver.next().await {
+                    let _ = sender.unbounded_send(status);
+                }
+                server.abort();
+            });
+            job_sender
+        };
+
+        self.local_broker_sender
+            .send(router::Message::RunJob(Box::new(spec), sender))?;
+        Ok(receiver)
+    }
+
     async fn get_container_image(&self, name: &str) -> Result<ContainerImage> {
         let dl_name = name.to_owned();
         let tracker = self.image_download_tracker.clone();
@@ -205,6 +639,32 @@ impl ClientState {
     }
 }
 
+/// Connect to the broker as the client side of the relay that delivers a job's secrets.
+async fn connect_secrets_relay(
+    broker_addr: BrokerAddr,
+    key: [u8; 16],
+    log: &Logger,
+) -> Result<TcpStream> {
+    let mut socket = TcpStream::connect(broker_addr.inner()).await?;
+    net::write_message_to_async_socket(
+        &mut socket,
+        Hello::Relay {
+            key,
+            side: RelaySide::Client,
+        },
+        log,
+    )
+    .await?;
+    Ok(socket)
+}
+
+/// The parts of a container that are resolved by the client before a job is sent to the broker.
+struct ResolvedContainer {
+    layers: NonEmpty<(Sha256Digest, ArtifactType)>,
+    environment: Vec<String>,
+    working_directory: Option<Utf8PathBuf>,
+}
+
 /// For files under this size, the data is stashed in the manifest rather than uploaded separately
 const MANIFEST_INLINE_LIMIT: u64 = 200 * 1024;
 
@@ -280,9 +740,27 @@ impl Client {
             // Ensure all of the appropriate subdirectories have been created in the cache
             // directory.
             const LOCAL_WORKER_DIR: &str = "local-worker";
-            for d in [STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR, LOCAL_WORKER_DIR] {
+            for d in [
+                STUB_MANIFEST_DIR,
+                SYMLINK_MANIFEST_DIR,
+                FILES_MANIFEST_DIR,
+                CHUNK_DIR,
+                ARCHIVE_BLOB_DIR,
+                GIT_BLOB_DIR,
+                COMMAND_LAYER_DIR,
+                LOCAL_WORKER_DIR,
+            ] {
                 fs.create_dir_all((**cache_dir).join(d)).await?;
             }
+            // Command layer outputs and blobs are kept between runs, as long as they keep being
+            // used.
+            for d in [COMMAND_LAYER_DIR, ARCHIVE_BLOB_DIR, GIT_BLOB_DIR] {
+                layer_builder::prune_unused_entries(
+                    &(**cache_dir).join(d),
+                    layer_builder::UNUSED_CACHE_ENTRY_MAX_AGE,
+                )
+                .await?;
+            }
 
             // Create standalone sub-components.
             let container_image_depot = ContainerImageDepot::new(
@@ -290,7 +768,12 @@ impl Client {
                 container_image_depot_cache_dir,
                 accept_invalid_remote_container_tls_certs.into_inner(),
             )?;
-            let digest_repo = DigestRepository::new(&cache_dir);
+            // Chunks are kept between runs so large files don't have to be split again, but only
+            // as long as the files they came from are unchanged.
+            let mut digest_repo = DigestRepository::new(&cache_dir);
+            digest_repo
+                .prune_chunks(&(**cache_dir).join(CHUNK_DIR))
+                .await?;
             let artifact_upload_tracker = ProgressTracker::default();
             let image_download_tracker = ProgressTracker::default();
 
@@ -381,18 +864,23 @@ impl Client {
                 let cache_root = cache_dir.join::<local_worker::WorkerCacheDir>(LOCAL_WORKER_DIR);
                 let mount_dir = cache_root.join::<local_worker::MountDir>("mount");
                 let tmpfs_dir = cache_root.join::<local_worker::TmpfsDir>("upper");
+                let volume_cache_root = cache_root.join::<local_worker::CacheDir>("volumes");
                 let cache_root = cache_root.join::<local_worker::CacheDir>("artifacts");
                 let blob_dir = cache_root.join::<local_worker::BlobDir>("sha256/blob");
 
                 // Create the local_worker's cache. This is the same cache as the "real" worker
-                // uses.
-                let (local_worker_cache, local_worker_temp_file_factory) =
-                    local_worker::Cache::new(
-                        local_worker::Fs,
-                        cache_root,
-                        cache_size,
-                        log.clone(),
-                    )?;
+                // uses. The local worker uses the same size target for volumes as for artifacts.
+                let (
+                    local_worker_cache,
+                    local_worker_temp_file_factory,
+                    local_worker_volume_temp_file_factory,
+                ) = local_worker::Cache::new(
+                    cache_root,
+                    cache_size,
+                    volume_cache_root,
+                    cache_size,
+                    log.clone(),
+                )?;
 
                 // Create the local_worker's deps. This the same adapter as the "real" worker uses.
                 let local_worker_dispatcher_adapter = local_worker::DispatcherAdapter::new(
@@ -403,6 +891,11 @@ impl Client {
                     tmpfs_dir,
                     blob_dir,
                     local_worker_temp_file_factory,
+                    local_worker_volume_temp_file_factory,
+                    broker_addr,
+                    // The local worker runs on the client's own machine, so it allows all devices.
+                    None,
+                    local_worker::LayerStacking::Fuse,
                 )?;
 
                 // Create an ArtifactFetcher for the local_worker that just forwards requests to
@@ -440,6 +933,8 @@ impl Client {
                     worker_broker_sender,
                     local_worker_cache,
                     slots,
+                    // The local worker keeps one pre-warmed sandbox around per slot.
+                    u16::from(slots).into(),
                 );
 
                 let handle_worker_message =
@@ -491,7 +986,9 @@ impl Client {
 
             Ok((
                 ClientState {
+                    broker_addr,
                     local_broker_sender,
+                    project_dir: project_dir.clone(),
                     layer_builder: Arc::new(LayerBuilder::new(
                         cache_dir,
                         project_dir,
@@ -499,7 +996,7 @@ impl Client {
                     )),
                     artifact_upload_tracker,
                     image_download_tracker,
-                    container_image_depot,
+                    container_image_depot: Arc::new(container_image_depot),
                     log,
                     locked: Arc::new(Mutex::new(ClientStateLocked {
                         digest_repo,
@@ -596,76 +1093,7 @@ impl Client {
         &self,
         spec: JobSpec,
     ) -> Result<futures::channel::mpsc::UnboundedReceiver<JobStatus>> {
-        let state = self.state_machine.active()?;
-        let (sender, receiver) = futures::channel::mpsc::unbounded();
-        debug!(state.log, "run_job"; "spec" => ?spec);
-
-        let container = match spec.container {
-            ContainerRef::Name(n) => {
-                let locked = state.locked.lock().await;
-                locked
-                    .containers
-                    .get(&n)
-                    .ok_or_else(|| anyhow!("container {n:?} unknown"))?
-                    .clone()
-            }
-            ContainerRef::Inline(c) => {
-                c.check_for_local_network_and_sys_mount()?;
-                c
-            }
-        };
-
-        let mut layers = container.layers;
-        let mut initial_env = Default::default();
-        let mut image_working_directory = None;
-        if let Some(image_spec) = container.image {
-            let image = state.get_container_image(&image_spec.name).await?;
-            let image_config = ImageConfig {
-                layers: image.layers.clone(),
-                environment: image.env().cloned(),
-                working_directory: image.working_dir().map(From::from),
-            };
-            let image = ConvertedImage::new(&image_spec.name, image_config);
-            if image_spec.use_layers {
-                let end = mem::replace(&mut layers, image.layers()?);
-                layers.extend(end);
-            }
-            if image_spec.use_environment {
-                initial_env = image.environment()?;
-            }
-            if image_spec.use_working_directory {
-                image_working_directory = Some(image.working_directory()?);
-            }
-        }
-        if image_working_directory.is_some() && container.working_directory.is_some() {
-            bail!("can't provide both `working_directory` and `image.use_working_directory`");
-        }
-
-        let working_directory = image_working_directory.or(container.working_directory);
-        let converted_layers = state.get_layers(layers).await?;
-
-        let spec = maelstrom_base::JobSpec {
-            program: spec.program,
-            arguments: spec.arguments,
-            environment: environment_eval(initial_env, container.environment, std_env_lookup)?,
-            layers: converted_layers
-                .try_into()
-                .map_err(|_| anyhow!("missing layers"))?,
-            mounts: container.mounts,
-            network: container.network,
-            root_overlay: container.root_overlay,
-            working_directory,
-            user: container.user,
-            group: container.group,
-            timeout: spec.timeout,
-            estimated_duration: spec.estimated_duration,
-            allocate_tty: spec.allocate_tty,
-            priority: spec.priority,
-        };
-        state
-            .local_broker_sender
-            .send(router::Message::RunJob(spec, sender))?;
-        Ok(receiver)
+        self.state_machine.active()?.run_job(spec).await
     }
 
     pub async fn add_container(&self, name: String, container: ContainerSpec) -> Result<()> {
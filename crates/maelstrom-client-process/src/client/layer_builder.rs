@@ -1,6 +1,7 @@
 mod shared_libraries;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use base64::{prelude::BASE64_STANDARD, Engine as _};
 use futures::StreamExt as _;
 use itertools::Itertools as _;
 use maelstrom_base::{
@@ -11,26 +12,90 @@ use maelstrom_base::{
     ArtifactType, Sha256Digest, Utf8Path, Utf8PathBuf,
 };
 use maelstrom_client_base::{
-    spec::{LayerSpec, PrefixOptions, SymlinkSpec},
-    CacheDir, ProjectDir, MANIFEST_DIR, SO_LISTINGS_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
+    spec::{FileContents, FileSpec, LayerSpec, PrefixOptions, SymlinkSpec},
+    CacheDir, ProjectDir, ARCHIVE_BLOB_DIR, CHUNK_DIR, COMMAND_LAYER_DIR, FILES_MANIFEST_DIR,
+    GIT_BLOB_DIR, MANIFEST_DIR, SO_LISTINGS_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR,
 };
 use maelstrom_util::{
     async_fs,
-    manifest::{AsyncManifestWriter, DataUpload, ManifestBuilder},
+    manifest::{
+        tar::manifest_from_tar, zip::manifest_from_zip, AsyncManifestWriter, DataUpload,
+        ManifestBuilder, ManifestReader,
+    },
     root::RootBuf,
 };
 use sha2::{Digest as _, Sha256};
 use shared_libraries::get_shared_library_dependencies;
 use std::fmt;
+use std::future::Future;
+use std::os::unix::fs::PermissionsExt as _;
 use std::path::{Path, PathBuf};
 use std::pin::pin;
-use tokio::io::AsyncWriteExt as _;
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
 
 /// Having some deterministic time-stamp for files we create in manifests is useful for testing and
 /// potentially caching.
 /// I picked this time arbitrarily 2024-1-11 11:11:11
 const ARBITRARY_TIME: UnixTimestamp = UnixTimestamp(1705000271);
 
+/// How long the cached output of a command layer, or a blob extracted from an archive or a git
+/// repository, is kept after it was last used.
+pub const UNUSED_CACHE_ENTRY_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Mark the cache entry at `path` as used now, by setting its modification time.
+async fn mark_used(path: &Path) -> Result<()> {
+    let fs = async_fs::Fs::new();
+    let file = fs.open_file(path).await?.into_std().await;
+    tokio::task::spawn_blocking(move || file.set_modified(SystemTime::now())).await??;
+    Ok(())
+}
+
+/// Remove a directory that was used as the work directory of an overlayfs mount. Overlayfs leaves
+/// a directory in the work directory that we can't remove until we give ourselves permission to.
+fn remove_overlay_work_dir(fs: &maelstrom_util::fs::Fs, work: &Path) -> Result<()> {
+    let inner_work = work.join("work");
+    if fs.exists(&inner_work) {
+        let mut perm = fs.metadata(&inner_work)?.permissions();
+        perm.set_mode(0o777);
+        fs.set_permissions(&inner_work, perm)?;
+    }
+    fs.remove_dir_all(work)
+}
+
+/// Remove the entries in the cache directory `dir` that haven't been used for `max_age`. An
+/// entry's modification time is when it was last used. Temporary entries, whose names start with
+/// `.tmp`, are left over from a previous client that didn't finish with them, so they are always
+/// removed.
+pub async fn prune_unused_entries(dir: &Path, max_age: Duration) -> Result<()> {
+    let dir = dir.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let fs = maelstrom_util::fs::Fs::new();
+        let now = SystemTime::now();
+        for entry in fs.read_dir(&dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            let is_temp = entry.file_name().as_encoded_bytes().starts_with(b".tmp");
+            let age = now.duration_since(meta.modified()?).unwrap_or_default();
+            if !is_temp && age <= max_age {
+                continue;
+            }
+            if meta.is_dir() {
+                let work = entry.path().join("work");
+                if fs.exists(&work) {
+                    remove_overlay_work_dir(&fs, &work)?;
+                }
+                fs.remove_dir_all(entry.path())?;
+            } else {
+                fs.remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    })
+    .await?
+}
+
 #[derive(Default)]
 struct PathHasher {
     hasher: Sha256,
@@ -45,6 +110,10 @@ impl PathHasher {
         self.hasher.update(path.as_str().as_bytes());
     }
 
+    fn hash_bytes(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+    }
+
     fn finish(self) -> Sha256Digest {
         Sha256Digest::new(self.hasher.finalize().into())
     }
@@ -78,6 +147,154 @@ fn calculate_manifest_entry_path(
     Ok(path)
 }
 
+/// Apply the `chmod` and `chown` prefix options to an entry. This does the same thing for entries
+/// that didn't come from the local file system as [`ManifestBuilder::with_file_mode`] and
+/// [`ManifestBuilder::with_owner`] do for those that did.
+fn apply_metadata_options(entry: &mut ManifestEntry, prefix_options: &PrefixOptions) {
+    if let (Some(mode), ManifestEntryData::File(_)) = (prefix_options.chmod, &entry.data) {
+        entry.metadata.mode = entry.metadata.mode.with_permissions(Mode(mode));
+    }
+    if let Some(owner) = prefix_options.chown {
+        entry.metadata.owner = Some(owner.into());
+    }
+}
+
+/// The formats of archives that can be converted into layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    Tar,
+    TarGzip,
+    TarZstd,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Determine an archive's format from the extension of its file name.
+    fn from_path(path: &Utf8Path) -> Result<Self> {
+        let name = path.file_name().unwrap_or_default();
+        [
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGzip),
+            (".tgz", Self::TarGzip),
+            (".tar.zst", Self::TarZstd),
+            (".tzst", Self::TarZstd),
+            (".tar.xz", Self::TarXz),
+            (".txz", Self::TarXz),
+            (".zip", Self::Zip),
+        ]
+        .into_iter()
+        .find_map(|(extension, format)| name.ends_with(extension).then_some(format))
+        .ok_or_else(|| anyhow!("can't tell the format of archive {path:?} from its extension"))
+    }
+
+    /// Convert the archive at `path` into a manifest, storing file contents in `data_dir`.
+    /// Returns the paths of the stored files.
+    fn convert(
+        self,
+        path: &Path,
+        manifest: &mut Vec<u8>,
+        data_dir: &Path,
+        inline_limit: u64,
+    ) -> Result<Vec<PathBuf>> {
+        let fs = maelstrom_util::fs::Fs::new();
+        let file = std::io::BufReader::new(fs.open_file(path)?);
+        match self {
+            Self::Tar => manifest_from_tar(file, manifest, data_dir, inline_limit),
+            Self::TarGzip => manifest_from_tar(
+                flate2::read::MultiGzDecoder::new(file),
+                manifest,
+                data_dir,
+                inline_limit,
+            ),
+            Self::TarZstd => manifest_from_tar(
+                zstd::Decoder::with_buffer(file)?,
+                manifest,
+                data_dir,
+                inline_limit,
+            ),
+            Self::TarXz => manifest_from_tar(
+                xz2::read::XzDecoder::new_multi_decoder(file),
+                manifest,
+                data_dir,
+                inline_limit,
+            ),
+            Self::Zip => manifest_from_zip(file, manifest, data_dir, inline_limit),
+        }
+    }
+}
+
+/// An entry of the output of `git ls-tree -l -z`.
+struct GitTreeEntry<'a> {
+    mode: u32,
+    kind: &'a str,
+    id: &'a str,
+    size: u64,
+    path: &'a str,
+}
+
+impl<'a> GitTreeEntry<'a> {
+    fn parse(line: &'a [u8]) -> Result<Self> {
+        let line = std::str::from_utf8(line).map_err(|_| anyhow!("non-utf8 path from git"))?;
+        let parse = || {
+            let (info, path) = line.split_once('\t')?;
+            let mut fields = info.split_whitespace();
+            Some(Self {
+                mode: u32::from_str_radix(fields.next()?, 8).ok()?,
+                kind: fields.next()?,
+                id: fields.next()?,
+                size: fields.next()?.parse().unwrap_or(0),
+                path,
+            })
+        };
+        parse().ok_or_else(|| anyhow!("unexpected output from `git ls-tree`: {line:?}"))
+    }
+}
+
+/// Reads blobs out of git's object store by id, using one `git cat-file --batch` for all of them.
+struct GitCatFile {
+    _child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    stdout: tokio::io::BufReader<tokio::process::ChildStdout>,
+}
+
+impl GitCatFile {
+    fn new(dir: &Path) -> Result<Self> {
+        let mut child = tokio::process::Command::new("git")
+            .args(["cat-file", "--batch"])
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        Ok(Self {
+            stdin: child.stdin.take().unwrap(),
+            stdout: tokio::io::BufReader::new(child.stdout.take().unwrap()),
+            _child: child,
+        })
+    }
+
+    /// Write the contents of the blob with the given id to `out`.
+    async fn read_blob(&mut self, id: &str, out: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        self.stdin.write_all(format!("{id}\n").as_bytes()).await?;
+        self.stdin.flush().await?;
+        let mut header = String::new();
+        self.stdout.read_line(&mut header).await?;
+        let size = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size.parse::<u64>()?,
+            _ => bail!("couldn't read git blob {id}: {:?}", header.trim_end()),
+        };
+        let copied = tokio::io::copy(&mut (&mut self.stdout).take(size), out).await?;
+        if copied != size {
+            bail!("git blob {id} was truncated");
+        }
+        let mut newline = [0];
+        self.stdout.read_exact(&mut newline).await?;
+        Ok(())
+    }
+}
+
 fn expand_braces(expr: &str) -> Result<Vec<String>> {
     if expr.contains('{') {
         bracoxide::explode(expr).map_err(|e| anyhow!("{e}"))
@@ -135,6 +352,12 @@ impl LayerBuilder {
             .join(format!("{name}.manifest"))
     }
 
+    fn build_files_manifest_path(&self, name: &impl fmt::Display) -> PathBuf {
+        (**self.cache_dir)
+            .join(FILES_MANIFEST_DIR)
+            .join(format!("{name}.manifest"))
+    }
+
     async fn build_manifest(
         &self,
         mut paths: impl futures::stream::Stream<Item = Result<impl AsRef<Path>>>,
@@ -151,7 +374,10 @@ impl LayerBuilder {
             data_upload,
             self.inline_limit,
         )
-        .await?;
+        .await?
+        .with_chunk_dir((**self.cache_dir).join(CHUNK_DIR))
+        .with_file_mode(prefix_options.chmod.map(Mode))
+        .with_owner(prefix_options.chown.map(Into::into));
         let mut path_hasher = PathHasher::new();
         let mut pinned_paths = pin!(paths);
         while let Some(maybe_path) = pinned_paths.next().await {
@@ -199,6 +425,8 @@ impl LayerBuilder {
                 size: 0,
                 mode: Mode(0o444 | if is_dir { 0o111 } else { 0 }),
                 mtime: ARBITRARY_TIME,
+                xattrs: Default::default(),
+                owner: None,
             };
             let entry = ManifestEntry {
                 path: stub,
@@ -228,6 +456,8 @@ impl LayerBuilder {
                 size: 0,
                 mode: Mode(0o444),
                 mtime: ARBITRARY_TIME,
+                xattrs: Default::default(),
+                owner: None,
             };
             let entry = ManifestEntry {
                 path: link,
@@ -243,6 +473,459 @@ impl LayerBuilder {
         Ok(manifest_path)
     }
 
+    /// Build a manifest holding the given files. Their contents are stored inline in the
+    /// manifest.
+    async fn build_files_manifest(&self, files: Vec<FileSpec>) -> Result<PathBuf> {
+        let fs = async_fs::Fs::new();
+        let tmp_file_path = self.temp_manifest_path().await?;
+        let mut file = fs.create_file(&tmp_file_path).await?;
+        let mut writer = AsyncManifestWriter::new(&mut file).await?;
+        let mut path_hasher = PathHasher::new();
+        for FileSpec {
+            path,
+            contents,
+            mode,
+        } in files
+        {
+            let contents = match contents {
+                FileContents::Contents(text) => text.into_bytes(),
+                FileContents::Base64(encoded) => BASE64_STANDARD
+                    .decode(encoded)
+                    .map_err(|err| anyhow!("invalid base64 contents for {path}: {err}"))?,
+            };
+            let mode = mode.unwrap_or(0o644) & 0o7777;
+            path_hasher.hash_path(&path);
+            path_hasher.hash_bytes(&mode.to_le_bytes());
+            path_hasher.hash_bytes(&contents);
+            let metadata = ManifestEntryMetadata {
+                size: contents.len() as u64,
+                mode: Mode(mode),
+                mtime: ARBITRARY_TIME,
+                xattrs: Default::default(),
+                owner: None,
+            };
+            let data = if contents.is_empty() {
+                ManifestFileData::Empty
+            } else {
+                ManifestFileData::Inline(contents)
+            };
+            let entry = ManifestEntry {
+                path,
+                metadata,
+                data: ManifestEntryData::File(data),
+            };
+            writer.write_entry(&entry).await?;
+        }
+        file.flush().await?;
+
+        let manifest_path = self.build_files_manifest_path(&path_hasher.finish());
+        fs.rename(tmp_file_path, &manifest_path).await?;
+        Ok(manifest_path)
+    }
+
+    /// Run git in the project directory, returning its standard output.
+    async fn run_git(&self, args: &[&str], stdout: Stdio) -> Result<Vec<u8>> {
+        let output = tokio::process::Command::new("git")
+            .args(args)
+            .current_dir(&self.project_dir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(Stdio::piped())
+            .spawn()?
+            .wait_with_output()
+            .await?;
+        if !output.status.success() {
+            bail!(
+                "`git {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    /// List the files git tracks under `path` in the index, and the untracked files that aren't
+    /// ignored if `untracked` is true. Files that have been removed from the working tree, but not
+    /// the index, are skipped.
+    async fn git_files(&self, path: &Utf8Path, untracked: bool) -> Result<Vec<Utf8PathBuf>> {
+        let mut args = vec!["ls-files", "-z", "--cached", "--deduplicate"];
+        if untracked {
+            args.extend(["--others", "--exclude-standard"]);
+        }
+        args.extend(["--", path.as_str()]);
+        let output = self.run_git(&args, Stdio::piped()).await?;
+        let fs = async_fs::Fs::new();
+        let mut paths = vec![];
+        for path in output.split(|b| *b == 0).filter(|p| !p.is_empty()) {
+            let path = Utf8PathBuf::from(
+                std::str::from_utf8(path).map_err(|_| anyhow!("non-utf8 path from git"))?,
+            );
+            if fs
+                .symlink_metadata((**self.project_dir).join(&path))
+                .await
+                .is_ok()
+            {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Build a manifest from an archive. `convert` is run on a blocking thread to turn the archive
+    /// into a manifest, given the directory to store file contents in and the inline limit. The
+    /// stored files are uploaded, and the `prefix_options` are applied to the entries' paths. The
+    /// manifest is named after `path_hasher`.
+    async fn build_archive_manifest(
+        &self,
+        convert: impl FnOnce(&mut Vec<u8>, &Path, u64) -> Result<Vec<PathBuf>> + Send + 'static,
+        path_hasher: PathHasher,
+        prefix_options: PrefixOptions,
+        mut data_upload: impl DataUpload,
+    ) -> Result<PathBuf> {
+        let blob_dir = (**self.cache_dir).join(ARCHIVE_BLOB_DIR);
+        let inline_limit = self.inline_limit;
+        let (manifest, data_paths) = tokio::task::spawn_blocking(move || {
+            let mut manifest = vec![];
+            let data_paths = convert(&mut manifest, &blob_dir, inline_limit)?;
+            Result::<_>::Ok((manifest, data_paths))
+        })
+        .await??;
+        for data_path in data_paths {
+            data_upload.upload(&data_path).await?;
+        }
+        let entries =
+            ManifestReader::new(std::io::Cursor::new(manifest))?.collect::<std::io::Result<_>>()?;
+        self.write_foreign_manifest(entries, path_hasher, prefix_options)
+            .await
+    }
+
+    /// Write a manifest of entries that didn't come from the local file system, after applying
+    /// the `prefix_options` to them. The manifest is named after `path_hasher`.
+    async fn write_foreign_manifest(
+        &self,
+        entries: Vec<ManifestEntry>,
+        path_hasher: PathHasher,
+        prefix_options: PrefixOptions,
+    ) -> Result<PathBuf> {
+        // The files don't exist in the local file system, so there is nothing to canonicalize.
+        let prefix_options = PrefixOptions {
+            canonicalize: false,
+            ..prefix_options
+        };
+        let fs = async_fs::Fs::new();
+        let tmp_file_path = self.temp_manifest_path().await?;
+        let mut file = fs.create_file(&tmp_file_path).await?;
+        let mut writer = AsyncManifestWriter::new(&mut file).await?;
+        for mut entry in entries {
+            entry.path =
+                calculate_manifest_entry_path(&entry.path, &self.project_dir, &prefix_options)?;
+            apply_metadata_options(&mut entry, &prefix_options);
+            writer.write_entry(&entry).await?;
+        }
+        file.flush().await?;
+
+        let manifest_path = self.build_manifest_path(&path_hasher.finish());
+        fs.rename(tmp_file_path, &manifest_path).await?;
+        Ok(manifest_path)
+    }
+
+    /// Build a manifest from the files under `path` as of the given git revision. The tree is
+    /// listed with `git ls-tree`, and the files are read out of git's object store. Modes and
+    /// modification times are the ones `git archive` would give them.
+    ///
+    /// Contents too big to be inlined are stored in the git blob directory, named after their git
+    /// object ids, so building another layer with the same files doesn't store them again. Their
+    /// digests are cached along with those of any other uploaded file.
+    async fn build_git_revision_manifest(
+        &self,
+        path: &Utf8Path,
+        revision: &str,
+        prefix_options: PrefixOptions,
+        mut data_upload: impl DataUpload,
+    ) -> Result<PathBuf> {
+        let commit_time = self
+            .run_git(
+                &["log", "-1", "--format=%ct", revision, "--"],
+                Stdio::piped(),
+            )
+            .await?;
+        let mtime = UnixTimestamp(std::str::from_utf8(&commit_time)?.trim().parse()?);
+        let tree = self
+            .run_git(
+                &[
+                    "ls-tree",
+                    "-r",
+                    "-t",
+                    "-l",
+                    "-z",
+                    revision,
+                    "--",
+                    path.as_str(),
+                ],
+                Stdio::piped(),
+            )
+            .await?;
+
+        let fs = async_fs::Fs::new();
+        let blob_dir = (**self.cache_dir).join(GIT_BLOB_DIR);
+        let mut cat_file = GitCatFile::new(&self.project_dir)?;
+        let mut entries = vec![];
+        for line in tree.split(|b| *b == 0).filter(|l| !l.is_empty()) {
+            let entry = GitTreeEntry::parse(line)?;
+            let (mode, data) = match (entry.kind, entry.mode) {
+                ("tree" | "commit", _) => (0o775, ManifestEntryData::Directory { opaque: false }),
+                ("blob", 0o120000) => {
+                    let mut target = vec![];
+                    cat_file.read_blob(entry.id, &mut target).await?;
+                    (0o777, ManifestEntryData::Symlink(target))
+                }
+                ("blob", mode) => {
+                    let data = if entry.size == 0 {
+                        ManifestFileData::Empty
+                    } else if entry.size <= self.inline_limit {
+                        let mut data = vec![];
+                        cat_file.read_blob(entry.id, &mut data).await?;
+                        ManifestFileData::Inline(data)
+                    } else {
+                        let blob_path = blob_dir.join(entry.id);
+                        if fs.exists(&blob_path).await {
+                            mark_used(&blob_path).await?;
+                        } else {
+                            let tmp_file_path = self.temp_manifest_path().await?;
+                            let mut file = fs.create_file(&tmp_file_path).await?;
+                            cat_file.read_blob(entry.id, &mut file).await?;
+                            file.flush().await?;
+                            fs.rename(tmp_file_path, &blob_path).await?;
+                        }
+                        ManifestFileData::Digest(data_upload.upload(&blob_path).await?)
+                    };
+                    let mode = if mode & 0o111 != 0 { 0o775 } else { 0o664 };
+                    (mode, ManifestEntryData::File(data))
+                }
+                (kind, _) => bail!("unexpected {kind} {:?} in git tree", entry.path),
+            };
+            entries.push(ManifestEntry {
+                path: entry.path.into(),
+                metadata: ManifestEntryMetadata {
+                    size: if matches!(data, ManifestEntryData::File(_)) {
+                        entry.size
+                    } else {
+                        0
+                    },
+                    mode: Mode(mode),
+                    mtime,
+                    xattrs: Default::default(),
+                    owner: None,
+                },
+                data,
+            });
+        }
+
+        let mut path_hasher = PathHasher::new();
+        path_hasher.hash_path(path);
+        path_hasher.hash_path(revision.into());
+        self.write_foreign_manifest(entries, path_hasher, prefix_options)
+            .await
+    }
+
+    fn command_layer_dir(&self, key: &Sha256Digest) -> PathBuf {
+        (**self.cache_dir)
+            .join(COMMAND_LAYER_DIR)
+            .join(key.to_string())
+    }
+
+    /// Compute the key under which the output of a command layer is cached. It covers the
+    /// command, the directory it produces, the layers it is run on, and the contents of its
+    /// declared inputs. Inputs that are directories have all of the files under them included.
+    pub async fn command_layer_key(
+        &self,
+        command: &[String],
+        output: &Utf8Path,
+        inputs: &[Utf8PathBuf],
+        base_layers: &[(Sha256Digest, ArtifactType)],
+    ) -> Result<Sha256Digest> {
+        let fs = async_fs::Fs::new();
+        let mut hasher = Sha256::new();
+        for arg in command {
+            hasher.update(arg.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(output.as_str().as_bytes());
+        hasher.update([0]);
+        for (digest, artifact_type) in base_layers {
+            hasher.update(format!("{digest} {artifact_type:?}\0").as_bytes());
+        }
+
+        let mut glob_builder = globset::GlobSet::builder();
+        glob_builder.add(globset::Glob::new("**")?);
+        let glob = glob_builder.build()?;
+        for input in inputs {
+            let path = (**self.project_dir).join(input);
+            let mut files = vec![];
+            if fs.metadata(&path).await?.is_dir() {
+                let mut walker = fs.glob_walk(&path, &glob);
+                while let Some(file) = walker.next().await? {
+                    if fs.metadata(&file).await?.is_file() {
+                        files.push(file);
+                    }
+                }
+                files.sort();
+            } else {
+                files.push(path);
+            }
+            for file in files {
+                let (_, digest) = crate::calculate_digest(&file).await?;
+                let file = file.strip_prefix(&self.project_dir).unwrap_or(&file);
+                hasher.update(format!("{} {digest}\0", file.display()).as_bytes());
+            }
+        }
+        Ok(Sha256Digest::new(hasher.finalize().into()))
+    }
+
+    /// Run a command layer's command in the project directory, unless it has already been run
+    /// successfully with the same key and its output is still there.
+    async fn run_local_command(
+        &self,
+        command: &[String],
+        output: &Utf8Path,
+        key: &Sha256Digest,
+    ) -> Result<()> {
+        let fs = async_fs::Fs::new();
+        let stamp = self.command_layer_dir(key);
+        if fs.exists(&stamp).await && fs.exists((**self.project_dir).join(output)).await {
+            return mark_used(&stamp).await;
+        }
+        let Some((program, arguments)) = command.split_first() else {
+            bail!("a command layer's command can't be empty");
+        };
+        let result = tokio::process::Command::new(program)
+            .args(arguments)
+            .current_dir(&self.project_dir)
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !result.status.success() {
+            bail!(
+                "command `{}` failed: {}",
+                command.join(" "),
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+        }
+        fs.create_dir_all(&stamp).await?;
+        Ok(())
+    }
+
+    /// Get the directory holding the output of a command layer which is run as a job, calling
+    /// `run_job` to run it if it hasn't been run successfully with the same key before.
+    /// `run_job` is given the upper and work directories to use for the job's root overlay. The
+    /// upper directory is kept as the layer's output, minus any special files, like the
+    /// whiteouts overlayfs creates for deleted files.
+    pub async fn command_layer_upper_dir<RunJobT, FutureT>(
+        &self,
+        key: &Sha256Digest,
+        run_job: RunJobT,
+    ) -> Result<PathBuf>
+    where
+        RunJobT: FnOnce(Utf8PathBuf, Utf8PathBuf) -> FutureT,
+        FutureT: Future<Output = Result<()>>,
+    {
+        let fs = async_fs::Fs::new();
+        let entry = self.command_layer_dir(key);
+        let upper = entry.join("upper");
+        if fs.exists(&upper).await {
+            mark_used(&entry).await?;
+            return Ok(upper);
+        }
+
+        let command_layer_dir = (**self.cache_dir).join(COMMAND_LAYER_DIR);
+        let temp_dir = tokio::task::spawn_blocking(move || {
+            Result::<_>::Ok(
+                tempfile::Builder::new()
+                    .prefix(".tmp")
+                    .tempdir_in(command_layer_dir)?
+                    .into_path(),
+            )
+        })
+        .await??;
+        let temp_upper = temp_dir.join("upper");
+        let work = temp_dir.join("work");
+        fs.create_dir(&temp_upper).await?;
+        fs.create_dir(&work).await?;
+        let result = run_job(
+            Utf8PathBuf::try_from(temp_upper.clone())?,
+            Utf8PathBuf::try_from(work.clone())?,
+        )
+        .await;
+
+        // On success, the temporary directory becomes the cache entry, holding just the upper
+        // directory.
+        let succeeded = result.is_ok();
+        tokio::task::spawn_blocking(move || {
+            let fs = maelstrom_util::fs::Fs::new();
+            remove_overlay_work_dir(&fs, &work)?;
+            if !succeeded {
+                return fs.remove_dir_all(&temp_dir);
+            }
+            for path in fs.walk(&temp_upper) {
+                let path = path?;
+                let meta = fs.symlink_metadata(&path)?;
+                if !(meta.is_file() || meta.is_dir() || meta.is_symlink()) {
+                    fs.remove_file(path)?;
+                }
+            }
+            fs.rename(&temp_dir, entry)
+        })
+        .await??;
+        result?;
+        Ok(upper)
+    }
+
+    /// Build a manifest from the files under `output`, which is found relative to `root`. The
+    /// entries' paths start with `output`.
+    pub async fn build_command_manifest(
+        &self,
+        root: &Path,
+        output: &Utf8Path,
+        key: &Sha256Digest,
+        prefix_options: PrefixOptions,
+        data_upload: impl DataUpload,
+    ) -> Result<PathBuf> {
+        let fs = async_fs::Fs::new();
+        let output_dir = root.join(output.as_str().trim_start_matches('/'));
+        if !fs.metadata(&output_dir).await?.is_dir() {
+            bail!("command layer output {output:?} isn't a directory");
+        }
+        let tmp_file_path = self.temp_manifest_path().await?;
+        let mut manifest_file = fs.create_file(&tmp_file_path).await?;
+        let mut builder = ManifestBuilder::new(
+            &mut manifest_file,
+            prefix_options.follow_symlinks,
+            data_upload,
+            self.inline_limit,
+        )
+        .await?
+        .with_chunk_dir((**self.cache_dir).join(CHUNK_DIR))
+        .with_file_mode(prefix_options.chmod.map(Mode))
+        .with_owner(prefix_options.chown.map(Into::into));
+        let mut glob_builder = globset::GlobSet::builder();
+        glob_builder.add(globset::Glob::new("**")?);
+        let glob = glob_builder.build()?;
+        let mut paths = fs.glob_walk(&output_dir, &glob);
+        while let Some(path) = paths.next().await? {
+            let relative = Utf8Path::from_path(path.strip_prefix(&output_dir)?)
+                .ok_or_else(|| anyhow!("non-utf8 path"))?;
+            let dest =
+                calculate_manifest_entry_path(&output.join(relative), root, &prefix_options)?;
+            builder.add_file(&path, dest).await?;
+        }
+        drop(builder);
+        manifest_file.flush().await?;
+
+        let manifest_path = self.build_manifest_path(key);
+        fs.rename(tmp_file_path, &manifest_path).await?;
+        Ok(manifest_path)
+    }
+
     pub async fn build_layer(
         &self,
         layer: LayerSpec,
@@ -250,6 +933,26 @@ impl LayerBuilder {
     ) -> Result<(PathBuf, ArtifactType)> {
         Ok(match layer {
             LayerSpec::Tar { path } => (path.into_std_path_buf(), ArtifactType::Tar),
+            LayerSpec::Archive {
+                path,
+                prefix_options,
+            } => {
+                let format = ArchiveFormat::from_path(&path)?;
+                let archive_path = (**self.project_dir).join(&path);
+                let mut path_hasher = PathHasher::new();
+                path_hasher.hash_path(&path);
+                let manifest_path = self
+                    .build_archive_manifest(
+                        move |manifest, data_dir, inline_limit| {
+                            format.convert(&archive_path, manifest, data_dir, inline_limit)
+                        },
+                        path_hasher,
+                        prefix_options,
+                        data_upload,
+                    )
+                    .await?;
+                (manifest_path, ArtifactType::Manifest)
+            }
             LayerSpec::Paths {
                 paths,
                 prefix_options,
@@ -290,6 +993,10 @@ impl LayerBuilder {
                 let manifest_path = self.build_symlink_manifest(symlinks).await?;
                 (manifest_path, ArtifactType::Manifest)
             }
+            LayerSpec::Files { files } => {
+                let manifest_path = self.build_files_manifest(files).await?;
+                (manifest_path, ArtifactType::Manifest)
+            }
             LayerSpec::SharedLibraryDependencies {
                 binary_paths,
                 prefix_options,
@@ -310,6 +1017,62 @@ impl LayerBuilder {
                     .await?;
                 (manifest_path, ArtifactType::Manifest)
             }
+            LayerSpec::Git {
+                path,
+                revision: Some(revision),
+                untracked,
+                prefix_options,
+            } => {
+                if untracked {
+                    bail!("untracked files can't be included in a git layer with a revision");
+                }
+                let manifest_path = self
+                    .build_git_revision_manifest(&path, &revision, prefix_options, data_upload)
+                    .await?;
+                (manifest_path, ArtifactType::Manifest)
+            }
+            LayerSpec::Git {
+                path,
+                revision: None,
+                untracked,
+                prefix_options,
+            } => {
+                let paths = self.git_files(&path, untracked).await?;
+                let manifest_path = self
+                    .build_manifest(
+                        futures::stream::iter(paths.iter().map(Ok)),
+                        prefix_options,
+                        data_upload,
+                    )
+                    .await?;
+                (manifest_path, ArtifactType::Manifest)
+            }
+            LayerSpec::Command {
+                command,
+                output,
+                inputs,
+                image: None,
+                layers,
+                prefix_options,
+            } if layers.is_empty() => {
+                let key = self
+                    .command_layer_key(&command, &output, &inputs, &[])
+                    .await?;
+                self.run_local_command(&command, &output, &key).await?;
+                let manifest_path = self
+                    .build_command_manifest(
+                        &self.project_dir,
+                        &output,
+                        &key,
+                        prefix_options,
+                        data_upload,
+                    )
+                    .await?;
+                (manifest_path, ArtifactType::Manifest)
+            }
+            LayerSpec::Command { .. } => {
+                bail!("command layers with an image or layers must be run as jobs by the client")
+            }
         })
     }
 }
@@ -322,6 +1085,7 @@ mod tests {
     use maelstrom_util::manifest::AsyncManifestReader;
     use maplit::hashmap;
     use std::collections::HashMap;
+    use std::fs::Permissions;
     use std::os::unix::fs::MetadataExt as _;
     use tempfile::tempdir;
 
@@ -396,6 +1160,18 @@ mod tests {
         }
     }
 
+    async fn manifest_entries(manifest_path: &Path) -> Vec<ManifestEntry> {
+        let fs = async_fs::Fs::new();
+        let mut entry_iter = AsyncManifestReader::new(fs.open_file(manifest_path).await.unwrap())
+            .await
+            .unwrap();
+        let mut entries = vec![];
+        while let Some(entry) = entry_iter.next().await.unwrap() {
+            entries.push(entry);
+        }
+        entries
+    }
+
     async fn verify_single_entry_manifest(
         manifest_path: &Path,
         expected_entry_path: &Path,
@@ -445,7 +1221,15 @@ mod tests {
             fs.create_dir_all(&artifact_dir).await.unwrap();
             fs.create_dir_all(&cache_dir).await.unwrap();
 
-            for sub_dir in [MANIFEST_DIR, STUB_MANIFEST_DIR, SYMLINK_MANIFEST_DIR] {
+            for sub_dir in [
+                MANIFEST_DIR,
+                STUB_MANIFEST_DIR,
+                SYMLINK_MANIFEST_DIR,
+                FILES_MANIFEST_DIR,
+                ARCHIVE_BLOB_DIR,
+                GIT_BLOB_DIR,
+                COMMAND_LAYER_DIR,
+            ] {
                 fs.create_dir_all(cache_dir.join(sub_dir)).await.unwrap();
             }
 
@@ -485,6 +1269,42 @@ mod tests {
             let data = self.fs.read(path).await.unwrap();
             hash_data(&data)
         }
+
+        async fn write_files(&self, files: HashMap<&str, &str>) {
+            for (path, contents) in files {
+                let path = self.artifact_dir.join(path);
+                self.fs
+                    .create_dir_all(path.parent().unwrap())
+                    .await
+                    .unwrap();
+                self.fs.write(path, contents.as_bytes()).await.unwrap();
+            }
+        }
+
+        fn git(&self, args: &[&str]) {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&self.artifact_dir)
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?} failed");
+        }
+
+        /// Create a git repository in the project directory with some committed files.
+        async fn init_git_repo(&self) {
+            self.git(&["init", "--quiet"]);
+            self.write_files(hashmap! {
+                ".gitignore" => "*.log\n",
+                "foo/a.txt" => "hello world",
+                "foo/b.txt" => "hi",
+                "bar/c.txt" => "hello world",
+            })
+            .await;
+            self.git(&["add", "."]);
+            self.git(&["commit", "--quiet", "-m", "initial"]);
+        }
     }
 
     #[tokio::test]
@@ -559,20 +1379,62 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn paths_prefix_strip_and_prepend_absolute() {
-        paths_and_prefix_options_test(
-            |artifact_dir| artifact_dir.join("test_artifact"),
-            |artifact_dir| PrefixOptions {
-                strip_prefix: Some(artifact_dir.to_owned().try_into().unwrap()),
-                prepend_prefix: Some("foo/".into()),
-                ..Default::default()
-            },
-            |_| Path::new("foo/test_artifact").to_owned(),
-        )
-        .await;
-    }
-
-    #[tokio::test]
+    async fn paths_chmod_and_chown() {
+        let fix = Fixture::new().await;
+        fix.fs
+            .create_dir_all(fix.artifact_dir.join("dir"))
+            .await
+            .unwrap();
+        fix.fs
+            .write(fix.artifact_dir.join("dir/file"), b"hello world")
+            .await
+            .unwrap();
+        let dir_mode = fix
+            .fs
+            .metadata(fix.artifact_dir.join("dir"))
+            .await
+            .unwrap()
+            .mode();
+
+        let manifest = fix
+            .build_layer(LayerSpec::Paths {
+                paths: vec!["dir".into(), "dir/file".into()],
+                prefix_options: PrefixOptions {
+                    chmod: Some(0o4750),
+                    chown: Some("1000:2000".parse().unwrap()),
+                    ..Default::default()
+                },
+            })
+            .await;
+        let entries = manifest_entries(&manifest).await;
+        let owner = Some(maelstrom_base::manifest::Owner {
+            user: 1000.into(),
+            group: 2000.into(),
+        });
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "dir");
+        assert_eq!(entries[0].metadata.mode, Mode(dir_mode));
+        assert_eq!(entries[0].metadata.owner, owner);
+        assert_eq!(entries[1].path, "dir/file");
+        assert_eq!(u32::from(entries[1].metadata.mode) & 0o7777, 0o4750);
+        assert_eq!(entries[1].metadata.owner, owner);
+    }
+
+    #[tokio::test]
+    async fn paths_prefix_strip_and_prepend_absolute() {
+        paths_and_prefix_options_test(
+            |artifact_dir| artifact_dir.join("test_artifact"),
+            |artifact_dir| PrefixOptions {
+                strip_prefix: Some(artifact_dir.to_owned().try_into().unwrap()),
+                prepend_prefix: Some("foo/".into()),
+                ..Default::default()
+            },
+            |_| Path::new("foo/test_artifact").to_owned(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
     async fn paths_prefix_strip_and_prepend_relative() {
         paths_and_prefix_options_test(
             |_| Path::new("bar/test_artifact").to_owned(),
@@ -790,6 +1652,574 @@ mod tests {
         verify_empty_manifest(&manifest).await;
     }
 
+    #[tokio::test]
+    async fn git_index() {
+        let fix = Fixture::new().await;
+        fix.init_git_repo().await;
+        fix.write_files(hashmap! {
+            "foo/a.txt" => "hello again",
+            "foo/new.txt" => "hello world",
+            "foo/ignored.log" => "hello world",
+        })
+        .await;
+        fix.fs
+            .remove_file(fix.artifact_dir.join("foo/b.txt"))
+            .await
+            .unwrap();
+
+        let manifest = fix
+            .build_layer(LayerSpec::Git {
+                path: "foo".into(),
+                revision: None,
+                untracked: false,
+                prefix_options: Default::default(),
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![ExpectedManifestEntry::new(
+                "foo/a.txt",
+                0o100644,
+                ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello again"))),
+            )],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn git_index_and_untracked() {
+        let fix = Fixture::new().await;
+        fix.init_git_repo().await;
+        fix.write_files(hashmap! {
+            "foo/new.txt" => "hello world",
+            "foo/ignored.log" => "hello world",
+        })
+        .await;
+
+        let manifest = fix
+            .build_layer(LayerSpec::Git {
+                path: "foo".into(),
+                revision: None,
+                untracked: true,
+                prefix_options: PrefixOptions {
+                    strip_prefix: Some("foo".into()),
+                    ..Default::default()
+                },
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "a.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+                ExpectedManifestEntry::new(
+                    "b.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"hi".to_vec())),
+                ),
+                ExpectedManifestEntry::new(
+                    "new.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn git_revision() {
+        let fix = Fixture::new().await;
+        fix.init_git_repo().await;
+        fix.write_files(hashmap! { "foo/a.txt" => "hello again" })
+            .await;
+        fix.git(&["commit", "--quiet", "-a", "-m", "second"]);
+        fix.write_files(hashmap! { "foo/a.txt" => "uncommitted" })
+            .await;
+
+        let manifest = fix
+            .build_layer(LayerSpec::Git {
+                path: "foo".into(),
+                revision: Some("HEAD~1".into()),
+                untracked: false,
+                prefix_options: PrefixOptions {
+                    prepend_prefix: Some("/src".into()),
+                    ..Default::default()
+                },
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "/src/foo",
+                    0o775,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "/src/foo/a.txt",
+                    0o664,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+                ExpectedManifestEntry::new(
+                    "/src/foo/b.txt",
+                    0o664,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"hi".to_vec())),
+                ),
+            ],
+        )
+        .await;
+        assert!(
+            fix.fs
+                .exists(fix.artifact_dir.join(hash_data(b"hello world").to_string()))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn git_revision_stores_blobs_by_git_id() {
+        let fix = Fixture::new().await;
+        fix.init_git_repo().await;
+        fix.write_files(hashmap! { "foo/run.sh" => "#!/bin/sh\n" })
+            .await;
+        std::fs::set_permissions(
+            fix.artifact_dir.join("foo/run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fix.fs
+            .symlink("a.txt", fix.artifact_dir.join("foo/link"))
+            .await
+            .unwrap();
+        fix.git(&["add", "."]);
+        fix.git(&["commit", "--quiet", "-m", "second"]);
+
+        let layer = LayerSpec::Git {
+            path: "foo".into(),
+            revision: Some("HEAD".into()),
+            untracked: false,
+            prefix_options: Default::default(),
+        };
+        let manifest = fix.build_layer(layer.clone()).await;
+        let entries = manifest_entries(&manifest).await;
+        let entry = |path: &str| entries.iter().find(|e| e.path == path).unwrap();
+        assert_eq!(
+            entry("foo/link").data,
+            ManifestEntryData::Symlink(b"a.txt".to_vec())
+        );
+        assert_eq!(entry("foo/run.sh").metadata.mode, Mode(0o775));
+
+        // The contents of a.txt and run.sh are too big to inline, so they're stored, named after
+        // their git ids. Building the layer again reuses them.
+        let blob_dir = (**fix.builder.cache_dir).join(GIT_BLOB_DIR);
+        let blob_id = std::process::Command::new("git")
+            .args(["rev-parse", "HEAD:foo/a.txt"])
+            .current_dir(&fix.artifact_dir)
+            .output()
+            .unwrap()
+            .stdout;
+        let blob_path = blob_dir.join(std::str::from_utf8(&blob_id).unwrap().trim());
+        assert_eq!(
+            fix.fs.read_to_string(&blob_path).await.unwrap(),
+            "hello world"
+        );
+        let blob_count = || async {
+            let mut count = 0;
+            let mut entries = fix.fs.read_dir(&blob_dir).await.unwrap();
+            while entries.next_entry().await.unwrap().is_some() {
+                count += 1;
+            }
+            count
+        };
+        assert_eq!(blob_count().await, 2);
+        let ino = fix.fs.metadata(&blob_path).await.unwrap().ino();
+
+        fix.build_layer(layer).await;
+        assert_eq!(blob_count().await, 2);
+        assert_eq!(fix.fs.metadata(&blob_path).await.unwrap().ino(), ino);
+    }
+
+    fn test_tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "foo/", std::io::empty())
+            .unwrap();
+        for (path, contents) in [("foo/a.txt", "hello world"), ("foo/b.txt", "hi")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(contents.len() as u64);
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn test_zip() -> Vec<u8> {
+        use std::io::Write as _;
+        use zip::write::SimpleFileOptions;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = SimpleFileOptions::default();
+        zip.add_directory("foo/", options.unix_permissions(0o755))
+            .unwrap();
+        for (path, contents) in [("foo/a.txt", "hello world"), ("foo/b.txt", "hi")] {
+            zip.start_file(path, options.unix_permissions(0o644))
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    async fn archive_test(name: &str, archive: Vec<u8>) {
+        let fix = Fixture::new().await;
+        fix.fs
+            .write(fix.artifact_dir.join(name), archive)
+            .await
+            .unwrap();
+        let manifest = fix
+            .build_layer(LayerSpec::Archive {
+                path: name.into(),
+                prefix_options: PrefixOptions {
+                    strip_prefix: Some("foo/".into()),
+                    prepend_prefix: Some("/data/".into()),
+                    ..Default::default()
+                },
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "/data/",
+                    0o755,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "/data/a.txt",
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+                ExpectedManifestEntry::new(
+                    "/data/b.txt",
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"hi".to_vec())),
+                ),
+            ],
+        )
+        .await;
+        assert!(
+            fix.fs
+                .exists(fix.artifact_dir.join(hash_data(b"hello world").to_string()))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_tar() {
+        archive_test("test.tar", test_tar()).await;
+    }
+
+    #[tokio::test]
+    async fn archive_tar_gzip() {
+        use std::io::Write as _;
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&test_tar()).unwrap();
+        archive_test("test.tgz", encoder.finish().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn archive_tar_zstd() {
+        archive_test(
+            "test.tar.zst",
+            zstd::encode_all(&test_tar()[..], 0).unwrap(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn archive_tar_xz() {
+        use std::io::Write as _;
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(&test_tar()).unwrap();
+        archive_test("test.tar.xz", encoder.finish().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn archive_zip() {
+        archive_test("test.zip", test_zip()).await;
+    }
+
+    #[tokio::test]
+    async fn archive_unknown_format() {
+        let fix = Fixture::new().await;
+        fix.fs
+            .write(fix.artifact_dir.join("test.rar"), b"")
+            .await
+            .unwrap();
+        fix.builder
+            .build_layer(
+                LayerSpec::Archive {
+                    path: "test.rar".into(),
+                    prefix_options: Default::default(),
+                },
+                &fix.uploader,
+            )
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn archive_chmod_and_chown() {
+        let fix = Fixture::new().await;
+        fix.fs
+            .write(fix.artifact_dir.join("test.tar"), test_tar())
+            .await
+            .unwrap();
+        let manifest = fix
+            .build_layer(LayerSpec::Archive {
+                path: "test.tar".into(),
+                prefix_options: PrefixOptions {
+                    chmod: Some(0o600),
+                    chown: Some("0:100".parse().unwrap()),
+                    ..Default::default()
+                },
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "foo/",
+                    0o755,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "foo/a.txt",
+                    0o600,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+                ExpectedManifestEntry::new(
+                    "foo/b.txt",
+                    0o600,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"hi".to_vec())),
+                ),
+            ],
+        )
+        .await;
+        for entry in manifest_entries(&manifest).await {
+            assert_eq!(
+                entry.metadata.owner,
+                Some(maelstrom_base::manifest::Owner {
+                    user: 0.into(),
+                    group: 100.into(),
+                })
+            );
+        }
+    }
+
+    fn command_layer(script: &str) -> LayerSpec {
+        LayerSpec::Command {
+            command: vec!["/bin/sh".into(), "-c".into(), script.into()],
+            output: "out".into(),
+            inputs: vec!["in.txt".into()],
+            image: None,
+            layers: vec![],
+            prefix_options: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn command_local() {
+        let fix = Fixture::new().await;
+        fix.write_files(hashmap! { "in.txt" => "hello world" })
+            .await;
+        let layer = command_layer(
+            "umask 022 && mkdir -p out/sub && cp in.txt out/sub/a.txt && echo run >> runs",
+        );
+
+        let manifest = fix.build_layer(layer.clone()).await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "out/sub",
+                    0o40755,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "out/sub/a.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+            ],
+        )
+        .await;
+
+        // The command isn't run again until one of its inputs changes.
+        fix.build_layer(layer.clone()).await;
+        let runs = fix.artifact_dir.join("runs");
+        assert_eq!(fix.fs.read_to_string(&runs).await.unwrap(), "run\n");
+
+        fix.write_files(hashmap! { "in.txt" => "hello again" })
+            .await;
+        let manifest = fix.build_layer(layer).await;
+        assert_eq!(fix.fs.read_to_string(&runs).await.unwrap(), "run\nrun\n");
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "out/sub",
+                    0o40755,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "out/sub/a.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello again"))),
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn command_local_failure() {
+        let fix = Fixture::new().await;
+        fix.write_files(hashmap! { "in.txt" => "hello world" })
+            .await;
+        let err = fix
+            .builder
+            .build_layer(command_layer("echo oops >&2; exit 1"), &fix.uploader)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("oops"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn command_job() {
+        let fix = Fixture::new().await;
+        let key = hash_data(b"command");
+
+        // Pretend to be a job that writes some files into its root overlay's upper directory,
+        // including a special file, which shouldn't be kept.
+        let upper = fix
+            .builder
+            .command_layer_upper_dir(&key, |upper, work| async move {
+                let fs = async_fs::Fs::new();
+                assert!(fs.exists(&work).await);
+                fs.create_dir_all(upper.join("out/sub")).await?;
+                std::fs::set_permissions(upper.join("out/sub"), Permissions::from_mode(0o755))?;
+                fs.write(upper.join("out/sub/a.txt"), "hello world").await?;
+                std::fs::set_permissions(
+                    upper.join("out/sub/a.txt"),
+                    Permissions::from_mode(0o644),
+                )?;
+                std::os::unix::net::UnixListener::bind(upper.join("out/sub/socket"))?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        // The job isn't run again for the same key.
+        let cached_upper = fix
+            .builder
+            .command_layer_upper_dir(&key, |_, _| async { panic!("job run again") })
+            .await
+            .unwrap();
+        assert_eq!(cached_upper, upper);
+
+        let manifest = fix
+            .builder
+            .build_command_manifest(
+                &upper,
+                Utf8Path::new("/out"),
+                &key,
+                Default::default(),
+                &fix.uploader,
+            )
+            .await
+            .unwrap();
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "/out/sub",
+                    0o40755,
+                    ManifestEntryData::Directory { opaque: false },
+                ),
+                ExpectedManifestEntry::new(
+                    "/out/sub/a.txt",
+                    0o100644,
+                    ManifestEntryData::File(ManifestFileData::Digest(hash_data(b"hello world"))),
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn command_job_failure() {
+        let fix = Fixture::new().await;
+        let key = hash_data(b"command");
+        let err = fix
+            .builder
+            .command_layer_upper_dir(&key, |upper, _| async move {
+                async_fs::Fs::new().write(upper.join("a.txt"), "a").await?;
+                bail!("oops")
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "oops");
+
+        // Nothing is kept, so the job is run again next time.
+        let command_layer_dir = (**fix.builder.cache_dir).join(COMMAND_LAYER_DIR);
+        let mut entries = fix.fs.read_dir(&command_layer_dir).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn command_layers_pruned() {
+        let fix = Fixture::new().await;
+        let command_layer_dir = (**fix.builder.cache_dir).join(COMMAND_LAYER_DIR);
+        let set_age = |name: &str, age: Duration| {
+            std::fs::File::open(command_layer_dir.join(name))
+                .unwrap()
+                .set_modified(SystemTime::now() - age)
+                .unwrap()
+        };
+        for name in ["new", "old", ".tmpleftover/work/work"] {
+            fix.fs
+                .create_dir_all(command_layer_dir.join(name))
+                .await
+                .unwrap();
+        }
+        std::fs::set_permissions(
+            command_layer_dir.join(".tmpleftover/work/work"),
+            Permissions::from_mode(0o000),
+        )
+        .unwrap();
+        set_age("new", UNUSED_CACHE_ENTRY_MAX_AGE / 2);
+        set_age("old", UNUSED_CACHE_ENTRY_MAX_AGE * 2);
+
+        prune_unused_entries(&command_layer_dir, UNUSED_CACHE_ENTRY_MAX_AGE)
+            .await
+            .unwrap();
+        let mut entries = fix.fs.read_dir(&command_layer_dir).await.unwrap();
+        let mut names = vec![];
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name());
+        }
+        assert_eq!(names, vec!["new"]);
+    }
+
     async fn stubs_test(path: &str, expected: Vec<ExpectedManifestEntry>) {
         let fix = Fixture::new().await;
         let manifest = fix
@@ -913,6 +2343,73 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn files_test() {
+        let fix = Fixture::new().await;
+        let manifest = fix
+            .build_layer(LayerSpec::Files {
+                files: vec![
+                    FileSpec {
+                        path: utf8_path_buf!("/etc/foo.conf"),
+                        contents: FileContents::Contents("foo = 1\n".into()),
+                        mode: None,
+                    },
+                    FileSpec {
+                        path: utf8_path_buf!("/bin/bar"),
+                        contents: FileContents::Base64("IyEvYmluL3NoCg==".into()),
+                        mode: Some(0o755),
+                    },
+                    FileSpec {
+                        path: utf8_path_buf!("/empty"),
+                        contents: FileContents::Contents("".into()),
+                        mode: Some(0o600),
+                    },
+                ],
+            })
+            .await;
+        verify_manifest(
+            &manifest,
+            vec![
+                ExpectedManifestEntry::new(
+                    "/etc/foo.conf",
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"foo = 1\n".to_vec())),
+                ),
+                ExpectedManifestEntry::new(
+                    "/bin/bar",
+                    0o755,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"#!/bin/sh\n".to_vec())),
+                ),
+                ExpectedManifestEntry::new(
+                    "/empty",
+                    0o600,
+                    ManifestEntryData::File(ManifestFileData::Empty),
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn files_invalid_base64() {
+        let fix = Fixture::new().await;
+        let err = fix
+            .builder
+            .build_layer(
+                LayerSpec::Files {
+                    files: vec![FileSpec {
+                        path: utf8_path_buf!("/foo"),
+                        contents: FileContents::Base64("not base64!".into()),
+                        mode: None,
+                    }],
+                },
+                &fix.uploader,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("/foo"), "{err}");
+    }
+
     #[tokio::test]
     async fn shared_library_dependencies_test() {
         let fix = Fixture::new().await;
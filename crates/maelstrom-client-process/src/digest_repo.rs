@@ -1,12 +1,12 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use maelstrom_base::Sha256Digest;
-use maelstrom_util::async_fs::Fs;
+use maelstrom_base::{manifest::ManifestChunk, Sha256Digest};
+use maelstrom_util::async_fs::{self, Fs};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_with::{serde_as, DisplayFromStr};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::SeekFrom,
     path::{Path, PathBuf},
     time::SystemTime,
@@ -28,10 +28,40 @@ struct DigestRepositoryEntry {
     mtime: DateTime<Utc>,
 }
 
+#[serde_as]
+#[derive(Deserialize, Serialize)]
+struct ChunkEntry {
+    #[serde_as(as = "DisplayFromStr")]
+    digest: Sha256Digest,
+    size: u64,
+}
+
+/// The chunks a large file was split into, along with the modification time and size it had then.
+#[derive(Deserialize, Serialize)]
+struct ChunksEntry {
+    mtime: DateTime<Utc>,
+    size: u64,
+    chunks: Vec<ChunkEntry>,
+}
+
+impl ChunksEntry {
+    async fn is_current(&self, fs: &Fs, path: &Path) -> bool {
+        let Ok(metadata) = fs.metadata(path).await else {
+            return false;
+        };
+        let Ok(mtime) = metadata.modified() else {
+            return false;
+        };
+        DateTime::<Utc>::from(mtime) == self.mtime && metadata.len() == self.size
+    }
+}
+
 #[derive(Default, Deserialize, Serialize)]
 struct DigestRepositoryContents {
     version: DigestRepositoryVersion,
     digests: HashMap<PathBuf, DigestRepositoryEntry>,
+    #[serde(default)]
+    chunks: HashMap<PathBuf, ChunksEntry>,
 }
 
 impl DigestRepositoryContents {
@@ -61,12 +91,9 @@ impl DigestRepository {
         }
     }
 
-    pub async fn add(
-        &mut self,
-        path: PathBuf,
-        mtime: SystemTime,
-        digest: Sha256Digest,
-    ) -> Result<()> {
+    /// Open the file and lock it exclusively, returning it along with its contents. The contents
+    /// should then be written back with [`Self::write`].
+    async fn lock(&self) -> Result<(async_fs::File, DigestRepositoryContents)> {
         self.fs.create_dir_all(&self.path).await?;
         let mut file = self
             .fs
@@ -76,15 +103,15 @@ impl DigestRepository {
 
         let mut contents = String::new();
         file.read_to_string(&mut contents).await?;
-        let mut digests = DigestRepositoryContents::from_str(&contents).unwrap_or_default();
-        digests.digests.insert(
-            path,
-            DigestRepositoryEntry {
-                mtime: mtime.into(),
-                digest,
-            },
-        );
+        let digests = DigestRepositoryContents::from_str(&contents).unwrap_or_default();
+        Ok((file, digests))
+    }
 
+    async fn write(
+        &mut self,
+        mut file: async_fs::File,
+        digests: DigestRepositoryContents,
+    ) -> Result<()> {
         file.seek(SeekFrom::Start(0)).await?;
         file.set_len(0).await?;
         file.write_all(digests.to_pretty_string().as_bytes())
@@ -95,24 +122,130 @@ impl DigestRepository {
         Ok(())
     }
 
-    pub async fn get(&mut self, path: &PathBuf) -> Result<Option<Sha256Digest>> {
+    /// Read the file into the cache, if it hasn't been read yet and exists.
+    async fn load_cache(&mut self) -> Result<()> {
         if self.cache.is_none() {
-            let Some(contents) = self
+            if let Some(contents) = self
                 .fs
                 .read_to_string_if_exists(self.path.join(CACHED_IMAGE_FILE_NAME))
                 .await?
-            else {
-                return Ok(None);
-            };
-            self.cache = Some(DigestRepositoryContents::from_str(&contents).unwrap_or_default());
+            {
+                self.cache =
+                    Some(DigestRepositoryContents::from_str(&contents).unwrap_or_default());
+            }
         }
+        Ok(())
+    }
+
+    pub async fn add(
+        &mut self,
+        path: PathBuf,
+        mtime: SystemTime,
+        digest: Sha256Digest,
+    ) -> Result<()> {
+        let (file, mut digests) = self.lock().await?;
+        digests.digests.insert(
+            path,
+            DigestRepositoryEntry {
+                mtime: mtime.into(),
+                digest,
+            },
+        );
+        self.write(file, digests).await
+    }
 
-        let Some(entry) = self.cache.as_ref().unwrap().digests.get(path) else {
+    pub async fn get(&mut self, path: &PathBuf) -> Result<Option<Sha256Digest>> {
+        self.load_cache().await?;
+        let Some(entry) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.digests.get(path))
+        else {
             return Ok(None);
         };
         let current_mtime: DateTime<Utc> = self.fs.metadata(path).await?.modified()?.into();
         Ok((current_mtime == entry.mtime).then_some(entry.digest.clone()))
     }
+
+    /// Remember the chunks the file at `path` was split into, while it had the given modification
+    /// time and size.
+    pub async fn add_chunks(
+        &mut self,
+        path: PathBuf,
+        mtime: SystemTime,
+        size: u64,
+        chunks: &[ManifestChunk],
+    ) -> Result<()> {
+        let chunks = chunks
+            .iter()
+            .map(|chunk| ChunkEntry {
+                digest: chunk.digest.clone(),
+                size: chunk.size,
+            })
+            .collect();
+        let (file, mut digests) = self.lock().await?;
+        digests.chunks.insert(
+            path,
+            ChunksEntry {
+                mtime: mtime.into(),
+                size,
+                chunks,
+            },
+        );
+        self.write(file, digests).await
+    }
+
+    /// The chunks the file at `path` was split into, if its modification time and size haven't
+    /// changed since.
+    pub async fn get_chunks(&mut self, path: &Path) -> Result<Option<Vec<ManifestChunk>>> {
+        self.load_cache().await?;
+        let Some(entry) = self.cache.as_ref().and_then(|cache| cache.chunks.get(path)) else {
+            return Ok(None);
+        };
+        if !entry.is_current(&self.fs, path).await {
+            return Ok(None);
+        }
+        Ok(Some(
+            entry
+                .chunks
+                .iter()
+                .map(|chunk| ManifestChunk {
+                    digest: chunk.digest.clone(),
+                    size: chunk.size,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Forget the chunks of files that have changed or gone away since they were split, and remove
+    /// everything in `chunk_dir` that isn't a chunk of a file that's still remembered.
+    pub async fn prune_chunks(&mut self, chunk_dir: &Path) -> Result<()> {
+        let (file, mut digests) = self.lock().await?;
+        for (path, entry) in std::mem::take(&mut digests.chunks) {
+            if entry.is_current(&self.fs, &path).await {
+                digests.chunks.insert(path, entry);
+            }
+        }
+        let keep: HashSet<_> = digests
+            .chunks
+            .values()
+            .flat_map(|entry| &entry.chunks)
+            .map(|chunk| chunk.digest.to_string())
+            .collect();
+        self.write(file, digests).await?;
+
+        let mut entries = self.fs.read_dir(chunk_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let keep_entry = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| keep.contains(name));
+            if !keep_entry {
+                self.fs.remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[tokio::test]
@@ -150,3 +283,38 @@ async fn digest_repository_simple_add_get_after_modify() {
 
     assert_eq!(repo.get(&foo_path).await.unwrap(), None);
 }
+
+#[tokio::test]
+async fn digest_repository_chunks_pruned_after_modify() {
+    let fs = Fs::new();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let mut repo = DigestRepository::new(tmp_dir.path());
+    let chunk_dir = tmp_dir.path().join("chunks");
+    fs.create_dir(&chunk_dir).await.unwrap();
+
+    let foo_path = tmp_dir.path().join("foo.bin");
+    fs.write(&foo_path, "foobar").await.unwrap();
+    let chunk_path = chunk_dir.join(maelstrom_test::digest!(1).to_string());
+    fs.write(&chunk_path, "foobar").await.unwrap();
+    let stray_path = chunk_dir.join(maelstrom_test::digest!(2).to_string());
+    fs.write(&stray_path, "stray").await.unwrap();
+    let chunks = vec![ManifestChunk {
+        digest: maelstrom_test::digest!(1),
+        size: 6,
+    }];
+    let mtime = fs.metadata(&foo_path).await.unwrap().modified().unwrap();
+    repo.add_chunks(foo_path.clone(), mtime, 6, &chunks)
+        .await
+        .unwrap();
+    assert_eq!(repo.get_chunks(&foo_path).await.unwrap(), Some(chunks));
+
+    repo.prune_chunks(&chunk_dir).await.unwrap();
+    assert!(fs.exists(&chunk_path).await);
+    assert!(!fs.exists(&stray_path).await);
+
+    fs.write(&foo_path, "foobarbaz").await.unwrap();
+    assert_eq!(repo.get_chunks(&foo_path).await.unwrap(), None);
+
+    repo.prune_chunks(&chunk_dir).await.unwrap();
+    assert!(!fs.exists(&chunk_path).await);
+}
@@ -7,6 +7,7 @@ mod router;
 mod rpc;
 mod stream_wrapper;
 
+pub use client::LayerBuilder;
 pub use maelstrom_util::process::clone_into_pid_and_user_namespace;
 
 use anyhow::Result;
@@ -0,0 +1,305 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use clap::{Args, Parser, Subcommand};
+use maelstrom_base::{
+    manifest::{ManifestEntry, ManifestEntryData},
+    Sha256Digest, Utf8PathBuf,
+};
+use maelstrom_client_base::{
+    spec::{FileOwner, LayerSpec, PrefixOptions},
+    CacheDir, ProjectDir, CHUNK_DIR, MANIFEST_DIR,
+};
+use maelstrom_client_process::LayerBuilder;
+use maelstrom_util::{
+    async_fs,
+    fs::Fs,
+    io::Sha256Stream,
+    manifest::{
+        tar::{manifest_from_tar, manifest_to_tar},
+        DataUpload, ManifestReader,
+    },
+    root::RootBuf,
+};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The same limit the client uses when building layers.
+const DEFAULT_INLINE_LIMIT: u64 = 200 * 1024;
+
+#[derive(Subcommand)]
+enum CliCommands {
+    /// Build a manifest from the files under a directory, the same way the client builds a glob
+    /// layer.
+    Build {
+        output: PathBuf,
+        root: PathBuf,
+        /// Only include the files whose paths, relative to the root, match this glob.
+        #[arg(long, default_value = "**")]
+        glob: String,
+        #[command(flatten)]
+        prefix_options: PrefixOptionsArgs,
+        /// Store files no bigger than this many bytes in the manifest itself.
+        #[arg(long, default_value_t = DEFAULT_INLINE_LIMIT)]
+        inline_limit: u64,
+    },
+    /// List the entries of a manifest along with their sizes and modes.
+    Ls { manifest: PathBuf },
+    /// Print the raw entries of a manifest.
+    Dump { manifest: PathBuf },
+    /// Print the paths which differ between two manifests.
+    Diff { left: PathBuf, right: PathBuf },
+    /// Convert a tar file into a manifest.
+    FromTar {
+        tar: PathBuf,
+        output: PathBuf,
+        /// Store files no bigger than this many bytes in the manifest itself.
+        #[arg(long, default_value_t = DEFAULT_INLINE_LIMIT)]
+        inline_limit: u64,
+    },
+    /// Write the contents of a manifest to a tar file.
+    ToTar { manifest: PathBuf, output: PathBuf },
+}
+
+/// The prefix options of a layer, as described in the book.
+#[derive(Args)]
+struct PrefixOptionsArgs {
+    /// Remove this prefix from the paths of the files.
+    #[arg(long)]
+    strip_prefix: Option<Utf8PathBuf>,
+    /// Add this prefix to the paths of the files, after stripping any prefix.
+    #[arg(long)]
+    prepend_prefix: Option<Utf8PathBuf>,
+    /// Canonicalize the paths of the files before stripping and prepending prefixes.
+    #[arg(long)]
+    canonicalize: bool,
+    /// Include the files symlinks point to instead of the symlinks themselves.
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Replace the permission bits of every regular file with these, given in octal.
+    #[arg(long, value_parser = parse_octal)]
+    chmod: Option<u32>,
+    /// Make every file owned by this user and group, given as UID:GID.
+    #[arg(long)]
+    chown: Option<FileOwner>,
+}
+
+impl From<PrefixOptionsArgs> for PrefixOptions {
+    fn from(args: PrefixOptionsArgs) -> Self {
+        Self {
+            strip_prefix: args.strip_prefix,
+            prepend_prefix: args.prepend_prefix,
+            canonicalize: args.canonicalize,
+            follow_symlinks: args.follow_symlinks,
+            chmod: args.chmod,
+            chown: args.chown,
+        }
+    }
+}
+
+fn parse_octal(arg: &str) -> Result<u32> {
+    Ok(u32::from_str_radix(arg, 8)?)
+}
+
+#[derive(Parser)]
+#[command(styles=maelstrom_util::clap::styles())]
+struct CliOptions {
+    /// The directory containing file-data referred to by digest, each file named after its digest.
+    /// The build and from-tar commands write file-data to it, and to-tar reads file-data from it.
+    /// Without it, build and from-tar only compute digests, and to-tar can only write files whose
+    /// data is stored in the manifest.
+    #[arg(long, global = true)]
+    blob_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: CliCommands,
+}
+
+/// Computes the digests of files and, if there is a blob directory, copies them into it.
+struct BlobDirUpload {
+    blob_dir: Option<PathBuf>,
+}
+
+#[async_trait]
+impl DataUpload for BlobDirUpload {
+    async fn upload(&mut self, path: &Path) -> Result<Sha256Digest> {
+        let path = path.to_owned();
+        let blob_dir = self.blob_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let fs = Fs::new();
+            let mut stream = Sha256Stream::new(fs.open_file(&path)?);
+            io::copy(&mut stream, &mut io::sink())?;
+            let (_, digest) = stream.finalize();
+            if let Some(blob_dir) = blob_dir {
+                let blob_path = blob_dir.join(digest.to_string());
+                if !fs.exists(&blob_path) {
+                    fs.copy(&path, &blob_path)?;
+                }
+            }
+            Ok(digest)
+        })
+        .await?
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>> {
+    let fs = Fs::new();
+    let reader = ManifestReader::new(io::BufReader::new(fs.open_file(path)?))?;
+    Ok(reader.collect::<io::Result<_>>()?)
+}
+
+fn describe(entry: &ManifestEntry) -> String {
+    let (kind, suffix) = match &entry.data {
+        ManifestEntryData::Directory { opaque: false } => ("directory", String::new()),
+        ManifestEntryData::Directory { opaque: true } => ("directory", " (opaque)".into()),
+        ManifestEntryData::File(_) => ("file", String::new()),
+        ManifestEntryData::Symlink(target) => (
+            "symlink",
+            format!(" -> {}", String::from_utf8_lossy(target)),
+        ),
+        ManifestEntryData::Hardlink(target) => ("hardlink", format!(" => {target}")),
+        ManifestEntryData::Whiteout => ("whiteout", String::new()),
+    };
+    format!(
+        "{kind:<9} {:06o} {:>10} {:>12} {}{suffix}",
+        u32::from(entry.metadata.mode),
+        entry.metadata.size,
+        entry.metadata.mtime.0,
+        entry.path,
+    )
+}
+
+async fn build(
+    output: &Path,
+    root: &Path,
+    glob: String,
+    prefix_options: PrefixOptions,
+    inline_limit: u64,
+    blob_dir: Option<PathBuf>,
+) -> Result<()> {
+    let fs = async_fs::Fs::new();
+    let root = fs.canonicalize(root).await?;
+    // The layer builder keeps its manifests and chunks in a cache directory. Nothing in it is
+    // needed once the manifest has been copied out.
+    let cache_dir = tempfile::tempdir()?;
+    for dir in [MANIFEST_DIR, CHUNK_DIR] {
+        fs.create_dir_all(cache_dir.path().join(dir)).await?;
+    }
+    let layer_builder = LayerBuilder::new(
+        RootBuf::<CacheDir>::new(cache_dir.path().to_owned()),
+        RootBuf::<ProjectDir>::new(root),
+        inline_limit,
+    );
+    let layer = LayerSpec::Glob {
+        glob,
+        prefix_options,
+    };
+    let (manifest_path, _) = layer_builder
+        .build_layer(layer, BlobDirUpload { blob_dir })
+        .await?;
+    fs.copy(manifest_path, output).await?;
+    Ok(())
+}
+
+fn diff(left: &Path, right: &Path) -> Result<()> {
+    let by_path = |entries: Vec<ManifestEntry>| -> BTreeMap<_, _> {
+        entries
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect()
+    };
+    let left = by_path(read_manifest(left)?);
+    let mut right = by_path(read_manifest(right)?);
+    let mut differences = vec![];
+    for (path, left_entry) in left {
+        match right.remove(&path) {
+            None => differences.push(('-', path)),
+            Some(right_entry) if right_entry != left_entry => differences.push(('~', path)),
+            Some(_) => {}
+        }
+    }
+    differences.extend(right.into_keys().map(|path| ('+', path)));
+    differences.sort_by(|(_, a), (_, b)| a.cmp(b));
+    for (marker, path) in differences {
+        println!("{marker} {path}");
+    }
+    Ok(())
+}
+
+fn from_tar(tar: &Path, output: &Path, inline_limit: u64, blob_dir: Option<PathBuf>) -> Result<()> {
+    let fs = Fs::new();
+    // Without a blob directory, the file-data is thrown away once the digests are known.
+    let temp_dir;
+    let data_dir = match &blob_dir {
+        Some(blob_dir) => blob_dir.as_path(),
+        None => {
+            temp_dir = tempfile::tempdir()?;
+            temp_dir.path()
+        }
+    };
+    manifest_from_tar(
+        io::BufReader::new(fs.open_file(tar)?),
+        fs.create_file(output)?,
+        data_dir,
+        inline_limit,
+    )?;
+    Ok(())
+}
+
+fn to_tar(manifest: &Path, output: &Path, blob_dir: Option<PathBuf>) -> Result<()> {
+    let fs = Fs::new();
+    let result = manifest_to_tar(
+        io::BufReader::new(fs.open_file(manifest)?),
+        io::BufWriter::new(fs.create_file(output)?),
+        blob_dir.as_deref(),
+    );
+    if blob_dir.is_none() {
+        result.context("the manifest can't be converted without --blob-dir")
+    } else {
+        result
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = CliOptions::parse();
+    match opt.command {
+        CliCommands::Build {
+            output,
+            root,
+            glob,
+            prefix_options,
+            inline_limit,
+        } => {
+            build(
+                &output,
+                &root,
+                glob,
+                prefix_options.into(),
+                inline_limit,
+                opt.blob_dir,
+            )
+            .await?
+        }
+        CliCommands::Ls { manifest } => {
+            for entry in read_manifest(&manifest)? {
+                println!("{}", describe(&entry));
+            }
+        }
+        CliCommands::Dump { manifest } => {
+            for entry in read_manifest(&manifest)? {
+                println!("{entry:#?}");
+            }
+        }
+        CliCommands::Diff { left, right } => diff(&left, &right)?,
+        CliCommands::FromTar {
+            tar,
+            output,
+            inline_limit,
+        } => from_tar(&tar, &output, inline_limit, opt.blob_dir)?,
+        CliCommands::ToTar { manifest, output } => to_tar(&manifest, &output, opt.blob_dir)?,
+    }
+    Ok(())
+}
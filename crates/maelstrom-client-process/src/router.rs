@@ -34,7 +34,7 @@ pub trait Deps {
 pub enum Message<DepsT: Deps> {
     // These are requests from the client.
     AddArtifact(PathBuf, Sha256Digest),
-    RunJob(JobSpec, DepsT::JobHandle),
+    RunJob(Box<JobSpec>, DepsT::JobHandle),
     Shutdown(Error),
 
     // Only in remote-broker mode.
@@ -115,10 +115,10 @@ impl<DepsT: Deps> Router<DepsT> {
                             cid: ClientId::from(0),
                             cjid,
                         },
-                        spec,
+                        *spec,
                     );
                 } else {
-                    self.deps.send_job_request_to_broker(cjid, spec);
+                    self.deps.send_job_request_to_broker(cjid, *spec);
                 }
             }
             Message::Broker(BrokerToClient::JobResponse(cjid, result)) => {
@@ -201,9 +201,11 @@ impl Deps for Adapter {
     }
 
     fn send_enqueue_job_to_local_worker(&self, jid: JobId, spec: JobSpec) {
-        let _ = self.local_worker_sender.send(local_worker::Message::Broker(
-            BrokerToWorker::EnqueueJob(jid, spec),
-        ));
+        let _ = self
+            .local_worker_sender
+            .send(local_worker::Message::Broker(Box::new(
+                BrokerToWorker::EnqueueJob(jid, spec),
+            )));
     }
 
     fn send_artifact_fetch_completed_to_local_worker(
@@ -468,10 +470,10 @@ mod tests {
     script_test! {
         run_job_standalone,
         Fixture::new(true),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar)),
         };
-        RunJob(spec!(1, Tar), cjid!(1)) => {
+        RunJob(Box::new(spec!(1, Tar)), cjid!(1)) => {
             EnqueueJobToLocalWorker(jid!(0, 1), spec!(1, Tar)),
         };
     }
@@ -479,20 +481,20 @@ mod tests {
     script_test! {
         run_job_clustered,
         Fixture::new(false),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             JobRequestToBroker(cjid!(0), spec!(0, Tar)),
         };
-        RunJob(spec!(1, Tar), cjid!(1)) => {
+        RunJob(Box::new(spec!(1, Tar)), cjid!(1)) => {
             JobRequestToBroker(cjid!(1), spec!(1, Tar)),
         };
     }
     script_test! {
         run_job_must_be_local_clustered,
         Fixture::new(false),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             JobRequestToBroker(cjid!(0), spec!(0, Tar)),
         };
-        RunJob(spec!(1, Tar).network(JobNetwork::Local), cjid!(1)) => {
+        RunJob(Box::new(spec!(1, Tar).network(JobNetwork::Local)), cjid!(1)) => {
             EnqueueJobToLocalWorker(jid!(0, 1), spec!(1, Tar).network(JobNetwork::Local)),
         };
     }
@@ -504,7 +506,7 @@ mod tests {
         // Give it a job just so it doesn't crash subracting the job counts.
         fixture
             .router
-            .receive_message(RunJob(spec!(0, Tar), cjid!(0)));
+            .receive_message(RunJob(Box::new(spec!(0, Tar)), cjid!(0)));
         fixture
             .router
             .receive_message(LocalWorker(WorkerToBroker::JobResponse(
@@ -516,7 +518,7 @@ mod tests {
     script_test! {
         job_response_from_local_worker_known_standalone,
         Fixture::new(true),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar)),
         };
         LocalWorker(WorkerToBroker::JobResponse(jid!(0, 0), Ok(outcome!(0)))) => {
@@ -529,9 +531,10 @@ mod tests {
     fn job_response_from_local_worker_unknown_clustered() {
         let mut fixture = Fixture::new(false);
         // Give it a job just so it doesn't crash subracting the job counts.
-        fixture
-            .router
-            .receive_message(RunJob(spec!(0, Tar).network(JobNetwork::Local), cjid!(0)));
+        fixture.router.receive_message(RunJob(
+            Box::new(spec!(0, Tar).network(JobNetwork::Local)),
+            cjid!(0),
+        ));
         fixture
             .router
             .receive_message(LocalWorker(WorkerToBroker::JobResponse(
@@ -543,7 +546,7 @@ mod tests {
     script_test! {
         job_response_from_local_worker_known_clustered,
         Fixture::new(false),
-        RunJob(spec!(0, Tar).network(JobNetwork::Local), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar).network(JobNetwork::Local)), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar).network(JobNetwork::Local)),
         };
         LocalWorker(WorkerToBroker::JobResponse(jid!(0, 0), Ok(outcome!(0)))) => {
@@ -569,7 +572,7 @@ mod tests {
         let mut fixture = Fixture::new(true);
         fixture
             .router
-            .receive_message(RunJob(spec!(0, Tar), cjid!(0)));
+            .receive_message(RunJob(Box::new(spec!(0, Tar)), cjid!(0)));
         fixture
             .router
             .receive_message(Broker(BrokerToClient::JobResponse(
@@ -593,7 +596,7 @@ mod tests {
     script_test! {
         job_response_from_broker_known_clustered,
         Fixture::new(false),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             JobRequestToBroker(cjid!(0), spec!(0, Tar)),
         };
         Broker(BrokerToClient::JobResponse(cjid!(0), Ok(outcome!(0)))) => {
@@ -604,10 +607,10 @@ mod tests {
     script_test! {
         shutdown_standalone,
         Fixture::new(true),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar)),
         };
-        RunJob(spec!(1, Tar), cjid!(1)) => {
+        RunJob(Box::new(spec!(1, Tar)), cjid!(1)) => {
             EnqueueJobToLocalWorker(jid!(0, 1), spec!(1, Tar)),
         };
         Shutdown(anyhow!("test error")) => {
@@ -618,10 +621,10 @@ mod tests {
     script_test! {
         shutdown_clustered,
         Fixture::new(false),
-        RunJob(spec!(0, Tar), cjid!(0)) => {
+        RunJob(Box::new(spec!(0, Tar)), cjid!(0)) => {
             JobRequestToBroker(cjid!(0), spec!(0, Tar)),
         };
-        RunJob(spec!(1, Tar), cjid!(1)) => {
+        RunJob(Box::new(spec!(1, Tar)), cjid!(1)) => {
             JobRequestToBroker(cjid!(1), spec!(1, Tar)),
         };
         Shutdown(anyhow!("test error")) => {
@@ -1,13 +1,13 @@
 use assert_matches::assert_matches;
 use maelstrom_base::{
-    JobCompleted, JobEffects, JobMount, JobNetwork, JobOutcome, JobOutputResult,
-    JobTerminationStatus, Utf8Path, Utf8PathBuf,
+    JobCompleted, JobEffects, JobNetwork, JobOutcome, JobOutputResult, JobTerminationStatus,
+    Utf8Path, Utf8PathBuf,
 };
 use maelstrom_client::{
     AcceptInvalidRemoteContainerTlsCerts, CacheDir, Client, ClientBgProcess,
     ContainerImageDepotDir, ContainerSpec, ProjectDir, StateDir,
 };
-use maelstrom_client_base::spec::{JobSpec, LayerSpec, PrefixOptions, SymlinkSpec};
+use maelstrom_client_base::spec::{JobMount, JobSpec, LayerSpec, PrefixOptions, SymlinkSpec};
 use maelstrom_util::{elf::read_shared_libraries, fs::Fs, log::test_logger, root::Root};
 use regex::Regex;
 use std::panic::Location;
@@ -140,6 +140,12 @@ impl ClientFixture {
             network,
             user: None,
             group: None,
+            groups: vec![],
+            synthesize_user_files: false,
+            hostname: None,
+            hosts: vec![],
+            capabilities: Default::default(),
+            init: false,
         };
         self.client.add_container(name, spec).unwrap_err()
     }
@@ -391,3 +397,69 @@ fn single_test() {
     fix.run_test(symlinks_test, paths_test_job);
     fix.run_test(sys_local_network_error_test, panic_test_job);
 }
+
+/// Build a command layer by running this test binary as a job on a stack of layers, then run a job
+/// with that layer to check what it contains. Both jobs run this test too, and they tell what to
+/// do by which marker file the job's layers provide.
+#[test]
+fn command_layer_test() {
+    const COMMAND_MARKER: &str = "/command-layer-command";
+    const CHECK_MARKER: &str = "/command-layer-check";
+    let fs = Fs::new();
+    if fs.exists(COMMAND_MARKER) {
+        fs.create_dir_all("/out/sub").unwrap();
+        fs.write("/out/sub/foo.txt", b"hello from a command layer")
+            .unwrap();
+        return;
+    }
+    if fs.exists(CHECK_MARKER) {
+        let contents = fs.read_to_string("/out/sub/foo.txt").unwrap();
+        assert!(!fs.exists(COMMAND_MARKER));
+        println!("{contents}");
+        return;
+    }
+
+    let fix = ClientFixture::new();
+    let marker = |path: &str| LayerSpec::Stubs {
+        stubs: vec![path.into()],
+    };
+    let command_layer = LayerSpec::Command {
+        command: vec![
+            fix.self_path.to_string(),
+            "--exact".into(),
+            "command_layer_test".into(),
+        ],
+        output: "/out".into(),
+        inputs: vec![],
+        image: None,
+        layers: fix
+            .layers
+            .iter()
+            .cloned()
+            .chain([marker(COMMAND_MARKER)])
+            .collect(),
+        prefix_options: Default::default(),
+    };
+    let layers = fix
+        .layers
+        .iter()
+        .cloned()
+        .chain([command_layer, marker(CHECK_MARKER)])
+        .collect::<Vec<_>>();
+    let spec = JobSpec::new(fix.self_path.clone(), layers).arguments([
+        "--exact",
+        "command_layer_test",
+        "--nocapture",
+    ]);
+    let (_, outcome) = fix.client.run_job(spec).unwrap();
+    let output = assert_matches!(
+        outcome,
+        Ok(JobOutcome::Completed(JobCompleted {
+            status: JobTerminationStatus::Exited(0),
+            effects: JobEffects { stdout: JobOutputResult::Inline(stdout), .. },
+            ..
+        })) => stdout
+    );
+    let output = std::str::from_utf8(&output).unwrap();
+    assert!(output.contains("hello from a command layer"), "{output}");
+}
@@ -1,13 +1,13 @@
 use crate::fuser::ll::fuse_abi as abi;
 use crate::fuser::reply::ReplySender;
 use async_trait::async_trait;
-use maelstrom_linux::Fd;
+use maelstrom_linux::{self as linux, Fd};
 use maelstrom_util::io::MaybeFastWriter;
 use std::os::fd::AsRawFd as _;
 use std::{
     fs::File,
     io::{self, Read as _, Write as _},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 use tokio::io::unix::AsyncFd;
 use tokio::io::Interest;
@@ -86,6 +86,29 @@ fn run_splice_sender(
     }
 }
 
+/// A file registered with the kernel as the backing file for passthrough opens. The registration
+/// is removed when this is dropped, or when the connection goes away.
+#[derive(Debug)]
+pub struct BackingId {
+    device: Weak<AsyncFd<File>>,
+    id: u32,
+}
+
+impl BackingId {
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for BackingId {
+    fn drop(&mut self) {
+        if let Some(device) = self.device.upgrade() {
+            let device_fd = Fd::from_raw(device.get_ref().as_raw_fd());
+            linux::ioctl_fuse_dev_backing_close(&device_fd, self.id).ok();
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ChannelSender {
     device: Arc<AsyncFd<File>>,
@@ -140,4 +163,13 @@ impl ReplySender for ChannelSender {
             .unwrap();
         recv.await.unwrap()
     }
+
+    fn open_backing(&self, fd: Fd) -> io::Result<BackingId> {
+        let device_fd = Fd::from_raw(self.device.get_ref().as_raw_fd());
+        let id = linux::ioctl_fuse_dev_backing_open(&device_fd, &fd)?;
+        Ok(BackingId {
+            device: Arc::downgrade(&self.device),
+            id,
+        })
+    }
 }
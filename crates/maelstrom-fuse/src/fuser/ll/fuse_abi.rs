@@ -95,34 +95,37 @@ pub mod consts {
     pub const FOPEN_NONSEEKABLE: u32 = 1 << 2; // the file is not seekable
     pub const FOPEN_CACHE_DIR: u32 = 1 << 3; // allow caching this directory
     pub const FOPEN_STREAM: u32 = 1 << 4; // the file is stream-like (no file position at all)
+    pub const FOPEN_PASSTHROUGH: u32 = 1 << 7; // pass reads and writes through to the backing file
 
     // Init request/reply flags
-    pub const FUSE_ASYNC_READ: u32 = 1 << 0; // asynchronous read requests
-    pub const FUSE_POSIX_LOCKS: u32 = 1 << 1; // remote locking for POSIX file locks
-    pub const FUSE_FILE_OPS: u32 = 1 << 2; // kernel sends file handle for fstat, etc...
-    pub const FUSE_ATOMIC_O_TRUNC: u32 = 1 << 3; // handles the O_TRUNC open flag in the filesystem
-    pub const FUSE_EXPORT_SUPPORT: u32 = 1 << 4; // filesystem handles lookups of "." and ".."
-    pub const FUSE_BIG_WRITES: u32 = 1 << 5; // filesystem can handle write size larger than 4kB
-    pub const FUSE_DONT_MASK: u32 = 1 << 6; // don't apply umask to file mode on create operations
-    pub const FUSE_SPLICE_WRITE: u32 = 1 << 7; // kernel supports splice write on the device
-    pub const FUSE_SPLICE_MOVE: u32 = 1 << 8; // kernel supports splice move on the device
-    pub const FUSE_SPLICE_READ: u32 = 1 << 9; // kernel supports splice read on the device
-    pub const FUSE_FLOCK_LOCKS: u32 = 1 << 10; // remote locking for BSD style file locks
-    pub const FUSE_HAS_IOCTL_DIR: u32 = 1 << 11; // kernel supports ioctl on directories
-    pub const FUSE_AUTO_INVAL_DATA: u32 = 1 << 12; // automatically invalidate cached pages
-    pub const FUSE_DO_READDIRPLUS: u32 = 1 << 13; // do READDIRPLUS (READDIR+LOOKUP in one)
-    pub const FUSE_READDIRPLUS_AUTO: u32 = 1 << 14; // adaptive readdirplus
-    pub const FUSE_ASYNC_DIO: u32 = 1 << 15; // asynchronous direct I/O submission
-    pub const FUSE_WRITEBACK_CACHE: u32 = 1 << 16; // use writeback cache for buffered writes
-    pub const FUSE_NO_OPEN_SUPPORT: u32 = 1 << 17; // kernel supports zero-message opens
-    pub const FUSE_PARALLEL_DIROPS: u32 = 1 << 18; // allow parallel lookups and readdir
-    pub const FUSE_HANDLE_KILLPRIV: u32 = 1 << 19; // fs handles killing suid/sgid/cap on write/chown/trunc
-    pub const FUSE_POSIX_ACL: u32 = 1 << 20; // filesystem supports posix acls
-    pub const FUSE_ABORT_ERROR: u32 = 1 << 21; // reading the device after abort returns ECONNABORTED
-    pub const FUSE_MAX_PAGES: u32 = 1 << 22; // init_out.max_pages contains the max number of req pages
-    pub const FUSE_CACHE_SYMLINKS: u32 = 1 << 23; // cache READLINK responses
-    pub const FUSE_NO_OPENDIR_SUPPORT: u32 = 1 << 24; // kernel supports zero-message opendir
-    pub const FUSE_EXPLICIT_INVAL_DATA: u32 = 1 << 25; // only invalidate cached pages on explicit request
+    pub const FUSE_ASYNC_READ: u64 = 1 << 0; // asynchronous read requests
+    pub const FUSE_POSIX_LOCKS: u64 = 1 << 1; // remote locking for POSIX file locks
+    pub const FUSE_FILE_OPS: u64 = 1 << 2; // kernel sends file handle for fstat, etc...
+    pub const FUSE_ATOMIC_O_TRUNC: u64 = 1 << 3; // handles the O_TRUNC open flag in the filesystem
+    pub const FUSE_EXPORT_SUPPORT: u64 = 1 << 4; // filesystem handles lookups of "." and ".."
+    pub const FUSE_BIG_WRITES: u64 = 1 << 5; // filesystem can handle write size larger than 4kB
+    pub const FUSE_DONT_MASK: u64 = 1 << 6; // don't apply umask to file mode on create operations
+    pub const FUSE_SPLICE_WRITE: u64 = 1 << 7; // kernel supports splice write on the device
+    pub const FUSE_SPLICE_MOVE: u64 = 1 << 8; // kernel supports splice move on the device
+    pub const FUSE_SPLICE_READ: u64 = 1 << 9; // kernel supports splice read on the device
+    pub const FUSE_FLOCK_LOCKS: u64 = 1 << 10; // remote locking for BSD style file locks
+    pub const FUSE_HAS_IOCTL_DIR: u64 = 1 << 11; // kernel supports ioctl on directories
+    pub const FUSE_AUTO_INVAL_DATA: u64 = 1 << 12; // automatically invalidate cached pages
+    pub const FUSE_DO_READDIRPLUS: u64 = 1 << 13; // do READDIRPLUS (READDIR+LOOKUP in one)
+    pub const FUSE_READDIRPLUS_AUTO: u64 = 1 << 14; // adaptive readdirplus
+    pub const FUSE_ASYNC_DIO: u64 = 1 << 15; // asynchronous direct I/O submission
+    pub const FUSE_WRITEBACK_CACHE: u64 = 1 << 16; // use writeback cache for buffered writes
+    pub const FUSE_NO_OPEN_SUPPORT: u64 = 1 << 17; // kernel supports zero-message opens
+    pub const FUSE_PARALLEL_DIROPS: u64 = 1 << 18; // allow parallel lookups and readdir
+    pub const FUSE_HANDLE_KILLPRIV: u64 = 1 << 19; // fs handles killing suid/sgid/cap on write/chown/trunc
+    pub const FUSE_POSIX_ACL: u64 = 1 << 20; // filesystem supports posix acls
+    pub const FUSE_ABORT_ERROR: u64 = 1 << 21; // reading the device after abort returns ECONNABORTED
+    pub const FUSE_MAX_PAGES: u64 = 1 << 22; // init_out.max_pages contains the max number of req pages
+    pub const FUSE_CACHE_SYMLINKS: u64 = 1 << 23; // cache READLINK responses
+    pub const FUSE_NO_OPENDIR_SUPPORT: u64 = 1 << 24; // kernel supports zero-message opendir
+    pub const FUSE_EXPLICIT_INVAL_DATA: u64 = 1 << 25; // only invalidate cached pages on explicit request
+    pub const FUSE_INIT_EXT: u64 = 1 << 30; // extended fuse_init_in request, flags2 holds the upper flags
+    pub const FUSE_PASSTHROUGH: u64 = 1 << 37; // kernel supports passthrough of opens to backing files
 
     // CUSE init request/reply flags
     pub const CUSE_UNRESTRICTED_IOCTL: u32 = 1 << 0; // use unrestricted ioctl
@@ -421,7 +424,7 @@ pub struct fuse_create_out(pub fuse_entry_out, pub fuse_open_out);
 pub struct fuse_open_out {
     pub fh: u64,
     pub open_flags: u32,
-    pub padding: u32,
+    pub backing_id: i32,
 }
 
 #[repr(C)]
@@ -566,8 +569,10 @@ pub struct fuse_init_out {
     pub max_write: u32,
     pub time_gran: u32,
     pub max_pages: u16,
-    pub unused2: u16,
-    pub reserved: [u32; 8],
+    pub map_alignment: u16,
+    pub flags2: u32,
+    pub max_stack_depth: u32,
+    pub reserved: [u32; 6],
 }
 
 #[repr(C)]
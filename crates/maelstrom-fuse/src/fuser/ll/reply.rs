@@ -132,7 +132,16 @@ impl<'a> Response<'a> {
         let r = abi::fuse_open_out {
             fh: fh.into(),
             open_flags: flags,
-            padding: 0,
+            backing_id: 0,
+        };
+        Self::from_struct(&r)
+    }
+
+    pub(crate) fn new_open_passthrough(fh: FileHandle, flags: u32, backing_id: u32) -> Self {
+        let r = abi::fuse_open_out {
+            fh: fh.into(),
+            open_flags: flags | abi::consts::FOPEN_PASSTHROUGH,
+            backing_id: backing_id as i32,
         };
         Self::from_struct(&r)
     }
@@ -211,7 +220,7 @@ impl<'a> Response<'a> {
             abi::fuse_open_out {
                 fh: fh.into(),
                 open_flags: flags,
-                padding: 0,
+                backing_id: 0,
             },
         );
         Self::from_struct(&r)
@@ -634,6 +643,18 @@ mod tests {
         assert_eq!(ioslice_to_vec(&r.as_iovec(&header)), expected);
     }
 
+    #[test]
+    fn reply_open_passthrough() {
+        let expected = vec![
+            0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xb3, 0x00, 0x00, 0x00,
+            0x44, 0x00, 0x00, 0x00,
+        ];
+        let r = Response::new_open_passthrough(FileHandle(0x1122), 0x33, 0x44);
+        let header = r.header(RequestId(0xdeadbeef));
+        assert_eq!(ioslice_to_vec(&r.as_iovec(&header)), expected);
+    }
+
     #[test]
     fn reply_write() {
         let expected = vec![
@@ -818,11 +818,19 @@ mod op {
     pub struct Init<'a> {
         header: &'a fuse_in_header,
         arg: &'a fuse_init_in,
+        /// The upper flags, only sent by kernels that support `FUSE_INIT_EXT`
+        flags2: Option<&'a u32>,
     }
     impl_request!(Init<'a>);
     impl<'a> Init<'a> {
-        pub fn capabilities(&self) -> u32 {
-            self.arg.flags
+        pub fn capabilities(&self) -> u64 {
+            let flags = u64::from(self.arg.flags);
+            match self.flags2 {
+                Some(flags2) if flags & consts::FUSE_INIT_EXT != 0 => {
+                    flags | (u64::from(*flags2) << 32)
+                }
+                _ => flags,
+            }
         }
         pub fn max_readahead(&self) -> u32 {
             self.arg.max_readahead
@@ -832,18 +840,21 @@ mod op {
         }
 
         pub fn reply(&self, config: &crate::fuser::KernelConfig) -> Response<'a> {
+            let flags = self.capabilities() & config.requested; // use requested features and reported as capable
             let init = fuse_init_out {
                 major: FUSE_KERNEL_VERSION,
                 minor: FUSE_KERNEL_MINOR_VERSION,
                 max_readahead: config.max_readahead,
-                flags: self.capabilities() & config.requested, // use requested features and reported as capable
+                flags: flags as u32,
                 max_background: config.max_background,
                 congestion_threshold: config.congestion_threshold(),
                 max_write: config.max_write,
                 time_gran: config.time_gran.as_nanos() as u32,
                 max_pages: config.max_pages(),
-                unused2: 0,
-                reserved: [0; 8],
+                map_alignment: 0,
+                flags2: (flags >> 32) as u32,
+                max_stack_depth: config.max_stack_depth,
+                reserved: [0; 6],
             };
             Response::new_data(init.as_bytes())
         }
@@ -1482,6 +1493,7 @@ mod op {
             fuse_opcode::FUSE_INIT => Operation::Init(Init {
                 header,
                 arg: data.fetch()?,
+                flags2: data.fetch(),
             }),
             fuse_opcode::FUSE_OPENDIR => Operation::OpenDir(OpenDir {
                 header,
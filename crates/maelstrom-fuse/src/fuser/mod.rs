@@ -2,6 +2,7 @@
 //!
 //! This code is copied and adapted from <https://github.com/cberner/fuser>
 
+pub use channel::BackingId;
 pub use ll::{
     fuse_abi::{consts, fuse_forget_one, FUSE_ROOT_ID},
     TimeOrNow,
@@ -34,13 +35,16 @@ mod request;
 mod session;
 
 /// We generally support async reads
-const INIT_FLAGS: u32 = FUSE_ASYNC_READ | FUSE_BIG_WRITES;
+const INIT_FLAGS: u64 = FUSE_ASYNC_READ | FUSE_BIG_WRITES;
 
-const fn default_init_flags(#[allow(unused_variables)] capabilities: u32) -> u32 {
+const fn default_init_flags(#[allow(unused_variables)] capabilities: u64) -> u64 {
     let mut flags = INIT_FLAGS;
     if capabilities & FUSE_MAX_PAGES != 0 {
         flags |= FUSE_MAX_PAGES;
     }
+    if capabilities & FUSE_INIT_EXT != 0 {
+        flags |= FUSE_INIT_EXT;
+    }
     flags
 }
 
@@ -97,18 +101,19 @@ pub struct FileAttr {
 /// Configuration of the fuse kernel module connection
 #[derive(Debug)]
 pub struct KernelConfig {
-    capabilities: u32,
-    requested: u32,
+    capabilities: u64,
+    requested: u64,
     max_readahead: u32,
     max_max_readahead: u32,
     max_background: u16,
     congestion_threshold: Option<u16>,
     max_write: u32,
     time_gran: Duration,
+    max_stack_depth: u32,
 }
 
 impl KernelConfig {
-    fn new(capabilities: u32, max_readahead: u32) -> Self {
+    fn new(capabilities: u64, max_readahead: u32) -> Self {
         Self {
             capabilities,
             requested: default_init_flags(capabilities),
@@ -120,6 +125,8 @@ impl KernelConfig {
             max_write: MAX_WRITE_SIZE as u32,
             // 1ns means nano-second granularity.
             time_gran: Duration::new(0, 1),
+            // 0 means passthrough is disabled.
+            max_stack_depth: 0,
         }
     }
 
@@ -181,7 +188,7 @@ impl KernelConfig {
     /// Add a set of capabilities.
     ///
     /// On success returns Ok, else return bits of capabilities not supported when capabilities you provided are not all supported by kernel.
-    pub fn add_capabilities(&mut self, capabilities_to_add: u32) -> Result<(), u32> {
+    pub fn add_capabilities(&mut self, capabilities_to_add: u64) -> Result<(), u64> {
         if capabilities_to_add & self.capabilities != capabilities_to_add {
             return Err(capabilities_to_add - (capabilities_to_add & self.capabilities));
         }
@@ -189,6 +196,21 @@ impl KernelConfig {
         Ok(())
     }
 
+    /// Set the maximum stacking depth of the file-systems that backing files of passthrough opens
+    /// come from. Passthrough is only enabled if this is non-zero and `FUSE_PASSTHROUGH` was added
+    /// as a capability.
+    ///
+    /// On success returns the previous value. On error returns the nearest value which will succeed
+    pub fn set_max_stack_depth(&mut self, value: u32) -> Result<u32, u32> {
+        // FILESYSTEM_MAX_STACK_DEPTH in the kernel
+        if value > 2 {
+            return Err(2);
+        }
+        let previous = self.max_stack_depth;
+        self.max_stack_depth = value;
+        Ok(previous)
+    }
+
     /// Set the maximum number of pending background requests. Such as readahead requests.
     ///
     /// On success returns the previous value. On error returns the nearest value which will succeed
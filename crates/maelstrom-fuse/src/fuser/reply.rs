@@ -6,6 +6,7 @@
 //! data without cloning the data. A reply *must always* be used (by calling either ok() or
 //! error() exactly once).
 
+use crate::fuser::channel::BackingId;
 use crate::fuser::ll::fuse_abi as abi;
 use crate::fuser::ll::{
     self,
@@ -40,6 +41,11 @@ pub trait ReplySender: Send + Sync + Unpin + 'static {
         offset: u64,
         length: usize,
     ) -> std::io::Result<()>;
+
+    /// Register the given file with the kernel as a backing file for passthrough opens.
+    fn open_backing(&self, _fd: Fd) -> std::io::Result<BackingId> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
 }
 
 impl fmt::Debug for Box<dyn ReplySender> {
@@ -263,6 +269,25 @@ impl ReplyOpen {
             .await
     }
 
+    /// Register the given file with the kernel so that it can be used with
+    /// [`Self::opened_passthrough`]. This only works if passthrough was enabled in
+    /// [`crate::fuser::KernelConfig`], and if we have `CAP_SYS_ADMIN`.
+    pub fn open_backing(&self, fd: Fd) -> std::io::Result<BackingId> {
+        self.reply.sender.as_ref().unwrap().open_backing(fd)
+    }
+
+    /// Reply to a request with the given open result, having the kernel serve reads and writes
+    /// directly from the given backing file
+    pub async fn opened_passthrough(self, fh: u64, flags: u32, backing_id: &BackingId) {
+        self.reply
+            .send_ll(&ll::Response::new_open_passthrough(
+                ll::FileHandle(fh),
+                flags,
+                backing_id.id(),
+            ))
+            .await
+    }
+
     /// Reply to a request with the given error code
     pub async fn error(self, err: c_int) {
         self.reply.error(err).await;
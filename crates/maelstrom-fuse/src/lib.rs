@@ -6,18 +6,22 @@ pub mod fuser;
 pub use fuser::{FileAttr, FileType};
 
 use anyhow::Result;
-use fuser::{MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry};
+use fuser::{
+    consts::FUSE_PASSTHROUGH, BackingId, KernelConfig, MountOption, ReplyAttr, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyXattr,
+};
 use futures::stream::{Stream, StreamExt};
 use maelstrom_linux::{self as linux, Errno};
 use maelstrom_util::r#async::await_and_every_sec;
 use std::{
+    collections::HashMap,
     ffi::OsStr,
     fs::File,
     future::Future,
     os::fd::AsRawFd as _,
     path::{Path, PathBuf},
     pin::pin,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use tokio::{
@@ -143,7 +147,7 @@ pub async fn fuse_mount_namespace(
     let other_log = log.clone();
     let handle = task::spawn(async move {
         let mut session = crate::fuser::Session::from_fd(
-            DispatchingFs::new(handler),
+            DispatchingFs::new(handler, other_log.clone()),
             fd.unwrap().into(),
             crate::fuser::SessionACL::All,
             other_log,
@@ -170,7 +174,7 @@ pub async fn run_fuse(
     fd: linux::OwnedFd,
 ) -> Result<()> {
     let mut session = crate::fuser::Session::from_fd(
-        DispatchingFs::new(handler),
+        DispatchingFs::new(handler, log.clone()),
         fd.into(),
         crate::fuser::SessionACL::All,
         log,
@@ -207,6 +211,12 @@ impl ErrorResponse for ReplyEmpty {
     }
 }
 
+impl ErrorResponse for ReplyXattr {
+    fn error(self, e: i32) -> impl Future<Output = ()> {
+        ReplyXattr::error(self, e)
+    }
+}
+
 trait Response {
     type Reply: ErrorResponse;
     fn send(self, reply: Self::Reply) -> impl Future<Output = ()>;
@@ -251,16 +261,116 @@ async fn handle_read_dir_resp(
     }
 }
 
+/// The backing file registered for an inode's passthrough opens, along with how many opens of the
+/// inode haven't been released yet. The backing file is `None` if the inode was first opened without
+/// passthrough.
+struct BackingFile {
+    opens: usize,
+    backing_id: Option<Arc<BackingId>>,
+}
+
+/// The backing files of open inodes, by inode.
+///
+/// The kernel won't mix passthrough and regular opens of an inode, nor allow two different backing
+/// files for it, so whatever the first open of an inode got is used for all later opens. When the
+/// last open is released, the entry is removed, which closes the backing file.
+#[derive(Default)]
+struct BackingFiles {
+    files: HashMap<u64, BackingFile>,
+    /// Set when the kernel refuses to open a backing file with `EPERM`. Opening backing files
+    /// requires `CAP_SYS_ADMIN` in the initial user namespace, so if one open is refused, they all
+    /// will be, and there's no point in trying again for the rest of the connection.
+    disabled: bool,
+}
+
+fn release_backing_file(backing_files: &Mutex<BackingFiles>, ino: u64) {
+    let files = &mut backing_files.lock().unwrap().files;
+    if let Some(backing_file) = files.get_mut(&ino) {
+        backing_file.opens -= 1;
+        if backing_file.opens == 0 {
+            files.remove(&ino);
+        }
+    }
+}
+
+fn open_backing_file(
+    reply: &ReplyOpen,
+    file: &File,
+    disabled: &mut bool,
+    log: &slog::Logger,
+) -> Option<BackingId> {
+    if *disabled {
+        return None;
+    }
+    match reply.open_backing(linux::Fd::from_raw(file.as_raw_fd())) {
+        Ok(backing_id) => Some(backing_id),
+        Err(err) if err.raw_os_error() == Some(libc::EPERM) => {
+            slog::warn!(
+                log,
+                "not permitted to open FUSE backing files, disabling passthrough";
+                "error" => %err,
+            );
+            *disabled = true;
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+async fn handle_open_resp(
+    res: ErrnoResult<OpenResponse>,
+    reply: ReplyOpen,
+    ino: u64,
+    backing_files: Option<Arc<Mutex<BackingFiles>>>,
+    log: slog::Logger,
+) {
+    let resp = match res {
+        Ok(resp) => resp,
+        Err(e) => {
+            reply.error(e.as_i32()).await;
+            return;
+        }
+    };
+    let Some(backing_files) = backing_files else {
+        reply.opened(resp.fh, resp.flags).await;
+        return;
+    };
+    let backing_id = {
+        let BackingFiles { files, disabled } = &mut *backing_files.lock().unwrap();
+        let backing_file = files.entry(ino).or_insert_with(|| BackingFile {
+            opens: 0,
+            backing_id: resp
+                .passthrough
+                .and_then(|file| open_backing_file(&reply, &file, disabled, &log).map(Arc::new)),
+        });
+        backing_file.opens += 1;
+        backing_file.backing_id.clone()
+    };
+    match backing_id {
+        Some(backing_id) => {
+            reply
+                .opened_passthrough(resp.fh, resp.flags, &backing_id)
+                .await
+        }
+        None => reply.opened(resp.fh, resp.flags).await,
+    }
+}
+
 struct DispatchingFs<FileSystemT> {
     handler: Arc<FileSystemT>,
     sem: Arc<Semaphore>,
+    /// Only set if the kernel agreed to passthrough.
+    backing_files: Option<Arc<Mutex<BackingFiles>>>,
+    log: slog::Logger,
 }
 
 impl<FileSystemT> DispatchingFs<FileSystemT> {
-    fn new(handler: FileSystemT) -> Self {
+    fn new(handler: FileSystemT, log: slog::Logger) -> Self {
         Self {
             handler: Arc::new(handler),
             sem: Arc::new(Semaphore::new(MAX_INFLIGHT / 2)),
+            backing_files: None,
+            log,
         }
     }
 }
@@ -268,6 +378,20 @@ impl<FileSystemT> DispatchingFs<FileSystemT> {
 impl<FileSystemT: FuseFileSystem + Send + Sync + 'static> fuser::Filesystem
     for DispatchingFs<FileSystemT>
 {
+    fn init(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        config: &mut KernelConfig,
+    ) -> std::result::Result<(), libc::c_int> {
+        // Backing files must not come from a stacked file-system like overlayfs. This keeps the
+        // FUSE file-system itself shallow enough for an overlayfs to be stacked on top of it.
+        if config.add_capabilities(FUSE_PASSTHROUGH).is_ok() {
+            config.set_max_stack_depth(1).unwrap();
+            self.backing_files = Some(Default::default());
+        }
+        Ok(())
+    }
+
     async fn destroy(&mut self) {
         self.sem
             .acquire_many(MAX_INFLIGHT as u32 / 2)
@@ -356,6 +480,75 @@ impl<FileSystemT: FuseFileSystem + Send + Sync + 'static> fuser::Filesystem
         });
     }
 
+    async fn open(&mut self, req: &fuser::Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let handler = self.handler.clone();
+        let permit = self.sem.clone().acquire_owned().await.unwrap();
+        let request = req.into();
+        let backing_files = self.backing_files.clone();
+        let log = self.log.clone();
+        tokio::task::spawn(async move {
+            handle_open_resp(
+                handler.open(request, ino, flags).await,
+                reply,
+                ino,
+                backing_files,
+                log,
+            )
+            .await;
+            drop(permit);
+        });
+    }
+
+    async fn release(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        if let Some(backing_files) = &self.backing_files {
+            release_backing_file(backing_files, ino);
+        }
+        reply.ok().await;
+    }
+
+    async fn getxattr(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let handler = self.handler.clone();
+        let permit = self.sem.clone().acquire_owned().await.unwrap();
+        let request = req.into();
+        let name = name.to_owned();
+        tokio::task::spawn(async move {
+            handle_resp(handler.get_xattr(request, ino, &name, size).await, reply).await;
+            drop(permit);
+        });
+    }
+
+    async fn listxattr(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino: u64,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let handler = self.handler.clone();
+        let permit = self.sem.clone().acquire_owned().await.unwrap();
+        let request = req.into();
+        tokio::task::spawn(async move {
+            handle_resp(handler.list_xattr(request, ino, size).await, reply).await;
+            drop(permit);
+        });
+    }
+
     async fn access(&mut self, req: &fuser::Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
         let handler = self.handler.clone();
         let permit = self.sem.clone().acquire_owned().await.unwrap();
@@ -448,6 +641,51 @@ impl Response for ReadResponse {
     }
 }
 
+/// Response from a [`FuseFileSystem::open`] request
+#[derive(Debug, Default)]
+pub struct OpenResponse {
+    pub fh: u64,
+    pub flags: u32,
+    /// If given, and the kernel supports FUSE passthrough, the kernel serves reads of the opened
+    /// file directly from this file instead of sending read requests. Registering the file
+    /// requires `CAP_SYS_ADMIN`, and regular reads are used when that fails.
+    pub passthrough: Option<Arc<File>>,
+}
+
+/// Response from a [`FuseFileSystem::get_xattr`] or [`FuseFileSystem::list_xattr`] request
+#[derive(Debug)]
+pub enum XattrResponse {
+    /// The size of the data, sent when the caller asked for it by giving a size of zero.
+    Size(u32),
+    Data(Vec<u8>),
+}
+
+impl XattrResponse {
+    /// Respond with the given data, or just its size, depending on the size the caller asked for.
+    /// Errors with `ERANGE` if the data doesn't fit.
+    pub fn new(data: Vec<u8>, size: u32) -> ErrnoResult<Self> {
+        let length = u32::try_from(data.len()).map_err(|_| Errno::E2BIG)?;
+        if size == 0 {
+            Ok(Self::Size(length))
+        } else if length > size {
+            Err(Errno::ERANGE)
+        } else {
+            Ok(Self::Data(data))
+        }
+    }
+}
+
+impl Response for XattrResponse {
+    type Reply = ReplyXattr;
+
+    async fn send(self, reply: ReplyXattr) {
+        match self {
+            Self::Size(size) => reply.size(size).await,
+            Self::Data(data) => reply.data(&data).await,
+        }
+    }
+}
+
 /// Response from a [`FuseFileSystem::read_link`] request
 pub type ReadLinkResponse = ReadResponse;
 
@@ -500,11 +738,14 @@ pub trait FuseFileSystem {
         async move { Err(Errno::ENOSYS) }
     }
 
-    /*
-    fn open(&mut self, _req: Request, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        reply.opened(0, 0);
+    fn open(
+        &self,
+        _req: Request,
+        _ino: u64,
+        _flags: i32,
+    ) -> impl Future<Output = ErrnoResult<OpenResponse>> + Send {
+        async move { Ok(OpenResponse::default()) }
     }
-    */
 
     #[allow(clippy::too_many_arguments)]
     fn read(
@@ -585,6 +826,28 @@ pub trait FuseFileSystem {
     }
     */
 
+    /// Get the value of the extended attribute with the given name. See [`XattrResponse::new`].
+    fn get_xattr(
+        &self,
+        _req: Request,
+        _ino: u64,
+        _name: &OsStr,
+        _size: u32,
+    ) -> impl Future<Output = ErrnoResult<XattrResponse>> + Send {
+        async move { Err(Errno::ENOSYS) }
+    }
+
+    /// Get the names of the extended attributes, each terminated by a NUL. See
+    /// [`XattrResponse::new`].
+    fn list_xattr(
+        &self,
+        _req: Request,
+        _ino: u64,
+        _size: u32,
+    ) -> impl Future<Output = ErrnoResult<XattrResponse>> + Send {
+        async move { Err(Errno::ENOSYS) }
+    }
+
     fn access(
         &self,
         _req: Request,
@@ -1,13 +1,14 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use anyhow_trace::anyhow_trace;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, FromInto};
 use std::borrow::BorrowMut;
 use std::cmp::{self, Ordering};
+use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::num::NonZeroU64;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct AvlPtr(NonZeroU64);
 
 impl AvlPtr {
@@ -403,6 +404,43 @@ where
         self.storage.flush().await
     }
 
+    /// Check that the tree stored is a valid AVL tree: no node can be reached twice, the keys are
+    /// in order, and every node's stored height is correct and within one of its sibling's.
+    /// Returns the number of nodes in the tree.
+    pub async fn verify(&mut self) -> Result<usize> {
+        let mut seen = HashSet::new();
+        let mut previous_key = None;
+        let mut stack = vec![];
+        let mut current = self.storage.root().await?;
+        loop {
+            while let Some(ptr) = current {
+                if !seen.insert(ptr) {
+                    bail!("AVL node {} reached more than once", ptr.as_u64());
+                }
+                let node = self.storage.look_up(ptr).await?;
+                let left_height = self.get_height(node.left).await?;
+                let right_height = self.get_height(node.right).await?;
+                if node.height != 1 + cmp::max(left_height, right_height) {
+                    bail!("AVL node {} has the wrong height", ptr.as_u64());
+                }
+                if left_height.abs_diff(right_height) > 1 {
+                    bail!("AVL node {} is unbalanced", ptr.as_u64());
+                }
+                current = node.left;
+                stack.push((ptr, node));
+            }
+            let Some((ptr, node)) = stack.pop() else {
+                break;
+            };
+            if previous_key.as_ref().is_some_and(|key| *key >= node.key) {
+                bail!("AVL node {} is out of order", ptr.as_u64());
+            }
+            current = node.right;
+            previous_key = Some(node.key);
+        }
+        Ok(seen.len())
+    }
+
     #[cfg(test)]
     async fn height_of(&mut self, node_ptr: Option<AvlPtr>) -> Result<usize> {
         let Some(node_ptr) = node_ptr else {
@@ -601,6 +639,7 @@ mod tests {
             tree.print().await.unwrap();
             tree.assert_invariants().await.unwrap();
         }
+        assert_eq!(tree.verify().await.unwrap(), expected.len());
         expected.sort();
         let expected: Vec<_> = expected.into_iter().map(|v| (v, v)).collect();
         let tree_iter = tree.entries().await.unwrap();
@@ -697,4 +736,30 @@ mod tests {
         assert_eq!(tree.get(&1).await.unwrap(), Some(7));
         assert_eq!(tree.get(&2).await.unwrap(), None);
     }
+
+    #[tokio::test]
+    async fn verify_finds_corruption() {
+        let mut tree = AvlTree::new(MemoryStorage::default());
+        for v in [5, 3, 8, 1] {
+            tree.insert_if_not_exists(v, v)
+                .await
+                .unwrap()
+                .assert_is_true();
+        }
+        assert_eq!(tree.verify().await.unwrap(), 4);
+
+        let mut bad_height = AvlTree::new(MemoryStorage {
+            root: tree.storage.root,
+            values: tree.storage.values.clone(),
+        });
+        bad_height.storage.values[0].height += 1;
+        assert!(bad_height.verify().await.is_err());
+
+        let mut out_of_order = AvlTree::new(MemoryStorage {
+            root: tree.storage.root,
+            values: tree.storage.values.clone(),
+        });
+        out_of_order.storage.values[1].key = 9;
+        assert!(out_of_order.verify().await.is_err());
+    }
 }
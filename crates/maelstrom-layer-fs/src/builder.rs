@@ -1,8 +1,8 @@
 use crate::dir::{DirectoryDataReader, DirectoryDataWriter, OrderedDirectoryStream};
-use crate::file::{FileDataInput, FileMetadataWriter};
+use crate::file::{FileDataInput, FileMetadataReader, FileMetadataWriter};
 use crate::ty::{
-    DirectoryEntryData, DirectoryEntryFileData, FileAttributes, FileId, FileType, LayerId,
-    LayerSuper,
+    DirectoryEntryData, DirectoryEntryFileData, FileAttributes, FileChunk, FileData, FileId,
+    FileType, LayerId, LayerSuper,
 };
 use crate::{BlobDir, LayerFs};
 use anyhow::bail;
@@ -11,15 +11,23 @@ use anyhow_trace::anyhow_trace;
 use futures::stream::{Peekable, StreamExt as _};
 use lru::LruCache;
 use maelstrom_base::{
-    manifest::{ManifestEntryData, ManifestFileData, Mode, UnixTimestamp},
+    manifest::{ManifestEntryData, ManifestFileData, Mode, UnixTimestamp, Xattrs},
     Sha256Digest, Utf8Component, Utf8Path,
 };
-use maelstrom_util::{async_fs::Fs, ext::BoolExt as _, manifest::AsyncManifestReader, root::Root};
+use maelstrom_util::{
+    async_fs::{File, Fs},
+    ext::BoolExt as _,
+    manifest::{tar::pax_records_xattrs, AsyncManifestReader},
+    root::Root,
+};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::SeekFrom;
 use std::path::Path;
 use std::pin::Pin;
-use tokio::io::{AsyncRead, AsyncSeek};
-use tokio_tar::{Archive, EntryType};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncSeek, AsyncSeekExt as _};
+use tokio_tar::{Archive, Entry, EntryType};
 
 pub const DIRECTORY_DATA_WRITER_CACHE_SIZE: usize = 20;
 
@@ -61,6 +69,98 @@ impl<'fs> DirectoryDataWriterCache<'fs> {
 
 pub const LAYER_BUILDING_FILE_MAX: usize = DIRECTORY_DATA_WRITER_CACHE_SIZE + 1;
 
+/// Split the data of a PAX extended header into its key-value records. Each record is prefixed by
+/// its own length in decimal, including the length itself.
+fn pax_records(mut data: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    let mut records = vec![];
+    while !data.is_empty() {
+        let malformed = || anyhow!("malformed PAX record in tar");
+        let space = data.iter().position(|&b| b == b' ').ok_or_else(malformed)?;
+        let length: usize = std::str::from_utf8(&data[..space])?.parse()?;
+        if length <= space + 1 || length > data.len() || data[length - 1] != b'\n' {
+            return Err(malformed());
+        }
+        let record = &data[space + 1..length - 1];
+        let equals = record
+            .iter()
+            .position(|&b| b == b'=')
+            .ok_or_else(malformed)?;
+        records.push((&record[..equals], &record[equals + 1..]));
+        data = &data[length..];
+    }
+    Ok(records)
+}
+
+/// The extension entries that describe the entry following them in a tar file.
+///
+/// We read these ourselves instead of letting [`Archive::entries`] do it, because it loses them if
+/// reading one doesn't complete right away.
+#[derive(Default)]
+struct TarExtensions {
+    long_name: Option<Vec<u8>>,
+    long_link_name: Option<Vec<u8>>,
+    pax: Vec<u8>,
+}
+
+impl TarExtensions {
+    /// If the given entry is an extension entry, read it in and return true.
+    async fn read<R: AsyncRead + Unpin>(&mut self, entry: &mut Entry<R>) -> Result<bool> {
+        let entry_type = entry.header().entry_type();
+        let mut data = vec![];
+        if entry_type.is_gnu_longname() {
+            entry.read_to_end(&mut data).await?;
+            self.long_name = Some(data);
+        } else if entry_type.is_gnu_longlink() {
+            entry.read_to_end(&mut data).await?;
+            self.long_link_name = Some(data);
+        } else if entry_type.is_pax_local_extensions() {
+            entry.read_to_end(&mut self.pax).await?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn pax_value(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        Ok(pax_records(&self.pax)?
+            .into_iter()
+            .rev()
+            .find_map(|(k, v)| (k == key).then_some(v)))
+    }
+
+    /// The path of the described entry.
+    fn path<'a>(&'a self, header: &'a tokio_tar::Header) -> Result<Cow<'a, [u8]>> {
+        if let Some(long_name) = &self.long_name {
+            return Ok(Cow::Borrowed(trim_nul(long_name)));
+        }
+        if let Some(path) = self.pax_value(b"path")? {
+            return Ok(Cow::Borrowed(path));
+        }
+        Ok(header.path_bytes())
+    }
+
+    /// The target of the described entry, if it is a link.
+    fn link_name<'a>(&'a self, header: &'a tokio_tar::Header) -> Result<Option<Cow<'a, [u8]>>> {
+        if let Some(long_link_name) = &self.long_link_name {
+            return Ok(Some(Cow::Borrowed(trim_nul(long_link_name))));
+        }
+        if let Some(link_name) = self.pax_value(b"linkpath")? {
+            return Ok(Some(Cow::Borrowed(link_name)));
+        }
+        Ok(header.link_name_bytes())
+    }
+
+    /// The extended attributes of the described entry, stored the way GNU tar and others store
+    /// them.
+    fn xattrs(&self) -> Result<Xattrs> {
+        pax_records_xattrs(pax_records(&self.pax)?.into_iter().map(Ok))
+    }
+}
+
+fn trim_nul(bytes: &[u8]) -> &[u8] {
+    bytes.strip_suffix(b"\0").unwrap_or(bytes)
+}
+
 /// Creates a LayerFS bottom layer using a manifest or a tar file as input.
 pub struct BottomLayerBuilder<'fs> {
     layer_fs: LayerFs,
@@ -103,6 +203,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                     size: 0,
                     mode: Mode(0o777),
                     mtime: time,
+                    xattrs: Xattrs::new(),
+                    owner: None,
                 },
                 FileDataInput::Empty,
             )
@@ -166,6 +268,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                             size: 0,
                             mode: Mode(0o777),
                             mtime: self.time,
+                            xattrs: Xattrs::new(),
+                            owner: None,
                         };
                         self.add_dir(dir_id, comp, attrs).await?
                     }
@@ -325,6 +429,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                 size: 0,
                 mode: Mode(0o777),
                 mtime: self.time,
+                xattrs: Xattrs::new(),
+                owner: None,
             };
             self.add_dir(parent_id, name, attrs).await?;
         }
@@ -345,6 +451,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
             size: 0,
             mode: Mode(0o777),
             mtime: self.time,
+            xattrs: Xattrs::new(),
+            owner: None,
         };
         let file_id = self
             .file_writer
@@ -364,7 +472,7 @@ impl<'fs> BottomLayerBuilder<'fs> {
             .file_name()
             .ok_or_else(|| anyhow!("missing file name"))?;
         let inserted = self
-            .add_link(parent_id, name, file_id, FileType::RegularFile)
+            .add_link(parent_id, name, file_id, FileType::Symlink)
             .await?;
         if !inserted {
             return Err(anyhow!("file already exists at {path}"));
@@ -420,14 +528,19 @@ impl<'fs> BottomLayerBuilder<'fs> {
         tar_stream: impl AsyncRead + Unpin,
     ) -> Result<()> {
         let mut ar = Archive::new(tar_stream);
-        let mut entries = ar.entries()?;
+        let mut entries = ar.entries_raw()?;
+        let mut pending_extensions = TarExtensions::default();
         while let Some(entry) = entries.next().await {
-            let entry = entry?;
+            let mut entry = entry?;
+            if pending_extensions.read(&mut entry).await? {
+                continue;
+            }
+            let extensions = std::mem::take(&mut pending_extensions);
+            let xattrs = extensions.xattrs()?;
             let header = entry.header();
-            let entry_path = entry.path()?;
-            let utf8_path: &Utf8Path = entry_path
-                .to_str()
-                .ok_or_else(|| anyhow!("non-UTF8 path in tar"))?
+            let entry_path = extensions.path(header)?;
+            let utf8_path: &Utf8Path = std::str::from_utf8(&entry_path)
+                .map_err(|_| anyhow!("non-UTF8 path in tar"))?
                 .as_ref();
             let path = Utf8Path::new("/").join(utf8_path);
             match header.entry_type() {
@@ -452,6 +565,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                             size: header.size()?,
                             mode: Mode(header.mode()?),
                             mtime: UnixTimestamp(header.mtime()?.try_into()?),
+                            xattrs,
+                            owner: None,
                         },
                         FileDataInput::Digest {
                             digest: digest.clone(),
@@ -468,6 +583,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                             size: header.size()?,
                             mode: Mode(header.mode()?),
                             mtime: UnixTimestamp(header.mtime()?.try_into()?),
+                            xattrs,
+                            owner: None,
                         },
                     )
                     .await?;
@@ -475,7 +592,7 @@ impl<'fs> BottomLayerBuilder<'fs> {
                 EntryType::Symlink => {
                     self.add_symlink_path(
                         &path,
-                        header.link_name_bytes().expect("empty symlink in tar"),
+                        extensions.link_name(header)?.expect("empty symlink in tar"),
                     )
                     .await?;
                 }
@@ -483,7 +600,7 @@ impl<'fs> BottomLayerBuilder<'fs> {
                     self.add_link_path(
                         &path,
                         std::str::from_utf8(
-                            &header.link_name_bytes().expect("empty symlink in tar"),
+                            &extensions.link_name(header)?.expect("empty symlink in tar"),
                         )?
                         .into(),
                     )
@@ -511,6 +628,8 @@ impl<'fs> BottomLayerBuilder<'fs> {
                 size: entry.metadata.size,
                 mode: entry.metadata.mode,
                 mtime: entry.metadata.mtime,
+                xattrs: entry.metadata.xattrs,
+                owner: entry.metadata.owner,
             };
             let path = Utf8Path::new("/").join(&entry.path);
             match entry.data {
@@ -529,6 +648,15 @@ impl<'fs> BottomLayerBuilder<'fs> {
                         },
                         ManifestFileData::Inline(data) => FileDataInput::Inline(data),
                         ManifestFileData::Empty => FileDataInput::Empty,
+                        ManifestFileData::Chunked(chunks) => FileDataInput::Chunked(
+                            chunks
+                                .iter()
+                                .map(|chunk| FileChunk {
+                                    digest: chunk.digest.clone(),
+                                    length: chunk.size,
+                                })
+                                .collect(),
+                        ),
                     };
                     self.add_file_path(&path, attrs, data).await?;
                 }
@@ -769,6 +897,10 @@ impl<'fs> UpperLayerBuilder<'fs> {
         self.hard_link_files(other).await?;
         let mut dir_writers = DirectoryDataWriterCache::new(&self.upper.data_fs);
         let upper_id = self.upper.layer_super().await?.layer_id;
+        // The walk only writes directories that have entries, but the root always has to exist.
+        dir_writers
+            .get_writer(&self.upper, FileId::root(upper_id))
+            .await?;
         let mut walker = DoubleFsWalk::new(self.lower, other).await?;
         while let Some(res) = walker.next().await? {
             match res {
@@ -803,3 +935,178 @@ impl<'fs> UpperLayerBuilder<'fs> {
         Ok(self.upper)
     }
 }
+
+/// Reads the metadata and inline data of files from any layer in a stack.
+struct StackReader<'fs> {
+    stack: &'fs LayerFs,
+    file_readers: HashMap<LayerId, FileMetadataReader>,
+    inline_data: HashMap<LayerId, File>,
+    buffer: Vec<u8>,
+}
+
+#[anyhow_trace]
+impl<'fs> StackReader<'fs> {
+    fn new(stack: &'fs LayerFs) -> Self {
+        Self {
+            stack,
+            file_readers: Default::default(),
+            inline_data: Default::default(),
+            buffer: vec![],
+        }
+    }
+
+    async fn get_file(&mut self, file_id: FileId) -> Result<(FileType, FileAttributes, FileData)> {
+        let layer_id = file_id.layer();
+        if !self.file_readers.contains_key(&layer_id) {
+            let reader = FileMetadataReader::new(self.stack, layer_id).await?;
+            self.file_readers.insert(layer_id, reader);
+        }
+        let reader = self.file_readers.get_mut(&layer_id).unwrap();
+        let (kind, attrs) = reader.get_attr(file_id).await?;
+        let (_, data) = reader.get_data(file_id).await?;
+        Ok((kind, attrs, data))
+    }
+
+    async fn inline_data(&mut self, layer_id: LayerId, offset: u64, length: u64) -> Result<&[u8]> {
+        if !self.inline_data.contains_key(&layer_id) {
+            let path = self.stack.inline_data_path(layer_id).await?;
+            let file = self.stack.data_fs.open_file(path).await?;
+            self.inline_data.insert(layer_id, file);
+        }
+        let file = self.inline_data.get_mut(&layer_id).unwrap();
+        file.seek(SeekFrom::Start(offset)).await?;
+        self.buffer.resize(usize::try_from(length)?, 0);
+        file.read_exact(&mut self.buffer).await?;
+        Ok(&self.buffer)
+    }
+}
+
+/// Builds a bottom LayerFS layer by flattening a stack of layers.
+///
+/// In a stack, a [`FileId`] can point into any of the layers. In a compacted layer, every
+/// [`FileId`] points into the layer itself, so it doesn't depend on any other layers.
+pub struct CompactedLayerBuilder<'fs> {
+    layer_fs: LayerFs,
+    file_writer: FileMetadataWriter,
+    dir_writer_cache: DirectoryDataWriterCache<'fs>,
+}
+
+#[anyhow_trace]
+impl<'fs> CompactedLayerBuilder<'fs> {
+    /// Build the new layer in `data_dir`.
+    /// `blob_dir` should contain a path to a directory that can be used to look-up file-data via
+    /// digest.
+    pub async fn new(
+        _log: slog::Logger,
+        data_fs: &'fs Fs,
+        data_dir: &Path,
+        blob_dir: &Root<BlobDir>,
+    ) -> Result<Self> {
+        let layer_fs = LayerFs::new(data_dir, blob_dir, LayerSuper::default()).await?;
+        let file_writer = FileMetadataWriter::new(
+            data_fs,
+            LayerId::BOTTOM,
+            &layer_fs.file_table_path(LayerId::BOTTOM).await?,
+            &layer_fs.attributes_table_path(LayerId::BOTTOM).await?,
+            &layer_fs.inline_data_path(LayerId::BOTTOM).await?,
+        )
+        .await?;
+        Ok(Self {
+            layer_fs,
+            file_writer,
+            dir_writer_cache: DirectoryDataWriterCache::new(data_fs),
+        })
+    }
+
+    async fn copy_file(&mut self, stack: &mut StackReader<'_>, file_id: FileId) -> Result<FileId> {
+        let (kind, attrs, data) = stack.get_file(file_id).await?;
+        let data = match data {
+            FileData::Empty => FileDataInput::Empty,
+            FileData::Inline { offset, length } => {
+                FileDataInput::Inline(stack.inline_data(file_id.layer(), offset, length).await?)
+            }
+            FileData::Digest {
+                digest,
+                offset,
+                length,
+            } => FileDataInput::Digest {
+                digest,
+                offset,
+                length,
+            },
+            FileData::Chunked(chunks) => FileDataInput::Chunked(chunks),
+        };
+        self.file_writer.insert_file(kind, attrs, data).await
+    }
+
+    /// Fill this layer with the contents of the given stack of layers.
+    ///
+    /// This function only supports being called once per builder.
+    ///
+    /// The stack is walked from its root, and every file that can be reached is copied into the
+    /// new layer. Files that are reachable from more than one place, like hard-links, are only
+    /// copied once. Whiteouts and opaque directories have already done their job in the stack, so
+    /// the new layer has neither.
+    pub async fn fill_from_stack(&mut self, stack: &LayerFs) -> Result<()> {
+        let stack_root = stack.root().await?;
+        let mut stack_reader = StackReader::new(stack);
+        let root = self.copy_file(&mut stack_reader, stack_root).await?;
+        assert_eq!(root, FileId::root(LayerId::BOTTOM));
+        self.dir_writer_cache
+            .get_writer(&self.layer_fs, root)
+            .await?;
+
+        let mut copied = HashMap::from([(stack_root, root)]);
+        let mut dirs = vec![(stack_root, root)];
+        while let Some((stack_dir_id, dir_id)) = dirs.pop() {
+            let mut entries = DirectoryDataReader::new(stack, stack_dir_id)
+                .await?
+                .into_ordered_stream()
+                .await?;
+            while let Some((name, data)) = entries.next().await.transpose()? {
+                let Some(data) = data.into_file_data() else {
+                    continue;
+                };
+                let file_id = match copied.get(&data.file_id) {
+                    Some(file_id) => *file_id,
+                    None => {
+                        let file_id = self.copy_file(&mut stack_reader, data.file_id).await?;
+                        copied.insert(data.file_id, file_id);
+                        if data.kind == FileType::Directory {
+                            self.dir_writer_cache
+                                .get_writer(&self.layer_fs, file_id)
+                                .await?;
+                            dirs.push((data.file_id, file_id));
+                        }
+                        file_id
+                    }
+                };
+                let dir_writer = self
+                    .dir_writer_cache
+                    .get_writer(&self.layer_fs, dir_id)
+                    .await?;
+                dir_writer
+                    .insert_entry(
+                        &name,
+                        DirectoryEntryFileData {
+                            file_id,
+                            kind: data.kind,
+                            opaque_dir: false,
+                        },
+                    )
+                    .await?
+                    .assert_is_true();
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish building. Flush all caches to disk. Returns a `LayerFs` instance for the built layer
+    /// for convenience.
+    pub async fn finish(mut self) -> Result<LayerFs> {
+        self.file_writer.flush().await?;
+        self.dir_writer_cache.flush().await?;
+
+        Ok(self.layer_fs)
+    }
+}
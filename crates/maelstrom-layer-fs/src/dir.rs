@@ -6,7 +6,8 @@ use crate::ty::{
 use crate::LayerFs;
 use anyhow::{anyhow, bail, Result};
 use anyhow_trace::anyhow_trace;
-use maelstrom_util::async_fs::{File, Fs};
+use futures::TryStreamExt as _;
+use maelstrom_util::async_fs::{File, Fs, GetPath as _};
 use maelstrom_util::ext::BoolExt as _;
 use maelstrom_util::io::BufferedStream;
 use serde::{Deserialize, Serialize};
@@ -78,6 +79,19 @@ impl DirectoryDataReader {
         }))
     }
 
+    /// Check the directory's header and that its entries make a valid AVL tree. Returns the
+    /// entries in order.
+    pub async fn verify(mut self) -> Result<Vec<(String, DirectoryEntryData)>> {
+        self.stream.seek(SeekFrom::Start(0)).await?;
+        let header: DirectoryEntryStorageHeader = decode_with_rich_error(&mut self.stream).await?;
+        header.version.check_current(self.stream.path())?;
+
+        AvlTree::new(DirectoryEntryStorage::new(&mut self.stream))
+            .verify()
+            .await?;
+        self.into_ordered_stream().await?.try_collect().await
+    }
+
     pub async fn into_ordered_stream(self) -> Result<OrderedDirectoryStream> {
         Ok(Box::pin(
             AvlTree::new(DirectoryEntryStorage::new(self.stream))
@@ -1,11 +1,11 @@
 use crate::ty::{
-    decode_with_rich_error, encode_with_rich_error, AttributesId, FileAttributes, FileData, FileId,
-    FileTableEntry, FileType, LayerFsVersion, LayerId,
+    decode_with_rich_error, encode_with_rich_error, AttributesId, AttributesTableEntry,
+    FileAttributes, FileChunk, FileData, FileId, FileTableEntry, FileType, LayerFsVersion, LayerId,
 };
 use crate::LayerFs;
 use anyhow::Result;
 use anyhow_trace::anyhow_trace;
-use maelstrom_base::Sha256Digest;
+use maelstrom_base::{manifest::Xattrs, Sha256Digest};
 use maelstrom_util::async_fs::{File, Fs};
 use maelstrom_util::io::BufferedStream;
 use serde::{Deserialize, Serialize};
@@ -74,9 +74,28 @@ impl FileMetadataReader {
                 self.attr_table_start + entry.attr_id.offset() - 1,
             ))
             .await?;
-        let attrs: FileAttributes = decode_with_rich_error(&mut self.attr_table).await?;
+        let attrs: AttributesTableEntry = decode_with_rich_error(&mut self.attr_table).await?;
 
-        Ok((entry.kind, attrs))
+        let mut xattrs = Xattrs::new();
+        if attrs.xattrs_offset != 0 {
+            self.attr_table
+                .seek(SeekFrom::Start(
+                    self.attr_table_start + attrs.xattrs_offset - 1,
+                ))
+                .await?;
+            xattrs = decode_with_rich_error(&mut self.attr_table).await?;
+        }
+
+        Ok((
+            entry.kind,
+            FileAttributes {
+                size: attrs.size,
+                mode: attrs.mode,
+                mtime: attrs.mtime,
+                xattrs,
+                owner: attrs.owner(),
+            },
+        ))
     }
 
     pub async fn get_data(&mut self, id: FileId) -> Result<(FileType, FileData)> {
@@ -119,6 +138,7 @@ pub enum FileDataInput<'a> {
         offset: u64,
         length: u64,
     },
+    Chunked(Vec<FileChunk>),
 }
 
 #[anyhow_trace]
@@ -165,12 +185,41 @@ impl FileMetadataWriter {
         })
     }
 
+    /// Turn the given attributes into the entry stored in the attribute-table. Any extended
+    /// attributes are written out at the end of the table first.
+    async fn attributes_table_entry(
+        &mut self,
+        attrs: FileAttributes,
+    ) -> Result<AttributesTableEntry> {
+        let mut xattrs_offset = 0;
+        if !attrs.xattrs.is_empty() {
+            xattrs_offset = self.attr_table.stream_position().await? - self.attr_table_start + 1;
+            encode_with_rich_error(&mut self.attr_table, &attrs.xattrs).await?;
+        }
+        let (owner_user, owner_group) = match attrs.owner {
+            Some(owner) => (owner.user.into(), owner.group.into()),
+            None => (
+                AttributesTableEntry::NO_OWNER,
+                AttributesTableEntry::NO_OWNER,
+            ),
+        };
+        Ok(AttributesTableEntry {
+            size: attrs.size,
+            mode: attrs.mode,
+            mtime: attrs.mtime,
+            xattrs_offset,
+            owner_user,
+            owner_group,
+        })
+    }
+
     pub async fn insert_file(
         &mut self,
         kind: FileType,
         attrs: FileAttributes,
         data: FileDataInput<'_>,
     ) -> Result<FileId> {
+        let attrs = self.attributes_table_entry(attrs).await?;
         let attr_id = AttributesId::try_from(
             self.attr_table.stream_position().await? - self.attr_table_start + 1,
         )
@@ -188,6 +237,7 @@ impl FileMetadataWriter {
                 offset,
                 length,
             },
+            FileDataInput::Chunked(chunks) => FileData::Chunked(chunks),
             FileDataInput::Inline(data) => {
                 let offset = self.inline_data.stream_position().await?;
                 self.inline_data.write_all(data).await?;
@@ -214,6 +264,9 @@ impl FileMetadataWriter {
     }
 
     pub async fn update_attributes(&mut self, id: FileId, attrs: FileAttributes) -> Result<()> {
+        // Any new extended attributes are appended to the table, and the old ones are left unused.
+        let attrs = self.attributes_table_entry(attrs).await?;
+
         let old_file_table_pos = self.file_table.stream_position().await?;
         let old_attr_table_pos = self.attr_table.stream_position().await?;
 
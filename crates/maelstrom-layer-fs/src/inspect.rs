@@ -0,0 +1,356 @@
+//! Looking at the contents of a layer, or a stack of layers, without mounting it.
+
+use crate::{
+    dir::DirectoryDataReader,
+    file::FileMetadataReader,
+    ty::{FileAttributes, FileData, FileId, FileType, LayerId},
+    LayerFs,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use anyhow_trace::anyhow_trace;
+use futures::stream::StreamExt as _;
+use maelstrom_base::{Utf8Component, Utf8Path, Utf8PathBuf};
+use maelstrom_util::manifest::tar::pax_xattr_records;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::SeekFrom,
+};
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _};
+
+/// A file in a layer, along with where it was found.
+#[derive(Clone, Debug)]
+pub struct LayerEntry {
+    pub path: Utf8PathBuf,
+    pub file_id: FileId,
+    pub kind: FileType,
+    pub attrs: FileAttributes,
+    pub data: FileData,
+}
+
+/// Looks up files in a layer by path and reads their contents. When given the top of a stack, it
+/// sees the same files that a mount of the stack would.
+pub struct LayerInspector<'fs> {
+    layer_fs: &'fs LayerFs,
+    file_readers: HashMap<LayerId, FileMetadataReader>,
+}
+
+#[anyhow_trace]
+impl<'fs> LayerInspector<'fs> {
+    pub fn new(layer_fs: &'fs LayerFs) -> Self {
+        Self {
+            layer_fs,
+            file_readers: Default::default(),
+        }
+    }
+
+    async fn entry(&mut self, path: Utf8PathBuf, file_id: FileId) -> Result<LayerEntry> {
+        let layer_id = file_id.layer();
+        if !self.file_readers.contains_key(&layer_id) {
+            let reader = FileMetadataReader::new(self.layer_fs, layer_id).await?;
+            self.file_readers.insert(layer_id, reader);
+        }
+        let reader = self.file_readers.get_mut(&layer_id).unwrap();
+        let (kind, attrs) = reader.get_attr(file_id).await?;
+        let (_, data) = reader.get_data(file_id).await?;
+        Ok(LayerEntry {
+            path,
+            file_id,
+            kind,
+            attrs,
+            data,
+        })
+    }
+
+    /// Return the entry for the root directory.
+    pub async fn root(&mut self) -> Result<LayerEntry> {
+        let root = self.layer_fs.root().await?;
+        self.entry("/".into(), root).await
+    }
+
+    /// Return the entry at the given absolute path, or `None` if there is nothing there.
+    pub async fn look_up(&mut self, path: &Utf8Path) -> Result<Option<LayerEntry>> {
+        let mut entry = self.root().await?;
+        for comp in path.components() {
+            let name = match comp {
+                Utf8Component::RootDir | Utf8Component::CurDir => continue,
+                Utf8Component::Normal(name) => name,
+                _ => bail!("unsupported path {path}"),
+            };
+            if entry.kind != FileType::Directory {
+                return Ok(None);
+            }
+            let mut reader = DirectoryDataReader::new(self.layer_fs, entry.file_id).await?;
+            let Some(file_id) = reader.look_up(name).await? else {
+                return Ok(None);
+            };
+            entry = self.entry(entry.path.join(name), file_id).await?;
+        }
+        Ok(Some(entry))
+    }
+
+    /// Return the entries of the given directory, ordered by name.
+    pub async fn list(&mut self, dir: &LayerEntry) -> Result<Vec<LayerEntry>> {
+        if dir.kind != FileType::Directory {
+            bail!("{} is not a directory", dir.path);
+        }
+        let mut stream = DirectoryDataReader::new(self.layer_fs, dir.file_id)
+            .await?
+            .into_ordered_stream()
+            .await?;
+        let mut children = vec![];
+        while let Some((name, data)) = stream.next().await.transpose()? {
+            if let Some(data) = data.into_file_data() {
+                children.push((name, data.file_id));
+            }
+        }
+        let mut entries = vec![];
+        for (name, file_id) in children {
+            entries.push(self.entry(dir.path.join(name), file_id).await?);
+        }
+        Ok(entries)
+    }
+
+    /// Return the given entry and everything under it, with each directory coming right before its
+    /// contents.
+    pub async fn walk(&mut self, start: LayerEntry) -> Result<Vec<LayerEntry>> {
+        let mut entries = vec![];
+        let mut to_visit = vec![start];
+        while let Some(entry) = to_visit.pop() {
+            if entry.kind == FileType::Directory {
+                to_visit.extend(self.list(&entry).await?.into_iter().rev());
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Return the contents of the given regular file, or the target of the given symlink. Data
+    /// stored outside of the layer is read from the blob directory.
+    pub async fn read(&mut self, entry: &LayerEntry) -> Result<Vec<u8>> {
+        let mut contents = vec![];
+        self.copy(entry, &mut contents).await?;
+        Ok(contents)
+    }
+
+    /// Like [`Self::read`], but write the contents to `out` as they're read instead of returning
+    /// them. Returns the number of bytes written.
+    pub async fn copy(
+        &mut self,
+        entry: &LayerEntry,
+        out: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<u64> {
+        if !matches!(entry.kind, FileType::RegularFile | FileType::Symlink) {
+            bail!("{} is not a regular file or a symlink", entry.path);
+        }
+        let pieces = match &entry.data {
+            FileData::Empty => return Ok(0),
+            FileData::Inline { offset, length } => vec![(
+                self.layer_fs
+                    .inline_data_path(entry.file_id.layer())
+                    .await?,
+                *offset,
+                *length,
+            )],
+            FileData::Digest {
+                digest,
+                offset,
+                length,
+            } => vec![(
+                self.layer_fs.cache_entry(digest).into_path_buf(),
+                *offset,
+                *length,
+            )],
+            FileData::Chunked(chunks) => chunks
+                .iter()
+                .map(|chunk| {
+                    (
+                        self.layer_fs.cache_entry(&chunk.digest).into_path_buf(),
+                        0,
+                        chunk.length,
+                    )
+                })
+                .collect(),
+        };
+        let mut copied = 0;
+        for (path, offset, length) in pieces {
+            let mut file = self
+                .layer_fs
+                .data_fs
+                .open_file(&path)
+                .await
+                .with_context(|| format!("reading data for {}", entry.path))?;
+            file.seek(SeekFrom::Start(offset)).await?;
+            let piece = tokio::io::copy(&mut file.take(length), out).await?;
+            if piece != length {
+                bail!("data for {} is truncated", entry.path);
+            }
+            copied += piece;
+        }
+        Ok(copied)
+    }
+
+    /// Whether reading the contents of the given entry needs the blob directory.
+    pub fn needs_blob_dir(entry: &LayerEntry) -> bool {
+        matches!(entry.kind, FileType::RegularFile | FileType::Symlink)
+            && matches!(entry.data, FileData::Digest { .. } | FileData::Chunked(_))
+    }
+
+    async fn same_contents(
+        &mut self,
+        entry: &LayerEntry,
+        other_inspector: &mut LayerInspector<'_>,
+        other: &LayerEntry,
+    ) -> Result<bool> {
+        match (&entry.data, &other.data) {
+            (
+                FileData::Digest {
+                    digest,
+                    offset,
+                    length,
+                },
+                FileData::Digest {
+                    digest: other_digest,
+                    offset: other_offset,
+                    length: other_length,
+                },
+            ) => Ok(digest == other_digest && offset == other_offset && length == other_length),
+            (FileData::Chunked(chunks), FileData::Chunked(other_chunks)) => {
+                Ok(chunks == other_chunks)
+            }
+            (FileData::Digest { .. } | FileData::Chunked(_), _)
+            | (_, FileData::Digest { .. } | FileData::Chunked(_)) => Ok(false),
+            _ => Ok(self.read(entry).await? == other_inspector.read(other).await?),
+        }
+    }
+}
+
+/// A path whose file differs between two layers.
+#[derive(Clone, Debug)]
+pub enum Difference {
+    Added(LayerEntry),
+    Removed(LayerEntry),
+    Changed(LayerEntry, LayerEntry),
+}
+
+impl Difference {
+    pub fn path(&self) -> &Utf8Path {
+        match self {
+            Self::Added(entry) | Self::Removed(entry) | Self::Changed(entry, _) => &entry.path,
+        }
+    }
+}
+
+/// Compare the contents of two layers, returning every path that differs, ordered by path.
+///
+/// A file is changed if its type, attributes, or contents differ. Contents stored outside of the
+/// layers are compared by digest, without reading them.
+pub async fn diff(left: &LayerFs, right: &LayerFs) -> Result<Vec<Difference>> {
+    let mut left_inspector = LayerInspector::new(left);
+    let mut right_inspector = LayerInspector::new(right);
+    let left_root = left_inspector.root().await?;
+    let right_root = right_inspector.root().await?;
+    let mut left_entries: BTreeMap<_, _> = left_inspector
+        .walk(left_root)
+        .await?
+        .into_iter()
+        .map(|e| (e.path.clone(), e))
+        .collect();
+
+    let mut differences = vec![];
+    for right_entry in right_inspector.walk(right_root).await? {
+        let Some(left_entry) = left_entries.remove(&right_entry.path) else {
+            differences.push(Difference::Added(right_entry));
+            continue;
+        };
+        let same = left_entry.kind == right_entry.kind
+            && left_entry.attrs.mode == right_entry.attrs.mode
+            && left_entry.attrs.mtime == right_entry.attrs.mtime
+            && left_entry.attrs.size == right_entry.attrs.size
+            && left_entry.attrs.xattrs == right_entry.attrs.xattrs
+            && left_entry.attrs.owner == right_entry.attrs.owner
+            && (left_entry.kind == FileType::Directory
+                || left_inspector
+                    .same_contents(&left_entry, &mut right_inspector, &right_entry)
+                    .await?);
+        if !same {
+            differences.push(Difference::Changed(left_entry, right_entry));
+        }
+    }
+    differences.extend(left_entries.into_values().map(Difference::Removed));
+    differences.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(differences)
+}
+
+/// Write the contents of the layer to `out` as a tar file. Files with more than one path are
+/// written once, with the other paths as hard-links to the first.
+pub async fn export_tar(
+    layer_fs: &LayerFs,
+    out: impl AsyncWrite + Unpin + Send + 'static,
+) -> Result<()> {
+    let mut inspector = LayerInspector::new(layer_fs);
+    let root = inspector.root().await?;
+    let mut builder = tokio_tar::Builder::new(out);
+    let mut written = HashMap::<FileId, Utf8PathBuf>::new();
+    for entry in inspector.walk(root).await? {
+        let Ok(path) = entry.path.strip_prefix("/") else {
+            continue;
+        };
+        if path.as_str().is_empty() {
+            continue;
+        }
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_mode(u32::from(entry.attrs.mode));
+        header.set_mtime(entry.attrs.mtime.0.try_into().unwrap_or_default());
+        header.set_size(0);
+        if let Some(owner) = entry.attrs.owner {
+            header.set_uid(u32::from(owner.user).into());
+            header.set_gid(u32::from(owner.group).into());
+        }
+        if let Some(first_path) = written.get(&entry.file_id) {
+            header.set_entry_type(tokio_tar::EntryType::Link);
+            header.set_link_name(first_path)?;
+            builder
+                .append_data(&mut header, path, tokio::io::empty())
+                .await?;
+            continue;
+        }
+        if !entry.attrs.xattrs.is_empty() {
+            let records = pax_xattr_records(&entry.attrs.xattrs);
+            let mut pax_header = tokio_tar::Header::new_ustar();
+            pax_header.set_entry_type(tokio_tar::EntryType::XHeader);
+            pax_header.set_size(records.len() as u64);
+            builder
+                .append_data(&mut pax_header, "././@PaxHeader", &records[..])
+                .await?;
+        }
+        match entry.kind {
+            FileType::Directory => {
+                header.set_entry_type(tokio_tar::EntryType::Directory);
+                builder
+                    .append_data(&mut header, path, tokio::io::empty())
+                    .await?;
+            }
+            FileType::RegularFile => {
+                let contents = inspector.read(&entry).await?;
+                header.set_entry_type(tokio_tar::EntryType::Regular);
+                header.set_size(contents.len() as u64);
+                builder
+                    .append_data(&mut header, path, &contents[..])
+                    .await?;
+            }
+            FileType::Symlink => {
+                let target = String::from_utf8(inspector.read(&entry).await?)
+                    .map_err(|_| anyhow!("non-UTF-8 symlink target at {}", entry.path))?;
+                header.set_entry_type(tokio_tar::EntryType::Symlink);
+                header.set_link_name(target)?;
+                builder
+                    .append_data(&mut header, path, tokio::io::empty())
+                    .await?;
+            }
+            other => bail!("unsupported file type {other:?} at {}", entry.path),
+        }
+        written.insert(entry.file_id, path.to_owned());
+    }
+    builder.into_inner().await?.flush().await?;
+    Ok(())
+}
@@ -1,34 +1,172 @@
-use anyhow::{anyhow, Result};
-use maelstrom_layer_fs::{DirectoryDataReader, FileId, FileMetadataReader, FileType, LayerFs};
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand};
+use maelstrom_base::Utf8PathBuf;
+use maelstrom_layer_fs::{
+    diff, export_tar, verify_layer, BlobDir, Difference, DirectoryDataReader, FileId,
+    FileMetadataReader, FileType, LayerEntry, LayerFs, LayerInspector,
+};
 use maelstrom_util::root::Root;
-use std::env;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt as _;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let mut args = env::args();
-    args.next();
-    let path = args
-        .next()
-        .ok_or_else(|| anyhow!("expected path to fs layer"))?;
-    let offset: u32 = args
-        .next()
-        .ok_or_else(|| anyhow!("expected file offset"))?
-        .parse()?;
-
-    let layer_fs = LayerFs::from_path(path.as_ref(), Root::new("/dev/null".as_ref()))?;
+#[derive(Subcommand)]
+enum CliCommands {
+    /// List the entries of a directory.
+    Ls {
+        layer: PathBuf,
+        #[arg(default_value = "/")]
+        path: Utf8PathBuf,
+    },
+    /// Write the contents of a file to standard output.
+    Cat { layer: PathBuf, path: Utf8PathBuf },
+    /// Print every path under a directory along with the layer it comes from.
+    Tree {
+        layer: PathBuf,
+        #[arg(default_value = "/")]
+        path: Utf8PathBuf,
+    },
+    /// Print the paths which differ between two layers.
+    Diff { left: PathBuf, right: PathBuf },
+    /// Write the contents of a layer to a tar file.
+    Export { layer: PathBuf, output: PathBuf },
+    /// Check that a layer can be read by this version of LayerFS and isn't corrupt.
+    Verify { layer: PathBuf },
+    /// Print the raw attributes and directory entries of the file at the given offset.
+    Dump { layer: PathBuf, offset: u32 },
+}
+
+#[derive(Parser)]
+#[command(styles=maelstrom_util::clap::styles())]
+struct CliOptions {
+    /// The directory containing file-data referred to by digest. Without it, only data stored in
+    /// the layers themselves can be read.
+    #[arg(long, global = true)]
+    blob_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: CliCommands,
+}
+
+fn describe(entry: &LayerEntry) -> String {
+    format!(
+        "{:<12} {:06o} {:>10} {:>12} {}:{}",
+        format!("{:?}", entry.kind),
+        u32::from(entry.attrs.mode),
+        entry.attrs.size,
+        entry.attrs.mtime.0,
+        entry.file_id.layer().as_u32(),
+        entry.file_id.offset(),
+    )
+}
+
+async fn look_up(inspector: &mut LayerInspector<'_>, path: &Utf8PathBuf) -> Result<LayerEntry> {
+    inspector
+        .look_up(path)
+        .await?
+        .ok_or_else(|| anyhow!("{path} not found"))
+}
+
+/// Fail if reading the contents of `entry` needs the blob directory, but none was given.
+fn check_blob_dir(entry: &LayerEntry, blob_dir: &Option<PathBuf>) -> Result<()> {
+    if blob_dir.is_none() && LayerInspector::needs_blob_dir(entry) {
+        bail!(
+            "the contents of {} are stored in the blob directory, which must be given with \
+            --blob-dir",
+            entry.path
+        );
+    }
+    Ok(())
+}
+
+async fn dump(layer_fs: &LayerFs, offset: u32) -> Result<()> {
     let layer_id = layer_fs.layer_id().await?;
 
-    let mut reader = FileMetadataReader::new(&layer_fs, layer_id).await?;
+    let mut reader = FileMetadataReader::new(layer_fs, layer_id).await?;
     let file_id = FileId::new(layer_id, offset.try_into()?);
     let (type_, attrs) = reader.get_attr(file_id).await?;
     println!("{type_:#?}\n{attrs:#?}");
 
     if type_ == FileType::Directory {
-        let mut reader = DirectoryDataReader::new(&layer_fs, file_id).await?;
+        let mut reader = DirectoryDataReader::new(layer_fs, file_id).await?;
         while let Some(entry) = reader.next_entry().await? {
             println!("{entry:#?}");
         }
     }
+    Ok(())
+}
 
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = CliOptions::parse();
+    // Nothing is read from the blob directory unless one was given, so any path will do otherwise.
+    let blob_path = opt.blob_dir.clone().unwrap_or_default();
+    let blob_dir = Root::<BlobDir>::new(&blob_path);
+    match opt.command {
+        CliCommands::Ls { layer, path } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            let mut inspector = LayerInspector::new(&layer_fs);
+            let entry = look_up(&mut inspector, &path).await?;
+            let entries = if entry.kind == FileType::Directory {
+                inspector.list(&entry).await?
+            } else {
+                vec![entry]
+            };
+            for entry in entries {
+                println!(
+                    "{} {}",
+                    describe(&entry),
+                    entry.path.file_name().unwrap_or("/")
+                );
+            }
+        }
+        CliCommands::Cat { layer, path } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            let mut inspector = LayerInspector::new(&layer_fs);
+            let entry = look_up(&mut inspector, &path).await?;
+            check_blob_dir(&entry, &opt.blob_dir)?;
+            let mut stdout = tokio::io::stdout();
+            inspector.copy(&entry, &mut stdout).await?;
+            stdout.flush().await?;
+        }
+        CliCommands::Tree { layer, path } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            let mut inspector = LayerInspector::new(&layer_fs);
+            let entry = look_up(&mut inspector, &path).await?;
+            for entry in inspector.walk(entry).await? {
+                println!("{} {}", describe(&entry), entry.path);
+            }
+        }
+        CliCommands::Diff { left, right } => {
+            let left = LayerFs::from_path(&left, blob_dir)?;
+            let right = LayerFs::from_path(&right, blob_dir)?;
+            for difference in diff(&left, &right).await? {
+                let marker = match &difference {
+                    Difference::Added(_) => '+',
+                    Difference::Removed(_) => '-',
+                    Difference::Changed(..) => '~',
+                };
+                println!("{marker} {}", difference.path());
+            }
+        }
+        CliCommands::Export { layer, output } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            let mut inspector = LayerInspector::new(&layer_fs);
+            let root = inspector.root().await?;
+            for entry in inspector.walk(root).await? {
+                check_blob_dir(&entry, &opt.blob_dir)?;
+            }
+            let out = tokio::fs::File::create(&output).await?;
+            export_tar(&layer_fs, out).await?;
+        }
+        CliCommands::Verify { layer } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            verify_layer(&layer_fs).await?;
+            println!("{} is ok", layer.display());
+        }
+        CliCommands::Dump { layer, offset } => {
+            let layer_fs = LayerFs::from_path(&layer, blob_dir)?;
+            dump(&layer_fs, offset).await?;
+        }
+    }
     Ok(())
 }
@@ -10,7 +10,7 @@
 //! A layer is stored on disk as a directory of files. Each directory contains the following files
 //! - `super.bin` contains information about the layer including any layers it is stacked on top of
 //! - `file_table.bin` contains a listing of all the files in the layer
-//! - `attributes_table.bin` contains the attributes for all the files in the layer
+//! - `attributes_table.bin` contains the attributes, including extended ones, for all the files
 //! - `<offset>.dir_data.bin` contains directory contents for the directory found at `<offset>` in
 //!    the file table.
 //!
@@ -19,6 +19,11 @@
 //! used for. However, if a file contains a small amount of data it can actually be stored in the
 //! attributes as "inline data" to avoid the overhead of reading from another file.
 //!
+//! Each of these files starts with a header giving the version of the on-disk format. `super.bin`
+//! also starts with a magic number, so layers written by an incompatible version can be detected
+//! before being served. [`verify_layer`] checks this, along with the consistency of the rest of
+//! the layer's files.
+//!
 //! # The Stacking
 //! When a layer is stacked on top of other layers, the directory entries for that layer may point
 //! to directories or files in the lower layers. This is supported because [`FileId`] contains a
@@ -92,30 +97,48 @@
 //! means that layers can be reused in multiple stacks. This is also why the layer numbering is
 //! done from the bottom-up.
 //!
+//! A deep stack can be flattened with [`CompactedLayerBuilder`]. It creates a new bottom layer
+//! with the same contents as the stack, given to [`CompactedLayerBuilder::fill_from_stack`]. The
+//! new layer doesn't refer to any of the layers in the stack.
+//!
+//! # Inspecting Layers
+//! A layer or stack can be looked at without serving it with [`LayerInspector`]. Two layers can be
+//! compared with [`diff`], and the contents of one can be written out as a tar file with
+//! [`export_tar`]. The `layer-fs-cli` program provides these as commands.
+//!
 //! # Serving the File-System
 //! Serving the file-system is done via FUSE. First the path to the top of the layer stack you want
 //! to serve should be passed to [`LayerFs::from_path`]. Then either [`LayerFs::mount`] or
 //! [`LayerFs::run_fuse`] should be called.
+//!
+//! File-data doesn't have to be in the blob directory when the file-system is served. If a
+//! [`BlobFetcher`] is given with [`LayerFs::with_blob_fetcher`], a read of a file whose data is
+//! missing waits for the fetcher to put it there.
 
 mod avl;
 mod builder;
 mod dir;
 mod file;
+mod inspect;
 mod ty;
+mod verify;
 
 pub use builder::*;
 pub use dir::DirectoryDataReader;
 pub use file::FileMetadataReader;
-pub use ty::{FileAttributes, FileData, FileId, FileType, LayerId, LayerSuper};
+pub use inspect::{diff, export_tar, Difference, LayerEntry, LayerInspector};
+pub use ty::{FileAttributes, FileChunk, FileData, FileId, FileType, LayerId, LayerSuper};
+pub use verify::verify_layer;
 
 use anyhow::{anyhow, Result};
 use anyhow_trace::anyhow_trace;
+use async_trait::async_trait;
 use futures::stream::StreamExt as _;
 use lru::LruCache;
-use maelstrom_base::Sha256Digest;
+use maelstrom_base::{manifest::Xattrs, Sha256Digest};
 use maelstrom_fuse::{
-    AttrResponse, EntryResponse, ErrnoResult, FileAttr, FuseFileSystem, ReadLinkResponse,
-    ReadResponse, Request,
+    AttrResponse, EntryResponse, ErrnoResult, FileAttr, FuseFileSystem, OpenResponse,
+    ReadLinkResponse, ReadResponse, Request, XattrResponse,
 };
 use maelstrom_linux::Errno;
 use maelstrom_util::{
@@ -124,6 +147,7 @@ use maelstrom_util::{
 };
 use std::{
     ffi::OsStr,
+    os::unix::fs::FileExt as _,
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
@@ -187,6 +211,13 @@ impl LazyLayerSuper {
 
 pub struct BlobDir;
 
+/// Something that can get file-data into the blob directory on demand.
+#[async_trait]
+pub trait BlobFetcher: Send + Sync {
+    /// Return once the given blob is in the blob directory, or once it's known that it won't be.
+    async fn fetch_blob(&self, digest: &Sha256Digest) -> Result<()>;
+}
+
 /// The in-memory representation of a mountable `LayerFs` layer. This can be either a bottom layer
 /// or upper layer.
 pub struct LayerFs {
@@ -194,6 +225,7 @@ pub struct LayerFs {
     top_layer_path: PathBuf,
     layer_super: LazyLayerSuper,
     blob_dir: RootBuf<BlobDir>,
+    blob_fetcher: Option<Arc<dyn BlobFetcher>>,
 }
 
 #[anyhow_trace]
@@ -209,9 +241,16 @@ impl LayerFs {
             layer_super: LazyLayerSuper::not_cached(data_dir.join("super.bin")),
             top_layer_path: data_dir,
             blob_dir: blob_dir.to_owned(),
+            blob_fetcher: None,
         })
     }
 
+    /// Use the given fetcher to get file-data that isn't in the blob directory yet.
+    pub fn with_blob_fetcher(mut self, blob_fetcher: Arc<dyn BlobFetcher>) -> Self {
+        self.blob_fetcher = Some(blob_fetcher);
+        self
+    }
+
     async fn new(
         data_dir: &Path,
         blob_dir: &Root<BlobDir>,
@@ -229,6 +268,7 @@ impl LayerFs {
             top_layer_path: data_dir,
             layer_super: LazyLayerSuper::cached(layer_super),
             blob_dir: blob_dir.to_owned(),
+            blob_fetcher: None,
         })
     }
 
@@ -446,6 +486,41 @@ impl LayerFsFuseAdapter {
             length: read_length as usize,
         })
     }
+
+    /// Open the data file with the given digest, fetching it first if it hasn't been yet.
+    async fn data_file(&self, digest: &Sha256Digest) -> ErrnoResult<Arc<std::fs::File>> {
+        let mut file = self
+            .cache
+            .lock()
+            .await
+            .data_file(&self.layer_fs, digest)
+            .await;
+        if let (Err(_), Some(blob_fetcher)) = (&file, &self.layer_fs.blob_fetcher) {
+            // The data may not have been fetched yet.
+            to_eio(self.log.clone(), blob_fetcher.fetch_blob(digest).await)?;
+            file = self
+                .cache
+                .lock()
+                .await
+                .data_file(&self.layer_fs, digest)
+                .await;
+        }
+        to_eio(self.log.clone(), file)
+    }
+
+    async fn xattrs(&self, ino: u64) -> ErrnoResult<Xattrs> {
+        let file = to_einval(self.log.clone(), FileId::try_from(ino))?;
+        let reader = to_eio(
+            self.log.clone(),
+            self.cache
+                .lock()
+                .await
+                .files(&self.layer_fs, file.layer())
+                .await,
+        )?;
+        let (_, attrs) = to_eio(self.log.clone(), reader.lock().await.get_attr(file).await)?;
+        Ok(attrs.xattrs)
+    }
 }
 
 impl FuseFileSystem for LayerFsFuseAdapter {
@@ -481,6 +556,10 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 .await,
         )?;
         let (kind, attrs) = to_eio(self.log.clone(), reader.lock().await.get_attr(file).await)?;
+        // The kernel interprets the ids we return in the user namespace of the process that mounted
+        // us. The worker mounts us from within the job's user namespace, so an owner's ids, which
+        // are relative to the job's user namespace, are returned as they are. The requester's ids
+        // are in that namespace too.
         Ok(AttrResponse {
             ttl: TTL,
             attr: FileAttr {
@@ -493,14 +572,52 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 kind,
                 perm: u32::from(attrs.mode) as u16,
                 nlink: 1,
-                uid: req.uid,
-                gid: req.gid,
+                uid: attrs.owner.map_or(req.uid, |owner| owner.user.into()),
+                gid: attrs.owner.map_or(req.gid, |owner| owner.group.into()),
                 rdev: 0,
                 blksize: 512,
             },
         })
     }
 
+    async fn open(&self, _req: Request, ino: u64, _flags: i32) -> ErrnoResult<OpenResponse> {
+        let file = to_einval(self.log.clone(), FileId::try_from(ino))?;
+        let reader = to_eio(
+            self.log.clone(),
+            self.cache
+                .lock()
+                .await
+                .files(&self.layer_fs, file.layer())
+                .await,
+        )?;
+        let (kind, data) = to_eio(self.log.clone(), reader.lock().await.get_data(file).await)?;
+        let mut response = OpenResponse::default();
+        // Reads can be passed through to the data file when it holds exactly the file's contents.
+        // Data that hasn't been fetched yet is read the regular way.
+        if let (
+            FileType::RegularFile,
+            FileData::Digest {
+                digest,
+                offset: 0,
+                length,
+            },
+        ) = (kind, data)
+        {
+            let data_file = self
+                .cache
+                .lock()
+                .await
+                .data_file(&self.layer_fs, &digest)
+                .await;
+            if let Ok(data_file) = data_file {
+                if data_file.metadata().is_ok_and(|m| m.len() == length) {
+                    response.passthrough = Some(data_file);
+                }
+            }
+        }
+        Ok(response)
+    }
+
     async fn read(
         &self,
         _req: Request,
@@ -545,16 +662,34 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 offset: file_offset,
                 length: file_length,
             } => {
-                let file = to_eio(
-                    self.log.clone(),
-                    self.cache
-                        .lock()
-                        .await
-                        .data_file(&self.layer_fs, &digest)
-                        .await,
-                )?;
+                let file = self.data_file(&digest).await?;
                 self.splice_file(file, file_offset, file_length, offset, size as u64)
             }
+            FileData::Chunked(chunks) => {
+                let read_start = to_einval::<u64, _>(self.log.clone(), offset.try_into())?;
+                let read_end = read_start + size as u64;
+                let mut data = vec![];
+                let mut chunk_start = 0;
+                for chunk in chunks {
+                    let chunk_end = chunk_start + chunk.length;
+                    if chunk_start >= read_end {
+                        break;
+                    }
+                    if chunk_end > read_start {
+                        let file = self.data_file(&chunk.digest).await?;
+                        let start = read_start.max(chunk_start) - chunk_start;
+                        let mut piece =
+                            vec![0; (read_end.min(chunk_end) - chunk_start - start) as usize];
+                        let piece = tokio::task::spawn_blocking(move || {
+                            file.read_exact_at(&mut piece, start).map(|()| piece)
+                        })
+                        .await;
+                        data.extend(to_eio(self.log.clone(), to_eio(self.log.clone(), piece)?)?);
+                    }
+                    chunk_start = chunk_end;
+                }
+                Ok(ReadResponse::Buffer { data })
+            }
         }
     }
 
@@ -602,6 +737,30 @@ impl FuseFileSystem for LayerFsFuseAdapter {
         })))
     }
 
+    async fn get_xattr(
+        &self,
+        _req: Request,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+    ) -> ErrnoResult<XattrResponse> {
+        let mut xattrs = self.xattrs(ino).await?;
+        let value = name
+            .to_str()
+            .and_then(|name| xattrs.remove(name))
+            .ok_or(Errno::ENODATA)?;
+        XattrResponse::new(value, size)
+    }
+
+    async fn list_xattr(&self, _req: Request, ino: u64, size: u32) -> ErrnoResult<XattrResponse> {
+        let mut names = vec![];
+        for name in self.xattrs(ino).await?.into_keys() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        XattrResponse::new(names, size)
+    }
+
     async fn read_link(&self, _req: Request, ino: u64) -> ErrnoResult<ReadLinkResponse> {
         let file = to_einval(self.log.clone(), FileId::try_from(ino))?;
         let reader = to_eio(
@@ -629,7 +788,7 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 )?;
                 self.splice_file(file, offset, length, 0, length)
             }
-            FileData::Digest { .. } => Err(Errno::EIO),
+            FileData::Digest { .. } | FileData::Chunked(_) => Err(Errno::EIO),
         }
     }
 
@@ -645,7 +804,8 @@ mod tests {
     use maelstrom_base::manifest::UnixTimestamp;
     use maelstrom_base::{
         manifest::{
-            ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData, Mode,
+            ManifestChunk, ManifestEntry, ManifestEntryData, ManifestEntryMetadata,
+            ManifestFileData, Mode,
         },
         Utf8PathBuf,
     };
@@ -654,6 +814,7 @@ mod tests {
     use std::future::Future;
     use std::os::unix::fs::MetadataExt as _;
     use std::pin::Pin;
+    use tokio::io::AsyncReadExt as _;
 
     const ARBITRARY_TIME: UnixTimestamp = UnixTimestamp(1705000271);
 
@@ -701,6 +862,10 @@ mod tests {
             )
         }
 
+        fn reg_chunked(path: impl Into<String>, chunks: Vec<FileChunk>) -> Self {
+            Self::reg_mode(path, FileDataInput::Chunked(chunks), 0o555)
+        }
+
         fn reg_empty(path: impl Into<String>) -> Self {
             Self::reg_empty_mode(path, 0o555)
         }
@@ -988,6 +1153,8 @@ mod tests {
                                         size,
                                         mode: Mode(mode),
                                         mtime: ARBITRARY_TIME,
+                                        xattrs: Xattrs::new(),
+                                        owner: None,
                                     },
                                     data,
                                 )
@@ -1002,6 +1169,8 @@ mod tests {
                                         size,
                                         mode: Mode(mode),
                                         mtime: ARBITRARY_TIME,
+                                        xattrs: Xattrs::new(),
+                                        owner: None,
                                     },
                                 )
                                 .await
@@ -1032,6 +1201,16 @@ mod tests {
             builder.finish().await.unwrap()
         }
 
+        async fn build_compacted_layer(&mut self, stack: &LayerFs) -> LayerFs {
+            let data_dir = self.new_data_dir().await;
+            let mut builder =
+                CompactedLayerBuilder::new(self.log.clone(), &self.fs, &data_dir, &self.blob_dir)
+                    .await
+                    .unwrap();
+            builder.fill_from_stack(stack).await.unwrap();
+            builder.finish().await.unwrap()
+        }
+
         async fn build_tar(&self, files: Vec<BuildEntry>) -> (Sha256Digest, PathBuf) {
             let tar_path = self.blob_dir.join::<BlobFile>("temp.tar");
             let f = self.fs.create_file(&tar_path).await.unwrap();
@@ -1122,11 +1301,16 @@ mod tests {
                             FileDataInput::Empty => 0,
                             FileDataInput::Inline(d) => d.len() as u64,
                             FileDataInput::Digest { length, .. } => *length,
+                            FileDataInput::Chunked(chunks) => {
+                                chunks.iter().map(|chunk| chunk.length).sum()
+                            }
                         };
                         let metadata = ManifestEntryMetadata {
                             size,
                             mode: Mode(mode),
                             mtime: ARBITRARY_TIME,
+                            xattrs: Default::default(),
+                            owner: None,
                         };
                         match type_ {
                             FileType::Directory => builder
@@ -1145,6 +1329,15 @@ mod tests {
                                         assert_eq!(offset, 0);
                                         ManifestFileData::Digest(digest)
                                     }
+                                    FileDataInput::Chunked(chunks) => ManifestFileData::Chunked(
+                                        chunks
+                                            .into_iter()
+                                            .map(|chunk| ManifestChunk {
+                                                digest: chunk.digest,
+                                                size: chunk.length,
+                                            })
+                                            .collect(),
+                                    ),
                                 };
                                 builder
                                     .write_entry(&ManifestEntry {
@@ -1163,6 +1356,8 @@ mod tests {
                             size: 0,
                             mode: Mode(0o777),
                             mtime: ARBITRARY_TIME,
+                            xattrs: Default::default(),
+                            owner: None,
                         };
                         builder
                             .write_entry(&ManifestEntry {
@@ -1181,6 +1376,8 @@ mod tests {
                             size: 0,
                             mode: Mode(0o777),
                             mtime: ARBITRARY_TIME,
+                            xattrs: Default::default(),
+                            owner: None,
                         };
                         builder
                             .write_entry(&ManifestEntry {
@@ -1196,6 +1393,8 @@ mod tests {
                             size: 0,
                             mode: Mode(0o777),
                             mtime: ARBITRARY_TIME,
+                            xattrs: Default::default(),
+                            owner: None,
                         };
                         builder
                             .write_entry(&ManifestEntry {
@@ -1470,6 +1669,153 @@ mod tests {
         mount_handle.umount_and_join().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn read_chunked() {
+        let mut fix = Fixture::new().await;
+
+        let hello = fix.add_to_cache(b"hello ").await;
+        let world = fix.add_to_cache(b"world").await;
+        let layer_fs = fix
+            .build_bottom_layer_from_manifest(vec![BuildEntry::reg_chunked(
+                "/Foo",
+                vec![
+                    FileChunk {
+                        digest: hello,
+                        length: 6,
+                    },
+                    FileChunk {
+                        digest: world,
+                        length: 5,
+                    },
+                ],
+            )])
+            .await;
+        let adapter = LayerFsFuseAdapter::new(layer_fs, fix.log.clone(), fix.cache.clone());
+
+        let root = adapter.layer_fs.root().await.unwrap().as_u64();
+        let ino = adapter
+            .look_up(test_request(), root, OsStr::new("Foo"))
+            .await
+            .unwrap()
+            .attr
+            .ino;
+        for (offset, size, expected) in [
+            (0, 100, "hello world"),
+            (3, 5, "lo wo"),
+            (6, 5, "world"),
+            (11, 5, ""),
+        ] {
+            let response = adapter
+                .read(test_request(), ino, 0, offset, size, 0, None)
+                .await
+                .unwrap();
+            let ReadResponse::Buffer { data } = response else {
+                panic!("chunked file read wasn't buffered");
+            };
+            assert_eq!(std::str::from_utf8(&data).unwrap(), expected, "{offset}");
+        }
+    }
+
+    /// Fetches blobs by moving them into the blob directory from a staging directory.
+    struct TestBlobFetcher {
+        staging_dir: PathBuf,
+        blob_dir: RootBuf<BlobDir>,
+        fetched: std::sync::Mutex<Vec<Sha256Digest>>,
+    }
+
+    #[async_trait]
+    impl BlobFetcher for TestBlobFetcher {
+        async fn fetch_blob(&self, digest: &Sha256Digest) -> Result<()> {
+            self.fetched.lock().unwrap().push(digest.clone());
+            Fs::new()
+                .rename(
+                    self.staging_dir.join(digest.to_string()),
+                    self.blob_dir.join::<BlobFile>(digest.to_string()),
+                )
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn read_digest_fetched_on_demand() {
+        let mut fix = Fixture::new().await;
+
+        let digest = fix.add_to_cache(b"hello world").await;
+        let staging_dir = fix.temp.path().join("staging");
+        fix.fs.create_dir(&staging_dir).await.unwrap();
+        fix.fs
+            .rename(
+                fix.blob_dir.join::<BlobFile>(digest.to_string()),
+                staging_dir.join(digest.to_string()),
+            )
+            .await
+            .unwrap();
+        let blob_fetcher = Arc::new(TestBlobFetcher {
+            staging_dir,
+            blob_dir: fix.blob_dir.clone(),
+            fetched: Default::default(),
+        });
+
+        let layer_fs = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg_digest("/Foo", digest.clone(), 0, 5),
+                BuildEntry::reg_digest("/Bar", digest.clone(), 6, 5),
+            ])
+            .await
+            .with_blob_fetcher(blob_fetcher.clone());
+
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        assert_expectations(
+            &fix.fs,
+            mount_path,
+            vec![
+                Expect::Contents("Foo", "hello"),
+                Expect::Contents("Bar", "world"),
+            ],
+        )
+        .await;
+
+        mount_handle.umount_and_join().await.unwrap();
+        assert_eq!(*blob_fetcher.fetched.lock().unwrap(), vec![digest]);
+    }
+
+    #[tokio::test]
+    async fn open_passes_through_whole_data_files() {
+        let mut fix = Fixture::new().await;
+
+        let digest = fix.add_to_cache(b"hello world").await;
+        let layer_fs = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg_digest("/Whole", digest.clone(), 0, 11),
+                BuildEntry::reg_digest("/Part", digest.clone(), 0, 5),
+                BuildEntry::reg("/Small", b"hi"),
+            ])
+            .await;
+        let adapter = LayerFsFuseAdapter::new(layer_fs, fix.log.clone(), fix.cache.clone());
+
+        let root = adapter.layer_fs.root().await.unwrap().as_u64();
+        for (name, passthrough) in [("Whole", true), ("Part", false), ("Small", false)] {
+            let ino = adapter
+                .look_up(test_request(), root, OsStr::new(name))
+                .await
+                .unwrap()
+                .attr
+                .ino;
+            let response = adapter.open(test_request(), ino, 0).await.unwrap();
+            assert_eq!(response.passthrough.is_some(), passthrough, "{name}");
+        }
+    }
+
+    fn test_request() -> Request {
+        Request {
+            uid: 0,
+            gid: 0,
+            pid: 0,
+        }
+    }
+
     async fn calc_digest(fs: &Fs, path: &Path) -> Sha256Digest {
         let mut f = fs.open_file(path).await.unwrap();
         let mut hasher = maelstrom_util::io::Sha256Stream::new(tokio::io::sink());
@@ -1665,6 +2011,11 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn two_layer_both_empty() {
+        two_layer_test(vec![], vec![], vec![Expect::Entries("", vec![])]).await;
+    }
+
     #[tokio::test]
     async fn two_layer_empty_top() {
         two_layer_test(
@@ -1830,4 +2181,557 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test]
+    async fn compacted_layer() {
+        let mut fix = Fixture::new().await;
+
+        let layer_fs1 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Cake/Cupcakes/Sprinkle", b"sprinkles"),
+                BuildEntry::reg_empty("/Cake/Cupcakes/RedVelvet"),
+                BuildEntry::reg_empty("/Pie/Apple"),
+                BuildEntry::link("/Pie/Crumble", "/Pie/Apple"),
+            ])
+            .await;
+        let layer_fs2 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::whiteout("/Cake/Cupcakes/RedVelvet"),
+                BuildEntry::reg("/Cake/Chocolate", b"chocolate"),
+                BuildEntry::sym("/Pie/KeyLime", "Apple"),
+            ])
+            .await;
+        let layer_fs3 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::opaque_dir("/Pie/"),
+                BuildEntry::reg("/Pie/Pecan", b"pecan"),
+                BuildEntry::reg("/Cake/Chocolate", b"more chocolate"),
+            ])
+            .await;
+        let stack = fix.build_upper_layer(&layer_fs1, &layer_fs2).await;
+        let stack = fix.build_upper_layer(&stack, &layer_fs3).await;
+
+        let layer_fs = fix.build_compacted_layer(&stack).await;
+        let layer_super = layer_fs.layer_super().await.unwrap();
+        assert_eq!(layer_super.layer_id, LayerId::BOTTOM);
+        assert!(layer_super.lower_layers.is_empty());
+
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        assert_expectations(
+            &fix.fs,
+            mount_path,
+            vec![
+                Expect::Entries("", vec!["Cake/", "Pie/"]),
+                Expect::Entries("Cake", vec!["Chocolate", "Cupcakes/"]),
+                Expect::Entries("Cake/Cupcakes", vec!["Sprinkle"]),
+                Expect::Entries("Pie", vec!["Pecan"]),
+                Expect::Contents("Cake/Cupcakes/Sprinkle", "sprinkles"),
+                Expect::Contents("Cake/Chocolate", "more chocolate"),
+                Expect::Contents("Pie/Pecan", "pecan"),
+            ],
+        )
+        .await;
+
+        mount_handle.umount_and_join().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn compacted_layer_keeps_hard_links_and_symlinks() {
+        let mut fix = Fixture::new().await;
+
+        let layer_fs1 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::link("/Pie/Crumble", "/Pie/Apple"),
+            ])
+            .await;
+        let layer_fs2 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::sym("/Pie/KeyLime", "Apple"),
+                BuildEntry::link("/Pie/Tart", "/Pie/KeyLime"),
+            ])
+            .await;
+        let stack = fix.build_upper_layer(&layer_fs1, &layer_fs2).await;
+
+        let layer_fs = fix.build_compacted_layer(&stack).await;
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        let apple_attrs = fix.fs.metadata(mount_path.join("Pie/Apple")).await.unwrap();
+        let key_lime_attrs = fix
+            .fs
+            .symlink_metadata(mount_path.join("Pie/KeyLime"))
+            .await
+            .unwrap();
+        assert_expectations(
+            &fix.fs,
+            mount_path,
+            vec![
+                Expect::Entries("Pie", vec!["Apple", "Crumble", "KeyLime", "Tart"]),
+                Expect::Contents("Pie/Crumble", "apple"),
+                Expect::Attrs(
+                    "Pie/Crumble",
+                    ExpectedAttrs {
+                        ino: Some(apple_attrs.ino()),
+                        ..Default::default()
+                    },
+                ),
+                Expect::IsSymlink("Pie/KeyLime"),
+                Expect::IsSymlink("Pie/Tart"),
+                Expect::Contents("Pie/KeyLime", "apple"),
+            ],
+        )
+        .await;
+        let tart_attrs = fix
+            .fs
+            .symlink_metadata(mount_path.join("Pie/Tart"))
+            .await
+            .unwrap();
+        assert_eq!(tart_attrs.ino(), key_lime_attrs.ino());
+
+        mount_handle.umount_and_join().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn inspect_stack() {
+        let mut fix = Fixture::new().await;
+        let digest = fix.add_to_cache(b"hello world").await;
+
+        let layer_fs1 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::reg_digest("/Pie/Pecan", digest, 6, 5),
+                BuildEntry::reg_empty("/Cake"),
+            ])
+            .await;
+        let layer_fs2 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::sym("/Pie/KeyLime", "Apple"),
+                BuildEntry::whiteout("/Cake"),
+            ])
+            .await;
+        let stack = fix.build_upper_layer(&layer_fs1, &layer_fs2).await;
+
+        let mut inspector = LayerInspector::new(&stack);
+        assert!(inspector.look_up("/Cake".into()).await.unwrap().is_none());
+        assert!(inspector
+            .look_up("/Pie/Apple/Crumble".into())
+            .await
+            .unwrap()
+            .is_none());
+
+        let apple = inspector
+            .look_up("/Pie/Apple".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(apple.kind, FileType::RegularFile);
+        assert_eq!(apple.file_id.layer(), LayerId::BOTTOM);
+        assert_eq!(inspector.read(&apple).await.unwrap(), b"apple");
+
+        let pecan = inspector
+            .look_up("/Pie/Pecan".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(inspector.read(&pecan).await.unwrap(), b"world");
+
+        let key_lime = inspector
+            .look_up("/Pie/KeyLime".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key_lime.kind, FileType::Symlink);
+        assert_eq!(key_lime.file_id.layer(), LayerId::BOTTOM.inc());
+        assert_eq!(inspector.read(&key_lime).await.unwrap(), b"Apple");
+
+        let root = inspector.root().await.unwrap();
+        let walked: Vec<_> = inspector
+            .walk(root)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path.into_string())
+            .collect();
+        assert_eq!(
+            walked,
+            vec!["/", "/Pie", "/Pie/Apple", "/Pie/KeyLime", "/Pie/Pecan"]
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_layers() {
+        let mut fix = Fixture::new().await;
+        let digest = fix.add_to_cache(b"hello world").await;
+
+        let left = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::reg("/Pie/Cherry", b"cherry"),
+                BuildEntry::reg_digest("/Pie/Pecan", digest.clone(), 0, 5),
+                BuildEntry::reg_empty("/Cake"),
+            ])
+            .await;
+        let right = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::reg("/Pie/Cherry", b"sour cherry"),
+                BuildEntry::reg_digest("/Pie/Pecan", digest, 6, 5),
+                BuildEntry::reg_empty("/Cookie"),
+            ])
+            .await;
+
+        let differences: Vec<_> = diff(&left, &right)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| match d {
+                Difference::Added(e) => format!("+{}", e.path),
+                Difference::Removed(e) => format!("-{}", e.path),
+                Difference::Changed(e, _) => format!("~{}", e.path),
+            })
+            .collect();
+        assert_eq!(
+            differences,
+            vec!["-/Cake", "+/Cookie", "~/Pie/Cherry", "~/Pie/Pecan"]
+        );
+
+        assert!(diff(&left, &left).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn export_layer_as_tar() {
+        let mut fix = Fixture::new().await;
+
+        let layer_fs = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::link("/Pie/Crumble", "/Pie/Apple"),
+                BuildEntry::sym("/Pie/KeyLime", "Apple"),
+                BuildEntry::reg_empty("/Cake"),
+            ])
+            .await;
+
+        let tar_path = fix.temp.path().join("export.tar");
+        let out = fix.fs.create_file(&tar_path).await.unwrap();
+        export_tar(&layer_fs, out.into_inner()).await.unwrap();
+
+        let mut ar = tokio_tar::Archive::new(fix.fs.open_file(&tar_path).await.unwrap());
+        let mut entries = ar.entries().unwrap();
+        let mut found = vec![];
+        while let Some(mut entry) = entries.next().await.transpose().unwrap() {
+            let path = entry.path().unwrap().to_str().unwrap().to_owned();
+            let description = match entry.header().entry_type() {
+                tokio_tar::EntryType::Directory => format!("{path} dir"),
+                tokio_tar::EntryType::Regular => {
+                    let mut contents = String::new();
+                    entry.read_to_string(&mut contents).await.unwrap();
+                    format!("{path} file {contents:?}")
+                }
+                tokio_tar::EntryType::Symlink => format!(
+                    "{path} symlink {}",
+                    entry.link_name().unwrap().unwrap().to_str().unwrap()
+                ),
+                tokio_tar::EntryType::Link => format!(
+                    "{path} link {}",
+                    entry.link_name().unwrap().unwrap().to_str().unwrap()
+                ),
+                other => panic!("unexpected entry type {other:?}"),
+            };
+            found.push(description);
+        }
+        assert_eq!(
+            found,
+            vec![
+                "Cake file \"\"",
+                "Pie dir",
+                "Pie/Apple file \"apple\"",
+                "Pie/Crumble link Pie/Apple",
+                "Pie/KeyLime symlink Apple",
+            ]
+        );
+    }
+
+    fn xattrs(pairs: &[(&str, &[u8])]) -> Xattrs {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_vec()))
+            .collect()
+    }
+
+    fn attrs_with_xattrs(xattrs: Xattrs) -> FileAttributes {
+        FileAttributes {
+            size: 0,
+            mode: Mode(0o555),
+            mtime: ARBITRARY_TIME,
+            xattrs,
+            owner: None,
+        }
+    }
+
+    async fn build_layer_with_xattrs(fix: &mut Fixture) -> LayerFs {
+        let data_dir = fix.new_data_dir().await;
+        let mut builder = fix.bottom_layer_builder(&data_dir).await;
+        builder
+            .add_file_path(
+                "/Ping".into(),
+                attrs_with_xattrs(xattrs(&[
+                    ("security.capability", b"\x01\x00\x00\x02"),
+                    ("user.comment", b"pong"),
+                ])),
+                FileDataInput::Empty,
+            )
+            .await
+            .unwrap();
+        builder
+            .add_file_path(
+                "/Pie/Apple".into(),
+                attrs_with_xattrs(Xattrs::new()),
+                FileDataInput::Empty,
+            )
+            .await
+            .unwrap();
+        // The directory was created without extended attributes above, so they get added when its
+        // attributes are updated.
+        builder
+            .add_dir_path(
+                "/Pie".into(),
+                attrs_with_xattrs(xattrs(&[("user.flavor", b"apple")])),
+            )
+            .await
+            .unwrap();
+        builder.finish().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn xattrs_stored_in_layer() {
+        let mut fix = Fixture::new().await;
+        let layer_fs = build_layer_with_xattrs(&mut fix).await;
+        verify_layer(&layer_fs).await.unwrap();
+
+        let mut inspector = LayerInspector::new(&layer_fs);
+        let ping = inspector.look_up("/Ping".into()).await.unwrap().unwrap();
+        assert_eq!(
+            ping.attrs.xattrs,
+            xattrs(&[
+                ("security.capability", b"\x01\x00\x00\x02"),
+                ("user.comment", b"pong"),
+            ])
+        );
+        let pie = inspector.look_up("/Pie".into()).await.unwrap().unwrap();
+        assert_eq!(pie.attrs.xattrs, xattrs(&[("user.flavor", b"apple")]));
+        let apple = inspector
+            .look_up("/Pie/Apple".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(apple.attrs.xattrs, Xattrs::new());
+
+        let compacted = fix.build_compacted_layer(&layer_fs).await;
+        assert!(diff(&layer_fs, &compacted).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn xattrs_served_through_fuse() {
+        let mut fix = Fixture::new().await;
+        let layer_fs = build_layer_with_xattrs(&mut fix).await;
+
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        // The syscalls block, and must not keep the runtime from serving the requests.
+        let ping = mount_path.join("Ping");
+        tokio::task::spawn_blocking(move || {
+            let ping = std::ffi::CString::new(ping.as_os_str().as_encoded_bytes()).unwrap();
+            let mut names = [0; 100];
+            let length = maelstrom_linux::listxattr(&ping, &mut names).unwrap();
+            assert_eq!(&names[..length], b"security.capability\0user.comment\0");
+            let mut value = [0; 100];
+            let length = maelstrom_linux::getxattr(&ping, c"user.comment", &mut value).unwrap();
+            assert_eq!(&value[..length], b"pong");
+            assert_eq!(
+                maelstrom_linux::getxattr(&ping, c"user.comment", &mut value[..2]),
+                Err(Errno::ERANGE)
+            );
+            assert_eq!(
+                maelstrom_linux::getxattr(&ping, c"user.missing", &mut value),
+                Err(Errno::ENODATA)
+            );
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fix.fs.xattrs(mount_path.join("Pie")).await.unwrap(),
+            xattrs(&[("user.flavor", b"apple")])
+        );
+        assert_eq!(
+            fix.fs.xattrs(mount_path.join("Pie/Apple")).await.unwrap(),
+            Xattrs::new()
+        );
+
+        mount_handle.umount_and_join().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn xattrs_round_trip_through_tar() {
+        let mut fix = Fixture::new().await;
+        let layer_fs = build_layer_with_xattrs(&mut fix).await;
+
+        let tar_path = fix.temp.path().join("export.tar");
+        let out = fix.fs.create_file(&tar_path).await.unwrap();
+        export_tar(&layer_fs, out.into_inner()).await.unwrap();
+        let digest = calc_digest(&fix.fs, &tar_path).await;
+        let blob_path = fix.blob_dir.join::<BlobFile>(digest.to_string());
+        fix.fs.rename(&tar_path, &blob_path).await.unwrap();
+
+        let data_dir = fix.new_data_dir().await;
+        let mut builder = fix.bottom_layer_builder(&data_dir).await;
+        builder
+            .add_from_tar(digest, fix.fs.open_file(&blob_path).await.unwrap())
+            .await
+            .unwrap();
+        let imported = builder.finish().await.unwrap();
+
+        let mut inspector = LayerInspector::new(&imported);
+        let ping = inspector.look_up("/Ping".into()).await.unwrap().unwrap();
+        assert_eq!(
+            ping.attrs.xattrs,
+            xattrs(&[
+                ("security.capability", b"\x01\x00\x00\x02"),
+                ("user.comment", b"pong"),
+            ])
+        );
+        let pie = inspector.look_up("/Pie".into()).await.unwrap().unwrap();
+        assert_eq!(pie.attrs.xattrs, xattrs(&[("user.flavor", b"apple")]));
+    }
+
+    #[tokio::test]
+    async fn xattrs_from_manifest() {
+        let mut fix = Fixture::new().await;
+        let manifest_path = fix.temp.path().join("xattrs.manifest");
+        let mut writer =
+            AsyncManifestWriter::new(fix.fs.create_file(&manifest_path).await.unwrap())
+                .await
+                .unwrap();
+        writer
+            .write_entry(&ManifestEntry {
+                path: "Ping".into(),
+                metadata: ManifestEntryMetadata {
+                    size: 0,
+                    mode: Mode(0o555),
+                    mtime: ARBITRARY_TIME,
+                    xattrs: xattrs(&[("user.comment", b"pong")]),
+                    owner: None,
+                },
+                data: ManifestEntryData::File(ManifestFileData::Empty),
+            })
+            .await
+            .unwrap();
+        drop(writer);
+
+        let data_dir = fix.new_data_dir().await;
+        let mut builder = fix.bottom_layer_builder(&data_dir).await;
+        builder
+            .add_from_manifest(fix.fs.open_file(&manifest_path).await.unwrap())
+            .await
+            .unwrap();
+        let layer_fs = builder.finish().await.unwrap();
+
+        let mut inspector = LayerInspector::new(&layer_fs);
+        let ping = inspector.look_up("/Ping".into()).await.unwrap().unwrap();
+        assert_eq!(ping.attrs.xattrs, xattrs(&[("user.comment", b"pong")]));
+    }
+
+    #[tokio::test]
+    async fn verify_built_layers() {
+        let mut fix = Fixture::new().await;
+
+        let layer_fs1 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::link("/Pie/Crumble", "/Pie/Apple"),
+                BuildEntry::reg_empty("/Cake"),
+            ])
+            .await;
+        let layer_fs2 = fix
+            .build_bottom_layer(vec![
+                BuildEntry::sym("/Pie/KeyLime", "Apple"),
+                BuildEntry::whiteout("/Cake"),
+                BuildEntry::opaque_dir("/Cookie/"),
+            ])
+            .await;
+        let stack = fix.build_upper_layer(&layer_fs1, &layer_fs2).await;
+        let compacted = fix.build_compacted_layer(&stack).await;
+
+        for layer_fs in [&layer_fs1, &layer_fs2, &stack, &compacted] {
+            verify_layer(layer_fs).await.unwrap();
+        }
+    }
+
+    async fn verify_after(corrupt: impl FnOnce(&Path)) -> Result<()> {
+        let mut fix = Fixture::new().await;
+        let layer_fs = fix
+            .build_bottom_layer(vec![
+                BuildEntry::reg("/Pie/Apple", b"apple"),
+                BuildEntry::reg_empty("/Cake"),
+            ])
+            .await;
+        let layer_path = layer_fs.top_layer_path.clone();
+        verify_layer(&layer_fs).await.unwrap();
+        corrupt(&layer_path);
+        verify_layer(&LayerFs::from_path(&layer_path, &fix.blob_dir).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_super_without_header() {
+        let err = verify_after(|layer_path| {
+            let mut buffer = vec![0; 8];
+            maelstrom_base::proto::fixint_serialize_into(&mut buffer, &LayerSuper::default())
+                .unwrap();
+            let len = (buffer.len() - 8) as u64;
+            buffer[..8].copy_from_slice(&len.to_be_bytes());
+            std::fs::write(layer_path.join("super.bin"), buffer).unwrap();
+        })
+        .await
+        .unwrap_err();
+        assert!(
+            err.root_cause().to_string().contains("compatible"),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_truncated_file_table() {
+        verify_after(|layer_path| {
+            let path = layer_path.join("file_table.bin");
+            let len = std::fs::metadata(&path).unwrap().len();
+            let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(len - 3).unwrap();
+        })
+        .await
+        .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_missing_directory() {
+        verify_after(|layer_path| {
+            std::fs::remove_file(layer_path.join("1.dir_data.bin")).unwrap();
+        })
+        .await
+        .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_truncated_inline_data() {
+        verify_after(|layer_path| {
+            let path = layer_path.join("inline_data.bin");
+            let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(2).unwrap();
+        })
+        .await
+        .unwrap_err();
+    }
 }
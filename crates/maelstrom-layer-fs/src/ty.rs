@@ -3,7 +3,7 @@ pub use maelstrom_fuse::FileType;
 use anyhow::{bail, Context as _, Result};
 use derive_more::{From, Into};
 use maelstrom_base::{
-    manifest::{Mode, UnixTimestamp},
+    manifest::{Mode, Owner, UnixTimestamp, Xattrs},
     Sha256Digest,
 };
 use maelstrom_fuse::ErrnoResult;
@@ -52,13 +52,43 @@ impl Default for LayerSuper {
     }
 }
 
+/// Written to `super.bin` before the [`LayerSuper`]. It lets us tell a LayerFS layer we can read
+/// apart from one written by an incompatible version, or from something else entirely.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+struct LayerSuperHeader {
+    magic: [u8; 8],
+    version: u32,
+}
+
+const LAYER_SUPER_MAGIC: [u8; 8] = *b"MAELLYFS";
+
 impl LayerSuper {
     pub async fn read_from_path(fs: &Fs, path: &Path) -> Result<Self> {
-        decode(fs.open_file(path).await?).await
+        let mut file = fs.open_file(path).await?;
+        let header: LayerSuperHeader = decode(&mut file)
+            .await
+            .with_context(|| format!("reading header of {path:?}"))?;
+        if header.magic != LAYER_SUPER_MAGIC {
+            bail!("{path:?} isn't from a LayerFS layer written by a compatible version");
+        }
+        if header.version != LayerFsVersion::CURRENT as u32 {
+            bail!(
+                "{path:?} has LayerFS version {}, expected {}",
+                header.version,
+                LayerFsVersion::CURRENT as u32
+            );
+        }
+        decode(file).await
     }
 
     pub async fn write_to_path(&self, fs: &Fs, path: &Path) -> Result<()> {
-        encode(fs.create_file(path).await?, self).await
+        let mut file = fs.create_file(path).await?;
+        let header = LayerSuperHeader {
+            magic: LAYER_SUPER_MAGIC,
+            version: LayerFsVersion::CURRENT as u32,
+        };
+        encode(&mut file, &header).await?;
+        encode(file, self).await
     }
 }
 
@@ -157,11 +187,33 @@ impl DirectoryEntryData {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug, Deserialize_repr, Serialize_repr)]
+/// The version of the on-disk format. It is stored in the header of every file making up a layer.
+#[derive(Copy, Clone, Default, Debug, Deserialize_repr, Serialize_repr, PartialEq, Eq)]
 #[repr(u32)]
 pub enum LayerFsVersion {
-    #[default]
+    /// Layers written before `super.bin` had a header.
     V0 = 0,
+    /// Layers written before files had extended attributes.
+    V1 = 1,
+    /// Layers written before files had owners.
+    V2 = 2,
+    #[default]
+    V3 = 3,
+}
+
+impl LayerFsVersion {
+    pub const CURRENT: Self = Self::V3;
+
+    /// Error if this isn't the version we write, naming the file it was read from.
+    pub fn check_current(self, path: &Path) -> Result<()> {
+        if self != Self::CURRENT {
+            bail!(
+                "{path:?} has LayerFS version {self:?}, expected {:?}",
+                Self::CURRENT
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
@@ -182,30 +234,73 @@ impl TryFrom<u64> for AttributesId {
 }
 
 /// The attributes we store in attribute-table about each file.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FileAttributes {
     pub size: u64,
     pub mode: Mode,
     pub mtime: UnixTimestamp,
+    pub xattrs: Xattrs,
+    pub owner: Option<Owner>,
+}
+
+/// How [`FileAttributes`] are stored in the attribute-table. The entry has a fixed size so it can
+/// be updated in place, which means the extended attributes are stored as a separate item in the
+/// table.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AttributesTableEntry {
+    pub size: u64,
+    pub mode: Mode,
+    pub mtime: UnixTimestamp,
+    /// The offset of the extended attributes in the attribute-table, like [`AttributesId`], or
+    /// zero if the file has none.
+    pub xattrs_offset: u64,
+    /// The ids of the file's owner, or [`AttributesTableEntry::NO_OWNER`] for both if it has none.
+    pub owner_user: u32,
+    pub owner_group: u32,
+}
+
+impl AttributesTableEntry {
+    /// `(uid_t)-1` isn't a valid id, so it can stand for a missing owner.
+    pub const NO_OWNER: u32 = u32::MAX;
+
+    pub fn owner(&self) -> Option<Owner> {
+        (self.owner_user != Self::NO_OWNER).then(|| Owner {
+            user: self.owner_user.into(),
+            group: self.owner_group.into(),
+        })
+    }
 }
 
 #[test]
-fn file_attributes_encoding_size_remains_same() {
+fn attributes_table_entry_encoding_size_remains_same() {
     use maelstrom_base::proto;
 
-    let mut a = FileAttributes {
+    let mut a = AttributesTableEntry {
         size: 1,
         mode: Mode(1),
         mtime: UnixTimestamp(1),
+        xattrs_offset: 0,
+        owner_user: 0,
+        owner_group: 0,
     };
     let start_size = proto::fixint_serialized_size(&a).unwrap();
     a.size = u64::MAX;
     a.mode = Mode(u32::MAX);
     a.mtime = UnixTimestamp(i64::MAX);
+    a.xattrs_offset = u64::MAX;
+    a.owner_user = u32::MAX;
+    a.owner_group = u32::MAX;
     let end_size = proto::fixint_serialized_size(&a).unwrap();
     assert_eq!(start_size, end_size);
 }
 
+/// One piece of a file whose data is split across several data files.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FileChunk {
+    pub digest: Sha256Digest,
+    pub length: u64,
+}
+
 /// The data for a file
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum FileData {
@@ -219,6 +314,8 @@ pub enum FileData {
         offset: u64,
         length: u64,
     },
+    /// The file's data is the concatenation of the data files of the given chunks.
+    Chunked(Vec<FileChunk>),
 }
 
 /// What is stored in the file-table about each file.
@@ -0,0 +1,148 @@
+//! Checking a layer for corruption before serving it.
+
+use crate::{
+    dir::DirectoryDataReader,
+    file::{AttributesTableHeader, FileTableHeader},
+    ty::{
+        decode_with_rich_error, AttributesTableEntry, DirectoryEntryData, FileData, FileId,
+        FileTableEntry, FileType, LayerSuper,
+    },
+    LayerFs,
+};
+use anyhow::{bail, Context as _, Result};
+use anyhow_trace::anyhow_trace;
+use maelstrom_base::manifest::Xattrs;
+use maelstrom_util::{
+    async_fs::{File, Fs},
+    io::BufferedStream,
+};
+use std::{collections::HashMap, io::SeekFrom, num::NonZeroU32, path::Path};
+use tokio::io::AsyncSeekExt as _;
+
+const CHUNK_SIZE: usize = 512;
+const CACHE_SIZE: usize = 64;
+
+async fn open(fs: &Fs, path: &Path) -> Result<(BufferedStream<File>, u64)> {
+    let file = fs.open_file(path).await?;
+    let length = file.metadata().await?.len();
+    let stream = BufferedStream::new(CHUNK_SIZE, CACHE_SIZE.try_into().unwrap(), file).await?;
+    Ok((stream, length))
+}
+
+/// Check that the given layer was written by this version of LayerFS, and that its tables and
+/// directories are consistent with each other. This is meant to catch layers left half-written by
+/// a crash, before they're served.
+///
+/// Lower layers are only checked far enough to see that they're the layers the given one expects.
+/// File-data stored by digest isn't checked.
+#[anyhow_trace]
+pub async fn verify_layer(layer_fs: &LayerFs) -> Result<()> {
+    let fs = &layer_fs.data_fs;
+    let LayerSuper {
+        layer_id,
+        lower_layers,
+    } = layer_fs.layer_super().await?;
+    for (lower_id, lower_path) in &lower_layers {
+        if lower_id.as_u32() >= layer_id.as_u32() {
+            bail!("lower layer {lower_id:?} isn't below layer {layer_id:?}");
+        }
+        let lower_super = LayerSuper::read_from_path(fs, &lower_path.join("super.bin"))
+            .await
+            .with_context(|| format!("reading lower layer {lower_id:?}"))?;
+        if lower_super.layer_id != *lower_id {
+            bail!(
+                "layer at {lower_path:?} is {:?}, expected {lower_id:?}",
+                lower_super.layer_id
+            );
+        }
+    }
+
+    let inline_data_length = fs
+        .metadata(layer_fs.inline_data_path(layer_id).await?)
+        .await?
+        .len();
+
+    let attrs_path = layer_fs.attributes_table_path(layer_id).await?;
+    let (mut attrs, attrs_length) = open(fs, &attrs_path).await?;
+    let header: AttributesTableHeader = decode_with_rich_error(&mut attrs).await?;
+    header.version.check_current(&attrs_path)?;
+    let attrs_start = attrs.stream_position().await?;
+
+    let files_path = layer_fs.file_table_path(layer_id).await?;
+    let (mut files, files_length) = open(fs, &files_path).await?;
+    let header: FileTableHeader = decode_with_rich_error(&mut files).await?;
+    header.version.check_current(&files_path)?;
+    let files_start = files.stream_position().await?;
+
+    let mut kinds = HashMap::new();
+    loop {
+        let position = files.stream_position().await?;
+        if position >= files_length {
+            break;
+        }
+        let offset = NonZeroU32::try_from(u32::try_from(position - files_start + 1)?)?;
+        let entry: FileTableEntry = decode_with_rich_error(&mut files).await?;
+
+        let attrs_position = attrs_start + entry.attr_id.offset() - 1;
+        if attrs_position >= attrs_length {
+            bail!("file {offset} has attributes past the end of the attributes table");
+        }
+        attrs.seek(SeekFrom::Start(attrs_position)).await?;
+        let attrs_entry: AttributesTableEntry = decode_with_rich_error(&mut attrs).await?;
+        if attrs_entry.xattrs_offset != 0 {
+            let xattrs_position = attrs_start + attrs_entry.xattrs_offset - 1;
+            if xattrs_position >= attrs_length {
+                bail!("file {offset} has extended attributes past the end of the attributes table");
+            }
+            attrs.seek(SeekFrom::Start(xattrs_position)).await?;
+            let _: Xattrs = decode_with_rich_error(&mut attrs).await?;
+        }
+
+        if let FileData::Inline {
+            offset: data_offset,
+            length,
+        } = entry.data
+        {
+            if data_offset
+                .checked_add(length)
+                .map_or(true, |end| end > inline_data_length)
+            {
+                bail!("file {offset} has inline data past the end of the inline data");
+            }
+        }
+        kinds.insert(offset, entry.kind);
+    }
+
+    if kinds.get(&NonZeroU32::MIN) != Some(&FileType::Directory) {
+        bail!("layer has no root directory");
+    }
+
+    for (&offset, &kind) in &kinds {
+        if kind != FileType::Directory {
+            continue;
+        }
+        let entries = DirectoryDataReader::new(layer_fs, FileId::new(layer_id, offset))
+            .await?
+            .verify()
+            .await
+            .with_context(|| format!("checking directory {offset}"))?;
+        for (name, data) in entries {
+            let DirectoryEntryData::FileData(data) = data else {
+                continue;
+            };
+            let entry_layer_id = data.file_id.layer();
+            let found_kind = if entry_layer_id == layer_id {
+                kinds.get(&data.file_id.offset()).copied()
+            } else if lower_layers.contains_key(&entry_layer_id) {
+                Some(data.kind)
+            } else {
+                None
+            };
+            if found_kind != Some(data.kind) {
+                bail!("entry {name:?} of directory {offset} refers to a missing file");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -59,6 +59,81 @@ pub trait AsFd {
     fn fd(&self) -> Fd;
 }
 
+/// A capability, as described in capabilities(7). The libc crate doesn't provide these constants,
+/// so we take them from linux/capability.h.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capability(c_int);
+
+impl Capability {
+    pub const CHOWN: Self = Self(0);
+    pub const DAC_OVERRIDE: Self = Self(1);
+    pub const DAC_READ_SEARCH: Self = Self(2);
+    pub const FOWNER: Self = Self(3);
+    pub const FSETID: Self = Self(4);
+    pub const KILL: Self = Self(5);
+    pub const SETGID: Self = Self(6);
+    pub const SETUID: Self = Self(7);
+    pub const SETPCAP: Self = Self(8);
+    pub const LINUX_IMMUTABLE: Self = Self(9);
+    pub const NET_BIND_SERVICE: Self = Self(10);
+    pub const NET_BROADCAST: Self = Self(11);
+    pub const NET_ADMIN: Self = Self(12);
+    pub const NET_RAW: Self = Self(13);
+    pub const IPC_LOCK: Self = Self(14);
+    pub const IPC_OWNER: Self = Self(15);
+    pub const SYS_MODULE: Self = Self(16);
+    pub const SYS_RAWIO: Self = Self(17);
+    pub const SYS_CHROOT: Self = Self(18);
+    pub const SYS_PTRACE: Self = Self(19);
+    pub const SYS_PACCT: Self = Self(20);
+    pub const SYS_ADMIN: Self = Self(21);
+    pub const SYS_BOOT: Self = Self(22);
+    pub const SYS_NICE: Self = Self(23);
+    pub const SYS_RESOURCE: Self = Self(24);
+    pub const SYS_TIME: Self = Self(25);
+    pub const SYS_TTY_CONFIG: Self = Self(26);
+    pub const MKNOD: Self = Self(27);
+    pub const LEASE: Self = Self(28);
+    pub const AUDIT_WRITE: Self = Self(29);
+    pub const AUDIT_CONTROL: Self = Self(30);
+    pub const SETFCAP: Self = Self(31);
+    pub const MAC_OVERRIDE: Self = Self(32);
+    pub const MAC_ADMIN: Self = Self(33);
+    pub const SYSLOG: Self = Self(34);
+    pub const WAKE_ALARM: Self = Self(35);
+    pub const BLOCK_SUSPEND: Self = Self(36);
+    pub const AUDIT_READ: Self = Self(37);
+    pub const PERFMON: Self = Self(38);
+    pub const BPF: Self = Self(39);
+    pub const CHECKPOINT_RESTORE: Self = Self(40);
+
+    fn as_c_ulong(&self) -> c_ulong {
+        self.0.try_into().unwrap()
+    }
+}
+
+/// A set of capabilities, like the ones passed to capset(2).
+#[derive(BitOr, BitOrAssign, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CapabilitySet(u64);
+
+impl CapabilitySet {
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.0 |= 1 << capability.0;
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0 & (1 << capability.0) != 0
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
 #[derive(Clone)]
 #[repr(transparent)]
 pub struct CloneArgs(libc::clone_args);
@@ -94,6 +169,7 @@ impl CloneFlags {
     pub const NEWNS: Self = Self(libc::CLONE_NEWNS);
     pub const NEWPID: Self = Self(libc::CLONE_NEWPID);
     pub const NEWUSER: Self = Self(libc::CLONE_NEWUSER);
+    pub const NEWUTS: Self = Self(libc::CLONE_NEWUTS);
     pub const VM: Self = Self(libc::CLONE_VM);
     pub const VFORK: Self = Self(libc::CLONE_VFORK);
 
@@ -199,6 +275,10 @@ impl Errno {
     pub const EPIPE: Self = Self(libc::EPIPE);
     pub const EDOM: Self = Self(libc::EDOM);
     pub const ERANGE: Self = Self(libc::ERANGE);
+    pub const ENODATA: Self = Self(libc::ENODATA);
+    pub const EOPNOTSUPP: Self = Self(libc::EOPNOTSUPP);
+    pub const ECONNREFUSED: Self = Self(libc::ECONNREFUSED);
+    pub const ETIMEDOUT: Self = Self(libc::ETIMEDOUT);
     pub const EWOULDBLOCK: Self = Self::EAGAIN;
 }
 
@@ -296,7 +376,7 @@ impl Fd {
         self.0.try_into().unwrap()
     }
 
-    pub fn from_raw(raw: c_int) -> Self {
+    pub const fn from_raw(raw: c_int) -> Self {
         Self(raw)
     }
 
@@ -365,6 +445,7 @@ impl FsopenFlags {
 }
 
 #[derive(Clone, Copy, Display)]
+#[repr(transparent)]
 pub struct Gid(gid_t);
 
 impl Gid {
@@ -438,6 +519,7 @@ pub struct OpenFlags(c_int);
 impl OpenFlags {
     pub const RDWR: Self = Self(libc::O_RDWR);
     pub const WRONLY: Self = Self(libc::O_WRONLY);
+    pub const CREAT: Self = Self(libc::O_CREAT);
     pub const TRUNC: Self = Self(libc::O_TRUNC);
     pub const NONBLOCK: Self = Self(libc::O_NONBLOCK);
     pub const NOCTTY: Self = Self(libc::O_NOCTTY);
@@ -794,6 +876,41 @@ impl Deref for SockaddrStorage {
     }
 }
 
+#[repr(C)]
+pub struct SockaddrIn {
+    sin_family: sa_family_t,
+    sin_port: u16,
+    sin_addr: u32,
+    sin_zero: [u8; 8],
+}
+
+impl SockaddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            sin_family: libc::AF_INET as sa_family_t,
+            sin_port: port.to_be(),
+            sin_addr: u32::from_ne_bytes(addr),
+            sin_zero: [0; 8],
+        }
+    }
+
+    pub fn loopback(port: u16) -> Self {
+        Self::new([127, 0, 0, 1], port)
+    }
+}
+
+impl Deref for SockaddrIn {
+    type Target = Sockaddr;
+    fn deref(&self) -> &Self::Target {
+        unsafe {
+            Sockaddr::from_raw_parts(
+                self as *const SockaddrIn as *const sockaddr,
+                mem::size_of_val(self),
+            )
+        }
+    }
+}
+
 #[repr(C)]
 pub struct SockaddrNetlink {
     sin_family: sa_family_t,
@@ -931,6 +1048,7 @@ impl std::error::Error for SockaddrUnStoragePathTooLongError {}
 pub struct SocketDomain(c_int);
 
 impl SocketDomain {
+    pub const INET: Self = Self(libc::PF_INET);
     pub const NETLINK: Self = Self(libc::PF_NETLINK);
     pub const UNIX: Self = Self(libc::PF_UNIX);
 }
@@ -1053,6 +1171,58 @@ pub fn bind(fd: &impl AsFd, sockaddr: &Sockaddr) -> Result<(), Errno> {
     Errno::result(unsafe { libc::bind(fd.0, addr, len) }).map(drop)
 }
 
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+/// Returns the effective, permitted, and inheritable capability sets of the calling thread.
+pub fn capget() -> Result<(CapabilitySet, CapabilitySet, CapabilitySet), Errno> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+    Errno::result(unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) })?;
+    let combine = |f: fn(&CapUserData) -> u32| {
+        CapabilitySet(u64::from(f(&data[0])) | u64::from(f(&data[1])) << 32)
+    };
+    Ok((
+        combine(|d| d.effective),
+        combine(|d| d.permitted),
+        combine(|d| d.inheritable),
+    ))
+}
+
+/// Sets the effective, permitted, and inheritable capability sets of the calling thread.
+pub fn capset(
+    effective: CapabilitySet,
+    permitted: CapabilitySet,
+    inheritable: CapabilitySet,
+) -> Result<(), Errno> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [0, 32].map(|shift| CapUserData {
+        effective: (effective.0 >> shift) as u32,
+        permitted: (permitted.0 >> shift) as u32,
+        inheritable: (inheritable.0 >> shift) as u32,
+    });
+    Errno::result(unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) }).map(drop)
+}
+
 pub fn chdir(path: &CStr) -> Result<(), Errno> {
     let path_ptr = path.as_ptr();
     Errno::result(unsafe { libc::chdir(path_ptr) }).map(drop)
@@ -1332,16 +1502,59 @@ pub fn getuid() -> Uid {
     Uid(unsafe { libc::getuid() })
 }
 
+pub fn getxattr(path: &CStr, name: &CStr, value: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let name_ptr = name.as_ptr();
+    let value_ptr = value.as_mut_ptr() as *mut c_void;
+    Errno::result(unsafe { libc::getxattr(path_ptr, name_ptr, value_ptr, value.len()) })
+        .map(|ret| ret as usize)
+}
+
 pub fn grantpt(fd: &impl AsFd) -> Result<(), Errno> {
     let fd = fd.fd();
     Errno::result(unsafe { libc::grantpt(fd.0) }).map(drop)
 }
 
+/// Register `backing` with the FUSE connection `fd` as a backing file for passthrough opens.
+/// Returns the backing id to pass along in the open reply.
+pub fn ioctl_fuse_dev_backing_open(fd: &impl AsFd, backing: &impl AsFd) -> Result<u32, Errno> {
+    #[repr(C)]
+    struct FuseBackingMap {
+        fd: i32,
+        flags: u32,
+        padding: u64,
+    }
+    // _IOW(229, 1, struct fuse_backing_map)
+    const FUSE_DEV_IOC_BACKING_OPEN: libc::Ioctl = 0x4010e501;
+    let fd = fd.fd();
+    let map = FuseBackingMap {
+        fd: backing.fd().0,
+        flags: 0,
+        padding: 0,
+    };
+    let backing_id = Errno::result(unsafe { libc::ioctl(fd.0, FUSE_DEV_IOC_BACKING_OPEN, &map) })?;
+    Ok(backing_id as u32)
+}
+
+/// Remove a backing file registered with [`ioctl_fuse_dev_backing_open`].
+pub fn ioctl_fuse_dev_backing_close(fd: &impl AsFd, backing_id: u32) -> Result<(), Errno> {
+    // _IOW(229, 2, uint32_t)
+    const FUSE_DEV_IOC_BACKING_CLOSE: libc::Ioctl = 0x4004e502;
+    let fd = fd.fd();
+    Errno::result(unsafe { libc::ioctl(fd.0, FUSE_DEV_IOC_BACKING_CLOSE, &backing_id) }).map(drop)
+}
+
 pub fn ioctl_tiocsctty(fd: &impl AsFd, arg: i32) -> Result<(), Errno> {
     let fd = fd.fd();
     Errno::result(unsafe { libc::ioctl(fd.0, libc::TIOCSCTTY, arg as c_int) }).map(drop)
 }
 
+pub fn ioctl_tiocspgrp(fd: &impl AsFd, pgrp: Pid) -> Result<(), Errno> {
+    let fd = fd.fd();
+    let pgrp: pid_t = pgrp.0;
+    Errno::result(unsafe { libc::ioctl(fd.0, libc::TIOCSPGRP, &pgrp) }).map(drop)
+}
+
 pub fn ioctl_tiocgwinsz(fd: &impl AsFd) -> Result<(u16, u16), Errno> {
     let fd = fd.fd();
     let mut winsize: libc::winsize = unsafe { mem::zeroed() };
@@ -1364,11 +1577,33 @@ pub fn kill(pid: Pid, signal: Signal) -> Result<(), Errno> {
     Errno::result(unsafe { libc::kill(pid.0, signal.0) }).map(drop)
 }
 
+pub fn lgetxattr(path: &CStr, name: &CStr, value: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let name_ptr = name.as_ptr();
+    let value_ptr = value.as_mut_ptr() as *mut c_void;
+    Errno::result(unsafe { libc::lgetxattr(path_ptr, name_ptr, value_ptr, value.len()) })
+        .map(|ret| ret as usize)
+}
+
 pub fn listen(fd: &impl AsFd, backlog: u32) -> Result<(), Errno> {
     let fd = fd.fd();
     Errno::result(unsafe { libc::listen(fd.0, backlog as c_int) }).map(drop)
 }
 
+pub fn listxattr(path: &CStr, list: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let list_ptr = list.as_mut_ptr() as *mut c_char;
+    Errno::result(unsafe { libc::listxattr(path_ptr, list_ptr, list.len()) })
+        .map(|ret| ret as usize)
+}
+
+pub fn llistxattr(path: &CStr, list: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let list_ptr = list.as_mut_ptr() as *mut c_char;
+    Errno::result(unsafe { libc::llistxattr(path_ptr, list_ptr, list.len()) })
+        .map(|ret| ret as usize)
+}
+
 pub fn lseek(fd: &impl AsFd, offset: i64, whence: Whence) -> Result<i64, Errno> {
     let fd = fd.fd();
     Errno::result(unsafe { libc::lseek(fd.0, offset, whence.as_i32()) })
@@ -1379,6 +1614,13 @@ pub fn mkdir(path: &CStr, mode: FileMode) -> Result<(), Errno> {
     Errno::result(unsafe { libc::mkdir(path_ptr, mode.0) }).map(drop)
 }
 
+/// Create an overlayfs whiteout at `path`: a character device with device number 0/0. This doesn't
+/// need any privileges on Linux 5.8 and later.
+pub fn mknod_whiteout(path: &CStr) -> Result<(), Errno> {
+    let path_ptr = path.as_ptr();
+    Errno::result(unsafe { libc::mknod(path_ptr, libc::S_IFCHR, 0) }).map(drop)
+}
+
 // N.B. From the man page description I don't think the kernel actually dereferences the pointer, so we
 // don't have to worry about marking this function "unsafe".
 //
@@ -1509,6 +1751,25 @@ pub fn posix_openpt(flags: OpenFlags) -> Result<OwnedFd, Errno> {
     Ok(fd)
 }
 
+pub fn prctl_cap_ambient_raise(capability: Capability) -> Result<(), Errno> {
+    let capability = capability.as_c_ulong();
+    Errno::result(unsafe {
+        libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_RAISE as c_ulong,
+            capability,
+            0 as c_ulong,
+            0 as c_ulong,
+        )
+    })
+    .map(drop)
+}
+
+pub fn prctl_capbset_drop(capability: Capability) -> Result<(), Errno> {
+    let capability = capability.as_c_ulong();
+    Errno::result(unsafe { libc::prctl(libc::PR_CAPBSET_DROP, capability) }).map(drop)
+}
+
 pub fn prctl_set_pdeathsig(signal: Signal) -> Result<(), Errno> {
     let signal = signal.as_c_ulong();
     Errno::result(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, signal) }).map(drop)
@@ -1539,6 +1800,29 @@ pub fn read(fd: &impl AsFd, buf: &mut [u8]) -> Result<usize, Errno> {
     Errno::result(unsafe { libc::read(fd.0, buf_ptr, buf_len) }).map(|ret| ret as usize)
 }
 
+pub fn sendfile(out_fd: &impl AsFd, in_fd: &impl AsFd, count: usize) -> Result<usize, Errno> {
+    let out_fd = out_fd.fd();
+    let in_fd = in_fd.fd();
+    Errno::result(unsafe { libc::sendfile(out_fd.0, in_fd.0, ptr::null_mut(), count) })
+        .map(|ret| ret as usize)
+}
+
+pub fn setgroups(groups: &[Gid]) -> Result<(), Errno> {
+    let groups_ptr = groups.as_ptr() as *const gid_t;
+    let groups_len = groups.len();
+    Errno::result(unsafe { libc::setgroups(groups_len, groups_ptr) }).map(drop)
+}
+
+pub fn sethostname(name: &[u8]) -> Result<(), Errno> {
+    let name_ptr = name.as_ptr() as *const c_char;
+    let name_len = name.len();
+    Errno::result(unsafe { libc::sethostname(name_ptr, name_len) }).map(drop)
+}
+
+pub fn setpgid(pid: Pid, pgid: Pid) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::setpgid(pid.0, pgid.0) }).map(drop)
+}
+
 pub fn setsid() -> Result<(), Errno> {
     Errno::result(unsafe { libc::setsid() }).map(drop)
 }
@@ -1574,6 +1858,14 @@ fn setsockopt_u32(sock: &impl AsFd, level: c_int, name: c_int, value: u32) -> Re
     .map(drop)
 }
 
+pub fn setxattr(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Errno> {
+    let path_ptr = path.as_ptr();
+    let name_ptr = name.as_ptr();
+    let value_ptr = value.as_ptr() as *const c_void;
+    Errno::result(unsafe { libc::setxattr(path_ptr, name_ptr, value_ptr, value.len(), 0) })
+        .map(drop)
+}
+
 pub fn sigprocmask(how: SigprocmaskHow, set: Option<&SignalSet>) -> Result<SignalSet, Errno> {
     let set: *const sigset_t = set.map(|s| &s.0 as *const sigset_t).unwrap_or(ptr::null());
     let mut oldset: MaybeUninit<sigset_t> = MaybeUninit::uninit();
@@ -1638,6 +1930,20 @@ pub fn wait() -> Result<WaitResult, Errno> {
     })
 }
 
+pub fn wait_nohang() -> Result<Option<WaitResult>, Errno> {
+    let inner = |status: &mut c_int| {
+        let status_ptr = status as *mut c_int;
+        unsafe { libc::waitpid(-1, status_ptr, libc::WNOHANG) }
+    };
+    let mut status = 0;
+    Errno::result(inner(&mut status)).map(|pid| {
+        (pid != 0).then(|| WaitResult {
+            pid: Pid(pid),
+            status: extract_wait_status(status),
+        })
+    })
+}
+
 pub fn waitpid(pid: Pid) -> Result<WaitStatus, Errno> {
     let inner = |status: &mut c_int| {
         let status_ptr = status as *mut c_int;
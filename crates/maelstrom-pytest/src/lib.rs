@@ -5,11 +5,11 @@ mod pytest;
 
 use anyhow::{anyhow, bail, Result};
 use maelstrom_base::{
-    enum_set, JobDevice, JobMount, JobNetwork, JobOutcome, JobRootOverlay, JobTerminationStatus,
-    Timeout, Utf8PathBuf,
+    enum_set, JobDevice, JobNetwork, JobOutcome, JobRootOverlay, JobTerminationStatus, Timeout,
+    Utf8PathBuf,
 };
 use maelstrom_client::{
-    spec::{LayerSpec, PrefixOptions},
+    spec::{JobMount, LayerSpec, PrefixOptions},
     AcceptInvalidRemoteContainerTlsCerts, CacheDir, Client, ClientBgProcess,
     ContainerImageDepotDir, ImageSpec, JobSpec, ProjectDir, StateDir,
 };
@@ -239,6 +239,8 @@ impl<'client> PytestTestCollector<'client> {
                     prepend_prefix: Some("/etc/".into()),
                     canonicalize: false,
                     follow_symlinks: false,
+                    chmod: None,
+                    chown: None,
                 },
             },
         ];
@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Error, Result};
 use clap::Args;
 use maelstrom_base::{
+    proto::{BrokerToRelay, Hello, RelaySide},
     tty, ClientJobId, JobCompleted, JobEffects, JobError, JobOutcome, JobOutcomeResult,
     JobOutputResult, JobTerminationStatus, JobTty, WindowSize,
 };
@@ -17,16 +18,16 @@ use maelstrom_run::{
 use maelstrom_util::{
     config::common::{BrokerAddr, CacheSize, InlineLimit, LogLevel, Slots},
     fs::Fs,
-    log,
+    log, net,
     process::{ExitCode, ExitCodeAccumulator},
     root::{Root, RootBuf},
 };
 use slog::Logger;
 use std::{
     env,
-    io::{self, IsTerminal as _, Read, Stdin, Write as _},
+    io::{self, IsTerminal as _, Read, Stdin, Write},
     mem,
-    net::Shutdown,
+    net::{Shutdown, TcpStream},
     os::unix::net::{UnixListener, UnixStream},
     path::PathBuf,
     slice,
@@ -333,7 +334,7 @@ fn one_main(client: Client, job_spec: JobSpec) -> Result<ExitCode> {
 enum TtyMainMessage {
     Error(Error),
     JobCompleted(JobOutcomeResult),
-    JobConnected(UnixStream, UnixStream),
+    JobConnected(TtySocket, TtySocket),
     JobOutput([u8; 1024], usize),
     Signal(Signal),
 }
@@ -383,20 +384,86 @@ fn tty_job_main(
         .map_err(Error::new)
 }
 
-/// The listener thread listens on the socket for a connection, then sends the result to the main
-/// thread.
+/// How we wait for the job to connect to its TTY. If there is no broker, the job will be run on the
+/// local worker, which will connect directly to our Unix domain socket. Otherwise, both we and the
+/// worker connect to a TTY relay on the broker, which tells us when the job has connected.
+enum TtyListener {
+    Local(linux::OwnedFd),
+    Relay(TcpStream),
+}
+
+/// The connection to the job's TTY.
+enum TtySocket {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl TtySocket {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Unix(sock) => Self::Unix(sock.try_clone()?),
+            Self::Tcp(sock) => Self::Tcp(sock.try_clone()?),
+        })
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::Unix(sock) => sock.shutdown(how),
+            Self::Tcp(sock) => sock.shutdown(how),
+        }
+    }
+}
+
+impl Read for TtySocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(sock) => sock.read(buf),
+            Self::Tcp(sock) => sock.read(buf),
+        }
+    }
+}
+
+impl Write for TtySocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(sock) => sock.write(buf),
+            Self::Tcp(sock) => sock.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Unix(sock) => sock.flush(),
+            Self::Tcp(sock) => sock.flush(),
+        }
+    }
+}
+
+/// The listener thread waits for the job to connect, then sends the result to the main thread.
 ///
 /// If the thread an error writing to the sender, it ignores it, as it means the main thread has
 /// gone away.
-fn tty_listener_main(sock: linux::OwnedFd, sender: SyncSender<TtyMainMessage>) -> Result<()> {
-    fn inner(sock: linux::OwnedFd) -> Result<(UnixStream, UnixStream)> {
-        let listener = UnixListener::from(sock);
-        let sock = listener.accept()?.0;
+fn tty_listener_main(
+    listener: TtyListener,
+    sender: SyncSender<TtyMainMessage>,
+    log: Logger,
+) -> Result<()> {
+    fn inner(listener: TtyListener, log: &Logger) -> Result<(TtySocket, TtySocket)> {
+        let sock = match listener {
+            TtyListener::Local(sock) => {
+                let listener = UnixListener::from(sock);
+                TtySocket::Unix(listener.accept()?.0)
+            }
+            TtyListener::Relay(mut sock) => {
+                let BrokerToRelay::Connected = net::read_message_from_socket(&mut sock, log)?;
+                TtySocket::Tcp(sock)
+            }
+        };
         let sock_clone = sock.try_clone()?;
         Ok((sock, sock_clone))
     }
     sender
-        .send(match inner(sock) {
+        .send(match inner(listener, &log) {
             Ok((sock1, sock2)) => TtyMainMessage::JobConnected(sock1, sock2),
             Err(err) => TtyMainMessage::Error(err.context("job connecting")),
         })
@@ -413,7 +480,7 @@ fn tty_listener_main(sock: linux::OwnedFd, sender: SyncSender<TtyMainMessage>) -
 ///
 /// If the thread ever encounters an error writing to the sender, it exits, as it means the main
 /// thread has gone away.
-fn tty_socket_reader_main(sender: SyncSender<TtyMainMessage>, mut sock: UnixStream) -> Result<()> {
+fn tty_socket_reader_main(sender: SyncSender<TtyMainMessage>, mut sock: TtySocket) -> Result<()> {
     let mut bytes = [0u8; 1024];
     loop {
         match sock.read(&mut bytes) {
@@ -543,7 +610,7 @@ fn tty_socket_writer_main(
     job_input_receiver: Receiver<TtyJobInputMessage>,
     sender: SyncSender<TtyMainMessage>,
     sigwinch_pending: Arc<AtomicBool>,
-    mut sock: UnixStream,
+    mut sock: TtySocket,
 ) -> Result<()> {
     // We block sigpipe so we don't kill the process if the socket is closed out from under us. In
     // that case, we should just exit the thread.
@@ -634,12 +701,34 @@ impl Drop for RawModeKeeper {
 fn tty_main(
     blocked_signals: SignalSet,
     client: Client,
+    broker: Option<BrokerAddr>,
     escape_char: EscapeChar,
     mut job_spec: JobSpec,
+    log: Logger,
 ) -> Result<ExitCode> {
     let (rows, columns) = linux::ioctl_tiocgwinsz(&Fd::STDIN)?;
-    let (sock, addr) = linux::autobound_unix_listener(Default::default(), 1)?;
-    job_spec.allocate_tty = Some(JobTty::new(&addr, WindowSize::new(rows, columns)));
+    let window_size = WindowSize::new(rows, columns);
+    let listener = match broker {
+        None => {
+            let (sock, addr) = linux::autobound_unix_listener(Default::default(), 1)?;
+            job_spec.allocate_tty = Some(JobTty::new(&addr, window_size));
+            TtyListener::Local(sock)
+        }
+        Some(broker) => {
+            let key: [u8; 16] = rand::random();
+            let mut sock = TcpStream::connect(broker.inner())?;
+            net::write_message_to_socket(
+                &mut sock,
+                Hello::Relay {
+                    key,
+                    side: RelaySide::Client,
+                },
+                &log,
+            )?;
+            job_spec.allocate_tty = Some(JobTty::relay(key, window_size));
+            TtyListener::Relay(sock)
+        }
+    };
 
     print!("Waiting for job to start...");
     io::stdout().flush()?;
@@ -653,7 +742,7 @@ fn tty_main(
     thread::spawn(move || tty_job_main(client, job_spec, sender_clone));
 
     let sender_clone = sender.clone();
-    thread::spawn(move || tty_listener_main(sock, sender_clone));
+    thread::spawn(move || tty_listener_main(listener, sender_clone, log));
 
     let mut raw_mode_keeper = RawModeKeeper::default();
     let sigwinch_pending = Arc::new(AtomicBool::new(false));
@@ -760,7 +849,7 @@ fn main_with_logger(
         config.inline_limit,
         config.slots,
         config.accept_invalid_remote_container_tls_certs,
-        log,
+        log.clone(),
     )?;
     let mut job_specs = spec::job_spec_iter_from_reader(reader);
     if extra_options.one_or_tty.any() {
@@ -783,7 +872,14 @@ fn main_with_logger(
         if extra_options.one_or_tty.tty {
             // Re-block the signals for the local thread.
             linux::pthread_sigmask(SigprocmaskHow::BLOCK, Some(&blocked_signals))?;
-            tty_main(blocked_signals, client, config.escape_char, job_spec)
+            tty_main(
+                blocked_signals,
+                client,
+                config.broker,
+                config.escape_char,
+                job_spec,
+                log,
+            )
         } else {
             one_main(client, job_spec)
         }
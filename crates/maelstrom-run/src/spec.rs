@@ -1,11 +1,12 @@
 use anyhow::{anyhow, Result};
 use maelstrom_base::{
-    GroupId, JobMountForTomlAndJson, JobNetwork, JobRootOverlay, NonEmpty, Timeout, UserId,
-    Utf8PathBuf,
+    GroupId, JobCapabilitiesForTomlAndJson, JobHostsEntry, JobNetwork, JobRootOverlay, NonEmpty,
+    Timeout, UserId, Utf8PathBuf,
 };
 use maelstrom_client::spec::{
-    incompatible, ContainerSpec, EnvironmentSpec, Image, ImageSpec, ImageUse, IntoEnvironment,
-    JobSpec, LayerSpec, PossiblyImage,
+    incompatible, ByteSizeForTomlAndJson, ContainerSpec, EnvironmentSpec, Image, ImageSpec,
+    ImageUse, IntoEnvironment, JobMountForTomlAndJson, JobSecretForTomlAndJson, JobSecretSpec,
+    JobSidecarForTomlAndJson, JobSidecarSpec, JobSpec, LayerSpec, PossiblyImage,
 };
 use serde::de::Error as _;
 use serde::{de, Deserialize, Deserializer};
@@ -46,12 +47,21 @@ struct Job {
     mounts: Option<Vec<JobMountForTomlAndJson>>,
     network: Option<JobNetwork>,
     enable_writable_file_system: Option<bool>,
+    writable_file_system_size: Option<ByteSizeForTomlAndJson>,
     working_directory: Option<PossiblyImage<Utf8PathBuf>>,
     user: Option<UserId>,
     group: Option<GroupId>,
+    groups: Option<Vec<GroupId>>,
+    synthesize_user_files: Option<bool>,
+    hostname: Option<String>,
+    hosts: Option<Vec<JobHostsEntry>>,
+    capabilities: Option<JobCapabilitiesForTomlAndJson>,
+    init: Option<bool>,
     image: Option<String>,
     timeout: Option<u32>,
     priority: Option<i8>,
+    sidecars: Option<Vec<JobSidecarSpec>>,
+    secrets: Option<Vec<JobSecretSpec>>,
 }
 
 impl Job {
@@ -67,12 +77,21 @@ impl Job {
             mounts: None,
             network: None,
             enable_writable_file_system: None,
+            writable_file_system_size: None,
             working_directory: None,
             user: None,
             group: None,
+            groups: None,
+            synthesize_user_files: None,
+            hostname: None,
+            hosts: None,
+            capabilities: None,
+            init: None,
             image: None,
             timeout: None,
             priority: None,
+            sidecars: None,
+            secrets: None,
         }
     }
 
@@ -118,13 +137,21 @@ impl Job {
                 .collect(),
             network: self.network.unwrap_or_default(),
             root_overlay: if self.enable_writable_file_system.unwrap_or_default() {
-                JobRootOverlay::Tmp
+                JobRootOverlay::Tmp {
+                    size: self.writable_file_system_size.map(Into::into),
+                }
             } else {
                 JobRootOverlay::None
             },
             working_directory,
             user: self.user,
             group: self.group,
+            groups: self.groups.unwrap_or_default(),
+            synthesize_user_files: self.synthesize_user_files.unwrap_or_default(),
+            hostname: self.hostname,
+            hosts: self.hosts.unwrap_or_default(),
+            capabilities: self.capabilities.unwrap_or_default().into(),
+            init: self.init.unwrap_or_default(),
         }
         .into();
         Ok(JobSpec {
@@ -135,6 +162,8 @@ impl Job {
             estimated_duration: None,
             allocate_tty: None,
             priority: self.priority.unwrap_or_default(),
+            sidecars: self.sidecars.unwrap_or_default(),
+            secrets: self.secrets.unwrap_or_default(),
         })
     }
 }
@@ -150,12 +179,21 @@ enum JobField {
     Mounts,
     Network,
     EnableWritableFileSystem,
+    WritableFileSystemSize,
     WorkingDirectory,
     User,
     Group,
+    Groups,
+    SynthesizeUserFiles,
+    Hostname,
+    Hosts,
+    Capabilities,
+    Init,
     Image,
     Timeout,
     Priority,
+    Sidecars,
+    Secrets,
 }
 
 struct JobVisitor;
@@ -211,12 +249,21 @@ impl<'de> de::Visitor<'de> for JobVisitor {
         let mut mounts = None;
         let mut network = None;
         let mut enable_writable_file_system = None;
+        let mut writable_file_system_size = None;
         let mut working_directory = None;
         let mut user = None;
         let mut group = None;
+        let mut groups = None;
+        let mut synthesize_user_files = None;
+        let mut hostname = None;
+        let mut hosts = None;
+        let mut capabilities = None;
+        let mut init = None;
         let mut image = None;
         let mut timeout = None;
         let mut priority = None;
+        let mut sidecars = None;
+        let mut secrets = None;
         while let Some(key) = map.next_key()? {
             match key {
                 JobField::Program => {
@@ -267,6 +314,9 @@ impl<'de> de::Visitor<'de> for JobVisitor {
                 JobField::EnableWritableFileSystem => {
                     enable_writable_file_system = Some(map.next_value()?);
                 }
+                JobField::WritableFileSystemSize => {
+                    writable_file_system_size = Some(map.next_value()?);
+                }
                 JobField::WorkingDirectory => {
                     incompatible(
                         &working_directory,
@@ -283,12 +333,48 @@ impl<'de> de::Visitor<'de> for JobVisitor {
                 JobField::Group => {
                     group = Some(map.next_value()?);
                 }
+                JobField::Groups => {
+                    groups = Some(map.next_value()?);
+                }
+                JobField::SynthesizeUserFiles => {
+                    synthesize_user_files = Some(map.next_value()?);
+                }
+                JobField::Hostname => {
+                    hostname = Some(map.next_value()?);
+                }
+                JobField::Hosts => {
+                    hosts = Some(map.next_value()?);
+                }
+                JobField::Capabilities => {
+                    capabilities = Some(map.next_value()?);
+                }
+                JobField::Init => {
+                    init = Some(map.next_value()?);
+                }
                 JobField::Timeout => {
                     timeout = Some(map.next_value()?);
                 }
                 JobField::Priority => {
                     priority = Some(map.next_value()?);
                 }
+                JobField::Sidecars => {
+                    sidecars = Some(
+                        map.next_value::<Vec<JobSidecarForTomlAndJson>>()?
+                            .into_iter()
+                            .map(TryInto::try_into)
+                            .collect::<Result<_>>()
+                            .map_err(A::Error::custom)?,
+                    );
+                }
+                JobField::Secrets => {
+                    secrets = Some(
+                        map.next_value::<Vec<JobSecretForTomlAndJson>>()?
+                            .into_iter()
+                            .map(TryInto::try_into)
+                            .collect::<Result<_>>()
+                            .map_err(A::Error::custom)?,
+                    );
+                }
                 JobField::Image => {
                     let i = map.next_value::<Image>()?;
                     image = Some(i.name);
@@ -338,12 +424,21 @@ impl<'de> de::Visitor<'de> for JobVisitor {
             mounts,
             network,
             enable_writable_file_system,
+            writable_file_system_size,
             working_directory,
             user,
             group,
+            groups,
+            synthesize_user_files,
+            hostname,
+            hosts,
+            capabilities,
+            init,
             image,
             timeout,
             priority,
+            sidecars,
+            secrets,
         })
     }
 }
@@ -360,9 +455,14 @@ impl<'de> de::Deserialize<'de> for Job {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use maelstrom_base::{enum_set, nonempty, JobDevice, JobDeviceForTomlAndJson, JobMount};
+    use maelstrom_base::{
+        enum_set, nonempty, JobCapabilities, JobCapability, JobDevice, JobDeviceForTomlAndJson,
+        JobSecretDestination,
+    };
+    use maelstrom_client::spec::{JobMount, JobSecretSource, PrefixOptions};
     use maelstrom_test::{non_root_utf8_path_buf, string, string_vec, tar_layer, utf8_path_buf};
     use maplit::btreemap;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn minimum_into_job_spec() {
@@ -383,6 +483,11 @@ mod tests {
                 mounts: Some(vec![
                     JobMountForTomlAndJson::Tmp {
                         mount_point: non_root_utf8_path_buf!("/tmp"),
+                        size: None,
+                        mode: None,
+                        uid: None,
+                        gid: None,
+                        nr_inodes: None,
                     },
                     JobMountForTomlAndJson::Devices {
                         devices: enum_set! {JobDeviceForTomlAndJson::Null},
@@ -401,6 +506,11 @@ mod tests {
                 .mounts([
                     JobMount::Tmp {
                         mount_point: utf8_path_buf!("/tmp"),
+                        size: None,
+                        mode: None,
+                        uid: None,
+                        gid: None,
+                        nr_inodes: None,
                     },
                     JobMount::Devices {
                         devices: enum_set! {JobDevice::Null},
@@ -470,7 +580,8 @@ mod tests {
             }
             .into_job_spec()
             .unwrap(),
-            JobSpec::new("program", vec![tar_layer!("1")]).root_overlay(JobRootOverlay::Tmp),
+            JobSpec::new("program", vec![tar_layer!("1")])
+                .root_overlay(JobRootOverlay::Tmp { size: None }),
         );
     }
 
@@ -924,7 +1035,14 @@ mod tests {
             .unwrap(),
             JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")])
                 .mounts([
-                    JobMount::Tmp { mount_point: utf8_path_buf!("/tmp") },
+                    JobMount::Tmp {
+                        mount_point: utf8_path_buf!("/tmp"),
+                        size: None,
+                        mode: None,
+                        uid: None,
+                        gid: None,
+                        nr_inodes: None,
+                    },
                     JobMount::Bind {
                         mount_point: utf8_path_buf!("/bind"),
                         local_path: utf8_path_buf!("/a"),
@@ -944,6 +1062,48 @@ mod tests {
         )
     }
 
+    #[test]
+    fn layer_mounts() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "mounts": [
+                        { "type": "layer", "mount_point": "/data", "layer": { "tar": "2" } },
+                        {
+                            "type": "layer",
+                            "mount_point": "/src",
+                            "layer": { "glob": "src/**", "prepend_prefix": "/" },
+                            "read_only": true
+                        }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).mounts([
+                JobMount::Layer {
+                    mount_point: utf8_path_buf!("/data"),
+                    layer: tar_layer!("2"),
+                    read_only: false,
+                },
+                JobMount::Layer {
+                    mount_point: utf8_path_buf!("/src"),
+                    layer: LayerSpec::Glob {
+                        glob: string!("src/**"),
+                        prefix_options: PrefixOptions {
+                            prepend_prefix: Some(utf8_path_buf!("/")),
+                            ..Default::default()
+                        },
+                    },
+                    read_only: true,
+                },
+            ])
+        )
+    }
+
     #[test]
     fn foo() {
         assert_eq!(
@@ -975,7 +1135,55 @@ mod tests {
             .into_job_spec()
             .unwrap(),
             JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")])
-                .root_overlay(JobRootOverlay::Tmp),
+                .root_overlay(JobRootOverlay::Tmp { size: None }),
+        )
+    }
+
+    #[test]
+    fn writable_file_system_size() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "enable_writable_file_system": true,
+                    "writable_file_system_size": "1 MiB"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).root_overlay(
+                JobRootOverlay::Tmp {
+                    size: Some(1024 * 1024)
+                }
+            ),
+        )
+    }
+
+    #[test]
+    fn tmp_mount_options() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "mounts": [
+                        { "type": "tmp", "mount_point": "/tmp", "size": 4096, "mode": 448, "uid": 1, "gid": 2, "nr_inodes": 10 }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).mounts([JobMount::Tmp {
+                mount_point: utf8_path_buf!("/tmp"),
+                size: Some(4096),
+                mode: Some(0o700),
+                uid: Some(UserId::from(1)),
+                gid: Some(GroupId::from(2)),
+                nr_inodes: Some(10),
+            }]),
         )
     }
 
@@ -1097,6 +1305,117 @@ mod tests {
         )
     }
 
+    #[test]
+    fn groups() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "groups": [ 100, 200 ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).groups([100, 200]),
+        )
+    }
+
+    #[test]
+    fn synthesize_user_files() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "synthesize_user_files": true
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).synthesize_user_files(true),
+        )
+    }
+
+    #[test]
+    fn hostname() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "hostname": "job.local"
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).hostname(Some("job.local")),
+        )
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "hosts": [
+                        { "address": "127.0.0.1", "names": [ "db.local", "db" ] },
+                        { "address": "::1", "names": [ "cache.local" ] }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).hosts([
+                JobHostsEntry::new(Ipv4Addr::LOCALHOST, ["db.local", "db"]),
+                JobHostsEntry::new(Ipv6Addr::LOCALHOST, ["cache.local"]),
+            ]),
+        )
+    }
+
+    #[test]
+    fn capabilities() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "capabilities": { "add": [ "NET_RAW" ], "drop": [ "SYS_ADMIN" ] }
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).capabilities(JobCapabilities {
+                add: enum_set!(JobCapability::NetRaw),
+                drop: enum_set!(JobCapability::SysAdmin),
+            }),
+        )
+    }
+
+    #[test]
+    fn init() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "init": true
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).init(true),
+        )
+    }
+
     #[test]
     fn timeout() {
         assert_eq!(
@@ -1147,4 +1466,118 @@ mod tests {
             JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).priority(-42),
         )
     }
+
+    #[test]
+    fn sidecars() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "sidecars": [
+                        {
+                            "program": "/bin/redis-server",
+                            "arguments": [ "--port", "6379" ],
+                            "layers": [ { "tar": "2" } ],
+                            "environment": { "FOO": "foo" },
+                            "working_directory": "/data",
+                            "ready_port": 6379,
+                            "ready_timeout": 10
+                        },
+                        {
+                            "program": "mock-server",
+                            "image": "mock"
+                        }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).sidecars([
+                JobSidecarSpec::new(
+                    "/bin/redis-server",
+                    JobSpec::new("/bin/redis-server", vec![tar_layer!("2")])
+                        .environment([("FOO", "foo")])
+                        .working_directory(Some("/data"))
+                        .container,
+                )
+                .arguments(["--port", "6379"])
+                .ready_port(Some(6379))
+                .ready_timeout(Timeout::new(10)),
+                JobSidecarSpec::new(
+                    "mock-server",
+                    JobSpec::new("mock-server", vec![])
+                        .image(ImageSpec {
+                            name: string!("mock"),
+                            use_layers: true,
+                            use_environment: true,
+                            use_working_directory: false,
+                        })
+                        .container,
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    fn sidecar_without_layers() {
+        assert_error(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "sidecars": [ { "program": "/bin/redis-server" } ]
+                }"#,
+            )
+            .unwrap_err(),
+            "sidecar must have `layers` or an `image` with a `use` of `layers`",
+        )
+    }
+
+    #[test]
+    fn secrets() {
+        assert_eq!(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "secrets": [
+                        { "variable": "API_TOKEN", "from_env": "API_TOKEN" },
+                        { "file": "/root/.netrc", "from_file": "/home/me/.netrc" }
+                    ]
+                }"#,
+            )
+            .unwrap()
+            .into_job_spec()
+            .unwrap(),
+            JobSpec::new(string!("/bin/sh"), vec![tar_layer!("1")]).secrets([
+                JobSecretSpec::new(
+                    JobSecretSource::Environment(string!("API_TOKEN")),
+                    JobSecretDestination::Environment(string!("API_TOKEN")),
+                ),
+                JobSecretSpec::new(
+                    JobSecretSource::File(utf8_path_buf!("/home/me/.netrc")),
+                    JobSecretDestination::File(utf8_path_buf!("/root/.netrc")),
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    fn secret_with_two_destinations() {
+        assert_error(
+            parse_job(
+                r#"{
+                    "program": "/bin/sh",
+                    "layers": [ { "tar": "1" } ],
+                    "secrets": [
+                        { "variable": "API_TOKEN", "file": "/token", "from_env": "API_TOKEN" }
+                    ]
+                }"#,
+            )
+            .unwrap_err(),
+            "secret must have exactly one of `variable` or `file`",
+        )
+    }
 }
@@ -231,13 +231,21 @@ impl<'deps, DepsT: Deps> MainApp<'deps, DepsT> {
             mounts: test_metadata.container.mounts,
             network: test_metadata.container.network,
             root_overlay: if test_metadata.container.enable_writable_file_system {
-                JobRootOverlay::Tmp
+                JobRootOverlay::Tmp {
+                    size: test_metadata.container.writable_file_system_size,
+                }
             } else {
                 JobRootOverlay::None
             },
             working_directory: test_metadata.container.working_directory,
             user: test_metadata.container.user,
             group: test_metadata.container.group,
+            groups: test_metadata.container.groups,
+            synthesize_user_files: test_metadata.container.synthesize_user_files,
+            hostname: test_metadata.container.hostname,
+            hosts: test_metadata.container.hosts,
+            capabilities: test_metadata.container.capabilities,
+            init: test_metadata.container.init,
         }
         .into();
         let spec = JobSpec {
@@ -251,6 +259,8 @@ impl<'deps, DepsT: Deps> MainApp<'deps, DepsT> {
             estimated_duration,
             allocate_tty: None,
             priority,
+            sidecars: test_metadata.sidecars,
+            secrets: test_metadata.secrets,
         };
 
         let job_id = self.vend_job_id();
@@ -14,11 +14,11 @@ use anyhow::anyhow;
 use itertools::Itertools as _;
 use maelstrom_base::{
     nonempty, ClientJobId, JobBrokerStatus, JobCompleted, JobDevice, JobEffects, JobError,
-    JobMount, JobNetwork, JobOutcome, JobOutputResult, JobRootOverlay, JobTerminationStatus,
-    JobWorkerStatus, NonEmpty,
+    JobNetwork, JobOutcome, JobOutputResult, JobRootOverlay, JobTerminationStatus, JobWorkerStatus,
+    NonEmpty,
 };
 use maelstrom_client::{
-    spec::{ContainerRef, ContainerSpec, JobSpec, LayerSpec},
+    spec::{ContainerRef, ContainerSpec, JobMount, JobSpec, LayerSpec},
     JobRunningStatus, JobStatus,
 };
 use maelstrom_simex::SimulationExplorer;
@@ -33,7 +33,7 @@ use TestMessage::*;
 enum TestMessage {
     AddJob {
         job_id: JobId,
-        spec: JobSpec,
+        spec: Box<JobSpec>,
     },
     ListTests {
         artifact: FakeTestArtifact,
@@ -82,7 +82,10 @@ impl Deps for TestDeps {
 
     fn add_job(&self, job_id: JobId, spec: JobSpec) {
         let mut self_ = self.0.borrow_mut();
-        self_.messages.push(TestMessage::AddJob { job_id, spec });
+        self_.messages.push(TestMessage::AddJob {
+            job_id,
+            spec: Box::new(spec),
+        });
     }
 
     fn list_tests(&self, artifact: FakeTestArtifact) {
@@ -423,11 +426,13 @@ fn test_spec(bin: &str, name: &str) -> JobSpec {
         estimated_duration: None,
         allocate_tty: None,
         priority: 1,
+        sidecars: vec![],
+        secrets: vec![],
     }
 }
 
 fn default_container() -> ContainerRef {
-    ContainerRef::Inline(ContainerSpec {
+    ContainerRef::Inline(Box::new(ContainerSpec {
         image: None,
         layers: vec![LayerSpec::Stubs {
             stubs: vec![
@@ -441,6 +446,11 @@ fn default_container() -> ContainerRef {
         mounts: vec![
             JobMount::Tmp {
                 mount_point: "/tmp".into(),
+                size: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                nr_inodes: None,
             },
             JobMount::Proc {
                 mount_point: "/proc".into(),
@@ -459,7 +469,13 @@ fn default_container() -> ContainerRef {
         network: JobNetwork::Disabled,
         user: None,
         group: None,
-    })
+        groups: vec![],
+        synthesize_user_files: false,
+        hostname: None,
+        hosts: vec![],
+        capabilities: Default::default(),
+        init: false,
+    }))
 }
 
 macro_rules! test_output_test_inner {
@@ -504,7 +520,7 @@ macro_rules! test_output_test_inner {
             } => {
                 AddJob {
                     job_id: JobId::from(1),
-                    spec: test_spec("foo_test", "test_a"),
+                    spec: Box::new(test_spec("foo_test", "test_a")),
                 },
                 SendUiMsg {
                     msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -626,7 +642,7 @@ macro_rules! test_db_test {
             } => {
                 AddJob {
                     job_id: JobId::from(1),
-                    spec: $test_a_job_spec,
+                    spec: Box::new($test_a_job_spec),
                 },
                 SendUiMsg {
                     msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -636,7 +652,7 @@ macro_rules! test_db_test {
                 },
                 AddJob {
                     job_id: JobId::from(2),
-                    spec: $test_b_job_spec,
+                    spec: Box::new($test_b_job_spec),
                 },
                 SendUiMsg {
                     msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1529,7 +1545,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1542,7 +1558,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1622,7 +1638,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1635,7 +1651,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1710,7 +1726,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1794,7 +1810,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1877,7 +1893,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -1948,7 +1964,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("bar_test", "test_a"),
+            spec: Box::new(test_spec("bar_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2023,7 +2039,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("bar_test", "test_a"),
+            spec: Box::new(test_spec("bar_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2119,11 +2135,11 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: JobSpec {
+            spec: Box::new(JobSpec {
                 estimated_duration: Some(Duration::from_secs(1)),
                 priority: 0,
                 ..test_spec("foo_test", "test_a")
-            }
+            })
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2133,11 +2149,11 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: JobSpec {
+            spec: Box::new(JobSpec {
                 estimated_duration: Some(Duration::from_secs(1)),
                 priority: 0,
                 ..test_spec("foo_test", "test_b")
-            }
+            })
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2314,7 +2330,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2327,7 +2343,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2444,7 +2460,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2458,7 +2474,7 @@ script_test_with_error_simex! {
 
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2472,7 +2488,7 @@ script_test_with_error_simex! {
 
         AddJob {
             job_id: JobId::from(3),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2486,7 +2502,7 @@ script_test_with_error_simex! {
 
         AddJob {
             job_id: JobId::from(4),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2586,7 +2602,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2600,7 +2616,7 @@ script_test_with_error_simex! {
 
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2683,7 +2699,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2696,7 +2712,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2789,7 +2805,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2802,7 +2818,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2873,7 +2889,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2952,7 +2968,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -2965,7 +2981,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3054,7 +3070,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3064,7 +3080,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3134,7 +3150,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3206,7 +3222,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3219,7 +3235,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3232,7 +3248,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(3),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3307,7 +3323,7 @@ script_test_with_error_simex! {
     } => {
         AddJob {
             job_id: JobId::from(1),
-            spec: test_spec("foo_test", "test_a"),
+            spec: Box::new(test_spec("foo_test", "test_a")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -3320,7 +3336,7 @@ script_test_with_error_simex! {
         },
         AddJob {
             job_id: JobId::from(2),
-            spec: test_spec("foo_test", "test_b"),
+            spec: Box::new(test_spec("foo_test", "test_b")),
         },
         SendUiMsg {
             msg: UiMessage::JobEnqueued(UiJobEnqueued {
@@ -5,9 +5,14 @@ use crate::TestFilter;
 use anyhow::{anyhow, Context as _, Result};
 use container::{NamedTestContainer, TestContainer};
 use directive::TestDirective;
-use maelstrom_base::{GroupId, JobMount, JobNetwork, Timeout, UserId, Utf8PathBuf};
+use maelstrom_base::{
+    GroupId, JobCapabilities, JobHostsEntry, JobNetwork, Timeout, UserId, Utf8PathBuf,
+};
 use maelstrom_client::{
-    spec::{EnvironmentSpec, ImageSpec, LayerSpec, PossiblyImage},
+    spec::{
+        EnvironmentSpec, ImageSpec, JobMount, JobSecretSpec, JobSidecarSpec, LayerSpec,
+        PossiblyImage,
+    },
     ProjectDir,
 };
 use maelstrom_util::{fs::Fs, root::Root, template::TemplateVars};
@@ -31,9 +36,16 @@ pub struct TestMetadataContainer {
     pub image: Option<ImageSpec>,
     pub network: JobNetwork,
     pub enable_writable_file_system: bool,
+    pub writable_file_system_size: Option<u64>,
     pub working_directory: Option<Utf8PathBuf>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
+    pub groups: Vec<GroupId>,
+    pub synthesize_user_files: bool,
+    pub hostname: Option<String>,
+    pub hosts: Vec<JobHostsEntry>,
+    pub capabilities: JobCapabilities,
+    pub init: bool,
     pub layers: Vec<LayerSpec>,
     pub environment: Vec<EnvironmentSpec>,
     pub mounts: Vec<JobMount>,
@@ -52,8 +64,26 @@ impl TestMetadataContainer {
         self.enable_writable_file_system = container
             .enable_writable_file_system
             .unwrap_or(self.enable_writable_file_system);
+        self.writable_file_system_size = container
+            .writable_file_system_size
+            .map(Into::into)
+            .or(self.writable_file_system_size);
         self.user = container.user.or(self.user);
         self.group = container.group.or(self.group);
+        if let Some(groups) = &container.groups {
+            self.groups = groups.clone();
+        }
+        self.synthesize_user_files = container
+            .synthesize_user_files
+            .unwrap_or(self.synthesize_user_files);
+        self.hostname = container.hostname.clone().or(self.hostname);
+        if let Some(hosts) = &container.hosts {
+            self.hosts = hosts.clone();
+        }
+        if let Some(capabilities) = &container.capabilities {
+            self.capabilities = capabilities.clone().into();
+        }
+        self.init = container.init.unwrap_or(self.init);
 
         match &container.layers {
             Some(PossiblyImage::Explicit(layers)) => {
@@ -120,6 +150,8 @@ pub struct TestMetadata {
     include_shared_libraries: Option<bool>,
     pub timeout: Option<Timeout>,
     pub ignore: bool,
+    pub sidecars: Vec<JobSidecarSpec>,
+    pub secrets: Vec<JobSecretSpec>,
 }
 
 impl TestMetadata {
@@ -145,6 +177,12 @@ impl TestMetadata {
             .or(self.include_shared_libraries);
         self.timeout = directive.timeout.unwrap_or(self.timeout);
         self.ignore = directive.ignore.unwrap_or(self.ignore);
+        if let Some(sidecars) = &directive.sidecars {
+            self.sidecars = sidecars.clone();
+        }
+        if let Some(secrets) = &directive.secrets {
+            self.secrets = secrets.clone();
+        }
 
         Ok(self)
     }
@@ -175,6 +213,19 @@ where
             for added_layer in &mut directive.container.added_layers {
                 added_layer.replace_template_vars(vars)?;
             }
+            for mount in directive.container.mounts.iter_mut().flatten() {
+                mount.replace_template_vars(vars)?;
+            }
+            for added_mount in &mut directive.container.added_mounts {
+                added_mount.replace_template_vars(vars)?;
+            }
+            for sidecar in directive.sidecars.iter_mut().flatten() {
+                if let Some(container) = sidecar.container.as_inline_mut() {
+                    for layer in &mut container.layers {
+                        layer.replace_template_vars(vars)?;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -559,6 +610,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn writable_file_system_size() {
+        let all = AllMetadata::<SimpleFilter>::from_str(
+            r#"
+            [[directives]]
+            filter = "package = \"package1\""
+            enable_writable_file_system = true
+            writable_file_system_size = "1 MiB"
+
+            [[directives]]
+            filter = "and = [{ package = \"package1\" }, { name = \"test1\" }]"
+            writable_file_system_size = 4096
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            all.get_metadata_for_test(
+                &"package1".into(),
+                &"package1".into(),
+                ("test1", &NoCaseMetadata)
+            )
+            .unwrap()
+            .container
+            .writable_file_system_size,
+            Some(4096)
+        );
+        assert_eq!(
+            all.get_metadata_for_test(
+                &"package1".into(),
+                &"package1".into(),
+                ("test2", &NoCaseMetadata)
+            )
+            .unwrap()
+            .container
+            .writable_file_system_size,
+            Some(1024 * 1024)
+        );
+        assert_eq!(
+            all.get_metadata_for_test(
+                &"package2".into(),
+                &"package2".into(),
+                ("test1", &NoCaseMetadata)
+            )
+            .unwrap()
+            .container
+            .writable_file_system_size,
+            None
+        );
+    }
+
     #[test]
     fn working_directory() {
         let all = AllMetadata::<SimpleFilter>::from_str(
@@ -731,6 +832,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sidecars() {
+        let all = AllMetadata::<SimpleFilter>::from_str(
+            r#"
+            [[directives]]
+            filter = "package = \"package1\""
+            sidecars = [{ program = "/bin/server", layers = [{ tar = "1" }] }]
+
+            [[directives]]
+            filter = "and = [{ package = \"package1\" }, { name = \"test1\" }]"
+            sidecars = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            all.get_metadata_for_test(
+                &"package1".into(),
+                &"package1".into(),
+                ("test1", &NoCaseMetadata)
+            )
+            .unwrap()
+            .sidecars,
+            vec![],
+        );
+        assert_eq!(
+            all.get_metadata_for_test(
+                &"package1".into(),
+                &"package1".into(),
+                ("test2", &NoCaseMetadata)
+            )
+            .unwrap()
+            .sidecars,
+            vec![JobSidecarSpec::new(
+                "/bin/server",
+                maelstrom_client::spec::JobSpec::new("/bin/server", vec![tar_layer!("1")])
+                    .container
+            )],
+        );
+    }
+
     #[test]
     fn timeout() {
         let all = AllMetadata::<SimpleFilter>::from_str(
@@ -1299,6 +1440,11 @@ mod tests {
             vec![
                 JobMount::Tmp {
                     mount_point: utf8_path_buf!("/tmp"),
+                    size: None,
+                    mode: None,
+                    uid: None,
+                    gid: None,
+                    nr_inodes: None,
                 },
                 JobMount::Sys {
                     mount_point: utf8_path_buf!("/sys"),
@@ -1393,6 +1539,11 @@ mod tests {
                 },
                 JobMount::Tmp {
                     mount_point: utf8_path_buf!("/tmp"),
+                    size: None,
+                    mode: None,
+                    uid: None,
+                    gid: None,
+                    nr_inodes: None,
                 },
             ],
         );
@@ -1414,6 +1565,11 @@ mod tests {
                 },
                 JobMount::Tmp {
                     mount_point: utf8_path_buf!("/tmp"),
+                    size: None,
+                    mode: None,
+                    uid: None,
+                    gid: None,
+                    nr_inodes: None,
                 },
                 JobMount::Bind {
                     mount_point: utf8_path_buf!("/foo"),
@@ -1432,7 +1588,12 @@ mod tests {
             .container
             .mounts,
             vec![JobMount::Tmp {
-                mount_point: utf8_path_buf!("/tmp")
+                mount_point: utf8_path_buf!("/tmp"),
+                size: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                nr_inodes: None,
             }],
         );
         assert_eq!(
@@ -1445,7 +1606,12 @@ mod tests {
             .container
             .mounts,
             vec![JobMount::Tmp {
-                mount_point: utf8_path_buf!("/tmp")
+                mount_point: utf8_path_buf!("/tmp"),
+                size: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                nr_inodes: None,
             }],
         );
     }
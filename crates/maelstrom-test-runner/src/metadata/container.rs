@@ -1,7 +1,12 @@
 #![allow(unused_imports)]
 use anyhow::Result;
-use maelstrom_base::{GroupId, JobMountForTomlAndJson, JobNetwork, Timeout, UserId, Utf8PathBuf};
-use maelstrom_client::spec::{incompatible, Image, ImageUse, LayerSpec, PossiblyImage};
+use maelstrom_base::{
+    GroupId, JobCapabilitiesForTomlAndJson, JobHostsEntry, JobNetwork, Timeout, UserId, Utf8PathBuf,
+};
+use maelstrom_client::spec::{
+    incompatible, ByteSizeForTomlAndJson, Image, ImageUse, JobMountForTomlAndJson, LayerSpec,
+    PossiblyImage,
+};
 use serde::{de, Deserialize, Deserializer};
 use std::{
     collections::BTreeMap,
@@ -15,8 +20,15 @@ pub enum ContainerField {
     Name,
     Network,
     EnableWritableFileSystem,
+    WritableFileSystemSize,
     User,
     Group,
+    Groups,
+    SynthesizeUserFiles,
+    Hostname,
+    Hosts,
+    Capabilities,
+    Init,
     Mounts,
     AddedMounts,
     Image,
@@ -33,8 +45,15 @@ pub struct TestContainer {
     pub image: Option<String>,
     pub network: Option<JobNetwork>,
     pub enable_writable_file_system: Option<bool>,
+    pub writable_file_system_size: Option<ByteSizeForTomlAndJson>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
+    pub groups: Option<Vec<GroupId>>,
+    pub synthesize_user_files: Option<bool>,
+    pub hostname: Option<String>,
+    pub hosts: Option<Vec<JobHostsEntry>>,
+    pub capabilities: Option<JobCapabilitiesForTomlAndJson>,
+    pub init: Option<bool>,
     pub layers: Option<PossiblyImage<Vec<LayerSpec>>>,
     pub added_layers: Vec<LayerSpec>,
     pub mounts: Option<Vec<JobMountForTomlAndJson>>,
@@ -56,8 +75,15 @@ pub struct TestContainerVisitor {
     image: Option<String>,
     network: Option<JobNetwork>,
     enable_writable_file_system: Option<bool>,
+    writable_file_system_size: Option<ByteSizeForTomlAndJson>,
     user: Option<UserId>,
     group: Option<GroupId>,
+    groups: Option<Vec<GroupId>>,
+    synthesize_user_files: Option<bool>,
+    hostname: Option<String>,
+    hosts: Option<Vec<JobHostsEntry>>,
+    capabilities: Option<JobCapabilitiesForTomlAndJson>,
+    init: Option<bool>,
     layers: Option<PossiblyImage<Vec<LayerSpec>>>,
     added_layers: Option<Vec<LayerSpec>>,
     mounts: Option<Vec<JobMountForTomlAndJson>>,
@@ -82,12 +108,33 @@ impl TestContainerVisitor {
             ContainerField::EnableWritableFileSystem => {
                 self.enable_writable_file_system = Some(map.next_value()?);
             }
+            ContainerField::WritableFileSystemSize => {
+                self.writable_file_system_size = Some(map.next_value()?);
+            }
             ContainerField::User => {
                 self.user = Some(map.next_value()?);
             }
             ContainerField::Group => {
                 self.group = Some(map.next_value()?);
             }
+            ContainerField::Groups => {
+                self.groups = Some(map.next_value()?);
+            }
+            ContainerField::SynthesizeUserFiles => {
+                self.synthesize_user_files = Some(map.next_value()?);
+            }
+            ContainerField::Hostname => {
+                self.hostname = Some(map.next_value()?);
+            }
+            ContainerField::Hosts => {
+                self.hosts = Some(map.next_value()?);
+            }
+            ContainerField::Capabilities => {
+                self.capabilities = Some(map.next_value()?);
+            }
+            ContainerField::Init => {
+                self.init = Some(map.next_value()?);
+            }
             ContainerField::Mounts => {
                 incompatible(
                     &self.added_mounts,
@@ -179,8 +226,15 @@ impl TestContainerVisitor {
             image: self.image,
             network: self.network,
             enable_writable_file_system: self.enable_writable_file_system,
+            writable_file_system_size: self.writable_file_system_size,
             user: self.user,
             group: self.group,
+            groups: self.groups,
+            synthesize_user_files: self.synthesize_user_files,
+            hostname: self.hostname,
+            hosts: self.hosts,
+            capabilities: self.capabilities,
+            init: self.init,
             layers: self.layers,
             added_layers: self.added_layers.unwrap_or_default(),
             mounts: self.mounts,
@@ -1,8 +1,11 @@
 #![allow(unused_imports)]
 use super::container::{ContainerField, TestContainer, TestContainerVisitor};
 use anyhow::Result;
-use maelstrom_base::{GroupId, JobMountForTomlAndJson, JobNetwork, Timeout, UserId, Utf8PathBuf};
-use maelstrom_client::spec::{incompatible, Image, ImageUse, LayerSpec, PossiblyImage};
+use maelstrom_base::{GroupId, JobHostsEntry, JobNetwork, Timeout, UserId, Utf8PathBuf};
+use maelstrom_client::spec::{
+    incompatible, Image, ImageUse, JobMountForTomlAndJson, JobSecretForTomlAndJson, JobSecretSpec,
+    JobSidecarForTomlAndJson, JobSidecarSpec, LayerSpec, PossiblyImage,
+};
 use serde::{de, Deserialize, Deserializer};
 use std::{
     collections::BTreeMap,
@@ -17,6 +20,8 @@ pub struct TestDirective<TestFilterT> {
     pub include_shared_libraries: Option<bool>,
     pub timeout: Option<Option<Timeout>>,
     pub ignore: Option<bool>,
+    pub sidecars: Option<Vec<JobSidecarSpec>>,
+    pub secrets: Option<Vec<JobSecretSpec>>,
 }
 
 // The derived Default will put a TestFilterT: Default bound on the implementation
@@ -28,6 +33,8 @@ impl<TestFilterT> Default for TestDirective<TestFilterT> {
             include_shared_libraries: None,
             timeout: None,
             ignore: None,
+            sidecars: None,
+            secrets: None,
         }
     }
 }
@@ -39,10 +46,19 @@ enum DirectiveField {
     IncludeSharedLibraries,
     Timeout,
     Ignore,
+    Sidecars,
+    Secrets,
     Network,
     EnableWritableFileSystem,
+    WritableFileSystemSize,
     User,
     Group,
+    Groups,
+    SynthesizeUserFiles,
+    Hostname,
+    Hosts,
+    Capabilities,
+    Init,
     Mounts,
     AddedMounts,
     Image,
@@ -60,10 +76,19 @@ impl DirectiveField {
             Self::IncludeSharedLibraries => None,
             Self::Timeout => None,
             Self::Ignore => None,
+            Self::Sidecars => None,
+            Self::Secrets => None,
             Self::Network => Some(ContainerField::Network),
             Self::EnableWritableFileSystem => Some(ContainerField::EnableWritableFileSystem),
+            Self::WritableFileSystemSize => Some(ContainerField::WritableFileSystemSize),
             Self::User => Some(ContainerField::User),
             Self::Group => Some(ContainerField::Group),
+            Self::Groups => Some(ContainerField::Groups),
+            Self::SynthesizeUserFiles => Some(ContainerField::SynthesizeUserFiles),
+            Self::Hostname => Some(ContainerField::Hostname),
+            Self::Hosts => Some(ContainerField::Hosts),
+            Self::Capabilities => Some(ContainerField::Capabilities),
+            Self::Init => Some(ContainerField::Init),
             Self::Mounts => Some(ContainerField::Mounts),
             Self::AddedMounts => Some(ContainerField::AddedMounts),
             Self::Image => Some(ContainerField::Image),
@@ -115,6 +140,24 @@ where
             DirectiveField::Ignore => {
                 self.value.ignore = Some(map.next_value()?);
             }
+            DirectiveField::Sidecars => {
+                self.value.sidecars = Some(
+                    map.next_value::<Vec<JobSidecarForTomlAndJson>>()?
+                        .into_iter()
+                        .map(TryInto::try_into)
+                        .collect::<Result<_>>()
+                        .map_err(de::Error::custom)?,
+                );
+            }
+            DirectiveField::Secrets => {
+                self.value.secrets = Some(
+                    map.next_value::<Vec<JobSecretForTomlAndJson>>()?
+                        .into_iter()
+                        .map(TryInto::try_into)
+                        .collect::<Result<_>>()
+                        .map_err(de::Error::custom)?,
+                );
+            }
             c => {
                 self.container_visitor
                     .fill_entry(c.into_container_field().unwrap(), map)?;
@@ -165,12 +208,18 @@ mod tests {
     use crate::metadata::container::NamedTestContainer;
     use anyhow::Error;
     use indoc::indoc;
-    use maelstrom_base::{enum_set, JobDeviceForTomlAndJson};
-    use maelstrom_client::spec::SymlinkSpec;
+    use maelstrom_base::{
+        enum_set, JobCapabilitiesForTomlAndJson, JobCapabilityForTomlAndJson,
+        JobDeviceForTomlAndJson, JobSecretDestination,
+    };
+    use maelstrom_client::spec::{
+        FileContents, FileOwner, FileSpec, JobSecretSource, PrefixOptions, SymlinkSpec,
+    };
     use maelstrom_test::{
         glob_layer, non_root_utf8_path_buf, paths_layer, so_deps_layer, string, tar_layer,
         utf8_path_buf,
     };
+    use std::net::{Ipv4Addr, Ipv6Addr};
     use toml::de::Error as TomlError;
 
     fn parse_test_directive(file: &str) -> Result<TestDirective<String>> {
@@ -272,6 +321,163 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sidecars() {
+        directive_parse_test(
+            r#"
+            [[sidecars]]
+            program = "/usr/bin/redis-server"
+            arguments = ["--port", "6379"]
+            image = { name = "redis", use = ["layers", "environment"] }
+            ready_port = 6379
+            ready_timeout = 10
+            "#,
+            TestDirective {
+                sidecars: Some(vec![JobSidecarSpec::new(
+                    "/usr/bin/redis-server",
+                    maelstrom_client::spec::JobSpec::new("/usr/bin/redis-server", vec![])
+                        .image(maelstrom_client::spec::ImageSpec {
+                            name: string!("redis"),
+                            use_layers: true,
+                            use_environment: true,
+                            use_working_directory: false,
+                        })
+                        .container,
+                )
+                .arguments(["--port", "6379"])
+                .ready_port(Some(6379))
+                .ready_timeout(Timeout::new(10))]),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn sidecar_without_layers() {
+        directive_error_test(
+            r#"
+            [[sidecars]]
+            program = "/usr/bin/redis-server"
+            "#,
+            "sidecar must have `layers` or an `image` with a `use` of `layers`",
+        );
+    }
+
+    #[test]
+    fn secrets() {
+        directive_parse_test(
+            r#"
+            secrets = [
+                { variable = "API_TOKEN", from_env = "CI_API_TOKEN" },
+                { file = "/etc/ssl/private/key.pem", from_file = "secrets/key.pem" },
+            ]
+            "#,
+            TestDirective {
+                secrets: Some(vec![
+                    JobSecretSpec::new(
+                        JobSecretSource::Environment(string!("CI_API_TOKEN")),
+                        JobSecretDestination::Environment(string!("API_TOKEN")),
+                    ),
+                    JobSecretSpec::new(
+                        JobSecretSource::File(utf8_path_buf!("secrets/key.pem")),
+                        JobSecretDestination::File(utf8_path_buf!("/etc/ssl/private/key.pem")),
+                    ),
+                ]),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn secret_without_source() {
+        directive_error_test(
+            r#"
+            secrets = [{ variable = "API_TOKEN" }]
+            "#,
+            "secret must have exactly one of `from_env` or `from_file`",
+        );
+    }
+
+    #[test]
+    fn groups_and_synthesize_user_files() {
+        directive_or_container_parse_test(
+            r#"
+            groups = [100, 200]
+            synthesize_user_files = true
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    groups: Some(vec![GroupId::from(100), GroupId::from(200)]),
+                    synthesize_user_files: Some(true),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn hostname_and_hosts() {
+        directive_or_container_parse_test(
+            r#"
+            hostname = "test-host"
+            hosts = [
+                { address = "127.0.0.2", names = ["db.local", "db"] },
+                { address = "::1", names = ["cache.local"] },
+            ]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    hostname: Some("test-host".into()),
+                    hosts: Some(vec![
+                        JobHostsEntry::new(Ipv4Addr::new(127, 0, 0, 2), ["db.local", "db"]),
+                        JobHostsEntry::new(Ipv6Addr::LOCALHOST, ["cache.local"]),
+                    ]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn capabilities() {
+        directive_or_container_parse_test(
+            r#"
+            capabilities = { add = ["NET_RAW"], drop = ["SYS_ADMIN", "MKNOD"] }
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    capabilities: Some(JobCapabilitiesForTomlAndJson {
+                        add: enum_set!(JobCapabilityForTomlAndJson::NetRaw),
+                        drop: enum_set!(
+                            JobCapabilityForTomlAndJson::SysAdmin
+                                | JobCapabilityForTomlAndJson::Mknod
+                        ),
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn init() {
+        directive_or_container_parse_test(
+            r#"
+            init = true
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    init: Some(true),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
     #[test]
     fn zero_timeout() {
         directive_parse_test(
@@ -379,6 +585,11 @@ mod tests {
                     }]),
                     added_mounts: vec![JobMountForTomlAndJson::Tmp {
                         mount_point: non_root_utf8_path_buf!("/tmp"),
+                        size: None,
+                        mode: None,
+                        uid: None,
+                        gid: None,
+                        nr_inodes: None,
                     }],
                     ..Default::default()
                 },
@@ -852,6 +1063,178 @@ mod tests {
         );
     }
 
+    #[test]
+    fn layers_git() {
+        directive_or_container_parse_test(
+            r#"
+            layers = [
+                { git = "crates/foo" },
+                { git = "crates/bar", revision = "HEAD~1", strip_prefix = "crates/" },
+                { git = ".", untracked = true },
+            ]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    layers: Some(PossiblyImage::Explicit(vec![
+                        LayerSpec::Git {
+                            path: "crates/foo".into(),
+                            revision: None,
+                            untracked: false,
+                            prefix_options: Default::default(),
+                        },
+                        LayerSpec::Git {
+                            path: "crates/bar".into(),
+                            revision: Some("HEAD~1".into()),
+                            untracked: false,
+                            prefix_options: PrefixOptions {
+                                strip_prefix: Some("crates/".into()),
+                                ..Default::default()
+                            },
+                        },
+                        LayerSpec::Git {
+                            path: ".".into(),
+                            revision: None,
+                            untracked: true,
+                            prefix_options: Default::default(),
+                        },
+                    ])),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn layers_archive() {
+        directive_or_container_parse_test(
+            r#"
+            layers = [
+                { archive = "toolchain.tar.zst" },
+                { archive = "fixtures.zip", strip_prefix = "fixtures/", prepend_prefix = "/data/" },
+            ]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    layers: Some(PossiblyImage::Explicit(vec![
+                        LayerSpec::Archive {
+                            path: "toolchain.tar.zst".into(),
+                            prefix_options: Default::default(),
+                        },
+                        LayerSpec::Archive {
+                            path: "fixtures.zip".into(),
+                            prefix_options: PrefixOptions {
+                                strip_prefix: Some("fixtures/".into()),
+                                prepend_prefix: Some("/data/".into()),
+                                ..Default::default()
+                            },
+                        },
+                    ])),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn layers_command() {
+        directive_or_container_parse_test(
+            r#"
+            layers = [
+                { command = ["make", "docs"], output = "target/docs", inputs = ["docs"] },
+                { command = ["pip", "install", "foo"], output = "/usr/local", image = "python", layers = [{ stubs = ["/tmp/"] }] },
+            ]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    layers: Some(PossiblyImage::Explicit(vec![
+                        LayerSpec::Command {
+                            command: vec!["make".into(), "docs".into()],
+                            output: "target/docs".into(),
+                            inputs: vec!["docs".into()],
+                            image: None,
+                            layers: vec![],
+                            prefix_options: Default::default(),
+                        },
+                        LayerSpec::Command {
+                            command: vec!["pip".into(), "install".into(), "foo".into()],
+                            output: "/usr/local".into(),
+                            inputs: vec![],
+                            image: Some("python".into()),
+                            layers: vec![LayerSpec::Stubs {
+                                stubs: vec!["/tmp/".into()],
+                            }],
+                            prefix_options: Default::default(),
+                        },
+                    ])),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn layers_files() {
+        directive_or_container_parse_test(
+            r#"
+            layers = [
+                { files = [
+                    { path = "/etc/foo.conf", contents = "foo = 1\n" },
+                    { path = "/bin/bar", base64 = "IyEvYmluL3NoCg==", mode = 0o755 },
+                ] },
+            ]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    layers: Some(PossiblyImage::Explicit(vec![LayerSpec::Files {
+                        files: vec![
+                            FileSpec {
+                                path: "/etc/foo.conf".into(),
+                                contents: FileContents::Contents("foo = 1\n".into()),
+                                mode: None,
+                            },
+                            FileSpec {
+                                path: "/bin/bar".into(),
+                                contents: FileContents::Base64("IyEvYmluL3NoCg==".into()),
+                                mode: Some(0o755),
+                            },
+                        ],
+                    }])),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn layers_chmod_and_chown() {
+        directive_or_container_parse_test(
+            r#"
+            layers = [{ glob = "*.bin", chmod = 0o644, chown = "1000:2000" }]
+            "#,
+            TestDirective {
+                container: TestContainer {
+                    layers: Some(PossiblyImage::Explicit(vec![LayerSpec::Glob {
+                        glob: "*.bin".into(),
+                        prefix_options: PrefixOptions {
+                            chmod: Some(0o644),
+                            chown: Some(FileOwner {
+                                user: UserId::new(1000),
+                                group: GroupId::new(2000),
+                            }),
+                            ..Default::default()
+                        },
+                    }])),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
     #[test]
     fn image_with_layers() {
         directive_or_container_parse_test(
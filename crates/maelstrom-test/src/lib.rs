@@ -261,6 +261,8 @@ macro_rules! glob_layer {
                 prepend_prefix: $prepend_prefix,
                 canonicalize: $canonicalize,
                 follow_symlinks: $follow_symlinks,
+                chmod: None,
+                chown: None,
             },
         }
     };
@@ -327,6 +329,8 @@ macro_rules! paths_layer {
                 prepend_prefix: $prepend_prefix,
                 canonicalize: $canonicalize,
                 follow_symlinks: $follow_symlinks,
+                chmod: None,
+                chown: None,
             },
         }
     };
@@ -408,6 +412,8 @@ macro_rules! so_deps_layer {
                 prepend_prefix: $prepend_prefix,
                 canonicalize: $canonicalize,
                 follow_symlinks: $follow_symlinks,
+                chmod: None,
+                chown: None,
             },
         }
     };
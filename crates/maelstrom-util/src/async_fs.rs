@@ -3,6 +3,7 @@ pub use crate::fs::{GetPath, Metadata};
 use anyhow::{Context as _, Result};
 use fs2::FileExt as _;
 use futures_lite::stream::StreamExt;
+use maelstrom_base::manifest::Xattrs;
 use std::{
     ffi::OsString,
     io,
@@ -173,6 +174,19 @@ impl Fs {
         })
     }
 
+    /// Read all of the extended attributes of the file at the given path, following symlinks.
+    pub async fn xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Xattrs> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || crate::fs::Fs::new().xattrs(path)).await?
+    }
+
+    /// Read all of the extended attributes of the file at the given path, without following
+    /// symlinks.
+    pub async fn symlink_xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Xattrs> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || crate::fs::Fs::new().symlink_xattrs(path)).await?
+    }
+
     pub async fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
         fs_trampoline!(tokio::fs::rename, from, to)
     }
@@ -622,6 +622,37 @@ impl<FsT: Fs, KeyKindT: KeyKind, GetStrategyT: GetStrategy> Cache<FsT, KeyKindT,
         }
     }
 
+    /// Take a reference to `artifact` if it's already in the cache, without getting it if it isn't.
+    /// Return whether a reference was taken.
+    #[must_use]
+    pub fn get_artifact_if_present(&mut self, kind: KeyKindT, digest: &Sha256Digest) -> bool {
+        let Some(entry) = self.entries.get_mut(&Key::new(kind, digest.clone())) else {
+            return false;
+        };
+        match entry {
+            Entry::Getting { .. } => false,
+            Entry::InUse { ref_count, .. } => {
+                *ref_count = ref_count.checked_add(1).unwrap();
+                true
+            }
+            Entry::InHeap {
+                file_type,
+                bytes_used,
+                heap_index,
+                ..
+            } => {
+                let heap_index = *heap_index;
+                *entry = Entry::InUse {
+                    file_type: *file_type,
+                    ref_count: NonZeroU32::new(1).unwrap(),
+                    bytes_used: *bytes_used,
+                };
+                self.heap.remove(&mut self.entries, heap_index);
+                true
+            }
+        }
+    }
+
     /// Notify the cache that an artifact fetch has failed. The returned vector lists the jobs that
     /// are affected and that need to be canceled.
     pub fn got_artifact_failure(&mut self, kind: KeyKindT, digest: &Sha256Digest) -> Vec<JobId> {
@@ -821,6 +852,12 @@ impl<FsT: Fs, KeyKindT: KeyKind, GetStrategyT: GetStrategy> Cache<FsT, KeyKindT,
         });
     }
 
+    /// Return whether the cache is over its goal size. This only stays true when the cache can't
+    /// remove any more artifacts because they are all in use.
+    pub fn is_over_target(&self) -> bool {
+        self.bytes_used > self.bytes_used_target
+    }
+
     /// Return the directory path for the artifact referenced by `digest`.
     pub fn cache_path(&self, kind: KeyKindT, digest: &Sha256Digest) -> RootBuf<EntryPath> {
         let kind_dir: RootBuf<KindDir> = self.sha256.join(kind.to_string());
@@ -840,30 +877,35 @@ impl<FsT: Fs, KeyKindT: KeyKind, GetStrategyT: GetStrategy> Cache<FsT, KeyKindT,
             let Some(key) = self.heap.pop(&mut self.entries) else {
                 break;
             };
-            let Some(Entry::InHeap {
-                file_type,
-                bytes_used,
-                ..
-            }) = self.entries.remove(&key)
-            else {
-                panic!("Entry popped off of heap was in unexpected state");
-            };
-            let cache_path = self.cache_path(key.kind, &key.digest);
-            if file_type == FileType::Directory {
-                rmdir_in_background(&self.fs, &self.removing, &cache_path)?;
-                self.fs.remove(&size_file_name(&cache_path))?;
-            } else {
-                self.fs.remove(&cache_path)?;
-            }
-            self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
-            debug!(self.log, "cache removed unused artifact";
-                "key" => ?key,
-                "artifact_bytes_used" => %ByteSize::b(bytes_used),
-                "entries" => %(self.entries.len() - self.getting),
-                "bytes_used" => %ByteSize::b(self.bytes_used),
-                "byte_used_target" => %ByteSize::b(self.bytes_used_target)
-            );
+            self.remove_entry_popped_off_heap(key)?;
+        }
+        Ok(())
+    }
+
+    fn remove_entry_popped_off_heap(&mut self, key: Key<KeyKindT>) -> Result<()> {
+        let Some(Entry::InHeap {
+            file_type,
+            bytes_used,
+            ..
+        }) = self.entries.remove(&key)
+        else {
+            panic!("Entry popped off of heap was in unexpected state");
+        };
+        let cache_path = self.cache_path(key.kind, &key.digest);
+        if file_type == FileType::Directory {
+            rmdir_in_background(&self.fs, &self.removing, &cache_path)?;
+            self.fs.remove(&size_file_name(&cache_path))?;
+        } else {
+            self.fs.remove(&cache_path)?;
         }
+        self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+        debug!(self.log, "cache removed unused artifact";
+            "key" => ?key,
+            "artifact_bytes_used" => %ByteSize::b(bytes_used),
+            "entries" => %(self.entries.len() - self.getting),
+            "bytes_used" => %ByteSize::b(self.bytes_used),
+            "byte_used_target" => %ByteSize::b(self.bytes_used_target)
+        );
         Ok(())
     }
 }
@@ -1220,6 +1262,15 @@ mod tests {
             assert_eq!(self.cache.try_increment_ref_count(kind, &digest), expected);
         }
 
+        fn get_artifact_if_present(
+            &mut self,
+            kind: TestKeyKind,
+            digest: Sha256Digest,
+            expected: bool,
+        ) {
+            assert_eq!(self.cache.get_artifact_if_present(kind, &digest), expected);
+        }
+
         fn decrement_ref_count(&mut self, kind: TestKeyKind, digest: Sha256Digest) {
             self.cache.decrement_ref_count(kind, &digest);
         }
@@ -1642,6 +1693,34 @@ mod tests {
         fixture.assert_bytes_used(10);
     }
 
+    #[test]
+    fn get_artifact_if_present() {
+        let mut fixture = Fixture::new(10, fs! {});
+
+        fixture.get_artifact(Apple, digest!(2), jid!(1), GetArtifact::Get);
+
+        fixture.get_artifact(Apple, digest!(3), jid!(1), GetArtifact::Get);
+        fixture.got_artifact_success_file(Apple, digest!(3), b"abc", vec![jid!(1)]);
+        fixture.decrement_ref_count(Apple, digest!(3));
+
+        fixture.get_artifact(Apple, digest!(4), jid!(1), GetArtifact::Get);
+        fixture.got_artifact_success_file(Apple, digest!(4), b"def", vec![jid!(1)]);
+
+        fixture.get_artifact_if_present(Apple, digest!(1), false);
+        fixture.get_artifact_if_present(Apple, digest!(2), false);
+        fixture.get_artifact_if_present(Apple, digest!(3), true);
+        fixture.get_artifact_if_present(Apple, digest!(4), true);
+
+        // Neither of the artifacts that were present can be removed to make room now.
+        fixture.get_artifact(Apple, digest!(5), jid!(1), GetArtifact::Get);
+        fixture.got_artifact_success_file(Apple, digest!(5), b"0123456789", vec![jid!(1)]);
+        fixture.assert_bytes_used(16);
+        fixture.assert_file_exists(Apple, digest!(3), Metadata::file(3));
+
+        // The artifact that wasn't present still can't be gotten without getting it.
+        fixture.get_artifact(Apple, digest!(1), jid!(2), GetArtifact::Get);
+    }
+
     #[test]
     fn got_artifact_failure_no_entry() {
         let mut fixture = Fixture::new(1, fs! {});
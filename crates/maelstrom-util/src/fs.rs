@@ -1,9 +1,15 @@
 use anyhow::{Context as _, Result};
 use fs2::FileExt as _;
+use maelstrom_base::manifest::Xattrs;
+use maelstrom_linux::{self as linux, Errno};
 use std::{
+    ffi::{CStr, CString},
     fmt,
     io::{self},
-    os::fd::{AsRawFd, RawFd},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::ffi::OsStrExt as _,
+    },
     path::{Path, PathBuf},
 };
 
@@ -239,6 +245,56 @@ impl std::os::unix::fs::MetadataExt for Metadata {
     }
 }
 
+/// Call one of the xattr syscalls which fill a buffer, first asking for the size of the buffer
+/// needed. The size is asked for again if the result grows in between.
+fn read_xattr_buffer(
+    mut f: impl FnMut(&mut [u8]) -> std::result::Result<usize, Errno>,
+) -> std::result::Result<Vec<u8>, Errno> {
+    loop {
+        let mut buffer = vec![0; f(&mut [])?];
+        match f(&mut buffer) {
+            Ok(size) => {
+                buffer.truncate(size);
+                return Ok(buffer);
+            }
+            Err(Errno::ERANGE) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+type ListXattrFn = fn(&CStr, &mut [u8]) -> std::result::Result<usize, Errno>;
+type GetXattrFn = fn(&CStr, &CStr, &mut [u8]) -> std::result::Result<usize, Errno>;
+
+fn read_xattrs(path: &Path, follow_symlinks: bool) -> io::Result<Xattrs> {
+    let (list, get): (ListXattrFn, GetXattrFn) = if follow_symlinks {
+        (linux::listxattr, linux::getxattr)
+    } else {
+        (linux::llistxattr, linux::lgetxattr)
+    };
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let names = match read_xattr_buffer(|buffer| list(&path, buffer)) {
+        Ok(names) => names,
+        // The file-system doesn't support extended attributes at all.
+        Err(Errno::EOPNOTSUPP) => return Ok(Xattrs::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut xattrs = Xattrs::new();
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let c_name = CString::new(name)?;
+        let value = match read_xattr_buffer(|buffer| get(&path, &c_name, buffer)) {
+            Ok(value) => value,
+            // It was removed after we listed it.
+            Err(Errno::ENODATA) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let name = String::from_utf8(name.to_vec())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        xattrs.insert(name, value);
+    }
+    Ok(xattrs)
+}
+
 fn is_not_found_err(err: &anyhow::Error) -> bool {
     let std_err = err.root_cause().downcast_ref::<std::io::Error>();
     matches!(std_err, Some(e) if e.kind() == std::io::ErrorKind::NotFound)
@@ -277,6 +333,19 @@ impl Fs {
         })
     }
 
+    /// Read all of the extended attributes of the file at the given path, following symlinks.
+    pub fn xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Xattrs> {
+        let path = path.as_ref();
+        read_xattrs(path, true).with_context(|| format!("xattrs(\"{}\")", path.display()))
+    }
+
+    /// Read all of the extended attributes of the file at the given path, without following
+    /// symlinks.
+    pub fn symlink_xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Xattrs> {
+        let path = path.as_ref();
+        read_xattrs(path, false).with_context(|| format!("symlink_xattrs(\"{}\")", path.display()))
+    }
+
     pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
         fs_trampoline!(std::fs::rename, from, to)
     }
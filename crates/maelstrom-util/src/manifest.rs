@@ -1,17 +1,22 @@
+pub mod tar;
+pub mod zip;
+
 use crate::async_fs::{self, Fs};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use maelstrom_base::{
     manifest::{
-        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData, ManifestVersion,
-        Mode, UnixTimestamp,
+        ManifestChunk, ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData,
+        ManifestVersion, Mode, Owner, UnixTimestamp, Xattrs,
     },
     proto, Sha256Digest, Utf8PathBuf,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::io;
+use sha2::{Digest as _, Sha256};
+use std::io::{self, Write as _};
 use std::os::unix::fs::MetadataExt as _;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tokio::io::{
     AsyncRead, AsyncReadExt as _, AsyncSeek, AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _,
 };
@@ -180,17 +185,78 @@ fn to_utf8_path(path: impl AsRef<Path>) -> Utf8PathBuf {
     path.as_ref().to_owned().try_into().unwrap()
 }
 
-fn convert_metadata(meta: &async_fs::Metadata) -> ManifestEntryMetadata {
+fn convert_metadata(meta: &async_fs::Metadata, xattrs: Xattrs) -> ManifestEntryMetadata {
     ManifestEntryMetadata {
         size: meta.is_file().then(|| meta.size()).unwrap_or(0),
         mode: Mode(meta.mode()),
         mtime: UnixTimestamp(meta.mtime()),
+        xattrs,
+        owner: None,
     }
 }
 
 #[async_trait]
 pub trait DataUpload: Send {
     async fn upload(&mut self, path: &Path) -> Result<Sha256Digest>;
+
+    /// Upload the chunk at `path`, whose contents are already known to have the given digest.
+    async fn upload_chunk(&mut self, path: &Path, _digest: &Sha256Digest) -> Result<()> {
+        self.upload(path).await?;
+        Ok(())
+    }
+
+    /// The chunks the file at `path` was split into the last time, if it hasn't changed since.
+    async fn cached_chunks(&mut self, _path: &Path) -> Result<Option<Vec<ManifestChunk>>> {
+        Ok(None)
+    }
+
+    /// Remember the chunks the file at `path` was split into, while it had the given modification
+    /// time and size.
+    async fn cache_chunks(
+        &mut self,
+        _path: &Path,
+        _mtime: SystemTime,
+        _size: u64,
+        _chunks: &[ManifestChunk],
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Files at least this big are split into chunks by a [`ManifestBuilder`] that has been given a
+/// chunk directory.
+pub const CHUNKING_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+const CHUNK_MIN_SIZE: u32 = 1024 * 1024;
+const CHUNK_AVG_SIZE: u32 = 4 * 1024 * 1024;
+const CHUNK_MAX_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Split the given file into content-defined chunks using FastCDC, and write each chunk to
+/// `chunk_dir`, named after its digest, unless it's already there. Return the chunks in order.
+fn write_chunks(source: &Path, chunk_dir: &Path) -> Result<Vec<ManifestChunk>> {
+    let fs = crate::fs::Fs::new();
+    let chunker = fastcdc::v2020::StreamCDC::new(
+        fs.open_file(source)?,
+        CHUNK_MIN_SIZE,
+        CHUNK_AVG_SIZE,
+        CHUNK_MAX_SIZE,
+    );
+    let mut chunks = vec![];
+    for chunk in chunker {
+        let chunk = chunk?;
+        let digest = Sha256Digest::new(Sha256::digest(&chunk.data).into());
+        let path = chunk_dir.join(digest.to_string());
+        if !fs.exists(&path) {
+            let mut temp_file = tempfile::NamedTempFile::new_in(chunk_dir)?;
+            temp_file.write_all(&chunk.data)?;
+            temp_file.persist(&path)?;
+        }
+        chunks.push(ManifestChunk {
+            digest,
+            size: chunk.length as u64,
+        });
+    }
+    Ok(chunks)
 }
 
 pub struct ManifestBuilder<'cb, WriteT> {
@@ -199,6 +265,10 @@ pub struct ManifestBuilder<'cb, WriteT> {
     follow_symlinks: bool,
     data_upload: Box<dyn DataUpload + 'cb>,
     inline_limit: u64,
+    chunk_dir: Option<PathBuf>,
+    chunking_threshold: u64,
+    file_mode: Option<Mode>,
+    owner: Option<Owner>,
 }
 
 impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
@@ -214,18 +284,98 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
             data_upload: Box::new(data_upload),
             follow_symlinks,
             inline_limit,
+            chunk_dir: None,
+            chunking_threshold: CHUNKING_THRESHOLD,
+            file_mode: None,
+            owner: None,
         })
     }
 
+    /// Split files of at least [`CHUNKING_THRESHOLD`] bytes into chunks, which are written to
+    /// `chunk_dir` and uploaded individually. When only part of a large file changes, most of its
+    /// chunks stay the same, and don't have to be uploaded again. A file isn't split again if the
+    /// [`DataUpload`] has its chunks cached and they're all still in `chunk_dir`.
+    pub fn with_chunk_dir(mut self, chunk_dir: impl Into<PathBuf>) -> Self {
+        self.chunk_dir = Some(chunk_dir.into());
+        self
+    }
+
+    /// Replace the permission bits of every regular file added with those of `mode`. Directories
+    /// and symlinks keep their own modes.
+    pub fn with_file_mode(mut self, mode: Option<Mode>) -> Self {
+        self.file_mode = mode;
+        self
+    }
+
+    /// Give every entry added the given owner, instead of leaving them owned by the job's user.
+    pub fn with_owner(mut self, owner: Option<Owner>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    async fn cached_chunks(
+        &mut self,
+        source: &Path,
+        chunk_dir: &Path,
+    ) -> Result<Option<Vec<ManifestChunk>>> {
+        let Some(chunks) = self.data_upload.cached_chunks(source).await? else {
+            return Ok(None);
+        };
+        for chunk in &chunks {
+            if !self
+                .fs
+                .exists(chunk_dir.join(chunk.digest.to_string()))
+                .await
+            {
+                return Ok(None);
+            }
+        }
+        Ok(Some(chunks))
+    }
+
+    async fn upload_chunks(
+        &mut self,
+        source: &Path,
+        meta: &async_fs::Metadata,
+        chunk_dir: PathBuf,
+    ) -> Result<Vec<ManifestChunk>> {
+        let chunks = match self.cached_chunks(source, &chunk_dir).await? {
+            Some(chunks) => chunks,
+            None => {
+                let chunks = tokio::task::spawn_blocking({
+                    let source = source.to_owned();
+                    let chunk_dir = chunk_dir.clone();
+                    move || write_chunks(&source, &chunk_dir)
+                })
+                .await??;
+                self.data_upload
+                    .cache_chunks(source, meta.modified()?, meta.size(), &chunks)
+                    .await?;
+                chunks
+            }
+        };
+        for chunk in &chunks {
+            let path = chunk_dir.join(chunk.digest.to_string());
+            self.data_upload.upload_chunk(&path, &chunk.digest).await?;
+        }
+        Ok(chunks)
+    }
+
     async fn add_entry(
         &mut self,
         meta: &async_fs::Metadata,
+        xattrs: Xattrs,
         path: impl AsRef<Path>,
         data: ManifestEntryData,
     ) -> Result<()> {
+        let mut metadata = convert_metadata(meta, xattrs);
+        if let (Some(mode), ManifestEntryData::File(_)) = (self.file_mode, &data) {
+            metadata.mode = metadata.mode.with_permissions(mode);
+        }
+        metadata.owner = self.owner;
         let entry = ManifestEntry {
             path: to_utf8_path(path),
-            metadata: convert_metadata(meta),
+            metadata,
             data,
         };
         self.writer.write_entry(&entry).await?;
@@ -237,29 +387,50 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
         source: impl AsRef<Path>,
         dest: impl AsRef<Path>,
     ) -> Result<()> {
-        let meta = if self.follow_symlinks {
-            self.fs.metadata(source.as_ref()).await?
+        let (meta, xattrs) = if self.follow_symlinks {
+            (
+                self.fs.metadata(source.as_ref()).await?,
+                self.fs.xattrs(source.as_ref()).await?,
+            )
         } else {
-            self.fs.symlink_metadata(source.as_ref()).await?
+            (
+                self.fs.symlink_metadata(source.as_ref()).await?,
+                self.fs.symlink_xattrs(source.as_ref()).await?,
+            )
         };
         if meta.is_file() {
             let file_size = meta.size();
+            let chunk_dir = self
+                .chunk_dir
+                .clone()
+                .filter(|_| file_size >= self.chunking_threshold);
             let data = if file_size <= self.inline_limit {
                 ManifestFileData::Inline(self.fs.read(source.as_ref()).await?)
+            } else if let Some(chunk_dir) = chunk_dir {
+                ManifestFileData::Chunked(
+                    self.upload_chunks(source.as_ref(), &meta, chunk_dir)
+                        .await?,
+                )
             } else if file_size > 0 {
                 ManifestFileData::Digest(self.data_upload.upload(source.as_ref()).await?)
             } else {
                 ManifestFileData::Empty
             };
-            self.add_entry(&meta, dest, ManifestEntryData::File(data))
+            self.add_entry(&meta, xattrs, dest, ManifestEntryData::File(data))
                 .await
         } else if meta.is_dir() {
-            self.add_entry(&meta, dest, ManifestEntryData::Directory { opaque: false })
-                .await
+            self.add_entry(
+                &meta,
+                xattrs,
+                dest,
+                ManifestEntryData::Directory { opaque: false },
+            )
+            .await
         } else if meta.is_symlink() {
             let data = self.fs.read_link(source.as_ref()).await?;
             self.add_entry(
                 &meta,
+                xattrs,
                 dest,
                 ManifestEntryData::Symlink(data.into_os_string().into_encoded_bytes()),
             )
@@ -276,6 +447,7 @@ mod tests {
     use std::future::Future;
     use std::path::PathBuf;
     use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
     use tempfile::{tempdir, TempDir};
 
     struct Fixture {
@@ -304,6 +476,16 @@ mod tests {
         }
     }
 
+    struct HashingDataUpload;
+
+    #[async_trait]
+    impl DataUpload for HashingDataUpload {
+        async fn upload(&mut self, path: &Path) -> Result<Sha256Digest> {
+            let data = Fs::new().read(path).await?;
+            Ok(Sha256Digest::new(Sha256::digest(data).into()))
+        }
+    }
+
     async fn assert_entry<BuildT>(
         build: BuildT,
         follow_symlinks: bool,
@@ -329,14 +511,24 @@ mod tests {
             .map(|e| e.unwrap())
             .collect();
 
-        let input_meta = if follow_symlinks {
-            fixture.fs.metadata(&fixture.input_path).await.unwrap()
+        let (input_meta, input_xattrs) = if follow_symlinks {
+            (
+                fixture.fs.metadata(&fixture.input_path).await.unwrap(),
+                fixture.fs.xattrs(&fixture.input_path).await.unwrap(),
+            )
         } else {
-            fixture
-                .fs
-                .symlink_metadata(&fixture.input_path)
-                .await
-                .unwrap()
+            (
+                fixture
+                    .fs
+                    .symlink_metadata(&fixture.input_path)
+                    .await
+                    .unwrap(),
+                fixture
+                    .fs
+                    .symlink_xattrs(&fixture.input_path)
+                    .await
+                    .unwrap(),
+            )
         };
         assert_eq!(
             actual_entries,
@@ -344,7 +536,7 @@ mod tests {
                 path: to_utf8_path(expected_path),
                 metadata: ManifestEntryMetadata {
                     size: expected_size,
-                    ..convert_metadata(&input_meta)
+                    ..convert_metadata(&input_meta, input_xattrs)
                 },
                 data,
             }]
@@ -444,4 +636,192 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test]
+    async fn builder_captures_xattrs() {
+        let fixture = Fixture::new();
+        fixture
+            .fs
+            .write(&fixture.input_path, b"foobar")
+            .await
+            .unwrap();
+        let path =
+            std::ffi::CString::new(fixture.input_path.as_os_str().as_encoded_bytes()).unwrap();
+        match maelstrom_linux::setxattr(&path, c"user.maelstrom", b"value") {
+            Ok(()) => {}
+            // The temporary directory's file-system doesn't support user extended attributes.
+            Err(maelstrom_linux::Errno::EOPNOTSUPP) => return,
+            Err(err) => panic!("{err}"),
+        }
+
+        let mut buffer = vec![];
+        let mut builder = ManifestBuilder::new(&mut buffer, false, TestDataUpload, 10)
+            .await
+            .unwrap();
+        builder
+            .add_file(&fixture.input_path, "foo/bar.txt")
+            .await
+            .unwrap();
+
+        let entries: Vec<_> = ManifestReader::new(io::Cursor::new(buffer))
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            entries[0].metadata.xattrs.get("user.maelstrom"),
+            Some(&b"value".to_vec())
+        );
+    }
+
+    /// Bytes without any repetition, like the contents of a compressed file.
+    fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Hashes like [`HashingDataUpload`], and caches the chunks of one file.
+    #[derive(Clone, Default)]
+    struct ChunkCachingDataUpload {
+        chunks: Arc<Mutex<Option<Vec<ManifestChunk>>>>,
+    }
+
+    #[async_trait]
+    impl DataUpload for ChunkCachingDataUpload {
+        async fn upload(&mut self, path: &Path) -> Result<Sha256Digest> {
+            HashingDataUpload.upload(path).await
+        }
+
+        async fn cached_chunks(&mut self, _path: &Path) -> Result<Option<Vec<ManifestChunk>>> {
+            Ok(self.chunks.lock().unwrap().clone())
+        }
+
+        async fn cache_chunks(
+            &mut self,
+            _path: &Path,
+            _mtime: SystemTime,
+            _size: u64,
+            chunks: &[ManifestChunk],
+        ) -> Result<()> {
+            *self.chunks.lock().unwrap() = Some(chunks.to_vec());
+            Ok(())
+        }
+    }
+
+    async fn build_chunked_entry(fixture: &Fixture, chunk_dir: &Path) -> Vec<ManifestChunk> {
+        build_chunked_entry_with_upload(fixture, chunk_dir, HashingDataUpload).await
+    }
+
+    async fn build_chunked_entry_with_upload(
+        fixture: &Fixture,
+        chunk_dir: &Path,
+        data_upload: impl DataUpload,
+    ) -> Vec<ManifestChunk> {
+        let mut buffer = vec![];
+        let mut builder = ManifestBuilder::new(&mut buffer, false, data_upload, 10)
+            .await
+            .unwrap()
+            .with_chunk_dir(chunk_dir);
+        builder.chunking_threshold = 1024;
+        builder
+            .add_file(&fixture.input_path, "big.bin")
+            .await
+            .unwrap();
+        drop(builder);
+
+        let mut entries = ManifestReader::new(io::Cursor::new(buffer)).unwrap();
+        let entry = entries.next().unwrap().unwrap();
+        let ManifestEntryData::File(ManifestFileData::Chunked(chunks)) = entry.data else {
+            panic!("unexpected entry data {:?}", entry.data);
+        };
+        chunks
+    }
+
+    #[tokio::test]
+    async fn builder_chunks_large_files() {
+        let fixture = Fixture::new();
+        let chunk_dir = fixture.temp_dir.path().join("chunks");
+        fixture.fs.create_dir(&chunk_dir).await.unwrap();
+        let mut contents = pseudo_random_bytes(12 * 1024 * 1024);
+        fixture
+            .fs
+            .write(&fixture.input_path, &contents)
+            .await
+            .unwrap();
+
+        let chunks = build_chunked_entry(&fixture, &chunk_dir).await;
+        assert!(chunks.len() > 1, "{chunks:?}");
+        let mut reassembled = vec![];
+        for chunk in &chunks {
+            let data = fixture
+                .fs
+                .read(chunk_dir.join(chunk.digest.to_string()))
+                .await
+                .unwrap();
+            assert_eq!(data.len() as u64, chunk.size);
+            reassembled.extend(data);
+        }
+        assert!(reassembled == contents);
+
+        // Changing the end of the file only changes the last chunk.
+        *contents.last_mut().unwrap() ^= 0xff;
+        fixture
+            .fs
+            .write(&fixture.input_path, &contents)
+            .await
+            .unwrap();
+        let new_chunks = build_chunked_entry(&fixture, &chunk_dir).await;
+        assert_eq!(new_chunks.len(), chunks.len());
+        assert_eq!(new_chunks[..chunks.len() - 1], chunks[..chunks.len() - 1]);
+        assert_ne!(new_chunks.last(), chunks.last());
+    }
+
+    #[tokio::test]
+    async fn builder_uses_cached_chunks_while_they_exist() {
+        let fixture = Fixture::new();
+        let chunk_dir = fixture.temp_dir.path().join("chunks");
+        fixture.fs.create_dir(&chunk_dir).await.unwrap();
+        let mut contents = pseudo_random_bytes(12 * 1024 * 1024);
+        fixture
+            .fs
+            .write(&fixture.input_path, &contents)
+            .await
+            .unwrap();
+        let data_upload = ChunkCachingDataUpload::default();
+        let chunks =
+            build_chunked_entry_with_upload(&fixture, &chunk_dir, data_upload.clone()).await;
+        assert_eq!(data_upload.chunks.lock().unwrap().as_ref(), Some(&chunks));
+
+        // The cache doesn't notice the change, so the file isn't split again.
+        *contents.last_mut().unwrap() ^= 0xff;
+        fixture
+            .fs
+            .write(&fixture.input_path, &contents)
+            .await
+            .unwrap();
+        let cached_chunks =
+            build_chunked_entry_with_upload(&fixture, &chunk_dir, data_upload.clone()).await;
+        assert_eq!(cached_chunks, chunks);
+
+        // Once one of the chunks is gone, it is.
+        fixture
+            .fs
+            .remove_file(chunk_dir.join(chunks[0].digest.to_string()))
+            .await
+            .unwrap();
+        let new_chunks =
+            build_chunked_entry_with_upload(&fixture, &chunk_dir, data_upload.clone()).await;
+        assert_eq!(new_chunks[..chunks.len() - 1], chunks[..chunks.len() - 1]);
+        assert_ne!(new_chunks.last(), chunks.last());
+        assert_eq!(
+            data_upload.chunks.lock().unwrap().as_ref(),
+            Some(&new_chunks)
+        );
+    }
 }
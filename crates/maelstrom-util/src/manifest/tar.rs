@@ -0,0 +1,514 @@
+//! Conversions between manifests and tar files.
+
+use super::{ManifestReader, ManifestWriter};
+use crate::{fs::Fs, io::Sha256Stream};
+use anyhow::{anyhow, bail, Result};
+use maelstrom_base::{
+    manifest::{
+        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData, Mode,
+        UnixTimestamp, Xattrs,
+    },
+    Sha256Digest, Utf8Path, Utf8PathBuf,
+};
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, EntryType, Header};
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+const XATTR_PAX_PREFIX: &str = "SCHILY.xattr.";
+
+fn data_path(data_dir: &Path, digest: &Sha256Digest) -> PathBuf {
+    data_dir.join(digest.to_string())
+}
+
+/// Copy `data` into `data_dir`, naming the new file after the digest of its contents.
+pub(super) fn store_data(
+    fs: &Fs,
+    data: impl Read,
+    data_dir: &Path,
+) -> Result<(Sha256Digest, PathBuf)> {
+    let mut stream = Sha256Stream::new(tempfile::NamedTempFile::new_in(data_dir)?);
+    io::copy(&mut io::BufReader::new(data), &mut stream)?;
+    let (temp_file, digest) = stream.finalize();
+    let path = data_path(data_dir, &digest);
+    if !fs.exists(&path) {
+        temp_file.persist(&path)?;
+    }
+    Ok((digest, path))
+}
+
+/// The extended attributes stored in the given PAX records, the way GNU tar and others store them.
+/// Each record is a key and a value. Records for anything other than extended attributes are
+/// ignored.
+pub fn pax_records_xattrs<'a>(
+    records: impl IntoIterator<Item = Result<(&'a [u8], &'a [u8])>>,
+) -> Result<Xattrs> {
+    let mut xattrs = Xattrs::new();
+    for record in records {
+        let (key, value) = record?;
+        if let Some(name) = key.strip_prefix(XATTR_PAX_PREFIX.as_bytes()) {
+            xattrs.insert(std::str::from_utf8(name)?.into(), value.into());
+        }
+    }
+    Ok(xattrs)
+}
+
+/// The extended attributes of `entry`, stored in its PAX extensions.
+pub fn entry_xattrs(entry: &mut tar::Entry<impl Read>) -> Result<Xattrs> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(Xattrs::new());
+    };
+    pax_records_xattrs(extensions.map(|extension| {
+        let extension = extension?;
+        Ok((extension.key_bytes(), extension.value_bytes()))
+    }))
+}
+
+fn utf8_path(path: &[u8]) -> Result<Utf8PathBuf> {
+    Ok(std::str::from_utf8(path)
+        .map_err(|_| anyhow!("non-UTF8 path in tar"))?
+        .trim_end_matches('/')
+        .into())
+}
+
+/// Convert the tar file read from `tar` into a manifest written to `manifest`.
+///
+/// Regular files no bigger than `inline_limit` are stored inline in the manifest. The contents of
+/// the other files are written to `data_dir`, named after their digests. The paths of those files
+/// are returned, so they can be uploaded.
+///
+/// Whiteout files and opaque-directory markers, as found in OCI image layers, are turned into the
+/// equivalent manifest entries.
+pub fn manifest_from_tar(
+    tar: impl Read,
+    manifest: impl Write,
+    data_dir: &Path,
+    inline_limit: u64,
+) -> Result<Vec<PathBuf>> {
+    let fs = Fs::new();
+    let mut entries: Vec<ManifestEntry> = vec![];
+    let mut data_paths = BTreeSet::new();
+    let mut archive = Archive::new(tar);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let xattrs = entry_xattrs(&mut entry)?;
+        let header = entry.header();
+        let path = utf8_path(&entry.path_bytes())?;
+        let entry_type = header.entry_type();
+        let metadata = ManifestEntryMetadata {
+            size: 0,
+            mode: Mode(header.mode()?),
+            mtime: UnixTimestamp(header.mtime()?.try_into()?),
+            xattrs,
+            owner: None,
+        };
+        let link_name = entry.link_name_bytes().map(|name| name.into_owned());
+        let data = match entry_type {
+            EntryType::Regular | EntryType::Continuous => {
+                let file_name = path.file_name().unwrap_or_default();
+                if file_name == OPAQUE_MARKER {
+                    let parent = path.parent().unwrap_or(Utf8Path::new(""));
+                    match entries.iter_mut().rfind(|e| e.path == parent) {
+                        Some(ManifestEntry {
+                            data: ManifestEntryData::Directory { opaque },
+                            ..
+                        }) => *opaque = true,
+                        _ => entries.push(ManifestEntry {
+                            path: parent.into(),
+                            metadata: ManifestEntryMetadata {
+                                mode: Mode(0o755),
+                                ..metadata
+                            },
+                            data: ManifestEntryData::Directory { opaque: true },
+                        }),
+                    }
+                    continue;
+                }
+                if let Some(name) = file_name.strip_prefix(WHITEOUT_PREFIX) {
+                    entries.push(ManifestEntry {
+                        path: path.with_file_name(name),
+                        metadata,
+                        data: ManifestEntryData::Whiteout,
+                    });
+                    continue;
+                }
+
+                let size = entry.size();
+                let data = if size == 0 {
+                    ManifestFileData::Empty
+                } else if size <= inline_limit {
+                    let mut data = vec![];
+                    entry.read_to_end(&mut data)?;
+                    ManifestFileData::Inline(data)
+                } else {
+                    let (digest, data_path) = store_data(&fs, &mut entry, data_dir)?;
+                    data_paths.insert(data_path);
+                    ManifestFileData::Digest(digest)
+                };
+                entries.push(ManifestEntry {
+                    path,
+                    metadata: ManifestEntryMetadata { size, ..metadata },
+                    data: ManifestEntryData::File(data),
+                });
+                continue;
+            }
+            EntryType::Directory => ManifestEntryData::Directory { opaque: false },
+            EntryType::Symlink => ManifestEntryData::Symlink(
+                link_name.ok_or_else(|| anyhow!("symlink {path} has no target"))?,
+            ),
+            EntryType::Link => ManifestEntryData::Hardlink(utf8_path(
+                &link_name.ok_or_else(|| anyhow!("hard-link {path} has no target"))?,
+            )?),
+            // Headers that apply to the whole archive, like the commit ID written by `git archive`.
+            EntryType::XGlobalHeader => continue,
+            other => bail!("unsupported tar entry type {other:?} at {path}"),
+        };
+        entries.push(ManifestEntry {
+            path,
+            metadata,
+            data,
+        });
+    }
+
+    let mut writer = ManifestWriter::new(manifest)?;
+    writer.write_entries(&entries)?;
+    Ok(data_paths.into_iter().collect())
+}
+
+/// Encode the given extended attributes as PAX records, the way GNU tar does. Each record is
+/// prefixed by its own length in decimal, including the length itself.
+pub fn pax_xattr_records(xattrs: &Xattrs) -> Vec<u8> {
+    let mut records = vec![];
+    for (name, value) in xattrs {
+        let key = format!(" {XATTR_PAX_PREFIX}{name}=");
+        let rest_length = key.len() + value.len() + 1;
+        let mut length = rest_length;
+        while length != rest_length + length.to_string().len() {
+            length = rest_length + length.to_string().len();
+        }
+        records.extend_from_slice(length.to_string().as_bytes());
+        records.extend_from_slice(key.as_bytes());
+        records.extend_from_slice(value);
+        records.push(b'\n');
+    }
+    records
+}
+
+fn file_contents<'fs>(
+    fs: &'fs Fs,
+    path: &Utf8Path,
+    data: &ManifestFileData,
+    data_dir: Option<&Path>,
+) -> Result<Box<dyn Read + 'fs>> {
+    let data_dir = || {
+        data_dir.ok_or_else(|| {
+            anyhow!("the contents of {path} are stored by digest, but there is no data directory")
+        })
+    };
+    Ok(match data {
+        ManifestFileData::Empty => Box::new(io::empty()),
+        ManifestFileData::Inline(data) => Box::new(io::Cursor::new(data.clone())),
+        ManifestFileData::Digest(digest) => Box::new(fs.open_file(data_path(data_dir()?, digest))?),
+        ManifestFileData::Chunked(chunks) => {
+            let mut contents: Box<dyn Read + 'fs> = Box::new(io::empty());
+            for chunk in chunks {
+                let chunk = fs.open_file(data_path(data_dir()?, &chunk.digest))?;
+                contents = Box::new(contents.chain(chunk));
+            }
+            contents
+        }
+    })
+}
+
+/// Write the entries of the manifest read from `manifest` to `tar` as a tar file. File contents
+/// stored by digest are read from `data_dir`, where they're expected to be named after their
+/// digests. If there is no `data_dir`, a manifest with such files is an error.
+///
+/// Whiteouts and opaque directories are written the way OCI image layers represent them.
+pub fn manifest_to_tar(
+    manifest: impl Read + Seek,
+    tar: impl Write,
+    data_dir: Option<&Path>,
+) -> Result<()> {
+    let fs = Fs::new();
+    let mut builder = Builder::new(tar);
+    for entry in ManifestReader::new(manifest)? {
+        let ManifestEntry {
+            path,
+            metadata,
+            data,
+        } = entry?;
+        let path = path.strip_prefix("/").unwrap_or(&path);
+        if path.as_str().is_empty() {
+            continue;
+        }
+        let mut header = Header::new_gnu();
+        header.set_mode(metadata.mode.into());
+        header.set_mtime(metadata.mtime.0.try_into().unwrap_or_default());
+        header.set_size(0);
+        if let Some(owner) = metadata.owner {
+            header.set_uid(u32::from(owner.user).into());
+            header.set_gid(u32::from(owner.group).into());
+        }
+        if !metadata.xattrs.is_empty() {
+            let records = pax_xattr_records(&metadata.xattrs);
+            let mut pax_header = Header::new_ustar();
+            pax_header.set_entry_type(EntryType::XHeader);
+            pax_header.set_size(records.len() as u64);
+            builder.append_data(&mut pax_header, "././@PaxHeader", &records[..])?;
+        }
+        match data {
+            ManifestEntryData::Directory { opaque } => {
+                header.set_entry_type(EntryType::Directory);
+                builder.append_data(&mut header, path, io::empty())?;
+                if opaque {
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_mode(0o644);
+                    builder.append_data(&mut header, path.join(OPAQUE_MARKER), io::empty())?;
+                }
+            }
+            ManifestEntryData::File(data) => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(metadata.size);
+                let contents = file_contents(&fs, path, &data, data_dir)?;
+                builder.append_data(&mut header, path, contents)?;
+            }
+            ManifestEntryData::Symlink(target) => {
+                let target = String::from_utf8(target)
+                    .map_err(|_| anyhow!("non-UTF-8 symlink target at {path}"))?;
+                header.set_entry_type(EntryType::Symlink);
+                builder.append_link(&mut header, path, target)?;
+            }
+            ManifestEntryData::Hardlink(target) => {
+                header.set_entry_type(EntryType::Link);
+                let target = target.strip_prefix("/").unwrap_or(&target);
+                builder.append_link(&mut header, path, target)?;
+            }
+            ManifestEntryData::Whiteout => {
+                header.set_entry_type(EntryType::Regular);
+                let name = format!("{WHITEOUT_PREFIX}{}", path.file_name().unwrap_or_default());
+                builder.append_data(&mut header, path.with_file_name(name), io::empty())?;
+            }
+        }
+    }
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::manifest::ManifestChunk;
+    use sha2::{Digest as _, Sha256};
+    use tempfile::tempdir;
+
+    fn metadata(size: u64, mode: u32) -> ManifestEntryMetadata {
+        ManifestEntryMetadata {
+            size,
+            mode: Mode(mode),
+            mtime: UnixTimestamp(1705000271),
+            xattrs: Xattrs::new(),
+            owner: None,
+        }
+    }
+
+    fn entry(path: &str, size: u64, mode: u32, data: ManifestEntryData) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            metadata: metadata(size, mode),
+            data,
+        }
+    }
+
+    fn read_entries(manifest: &[u8]) -> Vec<ManifestEntry> {
+        ManifestReader::new(io::Cursor::new(manifest))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn append(builder: &mut Builder<Vec<u8>>, path: &str, type_: EntryType, data: &[u8]) {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(type_);
+        header.set_mode(0o644);
+        header.set_mtime(1705000271);
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    #[test]
+    fn from_tar() {
+        let data_dir = tempdir().unwrap();
+        let mut builder = Builder::new(vec![]);
+        append(
+            &mut builder,
+            "pax_global_header",
+            EntryType::XGlobalHeader,
+            b"18 comment=abcdef\n",
+        );
+        append(&mut builder, "dir/", EntryType::Directory, b"");
+        append(&mut builder, "dir/.wh..wh..opq", EntryType::Regular, b"");
+        append(&mut builder, "dir/.wh.gone", EntryType::Regular, b"");
+        append(&mut builder, "dir/small", EntryType::Regular, b"abc");
+        append(&mut builder, "dir/big", EntryType::Regular, b"0123456789");
+        append(&mut builder, "dir/empty", EntryType::Regular, b"");
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o644);
+        header.set_mtime(1705000271);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "dir/symlink", "small")
+            .unwrap();
+        header.set_entry_type(EntryType::Link);
+        builder
+            .append_link(&mut header, "dir/hardlink", "dir/big")
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+
+        let mut manifest = vec![];
+        let data_paths = manifest_from_tar(&tar[..], &mut manifest, data_dir.path(), 5).unwrap();
+
+        let digest = Sha256Digest::new(Sha256::digest(b"0123456789").into());
+        assert_eq!(data_paths, vec![data_path(data_dir.path(), &digest)]);
+        assert_eq!(std::fs::read(&data_paths[0]).unwrap(), b"0123456789");
+        assert_eq!(
+            read_entries(&manifest),
+            vec![
+                entry(
+                    "dir",
+                    0,
+                    0o644,
+                    ManifestEntryData::Directory { opaque: true }
+                ),
+                entry("dir/gone", 0, 0o644, ManifestEntryData::Whiteout),
+                entry(
+                    "dir/small",
+                    3,
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"abc".to_vec()))
+                ),
+                entry(
+                    "dir/big",
+                    10,
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Digest(digest))
+                ),
+                entry(
+                    "dir/empty",
+                    0,
+                    0o644,
+                    ManifestEntryData::File(ManifestFileData::Empty)
+                ),
+                entry(
+                    "dir/symlink",
+                    0,
+                    0o644,
+                    ManifestEntryData::Symlink(b"small".to_vec())
+                ),
+                entry(
+                    "dir/hardlink",
+                    0,
+                    0o644,
+                    ManifestEntryData::Hardlink("dir/big".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip_through_tar() {
+        let data_dir = tempdir().unwrap();
+        let fs = Fs::new();
+        let mut chunks = vec![];
+        for contents in [&b"hello "[..], b"world"] {
+            let (digest, _) = store_data(&fs, contents, data_dir.path()).unwrap();
+            chunks.push(ManifestChunk {
+                digest,
+                size: contents.len() as u64,
+            });
+        }
+        let (digest, _) = store_data(&fs, &b"0123456789"[..], data_dir.path()).unwrap();
+        let mut with_xattrs = entry(
+            "dir/inline",
+            3,
+            0o100600,
+            ManifestEntryData::File(ManifestFileData::Inline(b"abc".to_vec())),
+        );
+        with_xattrs
+            .metadata
+            .xattrs
+            .insert("user.comment".into(), b"hi".to_vec());
+        let entries = vec![
+            entry(
+                "dir",
+                0,
+                0o40755,
+                ManifestEntryData::Directory { opaque: true },
+            ),
+            with_xattrs,
+            entry(
+                "dir/digest",
+                10,
+                0o100644,
+                ManifestEntryData::File(ManifestFileData::Digest(digest)),
+            ),
+            entry("dir/gone", 0, 0o100644, ManifestEntryData::Whiteout),
+            entry(
+                "dir/symlink",
+                0,
+                0o120777,
+                ManifestEntryData::Symlink(b"inline".to_vec()),
+            ),
+            entry(
+                "dir/hardlink",
+                0,
+                0o100644,
+                ManifestEntryData::Hardlink("dir/digest".into()),
+            ),
+        ];
+        let mut manifest = vec![];
+        ManifestWriter::new(&mut manifest)
+            .unwrap()
+            .write_entries(&entries)
+            .unwrap();
+        let mut manifest_with_chunks = vec![];
+        ManifestWriter::new(&mut manifest_with_chunks)
+            .unwrap()
+            .write_entries(&[entry(
+                "chunked",
+                11,
+                0o100644,
+                ManifestEntryData::File(ManifestFileData::Chunked(chunks)),
+            )])
+            .unwrap();
+
+        let mut tar = vec![];
+        manifest_to_tar(io::Cursor::new(&manifest), &mut tar, Some(data_dir.path())).unwrap();
+        let mut round_tripped = vec![];
+        manifest_from_tar(&tar[..], &mut round_tripped, data_dir.path(), 5).unwrap();
+        assert_eq!(read_entries(&round_tripped), entries);
+
+        let mut tar = vec![];
+        manifest_to_tar(
+            io::Cursor::new(&manifest_with_chunks),
+            &mut tar,
+            Some(data_dir.path()),
+        )
+        .unwrap();
+        let mut archive = Archive::new(&tar[..]);
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"hello world");
+
+        let err =
+            manifest_to_tar(io::Cursor::new(&manifest_with_chunks), io::sink(), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the contents of chunked are stored by digest, but there is no data directory"
+        );
+    }
+}
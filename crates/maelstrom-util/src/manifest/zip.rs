@@ -0,0 +1,198 @@
+//! Conversion of zip files into manifests.
+
+use super::{tar::store_data, ManifestWriter};
+use crate::fs::Fs;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use maelstrom_base::{
+    manifest::{
+        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData, Mode,
+        UnixTimestamp,
+    },
+    Utf8PathBuf,
+};
+use std::collections::BTreeSet;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Convert the zip file read from `zip` into a manifest written to `manifest`.
+///
+/// Like with [`super::tar::manifest_from_tar`], regular files no bigger than `inline_limit` are
+/// stored inline in the manifest, and the contents of the other files are written to `data_dir`,
+/// named after their digests. The paths of those files are returned, so they can be uploaded.
+///
+/// Entries without Unix permissions, like those written by most Windows tools, are given mode
+/// 0o755 if they are directories and 0o644 otherwise. Modification times are taken to be UTC.
+pub fn manifest_from_zip(
+    zip: impl Read + Seek,
+    manifest: impl Write,
+    data_dir: &Path,
+    inline_limit: u64,
+) -> Result<Vec<PathBuf>> {
+    let fs = Fs::new();
+    let mut entries = vec![];
+    let mut data_paths = BTreeSet::new();
+    let mut archive = ZipArchive::new(zip)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        if file.enclosed_name().is_none() {
+            return Err(anyhow!("unsafe path {:?} in zip", file.name()));
+        }
+        let path = Utf8PathBuf::from(file.name().trim_end_matches('/'));
+        let mode = match file.unix_mode() {
+            Some(mode) => mode & 0o7777,
+            None if file.is_dir() => 0o755,
+            None => 0o644,
+        };
+        let mtime = match file.last_modified() {
+            Some(mtime) => NaiveDateTime::try_from(mtime)
+                .map(|mtime| mtime.and_utc().timestamp())
+                .unwrap_or_default(),
+            None => 0,
+        };
+        let metadata = ManifestEntryMetadata {
+            size: 0,
+            mode: Mode(mode),
+            mtime: UnixTimestamp(mtime),
+            xattrs: Default::default(),
+            owner: None,
+        };
+        let (metadata, data) = if file.is_dir() {
+            (metadata, ManifestEntryData::Directory { opaque: false })
+        } else if file.is_symlink() {
+            let mut target = vec![];
+            file.read_to_end(&mut target)?;
+            (metadata, ManifestEntryData::Symlink(target))
+        } else {
+            let size = file.size();
+            let data = if size == 0 {
+                ManifestFileData::Empty
+            } else if size <= inline_limit {
+                let mut data = vec![];
+                file.read_to_end(&mut data)?;
+                ManifestFileData::Inline(data)
+            } else {
+                let (digest, data_path) = store_data(&fs, &mut file, data_dir)?;
+                data_paths.insert(data_path);
+                ManifestFileData::Digest(digest)
+            };
+            (
+                ManifestEntryMetadata { size, ..metadata },
+                ManifestEntryData::File(data),
+            )
+        };
+        entries.push(ManifestEntry {
+            path,
+            metadata,
+            data,
+        });
+    }
+
+    let mut writer = ManifestWriter::new(manifest)?;
+    writer.write_entries(&entries)?;
+    Ok(data_paths.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestReader;
+    use maelstrom_base::Sha256Digest;
+    use sha2::{Digest as _, Sha256};
+    use std::io::{self, Cursor};
+    use tempfile::tempdir;
+    use zip::{write::SimpleFileOptions, DateTime, ZipWriter};
+
+    #[test]
+    fn from_zip() {
+        let mtime = DateTime::from_date_and_time(2024, 1, 11, 11, 11, 10).unwrap();
+        let options = SimpleFileOptions::default().last_modified_time(mtime);
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.add_directory("foo/", options.unix_permissions(0o750))
+            .unwrap();
+        zip.start_file("foo/small.txt", options.unix_permissions(0o640))
+            .unwrap();
+        zip.write_all(b"hi").unwrap();
+        zip.start_file("foo/big.txt", options).unwrap();
+        zip.write_all(b"hello world").unwrap();
+        zip.start_file("foo/empty", options).unwrap();
+        zip.add_symlink("foo/link", "small.txt", options).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let data_dir = tempdir().unwrap();
+        let mut manifest = vec![];
+        let data_paths =
+            manifest_from_zip(Cursor::new(zip), &mut manifest, data_dir.path(), 5).unwrap();
+        let entries: Vec<_> = ManifestReader::new(io::Cursor::new(manifest))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let digest = Sha256Digest::new(Sha256::digest(b"hello world").into());
+        assert_eq!(data_paths, vec![data_dir.path().join(digest.to_string())]);
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.path.as_str(),
+                    e.metadata.mode.0,
+                    e.metadata.size,
+                    e.metadata.mtime,
+                    e.data.clone(),
+                )
+            })
+            .collect();
+        let mtime = UnixTimestamp(1704971470);
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "foo",
+                    0o750,
+                    0,
+                    mtime,
+                    ManifestEntryData::Directory { opaque: false }
+                ),
+                (
+                    "foo/small.txt",
+                    0o640,
+                    2,
+                    mtime,
+                    ManifestEntryData::File(ManifestFileData::Inline(b"hi".to_vec()))
+                ),
+                (
+                    "foo/big.txt",
+                    0o644,
+                    11,
+                    mtime,
+                    ManifestEntryData::File(ManifestFileData::Digest(digest))
+                ),
+                (
+                    "foo/empty",
+                    0o644,
+                    0,
+                    mtime,
+                    ManifestEntryData::File(ManifestFileData::Empty)
+                ),
+                (
+                    "foo/link",
+                    0o777,
+                    0,
+                    mtime,
+                    ManifestEntryData::Symlink(b"small.txt".to_vec())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_zip_rejects_escaping_paths() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("../evil", SimpleFileOptions::default())
+            .unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let data_dir = tempdir().unwrap();
+        manifest_from_zip(Cursor::new(zip), vec![], data_dir.path(), 5).unwrap_err();
+    }
+}
@@ -192,7 +192,7 @@ pub fn clone_into_pid_and_user_namespace() -> Result<()> {
 
     // Clone a new process into new user, pid, and mount namespaces.
     let mut clone_args = CloneArgs::default()
-        .flags(CloneFlags::NEWUSER | CloneFlags::NEWPID)
+        .flags(CloneFlags::NEWUSER | CloneFlags::NEWPID | CloneFlags::NEWNS)
         .exit_signal(Signal::CHLD);
     match linux::clone3(&mut clone_args).context("cloning the second-generation process")? {
         Some(gen_1_pid) => {
@@ -6,12 +6,13 @@
 //! its dependencies carefully.
 #![no_std]
 
-use core::{cell::UnsafeCell, ffi::CStr, fmt::Write as _, result};
+use core::{cell::UnsafeCell, ffi::CStr, fmt::Write as _, ops::Range, ptr, result, time::Duration};
 use maelstrom_linux::{
-    self as linux, AccessMode, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno, Fd,
-    FileMode, FsconfigCommand, FsmountFlags, FsopenFlags, Gid, MountAttrs, MountFlags,
-    MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd, Sockaddr, SocketDomain, SocketProtocol,
-    SocketType, Uid, UmountFlags,
+    self as linux, AccessMode, Capability, CapabilitySet, CloneArgs, CloneFlags, CloseRangeFirst,
+    CloseRangeFlags, CloseRangeLast, Errno, ExitCode, Fd, FileMode, FsconfigCommand, FsmountFlags,
+    FsopenFlags, Gid, MountAttrs, MountFlags, MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd,
+    Pid, PollEvents, PollFd, Signal, SignalSet, SigprocmaskHow, Sockaddr, SocketDomain,
+    SocketProtocol, SocketType, Uid, UmountFlags, WaitResult, WaitStatus,
 };
 
 struct SliceFmt<'a> {
@@ -71,6 +72,12 @@ pub enum Syscall<'a> {
         fd: FdSlot<'a>,
         addr: &'a Sockaddr,
     },
+    CapAmbientRaise {
+        capability: Capability,
+    },
+    CapBoundingDrop {
+        capability: Capability,
+    },
     Chdir {
         path: &'a CStr,
     },
@@ -79,6 +86,11 @@ pub enum Syscall<'a> {
         last: CloseRangeLast,
         flags: CloseRangeFlags,
     },
+    /// Append the contents of the file at `from` to `to`. Do nothing if there is no file at `from`.
+    CopyFileIfExists {
+        from: &'a CStr,
+        to: FdSlot<'a>,
+    },
     Dup2 {
         from: Fd,
         to: Fd,
@@ -121,6 +133,17 @@ pub enum Syscall<'a> {
         gid: Gid,
         fuse_fd: FdSlot<'a>,
     },
+    /// Fork. The parent becomes a minimal init process and never returns: it forwards signals to
+    /// the child, reaps orphaned processes, and exits with the child's status when the child exits.
+    /// The child continues with the rest of the script in its own process group, which is made the
+    /// foreground process group of the controlling terminal if `tty` is true.
+    ///
+    /// If the child is killed by a signal, the init process writes the signal's number to
+    /// `signal_fd` before exiting, so that the worker can report the job as killed by it.
+    Init {
+        tty: bool,
+        signal_fd: Fd,
+    },
     IoctlTiocsctty {
         fd: Fd,
         arg: i32,
@@ -129,6 +152,12 @@ pub enum Syscall<'a> {
         path: &'a CStr,
         mode: FileMode,
     },
+    /// Make a directory at `path`. Do nothing if there's already something there, even if the file
+    /// system is read-only.
+    MkdirIfMissing {
+        path: &'a CStr,
+        mode: FileMode,
+    },
     Mount {
         source: Option<&'a CStr>,
         target: &'a CStr,
@@ -163,10 +192,25 @@ pub enum Syscall<'a> {
         fd: FdSlot<'a>,
         buf: &'a mut [u8],
     },
+    /// Receive a file descriptor from the worker over the exec result socket and dup2 it to `to`.
+    /// Pre-warmed sandboxes use this to get file descriptors that the worker created after cloning
+    /// them.
+    RecvFd {
+        to: Fd,
+    },
     SendMsg {
         buf: &'a [u8],
         fd_to_send: FdSlot<'a>,
     },
+    SetGroups {
+        groups: &'a [Gid],
+    },
+    SetInheritableCapabilities {
+        capabilities: CapabilitySet,
+    },
+    SetHostname {
+        name: &'a [u8],
+    },
     SetSid,
     Socket {
         domain: SocketDomain,
@@ -174,10 +218,26 @@ pub enum Syscall<'a> {
         protocol: SocketProtocol,
         out: FdSlot<'a>,
     },
+    /// Fork a child process with `flags` that runs the next `script_len` syscalls, which should end
+    /// with an exec. The parent skips over them. The child reports errors just like the process
+    /// running the whole script, using the syscall's index in the whole script. The pidfd for the
+    /// child is stored in `pidfd`.
+    Spawn {
+        flags: CloneFlags,
+        script_len: usize,
+        pidfd: FdSlot<'a>,
+    },
     Umount2 {
         path: &'a CStr,
         flags: UmountFlags,
     },
+    /// Repeatedly try to connect to `addr` until it succeeds, the process referred to by `pidfd`
+    /// exits, or `timeout` elapses.
+    WaitForListener {
+        addr: &'a Sockaddr,
+        pidfd: FdSlot<'a>,
+        timeout: Duration,
+    },
     Write {
         fd: FdSlot<'a>,
         buf: &'a [u8],
@@ -188,8 +248,25 @@ impl<'a> Syscall<'a> {
     fn call(&mut self, write_sock: &linux::UnixStream) -> result::Result<(), Errno> {
         match self {
             Syscall::Bind { fd, addr } => linux::bind(fd, addr),
+            Syscall::CapAmbientRaise { capability } => linux::prctl_cap_ambient_raise(*capability),
+            Syscall::CapBoundingDrop { capability } => {
+                match linux::prctl_capbset_drop(*capability) {
+                    // The kernel doesn't know about this capability, so it can't be in the
+                    // bounding set.
+                    Err(Errno::EINVAL) => Ok(()),
+                    res => res,
+                }
+            }
             Syscall::Chdir { path } => linux::chdir(path),
             Syscall::CloseRange { first, last, flags } => linux::close_range(*first, *last, *flags),
+            Syscall::CopyFileIfExists { from, to } => {
+                let from = match linux::open(from, OpenFlags::default(), FileMode::default()) {
+                    Err(Errno::ENOENT) => return Ok(()),
+                    from => from?,
+                };
+                while linux::sendfile(to, &from, 1 << 20)? > 0 {}
+                Ok(())
+            }
             Syscall::Dup2 { from, to } => linux::dup2(&*from, &*to).map(drop),
             Syscall::Execve { path, argv, envp } => linux::execve(path, argv, envp),
             Syscall::ExecveList {
@@ -248,8 +325,31 @@ impl<'a> Syscall<'a> {
                 let fstype = Some(c"fuse");
                 linux::mount(source, target, fstype, *flags, Some(options.as_slice()))
             }
+            Syscall::Init { tty, signal_fd } => {
+                // Block all signals before forking so that we don't miss any that are sent before
+                // the init process starts waiting for them.
+                let old_mask =
+                    linux::sigprocmask(SigprocmaskHow::SETMASK, Some(&SignalSet::full()))?;
+                let mut clone_args = CloneArgs::default().exit_signal(Signal::CHLD);
+                match linux::clone3(&mut clone_args)? {
+                    None => {
+                        let pid = linux::getpid();
+                        linux::setpgid(pid, pid)?;
+                        if *tty {
+                            linux::ioctl_tiocspgrp(&Fd::STDIN, pid)?;
+                        }
+                        linux::sigprocmask(SigprocmaskHow::SETMASK, Some(&old_mask))?;
+                        Ok(())
+                    }
+                    Some(child) => run_init(child, *signal_fd),
+                }
+            }
             Syscall::IoctlTiocsctty { fd, arg } => linux::ioctl_tiocsctty(fd, *arg),
             Syscall::Mkdir { path, mode } => linux::mkdir(path, *mode),
+            Syscall::MkdirIfMissing { path, mode } => match linux::mkdir(path, *mode) {
+                Err(Errno::EEXIST) => Ok(()),
+                res => res,
+            },
             Syscall::Mount {
                 source,
                 target,
@@ -284,11 +384,23 @@ impl<'a> Syscall<'a> {
             }
             Syscall::PivotRoot { new_root, put_old } => linux::pivot_root(new_root, put_old),
             Syscall::Read { fd, buf } => linux::read(fd, buf).map(drop),
+            Syscall::RecvFd { to } => match write_sock.recv_with_fd(&mut [0])? {
+                (_, Some(fd)) => linux::dup2(&fd, to).map(drop),
+                (_, None) => Err(Errno::EBADF),
+            },
             Syscall::SendMsg { buf, fd_to_send } => {
                 let count = write_sock.send_with_fd(buf, fd_to_send.get())?;
                 assert_eq!(count, buf.len());
                 Ok(())
             }
+            Syscall::SetGroups { groups } => linux::setgroups(groups),
+            Syscall::SetInheritableCapabilities { capabilities } => {
+                // We can only make a capability inheritable if it's in our permitted set.
+                let (effective, permitted, _) = linux::capget()?;
+                let inheritable = capabilities.intersection(&permitted);
+                linux::capset(effective, permitted, inheritable)
+            }
+            Syscall::SetHostname { name } => linux::sethostname(name),
             Syscall::SetSid => linux::setsid(),
             Syscall::Socket {
                 domain,
@@ -299,44 +411,157 @@ impl<'a> Syscall<'a> {
                 out.set(linux::socket(*domain, *type_, *protocol).map(OwnedFd::into_fd)?);
                 Ok(())
             }
+            Syscall::Spawn { .. } => panic!("spawns are run by run_script"),
             Syscall::Umount2 { path, flags } => linux::umount2(path, *flags),
+            Syscall::WaitForListener {
+                addr,
+                pidfd,
+                timeout,
+            } => {
+                const INTERVAL: Duration = Duration::from_millis(100);
+                let mut waited = Duration::ZERO;
+                loop {
+                    let sock = linux::socket(
+                        SocketDomain::INET,
+                        SocketType::STREAM | SocketType::CLOEXEC,
+                        SocketProtocol::default(),
+                    )?;
+                    if linux::connect(&sock, addr).is_ok() {
+                        return Ok(());
+                    }
+                    drop(sock);
+                    if waited >= *timeout {
+                        return Err(Errno::ETIMEDOUT);
+                    }
+                    // The pidfd becomes readable when the process exits.
+                    let mut fds = [PollFd::new(pidfd.get(), PollEvents::IN)];
+                    match linux::poll(&mut fds, INTERVAL) {
+                        Ok(0) | Err(Errno::EINTR) => {}
+                        Ok(_) => return Err(Errno::ECHILD),
+                        Err(errno) => return Err(errno),
+                    }
+                    waited += INTERVAL;
+                }
+            }
             Syscall::Write { fd, buf } => linux::write(fd, buf).map(drop),
         }
     }
 }
 
-/// The guts of the child code. This function shouldn't return on success, because in that case,
-/// the last syscall should be an execve. If this function returns, than an error was encountered.
-/// In that case, the script item index and the errno will be returned.
-fn start_and_exec_in_child_inner(
+/// The main loop of the init process started by [`Syscall::Init`].
+fn run_init(child: Pid, signal_fd: Fd) -> ! {
+    // Close all of our file descriptors except `signal_fd`. In particular, this closes our copy of
+    // the exec result socket, so that the worker will see EOF when the child execs. It also means
+    // we won't hold the job's stdout and stderr open.
+    let _ = linux::close_range(
+        CloseRangeFirst::Fd(Fd::STDIN),
+        CloseRangeLast::Fd(Fd::from_raw(signal_fd.as_c_int() - 1)),
+        CloseRangeFlags::default(),
+    );
+    let _ = linux::close_range(
+        CloseRangeFirst::Fd(Fd::from_raw(signal_fd.as_c_int() + 1)),
+        CloseRangeLast::Max,
+        CloseRangeFlags::default(),
+    );
+
+    let signals = SignalSet::full();
+    loop {
+        let Ok(signal) = linux::sigwait(&signals) else {
+            continue;
+        };
+        if signal != Signal::CHLD {
+            let _ = linux::kill(child, signal);
+            continue;
+        }
+        while let Ok(Some(WaitResult { pid, status })) = linux::wait_nohang() {
+            if pid == child {
+                // We can't kill ourselves with the same signal, even after resetting it to its
+                // default disposition, since the kernel drops signals sent to the init process of
+                // a PID namespace from inside the namespace unless it handles them. Instead, we
+                // tell the worker which signal it was. The exit code follows the shell convention
+                // in case the worker doesn't get the message.
+                linux::_exit(match status {
+                    WaitStatus::Exited(code) => code,
+                    WaitStatus::Signaled(signal) => {
+                        let _ = linux::write(&signal_fd, &[signal.as_u8()]);
+                        ExitCode::from_u8(128u8.saturating_add(signal.as_u8()))
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Run the script in `syscalls`. If a syscall fails, the script item index and the errno will be
+/// returned.
+fn run_script(
     write_sock: &linux::UnixStream,
     syscalls: &mut [Syscall],
-) -> (usize, Errno) {
-    for (index, syscall) in syscalls.iter_mut().enumerate() {
-        if let Err(errno) = syscall.call(write_sock) {
-            return (index, errno);
+) -> result::Result<(), (usize, Errno)> {
+    run_script_range(write_sock, syscalls, 0..syscalls.len())
+}
+
+/// Run the syscalls in `syscalls[range]`. Errors are reported with the index into all of
+/// `syscalls`, so a child started by [`Syscall::Spawn`] reports them the same way we do.
+fn run_script_range(
+    write_sock: &linux::UnixStream,
+    syscalls: &mut [Syscall],
+    range: Range<usize>,
+) -> result::Result<(), (usize, Errno)> {
+    let mut index = range.start;
+    while index < range.end {
+        if let Syscall::Spawn {
+            flags,
+            script_len,
+            pidfd,
+        } = syscalls[index]
+        {
+            let script = index + 1..index + 1 + script_len;
+            let mut clone_args = CloneArgs::default().flags(flags).exit_signal(Signal::CHLD);
+            match linux::clone3_with_child_pidfd(&mut clone_args).map_err(|errno| (index, errno))? {
+                None => match run_script_range(write_sock, syscalls, script) {
+                    Ok(()) => panic!("should not reach here"),
+                    Err(err) => report_error_and_exit(write_sock, err),
+                },
+                Some((_, child_pidfd)) => {
+                    pidfd.set(child_pidfd.into_fd());
+                    index = script.end;
+                }
+            }
+        } else {
+            syscalls[index]
+                .call(write_sock)
+                .map_err(|errno| (index, errno))?;
+            index += 1;
         }
     }
-    panic!("should not reach here");
+    Ok(())
+}
+
+/// Write an 8-byte value to `write_sock` describing the error in little-endian format, then exit.
+/// The upper 32 bits will be the index in the script of the syscall that errored, and the lower 32
+/// bits will be the errno value.
+fn report_error_and_exit(write_sock: &linux::UnixStream, (index, errno): (usize, Errno)) -> ! {
+    let result = (index as u64) << 32 | errno.as_u64();
+    // There's not really much to do if this write fails. Therefore, we just ignore the result.
+    // However, it's hard to imagine any case where this could fail and we'd actually care.
+    let _ = write_sock.send(result.to_ne_bytes().as_slice());
+    linux::_exit(linux::ExitCode::from_u8(1));
 }
 
 /// Run the provided syscall script in `syscalls`.
 ///
 /// It is assumed that the last syscall won't return (i.e. will be `execve`). If there is an error,
-/// write an 8-byte value to `write_sock` describing the error in little-endian format.
-/// The upper 32 bits will be the index in the script of the syscall that errored, and the lower 32
-/// bits will be the errno value.
+/// it is reported over `write_sock` as described in [`report_error_and_exit`].
 ///
 /// The caller should ensure that `write_sock` is marked close-on-exec. This way, upon
 /// normal completion, no bytes will be written to the file descriptor and the worker can
 /// distinguish between an error and no error.
 pub fn start_and_exec_in_child(write_sock: linux::UnixStream, syscalls: &mut [Syscall]) -> ! {
-    let (index, errno) = start_and_exec_in_child_inner(&write_sock, syscalls);
-    let result = (index as u64) << 32 | errno.as_u64();
-    // There's not really much to do if this write fails. Therefore, we just ignore the result.
-    // However, it's hard to imagine any case where this could fail and we'd actually care.
-    let _ = write_sock.send(result.to_ne_bytes().as_slice());
-    linux::_exit(linux::ExitCode::from_u8(1));
+    match run_script(&write_sock, syscalls) {
+        Ok(()) => panic!("should not reach here"),
+        Err(err) => report_error_and_exit(&write_sock, err),
+    }
 }
 
 pub struct ChildArgs<'a, 'b> {
@@ -351,3 +576,80 @@ pub extern "C" fn start_and_exec_in_child_trampoline(arg: *mut core::ffi::c_void
         args.syscalls,
     )
 }
+
+/// Where the worker puts the rest of a pre-warmed sandbox's script before waking it up.
+pub struct ScriptSlot(UnsafeCell<(*mut Syscall<'static>, usize)>);
+
+impl Default for ScriptSlot {
+    fn default() -> Self {
+        Self(UnsafeCell::new((ptr::null_mut(), 0)))
+    }
+}
+
+impl ScriptSlot {
+    /// Set the script that the child will run when it's woken up.
+    ///
+    /// # Safety
+    ///
+    /// The child must not have been woken up yet, and `script` must stay alive until the child
+    /// execs or exits.
+    pub unsafe fn set(&self, script: &mut [Syscall<'_>]) {
+        *self.0.get() = (script.as_mut_ptr().cast(), script.len());
+    }
+
+    fn get(&self) -> *mut [Syscall<'static>] {
+        let (script, len) = unsafe { *self.0.get() };
+        ptr::slice_from_raw_parts_mut(script, len)
+    }
+}
+
+/// The file descriptor that a pre-warmed sandbox moves its exec result socket to.
+pub const WARM_CHILD_SOCK: Fd = Fd::from_raw(3);
+
+/// Run the provided syscall script in `syscalls`, then wait to be woken up before running the
+/// script in `continuation`, which should end with an exec.
+///
+/// Before running the first script, the exec result socket is moved to [`WARM_CHILD_SOCK`] and all
+/// other file descriptors except stdin, stdout, and stderr are closed. Otherwise, while waiting, we
+/// would hold open whatever the worker had open when it cloned us, like other jobs' pipes.
+///
+/// Errors in either script are reported just like in [`start_and_exec_in_child`]. Once the first
+/// script has completed, a single byte is written to the exec result socket. The worker wakes us up
+/// by writing a single byte back. If the worker closes the socket instead, we just exit.
+pub fn start_warm_and_exec_in_child(
+    write_sock: Fd,
+    syscalls: &mut [Syscall],
+    continuation: &ScriptSlot,
+) -> ! {
+    if linux::dup2(&write_sock, &WARM_CHILD_SOCK).is_err()
+        || linux::close_range(
+            CloseRangeFirst::Fd(Fd::from_raw(WARM_CHILD_SOCK.as_c_int() + 1)),
+            CloseRangeLast::Max,
+            CloseRangeFlags::default(),
+        )
+        .is_err()
+    {
+        linux::_exit(ExitCode::from_u8(1));
+    }
+    let write_sock = linux::UnixStream::from(OwnedFd::from_fd(WARM_CHILD_SOCK));
+    if let Err(err) = run_script(&write_sock, syscalls) {
+        report_error_and_exit(&write_sock, err);
+    }
+    if write_sock.send(&[0]).is_err()
+        || !matches!(linux::read(&write_sock.as_fd(), &mut [0]), Ok(1))
+    {
+        linux::_exit(ExitCode::from_u8(1));
+    }
+    start_and_exec_in_child(write_sock, unsafe { &mut *continuation.get() })
+}
+
+pub struct WarmChildArgs<'a, 'b> {
+    pub write_sock: linux::Fd,
+    pub syscalls: &'a mut [Syscall<'b>],
+    pub continuation: &'a ScriptSlot,
+}
+
+pub extern "C" fn start_warm_and_exec_in_child_trampoline(arg: *mut core::ffi::c_void) -> i32 {
+    let args = unsafe { &mut *(arg as *mut WarmChildArgs<'_, '_>) };
+    start_warm_and_exec_in_child(args.write_sock, args.syscalls, args.continuation)
+}
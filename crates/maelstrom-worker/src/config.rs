@@ -1,11 +1,30 @@
 use crate::WorkerCacheDir;
+use clap::ValueEnum;
 use maelstrom_macro::Config;
 use maelstrom_util::{
     config::common::{BrokerAddr, CacheSize, InlineLimit, LogLevel, Slots},
     root::RootBuf,
 };
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
 use xdg::BaseDirectories;
 
+/// How the worker stacks a job's layers into the file systems the job sees.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, EnumString, Eq, PartialEq, Serialize, ValueEnum,
+)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum LayerStacking {
+    /// Serve each layer stack with LayerFS over FUSE.
+    #[default]
+    Fuse,
+
+    /// Unpack each layer into a directory in the cache, and stack them with overlayfs.
+    Overlayfs,
+}
+
 #[derive(Config, Debug)]
 pub struct Config {
     /// Socket address of broker.
@@ -33,10 +52,31 @@ pub struct Config {
     #[config(value_name = "BYTES", default = "CacheSize::default()")]
     pub cache_size: CacheSize,
 
+    /// The target amount of memory to use for job volumes. This bound won't be followed
+    /// strictly, so it's best to be conservative. SI and binary suffixes are supported.
+    #[config(value_name = "BYTES", default = "CacheSize::default()")]
+    pub volume_cache_size: CacheSize,
+
     /// The maximum amount of bytes to return inline for captured stdout and stderr.
     #[config(value_name = "BYTES", default = "InlineLimit::default()")]
     pub inline_limit: InlineLimit,
 
+    /// Host devices, like `/dev/fuse` or `/dev/net/tun`, that jobs may mount. Jobs that mount a
+    /// host device will only be scheduled on workers that allow it.
+    #[config(list, value_name = "PATH", default = r#""no devices""#)]
+    pub allowed_devices: Vec<String>,
+
+    /// The number of pre-warmed sandboxes to keep ready for jobs. A sandbox has everything that
+    /// doesn't depend on the details of a job already set up, which cuts down on job startup
+    /// latency. A value of 0 disables the pool.
+    #[config(option, value_name = "N", default = r#""the number of slots""#)]
+    pub sandbox_pool_size: Option<usize>,
+
+    /// How to stack a job's layers: with LayerFS over FUSE, or with overlayfs. Overlayfs doesn't
+    /// need `/dev/fuse`, but it needs Linux 6.5 or later, and it unpacks every layer into the cache.
+    #[config(value_name = "STACKING", default = r#""fuse""#)]
+    pub layer_stacking: LayerStacking,
+
     /// Minimum log level to output.
     #[config(short = 'l', value_name = "LEVEL", default = r#""info""#)]
     pub log_level: LogLevel,
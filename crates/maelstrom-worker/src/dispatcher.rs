@@ -3,12 +3,16 @@
 
 mod tracker;
 
-use crate::types::{CacheKey, CacheKeyKind};
+use crate::{
+    executor::SandboxKey,
+    types::{CacheKey, CacheKeyKind},
+    volume, MAX_ARTIFACT_FETCHES,
+};
 use anyhow::{Error, Result};
 use maelstrom_base::{
     proto::{BrokerToWorker, WorkerToBroker},
-    ArtifactType, JobCompleted, JobError, JobId, JobOutcome, JobResult, JobSpec, JobWorkerStatus,
-    Sha256Digest,
+    ArtifactType, JobCompleted, JobError, JobId, JobMount, JobOutcome, JobResult, JobSpec,
+    JobVolumeMode, JobWorkerStatus, Sha256Digest, Utf8PathBuf,
 };
 use maelstrom_util::{
     cache::{fs::Fs, GetArtifact, GotArtifact},
@@ -16,9 +20,11 @@ use maelstrom_util::{
     duration,
     ext::OptionExt as _,
 };
+use sha2::{Digest as _, Sha256};
 use std::{
-    cmp::Ordering,
-    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+    cmp::{self, Ordering},
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet, VecDeque},
+    mem,
     path::{Path, PathBuf},
     result,
     time::Duration,
@@ -38,7 +44,7 @@ use tracker::{FetcherResult, LayerTracker};
 #[derive(Debug)]
 pub enum Message<FsT: Fs> {
     /// A message from the broker. These messages enqueue and cancel jobs.
-    Broker(BrokerToWorker),
+    Broker(Box<BrokerToWorker>),
 
     /// A message notifying the dispatcher that a job has completed. The dispatcher starts jobs by
     /// calling [`Deps::start_job`], and expects each call to eventually result in one of these
@@ -68,12 +74,29 @@ pub enum Message<FsT: Fs> {
     /// messages.
     BuiltUpperFsLayer(Sha256Digest, Result<GotArtifact<FsT>>),
 
+    /// A message notifying the dispatcher that the building of a compacted FS layer has
+    /// completed. The dispatcher starts building of compacted FS layers by calling
+    /// [`Deps::build_compacted_fs_layer`], and expects each call to eventually result in one of
+    /// these messages.
+    BuiltCompactedFsLayer(Sha256Digest, Result<GotArtifact<FsT>>),
+
     /// A message notifying the dispatcher that the reading of digests from a manifest has
     /// completed. The dispatcher starts reading digetss from a manifest by calling
     /// [`Deps::read_manifest_digests`], and expects each call to eventually result in one of these
     /// messages.
     ReadManifestDigests(Sha256Digest, JobId, Result<HashSet<Sha256Digest>>),
 
+    /// A message notifying the dispatcher that the creation of a job volume has completed. The
+    /// dispatcher starts creation of volumes by calling [`Deps::create_volume`], and expects each
+    /// call to eventually result in one of these messages.
+    CreatedVolume(Sha256Digest, Result<GotArtifact<FsT>>),
+
+    /// A message saying that a running job is trying to read a file whose contents haven't been
+    /// fetched yet. If the blob is waiting to be prefetched, the dispatcher fetches it right away.
+    /// Either way, it calls [`Deps::blob_fetch_finished`] once there's no fetch for the blob in
+    /// flight.
+    BlobNeeded(Sha256Digest),
+
     /// A message notifying the dispatcher that it must enter the shutdown state. In this state,
     /// the dispatcher will schedule no new work, but will continue to process job completions. The
     /// sender can check [`Dispatcher::num_executing`] to know when all jobs have completed.
@@ -89,13 +112,14 @@ where
     CacheT: Cache,
 {
     /// Create a new [`Dispatcher`] with the provided slot count. The slot count must be a positive
-    /// number.
+    /// number. At most `sandbox_pool_size` pre-warmed sandboxes will be kept around for jobs.
     pub fn new(
         deps: DepsT,
         artifact_fetcher: ArtifactFetcherT,
         broker_sender: BrokerSenderT,
         cache: CacheT,
         slots: Slots,
+        sandbox_pool_size: usize,
     ) -> Self {
         Dispatcher {
             deps,
@@ -103,9 +127,15 @@ where
             broker_sender,
             cache,
             slots: slots.into_inner().into(),
+            sandbox_pool_size,
+            warm_sandboxes: VecDeque::default(),
             awaiting_layers: HashMap::default(),
             available: BinaryHeap::default(),
             executing: HashMap::default(),
+            volumes_in_use: HashMap::default(),
+            lazy_blobs: HashMap::default(),
+            retried_lazy_blobs: HashSet::default(),
+            prefetcher: Prefetcher::default(),
         }
     }
 
@@ -113,10 +143,10 @@ where
     /// [`Message`] for more information.
     pub fn receive_message(&mut self, msg: Message<CacheT::Fs>) {
         match msg {
-            Message::Broker(BrokerToWorker::EnqueueJob(jid, spec)) => {
-                self.receive_enqueue_job(jid, spec)
-            }
-            Message::Broker(BrokerToWorker::CancelJob(jid)) => self.receive_cancel_job(jid),
+            Message::Broker(msg) => match *msg {
+                BrokerToWorker::EnqueueJob(jid, spec) => self.receive_enqueue_job(jid, spec),
+                BrokerToWorker::CancelJob(jid) => self.receive_cancel_job(jid),
+            },
             Message::JobCompleted(jid, result) => self.receive_job_completed(jid, result),
             Message::JobTimer(jid) => self.receive_job_timer(jid),
             Message::ArtifactFetchCompleted(digest, Ok(artifact)) => {
@@ -137,12 +167,25 @@ where
             Message::BuiltUpperFsLayer(digest, Err(err)) => {
                 self.receive_build_upper_fs_layer_failure(digest, err)
             }
+            Message::BuiltCompactedFsLayer(digest, Ok(artifact)) => {
+                self.receive_build_compacted_fs_layer_success(digest, artifact)
+            }
+            Message::BuiltCompactedFsLayer(digest, Err(err)) => {
+                self.receive_build_compacted_fs_layer_failure(digest, err)
+            }
             Message::ReadManifestDigests(digest, jid, Ok(digests)) => {
                 self.receive_read_manifest_digests_success(digest, jid, digests)
             }
             Message::ReadManifestDigests(digest, jid, Err(err)) => {
                 self.receive_read_manifest_digests_failure(digest, jid, err)
             }
+            Message::CreatedVolume(digest, Ok(artifact)) => {
+                self.receive_create_volume_success(digest, artifact)
+            }
+            Message::CreatedVolume(digest, Err(err)) => {
+                self.receive_create_volume_failure(digest, err)
+            }
+            Message::BlobNeeded(digest) => self.receive_blob_needed(digest),
             Message::Shutdown(_) => self.receive_shutdown(),
         }
     }
@@ -161,8 +204,24 @@ pub trait Deps {
     type JobHandle;
 
     /// Start a new job. The dispatcher expects a [`Message::JobCompleted`] message when the job
-    /// completes.
-    fn start_job(&mut self, jid: JobId, spec: JobSpec, path: PathBuf) -> Self::JobHandle;
+    /// completes. If `sandbox` is provided, the job should be run in it if it's ready.
+    fn start_job(
+        &mut self,
+        jid: JobId,
+        spec: JobSpec,
+        path: PathBuf,
+        mount_layer_paths: Vec<PathBuf>,
+        sandbox: Option<Self::SandboxHandle>,
+    ) -> Self::JobHandle;
+
+    /// The sandbox handle should kill a pre-warmed sandbox when it is dropped, whether or not the
+    /// sandbox is ready yet.
+    type SandboxHandle;
+
+    /// Start a pre-warmed sandbox for jobs like the one with `spec`, with its root file system
+    /// served from the layer stack at `path`. The sandbox stays around until the handle is dropped
+    /// or given to [`Deps::start_job`].
+    fn start_sandbox(&mut self, spec: JobSpec, path: PathBuf) -> Self::SandboxHandle;
 
     /// The timer handle should cancel an outstanding timer when it is dropped. It must be safe to
     /// drop this handle after the timer has completed. Dropping this handle may or may not result
@@ -190,8 +249,22 @@ pub trait Deps {
         upper_layer_path: PathBuf,
     );
 
+    /// Start a task that will build a layer-fs bottom layer by flattening a stack of layers.
+    fn build_compacted_fs_layer(&mut self, digest: Sha256Digest, layer_path: PathBuf);
+
     /// Start a task to read the digests out of the given path to a manfiest.
     fn read_manifest_digests(&mut self, digest: Sha256Digest, path: PathBuf, jid: JobId);
+
+    /// Start a task that will create the empty directory for a job volume. Jobs won't be able to
+    /// store more than `size` bytes in it. The volume will take up `size` bytes in the cache,
+    /// regardless of how much of it is actually used.
+    fn create_volume(&mut self, digest: Sha256Digest, size: u64);
+
+    /// Wake up anything waiting for the given blob to be fetched. This is called whenever a blob
+    /// fetch completes, successfully or not, and in response to [`Message::BlobNeeded`] when
+    /// there's no fetch in flight. If the blob couldn't be fetched, everything waiting for it
+    /// should be given the error.
+    fn blob_fetch_finished(&mut self, digest: Sha256Digest, result: result::Result<(), String>);
 }
 
 /// The artifact fetcher is split out of [`Deps`] for convenience. Artifact fetching is different
@@ -223,6 +296,7 @@ pub trait Cache {
         artifact: Sha256Digest,
         jid: JobId,
     ) -> GetArtifact;
+    fn get_artifact_if_present(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) -> bool;
     fn got_artifact_failure(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) -> Vec<JobId>;
     fn got_artifact_success(
         &mut self,
@@ -232,6 +306,7 @@ pub trait Cache {
     ) -> result::Result<Vec<JobId>, (Error, Vec<JobId>)>;
     fn decrement_ref_count(&mut self, kind: CacheKeyKind, digest: &Sha256Digest);
     fn cache_path(&self, kind: CacheKeyKind, digest: &Sha256Digest) -> PathBuf;
+    fn is_over_target(&self, kind: CacheKeyKind) -> bool;
 }
 
 /*             _            _
@@ -243,6 +318,50 @@ pub trait Cache {
  *  FIGLET: private
  */
 
+/// The digest used as the cache key for the job volume with the given name.
+///
+/// Only the name is used, so volumes are shared between all of the clients that use this worker.
+/// This is intentional: the client ids assigned by the broker change every time a client
+/// connects, and volumes are meant to be reused by later runs. Clients are expected to keep their
+/// volumes apart by choosing distinct names.
+pub fn volume_digest(name: &str) -> Sha256Digest {
+    Sha256Digest::new(Sha256::digest(name.as_bytes()).into())
+}
+
+/// How a job uses one of its volumes. If a job mounts the same volume more than once, the mounts
+/// are combined: the volume is locked exclusively if any mount asks for it, and the largest size
+/// is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct JobVolume {
+    mode: JobVolumeMode,
+    size: u64,
+}
+
+fn job_volumes(spec: &JobSpec) -> HashMap<Sha256Digest, JobVolume> {
+    let mut volumes = HashMap::<_, JobVolume>::new();
+    for (name, size, mode) in spec.volumes() {
+        match volumes.entry(volume_digest(name)) {
+            Entry::Vacant(entry) => {
+                entry.insert(JobVolume { mode, size });
+            }
+            Entry::Occupied(mut entry) => {
+                let volume = entry.get_mut();
+                if mode == JobVolumeMode::Exclusive {
+                    volume.mode = mode;
+                }
+                volume.size = cmp::max(volume.size, size);
+            }
+        }
+    }
+    volumes
+}
+
+/// How a volume is being used by executing jobs.
+enum VolumeInUse {
+    Shared(usize),
+    Exclusive,
+}
+
 /// This struct represents a job where there's still work to do to fetch and/or build layers. These
 /// jobs sit on the sideline until we have their layers ready. At that point, they become
 /// `AvailableJob`s.
@@ -259,7 +378,10 @@ struct AvailableJob {
     jid: JobId,
     spec: JobSpec,
     path: PathBuf,
+    mount_layer_paths: Vec<PathBuf>,
     cache_keys: HashSet<CacheKey>,
+    volumes: HashMap<Sha256Digest, JobVolume>,
+    sandbox_key: Option<SandboxKey>,
 }
 
 impl PartialEq for AvailableJob {
@@ -314,11 +436,91 @@ enum ExecutingJobState<DepsT: Deps> {
     TimedOut,
 }
 
+/// The blobs a job started without, because LayerFS will fetch them on demand. When one of them
+/// arrives, the job holds on to it until it is done.
+#[derive(Default)]
+struct LazyBlobs {
+    pending: HashSet<Sha256Digest>,
+    got: HashSet<Sha256Digest>,
+}
+
+/// Blobs that jobs can start without are fetched in the background. Only a few of these prefetches
+/// are in flight at a time, so that a blob that a job needs right away doesn't get stuck behind the
+/// rest of them.
+#[derive(Default)]
+struct Prefetcher {
+    /// Every blob fetch in flight, whether it's a prefetch or not.
+    fetching: HashSet<Sha256Digest>,
+    /// The prefetches in flight.
+    prefetching: HashSet<Sha256Digest>,
+    /// The prefetches that haven't been started yet, in the order they were asked for.
+    queued: VecDeque<Sha256Digest>,
+}
+
+impl Prefetcher {
+    /// Start fetching a blob right away.
+    fn fetch(&mut self, digest: Sha256Digest, artifact_fetcher: &mut impl ArtifactFetcher) {
+        self.fetching.insert(digest.clone());
+        artifact_fetcher.start_artifact_fetch(digest);
+    }
+
+    /// Fetch a blob in the background.
+    fn prefetch(&mut self, digest: Sha256Digest, artifact_fetcher: &mut impl ArtifactFetcher) {
+        self.queued.push_back(digest);
+        self.start_queued(artifact_fetcher);
+    }
+
+    /// Start the given blob's fetch if its prefetch is still queued. Return whether there is a
+    /// fetch for it in flight.
+    fn prioritize(
+        &mut self,
+        digest: &Sha256Digest,
+        artifact_fetcher: &mut impl ArtifactFetcher,
+    ) -> bool {
+        if let Some(index) = self.queued.iter().position(|queued| queued == digest) {
+            let digest = self.queued.remove(index).unwrap();
+            self.fetch(digest, artifact_fetcher);
+        }
+        self.fetching.contains(digest)
+    }
+
+    fn fetch_completed(
+        &mut self,
+        digest: &Sha256Digest,
+        artifact_fetcher: &mut impl ArtifactFetcher,
+    ) {
+        self.fetching.remove(digest);
+        self.prefetching.remove(digest);
+        self.start_queued(artifact_fetcher);
+    }
+
+    fn start_queued(&mut self, artifact_fetcher: &mut impl ArtifactFetcher) {
+        while self.prefetching.len() < MAX_ARTIFACT_FETCHES {
+            let Some(digest) = self.queued.pop_front() else {
+                break;
+            };
+            self.prefetching.insert(digest.clone());
+            self.fetch(digest, artifact_fetcher);
+        }
+    }
+}
+
 /// This struct represents an executing job. It is created when we call `start_job` on our deps,
 /// and destroyed when we get a `Message::JobCompleted`.
 struct ExecutingJob<DepsT: Deps> {
     state: ExecutingJobState<DepsT>,
     cache_keys: HashSet<CacheKey>,
+    /// The key and spec to start a warm sandbox with once the job completes, if it can have one.
+    sandbox: Option<(SandboxKey, JobSpec)>,
+}
+
+/// A pre-warmed sandbox. It has the layer stack for its root file system mounted, so it holds on
+/// to the stack's cache entries until it is killed or given to a job. It takes up a slot that no
+/// job is using.
+struct WarmSandbox<DepsT: Deps> {
+    key: SandboxKey,
+    _handle: DepsT::SandboxHandle,
+    cache_keys: Vec<CacheKey>,
 }
 
 /// Manage jobs based on the slot count and requests from the broker. If the broker sends more job
@@ -332,9 +534,18 @@ pub struct Dispatcher<DepsT: Deps, ArtifactFetcherT, BrokerSenderT, CacheT> {
     broker_sender: BrokerSenderT,
     cache: CacheT,
     slots: usize,
+    sandbox_pool_size: usize,
+    /// The pre-warmed sandboxes, oldest first.
+    warm_sandboxes: VecDeque<WarmSandbox<DepsT>>,
     awaiting_layers: HashMap<JobId, AwaitingLayersJob>,
     available: BinaryHeap<AvailableJob>,
     executing: HashMap<JobId, ExecutingJob<DepsT>>,
+    volumes_in_use: HashMap<Sha256Digest, VolumeInUse>,
+    /// Lazy blobs for jobs that are available or executing.
+    lazy_blobs: HashMap<JobId, LazyBlobs>,
+    /// Lazy blobs whose fetch failed once and is being retried.
+    retried_lazy_blobs: HashSet<Sha256Digest>,
+    prefetcher: Prefetcher,
 }
 
 impl<DepsT, ArtifactFetcherT, BrokerSenderT, CacheT>
@@ -345,31 +556,103 @@ where
     BrokerSenderT: BrokerSender,
     CacheT: Cache,
 {
-    /// Start at most one job, depending on whether there are any queued jobs and if there are any
-    /// available slots.
+    /// Return whether all of the given volumes can be locked right now.
+    fn volumes_available(&self, volumes: &HashMap<Sha256Digest, JobVolume>) -> bool {
+        volumes.iter().all(|(digest, volume)| {
+            match (self.volumes_in_use.get(digest), volume.mode) {
+                (None, _) => true,
+                (Some(VolumeInUse::Shared(_)), JobVolumeMode::Shared) => true,
+                (Some(_), _) => false,
+            }
+        })
+    }
+
+    fn lock_volumes(&mut self, volumes: &HashMap<Sha256Digest, JobVolume>) {
+        for (digest, volume) in volumes {
+            match (self.volumes_in_use.entry(digest.clone()), volume.mode) {
+                (Entry::Vacant(entry), JobVolumeMode::Shared) => {
+                    entry.insert(VolumeInUse::Shared(1));
+                }
+                (Entry::Vacant(entry), JobVolumeMode::Exclusive) => {
+                    entry.insert(VolumeInUse::Exclusive);
+                }
+                (Entry::Occupied(mut entry), JobVolumeMode::Shared) => {
+                    let VolumeInUse::Shared(count) = entry.get_mut() else {
+                        panic!("volume {digest} unexpectedly locked exclusively");
+                    };
+                    *count += 1;
+                }
+                (Entry::Occupied(_), JobVolumeMode::Exclusive) => {
+                    panic!("volume {digest} unexpectedly in use");
+                }
+            }
+        }
+    }
+
+    /// Unlock a volume used by a job that has completed, and release the job's reference to it.
+    fn release_volume(&mut self, digest: Sha256Digest) {
+        let Entry::Occupied(mut entry) = self.volumes_in_use.entry(digest.clone()) else {
+            panic!("volume {digest} unexpectedly not in use");
+        };
+        match entry.get_mut() {
+            VolumeInUse::Shared(count) if *count > 1 => {
+                *count -= 1;
+            }
+            _ => {
+                entry.remove();
+            }
+        }
+        self.cache
+            .decrement_ref_count(CacheKeyKind::Volume, &digest);
+    }
+
+    /// Start at most one job, depending on whether there are any queued jobs, if there are any
+    /// available slots, and if the volumes needed by the jobs are available.
     fn possibly_start_job(&mut self) -> bool {
         if self.executing.len() >= self.slots {
             return false;
         }
+        let mut blocked = vec![];
+        let job = loop {
+            match self.available.pop() {
+                None => break None,
+                Some(job) if self.volumes_available(&job.volumes) => break Some(job),
+                Some(job) => blocked.push(job),
+            }
+        };
+        self.available.extend(blocked);
         let Some(AvailableJob {
             jid,
             spec,
             path,
+            mount_layer_paths,
             cache_keys,
-        }) = self.available.pop()
+            volumes,
+            sandbox_key,
+        }) = job
         else {
             return false;
         };
+        self.lock_volumes(&volumes);
+        let sandbox = sandbox_key.as_ref().and_then(|key| self.take_sandbox(key));
+        if sandbox.is_none() && self.executing.len() + self.warm_sandboxes.len() >= self.slots {
+            // Warm sandboxes only get slots that jobs don't need.
+            self.kill_oldest_sandbox();
+        }
         let timer_handle = spec
             .timeout
             .map(|timeout| self.deps.start_timer(jid, Duration::from(timeout)));
-        let job_handle = self.deps.start_job(jid, spec, path);
+        let sandbox_spec = sandbox_key.map(|key| (key, spec.clone()));
+        let job_handle = self
+            .deps
+            .start_job(jid, spec, path, mount_layer_paths, sandbox);
         let executing_job = ExecutingJob {
             state: ExecutingJobState::Nominal {
                 _job_handle: job_handle,
                 _timer_handle: timer_handle,
             },
             cache_keys,
+            sandbox: sandbox_spec,
         };
         self.executing.insert(jid, executing_job).assert_is_none();
         self.broker_sender
@@ -381,13 +664,54 @@ where
     }
 
     /// Put a job on the available jobs queue. At this point, it must have all of its artifacts.
-    fn make_job_available(&mut self, jid: JobId, spec: JobSpec, tracker: LayerTracker) {
+    fn make_job_available(&mut self, jid: JobId, mut spec: JobSpec, tracker: LayerTracker) {
+        let mount_layer_paths = tracker.mount_layer_paths();
+        let volumes = job_volumes(&spec);
+
+        // The executor doesn't know anything about volumes. Each volume mount is turned into a
+        // writable bind mount of the volume's data directory in the cache.
+        for mount in &mut spec.mounts {
+            if let JobMount::Volume {
+                name, mount_point, ..
+            } = mount
+            {
+                let local_path = tracker
+                    .volume_path(&volume_digest(name))
+                    .join(volume::DATA_DIR);
+                *mount = JobMount::Bind {
+                    mount_point: mem::take(mount_point),
+                    local_path: Utf8PathBuf::from_path_buf(local_path)
+                        .expect("cache paths should be UTF-8"),
+                    read_only: false,
+                };
+            }
+        }
+
+        if !tracker.lazy_artifacts().is_empty() {
+            let pending = tracker.lazy_artifacts().clone();
+            self.lazy_blobs
+                .insert(
+                    jid,
+                    LazyBlobs {
+                        pending,
+                        ..Default::default()
+                    },
+                )
+                .assert_is_none();
+        }
+
         let (path, cache_keys) = tracker.into_path_and_cache_keys();
+        let sandbox_key = (self.sandbox_pool_size > 0)
+            .then(|| SandboxKey::new(&spec, path.clone()))
+            .flatten();
         self.available.push(AvailableJob {
             jid,
             spec,
             path,
+            mount_layer_paths,
             cache_keys,
+            volumes,
+            sandbox_key,
         });
         if !self.possibly_start_job() {
             self.broker_sender
@@ -403,9 +727,17 @@ where
             deps: &mut self.deps,
             artifact_fetcher: &mut self.artifact_fetcher,
             cache: &mut self.cache,
+            prefetcher: &mut self.prefetcher,
             jid,
         };
-        let tracker = LayerTracker::new(&spec.layers, &mut fetcher);
+        let mount_layers: Vec<_> = spec.mount_layers().cloned().collect();
+        let mut tracker = LayerTracker::new(&spec.layers, &mount_layers, &mut fetcher);
+        tracker.fetch_volumes(
+            job_volumes(&spec)
+                .into_iter()
+                .map(|(digest, volume)| (digest, volume.size)),
+            &mut fetcher,
+        );
         if tracker.is_complete() {
             self.make_job_available(jid, spec, tracker);
         } else {
@@ -447,14 +779,85 @@ where
             for CacheKey { kind, digest } in keys_to_drop {
                 self.cache.decrement_ref_count(kind, &digest);
             }
+            self.release_lazy_blobs(jid);
+        }
+    }
+
+    /// Release the references a job took to blobs that arrived after it stopped waiting for
+    /// layers.
+    fn release_lazy_blobs(&mut self, jid: JobId) {
+        if let Some(LazyBlobs { got, .. }) = self.lazy_blobs.remove(&jid) {
+            for digest in got {
+                self.cache.decrement_ref_count(CacheKeyKind::Blob, &digest);
+            }
+        }
+    }
+
+    /// Remove and return the handle for the newest warm sandbox with the given key, if there is
+    /// one. The sandbox's references to its cache entries are released: the job it is given to
+    /// has its own.
+    fn take_sandbox(&mut self, key: &SandboxKey) -> Option<DepsT::SandboxHandle> {
+        let index = self
+            .warm_sandboxes
+            .iter()
+            .rposition(|sandbox| &sandbox.key == key)?;
+        let WarmSandbox {
+            _handle: handle,
+            cache_keys,
+            ..
+        } = self.warm_sandboxes.remove(index).unwrap();
+        for CacheKey { kind, digest } in cache_keys {
+            self.cache.decrement_ref_count(kind, &digest);
+        }
+        Some(handle)
+    }
+
+    /// Kill the oldest warm sandbox, if there is one, and release its cache entries.
+    fn kill_oldest_sandbox(&mut self) {
+        if let Some(WarmSandbox { cache_keys, .. }) = self.warm_sandboxes.pop_front() {
+            for CacheKey { kind, digest } in cache_keys {
+                self.cache.decrement_ref_count(kind, &digest);
+            }
+        }
+    }
+
+    /// Kill warm sandboxes, oldest first, until the cache can shrink back down to its target size.
+    fn relieve_cache_pressure(&mut self) {
+        while !self.warm_sandboxes.is_empty() && self.cache.is_over_target(CacheKeyKind::Blob) {
+            self.kill_oldest_sandbox();
         }
     }
 
+    /// Return whether a job that just completed should leave a warm sandbox behind. We only start
+    /// one if there's a slot for it that no job is waiting for, and if the cache has room to keep
+    /// the sandbox's layers around. A job that is still waiting for lazily fetched blobs might
+    /// leave the sandbox with a root file system that can't be read.
+    fn should_warm_sandbox(&self, jid: JobId) -> bool {
+        self.available.is_empty()
+            && self.executing.len() + self.warm_sandboxes.len() < self.slots
+            && !self.cache.is_over_target(CacheKeyKind::Blob)
+            && self
+                .lazy_blobs
+                .get(&jid)
+                .map_or(true, |lazy| lazy.pending.is_empty())
+    }
+
     fn receive_job_completed(&mut self, jid: JobId, result: JobResult<JobCompleted, String>) {
-        let Some(ExecutingJob { state, cache_keys }) = self.executing.remove(&jid) else {
+        let Some(ExecutingJob {
+            state,
+            cache_keys,
+            sandbox,
+        }) = self.executing.remove(&jid)
+        else {
             panic!("missing entry for {jid:?}");
         };
 
+        // Canceled jobs don't leave sandboxes behind. This includes all jobs that complete after
+        // we've started shutting down.
+        let sandbox = sandbox.filter(|_| {
+            !matches!(state, ExecutingJobState::Canceled) && self.should_warm_sandbox(jid)
+        });
+
         match state {
             ExecutingJobState::Nominal { .. } => {
                 self.broker_sender
@@ -473,18 +876,43 @@ where
             }
         }
 
+        // The warm sandbox takes over the job's references to its layers and blobs, but not to
+        // its volumes.
+        let mut sandbox_cache_keys = vec![];
         for CacheKey { kind, digest } in cache_keys {
-            self.cache.decrement_ref_count(kind, &digest);
+            if kind == CacheKeyKind::Volume {
+                self.release_volume(digest);
+            } else if sandbox.is_some() {
+                sandbox_cache_keys.push(CacheKey { kind, digest });
+            } else {
+                self.cache.decrement_ref_count(kind, &digest);
+            }
         }
-        self.possibly_start_job();
+        if let Some((key, spec)) = sandbox {
+            if let Some(LazyBlobs { got, .. }) = self.lazy_blobs.remove(&jid) {
+                sandbox_cache_keys.extend(
+                    got.into_iter()
+                        .map(|digest| CacheKey::new(CacheKeyKind::Blob, digest)),
+                );
+            }
+            if self.warm_sandboxes.len() >= self.sandbox_pool_size {
+                self.kill_oldest_sandbox();
+            }
+            let handle = self.deps.start_sandbox(spec, key.layer_stack().to_owned());
+            self.warm_sandboxes.push_back(WarmSandbox {
+                key,
+                _handle: handle,
+                cache_keys: sandbox_cache_keys,
+            });
+        }
+        self.release_lazy_blobs(jid);
+
+        // Releasing the job's volumes may have unblocked more than one job.
+        while self.possibly_start_job() {}
     }
 
     fn receive_job_timer(&mut self, jid: JobId) {
-        let Some(&mut ExecutingJob {
-            ref mut state,
-            cache_keys: _,
-        }) = self.executing.get_mut(&jid)
-        else {
+        let Some(&mut ExecutingJob { ref mut state, .. }) = self.executing.get_mut(&jid) else {
             return;
         };
         // We kill the job, but we wait around until it's actually
@@ -545,9 +973,20 @@ where
     ) {
         match self.awaiting_layers.entry(jid) {
             Entry::Vacant(_) => {
-                // If there were previous errors for this job, or the job was canceled, then
-                // we'll find nothing in the hash table, and we'll need to release this layer.
-                self.cache.decrement_ref_count(kind, digest);
+                // If the job started without this blob, it keeps it until it's done.
+                //
+                // Otherwise, if there were previous errors for this job, or the job was canceled,
+                // then we'll find nothing in the hash table, and we'll need to release this layer.
+                let lazy = self
+                    .lazy_blobs
+                    .get_mut(&jid)
+                    .filter(|lazy| kind == CacheKeyKind::Blob && lazy.pending.contains(digest));
+                if let Some(lazy) = lazy {
+                    lazy.pending.remove(digest);
+                    lazy.got.insert(digest.clone());
+                } else {
+                    self.cache.decrement_ref_count(kind, digest);
+                }
             }
             Entry::Occupied(mut entry) => {
                 // So far all is good. We then need to check if we've gotten all layers. If we
@@ -556,6 +995,7 @@ where
                     deps: &mut self.deps,
                     artifact_fetcher: &mut self.artifact_fetcher,
                     cache: &mut self.cache,
+                    prefetcher: &mut self.prefetcher,
                     jid,
                 };
                 cb(&mut entry.get_mut().tracker, digest, &mut fetcher);
@@ -595,6 +1035,9 @@ where
                 }
             }
         }
+        // Warm sandboxes may be holding on to entries that the cache would otherwise remove to
+        // make room for this one.
+        self.relieve_cache_pressure();
     }
 
     fn receive_artifact_success(
@@ -604,16 +1047,82 @@ where
     ) {
         self.cache_fill_success(
             CacheKeyKind::Blob,
-            digest,
+            digest.clone(),
             artifact,
             "Failed to save artifact in cache",
             |tracker, digest, path, fetcher| tracker.got_artifact(digest, path, fetcher),
-        )
+        );
+        self.retried_lazy_blobs.remove(&digest);
+        self.prefetcher
+            .fetch_completed(&digest, &mut self.artifact_fetcher);
+        self.deps.blob_fetch_finished(digest, Ok(()));
     }
 
     fn receive_artifact_failure(&mut self, digest: Sha256Digest, err: Error) {
         let msg = "Failed to download and extract layer artifact";
-        self.cache_fill_failure(CacheKeyKind::Blob, digest, msg, err)
+        let mut lazy_jobs = vec![];
+        for jid in self.cache.got_artifact_failure(CacheKeyKind::Blob, &digest) {
+            if self.awaiting_layers.contains_key(&jid) {
+                self.job_failure(&digest, jid, msg, &err);
+            } else if self
+                .lazy_blobs
+                .get(&jid)
+                .is_some_and(|lazy| lazy.pending.contains(&digest))
+            {
+                lazy_jobs.push(jid);
+            }
+        }
+        self.prefetcher
+            .fetch_completed(&digest, &mut self.artifact_fetcher);
+
+        // Jobs that started without the blob are already running, so we can't fail them. We try
+        // the fetch one more time before giving up on it. Whatever is waiting for the blob keeps
+        // waiting in the meantime.
+        if !lazy_jobs.is_empty() && self.retried_lazy_blobs.insert(digest.clone()) {
+            for jid in lazy_jobs {
+                self.retry_lazy_blob(jid, &digest);
+            }
+            return;
+        }
+        self.retried_lazy_blobs.remove(&digest);
+        for jid in lazy_jobs {
+            self.lazy_blobs
+                .get_mut(&jid)
+                .unwrap()
+                .pending
+                .remove(&digest);
+        }
+        self.deps
+            .blob_fetch_finished(digest.clone(), Err(format!("{msg} {digest}: {err:?}")));
+    }
+
+    /// Get the given lazy blob for the given running job again, after the last fetch failed.
+    fn retry_lazy_blob(&mut self, jid: JobId, digest: &Sha256Digest) {
+        match self
+            .cache
+            .get_artifact(CacheKeyKind::Blob, digest.clone(), jid)
+        {
+            GetArtifact::Success => {
+                let lazy = self.lazy_blobs.get_mut(&jid).unwrap();
+                lazy.pending.remove(digest);
+                lazy.got.insert(digest.clone());
+            }
+            GetArtifact::Wait => {}
+            GetArtifact::Get => {
+                self.prefetcher
+                    .fetch(digest.clone(), &mut self.artifact_fetcher);
+            }
+        }
+    }
+
+    fn receive_blob_needed(&mut self, digest: Sha256Digest) {
+        if !self
+            .prefetcher
+            .prioritize(&digest, &mut self.artifact_fetcher)
+        {
+            // There's nothing to wait for.
+            self.deps.blob_fetch_finished(digest, Ok(()));
+        }
     }
 
     fn receive_build_bottom_fs_layer_success(
@@ -654,6 +1163,25 @@ where
         self.cache_fill_failure(CacheKeyKind::UpperFsLayer, digest, msg, err)
     }
 
+    fn receive_build_compacted_fs_layer_success(
+        &mut self,
+        digest: Sha256Digest,
+        artifact: GotArtifact<CacheT::Fs>,
+    ) {
+        self.cache_fill_success(
+            CacheKeyKind::CompactedFsLayer,
+            digest,
+            artifact,
+            "Failed to save compacted FS layer",
+            |tracker, digest, path, _| tracker.got_compacted_fs_layer(digest, path),
+        )
+    }
+
+    fn receive_build_compacted_fs_layer_failure(&mut self, digest: Sha256Digest, err: Error) {
+        let msg = "Failed to build compacted FS layer";
+        self.cache_fill_failure(CacheKeyKind::CompactedFsLayer, digest, msg, err)
+    }
+
     fn receive_read_manifest_digests_success(
         &mut self,
         digest: Sha256Digest,
@@ -679,11 +1207,34 @@ where
         self.job_failure(&digest, jid, "failed to read manifest", &err);
     }
 
-    /// Close our connection to the broker, drop pending work, and cancel all jobs.
+    fn receive_create_volume_success(
+        &mut self,
+        digest: Sha256Digest,
+        artifact: GotArtifact<CacheT::Fs>,
+    ) {
+        self.cache_fill_success(
+            CacheKeyKind::Volume,
+            digest,
+            artifact,
+            "Failed to save volume",
+            |tracker, digest, path, _| tracker.got_volume(digest, path),
+        )
+    }
+
+    fn receive_create_volume_failure(&mut self, digest: Sha256Digest, err: Error) {
+        let msg = "Failed to create volume";
+        self.cache_fill_failure(CacheKeyKind::Volume, digest, msg, err)
+    }
+
+    /// Close our connection to the broker, drop pending work and warm sandboxes, and cancel all
+    /// jobs.
     fn receive_shutdown(&mut self) {
         self.broker_sender.close();
         self.awaiting_layers = Default::default();
         self.available = Default::default();
+        while !self.warm_sandboxes.is_empty() {
+            self.kill_oldest_sandbox();
+        }
 
         for jid in self.executing.keys().cloned().collect::<Vec<_>>() {
             self.receive_cancel_job(jid);
@@ -695,6 +1246,7 @@ struct Fetcher<'dispatcher, DepsT, ArtifactFetcherT, CacheT> {
     deps: &'dispatcher mut DepsT,
     artifact_fetcher: &'dispatcher mut ArtifactFetcherT,
     cache: &'dispatcher mut CacheT,
+    prefetcher: &'dispatcher mut Prefetcher,
     jid: JobId,
 }
 
@@ -715,7 +1267,24 @@ where
             }
             GetArtifact::Wait => FetcherResult::Pending,
             GetArtifact::Get => {
-                self.artifact_fetcher.start_artifact_fetch(digest.clone());
+                self.prefetcher.fetch(digest.clone(), self.artifact_fetcher);
+                FetcherResult::Pending
+            }
+        }
+    }
+
+    fn fetch_artifact_lazily(&mut self, digest: &Sha256Digest) -> FetcherResult {
+        match self
+            .cache
+            .get_artifact(CacheKeyKind::Blob, digest.clone(), self.jid)
+        {
+            GetArtifact::Success => {
+                FetcherResult::Got(self.cache.cache_path(CacheKeyKind::Blob, digest))
+            }
+            GetArtifact::Wait => FetcherResult::Pending,
+            GetArtifact::Get => {
+                self.prefetcher
+                    .prefetch(digest.clone(), self.artifact_fetcher);
                 FetcherResult::Pending
             }
         }
@@ -771,10 +1340,57 @@ where
         }
     }
 
+    fn fetch_compacted_fs_layer(
+        &mut self,
+        digest: &Sha256Digest,
+        layer_path: &Path,
+    ) -> FetcherResult {
+        match self
+            .cache
+            .get_artifact(CacheKeyKind::CompactedFsLayer, digest.clone(), self.jid)
+        {
+            GetArtifact::Success => FetcherResult::Got(
+                self.cache
+                    .cache_path(CacheKeyKind::CompactedFsLayer, digest),
+            ),
+            GetArtifact::Wait => FetcherResult::Pending,
+            GetArtifact::Get => {
+                self.deps
+                    .build_compacted_fs_layer(digest.clone(), layer_path.into());
+                FetcherResult::Pending
+            }
+        }
+    }
+
+    fn fetch_existing_compacted_fs_layer(&mut self, digest: &Sha256Digest) -> Option<PathBuf> {
+        self.cache
+            .get_artifact_if_present(CacheKeyKind::CompactedFsLayer, digest)
+            .then(|| {
+                self.cache
+                    .cache_path(CacheKeyKind::CompactedFsLayer, digest)
+            })
+    }
+
     fn fetch_manifest_digests(&mut self, digest: &Sha256Digest, path: &Path) {
         self.deps
             .read_manifest_digests(digest.clone(), path.into(), self.jid);
     }
+
+    fn fetch_volume(&mut self, digest: &Sha256Digest, size: u64) -> FetcherResult {
+        match self
+            .cache
+            .get_artifact(CacheKeyKind::Volume, digest.clone(), self.jid)
+        {
+            GetArtifact::Success => {
+                FetcherResult::Got(self.cache.cache_path(CacheKeyKind::Volume, digest))
+            }
+            GetArtifact::Wait => FetcherResult::Pending,
+            GetArtifact::Get => {
+                self.deps.create_volume(digest.clone(), size);
+                FetcherResult::Pending
+            }
+        }
+    }
 }
 
 /*  _            _
@@ -798,18 +1414,26 @@ mod tests {
 
     #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
     enum TestMessage {
-        StartJob(JobId, JobSpec, PathBuf),
+        StartJob(JobId, JobSpec, PathBuf, Vec<PathBuf>),
+        StartJobInSandbox(JobId, JobSpec, PathBuf, Vec<PathBuf>),
+        StartSandbox(JobSpec, PathBuf),
         SendMessageToBroker(WorkerToBroker),
         StartArtifactFetch(Sha256Digest),
         BuildBottomFsLayer(Sha256Digest, ArtifactType, PathBuf),
         BuildUpperFsLayer(Sha256Digest, PathBuf, PathBuf),
+        BuildCompactedFsLayer(Sha256Digest, PathBuf),
         ReadManifestDigests(Sha256Digest, PathBuf, JobId),
+        CreateVolume(Sha256Digest, u64),
+        BlobFetchFinished(Sha256Digest),
+        BlobFetchFailed(Sha256Digest, String),
         CacheGetArtifact(CacheKeyKind, Sha256Digest, JobId),
+        CacheGetArtifactIfPresent(CacheKeyKind, Sha256Digest),
         CacheGotArtifactSuccess(CacheKeyKind, Sha256Digest, GotArtifact<TestFs>),
         CacheGotArtifactFailure(CacheKeyKind, Sha256Digest),
         CacheDecrementRefCount(CacheKeyKind, Sha256Digest),
         CachePath(CacheKeyKind, Sha256Digest),
         JobHandleDropped(JobId),
+        SandboxHandleDropped(PathBuf),
         StartTimer(JobId, Duration),
         TimerHandleDropped(JobId),
     }
@@ -822,6 +1446,7 @@ mod tests {
         got_artifact_success_returns: HashMap<CacheKey, Vec<JobId>>,
         got_artifact_failure_returns: HashMap<CacheKey, Vec<JobId>>,
         cache_path_returns: HashMap<CacheKey, PathBuf>,
+        over_target: bool,
         closed: bool,
     }
 
@@ -829,19 +1454,48 @@ mod tests {
 
     impl Drop for TestHandle {
         fn drop(&mut self) {
-            self.1.borrow_mut().messages.push(self.0.take().unwrap());
+            if let Some(message) = self.0.take() {
+                self.1.borrow_mut().messages.push(message);
+            }
         }
     }
 
     impl Deps for Rc<RefCell<TestState>> {
         type JobHandle = TestHandle;
 
-        fn start_job(&mut self, jid: JobId, spec: JobSpec, path: PathBuf) -> Self::JobHandle {
+        fn start_job(
+            &mut self,
+            jid: JobId,
+            spec: JobSpec,
+            path: PathBuf,
+            mount_layer_paths: Vec<PathBuf>,
+            sandbox: Option<Self::SandboxHandle>,
+        ) -> Self::JobHandle {
             let mut mut_ref = self.borrow_mut();
-            mut_ref.messages.push(StartJob(jid, spec, path));
+            match sandbox {
+                None => mut_ref
+                    .messages
+                    .push(StartJob(jid, spec, path, mount_layer_paths)),
+                Some(mut sandbox) => {
+                    // The job now owns the sandbox.
+                    sandbox.0 = None;
+                    mut_ref
+                        .messages
+                        .push(StartJobInSandbox(jid, spec, path, mount_layer_paths))
+                }
+            }
             TestHandle(Some(TestMessage::JobHandleDropped(jid)), self.clone())
         }
 
+        type SandboxHandle = TestHandle;
+
+        fn start_sandbox(&mut self, spec: JobSpec, path: PathBuf) -> Self::SandboxHandle {
+            self.borrow_mut()
+                .messages
+                .push(StartSandbox(spec, path.clone()));
+            TestHandle(Some(SandboxHandleDropped(path)), self.clone())
+        }
+
         type TimerHandle = TestHandle;
 
         fn start_timer(&mut self, jid: JobId, duration: Duration) -> Self::TimerHandle {
@@ -875,11 +1529,28 @@ mod tests {
             ));
         }
 
+        fn build_compacted_fs_layer(&mut self, digest: Sha256Digest, layer_path: PathBuf) {
+            self.borrow_mut()
+                .messages
+                .push(BuildCompactedFsLayer(digest, layer_path));
+        }
+
         fn read_manifest_digests(&mut self, digest: Sha256Digest, path: PathBuf, jid: JobId) {
             self.borrow_mut()
                 .messages
                 .push(TestMessage::ReadManifestDigests(digest, path, jid));
         }
+
+        fn create_volume(&mut self, digest: Sha256Digest, size: u64) {
+            self.borrow_mut().messages.push(CreateVolume(digest, size));
+        }
+
+        fn blob_fetch_finished(&mut self, digest: Sha256Digest, result: Result<(), String>) {
+            self.borrow_mut().messages.push(match result {
+                Ok(()) => BlobFetchFinished(digest),
+                Err(err) => BlobFetchFailed(digest, err),
+            });
+        }
     }
 
     impl ArtifactFetcher for Rc<RefCell<TestState>> {
@@ -920,6 +1591,13 @@ mod tests {
                 .unwrap_or_else(|| panic!("unexpected get_artifact of {kind:?} {digest}"))
         }
 
+        fn get_artifact_if_present(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) -> bool {
+            self.borrow_mut()
+                .messages
+                .push(CacheGetArtifactIfPresent(kind, digest.clone()));
+            false
+        }
+
         fn got_artifact_failure(
             &mut self,
             kind: CacheKeyKind,
@@ -968,6 +1646,10 @@ mod tests {
                 .unwrap()
                 .clone()
         }
+
+        fn is_over_target(&self, _kind: CacheKeyKind) -> bool {
+            self.borrow().over_target
+        }
     }
 
     struct Fixture {
@@ -994,6 +1676,7 @@ mod tests {
                 got_artifact_success_returns: HashMap::from(got_artifact_success_returns),
                 got_artifact_failure_returns: HashMap::from(got_artifact_failure_returns),
                 cache_path_returns: HashMap::from(cache_path_returns),
+                over_target: false,
                 closed: false,
             }));
             let dispatcher = Dispatcher::new(
@@ -1002,6 +1685,7 @@ mod tests {
                 test_state.clone(),
                 test_state.clone(),
                 Slots::try_from(slots).unwrap(),
+                0,
             );
             Fixture {
                 test_state,
@@ -1009,6 +1693,11 @@ mod tests {
             }
         }
 
+        fn sandbox_pool_size(mut self, sandbox_pool_size: usize) -> Self {
+            self.dispatcher.sandbox_pool_size = sandbox_pool_size;
+            self
+        }
+
         fn expect_messages_in_any_order(&mut self, mut expected: Vec<TestMessage>) {
             expected.sort();
             let messages = &mut self.test_state.borrow_mut().messages;
@@ -1040,6 +1729,9 @@ mod tests {
     }
 
     macro_rules! cache_key {
+        (Volume, $name:literal) => {
+            CacheKey::new(Volume, volume_digest($name))
+        };
         (UpperFsLayer, $($d:expr),*) => {
             CacheKey::new(UpperFsLayer, upper_digest!($($d),*))
         };
@@ -1076,7 +1768,7 @@ mod tests {
             (cache_key!(BottomFsLayer, 42), path_buf!("/z/bl/42")),
             (cache_key!(UpperFsLayer, 42, 41), path_buf!("/z/ul/42/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
@@ -1087,10 +1779,10 @@ mod tests {
             CachePath(BottomFsLayer, digest!(42)),
             CacheGetArtifact(UpperFsLayer, upper_digest!(42, 41), jid!(1)),
             CachePath(UpperFsLayer, upper_digest!(42, 41)),
-            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41")),
+            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
         };
     }
@@ -1107,7 +1799,7 @@ mod tests {
             (cache_key!(Blob, 42), path_buf!("/z/b/42")),
             (cache_key!(BottomFsLayer, 41), path_buf!("/z/bl/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar), (43, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar), (43, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
@@ -1117,7 +1809,7 @@ mod tests {
             StartArtifactFetch(digest!(42)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             CacheDecrementRefCount(Blob, digest!(41)),
             CacheDecrementRefCount(BottomFsLayer, digest!(41)),
         };
@@ -1148,37 +1840,37 @@ mod tests {
             (cache_key!(BottomFsLayer, 4), path_buf!("/z/bl/4")),
             (cache_key!(BottomFsLayer, 5), path_buf!("/z/bl/5")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
             CachePath(BottomFsLayer, digest!(2)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))))) => {
+        Broker(Box::new(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10)))))) => {
             CacheGetArtifact(Blob, digest!(3), jid!(3)),
             CachePath(Blob, digest!(3)),
             CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
             CachePath(BottomFsLayer, digest!(3)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(100))))) => {
+        Broker(Box::new(EnqueueJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(100)))))) => {
             CacheGetArtifact(Blob, digest!(4), jid!(4)),
             CachePath(Blob, digest!(4)),
             CacheGetArtifact(BottomFsLayer, digest!(4), jid!(4)),
             CachePath(BottomFsLayer, digest!(4)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(5), spec!(5, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(5), spec!(5, Tar)))) => {
             CacheGetArtifact(Blob, digest!(5), jid!(5)),
             CachePath(Blob, digest!(5)),
             CacheGetArtifact(BottomFsLayer, digest!(5), jid!(5)),
@@ -1186,33 +1878,33 @@ mod tests {
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(5), JobWorkerStatus::WaitingToExecute)),
         };
 
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
-            StartJob(jid!(5), spec!(5, Tar), path_buf!("/z/bl/5")),
+            StartJob(jid!(5), spec!(5, Tar), path_buf!("/z/bl/5"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(5), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(2))) => {
+        Broker(Box::new(CancelJob(jid!(2)))) => {
             JobHandleDropped(jid!(2)),
         };
         Message::JobCompleted(jid!(2), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(2)),
             CacheDecrementRefCount(BottomFsLayer, digest!(2)),
-            StartJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(100))), path_buf!("/z/bl/4")),
+            StartJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(100))), path_buf!("/z/bl/4"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(5))) => {
+        Broker(Box::new(CancelJob(jid!(5)))) => {
             JobHandleDropped(jid!(5)),
         };
         Message::JobCompleted(jid!(5), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(5)),
             CacheDecrementRefCount(BottomFsLayer, digest!(5)),
-            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/3")),
+            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/3"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::Executing)),
         };
     }
@@ -1250,51 +1942,51 @@ mod tests {
             (cache_key!(BottomFsLayer, 6), path_buf!("/z/bl/6")),
             (cache_key!(BottomFsLayer, 7), path_buf!("/z/bl/7")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
             CachePath(BottomFsLayer, digest!(2)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(30))))) => {
+        Broker(Box::new(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(30)))))) => {
             CacheGetArtifact(Blob, digest!(3), jid!(3)),
             CachePath(Blob, digest!(3)),
             CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
             CachePath(BottomFsLayer, digest!(3)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(40))))) => {
+        Broker(Box::new(EnqueueJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(40)))))) => {
             CacheGetArtifact(Blob, digest!(4), jid!(4)),
             CachePath(Blob, digest!(4)),
             CacheGetArtifact(BottomFsLayer, digest!(4), jid!(4)),
             CachePath(BottomFsLayer, digest!(4)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(5), spec!(5, Tar).priority(1).estimated_duration(Some(millis!(10))))) => {
+        Broker(Box::new(EnqueueJob(jid!(5), spec!(5, Tar).priority(1).estimated_duration(Some(millis!(10)))))) => {
             CacheGetArtifact(Blob, digest!(5), jid!(5)),
             CachePath(Blob, digest!(5)),
             CacheGetArtifact(BottomFsLayer, digest!(5), jid!(5)),
             CachePath(BottomFsLayer, digest!(5)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(5), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(6), spec!(6, Tar).priority(1).estimated_duration(Some(millis!(20))))) => {
+        Broker(Box::new(EnqueueJob(jid!(6), spec!(6, Tar).priority(1).estimated_duration(Some(millis!(20)))))) => {
             CacheGetArtifact(Blob, digest!(6), jid!(6)),
             CachePath(Blob, digest!(6)),
             CacheGetArtifact(BottomFsLayer, digest!(6), jid!(6)),
             CachePath(BottomFsLayer, digest!(6)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(6), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(7), spec!(7, Tar).priority(-1).estimated_duration(Some(millis!(100))))) => {
+        Broker(Box::new(EnqueueJob(jid!(7), spec!(7, Tar).priority(-1).estimated_duration(Some(millis!(100)))))) => {
             CacheGetArtifact(Blob, digest!(7), jid!(7)),
             CachePath(Blob, digest!(7)),
             CacheGetArtifact(BottomFsLayer, digest!(7), jid!(7)),
@@ -1302,53 +1994,53 @@ mod tests {
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(7), JobWorkerStatus::WaitingToExecute)),
         };
 
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
-            StartJob(jid!(6), spec!(6, Tar).priority(1).estimated_duration(Some(millis!(20))), path_buf!("/z/bl/6")),
+            StartJob(jid!(6), spec!(6, Tar).priority(1).estimated_duration(Some(millis!(20))), path_buf!("/z/bl/6"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(6), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(2))) => {
+        Broker(Box::new(CancelJob(jid!(2)))) => {
             JobHandleDropped(jid!(2)),
         };
         Message::JobCompleted(jid!(2), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(2)),
             CacheDecrementRefCount(BottomFsLayer, digest!(2)),
-            StartJob(jid!(5), spec!(5, Tar).priority(1).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/5")),
+            StartJob(jid!(5), spec!(5, Tar).priority(1).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/5"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(5), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(6))) => {
+        Broker(Box::new(CancelJob(jid!(6)))) => {
             JobHandleDropped(jid!(6)),
         };
         Message::JobCompleted(jid!(6), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(6)),
             CacheDecrementRefCount(BottomFsLayer, digest!(6)),
-            StartJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(40))), path_buf!("/z/bl/4")),
+            StartJob(jid!(4), spec!(4, Tar).estimated_duration(Some(millis!(40))), path_buf!("/z/bl/4"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(5))) => {
+        Broker(Box::new(CancelJob(jid!(5)))) => {
             JobHandleDropped(jid!(5)),
         };
         Message::JobCompleted(jid!(5), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(5)),
             CacheDecrementRefCount(BottomFsLayer, digest!(5)),
-            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(30))), path_buf!("/z/bl/3")),
+            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(30))), path_buf!("/z/bl/3"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::Executing)),
         };
 
-        Broker(CancelJob(jid!(4))) => {
+        Broker(Box::new(CancelJob(jid!(4)))) => {
             JobHandleDropped(jid!(4)),
         };
         Message::JobCompleted(jid!(4), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(4)),
             CacheDecrementRefCount(BottomFsLayer, digest!(4)),
-            StartJob(jid!(7), spec!(7, Tar).priority(-1).estimated_duration(Some(millis!(100))), path_buf!("/z/bl/7")),
+            StartJob(jid!(7), spec!(7, Tar).priority(-1).estimated_duration(Some(millis!(100))), path_buf!("/z/bl/7"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(7), JobWorkerStatus::Executing)),
         };
     }
@@ -1363,7 +2055,7 @@ mod tests {
             (cache_key!(Blob, 41), path_buf!("/z/b/41")),
             (cache_key!(BottomFsLayer, 41), path_buf!("/z/bl/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(Blob, digest!(42), jid!(1)),
@@ -1371,7 +2063,7 @@ mod tests {
             CachePath(BottomFsLayer, digest!(41)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             CacheDecrementRefCount(BottomFsLayer, digest!(41)),
             CacheDecrementRefCount(Blob, digest!(41)),
         };
@@ -1396,7 +2088,7 @@ mod tests {
             (cache_key!(BottomFsLayer, 43), path_buf!("/z/bl/43")),
             (cache_key!(UpperFsLayer, 42, 41), path_buf!("/z/ul/42/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
@@ -1407,17 +2099,17 @@ mod tests {
             CachePath(BottomFsLayer, digest!(42)),
             CacheGetArtifact(UpperFsLayer, upper_digest!(42, 41), jid!(1)),
             CachePath(UpperFsLayer, upper_digest!(42, 41)),
-            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41")),
+            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, [(43, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, [(43, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(43), jid!(2)),
             CachePath(Blob, digest!(43)),
             CacheGetArtifact(BottomFsLayer, digest!(43), jid!(2)),
             CachePath(BottomFsLayer, digest!(43)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
@@ -1426,7 +2118,7 @@ mod tests {
             CacheDecrementRefCount(UpperFsLayer, upper_digest!(42, 41)),
             CacheDecrementRefCount(Blob, digest!(41)),
             CacheDecrementRefCount(Blob, digest!(42)),
-            StartJob(jid!(2), spec!(2, [(43, Tar)]), path_buf!("/z/bl/43")),
+            StartJob(jid!(2), spec!(2, [(43, Tar)]), path_buf!("/z/bl/43"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
     }
@@ -1460,23 +2152,23 @@ mod tests {
             (cache_key!(UpperFsLayer, 42, 41), path_buf!("/z/ul/42/41")),
             (cache_key!(UpperFsLayer, 41, 42, 41), path_buf!("/z/ul/41/42/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
             CachePath(BottomFsLayer, digest!(2)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(3), spec!(3, [(41, Tar), (42, Tar), (41, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(3), spec!(3, [(41, Tar), (42, Tar), (41, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(3)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(3)),
@@ -1491,14 +2183,14 @@ mod tests {
             CachePath(UpperFsLayer, upper_digest!(41, 42, 41)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(4), spec!(4, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(4), spec!(4, Tar)))) => {
             CacheGetArtifact(Blob, digest!(4), jid!(4)),
             CachePath(Blob, digest!(4)),
             CacheGetArtifact(BottomFsLayer, digest!(4), jid!(4)),
             CachePath(BottomFsLayer, digest!(4)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(CancelJob(jid!(3))) => {
+        Broker(Box::new(CancelJob(jid!(3)))) => {
             CacheDecrementRefCount(Blob, digest!(41)),
             CacheDecrementRefCount(BottomFsLayer, digest!(41)),
             CacheDecrementRefCount(Blob, digest!(42)),
@@ -1511,7 +2203,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
-            StartJob(jid!(4), spec!(4, Tar), path_buf!("/z/bl/4")),
+            StartJob(jid!(4), spec!(4, Tar), path_buf!("/z/bl/4"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(4), JobWorkerStatus::Executing)),
         };
     }
@@ -1519,7 +2211,7 @@ mod tests {
     script_test! {
         cancel_unknown,
         Fixture::new(1, [], [], [], []),
-        Broker(CancelJob(jid!(1))) => {};
+        Broker(Box::new(CancelJob(jid!(1)))) => {};
     }
 
     script_test! {
@@ -1531,17 +2223,17 @@ mod tests {
             (cache_key!(Blob, 1), path_buf!("/z/b/1")),
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(CancelJob(jid!(1))) => { JobHandleDropped(jid!(1)) };
-        Broker(CancelJob(jid!(1))) => {};
-        Broker(CancelJob(jid!(1))) => {};
+        Broker(Box::new(CancelJob(jid!(1)))) => { JobHandleDropped(jid!(1)) };
+        Broker(Box::new(CancelJob(jid!(1)))) => {};
+        Broker(Box::new(CancelJob(jid!(1)))) => {};
     }
 
     script_test! {
@@ -1557,16 +2249,16 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(1)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
@@ -1577,11 +2269,11 @@ mod tests {
             JobHandleDropped(jid!(1)),
             TimerHandleDropped(jid!(1)),
         };
-        Broker(CancelJob(jid!(1))) => {};
+        Broker(Box::new(CancelJob(jid!(1)))) => {};
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
     }
@@ -1608,13 +2300,13 @@ mod tests {
 
         fixture
             .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+            .receive_message(Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))));
         fixture.expect_messages_in_any_order(vec![
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
                 jid!(1),
                 JobWorkerStatus::Executing,
@@ -1623,7 +2315,7 @@ mod tests {
 
         fixture
             .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, Tar))));
+            .receive_message(Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))));
         fixture.expect_messages_in_any_order(vec![
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
@@ -1663,15 +2355,15 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
@@ -1683,7 +2375,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
     }
@@ -1705,22 +2397,22 @@ mod tests {
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
             (cache_key!(BottomFsLayer, 3), path_buf!("/z/bl/3")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
             CachePath(BottomFsLayer, digest!(2)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))))) => {
+        Broker(Box::new(EnqueueJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10)))))) => {
             CacheGetArtifact(Blob, digest!(3), jid!(3)),
             CachePath(Blob, digest!(3)),
             CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
@@ -1733,7 +2425,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
         Message::JobCompleted(jid!(2), Err(JobError::Execution(string!("execution error")))) => {
@@ -1742,7 +2434,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(2)),
             CacheDecrementRefCount(BottomFsLayer, digest!(2)),
             JobHandleDropped(jid!(2)),
-            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/3")),
+            StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))), path_buf!("/z/bl/3"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(3), JobWorkerStatus::Executing)),
         };
     }
@@ -1762,7 +2454,7 @@ mod tests {
             (cache_key!(BottomFsLayer, 42), path_buf!("/z/bl/41")),
             (cache_key!(UpperFsLayer, 42, 41), path_buf!("/z/ul/42/41")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CachePath(Blob, digest!(41)),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
@@ -1773,10 +2465,10 @@ mod tests {
             CachePath(BottomFsLayer, digest!(42)),
             CacheGetArtifact(UpperFsLayer, upper_digest!(42, 41), jid!(1)),
             CachePath(UpperFsLayer, upper_digest!(42, 41)),
-            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41")),
+            StartJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]), path_buf!("/z/ul/42/41"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(3))) => {
@@ -1806,12 +2498,12 @@ mod tests {
             (cache_key!(Blob, 1), path_buf!("/z/b/1")),
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(33))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(33)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
@@ -1833,16 +2525,16 @@ mod tests {
             (cache_key!(Blob, 1), path_buf!("/z/b/1")),
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(33))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(33)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(33)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
             TimerHandleDropped(jid!(1)),
         };
@@ -1861,16 +2553,16 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(1)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
@@ -1896,7 +2588,7 @@ mod tests {
                 stderr: JobOutputResult::Inline(boxed_u8!(b"stderr")),
                 duration: std::time::Duration::from_secs(1),
             })))),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
     }
@@ -1914,16 +2606,16 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(1)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
@@ -1936,7 +2628,7 @@ mod tests {
             TimerHandleDropped(jid!(1)),
             SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
             JobHandleDropped(jid!(1)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
         JobTimer(jid!(1)) => {};
@@ -1955,23 +2647,23 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1))))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
-            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1")),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/z/bl/1"), vec![]),
             StartTimer(jid!(1), Duration::from_secs(1)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
-        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+        Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))) => {
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CachePath(Blob, digest!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
             CachePath(BottomFsLayer, digest!(2)),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::WaitingToExecute)),
         };
-        Broker(CancelJob(jid!(1))) => {
+        Broker(Box::new(CancelJob(jid!(1)))) => {
             JobHandleDropped(jid!(1)),
             TimerHandleDropped(jid!(1)),
         };
@@ -1979,7 +2671,7 @@ mod tests {
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
-            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2")),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(2), JobWorkerStatus::Executing)),
         };
     }
@@ -2003,7 +2695,7 @@ mod tests {
             (cache_key!(Blob, 41), path_buf!("/a")),
             (cache_key!(Blob, 43), path_buf!("/c")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar), (43, Tar), (44, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar), (43, Tar), (44, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(41), jid!(1)),
             CacheGetArtifact(Blob, digest!(42), jid!(1)),
             CacheGetArtifact(Blob, digest!(43), jid!(1)),
@@ -2014,38 +2706,272 @@ mod tests {
             CachePath(Blob, digest!(41)),
             CacheGotArtifactSuccess(Blob, digest!(41), GotArtifact::file("/tmp/foo".into())),
             CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            BlobFetchFinished(digest!(41)),
         };
         ArtifactFetchCompleted(digest!(42), Err(anyhow!("foo"))) => {
             CacheGotArtifactFailure(Blob, digest!(42)),
             SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Err(JobError::System(
                 string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: foo"))))),
-            CacheDecrementRefCount(Blob, digest!(41))
+            CacheDecrementRefCount(Blob, digest!(41)),
+            BlobFetchFailed(digest!(42), string!(
+                "Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: foo")),
         };
         ArtifactFetchCompleted(digest!(43), Ok(GotArtifact::file("/tmp/bar".into()))) => {
             CachePath(Blob, digest!(43)),
             CacheGotArtifactSuccess(Blob, digest!(43), GotArtifact::file("/tmp/bar".into())),
-            CacheDecrementRefCount(Blob, digest!(43))
+            CacheDecrementRefCount(Blob, digest!(43)),
+            BlobFetchFinished(digest!(43)),
         };
         ArtifactFetchCompleted(digest!(44), Err(anyhow!("foo"))) => {
             CacheGotArtifactFailure(Blob, digest!(44)),
+            BlobFetchFailed(digest!(44), string!(
+                "Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002c: foo")),
         };
     }
 
-    #[test]
-    #[should_panic(expected = "assertion failed: self.is_none()")]
-    fn duplicate_ids_from_broker_panics() {
-        let mut fixture = Fixture::new(
-            2,
-            [
-                (cache_key!(Blob, 1), GetArtifact::Success),
-                (cache_key!(Blob, 2), GetArtifact::Success),
-                (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
-                (cache_key!(BottomFsLayer, 2), GetArtifact::Success),
-            ],
-            [],
-            [],
-            [
-                (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+    script_test! {
+        manifest_job_starts_before_lazy_blob_arrives,
+        Fixture::new(2, [
+            (cache_key!(Blob, 1), GetArtifact::Success),
+            (cache_key!(Blob, 2), GetArtifact::Get),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+        ], [
+            (cache_key!(Blob, 2), vec![jid!(1)]),
+        ], [], [
+            (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+            (cache_key!(Blob, 2), path_buf!("/z/b/2")),
+            (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+        ]),
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Manifest)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CachePath(Blob, digest!(1)),
+            TestMessage::ReadManifestDigests(digest!(1), path_buf!("/z/b/1"), jid!(1)),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
+        };
+        Message::ReadManifestDigests(digest!(1), jid!(1), Ok(HashSet::from([digest!(2)]))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(1)),
+            StartArtifactFetch(digest!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            CachePath(BottomFsLayer, digest!(1)),
+            StartJob(jid!(1), spec!(1, Manifest), path_buf!("/z/bl/1"), vec![]),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
+        };
+        ArtifactFetchCompleted(digest!(2), Ok(GotArtifact::file("/tmp/foo".into()))) => {
+            CacheGotArtifactSuccess(Blob, digest!(2), GotArtifact::file("/tmp/foo".into())),
+            CachePath(Blob, digest!(2)),
+            BlobFetchFinished(digest!(2)),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+        };
+    }
+
+    script_test! {
+        lazy_blob_arriving_after_job_completes_is_released,
+        Fixture::new(2, [
+            (cache_key!(Blob, 1), GetArtifact::Success),
+            (cache_key!(Blob, 2), GetArtifact::Get),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+        ], [
+            (cache_key!(Blob, 2), vec![jid!(1)]),
+        ], [], [
+            (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+            (cache_key!(Blob, 2), path_buf!("/z/b/2")),
+            (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+        ]),
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Manifest)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CachePath(Blob, digest!(1)),
+            TestMessage::ReadManifestDigests(digest!(1), path_buf!("/z/b/1"), jid!(1)),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
+        };
+        Message::ReadManifestDigests(digest!(1), jid!(1), Ok(HashSet::from([digest!(2)]))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(1)),
+            StartArtifactFetch(digest!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            CachePath(BottomFsLayer, digest!(1)),
+            StartJob(jid!(1), spec!(1, Manifest), path_buf!("/z/bl/1"), vec![]),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+        };
+        ArtifactFetchCompleted(digest!(2), Ok(GotArtifact::file("/tmp/foo".into()))) => {
+            CacheGotArtifactSuccess(Blob, digest!(2), GotArtifact::file("/tmp/foo".into())),
+            CachePath(Blob, digest!(2)),
+            CacheDecrementRefCount(Blob, digest!(2)),
+            BlobFetchFinished(digest!(2)),
+        };
+    }
+
+    fn two_manifest_jobs_with_lazy_blobs() -> Fixture {
+        let mut fixture = Fixture::new(
+            2,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success),
+                (cache_key!(Blob, 2), GetArtifact::Success),
+                (cache_key!(Blob, 3), GetArtifact::Get),
+                (cache_key!(Blob, 4), GetArtifact::Get),
+                (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+                (cache_key!(BottomFsLayer, 2), GetArtifact::Success),
+            ],
+            [
+                (cache_key!(Blob, 3), vec![jid!(1)]),
+                (cache_key!(Blob, 4), vec![jid!(2)]),
+            ],
+            [],
+            [
+                (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+                (cache_key!(Blob, 2), path_buf!("/z/b/2")),
+                (cache_key!(Blob, 3), path_buf!("/z/b/3")),
+                (cache_key!(Blob, 4), path_buf!("/z/b/4")),
+                (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+                (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
+            ],
+        );
+        for (jid, spec, lazy) in [
+            (jid!(1), spec!(1, Manifest), digest!(3)),
+            (jid!(2), spec!(2, Manifest), digest!(4)),
+        ] {
+            let digest = spec.layers.first().0.clone();
+            fixture
+                .dispatcher
+                .receive_message(Broker(Box::new(EnqueueJob(jid, spec))));
+            fixture
+                .dispatcher
+                .receive_message(Message::ReadManifestDigests(
+                    digest,
+                    jid,
+                    Ok(HashSet::from([lazy])),
+                ));
+        }
+        fixture.test_state.borrow_mut().messages.clear();
+        fixture
+    }
+
+    #[test]
+    fn prefetches_are_throttled() {
+        let mut fixture = two_manifest_jobs_with_lazy_blobs();
+
+        fixture.dispatcher.receive_message(ArtifactFetchCompleted(
+            digest!(3),
+            Ok(GotArtifact::file("/tmp/foo".into())),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactSuccess(Blob, digest!(3), GotArtifact::file("/tmp/foo".into())),
+            CachePath(Blob, digest!(3)),
+            StartArtifactFetch(digest!(4)),
+            BlobFetchFinished(digest!(3)),
+        ]);
+    }
+
+    #[test]
+    fn blob_needed_starts_queued_prefetch() {
+        let mut fixture = two_manifest_jobs_with_lazy_blobs();
+
+        fixture.dispatcher.receive_message(BlobNeeded(digest!(4)));
+        fixture.expect_messages_in_any_order(vec![StartArtifactFetch(digest!(4))]);
+
+        fixture.dispatcher.receive_message(BlobNeeded(digest!(4)));
+        fixture.expect_messages_in_any_order(vec![]);
+
+        fixture.dispatcher.receive_message(ArtifactFetchCompleted(
+            digest!(4),
+            Ok(GotArtifact::file("/tmp/foo".into())),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactSuccess(Blob, digest!(4), GotArtifact::file("/tmp/foo".into())),
+            CachePath(Blob, digest!(4)),
+            BlobFetchFinished(digest!(4)),
+        ]);
+
+        fixture.dispatcher.receive_message(ArtifactFetchCompleted(
+            digest!(3),
+            Ok(GotArtifact::file("/tmp/bar".into())),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactSuccess(Blob, digest!(3), GotArtifact::file("/tmp/bar".into())),
+            CachePath(Blob, digest!(3)),
+            BlobFetchFinished(digest!(3)),
+        ]);
+
+        fixture.dispatcher.receive_message(BlobNeeded(digest!(3)));
+        fixture.expect_messages_in_any_order(vec![BlobFetchFinished(digest!(3))]);
+    }
+
+    #[test]
+    fn lazy_blob_fetch_failure_is_retried_once() {
+        let mut fixture = two_manifest_jobs_with_lazy_blobs();
+        fixture.dispatcher.receive_message(BlobNeeded(digest!(4)));
+        fixture.expect_messages_in_any_order(vec![StartArtifactFetch(digest!(4))]);
+
+        let fail = |fixture: &mut Fixture, err: &str| {
+            let mut test_state = fixture.test_state.borrow_mut();
+            test_state
+                .got_artifact_failure_returns
+                .insert(cache_key!(Blob, 4), vec![jid!(2)]);
+            test_state
+                .get_artifact_returns
+                .insert(cache_key!(Blob, 4), GetArtifact::Get);
+            drop(test_state);
+            fixture
+                .dispatcher
+                .receive_message(ArtifactFetchCompleted(digest!(4), Err(anyhow!("{err}"))));
+        };
+
+        fail(&mut fixture, "foo");
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactFailure(Blob, digest!(4)),
+            CacheGetArtifact(Blob, digest!(4), jid!(2)),
+            StartArtifactFetch(digest!(4)),
+        ]);
+
+        fail(&mut fixture, "bar");
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactFailure(Blob, digest!(4)),
+            BlobFetchFailed(
+                digest!(4),
+                string!(
+                    "Failed to download and extract layer artifact \
+                    0000000000000000000000000000000000000000000000000000000000000004: bar"
+                ),
+            ),
+        ]);
+
+        // The job never got the blob, so it doesn't release it.
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(2), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(2), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(2)),
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: self.is_none()")]
+    fn duplicate_ids_from_broker_panics() {
+        let mut fixture = Fixture::new(
+            2,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success),
+                (cache_key!(Blob, 2), GetArtifact::Success),
+                (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+                (cache_key!(BottomFsLayer, 2), GetArtifact::Success),
+            ],
+            [],
+            [],
+            [
+                (cache_key!(Blob, 1), path_buf!("/z/b/1")),
                 (cache_key!(Blob, 2), path_buf!("/z/b/2")),
                 (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
                 (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
@@ -2053,10 +2979,10 @@ mod tests {
         );
         fixture
             .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+            .receive_message(Broker(Box::new(EnqueueJob(jid!(1), spec!(1, Tar)))));
         fixture
             .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(2, Tar))));
+            .receive_message(Broker(Box::new(EnqueueJob(jid!(1), spec!(2, Tar)))));
     }
 
     script_test! {
@@ -2070,14 +2996,14 @@ mod tests {
             (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
             (cache_key!(UpperFsLayer, 1, 1), path_buf!("/z/ul/1/1")),
         ]),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(1, Tar), (1, Tar)]))) => {
+        Broker(Box::new(EnqueueJob(jid!(1), spec!(1, [(1, Tar), (1, Tar)])))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
             CachePath(Blob, digest!(1)),
             CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
             CachePath(BottomFsLayer, digest!(1)),
             CacheGetArtifact(UpperFsLayer, upper_digest!(1, 1), jid!(1)),
             CachePath(UpperFsLayer, upper_digest!(1, 1)),
-            StartJob(jid!(1), spec!(1, [(1, Tar), (1, Tar)]), path_buf!("/z/ul/1/1")),
+            StartJob(jid!(1), spec!(1, [(1, Tar), (1, Tar)]), path_buf!("/z/ul/1/1"), vec![]),
             SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
@@ -2088,4 +3014,501 @@ mod tests {
             JobHandleDropped(jid!(1)),
         };
     }
+
+    fn volume_spec(n: u32, name: &str, mode: JobVolumeMode) -> JobSpec {
+        JobSpec::new(
+            format!("test_{n}"),
+            base::nonempty![(digest!(n), ArtifactType::Tar)],
+        )
+        .mounts([JobMount::Volume {
+            name: name.into(),
+            mount_point: utf8_path_buf!("/cache"),
+            size: 100,
+            mode,
+        }])
+    }
+
+    fn volume_spec_resolved(n: u32, local_path: &str) -> JobSpec {
+        JobSpec::new(
+            format!("test_{n}"),
+            base::nonempty![(digest!(n), ArtifactType::Tar)],
+        )
+        .mounts([JobMount::Bind {
+            mount_point: utf8_path_buf!("/cache"),
+            local_path: utf8_path_buf!(local_path),
+            read_only: false,
+        }])
+    }
+
+    script_test! {
+        volume_created_then_released,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+            (cache_key!(Volume, "cache"), GetArtifact::Get),
+        ], [
+            (cache_key!(Volume, "cache"), vec![jid!(1)]),
+        ], [], [
+            (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+            (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+            (cache_key!(Volume, "cache"), path_buf!("/z/v/cache")),
+        ]),
+        Broker(Box::new(EnqueueJob(jid!(1), volume_spec(1, "cache", JobVolumeMode::Shared)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CachePath(Blob, digest!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            CachePath(BottomFsLayer, digest!(1)),
+            CacheGetArtifact(Volume, volume_digest("cache"), jid!(1)),
+            CreateVolume(volume_digest("cache"), 100),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
+        };
+        CreatedVolume(volume_digest("cache"), Ok(GotArtifact::directory("/tmp/v".into(), 100))) => {
+            CacheGotArtifactSuccess(Volume, volume_digest("cache"), GotArtifact::directory("/tmp/v".into(), 100)),
+            CachePath(Volume, volume_digest("cache")),
+            StartJob(jid!(1), volume_spec_resolved(1, "/z/v/cache/data"), path_buf!("/z/bl/1"), vec![]),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::Executing)),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            CacheDecrementRefCount(Volume, volume_digest("cache")),
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+        };
+    }
+
+    script_test! {
+        volume_create_failure,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+            (cache_key!(Volume, "cache"), GetArtifact::Get),
+        ], [], [
+            (cache_key!(Volume, "cache"), vec![jid!(1)]),
+        ], [
+            (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+            (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+        ]),
+        Broker(Box::new(EnqueueJob(jid!(1), volume_spec(1, "cache", JobVolumeMode::Shared)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CachePath(Blob, digest!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            CachePath(BottomFsLayer, digest!(1)),
+            CacheGetArtifact(Volume, volume_digest("cache"), jid!(1)),
+            CreateVolume(volume_digest("cache"), 100),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(jid!(1), JobWorkerStatus::WaitingForLayers)),
+        };
+        CreatedVolume(volume_digest("cache"), Err(anyhow!("foo"))) => {
+            CacheGotArtifactFailure(Volume, volume_digest("cache")),
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Err(JobError::System(
+                format!("Failed to create volume {}: foo", volume_digest("cache")))))),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+        };
+    }
+
+    #[test]
+    fn exclusive_volume_blocks_other_jobs_until_released() {
+        let mut fixture = Fixture::new(
+            2,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success),
+                (cache_key!(BottomFsLayer, 1), GetArtifact::Success),
+                (cache_key!(Blob, 2), GetArtifact::Success),
+                (cache_key!(BottomFsLayer, 2), GetArtifact::Success),
+                (cache_key!(Volume, "cache"), GetArtifact::Success),
+            ],
+            [],
+            [],
+            [
+                (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+                (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+                (cache_key!(Blob, 2), path_buf!("/z/b/2")),
+                (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
+                (cache_key!(Volume, "cache"), path_buf!("/z/v/cache")),
+            ],
+        );
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(Box::new(EnqueueJob(
+                jid!(1),
+                volume_spec(1, "cache", JobVolumeMode::Exclusive),
+            ))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CachePath(Blob, digest!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            CachePath(BottomFsLayer, digest!(1)),
+            CacheGetArtifact(Volume, volume_digest("cache"), jid!(1)),
+            CachePath(Volume, volume_digest("cache")),
+            StartJob(
+                jid!(1),
+                volume_spec_resolved(1, "/z/v/cache/data"),
+                path_buf!("/z/bl/1"),
+                vec![],
+            ),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+                jid!(1),
+                JobWorkerStatus::Executing,
+            )),
+        ]);
+
+        fixture
+            .test_state
+            .borrow_mut()
+            .get_artifact_returns
+            .insert(cache_key!(Volume, "cache"), GetArtifact::Success);
+        fixture
+            .dispatcher
+            .receive_message(Broker(Box::new(EnqueueJob(
+                jid!(2),
+                volume_spec(2, "cache", JobVolumeMode::Shared),
+            ))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CachePath(Blob, digest!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+            CachePath(BottomFsLayer, digest!(2)),
+            CacheGetArtifact(Volume, volume_digest("cache"), jid!(2)),
+            CachePath(Volume, volume_digest("cache")),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+                jid!(2),
+                JobWorkerStatus::WaitingToExecute,
+            )),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            CacheDecrementRefCount(Volume, volume_digest("cache")),
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            StartJob(
+                jid!(2),
+                volume_spec_resolved(2, "/z/v/cache/data"),
+                path_buf!("/z/bl/2"),
+                vec![],
+            ),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+                jid!(2),
+                JobWorkerStatus::Executing,
+            )),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(2), Ok(completed!(2))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+            CacheDecrementRefCount(Volume, volume_digest("cache")),
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(2), Ok(outcome!(2)))),
+            JobHandleDropped(jid!(2)),
+        ]);
+    }
+
+    fn sandbox_fixture(slots: u16) -> Fixture {
+        Fixture::new(
+            slots,
+            [],
+            [],
+            [],
+            [
+                (cache_key!(Blob, 1), path_buf!("/z/b/1")),
+                (cache_key!(Blob, 2), path_buf!("/z/b/2")),
+                (cache_key!(BottomFsLayer, 1), path_buf!("/z/bl/1")),
+                (cache_key!(BottomFsLayer, 2), path_buf!("/z/bl/2")),
+            ],
+        )
+        .sandbox_pool_size(1)
+    }
+
+    /// Enqueue a job with the spec `spec!(n, Tar)` whose layers are already in the cache. Expect
+    /// `expected` in addition to the cache lookups.
+    fn enqueue_cached_job(
+        fixture: &mut Fixture,
+        jid: JobId,
+        n: u32,
+        expected: impl IntoIterator<Item = TestMessage>,
+    ) {
+        let spec = JobSpec::new(
+            format!("test_{n}"),
+            base::nonempty![(digest!(n), ArtifactType::Tar)],
+        );
+        let spec = if n == 1 {
+            spec
+        } else {
+            spec.arguments(["arg_1"])
+        };
+        for kind in [Blob, BottomFsLayer] {
+            fixture
+                .test_state
+                .borrow_mut()
+                .get_artifact_returns
+                .insert(CacheKey::new(kind, digest!(n)), GetArtifact::Success);
+        }
+        fixture
+            .dispatcher
+            .receive_message(Broker(Box::new(EnqueueJob(jid, spec))));
+        let mut messages = vec![
+            CacheGetArtifact(Blob, digest!(n), jid),
+            CachePath(Blob, digest!(n)),
+            CacheGetArtifact(BottomFsLayer, digest!(n), jid),
+            CachePath(BottomFsLayer, digest!(n)),
+        ];
+        messages.extend(expected);
+        fixture.expect_messages_in_any_order(messages);
+    }
+
+    fn executing(jid: JobId) -> TestMessage {
+        SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+            jid,
+            JobWorkerStatus::Executing,
+        ))
+    }
+
+    #[test]
+    fn warm_sandbox_holds_layers_until_given_to_job() {
+        let mut fixture = sandbox_fixture(2);
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(1),
+            1,
+            [
+                StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+                executing(jid!(1)),
+            ],
+        );
+
+        // The sandbox takes over the job's references to its layers.
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            StartSandbox(spec!(1, Tar), path_buf!("/z/bl/1")),
+        ]);
+
+        // A job with a different root leaves it alone, since there's a free slot.
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(2),
+            2,
+            [
+                StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
+                executing(jid!(2)),
+            ],
+        );
+
+        // A job with the same root gets it, and the sandbox's references are released.
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(3),
+            1,
+            [
+                StartJobInSandbox(jid!(3), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+                CacheDecrementRefCount(Blob, digest!(1)),
+                CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+                executing(jid!(3)),
+            ],
+        );
+
+        // That job leaves a new sandbox behind.
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(3), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(3), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(3)),
+            StartSandbox(spec!(1, Tar), path_buf!("/z/bl/1")),
+        ]);
+    }
+
+    #[test]
+    fn warm_sandbox_killed_when_job_needs_its_slot() {
+        let mut fixture = sandbox_fixture(1);
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(1),
+            1,
+            [
+                StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+                executing(jid!(1)),
+            ],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            StartSandbox(spec!(1, Tar), path_buf!("/z/bl/1")),
+        ]);
+
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(2),
+            2,
+            [
+                SandboxHandleDropped(path_buf!("/z/bl/1")),
+                CacheDecrementRefCount(Blob, digest!(1)),
+                CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+                StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
+                executing(jid!(2)),
+            ],
+        );
+
+        // A job waiting for the slot gets it before a sandbox does.
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(3),
+            1,
+            [SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+                jid!(3),
+                JobWorkerStatus::WaitingToExecute,
+            ))],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(2), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(2), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(2)),
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+            StartJob(jid!(3), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+            executing(jid!(3)),
+        ]);
+    }
+
+    #[test]
+    fn warm_sandbox_killed_under_cache_pressure() {
+        let mut fixture = sandbox_fixture(2);
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(1),
+            1,
+            [
+                StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+                executing(jid!(1)),
+            ],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            StartSandbox(spec!(1, Tar), path_buf!("/z/bl/1")),
+        ]);
+
+        fixture
+            .test_state
+            .borrow_mut()
+            .get_artifact_returns
+            .extend([
+                (cache_key!(Blob, 2), GetArtifact::Get),
+                (cache_key!(BottomFsLayer, 2), GetArtifact::Success),
+            ]);
+        fixture
+            .test_state
+            .borrow_mut()
+            .got_artifact_success_returns
+            .insert(cache_key!(Blob, 2), vec![jid!(2)]);
+        fixture
+            .dispatcher
+            .receive_message(Broker(Box::new(EnqueueJob(jid!(2), spec!(2, Tar)))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            StartArtifactFetch(digest!(2)),
+            SendMessageToBroker(WorkerToBroker::JobStatusUpdate(
+                jid!(2),
+                JobWorkerStatus::WaitingForLayers,
+            )),
+        ]);
+
+        fixture.test_state.borrow_mut().over_target = true;
+        fixture.dispatcher.receive_message(ArtifactFetchCompleted(
+            digest!(2),
+            Ok(GotArtifact::file("/tmp/foo".into())),
+        ));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactSuccess(Blob, digest!(2), GotArtifact::file("/tmp/foo".into())),
+            CachePath(Blob, digest!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+            CachePath(BottomFsLayer, digest!(2)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
+            executing(jid!(2)),
+            SandboxHandleDropped(path_buf!("/z/bl/1")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            BlobFetchFinished(digest!(2)),
+        ]);
+
+        // No new sandbox is started while the cache is over its target.
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(2), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(2), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(2)),
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+        ]);
+    }
+
+    #[test]
+    fn warm_sandbox_killed_on_shutdown() {
+        let mut fixture = sandbox_fixture(2);
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(1),
+            1,
+            [
+                StartJob(jid!(1), spec!(1, Tar), path_buf!("/z/bl/1"), vec![]),
+                executing(jid!(1)),
+            ],
+        );
+        enqueue_cached_job(
+            &mut fixture,
+            jid!(2),
+            2,
+            [
+                StartJob(jid!(2), spec!(2, Tar), path_buf!("/z/bl/2"), vec![]),
+                executing(jid!(2)),
+            ],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse(jid!(1), Ok(outcome!(1)))),
+            JobHandleDropped(jid!(1)),
+            StartSandbox(spec!(1, Tar), path_buf!("/z/bl/1")),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Shutdown(anyhow!("test error")));
+        fixture.expect_messages_in_any_order(vec![
+            SandboxHandleDropped(path_buf!("/z/bl/1")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(2)),
+        ]);
+
+        // Jobs that complete after shutdown don't leave sandboxes behind.
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(2), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+        ]);
+    }
 }
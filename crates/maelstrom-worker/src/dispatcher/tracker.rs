@@ -72,6 +72,18 @@ impl PendingTopLayer {
     }
 }
 
+/// Stacks of at least this many layers are flattened into a single compacted layer before the job
+/// is started, so that LayerFS doesn't have to go through the whole stack to look things up.
+pub const MIN_LAYERS_TO_COMPACT: usize = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+enum PendingCompactedLayer {
+    NotNeeded,
+    WaitingForUpperLayers,
+    WaitingForFsLayer,
+    Ready { fs_layer_path: PathBuf },
+}
+
 pub fn upper_layer_digest(upper_layer: &Sha256Digest, lower_layer: &Sha256Digest) -> Sha256Digest {
     let mut hasher = Sha256::new();
     hasher.update(lower_layer.as_bytes());
@@ -83,10 +95,16 @@ pub fn upper_layer_digest(upper_layer: &Sha256Digest, lower_layer: &Sha256Digest
 #[derive(Debug)]
 pub struct LayerTracker {
     layers: NonEmpty<Sha256Digest>,
+    mount_layers: Vec<Sha256Digest>,
     bottom_layers: HashMap<Sha256Digest, PendingBottomLayer>,
     top_fs_layer: PendingTopLayer,
+    compacted_fs_layer: PendingCompactedLayer,
     cache_keys: HashSet<Key<CacheKeyKind>>,
     pending_manifest_dependencies: HashMap<Sha256Digest, Vec<Sha256Digest>>,
+    /// Artifacts for the contents of files in manifest layers that we've asked for, but don't have
+    /// yet. We don't wait for these before starting the job: LayerFS fetches them on demand.
+    lazy_artifacts: HashSet<Sha256Digest>,
+    volumes: HashMap<Sha256Digest, Option<PathBuf>>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -97,6 +115,7 @@ pub enum FetcherResult {
 
 pub trait Fetcher {
     fn fetch_artifact(&mut self, digest: &Sha256Digest) -> FetcherResult;
+    fn fetch_artifact_lazily(&mut self, digest: &Sha256Digest) -> FetcherResult;
     fn fetch_bottom_fs_layer(
         &mut self,
         digest: &Sha256Digest,
@@ -109,23 +128,40 @@ pub trait Fetcher {
         lower_layer_path: &Path,
         upper_layer_path: &Path,
     ) -> FetcherResult;
+    fn fetch_compacted_fs_layer(
+        &mut self,
+        digest: &Sha256Digest,
+        layer_path: &Path,
+    ) -> FetcherResult;
+    /// Get the compacted layer only if it's already in the cache.
+    fn fetch_existing_compacted_fs_layer(&mut self, digest: &Sha256Digest) -> Option<PathBuf>;
     fn fetch_manifest_digests(&mut self, digest: &Sha256Digest, path: &Path);
+    fn fetch_volume(&mut self, digest: &Sha256Digest, size: u64) -> FetcherResult;
 }
 
 impl LayerTracker {
     pub fn new(
         layers: &NonEmpty<(Sha256Digest, ArtifactType)>,
+        mount_layers: &[(Sha256Digest, ArtifactType)],
         fetcher: &mut impl Fetcher,
     ) -> Self {
         let mut tracker = Self {
             layers: layers.clone().map(|(d, _)| d),
+            mount_layers: mount_layers.iter().map(|(d, _)| d.clone()).collect(),
             bottom_layers: HashMap::new(),
             top_fs_layer: PendingTopLayer::NoStackedUpperLayers,
+            compacted_fs_layer: if layers.len() >= MIN_LAYERS_TO_COMPACT {
+                PendingCompactedLayer::WaitingForUpperLayers
+            } else {
+                PendingCompactedLayer::NotNeeded
+            },
             cache_keys: HashSet::new(),
             pending_manifest_dependencies: HashMap::new(),
+            lazy_artifacts: HashSet::new(),
+            volumes: HashMap::new(),
         };
         let mut seen = HashMap::<Sha256Digest, ArtifactType>::new();
-        for (digest, type_) in layers {
+        for (digest, type_) in layers.iter().chain(mount_layers) {
             if let Some(previous_type) = seen.get(digest) {
                 // this needs to be avoided perhaps in the broker
                 assert_eq!(
@@ -152,6 +188,20 @@ impl LayerTracker {
         tracker
     }
 
+    /// Get the given volumes, each of which is identified by its digest and size limit.
+    pub fn fetch_volumes(
+        &mut self,
+        volumes: impl IntoIterator<Item = (Sha256Digest, u64)>,
+        fetcher: &mut impl Fetcher,
+    ) {
+        for (digest, size) in volumes {
+            self.volumes.insert(digest.clone(), None).assert_is_none();
+            if let FetcherResult::Got(path) = fetcher.fetch_volume(&digest, size) {
+                self.got_volume(&digest, path);
+            }
+        }
+    }
+
     fn bottom_layers_all_ready(&self) -> bool {
         self.bottom_layers
             .values()
@@ -181,6 +231,7 @@ impl LayerTracker {
             };
 
             if upper_index >= self.layers.len() {
+                self.fetch_compacted_fs_layer(fetcher);
                 break;
             }
 
@@ -203,6 +254,52 @@ impl LayerTracker {
         }
     }
 
+    /// The digest of the stack of all of the layers, which is also the digest of its compacted
+    /// layer.
+    fn stack_digest(&self) -> Sha256Digest {
+        self.layers
+            .tail
+            .iter()
+            .fold(self.layers.head.clone(), |lower_digest, upper_digest| {
+                upper_layer_digest(upper_digest, &lower_digest)
+            })
+    }
+
+    /// If the stack's compacted layer is already in the cache, use it, so that the upper layers
+    /// don't have to be built. Return whether it was used.
+    fn fetch_existing_compacted_fs_layer(&mut self, fetcher: &mut impl Fetcher) -> bool {
+        if self.compacted_fs_layer != PendingCompactedLayer::WaitingForUpperLayers {
+            return false;
+        }
+        let digest = self.stack_digest();
+        let Some(path) = fetcher.fetch_existing_compacted_fs_layer(&digest) else {
+            return false;
+        };
+        self.compacted_fs_layer = PendingCompactedLayer::WaitingForFsLayer;
+        self.got_compacted_fs_layer(&digest, path);
+        true
+    }
+
+    fn fetch_compacted_fs_layer(&mut self, fetcher: &mut impl Fetcher) {
+        if self.compacted_fs_layer != PendingCompactedLayer::WaitingForUpperLayers {
+            return;
+        }
+        let PendingTopLayer::StackedUpperLayers {
+            top_layer_path,
+            top_layer_digest,
+            ..
+        } = &self.top_fs_layer
+        else {
+            panic!("top layer unexpectedly not stacked");
+        };
+        let digest = top_layer_digest.clone();
+        let result = fetcher.fetch_compacted_fs_layer(&digest, top_layer_path);
+        self.compacted_fs_layer = PendingCompactedLayer::WaitingForFsLayer;
+        if let FetcherResult::Got(path) = result {
+            self.got_compacted_fs_layer(&digest, path);
+        }
+    }
+
     fn fetch_bottom_fs_layer(
         &mut self,
         digest: &Sha256Digest,
@@ -226,6 +323,10 @@ impl LayerTracker {
         self.cache_keys
             .insert(Key::new(CacheKeyKind::Blob, digest.clone()));
 
+        if self.lazy_artifacts.remove(digest) {
+            return;
+        }
+
         if self.pending_manifest_dependencies.contains_key(digest) {
             self.got_manifest_artifact(digest, fetcher);
             if !self.bottom_layers.contains_key(digest) {
@@ -294,12 +395,14 @@ impl LayerTracker {
             if let Some(pending_entry) = self.pending_manifest_dependencies.get_mut(&digest) {
                 pending_entry.push(manifest_digest.clone());
                 num_remaining += 1;
-            } else if bottom_layer_keys.contains(&digest)
-                || fetcher.fetch_artifact(&digest) == FetcherResult::Pending
-            {
+            } else if bottom_layer_keys.contains(&digest) {
                 self.pending_manifest_dependencies
                     .insert(digest, vec![manifest_digest.clone()]);
                 num_remaining += 1;
+            } else if self.lazy_artifacts.contains(&digest) {
+                continue;
+            } else if fetcher.fetch_artifact_lazily(&digest) == FetcherResult::Pending {
+                self.lazy_artifacts.insert(digest);
             } else {
                 self.cache_keys.insert(cache_key);
             }
@@ -328,7 +431,7 @@ impl LayerTracker {
         self.cache_keys
             .insert(Key::new(CacheKeyKind::BottomFsLayer, digest.clone()));
 
-        if self.bottom_layers_all_ready() {
+        if self.bottom_layers_all_ready() && !self.fetch_existing_compacted_fs_layer(fetcher) {
             self.fetch_upper_layers(fetcher);
         }
     }
@@ -345,20 +448,75 @@ impl LayerTracker {
         self.fetch_upper_layers(fetcher);
     }
 
+    pub fn got_compacted_fs_layer(&mut self, digest: &Sha256Digest, path: PathBuf) {
+        let existing = mem::replace(
+            &mut self.compacted_fs_layer,
+            PendingCompactedLayer::Ready {
+                fs_layer_path: path,
+            },
+        );
+        assert_eq!(existing, PendingCompactedLayer::WaitingForFsLayer);
+        self.cache_keys
+            .insert(Key::new(CacheKeyKind::CompactedFsLayer, digest.clone()));
+    }
+
+    pub fn got_volume(&mut self, digest: &Sha256Digest, path: PathBuf) {
+        self.cache_keys
+            .insert(Key::new(CacheKeyKind::Volume, digest.clone()));
+        let existing = self.volumes.get_mut(digest).unwrap().replace(path);
+        assert!(existing.is_none(), "unexpected got_volume");
+    }
+
     pub fn is_complete(&self) -> bool {
-        matches!(
-            self.top_fs_layer,
-            PendingTopLayer::StackedUpperLayers { index, .. } if index >= self.layers.len()
-        ) || (self.bottom_layers_all_ready() && self.layers.len() < 2)
+        let root_complete = match self.compacted_fs_layer {
+            PendingCompactedLayer::NotNeeded => {
+                matches!(
+                    self.top_fs_layer,
+                    PendingTopLayer::StackedUpperLayers { index, .. } if index >= self.layers.len()
+                ) || (self.bottom_layers_all_ready() && self.layers.len() < 2)
+            }
+            PendingCompactedLayer::Ready { .. } => true,
+            PendingCompactedLayer::WaitingForUpperLayers
+            | PendingCompactedLayer::WaitingForFsLayer => false,
+        };
+        root_complete && self.volumes.values().all(Option::is_some)
+    }
+
+    /// The artifacts for file contents that the job may start without. The job's reference to
+    /// each of these will be taken when it arrives.
+    pub fn lazy_artifacts(&self) -> &HashSet<Sha256Digest> {
+        &self.lazy_artifacts
     }
 
     pub fn into_cache_keys(self) -> HashSet<Key<CacheKeyKind>> {
         self.cache_keys
     }
 
+    /// The paths of the bottom FS layers for each of the job's layer mounts, in mount order.
+    pub fn mount_layer_paths(&self) -> Vec<PathBuf> {
+        assert!(self.is_complete());
+        self.mount_layers
+            .iter()
+            .map(|digest| {
+                self.bottom_layers
+                    .get(digest)
+                    .unwrap()
+                    .assert_ready_and_get_path()
+                    .clone()
+            })
+            .collect()
+    }
+
+    /// The path of the directory for the given volume.
+    pub fn volume_path(&self, digest: &Sha256Digest) -> &Path {
+        self.volumes.get(digest).unwrap().as_deref().unwrap()
+    }
+
     pub fn into_path_and_cache_keys(self) -> (PathBuf, HashSet<Key<CacheKeyKind>>) {
         assert!(self.is_complete());
-        if self.layers.len() < 2 {
+        if let PendingCompactedLayer::Ready { fs_layer_path } = self.compacted_fs_layer {
+            (fs_layer_path, self.cache_keys)
+        } else if self.layers.len() < 2 {
             (
                 self.bottom_layers
                     .get(self.layers.first())
@@ -388,7 +546,9 @@ mod tests {
         artifacts: HashMap<Sha256Digest, FetcherResult>,
         bottom_fs_layers: HashMap<Sha256Digest, FetcherResult>,
         upper_fs_layers: HashMap<Sha256Digest, FetcherResult>,
+        compacted_fs_layers: HashMap<Sha256Digest, FetcherResult>,
         manifest_digests: HashSet<(Sha256Digest, PathBuf)>,
+        volumes: HashMap<(Sha256Digest, u64), FetcherResult>,
     }
 
     impl TestFetcher {
@@ -402,9 +562,27 @@ mod tests {
                 artifacts: artifacts.into_iter().collect(),
                 bottom_fs_layers: bottom_fs_layers.into_iter().collect(),
                 upper_fs_layers: upper_fs_layers.into_iter().collect(),
+                compacted_fs_layers: HashMap::new(),
                 manifest_digests: manifest_digests.into_iter().collect(),
+                volumes: HashMap::new(),
             }
         }
+
+        fn with_volumes(
+            mut self,
+            volumes: impl IntoIterator<Item = ((Sha256Digest, u64), FetcherResult)>,
+        ) -> Self {
+            self.volumes = volumes.into_iter().collect();
+            self
+        }
+
+        fn with_compacted_fs_layers(
+            mut self,
+            compacted_fs_layers: impl IntoIterator<Item = (Sha256Digest, FetcherResult)>,
+        ) -> Self {
+            self.compacted_fs_layers = compacted_fs_layers.into_iter().collect();
+            self
+        }
     }
 
     impl Fetcher for TestFetcher {
@@ -412,6 +590,10 @@ mod tests {
             self.artifacts.remove(digest).unwrap()
         }
 
+        fn fetch_artifact_lazily(&mut self, digest: &Sha256Digest) -> FetcherResult {
+            self.artifacts.remove(digest).unwrap()
+        }
+
         fn fetch_bottom_fs_layer(
             &mut self,
             digest: &Sha256Digest,
@@ -430,11 +612,29 @@ mod tests {
             self.upper_fs_layers.remove(digest).unwrap()
         }
 
+        fn fetch_compacted_fs_layer(&mut self, digest: &Sha256Digest, _: &Path) -> FetcherResult {
+            self.compacted_fs_layers.remove(digest).unwrap()
+        }
+
+        fn fetch_existing_compacted_fs_layer(&mut self, digest: &Sha256Digest) -> Option<PathBuf> {
+            if *self.compacted_fs_layers.get(digest)? == FetcherResult::Pending {
+                return None;
+            }
+            let Some(FetcherResult::Got(path)) = self.compacted_fs_layers.remove(digest) else {
+                unreachable!();
+            };
+            Some(path)
+        }
+
         fn fetch_manifest_digests(&mut self, manifest_digest: &Sha256Digest, path: &Path) {
             self.manifest_digests
                 .remove(&(manifest_digest.clone(), path.to_path_buf()))
                 .assert_is_true();
         }
+
+        fn fetch_volume(&mut self, digest: &Sha256Digest, size: u64) -> FetcherResult {
+            self.volumes.remove(&(digest.clone(), size)).unwrap()
+        }
     }
 
     impl Drop for TestFetcher {
@@ -442,7 +642,9 @@ mod tests {
             assert_eq!(self.artifacts, Default::default());
             assert_eq!(self.bottom_fs_layers, Default::default());
             assert_eq!(self.upper_fs_layers, Default::default());
+            assert_eq!(self.compacted_fs_layers, Default::default());
             assert_eq!(self.manifest_digests, Default::default());
+            assert_eq!(self.volumes, Default::default());
         }
     }
 
@@ -464,7 +666,7 @@ mod tests {
             [],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(tracker.is_complete());
         assert_eq!(
@@ -491,7 +693,7 @@ mod tests {
             [],
             [(digest!(1), path_buf!("/blob/1"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_manifest_digests(&digest!(1), hashset! { digest!(2) }, &mut fetcher);
 
         assert!(tracker.is_complete());
@@ -508,6 +710,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn one_layer_volume_in_cache_into_path_and_cache_keys() {
+        let layers = nonempty![(digest!(1), ArtifactType::Tar)];
+        let mut fetcher = TestFetcher::new(
+            [(digest!(1), FetcherResult::Got(path_buf!("/blob/1")))],
+            [(digest!(1), FetcherResult::Got(path_buf!("/fs_b/1")))],
+            [],
+            [],
+        )
+        .with_volumes([(
+            (digest!(2), 100),
+            FetcherResult::Got(path_buf!("/volume/2")),
+        )]);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+        tracker.fetch_volumes([(digest!(2), 100)], &mut fetcher);
+
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.volume_path(&digest!(2)), Path::new("/volume/2"));
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (
+                path_buf!("/fs_b/1"),
+                hashset! {
+                    Key::new(CacheKeyKind::Blob, digest!(1)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+                    Key::new(CacheKeyKind::Volume, digest!(2)),
+                }
+            ),
+        );
+    }
+
+    #[test]
+    fn one_layer_volume_pending_then_got_into_path_and_cache_keys() {
+        let layers = nonempty![(digest!(1), ArtifactType::Tar)];
+        let mut fetcher = TestFetcher::new(
+            [(digest!(1), FetcherResult::Got(path_buf!("/blob/1")))],
+            [(digest!(1), FetcherResult::Got(path_buf!("/fs_b/1")))],
+            [],
+            [],
+        )
+        .with_volumes([((digest!(2), 100), FetcherResult::Pending)]);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+        tracker.fetch_volumes([(digest!(2), 100)], &mut fetcher);
+        assert!(!tracker.is_complete());
+
+        tracker.got_volume(&digest!(2), path_buf!("/volume/2"));
+
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.volume_path(&digest!(2)), Path::new("/volume/2"));
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (
+                path_buf!("/fs_b/1"),
+                hashset! {
+                    Key::new(CacheKeyKind::Blob, digest!(1)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+                    Key::new(CacheKeyKind::Volume, digest!(2)),
+                }
+            ),
+        );
+    }
+
     #[test]
     fn one_layer_pending_then_got_into_path_and_cache_keys() {
         let layers = nonempty![(digest!(1), ArtifactType::Tar)];
@@ -517,7 +781,7 @@ mod tests {
             [],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_artifact(&digest!(1), path_buf!("/blob/1"), &mut fetcher);
         tracker.got_bottom_fs_layer(&digest!(1), path_buf!("/fs_b/1"), &mut fetcher);
 
@@ -546,7 +810,7 @@ mod tests {
             [],
             [(digest!(1), path_buf!("/blob/1"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_artifact(&digest!(1), path_buf!("/blob/1"), &mut fetcher);
 
@@ -569,6 +833,138 @@ mod tests {
         );
     }
 
+    #[test]
+    fn one_layer_manifest_complete_without_lazy_artifacts() {
+        let layers = nonempty![(digest!(1), ArtifactType::Manifest)];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/blob/1"))),
+                (digest!(2), FetcherResult::Pending),
+                (digest!(3), FetcherResult::Got(path_buf!("/blob/3"))),
+            ],
+            [(digest!(1), FetcherResult::Got(path_buf!("/fs_b/1")))],
+            [],
+            [(digest!(1), path_buf!("/blob/1"))],
+        );
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+
+        tracker.got_manifest_digests(
+            &digest!(1),
+            hashset! { digest!(2), digest!(3) },
+            &mut fetcher,
+        );
+
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.lazy_artifacts(), &hashset! { digest!(2) });
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (
+                path_buf!("/fs_b/1"),
+                hashset! {
+                    Key::new(CacheKeyKind::Blob, digest!(1)),
+                    Key::new(CacheKeyKind::Blob, digest!(3)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+                }
+            ),
+        );
+    }
+
+    #[test]
+    fn one_layer_manifest_lazy_artifact_got_before_complete() {
+        let layers = nonempty![(digest!(1), ArtifactType::Manifest)];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/blob/1"))),
+                (digest!(2), FetcherResult::Pending),
+            ],
+            [(digest!(1), FetcherResult::Pending)],
+            [],
+            [(digest!(1), path_buf!("/blob/1"))],
+        );
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+
+        tracker.got_manifest_digests(&digest!(1), hashset! { digest!(2) }, &mut fetcher);
+        assert!(!tracker.is_complete());
+
+        tracker.got_artifact(&digest!(2), path_buf!("/blob/2"), &mut fetcher);
+        assert!(!tracker.is_complete());
+
+        tracker.got_bottom_fs_layer(&digest!(1), path_buf!("/fs_b/1"), &mut fetcher);
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.lazy_artifacts(), &hashset! {});
+        assert_eq!(
+            tracker.into_cache_keys(),
+            hashset! {
+                Key::new(CacheKeyKind::Blob, digest!(1)),
+                Key::new(CacheKeyKind::Blob, digest!(2)),
+                Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+            }
+        );
+    }
+
+    #[test]
+    fn one_layer_with_mount_layer_pending_then_got_into_path_and_cache_keys() {
+        let layers = nonempty![(digest!(1), ArtifactType::Tar)];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/blob/1"))),
+                (digest!(2), FetcherResult::Pending),
+            ],
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/fs_b/1"))),
+                (digest!(2), FetcherResult::Pending),
+            ],
+            [],
+            [],
+        );
+        let mut tracker =
+            LayerTracker::new(&layers, &[(digest!(2), ArtifactType::Tar)], &mut fetcher);
+        assert!(!tracker.is_complete());
+
+        tracker.got_artifact(&digest!(2), path_buf!("/blob/2"), &mut fetcher);
+        assert!(!tracker.is_complete());
+
+        tracker.got_bottom_fs_layer(&digest!(2), path_buf!("/fs_b/2"), &mut fetcher);
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.mount_layer_paths(), vec![path_buf!("/fs_b/2")]);
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (
+                path_buf!("/fs_b/1"),
+                hashset! {
+                    Key::new(CacheKeyKind::Blob, digest!(1)),
+                    Key::new(CacheKeyKind::Blob, digest!(2)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(2)),
+                }
+            ),
+        );
+    }
+
+    #[test]
+    fn mount_layer_same_as_layer() {
+        let layers = nonempty![(digest!(1), ArtifactType::Tar)];
+        let mut fetcher = TestFetcher::new(
+            [(digest!(1), FetcherResult::Got(path_buf!("/blob/1")))],
+            [(digest!(1), FetcherResult::Got(path_buf!("/fs_b/1")))],
+            [],
+            [],
+        );
+        let tracker = LayerTracker::new(
+            &layers,
+            &[
+                (digest!(1), ArtifactType::Tar),
+                (digest!(1), ArtifactType::Tar),
+            ],
+            &mut fetcher,
+        );
+        assert!(tracker.is_complete());
+        assert_eq!(
+            tracker.mount_layer_paths(),
+            vec![path_buf!("/fs_b/1"), path_buf!("/fs_b/1")]
+        );
+    }
+
     #[test]
     fn two_layers_everything_in_cache_into_path_and_cache_keys() {
         let layers = nonempty![
@@ -590,7 +986,7 @@ mod tests {
             )],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(tracker.is_complete());
         assert_eq!(
@@ -631,7 +1027,7 @@ mod tests {
             )],
             [(digest!(1), path_buf!("/blob/1"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(
             &digest!(1),
@@ -672,7 +1068,7 @@ mod tests {
             [],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(!tracker.is_complete());
         assert_eq!(
@@ -697,11 +1093,11 @@ mod tests {
                 (digest!(3), FetcherResult::Got(path_buf!("/blob/3"))),
                 (digest!(4), FetcherResult::Pending),
             ],
-            [],
+            [(digest!(2), FetcherResult::Pending)],
             [],
             [(digest!(2), path_buf!("/blob/2"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(
             &digest!(2),
@@ -737,7 +1133,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_artifact(&digest!(1), path_buf!("/blob/1"), &mut fetcher);
 
         assert!(!tracker.is_complete());
@@ -772,7 +1168,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [(digest!(2), path_buf!("/blob/2"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(
             &digest!(2),
@@ -814,7 +1210,7 @@ mod tests {
             [],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(!tracker.is_complete());
         assert_eq!(
@@ -845,7 +1241,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_bottom_fs_layer(&digest!(1), path_buf!("/fs_b/1"), &mut fetcher);
 
         assert!(!tracker.is_complete());
@@ -878,7 +1274,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(!tracker.is_complete());
         assert_eq!(
@@ -910,7 +1306,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_upper_fs_layer(&upper_digest!(1, 2), path_buf!("/fs_u/2"), &mut fetcher);
 
         assert!(tracker.is_complete());
@@ -944,7 +1340,7 @@ mod tests {
             [(upper_digest!(1, 2), FetcherResult::Pending)],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_artifact(&digest!(2), path_buf!("/blob/2"), &mut fetcher);
         tracker.got_bottom_fs_layer(&digest!(2), path_buf!("/fs_b/2"), &mut fetcher);
 
@@ -999,7 +1395,7 @@ mod tests {
             ],
             [],
         );
-        let tracker = LayerTracker::new(&layers, &mut fetcher);
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         assert!(tracker.is_complete());
         assert_eq!(
@@ -1056,7 +1452,7 @@ mod tests {
                 (digest!(3), path_buf!("/blob/3")),
             ],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(
             &digest!(1),
@@ -1095,6 +1491,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn two_manifest_layers_share_lazy_artifact() {
+        let layers = nonempty![
+            (digest!(2), ArtifactType::Manifest),
+            (digest!(1), ArtifactType::Manifest)
+        ];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/blob/1"))),
+                (digest!(2), FetcherResult::Got(path_buf!("/blob/2"))),
+                (digest!(3), FetcherResult::Pending),
+            ],
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/fs_b/1"))),
+                (digest!(2), FetcherResult::Got(path_buf!("/fs_b/2"))),
+            ],
+            [(
+                upper_digest!(1, 2),
+                FetcherResult::Got(path_buf!("/fs_u/2")),
+            )],
+            [
+                (digest!(1), path_buf!("/blob/1")),
+                (digest!(2), path_buf!("/blob/2")),
+            ],
+        );
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+
+        tracker.got_manifest_digests(&digest!(1), hashset! { digest!(3) }, &mut fetcher);
+        tracker.got_manifest_digests(&digest!(2), hashset! { digest!(3) }, &mut fetcher);
+        assert!(tracker.is_complete());
+        assert_eq!(tracker.lazy_artifacts(), &hashset! { digest!(3) });
+
+        tracker.got_artifact(&digest!(3), path_buf!("/blob/3"), &mut fetcher);
+        assert_eq!(tracker.lazy_artifacts(), &hashset! {});
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (
+                path_buf!("/fs_u/2"),
+                hashset! {
+                    Key::new(CacheKeyKind::Blob, digest!(1)),
+                    Key::new(CacheKeyKind::Blob, digest!(2)),
+                    Key::new(CacheKeyKind::Blob, digest!(3)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(1)),
+                    Key::new(CacheKeyKind::BottomFsLayer, digest!(2)),
+                    Key::new(CacheKeyKind::UpperFsLayer, upper_digest!(1, 2)),
+                }
+            ),
+        );
+    }
+
     #[test]
     fn six_layers_with_duplicates_three_pending_then_got_into_path_and_cache_keys() {
         let layers = nonempty![
@@ -1138,7 +1584,7 @@ mod tests {
             ],
             [],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
         tracker.got_artifact(&digest!(2), path_buf!("/blob/2"), &mut fetcher);
         tracker.got_bottom_fs_layer(&digest!(2), path_buf!("/fs_b/2"), &mut fetcher);
 
@@ -1162,6 +1608,89 @@ mod tests {
         );
     }
 
+    /// The upper layers are only expected to be fetched if the compacted layer isn't in the cache.
+    fn eight_layers_fetcher(compacted_fs_layer: FetcherResult) -> TestFetcher {
+        let mut upper_fs_layers = vec![];
+        let mut lower_digest = digest!(1);
+        for n in 2..=8 {
+            lower_digest = upper_layer_digest(&digest!(n), &lower_digest);
+            upper_fs_layers.push((
+                lower_digest.clone(),
+                FetcherResult::Got(PathBuf::from(format!("/fs_u/{n}"))),
+            ));
+        }
+        if compacted_fs_layer != FetcherResult::Pending {
+            upper_fs_layers.clear();
+        }
+        TestFetcher::new(
+            (1..=8).map(|n| {
+                (
+                    digest!(n),
+                    FetcherResult::Got(PathBuf::from(format!("/blob/{n}"))),
+                )
+            }),
+            (1..=8).map(|n| {
+                (
+                    digest!(n),
+                    FetcherResult::Got(PathBuf::from(format!("/fs_b/{n}"))),
+                )
+            }),
+            upper_fs_layers,
+            [],
+        )
+        .with_compacted_fs_layers([(lower_digest, compacted_fs_layer)])
+    }
+
+    fn eight_layers_cache_keys(with_upper_fs_layers: bool) -> HashSet<Key<CacheKeyKind>> {
+        let mut cache_keys = HashSet::new();
+        let mut lower_digest = digest!(1);
+        for n in 1..=8 {
+            cache_keys.insert(Key::new(CacheKeyKind::Blob, digest!(n)));
+            cache_keys.insert(Key::new(CacheKeyKind::BottomFsLayer, digest!(n)));
+            if n > 1 {
+                lower_digest = upper_layer_digest(&digest!(n), &lower_digest);
+                if with_upper_fs_layers {
+                    cache_keys.insert(Key::new(CacheKeyKind::UpperFsLayer, lower_digest.clone()));
+                }
+            }
+        }
+        cache_keys.insert(Key::new(CacheKeyKind::CompactedFsLayer, lower_digest));
+        cache_keys
+    }
+
+    #[test]
+    fn eight_layers_everything_in_cache_compacted_into_path_and_cache_keys() {
+        let layers = NonEmpty::collect((1..=8).map(|n| (digest!(n), ArtifactType::Tar))).unwrap();
+        let mut fetcher = eight_layers_fetcher(FetcherResult::Got(path_buf!("/fs_c/8")));
+        let tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+
+        assert!(tracker.is_complete());
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (path_buf!("/fs_c/8"), eight_layers_cache_keys(false)),
+        );
+    }
+
+    #[test]
+    fn eight_layers_compacted_layer_pending_then_got_into_path_and_cache_keys() {
+        let layers = NonEmpty::collect((1..=8).map(|n| (digest!(n), ArtifactType::Tar))).unwrap();
+        let mut fetcher = eight_layers_fetcher(FetcherResult::Pending);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
+
+        assert!(!tracker.is_complete());
+
+        let compacted_digest = (2..=8).fold(digest!(1), |lower_digest, n| {
+            upper_layer_digest(&digest!(n), &lower_digest)
+        });
+        tracker.got_compacted_fs_layer(&compacted_digest, path_buf!("/fs_c/8"));
+
+        assert!(tracker.is_complete());
+        assert_eq!(
+            tracker.into_path_and_cache_keys(),
+            (path_buf!("/fs_c/8"), eight_layers_cache_keys(true)),
+        );
+    }
+
     #[test]
     fn manifest_depends_on_existing_layer_fetch_manifest_dependency_after_layer() {
         let layers = nonempty![
@@ -1183,7 +1712,7 @@ mod tests {
             )],
             [(digest!(1), path_buf!("/blob/1"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(&digest!(1), hashset! { digest!(2) }, &mut fetcher);
 
@@ -1224,7 +1753,7 @@ mod tests {
             )],
             [(digest!(1), path_buf!("/blob/1"))],
         );
-        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        let mut tracker = LayerTracker::new(&layers, &[], &mut fetcher);
 
         tracker.got_manifest_digests(&digest!(1), hashset! { digest!(2) }, &mut fetcher);
         tracker.got_artifact(&digest!(2), path_buf!("/blob/2"), &mut fetcher);
@@ -1,28 +1,38 @@
 use crate::{
+    config::LayerStacking,
     dispatcher::{Deps, Message},
-    executor::{self, Executor, MountDir, TmpfsDir},
-    layer_fs,
+    executor::{self, Executor, LayerStacks, MountDir, SandboxKey, TmpfsDir, WarmSandbox},
+    layer_fs::{self, DispatcherBlobFetcher},
     manifest_digest_cache::ManifestDigestCache,
-    types::{DispatcherSender, TempFileFactory},
-    MAX_IN_FLIGHT_LAYERS_BUILDS,
+    overlay_layer,
+    subgid::SubordinateGids,
+    types::{DispatcherSender, TempFileFactory, VolumeTempFileFactory},
+    volume, MAX_IN_FLIGHT_LAYERS_BUILDS,
 };
-use anyhow::Result;
-use maelstrom_base::{ArtifactType, JobError, JobId, JobSpec, Sha256Digest};
+use anyhow::{anyhow, Result};
+use maelstrom_base::{ArtifactType, JobError, JobId, JobSpec, Sha256Digest, Utf8PathBuf};
 use maelstrom_layer_fs::{BlobDir, LayerFs, ReaderCache};
+use maelstrom_linux::{self as linux, OwnedFd};
 use maelstrom_util::{
-    cache::GotArtifact,
-    config::common::InlineLimit,
+    cache::{fs::TempDir as _, GotArtifact},
+    config::common::{BrokerAddr, InlineLimit},
     fs::Fs,
     root::RootBuf,
     sync::{self, EventReceiver, EventSender},
     time::SystemMonotonicClock,
 };
-use slog::{debug, o, Logger};
+use slog::{debug, o, warn, Logger};
 use std::{
-    sync::Arc,
-    {path::PathBuf, time::Duration},
+    collections::BTreeSet,
+    iter,
+    sync::{Arc, Mutex},
+    {
+        path::{Path, PathBuf},
+        time::Duration,
+    },
 };
 use tokio::{
+    runtime,
     task::{self, JoinHandle},
     time,
 };
@@ -35,10 +45,13 @@ pub struct DispatcherAdapter {
     log: Logger,
     executor: Arc<Executor<'static, SystemMonotonicClock>>,
     blob_dir: RootBuf<BlobDir>,
+    blob_fetcher: DispatcherBlobFetcher,
     layer_fs_cache: Arc<tokio::sync::Mutex<ReaderCache>>,
     manifest_digest_cache: ManifestDigestCache,
     layer_building_semaphore: Arc<tokio::sync::Semaphore>,
     temp_file_factory: TempFileFactory,
+    volume_temp_file_factory: VolumeTempFileFactory,
+    layer_stacking: LayerStacking,
 }
 
 impl DispatcherAdapter {
@@ -51,13 +64,32 @@ impl DispatcherAdapter {
         tmpfs_dir: RootBuf<TmpfsDir>,
         blob_dir: RootBuf<BlobDir>,
         temp_file_factory: TempFileFactory,
+        volume_temp_file_factory: VolumeTempFileFactory,
+        broker: Option<BrokerAddr>,
+        allowed_devices: Option<BTreeSet<Utf8PathBuf>>,
+        layer_stacking: LayerStacking,
     ) -> Result<Self> {
         let fs = Fs::new();
         fs.create_dir_all(&mount_dir)?;
         fs.create_dir_all(&tmpfs_dir)?;
+        let subordinate_gids = SubordinateGids::for_user(linux::getuid().as_u32())?;
+        if subordinate_gids.is_none() {
+            debug!(
+                log,
+                "no subordinate gids in /etc/subgid, jobs can't have supplementary groups"
+            );
+        }
         Ok(DispatcherAdapter {
             inline_limit,
-            executor: Arc::new(Executor::new(mount_dir, tmpfs_dir, &SystemMonotonicClock)?),
+            executor: Arc::new(Executor::new(
+                mount_dir,
+                tmpfs_dir,
+                broker,
+                allowed_devices,
+                subordinate_gids,
+                &SystemMonotonicClock,
+            )?),
+            blob_fetcher: DispatcherBlobFetcher::new(blob_dir.clone(), dispatcher_sender.clone()),
             blob_dir,
             layer_fs_cache: Arc::new(tokio::sync::Mutex::new(ReaderCache::new())),
             manifest_digest_cache: ManifestDigestCache::new(
@@ -71,14 +103,45 @@ impl DispatcherAdapter {
                 MAX_IN_FLIGHT_LAYERS_BUILDS,
             )),
             temp_file_factory,
+            volume_temp_file_factory,
+            layer_stacking,
         })
     }
 
+    /// The file system for the given layer. File contents that haven't been fetched yet are fetched
+    /// when they're first read.
+    fn layer_fs(&self, path: &Path) -> Result<LayerFs> {
+        Ok(LayerFs::from_path(path, self.blob_dir.as_root())?
+            .with_blob_fetcher(Arc::new(self.blob_fetcher.clone())))
+    }
+
+    /// The spec the executor needs to run a job, or to start a sandbox for one, whose layer stacks
+    /// are at `layer_fs_path` and `mount_layer_paths`.
+    fn executor_spec(
+        &self,
+        spec: JobSpec,
+        layer_fs_path: &Path,
+        mount_layer_paths: &[PathBuf],
+    ) -> Result<executor::JobSpec> {
+        let mut spec = executor::JobSpec::from_spec(spec);
+        if self.layer_stacking == LayerStacking::Overlayfs {
+            spec.layer_stacks = LayerStacks::Overlay(
+                iter::once(layer_fs_path)
+                    .chain(mount_layer_paths.iter().map(PathBuf::as_path))
+                    .map(overlay_layer::overlay_lower_dirs)
+                    .collect::<Result<_>>()?,
+            );
+        }
+        Ok(spec)
+    }
+
     fn start_job_inner(
         &mut self,
         jid: JobId,
         spec: JobSpec,
         layer_fs_path: PathBuf,
+        mount_layer_paths: Vec<PathBuf>,
+        sandbox: Option<SandboxHandle>,
         kill_event_receiver: EventReceiver,
     ) -> Result<()> {
         debug!(self.log, "job starting"; "spec" => ?spec);
@@ -88,21 +151,36 @@ impl DispatcherAdapter {
             "args" => format!("{:?}", spec.arguments)
         ));
 
-        let layer_fs = LayerFs::from_path(&layer_fs_path, self.blob_dir.as_root())?;
+        let spec = self.executor_spec(spec, &layer_fs_path, &mount_layer_paths)?;
+
+        // With FUSE, the executor asks for the root's file system first, followed by one for each
+        // layer mount, in order. With overlayfs, it doesn't ask for any. If the job is run in a warm
+        // sandbox, it skips the root's, unless the sandbox died and it has to start a new one.
+        let mut layer_fses = match self.layer_stacking {
+            LayerStacking::Fuse => iter::once(&layer_fs_path)
+                .chain(&mount_layer_paths)
+                .map(|path| self.layer_fs(path))
+                .collect::<Result<Vec<_>>>()?,
+            LayerStacking::Overlayfs => vec![],
+        }
+        .into_iter();
         let layer_fs_cache = self.layer_fs_cache.clone();
+        let fuse_log = log.clone();
         let fuse_spawn = move |fd| {
-            tokio::spawn(async move {
-                if let Err(e) = layer_fs.run_fuse(log.clone(), layer_fs_cache, fd).await {
-                    slog::error!(log, "FUSE handling got error {e:?}");
-                }
-            });
+            spawn_fuse(
+                layer_fses.next().unwrap(),
+                fuse_log.clone(),
+                layer_fs_cache.clone(),
+                fd,
+            )
         };
 
+        // If the sandbox isn't ready yet, we run the job without it.
+        let warm_sandbox = sandbox.and_then(|sandbox| sandbox.take());
         let executor = self.executor.clone();
-        let spec = executor::JobSpec::from_spec(spec);
         let inline_limit = self.inline_limit;
         let dispatcher_sender = self.dispatcher_sender.clone();
-        let runtime = tokio::runtime::Handle::current();
+        let runtime = runtime::Handle::current();
         task::spawn_blocking(move || {
             dispatcher_sender
                 .send(Message::JobCompleted(
@@ -110,6 +188,7 @@ impl DispatcherAdapter {
                     executor
                         .run_job(
                             &spec,
+                            warm_sandbox,
                             inline_limit,
                             kill_event_receiver,
                             fuse_spawn,
@@ -117,10 +196,80 @@ impl DispatcherAdapter {
                         )
                         .map_err(|e| e.map(|inner| inner.to_string())),
                 ))
-                .ok()
+                .ok();
         });
         Ok(())
     }
+
+    fn start_sandbox_inner(
+        &mut self,
+        spec: JobSpec,
+        layer_fs_path: PathBuf,
+        slot: Arc<Mutex<Option<WarmSandbox>>>,
+    ) -> Result<()> {
+        let key = SandboxKey::new(&spec, layer_fs_path.clone())
+            .ok_or_else(|| anyhow!("job can't be run in a warm sandbox"))?;
+        let spec = self.executor_spec(spec, &layer_fs_path, &[])?;
+        let layer_fs = (self.layer_stacking == LayerStacking::Fuse)
+            .then(|| self.layer_fs(&layer_fs_path))
+            .transpose()?;
+        let layer_fs_cache = self.layer_fs_cache.clone();
+        let log = self.log.clone();
+        let executor = self.executor.clone();
+        task::spawn_blocking(move || {
+            let fuse_log = log.clone();
+            let fuse_spawn = |fd| {
+                if let Some(layer_fs) = layer_fs {
+                    spawn_fuse(layer_fs, fuse_log, layer_fs_cache, fd)
+                }
+            };
+            match executor.warm_sandbox(&spec, &key, fuse_spawn) {
+                Ok(sandbox) => {
+                    // If the handle has already been dropped, the sandbox is killed when we drop
+                    // the slot.
+                    *slot.lock().unwrap() = Some(sandbox);
+                }
+                Err(e) => {
+                    debug!(log, "couldn't start warm sandbox"; "error" => ?e);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Serve `layer_fs` over the FUSE connection `fd` until it is unmounted.
+fn spawn_fuse(
+    layer_fs: LayerFs,
+    log: Logger,
+    layer_fs_cache: Arc<tokio::sync::Mutex<ReaderCache>>,
+    fd: OwnedFd,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = layer_fs.run_fuse(log.clone(), layer_fs_cache, fd).await {
+            slog::error!(log, "FUSE handling got error {e:?}");
+        }
+    });
+}
+
+/// A handle to a warm sandbox that may still be starting. Dropping it kills the sandbox.
+#[derive(Default)]
+pub struct SandboxHandle(Arc<Mutex<Option<WarmSandbox>>>);
+
+impl SandboxHandle {
+    /// Take the sandbox, if it's ready.
+    fn take(&self) -> Option<WarmSandbox> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl Drop for SandboxHandle {
+    fn drop(&mut self) {
+        // Killing the sandbox waits for it, so don't do it on the dispatcher's task.
+        if let (Some(sandbox), Ok(runtime)) = (self.take(), runtime::Handle::try_current()) {
+            runtime.spawn_blocking(move || drop(sandbox));
+        }
+    }
 }
 
 pub struct TimerHandle(JoinHandle<()>);
@@ -134,9 +283,23 @@ impl Drop for TimerHandle {
 impl Deps for DispatcherAdapter {
     type JobHandle = EventSender;
 
-    fn start_job(&mut self, jid: JobId, spec: JobSpec, layer_fs_path: PathBuf) -> Self::JobHandle {
+    fn start_job(
+        &mut self,
+        jid: JobId,
+        spec: JobSpec,
+        layer_fs_path: PathBuf,
+        mount_layer_paths: Vec<PathBuf>,
+        sandbox: Option<Self::SandboxHandle>,
+    ) -> Self::JobHandle {
         let (kill_event_sender, kill_event_receiver) = sync::event();
-        if let Err(e) = self.start_job_inner(jid, spec, layer_fs_path, kill_event_receiver) {
+        if let Err(e) = self.start_job_inner(
+            jid,
+            spec,
+            layer_fs_path,
+            mount_layer_paths,
+            sandbox,
+            kill_event_receiver,
+        ) {
             let _ = self.dispatcher_sender.send(Message::JobCompleted(
                 jid,
                 Err(JobError::System(e.to_string())),
@@ -145,6 +308,16 @@ impl Deps for DispatcherAdapter {
         kill_event_sender
     }
 
+    type SandboxHandle = SandboxHandle;
+
+    fn start_sandbox(&mut self, spec: JobSpec, layer_fs_path: PathBuf) -> Self::SandboxHandle {
+        let handle = SandboxHandle::default();
+        if let Err(e) = self.start_sandbox_inner(spec, layer_fs_path, handle.0.clone()) {
+            debug!(self.log, "couldn't start warm sandbox"; "error" => ?e);
+        }
+        handle
+    }
+
     type TimerHandle = TimerHandle;
 
     fn start_timer(&mut self, jid: JobId, duration: Duration) -> Self::TimerHandle {
@@ -166,19 +339,36 @@ impl Deps for DispatcherAdapter {
         let blob_dir = self.blob_dir.clone();
         let sem = self.layer_building_semaphore.clone();
         let temp_file_factory = self.temp_file_factory.clone();
+        let blob_fetcher = Arc::new(self.blob_fetcher.clone());
+        let layer_stacking = self.layer_stacking;
         task::spawn(async move {
             let _permit = sem.acquire().await.unwrap();
 
             debug!(log, "building bottom FS layer");
-            let result = layer_fs::build_bottom_layer(
-                log.clone(),
-                temp_file_factory,
-                blob_dir.as_root(),
-                digest.clone(),
-                artifact_type,
-                artifact_path,
-            )
-            .await;
+            let result = match layer_stacking {
+                LayerStacking::Fuse => {
+                    layer_fs::build_bottom_layer(
+                        log.clone(),
+                        temp_file_factory,
+                        blob_dir.as_root(),
+                        digest.clone(),
+                        artifact_type,
+                        artifact_path,
+                    )
+                    .await
+                }
+                LayerStacking::Overlayfs => {
+                    overlay_layer::build_bottom_layer(
+                        log.clone(),
+                        temp_file_factory,
+                        blob_dir.as_root(),
+                        blob_fetcher,
+                        artifact_type,
+                        artifact_path,
+                    )
+                    .await
+                }
+            };
             debug!(log, "built bottom FS layer"; "result" => ?result);
             sender
                 .send(Message::BuiltBottomFsLayer(
@@ -200,18 +390,31 @@ impl Deps for DispatcherAdapter {
         let blob_dir = self.blob_dir.clone();
         let sem = self.layer_building_semaphore.clone();
         let temp_file_factory = self.temp_file_factory.clone();
+        let layer_stacking = self.layer_stacking;
         task::spawn(async move {
             let _permit = sem.acquire().await.unwrap();
 
             debug!(log, "building upper FS layer");
-            let result = layer_fs::build_upper_layer(
-                log.clone(),
-                temp_file_factory,
-                blob_dir.as_root(),
-                lower_layer_path,
-                upper_layer_path,
-            )
-            .await;
+            let result = match layer_stacking {
+                LayerStacking::Fuse => {
+                    layer_fs::build_upper_layer(
+                        log.clone(),
+                        temp_file_factory,
+                        blob_dir.as_root(),
+                        lower_layer_path,
+                        upper_layer_path,
+                    )
+                    .await
+                }
+                LayerStacking::Overlayfs => {
+                    overlay_layer::build_upper_layer(
+                        temp_file_factory,
+                        lower_layer_path,
+                        upper_layer_path,
+                    )
+                    .await
+                }
+            };
             debug!(log, "built upper FS layer"; "result" => ?result);
             sender
                 .send(Message::BuiltUpperFsLayer(
@@ -222,7 +425,59 @@ impl Deps for DispatcherAdapter {
         });
     }
 
+    fn build_compacted_fs_layer(&mut self, digest: Sha256Digest, layer_path: PathBuf) {
+        let sender = self.dispatcher_sender.clone();
+        let log = self.log.clone();
+        let blob_dir = self.blob_dir.clone();
+        let sem = self.layer_building_semaphore.clone();
+        let temp_file_factory = self.temp_file_factory.clone();
+        let layer_stacking = self.layer_stacking;
+        task::spawn(async move {
+            let _permit = sem.acquire().await.unwrap();
+
+            debug!(log, "building compacted FS layer");
+            let result = match layer_stacking {
+                LayerStacking::Fuse => {
+                    layer_fs::build_compacted_layer(
+                        log.clone(),
+                        temp_file_factory,
+                        blob_dir.as_root(),
+                        layer_path,
+                    )
+                    .await
+                }
+                LayerStacking::Overlayfs => {
+                    overlay_layer::build_compacted_layer(temp_file_factory, layer_path).await
+                }
+            };
+            debug!(log, "built compacted FS layer"; "result" => ?result);
+            sender
+                .send(Message::BuiltCompactedFsLayer(
+                    digest,
+                    result.map(|(source, size)| GotArtifact::directory(source, size)),
+                ))
+                .ok();
+        });
+    }
+
     fn read_manifest_digests(&mut self, digest: Sha256Digest, path: PathBuf, jid: JobId) {
         self.manifest_digest_cache.get(digest, path, jid);
     }
+
+    fn create_volume(&mut self, digest: Sha256Digest, size: u64) {
+        let result = self.volume_temp_file_factory.temp_dir().and_then(|source| {
+            volume::mount_data_dir(source.path(), size)?;
+            Ok(GotArtifact::directory(source, size))
+        });
+        self.dispatcher_sender
+            .send(Message::CreatedVolume(digest, result))
+            .ok();
+    }
+
+    fn blob_fetch_finished(&mut self, digest: Sha256Digest, result: Result<(), String>) {
+        if let Err(err) = &result {
+            warn!(self.log, "couldn't fetch blob"; "digest" => %digest, "error" => %err);
+        }
+        self.blob_fetcher.blob_fetch_finished(&digest, result);
+    }
 }
@@ -1,44 +1,57 @@
 //! Easily start and stop processes.
 
+use crate::subgid::{self, SubordinateGids};
 use anyhow::{anyhow, Error, Result};
 use bumpalo::{
     collections::{CollectIn as _, String as BumpString, Vec as BumpVec},
     Bump,
 };
 use maelstrom_base::{
+    proto::{Hello, RelaySide},
     tty::{self, DecodeInputChunk, DecodeInputRemainder},
-    GroupId, JobCompleted, JobDevice, JobEffects, JobError, JobMount, JobNetwork, JobOutputResult,
-    JobResult, JobRootOverlay, JobTerminationStatus, JobTty, UserId, Utf8PathBuf, WindowSize,
+    EnumSet, GroupId, JobCapabilities, JobCapability, JobCompleted, JobDevice, JobEffects,
+    JobError, JobHostsEntry, JobMount, JobNetwork, JobOutputResult, JobResult, JobRootOverlay,
+    JobSecret, JobSecretDestination, JobSecretValue, JobSecrets, JobSecretsAddress, JobSidecar,
+    JobTerminationStatus, JobTty, JobTtyAddress, UserId, Utf8PathBuf, WindowSize,
 };
 use maelstrom_linux::{
-    self as linux, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno,
-    Fd, FileMode, FsconfigCommand, FsmountFlags, FsopenFlags, Gid, MountAttrs, MountFlags,
-    MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd, Signal, SockaddrNetlink, SockaddrUnStorage,
-    SocketDomain, SocketProtocol, SocketType, Uid, UmountFlags, WaitStatus,
+    self as linux, Capability, CapabilitySet, CloneArgs, CloneFlags, CloseRangeFirst,
+    CloseRangeFlags, CloseRangeLast, Errno, Fd, FileMode, FsconfigCommand, FsmountFlags,
+    FsopenFlags, Gid, MountAttrs, MountFlags, MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd,
+    Pid, PollEvents, PollFd, Signal, SockaddrIn, SockaddrNetlink, SockaddrUnStorage, SocketDomain,
+    SocketProtocol, SocketType, Uid, UmountFlags, WaitStatus,
 };
 use maelstrom_util::{
-    config::common::InlineLimit,
+    config::common::{BrokerAddr, InlineLimit},
     io::AsyncFile,
+    net,
     root::RootBuf,
     sync::EventReceiver,
     time::{Clock, ClockInstant as _},
     tty::open_pseudoterminal,
 };
-use maelstrom_worker_child::{FdSlot, Syscall};
+use maelstrom_worker_child::{FdSlot, ScriptSlot, Syscall, WarmChildArgs};
 use netlink_packet_core::{NetlinkMessage, NLM_F_ACK, NLM_F_CREATE, NLM_F_EXCL, NLM_F_REQUEST};
 use netlink_packet_route::{rtnl::constants::RTM_SETLINK, LinkMessage, RtnlMessage, IFF_UP};
+use slog::{o, Discard, Logger};
 use std::{
     cell::UnsafeCell,
+    collections::BTreeSet,
     ffi::{CStr, CString},
     fmt::Write as _,
     marker::PhantomData,
     mem,
     os::unix::{ffi::OsStrExt as _, fs::MetadataExt},
+    path::{Path, PathBuf},
+    ptr::NonNull,
     result,
+    time::Duration,
 };
 use tokio::{
-    io::{self, unix::AsyncFd, AsyncReadExt as _, AsyncWriteExt as _, Interest},
-    net::UnixStream,
+    io::{
+        self, unix::AsyncFd, AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _, Interest,
+    },
+    net::{TcpStream, UnixStream},
     runtime, select,
     sync::oneshot,
     task::JoinSet,
@@ -64,7 +77,29 @@ pub struct JobSpec {
     pub working_directory: Option<Utf8PathBuf>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
+    pub groups: Vec<GroupId>,
+    pub synthesize_user_files: bool,
+    pub hostname: Option<String>,
+    pub hosts: Vec<JobHostsEntry>,
+    pub capabilities: JobCapabilities,
+    pub init: bool,
     pub allocate_tty: Option<JobTty>,
+    pub sidecars: Vec<JobSidecar>,
+    pub secrets: Option<JobSecrets>,
+    pub layer_stacks: LayerStacks,
+}
+
+/// How the job's layer stacks are mounted. There is one stack for the root, followed by one for
+/// each layer mount, in order, followed by one for each sidecar layer.
+#[derive(Clone, Debug, Default)]
+pub enum LayerStacks {
+    /// Mount a FUSE file system for each stack, and have the parent serve a LayerFS on it.
+    #[default]
+    Fuse,
+
+    /// Mount a read-only overlay file system for each stack. Each stack is given as its lower
+    /// directories, from the top down. Overlay file systems need at least two of these.
+    Overlay(Vec<Vec<CString>>),
 }
 
 impl JobSpec {
@@ -80,8 +115,16 @@ impl JobSpec {
             working_directory,
             user,
             group,
+            groups,
+            synthesize_user_files,
+            hostname,
+            hosts,
+            capabilities,
+            init,
             estimated_duration: _,
             allocate_tty,
+            sidecars,
+            secrets,
             ..
         } = spec;
         JobSpec {
@@ -94,8 +137,101 @@ impl JobSpec {
             working_directory,
             user,
             group,
+            groups,
+            synthesize_user_files,
+            hostname,
+            hosts,
+            capabilities,
+            init,
             allocate_tty,
+            sidecars,
+            secrets,
+            layer_stacks: LayerStacks::Fuse,
+        }
+    }
+
+    /// The home directory of the user, if we're synthesizing user files. It matches the user's
+    /// name in the synthesized passwd entry. We mount a tmpfs on it so the user can write to it.
+    fn home(&self) -> Option<&'static CStr> {
+        self.synthesize_user_files
+            .then(|| match u32::from(self.user.unwrap_or(UserId::new(0))) {
+                0 => c"/root",
+                _ => c"/home/maelstrom",
+            })
+    }
+
+    /// Whether the user's home directory may not be in the image, and we can't create it in the
+    /// root because the root isn't writable. In that case, we mount an overlay on /home to create
+    /// it in. Root's home directory exists in nearly all images.
+    fn home_needs_overlay(&self) -> bool {
+        self.home().is_some_and(|home| home != c"/root")
+            && matches!(self.root_overlay, JobRootOverlay::None)
+    }
+
+    /// The number of [`JobMount::Layer`] mounts. Each one gets its own FUSE file system.
+    fn num_layer_mounts(&self) -> usize {
+        self.mounts
+            .iter()
+            .filter(|mount| matches!(mount, JobMount::Layer { .. }))
+            .count()
+    }
+
+    /// The total number of layers across all sidecars. Each one gets its own FUSE file system.
+    fn num_sidecar_layers(&self) -> usize {
+        self.sidecars
+            .iter()
+            .map(|sidecar| sidecar.layers.len())
+            .sum()
+    }
+
+    /// The supplementary groups for the job, not including the primary group.
+    fn supplementary_groups(&self) -> impl Iterator<Item = GroupId> + '_ {
+        let group = self.group.unwrap_or(GroupId::new(0));
+        self.groups.iter().copied().filter(move |g| *g != group)
+    }
+}
+
+/// The parts of a job's container that a pre-warmed sandbox has already set up: the user and
+/// network namespaces, the FUSE mount of the job's layer stack, and the root overlay. A warm
+/// sandbox can only be given to a job with the same key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SandboxKey {
+    layer_stack: PathBuf,
+    root_overlay: JobRootOverlay,
+    user: Option<UserId>,
+    group: Option<GroupId>,
+    new_network_namespace: bool,
+}
+
+impl SandboxKey {
+    /// Return the key for a job whose root file system is served from the layer stack at
+    /// `layer_stack`, or `None` if the job can't be run in a warm sandbox.
+    pub fn new(spec: &maelstrom_base::JobSpec, layer_stack: PathBuf) -> Option<Self> {
+        // Warm sandboxes only have a FUSE file system for the root. They share our address space
+        // until they exec, so they can't become init processes. Supplementary groups require us to
+        // write the gid map when the child starts, and a local root overlay's directories can't be
+        // used by two overlay mounts at once.
+        let group = spec.group.unwrap_or(GroupId::new(0));
+        if spec.init
+            || !spec.sidecars.is_empty()
+            || spec.mount_layers().next().is_some()
+            || spec.groups.iter().any(|g| *g != group)
+            || matches!(spec.root_overlay, JobRootOverlay::Local { .. })
+        {
+            return None;
         }
+        Some(SandboxKey {
+            layer_stack,
+            root_overlay: spec.root_overlay.clone(),
+            user: spec.user,
+            group: spec.group,
+            new_network_namespace: spec.network != JobNetwork::Local,
+        })
+    }
+
+    /// The layer stack that the root file system is served from.
+    pub fn layer_stack(&self) -> &Path {
+        &self.layer_stack
     }
 }
 
@@ -109,16 +245,29 @@ pub struct Executor<'clock, ClockT> {
     tmpfs_dir: CString,
     upper_dir: CString,
     work_dir: CString,
+    home_upper_dir: CString,
+    home_work_dir: CString,
+    files_dir: CString,
+    layers_dir: CString,
     root_mode: u32,
     netlink_socket_addr: SockaddrNetlink,
     netlink_message: Box<[u8]>,
+    broker: Option<BrokerAddr>,
+    allowed_devices: Option<BTreeSet<Utf8PathBuf>>,
+    subordinate_gids: Option<SubordinateGids>,
     clock: &'clock ClockT,
 }
 
 impl<'clock, ClockT> Executor<'clock, ClockT> {
+    /// Create a new executor. Jobs may only mount the host devices in `allowed_devices`. If it is
+    /// `None`, they may mount any host device. Jobs' supplementary groups are mapped into
+    /// `subordinate_gids`. If it is `None`, jobs may not have supplementary groups.
     pub fn new(
         mount_dir: RootBuf<MountDir>,
         tmpfs_dir: RootBuf<TmpfsDir>,
+        broker: Option<BrokerAddr>,
+        allowed_devices: Option<BTreeSet<Utf8PathBuf>>,
+        subordinate_gids: Option<SubordinateGids>,
         clock: &'clock ClockT,
     ) -> Result<Self> {
         // Set up stdin to be a file that will always return EOF. We could do something similar
@@ -143,6 +292,10 @@ impl<'clock, ClockT> Executor<'clock, ClockT> {
 
         struct OverlayFsUpperDir;
         struct OverlayFsWorkDir;
+        struct HomeOverlayFsUpperDir;
+        struct HomeOverlayFsWorkDir;
+        struct SynthesizedFilesDir;
+        struct LayerMountsDir;
 
         let user = UserId::from(linux::getuid().as_u32());
         let group = GroupId::from(linux::getgid().as_u32());
@@ -150,9 +303,17 @@ impl<'clock, ClockT> Executor<'clock, ClockT> {
         let mount_dir = CString::new(mount_dir.as_os_str().as_bytes())?;
         let upper_dir = tmpfs_dir.join::<OverlayFsUpperDir>("upper");
         let work_dir = tmpfs_dir.join::<OverlayFsWorkDir>("work");
+        let home_upper_dir = tmpfs_dir.join::<HomeOverlayFsUpperDir>("home-upper");
+        let home_work_dir = tmpfs_dir.join::<HomeOverlayFsWorkDir>("home-work");
+        let files_dir = tmpfs_dir.join::<SynthesizedFilesDir>("files");
+        let layers_dir = tmpfs_dir.join::<LayerMountsDir>("layers");
         let tmpfs_dir = CString::new(tmpfs_dir.as_os_str().as_bytes())?;
         let upper_dir = CString::new(upper_dir.as_os_str().as_bytes())?;
         let work_dir = CString::new(work_dir.as_os_str().as_bytes())?;
+        let home_upper_dir = CString::new(home_upper_dir.as_os_str().as_bytes())?;
+        let home_work_dir = CString::new(home_work_dir.as_os_str().as_bytes())?;
+        let files_dir = CString::new(files_dir.as_os_str().as_bytes())?;
+        let layers_dir = CString::new(layers_dir.as_os_str().as_bytes())?;
         let netlink_socket_addr = SockaddrNetlink::default();
         let mut netlink_message = LinkMessage::default();
         netlink_message.header.index = 1;
@@ -172,9 +333,16 @@ impl<'clock, ClockT> Executor<'clock, ClockT> {
             tmpfs_dir,
             upper_dir,
             work_dir,
+            home_upper_dir,
+            home_work_dir,
+            files_dir,
+            layers_dir,
             root_mode,
             netlink_socket_addr,
             netlink_message: buffer,
+            broker,
+            allowed_devices,
+            subordinate_gids,
             clock,
         })
     }
@@ -194,18 +362,50 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
     /// The `kill_event_receiver` is used to kill the child process. If the attached sender is ever
     /// closed, the child will be immediately killed with a SIGTERM.
     ///
+    /// The `fuse_spawn` callback is called with a FUSE file descriptor for the root file system,
+    /// followed by one for each [`JobMount::Layer`] mount, in order, and then one for each layer of
+    /// each sidecar, in order.
+    ///
+    /// If `warm_sandbox` is provided and it is still alive, the job is run in it. In that case, the
+    /// root file system is already being served, so `fuse_spawn` isn't called for it. The sandbox
+    /// must have been started for a job with the same [`SandboxKey`].
+    ///
     /// This function should be run in a `spawn_blocking` context. Ideally, this function would be
     /// async, but that doesn't work because we rely on [`bumpalo::Bump`] as a fast arena
     /// allocator, and it's not `Sync`.
     pub fn run_job(
         &self,
         spec: &JobSpec,
+        warm_sandbox: Option<WarmSandbox>,
         inline_limit: InlineLimit,
         kill_event_receiver: EventReceiver,
-        fuse_spawn: impl FnOnce(OwnedFd),
+        fuse_spawn: impl FnMut(OwnedFd),
         runtime: runtime::Handle,
     ) -> JobResult<JobCompleted, Error> {
-        self.run_job_inner(spec, inline_limit, kill_event_receiver, fuse_spawn, runtime)
+        self.run_job_inner(
+            spec,
+            warm_sandbox.filter(WarmSandbox::is_alive),
+            inline_limit,
+            kill_event_receiver,
+            fuse_spawn,
+            runtime,
+        )
+    }
+
+    /// Start a sandbox for jobs with the key `key`. The sandbox is set up as far as it can be
+    /// without knowing the rest of the job. `spec` should be the spec of a job with the same key.
+    ///
+    /// The `fuse_spawn` callback is called with the FUSE file descriptor for the root file system.
+    /// It must keep serving the file system for as long as the sandbox is around.
+    ///
+    /// This function should be run in a `spawn_blocking` context, just like [`Self::run_job`].
+    pub fn warm_sandbox(
+        &self,
+        spec: &JobSpec,
+        key: &SandboxKey,
+        fuse_spawn: impl FnOnce(OwnedFd),
+    ) -> JobResult<WarmSandbox, Error> {
+        self.start_warm_sandbox(spec, key, fuse_spawn)
     }
 }
 
@@ -220,6 +420,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
 
 async fn wait_for_child(
     child_pidfd: OwnedFd,
+    init_signal_fd: Option<OwnedFd>,
     mut kill_event_receiver: EventReceiver,
 ) -> Result<JobTerminationStatus> {
     let async_fd = AsyncFd::with_interest(child_pidfd, Interest::READABLE)?;
@@ -236,7 +437,18 @@ async fn wait_for_child(
             },
         }
     }
-    Ok(match linux::waitid(&async_fd.into_inner())? {
+    let status = linux::waitid(&async_fd.into_inner())?;
+
+    // An init process can't be killed by the signal that killed the job, so it tells us which
+    // signal it was instead.
+    if let Some(init_signal_fd) = &init_signal_fd {
+        let mut signal = [0u8];
+        if let Ok(1) = linux::read(init_signal_fd, &mut signal) {
+            return Ok(JobTerminationStatus::Signaled(signal[0]));
+        }
+    }
+
+    Ok(match status {
         WaitStatus::Exited(code) => JobTerminationStatus::Exited(code.as_u8()),
         WaitStatus::Signaled(signo) => JobTerminationStatus::Signaled(signo.as_u8()),
     })
@@ -292,6 +504,28 @@ impl<'a> ScriptBuilder<'a> {
         self.syscalls.push(syscall);
         self.error_transformers.push(error_transformer);
     }
+
+    /// Push a [`Syscall::Spawn`] that starts a child with `flags` to run `script`, followed by
+    /// `script` itself. Errors in `script` are reported by the child using their index in our
+    /// script, so they're mapped with `script`'s error transformers like any others.
+    fn push_spawn(
+        &mut self,
+        flags: CloneFlags,
+        script: ScriptBuilder<'a>,
+        pidfd: FdSlot<'a>,
+        error_transformer: &'a dyn Fn(&'static str) -> JobError<Error>,
+    ) {
+        self.push(
+            Syscall::Spawn {
+                flags,
+                script_len: script.syscalls.len(),
+                pidfd,
+            },
+            error_transformer,
+        );
+        self.syscalls.extend(script.syscalls);
+        self.error_transformers.extend(script.error_transformers);
+    }
 }
 
 fn bump_c_str<'bump>(bump: &'bump Bump, bytes: &str) -> Result<&'bump CStr> {
@@ -319,6 +553,104 @@ where
     JobError::Execution(Error::from(err))
 }
 
+/// Build the syscall that execs `program`. If `program` doesn't contain a slash and the environment
+/// has a `PATH`, we search the `PATH` for it. Secrets that are exposed as environment variables
+/// take precedence over variables in `environment` with the same name.
+fn exec_syscall<'bump>(
+    bump: &'bump Bump,
+    program: &Utf8PathBuf,
+    arguments: &[String],
+    environment: &[String],
+    secrets: &'bump [JobSecret],
+    home: Option<&CStr>,
+) -> JobResult<Syscall<'bump>, Error> {
+    let program_cstr = bump_c_str(bump, program.as_str()).map_err(syserr)?;
+    let mut argv = BumpVec::with_capacity_in(arguments.len().checked_add(2).unwrap(), bump);
+    argv.push(Some(&program_cstr.to_bytes_with_nul()[0]));
+    for argument in arguments {
+        let argument_cstr = bump_c_str(bump, argument.as_str()).map_err(syserr)?;
+        argv.push(Some(&argument_cstr.to_bytes_with_nul()[0]));
+    }
+    argv.push(None);
+    let secret_vars = || {
+        secrets
+            .iter()
+            .filter_map(|secret| match &secret.destination {
+                JobSecretDestination::Environment(name) => Some((name, &secret.value)),
+                JobSecretDestination::File(_) => None,
+            })
+    };
+    let mut envp = BumpVec::with_capacity_in(
+        environment
+            .len()
+            .checked_add(secrets.len())
+            .unwrap()
+            .checked_add(2)
+            .unwrap(),
+        bump,
+    );
+    for var in environment {
+        let overridden = secret_vars().any(|(name, _)| {
+            var.strip_prefix(name.as_str())
+                .is_some_and(|rest| rest.starts_with('='))
+        });
+        if !overridden {
+            let var_cstr = bump_c_str(bump, var.as_str()).map_err(syserr)?;
+            envp.push(Some(&var_cstr.to_bytes_with_nul()[0]));
+        }
+    }
+    for (name, value) in secret_vars() {
+        let mut var = BumpVec::new_in(bump);
+        var.extend_from_slice(name.as_bytes());
+        var.push(b'=');
+        var.extend_from_slice(value.as_bytes());
+        let var_cstr = bump_c_str_from_bytes(bump, &var)
+            .map_err(|err| execerr(anyhow!("secret environment variable {name}: {err}")))?;
+        envp.push(Some(&var_cstr.to_bytes_with_nul()[0]));
+    }
+    if let Some(home) = home {
+        if !environment.iter().any(|var| var.starts_with("HOME=")) {
+            let mut var = BumpVec::new_in(bump);
+            var.extend_from_slice(b"HOME=");
+            var.extend_from_slice(home.to_bytes());
+            let var_cstr = bump_c_str_from_bytes(bump, &var).map_err(syserr)?;
+            envp.push(Some(&var_cstr.to_bytes_with_nul()[0]));
+        }
+    }
+    envp.push(None);
+    Ok(
+        match (
+            environment.iter().find(|var| var.starts_with("PATH=")),
+            program.as_str(),
+        ) {
+            (Some(path), program_str) if !program_str.contains('/') => Syscall::ExecveList {
+                paths: path
+                    .strip_prefix("PATH=")
+                    .unwrap()
+                    .split(':')
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| {
+                        let mut bump_string = BumpString::from_str_in(dir, bump);
+                        bump_string.push('/');
+                        bump_string.push_str(program_str);
+                        bump_c_str(bump, bump_string.as_str())
+                    })
+                    .collect_in::<result::Result<BumpVec<_>, _>>(bump)
+                    .map_err(syserr)?
+                    .into_bump_slice(),
+                fallback: program_cstr,
+                argv: argv.into_bump_slice(),
+                envp: envp.into_bump_slice(),
+            },
+            _ => Syscall::Execve {
+                path: program_cstr,
+                argv: argv.into_bump_slice(),
+                envp: envp.into_bump_slice(),
+            },
+        },
+    )
+}
+
 fn new_fd_slot(bump: &Bump) -> FdSlot<'_> {
     FdSlot::new(bump.alloc(UnsafeCell::new(Fd::from_raw(-1))))
 }
@@ -344,7 +676,82 @@ impl Device {
     }
 }
 
+/// Map a capability to the Linux capability and its name, for error messages.
+fn capability(capability: JobCapability) -> (Capability, &'static str) {
+    match capability {
+        JobCapability::AuditControl => (Capability::AUDIT_CONTROL, "CAP_AUDIT_CONTROL"),
+        JobCapability::AuditRead => (Capability::AUDIT_READ, "CAP_AUDIT_READ"),
+        JobCapability::AuditWrite => (Capability::AUDIT_WRITE, "CAP_AUDIT_WRITE"),
+        JobCapability::BlockSuspend => (Capability::BLOCK_SUSPEND, "CAP_BLOCK_SUSPEND"),
+        JobCapability::Bpf => (Capability::BPF, "CAP_BPF"),
+        JobCapability::CheckpointRestore => {
+            (Capability::CHECKPOINT_RESTORE, "CAP_CHECKPOINT_RESTORE")
+        }
+        JobCapability::Chown => (Capability::CHOWN, "CAP_CHOWN"),
+        JobCapability::DacOverride => (Capability::DAC_OVERRIDE, "CAP_DAC_OVERRIDE"),
+        JobCapability::DacReadSearch => (Capability::DAC_READ_SEARCH, "CAP_DAC_READ_SEARCH"),
+        JobCapability::Fowner => (Capability::FOWNER, "CAP_FOWNER"),
+        JobCapability::Fsetid => (Capability::FSETID, "CAP_FSETID"),
+        JobCapability::IpcLock => (Capability::IPC_LOCK, "CAP_IPC_LOCK"),
+        JobCapability::IpcOwner => (Capability::IPC_OWNER, "CAP_IPC_OWNER"),
+        JobCapability::Kill => (Capability::KILL, "CAP_KILL"),
+        JobCapability::Lease => (Capability::LEASE, "CAP_LEASE"),
+        JobCapability::LinuxImmutable => (Capability::LINUX_IMMUTABLE, "CAP_LINUX_IMMUTABLE"),
+        JobCapability::MacAdmin => (Capability::MAC_ADMIN, "CAP_MAC_ADMIN"),
+        JobCapability::MacOverride => (Capability::MAC_OVERRIDE, "CAP_MAC_OVERRIDE"),
+        JobCapability::Mknod => (Capability::MKNOD, "CAP_MKNOD"),
+        JobCapability::NetAdmin => (Capability::NET_ADMIN, "CAP_NET_ADMIN"),
+        JobCapability::NetBindService => (Capability::NET_BIND_SERVICE, "CAP_NET_BIND_SERVICE"),
+        JobCapability::NetBroadcast => (Capability::NET_BROADCAST, "CAP_NET_BROADCAST"),
+        JobCapability::NetRaw => (Capability::NET_RAW, "CAP_NET_RAW"),
+        JobCapability::Perfmon => (Capability::PERFMON, "CAP_PERFMON"),
+        JobCapability::Setfcap => (Capability::SETFCAP, "CAP_SETFCAP"),
+        JobCapability::Setgid => (Capability::SETGID, "CAP_SETGID"),
+        JobCapability::Setpcap => (Capability::SETPCAP, "CAP_SETPCAP"),
+        JobCapability::Setuid => (Capability::SETUID, "CAP_SETUID"),
+        JobCapability::Syslog => (Capability::SYSLOG, "CAP_SYSLOG"),
+        JobCapability::SysAdmin => (Capability::SYS_ADMIN, "CAP_SYS_ADMIN"),
+        JobCapability::SysBoot => (Capability::SYS_BOOT, "CAP_SYS_BOOT"),
+        JobCapability::SysChroot => (Capability::SYS_CHROOT, "CAP_SYS_CHROOT"),
+        JobCapability::SysModule => (Capability::SYS_MODULE, "CAP_SYS_MODULE"),
+        JobCapability::SysNice => (Capability::SYS_NICE, "CAP_SYS_NICE"),
+        JobCapability::SysPacct => (Capability::SYS_PACCT, "CAP_SYS_PACCT"),
+        JobCapability::SysPtrace => (Capability::SYS_PTRACE, "CAP_SYS_PTRACE"),
+        JobCapability::SysRawio => (Capability::SYS_RAWIO, "CAP_SYS_RAWIO"),
+        JobCapability::SysResource => (Capability::SYS_RESOURCE, "CAP_SYS_RESOURCE"),
+        JobCapability::SysTime => (Capability::SYS_TIME, "CAP_SYS_TIME"),
+        JobCapability::SysTtyConfig => (Capability::SYS_TTY_CONFIG, "CAP_SYS_TTY_CONFIG"),
+        JobCapability::WakeAlarm => (Capability::WAKE_ALARM, "CAP_WAKE_ALARM"),
+    }
+}
+
+/// A file whose contents are generated by the executor and bind mounted over a file in the
+/// container's root. If `base` is provided, the contents are appended to that file's contents.
+struct SynthesizedFile<'bump> {
+    local_path: &'bump CStr,
+    base: Option<&'bump CStr>,
+    target: &'bump CStr,
+    target_str: &'bump str,
+    mode: FileMode,
+    contents: &'bump [u8],
+}
+
+fn user_name(user: UserId) -> &'static str {
+    match u32::from(user) {
+        0 => "root",
+        _ => "maelstrom",
+    }
+}
+
+fn group_name(group: GroupId) -> String {
+    match u32::from(group) {
+        0 => "root".into(),
+        _ => format!("maelstrom-{group}"),
+    }
+}
+
 struct ChildProcess<'bump, 'arg> {
+    child_pid: Pid,
     child_pidfd: Option<OwnedFd>,
     // These are like this so that the type is invariant over the lifetimes
     // This ensures the things these lifetimes represent actually outlive our type.
@@ -353,19 +760,22 @@ struct ChildProcess<'bump, 'arg> {
 }
 
 impl<'bump, 'arg> ChildProcess<'bump, 'arg> {
-    fn new(
+    fn new<ArgsT>(
         bump: &'bump Bump,
         clone_flags: CloneFlags,
         func: extern "C" fn(*mut core::ffi::c_void) -> i32,
-        args: &'arg mut maelstrom_worker_child::ChildArgs<'arg, 'bump>,
+        args: &'arg mut ArgsT,
     ) -> Result<Self> {
         let mut clone_args = CloneArgs::default()
             .flags(clone_flags)
             .exit_signal(Signal::CHLD);
-        const CHILD_STACK_SIZE: usize = 1024;
+        // The child runs the whole script on this stack, including forking sidecars from within it,
+        // and unoptimized builds use large stack frames. Overflowing it would silently corrupt the
+        // rest of the bump allocator's memory, so be generous.
+        const CHILD_STACK_SIZE: usize = 65536;
         let stack = bump.alloc_slice_fill_default(CHILD_STACK_SIZE);
         let stack_ptr: *mut u8 = stack.as_mut_ptr();
-        let (_, child_pidfd) = unsafe {
+        let (child_pid, child_pidfd) = unsafe {
             linux::clone_with_child_pidfd(
                 func,
                 stack_ptr.wrapping_add(CHILD_STACK_SIZE) as *mut _,
@@ -374,6 +784,7 @@ impl<'bump, 'arg> ChildProcess<'bump, 'arg> {
             )
         }?;
         Ok(Self {
+            child_pid,
             child_pidfd: Some(child_pidfd),
             _stack: PhantomData,
             _args: PhantomData,
@@ -399,6 +810,105 @@ impl<'bump, 'arg> Drop for ChildProcess<'bump, 'arg> {
     }
 }
 
+/// A child that has set up its namespaces, root FUSE mount, and root overlay, and is now waiting
+/// to be given the rest of a job's script. Dropping it kills the child and waits for it.
+pub struct WarmSandbox {
+    child_pidfd: Option<OwnedFd>,
+    read_sock: linux::UnixStream,
+    continuation: NonNull<ScriptSlot>,
+    // The child's stack lives in here, so it must outlive the child. We don't keep a
+    // `ChildProcess` around because it would borrow from this.
+    _bump: Bump,
+}
+
+// The child only reads the continuation slot after we write to it and wake the child up, and we
+// never touch the bump again after that.
+unsafe impl Send for WarmSandbox {}
+
+impl WarmSandbox {
+    fn is_alive(&self) -> bool {
+        let Some(child_pidfd) = &self.child_pidfd else {
+            return false;
+        };
+        // A pidfd becomes readable when the process exits.
+        let mut fds = [PollFd::new(child_pidfd.as_fd(), PollEvents::IN)];
+        matches!(linux::poll(&mut fds, Duration::ZERO), Ok(0))
+    }
+
+    /// Give the child the script in `script` and the job's stdio file descriptors, and wake it up.
+    /// The child will run `script` as if it were the rest of the script it was started with.
+    fn wake<'script>(
+        self,
+        script: &'script mut [Syscall<'_>],
+        stdio: &Stdio,
+    ) -> JobResult<WokenSandbox<'script>, Error> {
+        unsafe { self.continuation.as_ref().set(script) };
+        let sandbox = WokenSandbox {
+            sandbox: self,
+            _script: PhantomData,
+        };
+        let read_sock = &sandbox.sandbox.read_sock;
+        read_sock.send(&[0]).map_err(syserr)?;
+        let fds = match stdio {
+            Stdio::Pipes {
+                stdout_write,
+                stderr_write,
+                ..
+            } => [Some(stdout_write), Some(stderr_write), None],
+            Stdio::Pty { slave, .. } => [Some(slave), Some(slave), Some(slave)],
+        };
+        for fd in fds.into_iter().flatten() {
+            read_sock.send_with_fd(&[0], fd.as_fd()).map_err(syserr)?;
+        }
+        Ok(sandbox)
+    }
+}
+
+impl Drop for WarmSandbox {
+    fn drop(&mut self) {
+        if let Some(child_pidfd) = &self.child_pidfd {
+            // Just like for `ChildProcess`, we need to make sure the child is gone before we free
+            // its stack.
+            let _ = linux::pidfd_send_signal(child_pidfd, Signal::KILL);
+            let _ = linux::waitid(child_pidfd);
+        }
+    }
+}
+
+/// A [`WarmSandbox`] that has been woken up and is running a script that lives for `'script`.
+struct WokenSandbox<'script> {
+    sandbox: WarmSandbox,
+    _script: PhantomData<&'script mut &'script ()>,
+}
+
+impl WokenSandbox<'_> {
+    fn into_child_pidfd(mut self) -> OwnedFd {
+        self.sandbox.child_pidfd.take().unwrap()
+    }
+}
+
+/// The process that will exec a job: either one we cloned just for it, or a woken-up sandbox.
+enum JobChild<'bump, 'arg> {
+    Cold(ChildProcess<'bump, 'arg>, linux::UnixStream),
+    Warm(WokenSandbox<'arg>),
+}
+
+impl JobChild<'_, '_> {
+    fn read_sock(&self) -> &linux::UnixStream {
+        match self {
+            JobChild::Cold(_, read_sock) => read_sock,
+            JobChild::Warm(sandbox) => &sandbox.sandbox.read_sock,
+        }
+    }
+
+    fn into_child_pidfd(self) -> OwnedFd {
+        match self {
+            JobChild::Cold(child_process, _) => child_process.into_child_pidfd(),
+            JobChild::Warm(sandbox) => sandbox.into_child_pidfd(),
+        }
+    }
+}
+
 enum Stdio {
     Pipes {
         stdout_read: OwnedFd,
@@ -409,10 +919,19 @@ enum Stdio {
     Pty {
         master: OwnedFd,
         slave: OwnedFd,
-        socket: OwnedFd,
+        socket: TtySocket,
     },
 }
 
+/// The connection to whatever is on the other end of the job's TTY.
+enum TtySocket {
+    /// A Unix domain socket connected directly to the client.
+    Unix(OwnedFd),
+
+    /// A TCP socket connected to a TTY relay on the broker.
+    Tcp(std::net::TcpStream),
+}
+
 impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
     // Set up the network namespace. It's possible that we won't even create a new network
     // namespace, if JobNetwork::Local is specified.
@@ -490,7 +1009,24 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         builder.push(Syscall::SetSid, &|err| syserr(anyhow!("setsid: {err}")));
     }
 
-    fn set_up_stdio<'bump>(&'bump self, stdio: &Stdio, builder: &mut ScriptBuilder<'bump>) {
+    /// A warm sandbox was cloned before `stdio` was created, so it has to receive the file
+    /// descriptors from us instead of just using them.
+    fn set_up_stdio<'bump>(
+        &'bump self,
+        stdio: &Stdio,
+        warm: bool,
+        builder: &mut ScriptBuilder<'bump>,
+    ) {
+        let dup2 = |from: &OwnedFd, to| {
+            if warm {
+                Syscall::RecvFd { to }
+            } else {
+                Syscall::Dup2 {
+                    from: from.as_fd(),
+                    to,
+                }
+            }
+        };
         match stdio {
             Stdio::Pipes {
                 stdout_write,
@@ -500,46 +1036,26 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 // Dup2 the pipe file descriptors to be stdout and stderr. This will close the old
                 // stdout and stderr. We don't have to worry about closing the old fds because they
                 // will be marked close-on-exec below.
-                builder.push(
-                    Syscall::Dup2 {
-                        from: stdout_write.as_fd(),
-                        to: Fd::STDOUT,
-                    },
-                    &|err| syserr(anyhow!("dup2-ing to stdout: {err}")),
-                );
-                builder.push(
-                    Syscall::Dup2 {
-                        from: stderr_write.as_fd(),
-                        to: Fd::STDERR,
-                    },
-                    &|err| syserr(anyhow!("dup2-ing to stderr: {err}")),
-                );
+                builder.push(dup2(stdout_write, Fd::STDOUT), &|err| {
+                    syserr(anyhow!("dup2-ing to stdout: {err}"))
+                });
+                builder.push(dup2(stderr_write, Fd::STDERR), &|err| {
+                    syserr(anyhow!("dup2-ing to stderr: {err}"))
+                });
             }
             Stdio::Pty { slave, .. } => {
                 // Dup2 the pipe file descriptor to be stdin, stdout, and stderr. This will close
                 // the old stdin, stdout, and stderr. We don't have to worry about closing the old
                 // fd because it will be marked close-on-exec below.
-                builder.push(
-                    Syscall::Dup2 {
-                        from: slave.as_fd(),
-                        to: Fd::STDIN,
-                    },
-                    &|err| syserr(anyhow!("dup2-ing to stdin: {err}")),
-                );
-                builder.push(
-                    Syscall::Dup2 {
-                        from: slave.as_fd(),
-                        to: Fd::STDOUT,
-                    },
-                    &|err| syserr(anyhow!("dup2-ing to stdout: {err}")),
-                );
-                builder.push(
-                    Syscall::Dup2 {
-                        from: slave.as_fd(),
-                        to: Fd::STDERR,
-                    },
-                    &|err| syserr(anyhow!("dup2-ing to stderr: {err}")),
-                );
+                builder.push(dup2(slave, Fd::STDIN), &|err| {
+                    syserr(anyhow!("dup2-ing to stdin: {err}"))
+                });
+                builder.push(dup2(slave, Fd::STDOUT), &|err| {
+                    syserr(anyhow!("dup2-ing to stdout: {err}"))
+                });
+                builder.push(dup2(slave, Fd::STDERR), &|err| {
+                    syserr(anyhow!("dup2-ing to stderr: {err}"))
+                });
 
                 // We now have to make the slave PTY be our controlling terminal.
                 builder.push(
@@ -556,6 +1072,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
     fn set_up_user_namespace<'bump>(
         &'bump self,
         spec: &JobSpec,
+        write_sock: Fd,
         bump: &'bump Bump,
         builder: &mut ScriptBuilder<'bump>,
     ) -> JobResult<(), Error> {
@@ -587,6 +1104,30 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
             &|err| syserr(anyhow!("writing to /proc/self/uid_map: {err}")),
         );
 
+        if spec.supplementary_groups().next().is_some() {
+            // Mapping more than one gid requires CAP_SETGID in the parent user namespace, so we
+            // can't write the gid mapping ourselves. Instead, the parent writes it, then signals
+            // us by writing a byte to the exec result socket. After that, we're allowed to set
+            // our supplementary groups.
+            let sync_fd = FdSlot::new(bump.alloc(UnsafeCell::new(write_sock)));
+            builder.push(
+                Syscall::Read {
+                    fd: sync_fd,
+                    buf: bump.alloc_slice_fill_default(1),
+                },
+                &|err| syserr(anyhow!("waiting for parent to write gid_map: {err}")),
+            );
+            let groups = spec
+                .supplementary_groups()
+                .map(|group| Gid::from_u32(group.into()))
+                .collect_in::<BumpVec<_>>(bump)
+                .into_bump_slice();
+            builder.push(Syscall::SetGroups { groups }, &|err| {
+                syserr(anyhow!("setgroups: {err}"))
+            });
+            return Ok(());
+        }
+
         // This set of syscalls disables setgroups, which is required for setting up the gid
         // mapping.
         builder.push(
@@ -631,105 +1172,643 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         Ok(())
     }
 
-    fn set_up_fuse_root<'bump>(
+    fn set_up_hostname<'bump>(
         &'bump self,
-        spec: &JobSpec,
-        new_root_path: &'bump CStr,
-        bump: &'bump Bump,
+        spec: &'bump JobSpec,
         builder: &mut ScriptBuilder<'bump>,
     ) {
-        let fd = new_fd_slot(bump);
-
-        // Create the fuse mount. We need to do this in the child's namespace, then pass the file
-        // descriptor back to the parent.
-        builder.push(
-            Syscall::Open {
-                path: c"/dev/fuse",
-                flags: OpenFlags::RDWR | OpenFlags::NONBLOCK,
-                mode: FileMode::default(),
-                out: fd,
-            },
-            &|err| syserr(anyhow!("open /dev/fuse: {err}")),
-        );
+        // We're always in a new UTS namespace, so this won't affect the host.
+        if let Some(hostname) = &spec.hostname {
+            builder.push(
+                Syscall::SetHostname {
+                    name: hostname.as_bytes(),
+                },
+                &|err| execerr(anyhow!("sethostname: {err}")),
+            );
+        }
+    }
 
-        // Mount the fuse file system.
-        builder.push(
-            Syscall::FuseMount {
-                source: c"Maelstrom LayerFS",
-                target: new_root_path,
-                flags: MountFlags::NODEV | MountFlags::NOSUID | MountFlags::RDONLY,
-                root_mode: self.root_mode,
-                uid: Uid::from_u32(spec.user.unwrap_or(UserId::new(0)).into()),
-                gid: Gid::from_u32(spec.group.unwrap_or(GroupId::new(0)).into()),
-                fuse_fd: fd,
-            },
-            &|err| syserr(anyhow!("fuse mount: {err}")),
-        );
+    /// Generate the contents of all of the files we're going to synthesize for the job. The
+    /// contents are computed here in the parent, since we can't allocate in the child.
+    fn synthesize_files<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        secrets: &[JobSecret],
+        bump: &'bump Bump,
+    ) -> JobResult<BumpVec<'bump, SynthesizedFile<'bump>>, Error> {
+        let mut files = BumpVec::new_in(bump);
+        if !spec.hosts.is_empty() {
+            let mut contents = BumpString::new_in(bump);
+            writeln!(contents, "127.0.0.1\tlocalhost").map_err(syserr)?;
+            writeln!(contents, "::1\tlocalhost ip6-localhost ip6-loopback").map_err(syserr)?;
+            if let Some(hostname) = &spec.hostname {
+                writeln!(contents, "127.0.1.1\t{hostname}").map_err(syserr)?;
+            }
+            for JobHostsEntry { address, names } in &spec.hosts {
+                write!(contents, "{address}\t").map_err(syserr)?;
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        contents.push(' ');
+                    }
+                    contents.push_str(name);
+                }
+                contents.push('\n');
+            }
+            files.push(self.synthesized_file(
+                bump,
+                "hosts",
+                false,
+                c"/etc/hosts",
+                contents.into_bump_str().as_bytes(),
+            )?);
+        }
+        if let Some(home) = spec.home() {
+            // We add entries for the user and groups to the end of the image's files. If the
+            // image already has entries for them, those will take precedence.
+            let user = spec.user.unwrap_or(UserId::new(0));
+            let group = spec.group.unwrap_or(GroupId::new(0));
+            let user_name = user_name(user);
+
+            let mut passwd = BumpString::new_in(bump);
+            writeln!(
+                passwd,
+                "{user_name}:x:{user}:{group}::{}:/bin/sh",
+                home.to_str().unwrap()
+            )
+            .map_err(syserr)?;
+            files.push(self.synthesized_file(
+                bump,
+                "passwd",
+                true,
+                c"/etc/passwd",
+                passwd.into_bump_str().as_bytes(),
+            )?);
+
+            let mut groups = BumpString::new_in(bump);
+            writeln!(groups, "{}:x:{group}:{user_name}", group_name(group)).map_err(syserr)?;
+            for group in spec.supplementary_groups() {
+                writeln!(groups, "{}:x:{group}:{user_name}", group_name(group)).map_err(syserr)?;
+            }
+            files.push(self.synthesized_file(
+                bump,
+                "group",
+                true,
+                c"/etc/group",
+                groups.into_bump_str().as_bytes(),
+            )?);
+        }
+        for (index, secret) in secrets.iter().enumerate() {
+            if let JobSecretDestination::File(path) = &secret.destination {
+                // Only the job's user can read secret files. The files live in our scratch tmpfs,
+                // so they never touch the disk.
+                let mut name = BumpString::new_in(bump);
+                write!(name, "secret-{index}").map_err(syserr)?;
+                files.push(SynthesizedFile {
+                    mode: FileMode::RUSR,
+                    ..self.synthesized_file(
+                        bump,
+                        &name,
+                        false,
+                        bump_c_str(bump, path.as_str()).map_err(syserr)?,
+                        bump.alloc_slice_copy(secret.value.as_bytes()),
+                    )?
+                });
+            }
+        }
+        Ok(files)
+    }
 
-        // Send the fuse mount file descriptor from the child to the parent.
-        builder.push(
-            Syscall::SendMsg {
-                buf: &[0xFF; 8],
-                fd_to_send: fd,
-            },
-            &|err| syserr(anyhow!("sendmsg: {err}")),
-        );
+    fn synthesized_file<'bump>(
+        &'bump self,
+        bump: &'bump Bump,
+        name: &str,
+        append_to_image: bool,
+        target: &'bump CStr,
+        contents: &'bump [u8],
+    ) -> JobResult<SynthesizedFile<'bump>, Error> {
+        let mut local_path = BumpVec::new_in(bump);
+        local_path.extend_from_slice(self.files_dir.as_bytes());
+        local_path.push(b'/');
+        local_path.extend_from_slice(name.as_bytes());
+        let base = if append_to_image {
+            let mut base = BumpVec::new_in(bump);
+            base.extend_from_slice(self.mount_dir.as_bytes());
+            base.extend_from_slice(target.to_bytes());
+            Some(bump_c_str_from_bytes(bump, &base).map_err(syserr)?)
+        } else {
+            None
+        };
+        Ok(SynthesizedFile {
+            local_path: bump_c_str_from_bytes(bump, &local_path).map_err(syserr)?,
+            base,
+            target,
+            target_str: target.to_str().map_err(syserr)?,
+            mode: FileMode::RUSR | FileMode::WUSR | FileMode::RGRP | FileMode::ROTH,
+            contents,
+        })
     }
 
-    /// Set up the root overlay file system. This needs to happen after the root file system has
-    /// been mounted, but before the `pivot_root` has occurred, since we need to be able to access
-    /// local paths. Also, we need to do this before we mount other file systems, so we don't hide
-    /// other file systems.
-    fn set_up_root_overlay<'bump>(
+    /// Mount the tmpfs we use for scratch space, if we need it. We need it for the overlayfs's
+    /// upperdir and workdir if we have a tmp root overlay or an overlay on /home, to hold any
+    /// synthesized files, and to hold the mount points and overlayfs directories for layer mounts
+    /// and sidecars.
+    ///
+    /// A warm sandbox with a tmp root overlay has already mounted the tmpfs, so we just add to it.
+    fn set_up_tmpfs<'bump>(
         &'bump self,
         spec: &JobSpec,
-        new_root_path: &'bump CStr,
+        files: &[SynthesizedFile<'bump>],
+        warm: bool,
         bump: &'bump Bump,
         builder: &mut ScriptBuilder<'bump>,
-    ) -> JobResult<(), Error> {
-        let (upper, work) = match spec.root_overlay {
-            JobRootOverlay::None => {
-                // There is nothing to do. We're just going to have a read-only root without an
-                // overlay on top of it.
-                return Ok(());
-            }
+    ) {
+        let need_layers_dir = spec.num_layer_mounts() > 0 || !spec.sidecars.is_empty();
+        let tmp_root_overlay = matches!(spec.root_overlay, JobRootOverlay::Tmp { .. });
+        if !tmp_root_overlay && files.is_empty() && !need_layers_dir && !spec.home_needs_overlay() {
+            return;
+        }
 
-            JobRootOverlay::Tmp => {
-                // The overlay is going to write to a tmpfs that will be discarded when the job finishes.
-                // We need two directories on the same mount.
+        if !(warm && tmp_root_overlay) {
+            // Mount a new tmpfs that's local to this mount namespace. If the root overlay has a
+            // size limit, it applies to this whole tmpfs, since that's where the overlay's
+            // upperdir lives.
+            let data = match spec.root_overlay {
+                JobRootOverlay::Tmp { size: Some(size) } => {
+                    Some(bump.alloc_slice_copy(format!("size={size}\0").as_bytes()) as &[u8])
+                }
+                _ => None,
+            };
+            builder.push(
+                Syscall::Mount {
+                    source: None,
+                    target: self.tmpfs_dir.as_c_str(),
+                    fstype: Some(c"tmpfs"),
+                    flags: MountFlags::default(),
+                    data,
+                },
+                &|err| syserr(anyhow!("mounting tmpfs file system for job: {err}")),
+            );
+        }
 
-                let upper = self.upper_dir.as_c_str();
-                let work = self.work_dir.as_c_str();
+        if !files.is_empty() {
+            builder.push(
+                Syscall::Mkdir {
+                    path: self.files_dir.as_c_str(),
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making directory for synthesized files: {err}")),
+            );
+        }
 
-                // Mount a new tmpfs that's local to this mount namespace.
-                builder.push(
-                    Syscall::Mount {
-                        source: None,
-                        target: self.tmpfs_dir.as_c_str(),
-                        fstype: Some(c"tmpfs"),
-                        flags: MountFlags::default(),
-                        data: None,
-                    },
-                    &|err| {
-                        syserr(anyhow!(
-                            "mounting tmpfs file system for overlayfs's upperdir and workdir: {err}"
-                        ))
-                    },
-                );
+        if need_layers_dir {
+            builder.push(
+                Syscall::Mkdir {
+                    path: self.layers_dir.as_c_str(),
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making directory for layer mounts: {err}")),
+            );
+        }
+    }
 
-                // Create the two directories in the newly-created tmpfs.
-                builder.push(
-                    Syscall::Mkdir {
-                        path: upper,
-                        mode: FileMode::RWXU,
-                    },
-                    &|err| syserr(anyhow!("making uppderdir for overlayfs: {err}")),
-                );
-                builder.push(
-                    Syscall::Mkdir {
-                        path: work,
-                        mode: FileMode::RWXU,
-                    },
+    /// The path of a directory in our scratch tmpfs used for the layer mount at `index`. Each
+    /// layer mount gets a "lower" directory for its FUSE file system, and "upper" and "work"
+    /// directories if it's writable.
+    fn layer_mount_dir<'bump>(
+        &'bump self,
+        bump: &'bump Bump,
+        index: usize,
+        name: &str,
+    ) -> JobResult<&'bump CStr, Error> {
+        let mut path = BumpString::new_in(bump);
+        write!(path, "/{index}-{name}").unwrap();
+        let mut bytes = BumpVec::new_in(bump);
+        bytes.extend_from_slice(self.layers_dir.as_bytes());
+        bytes.extend_from_slice(path.as_bytes());
+        bump_c_str_from_bytes(bump, &bytes).map_err(syserr)
+    }
+
+    /// The path of a directory in our scratch tmpfs used for the sidecar at `index`. Each sidecar
+    /// gets a "lower" directory for each of its layers' FUSE file systems, and "upper" and "work"
+    /// directories for the overlay file system that stacks them.
+    fn sidecar_dir<'bump>(
+        &'bump self,
+        bump: &'bump Bump,
+        index: usize,
+        name: impl std::fmt::Display,
+    ) -> JobResult<&'bump CStr, Error> {
+        let mut path = BumpString::new_in(bump);
+        write!(path, "/sidecar-{index}-{name}").unwrap();
+        let mut bytes = BumpVec::new_in(bump);
+        bytes.extend_from_slice(self.layers_dir.as_bytes());
+        bytes.extend_from_slice(path.as_bytes());
+        bump_c_str_from_bytes(bump, &bytes).map_err(syserr)
+    }
+
+    /// Write out all of the synthesized files and open detached bind mounts for them. This has to
+    /// happen before the `pivot_root`, since the files live outside of the container's root.
+    fn open_mount_fds_for_synthesized_files_pre_pivot_root<'bump>(
+        &'bump self,
+        files: &[SynthesizedFile<'bump>],
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+        mount_fds: &mut BumpVec<'bump, FdSlot<'bump>>,
+    ) {
+        for &SynthesizedFile {
+            local_path,
+            base,
+            target_str,
+            mode,
+            contents,
+            ..
+        } in files
+        {
+            let fd = new_fd_slot(bump);
+            builder.push(
+                Syscall::Open {
+                    path: local_path,
+                    flags: OpenFlags::WRONLY | OpenFlags::CREAT | OpenFlags::TRUNC,
+                    mode,
+                    out: fd,
+                },
+                bump.alloc(move |err| {
+                    syserr(anyhow!("creating synthesized file for {target_str}: {err}"))
+                }),
+            );
+            if let Some(base) = base {
+                // The container's root is mounted at this point, so we can read the image's
+                // version of the file. If the image doesn't have one, we start from scratch.
+                builder.push(
+                    Syscall::CopyFileIfExists { from: base, to: fd },
+                    bump.alloc(move |err| {
+                        execerr(anyhow!("copying {target_str} from container: {err}"))
+                    }),
+                );
+            }
+            builder.push(
+                Syscall::Write { fd, buf: contents },
+                bump.alloc(move |err| {
+                    syserr(anyhow!("writing synthesized file for {target_str}: {err}"))
+                }),
+            );
+
+            let mount_fd = new_fd_slot(bump);
+            mount_fds.push(mount_fd);
+            builder.push(
+                Syscall::OpenTree {
+                    dirfd: Fd::AT_FDCWD,
+                    path: local_path,
+                    // We don't pass recursive because we're just cloning a file.
+                    flags: OpenTreeFlags::CLONE,
+                    out: mount_fd,
+                },
+                bump.alloc(move |err| {
+                    syserr(anyhow!(
+                        "opening synthesized file for bind mount of {target_str}: {err}"
+                    ))
+                }),
+            );
+        }
+    }
+
+    fn complete_synthesized_files_post_pivot_root<'bump>(
+        &'bump self,
+        files: &[SynthesizedFile<'bump>],
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+        mount_fds: &mut impl Iterator<Item = FdSlot<'bump>>,
+    ) {
+        // The target files have to already exist in the container, since we don't assume we have
+        // a writable root to create them in. The exception is files we add to, which the image
+        // may not have: we create those if the root is writable.
+        for &SynthesizedFile {
+            base,
+            target,
+            target_str,
+            ..
+        } in files
+        {
+            if base.is_some() {
+                // Opening an existing file with O_CREAT works even on a read-only file system.
+                builder.push(
+                    Syscall::Open {
+                        path: target,
+                        flags: OpenFlags::CREAT,
+                        mode: FileMode::RUSR | FileMode::WUSR | FileMode::RGRP | FileMode::ROTH,
+                        out: new_fd_slot(bump),
+                    },
+                    bump.alloc(move |err| {
+                        execerr(anyhow!(
+                            "creating {target_str} in container (the image doesn't have one, and \
+                            the root file system isn't writable): {err}"
+                        ))
+                    }),
+                );
+            }
+            builder.push(
+                Syscall::MoveMount {
+                    from_dirfd: mount_fds.next().unwrap(),
+                    from_path: c"",
+                    to_dirfd: Fd::AT_FDCWD,
+                    to_path: target,
+                    flags: MoveMountFlags::F_EMPTY_PATH,
+                },
+                bump.alloc(move |err| {
+                    execerr(anyhow!(
+                        "move_mount for bind mount of synthesized {target_str}: {err}"
+                    ))
+                }),
+            );
+        }
+    }
+
+    /// Make sure the user's home directory exists, so we can mount a tmpfs on it after the
+    /// pivot_root. If the root isn't writable, we first mount an overlay on /home, so the image's
+    /// home directories are still there.
+    fn set_up_home_pre_pivot_root<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        new_root_path: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        let Some(home) = spec.home() else {
+            return Ok(());
+        };
+        if home == c"/root" {
+            return Ok(());
+        }
+
+        if spec.home_needs_overlay() {
+            let upper = self.home_upper_dir.as_c_str();
+            let work = self.home_work_dir.as_c_str();
+            builder.push(
+                Syscall::Mkdir {
+                    path: upper,
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making upperdir for /home overlayfs: {err}")),
+            );
+            builder.push(
+                Syscall::Mkdir {
+                    path: work,
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making workdir for /home overlayfs: {err}")),
+            );
+            let mut homes = BumpVec::new_in(bump);
+            homes.extend_from_slice(new_root_path.to_bytes());
+            homes.extend_from_slice(b"/home");
+            let homes = bump_c_str_from_bytes(bump, &homes).map_err(syserr)?;
+            let fd = self.open_overlay_mount_fd(homes, upper, work, bump, builder);
+            builder.push(
+                Syscall::MoveMount {
+                    from_dirfd: fd,
+                    from_path: c"",
+                    to_dirfd: Fd::AT_FDCWD,
+                    to_path: homes,
+                    flags: MoveMountFlags::F_EMPTY_PATH,
+                },
+                &|err| execerr(anyhow!("move_mount for /home overlayfs: {err}")),
+            );
+        }
+
+        let mut path = BumpVec::new_in(bump);
+        path.extend_from_slice(new_root_path.to_bytes());
+        path.extend_from_slice(home.to_bytes());
+        builder.push(
+            Syscall::MkdirIfMissing {
+                path: bump_c_str_from_bytes(bump, &path).map_err(syserr)?,
+                mode: FileMode::RWXU
+                    | FileMode::RGRP
+                    | FileMode::XGRP
+                    | FileMode::ROTH
+                    | FileMode::XOTH,
+            },
+            &|err| execerr(anyhow!("making home directory: {err}")),
+        );
+        Ok(())
+    }
+
+    fn set_up_home<'bump>(&'bump self, spec: &'bump JobSpec, builder: &mut ScriptBuilder<'bump>) {
+        // Give the user an empty, writable home directory.
+        if let Some(home) = spec.home() {
+            builder.push(
+                Syscall::Mount {
+                    source: None,
+                    target: home,
+                    fstype: Some(c"tmpfs"),
+                    flags: MountFlags::default(),
+                    data: None,
+                },
+                &|err| execerr(anyhow!("mounting tmpfs for home directory: {err}")),
+            );
+        }
+    }
+
+    /// Mount a FUSE file system at `target`, and send the FUSE file descriptor back to the parent,
+    /// which will serve a LayerFS on it. The parent serves the file systems in the order they are
+    /// mounted: the root first, then each layer mount.
+    fn set_up_fuse_mount<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        target: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) {
+        let fd = new_fd_slot(bump);
+
+        // Create the fuse mount. We need to do this in the child's namespace, then pass the file
+        // descriptor back to the parent.
+        builder.push(
+            Syscall::Open {
+                path: c"/dev/fuse",
+                flags: OpenFlags::RDWR | OpenFlags::NONBLOCK,
+                mode: FileMode::default(),
+                out: fd,
+            },
+            &|err| syserr(anyhow!("open /dev/fuse: {err}")),
+        );
+
+        // Mount the fuse file system.
+        builder.push(
+            Syscall::FuseMount {
+                source: c"Maelstrom LayerFS",
+                target,
+                flags: MountFlags::NODEV | MountFlags::NOSUID | MountFlags::RDONLY,
+                root_mode: self.root_mode,
+                uid: Uid::from_u32(spec.user.unwrap_or(UserId::new(0)).into()),
+                gid: Gid::from_u32(spec.group.unwrap_or(GroupId::new(0)).into()),
+                fuse_fd: fd,
+            },
+            &|err| syserr(anyhow!("fuse mount: {err}")),
+        );
+
+        // Send the fuse mount file descriptor from the child to the parent.
+        builder.push(
+            Syscall::SendMsg {
+                buf: &[0xFF; 8],
+                fd_to_send: fd,
+            },
+            &|err| syserr(anyhow!("sendmsg: {err}")),
+        );
+    }
+
+    /// Mount the job's layer stack with the given index at `target`. See [`LayerStacks`] for how
+    /// the stacks are indexed.
+    fn set_up_layer_stack_mount<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        index: usize,
+        target: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        match &spec.layer_stacks {
+            LayerStacks::Fuse => {
+                self.set_up_fuse_mount(spec, target, bump, builder);
+            }
+            LayerStacks::Overlay(stacks) => {
+                let lowers = stacks
+                    .get(index)
+                    .ok_or_else(|| syserr(anyhow!("missing layer stack {index}")))?;
+                let lowers = lowers
+                    .iter()
+                    .map(|lower| bump_c_str_from_bytes(bump, lower.to_bytes()))
+                    .collect::<Result<Vec<_>>>()
+                    .map_err(syserr)?;
+                self.set_up_layer_overlay_mount(&lowers, target, bump, builder);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mount a read-only overlay file system of the layer directories `lowers` at `target`. The
+    /// layer directories are added one at a time with `lowerdir+`, which needs Linux 6.5 or later,
+    /// since `lowerdir` values can't be longer than a page. Opaque directories are marked with the
+    /// `user.overlay.opaque` extended attribute, since `trusted` ones can't be set without
+    /// privileges. Whiteouts are 0/0 character devices, which can only be created without
+    /// privileges on Linux 5.8 or later.
+    fn set_up_layer_overlay_mount<'bump>(
+        &'bump self,
+        lowers: &[&'bump CStr],
+        target: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) {
+        let fd = new_fd_slot(bump);
+
+        builder.push(
+            Syscall::Fsopen {
+                fsname: c"overlay",
+                flags: FsopenFlags::default(),
+                out: fd,
+            },
+            &|err| syserr(anyhow!("fsopen of overlayfs for layers: {err}")),
+        );
+        for lower in lowers {
+            builder.push(
+                Syscall::Fsconfig {
+                    fd,
+                    command: FsconfigCommand::SET_STRING,
+                    key: Some(c"lowerdir+"),
+                    value: Some(&lower.to_bytes_with_nul()[0]),
+                    aux: None,
+                },
+                &|err| {
+                    syserr(anyhow!(
+                        "fsconfig of lowerdir+ for overlayfs of layers: {err}"
+                    ))
+                },
+            );
+        }
+        builder.push(
+            Syscall::Fsconfig {
+                fd,
+                command: FsconfigCommand::SET_FLAG,
+                key: Some(c"userxattr"),
+                value: None,
+                aux: None,
+            },
+            &|err| {
+                syserr(anyhow!(
+                    "fsconfig of userxattr for overlayfs of layers: {err}"
+                ))
+            },
+        );
+        builder.push(
+            Syscall::Fsconfig {
+                fd,
+                command: FsconfigCommand::CMD_CREATE,
+                key: None,
+                value: None,
+                aux: None,
+            },
+            &|err| {
+                syserr(anyhow!(
+                    "fsconfig of CMD_CREATE for overlayfs of layers: {err}"
+                ))
+            },
+        );
+        builder.push(
+            Syscall::Fsmount {
+                fd,
+                flags: FsmountFlags::default(),
+                mount_attrs: MountAttrs::RDONLY | MountAttrs::NODEV | MountAttrs::NOSUID,
+                out: fd,
+            },
+            &|err| syserr(anyhow!("fsmount for overlayfs of layers: {err}")),
+        );
+        builder.push(
+            Syscall::MoveMount {
+                from_dirfd: fd,
+                from_path: c"",
+                to_dirfd: Fd::AT_FDCWD,
+                to_path: target,
+                flags: MoveMountFlags::F_EMPTY_PATH,
+            },
+            &|err| syserr(anyhow!("move_mount for overlayfs of layers: {err}")),
+        );
+    }
+
+    /// Set up the root overlay file system. This needs to happen after the root file system has
+    /// been mounted, but before the `pivot_root` has occurred, since we need to be able to access
+    /// local paths. Also, we need to do this before we mount other file systems, so we don't hide
+    /// other file systems.
+    fn set_up_root_overlay<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        new_root_path: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        let (upper, work) = match spec.root_overlay {
+            JobRootOverlay::None => {
+                // There is nothing to do. We're just going to have a read-only root without an
+                // overlay on top of it.
+                return Ok(());
+            }
+
+            JobRootOverlay::Tmp { .. } => {
+                // The overlay is going to write to a tmpfs that will be discarded when the job finishes.
+                // We need two directories on the same mount.
+
+                let upper = self.upper_dir.as_c_str();
+                let work = self.work_dir.as_c_str();
+
+                // Create the two directories in the tmpfs created by set_up_tmpfs.
+                builder.push(
+                    Syscall::Mkdir {
+                        path: upper,
+                        mode: FileMode::RWXU,
+                    },
+                    &|err| syserr(anyhow!("making uppderdir for overlayfs: {err}")),
+                );
+                builder.push(
+                    Syscall::Mkdir {
+                        path: work,
+                        mode: FileMode::RWXU,
+                    },
                     &|err| syserr(anyhow!("making workdir for overlayfs: {err}")),
                 );
 
@@ -749,6 +1828,33 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
             }
         };
 
+        let fd = self.open_overlay_mount_fd(new_root_path, upper, work, bump, builder);
+
+        // Attach the mount to the file tree.
+        builder.push(
+            Syscall::MoveMount {
+                from_dirfd: fd,
+                from_path: c"",
+                to_dirfd: Fd::AT_FDCWD,
+                to_path: new_root_path,
+                flags: MoveMountFlags::F_EMPTY_PATH,
+            },
+            &|err| syserr(anyhow!("move_mount for overlayfs: {err}")),
+        );
+
+        Ok(())
+    }
+
+    /// Create a detached overlay file system mount, returning the slot for the mount's file
+    /// descriptor. The upper and work directories must already exist on the same file system.
+    fn open_overlay_mount_fd<'bump>(
+        &'bump self,
+        lower: &'bump CStr,
+        upper: &'bump CStr,
+        work: &'bump CStr,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> FdSlot<'bump> {
         let fd = new_fd_slot(bump);
 
         // Open an fs context for an overlay file system.
@@ -767,7 +1873,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 fd,
                 command: FsconfigCommand::SET_STRING,
                 key: Some(c"lowerdir"),
-                value: Some(&new_root_path.to_bytes_with_nul()[0]),
+                value: Some(&lower.to_bytes_with_nul()[0]),
                 aux: None,
             },
             &|err| syserr(anyhow!("fsconfig of lowerdir for overlayfs: {err}")),
@@ -818,19 +1924,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
             &|err| syserr(anyhow!("fsmount for overlayfs: {err}")),
         );
 
-        // Attach the mount to the file tree.
-        builder.push(
-            Syscall::MoveMount {
-                from_dirfd: fd,
-                from_path: c"",
-                to_dirfd: Fd::AT_FDCWD,
-                to_path: new_root_path,
-                flags: MoveMountFlags::F_EMPTY_PATH,
-            },
-            &|err| syserr(anyhow!("move_mount for overlayfs: {err}")),
-        );
-
-        Ok(())
+        fd
     }
 
     fn open_mount_fds_for_mounts_pre_pivot_root<'bump>(
@@ -840,6 +1934,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         builder: &mut ScriptBuilder<'bump>,
         mount_fds: &mut BumpVec<'bump, FdSlot<'bump>>,
     ) -> JobResult<(), Error> {
+        let mut layer_index = 0;
         for mount in &spec.mounts {
             fn normal_mount<'a>(
                 bump: &'a Bump,
@@ -847,6 +1942,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 mount_fds: &mut BumpVec<'a, FdSlot<'a>>,
                 cfstype: &'static CStr,
                 fstype: &'static str,
+                options: &[(&'static CStr, &'a CStr)],
             ) -> JobResult<(), Error> {
                 let fd = new_fd_slot(bump);
 
@@ -860,6 +1956,25 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     bump.alloc(move |err| syserr(anyhow!("fsopen for mount of {fstype}: {err}"))),
                 );
 
+                // Set any user-provided configuration parameters. The kernel is the one that
+                // validates these, so failures are reported as execution errors.
+                for &(key, value) in options {
+                    builder.push(
+                        Syscall::Fsconfig {
+                            fd,
+                            command: FsconfigCommand::SET_STRING,
+                            key: Some(key),
+                            value: Some(&value.to_bytes_with_nul()[0]),
+                            aux: None,
+                        },
+                        bump.alloc(move |err| {
+                            execerr(anyhow!(
+                                "fsconfig of {key:?}={value:?} for mount of {fstype}: {err}"
+                            ))
+                        }),
+                    );
+                }
+
                 // Effect the configuration. This preps the file descriptor for the fsmount next.
                 builder.push(
                     Syscall::Fsconfig {
@@ -924,6 +2039,31 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                         }),
                     );
                 }
+                JobMount::Device { path } => {
+                    if let Some(allowed_devices) = &self.allowed_devices {
+                        if !allowed_devices.contains(path) {
+                            return Err(execerr(anyhow!(
+                                "device {path} isn't allowed on this worker"
+                            )));
+                        }
+                    }
+                    let mount_fd = new_fd_slot(bump);
+                    mount_fds.push(mount_fd);
+                    builder.push(
+                        Syscall::OpenTree {
+                            dirfd: Fd::AT_FDCWD,
+                            path: bump_c_str(bump, path.as_str()).map_err(syserr)?,
+                            // Like with the standard devices, we're just cloning a file.
+                            flags: OpenTreeFlags::CLONE,
+                            out: mount_fd,
+                        },
+                        bump.alloc(move |err| {
+                            execerr(anyhow!(
+                                "opening local path for bind mount of device {path}: {err}",
+                            ))
+                        }),
+                    );
+                }
                 JobMount::Devices { devices, .. } => {
                     // Open all of the source paths for devices before we chdir or pivot_root. We
                     // create devices in the container my bind mounting them from the host instead
@@ -955,17 +2095,109 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     // parameters via the new mount API. As a result, we mount it using the
                     // old-style mount syscall later.
                 }
+                JobMount::Layer {
+                    mount_point,
+                    read_only,
+                    ..
+                } => {
+                    // Mount the layer's file system in our scratch tmpfs. If it's a FUSE file
+                    // system, the parent will start serving it when we send it the FUSE file
+                    // descriptor. We then either
+                    // clone it directly, or put an overlay file system on top of it so that it's
+                    // writable, and later move that mount into place in the container.
+                    let index = layer_index;
+                    layer_index += 1;
+                    let lower = self.layer_mount_dir(bump, index, "lower")?;
+                    builder.push(
+                        Syscall::Mkdir {
+                            path: lower,
+                            mode: FileMode::RWXU,
+                        },
+                        &|err| syserr(anyhow!("making lowerdir for layer mount: {err}")),
+                    );
+                    self.set_up_layer_stack_mount(spec, 1 + index, lower, bump, builder)?;
+                    let mount_fd = if *read_only {
+                        let mount_fd = new_fd_slot(bump);
+                        builder.push(
+                            Syscall::OpenTree {
+                                dirfd: Fd::AT_FDCWD,
+                                path: lower,
+                                flags: OpenTreeFlags::CLONE | OpenTreeFlags::RECURSIVE,
+                                out: mount_fd,
+                            },
+                            bump.alloc(move |err| {
+                                syserr(anyhow!(
+                                    "opening layer for layer mount at {mount_point}: {err}"
+                                ))
+                            }),
+                        );
+                        mount_fd
+                    } else {
+                        let upper = self.layer_mount_dir(bump, index, "upper")?;
+                        let work = self.layer_mount_dir(bump, index, "work")?;
+                        builder.push(
+                            Syscall::Mkdir {
+                                path: upper,
+                                mode: FileMode::RWXU,
+                            },
+                            &|err| syserr(anyhow!("making upperdir for layer mount: {err}")),
+                        );
+                        builder.push(
+                            Syscall::Mkdir {
+                                path: work,
+                                mode: FileMode::RWXU,
+                            },
+                            &|err| syserr(anyhow!("making workdir for layer mount: {err}")),
+                        );
+                        self.open_overlay_mount_fd(lower, upper, work, bump, builder)
+                    };
+                    mount_fds.push(mount_fd);
+                }
                 JobMount::Mqueue { .. } => {
-                    normal_mount(bump, builder, mount_fds, c"mqueue", "mqueue")?;
+                    normal_mount(bump, builder, mount_fds, c"mqueue", "mqueue", &[])?;
                 }
                 JobMount::Proc { .. } => {
-                    normal_mount(bump, builder, mount_fds, c"proc", "proc")?;
+                    normal_mount(bump, builder, mount_fds, c"proc", "proc", &[])?;
                 }
                 JobMount::Sys { .. } => {
-                    normal_mount(bump, builder, mount_fds, c"sysfs", "sysfs")?;
+                    normal_mount(bump, builder, mount_fds, c"sysfs", "sysfs", &[])?;
+                }
+                JobMount::Tmp {
+                    size,
+                    mode,
+                    uid,
+                    gid,
+                    nr_inodes,
+                    ..
+                } => {
+                    let mut options = BumpVec::new_in(bump);
+                    let mut option = |key, value: String| -> JobResult<(), Error> {
+                        options.push((key, bump_c_str(bump, &value).map_err(syserr)?));
+                        Ok(())
+                    };
+                    if let Some(size) = size {
+                        option(c"size", size.to_string())?;
+                    }
+                    if let Some(mode) = mode {
+                        option(c"mode", format!("{mode:o}"))?;
+                    }
+                    if let Some(uid) = uid {
+                        option(c"uid", uid.to_string())?;
+                    }
+                    if let Some(gid) = gid {
+                        option(c"gid", gid.to_string())?;
+                    }
+                    if let Some(nr_inodes) = nr_inodes {
+                        option(c"nr_inodes", nr_inodes.to_string())?;
+                    }
+                    normal_mount(bump, builder, mount_fds, c"tmpfs", "tmpfs", &options)?;
                 }
-                JobMount::Tmp { .. } => {
-                    normal_mount(bump, builder, mount_fds, c"tmpfs", "tmpfs")?;
+                JobMount::Volume { name, .. } => {
+                    // The dispatcher turns volume mounts into bind mounts before handing jobs to
+                    // us.
+                    return Err(syserr(anyhow!(
+                        "volume {name} was not resolved to a local path"
+                    )));
                 }
             }
         }
@@ -1050,6 +2282,21 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                         );
                     }
                 }
+                JobMount::Device { path } => {
+                    let mount_local_path_fd = mount_fds.next().unwrap();
+                    builder.push(
+                        Syscall::MoveMount {
+                            from_dirfd: mount_local_path_fd,
+                            from_path: c"",
+                            to_dirfd: Fd::AT_FDCWD,
+                            to_path: bump_c_str(bump, path.as_str()).map_err(syserr)?,
+                            flags: MoveMountFlags::F_EMPTY_PATH,
+                        },
+                        bump.alloc(move |err| {
+                            execerr(anyhow!("move_mount for bind mount of device {path}: {err}"))
+                        }),
+                    );
+                }
                 JobMount::Devices { devices } => {
                     for device in devices.iter() {
                         let Device { cstr, str } = Device::new(device);
@@ -1086,6 +2333,22 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                         }),
                     )
                 }
+                JobMount::Layer { mount_point, .. } => {
+                    builder.push(
+                        Syscall::MoveMount {
+                            from_dirfd: mount_fds.next().unwrap(),
+                            from_path: c"",
+                            to_dirfd: Fd::AT_FDCWD,
+                            to_path: bump_c_str(bump, mount_point.as_str()).map_err(syserr)?,
+                            flags: MoveMountFlags::F_EMPTY_PATH,
+                        },
+                        bump.alloc(move |err| {
+                            execerr(anyhow!(
+                                "move_mount for layer mount to {mount_point}: {err}"
+                            ))
+                        }),
+                    );
+                }
                 JobMount::Mqueue { mount_point } => normal_mount(
                     bump,
                     builder,
@@ -1107,13 +2370,16 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     mount_fds.next().unwrap(),
                     mount_point,
                 )?,
-                JobMount::Tmp { mount_point } => normal_mount(
+                JobMount::Tmp { mount_point, .. } => normal_mount(
                     bump,
                     builder,
                     "tmpfs",
                     mount_fds.next().unwrap(),
                     mount_point,
                 )?,
+                JobMount::Volume { .. } => {
+                    unreachable!("volume mounts are rejected before pivot_root")
+                }
             };
         }
         Ok(())
@@ -1172,6 +2438,58 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         Ok(())
     }
 
+    fn set_up_capabilities<'bump>(
+        &'bump self,
+        spec: &'bump JobSpec,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) {
+        // Unless asked to, we leave the capabilities alone. The kernel will give root all of the
+        // capabilities on exec, and every other user none of them.
+        if spec.capabilities.is_empty() {
+            return;
+        }
+        let JobCapabilities { add, drop } = spec.capabilities;
+        let root = u32::from(spec.user.unwrap_or(UserId::new(0))) == 0;
+        let retained = if root { EnumSet::all() } else { add }.difference(drop);
+
+        // Remove the dropped capabilities from the bounding set. Otherwise, root would get them
+        // back when it execs.
+        for (cap, name) in drop.into_iter().map(capability) {
+            builder.push(
+                Syscall::CapBoundingDrop { capability: cap },
+                bump.alloc(move |err| {
+                    syserr(anyhow!(
+                        "dropping capability {name} from bounding set: {err}"
+                    ))
+                }),
+            );
+        }
+
+        let mut inheritable = CapabilitySet::empty();
+        for (cap, _) in retained.into_iter().map(capability) {
+            inheritable.insert(cap);
+        }
+        builder.push(
+            Syscall::SetInheritableCapabilities {
+                capabilities: inheritable,
+            },
+            &|err| syserr(anyhow!("setting inheritable capabilities: {err}")),
+        );
+
+        // A non-root user only keeps capabilities across exec if they're in the ambient set.
+        if !root {
+            for (cap, name) in retained.into_iter().map(capability) {
+                builder.push(
+                    Syscall::CapAmbientRaise { capability: cap },
+                    bump.alloc(move |err| {
+                        execerr(anyhow!("raising ambient capability {name}: {err}"))
+                    }),
+                );
+            }
+        }
+    }
+
     fn do_close_range<'bump>(&'bump self, builder: &mut ScriptBuilder<'bump>) {
         // Set close-on-exec for all file descriptors except stdin, stdout, and stderr. We do this
         // last thing, right before the exec, so that we catch any file descriptors opened above.
@@ -1189,71 +2507,425 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         );
     }
 
-    fn do_exec<'bump>(
+    /// Mount the file systems for each sidecar's layers in our scratch tmpfs, and stack them
+    /// with a writable overlay file system. The detached overlay mount for each sidecar becomes its
+    /// root. This has to happen before the `pivot_root`, since the scratch tmpfs is outside of the
+    /// container's root.
+    fn open_sidecar_root_fds_pre_pivot_root<'bump>(
         &'bump self,
         spec: &'bump JobSpec,
         bump: &'bump Bump,
         builder: &mut ScriptBuilder<'bump>,
-    ) -> JobResult<(), Error> {
-        let program = bump_c_str(bump, spec.program.as_str()).map_err(syserr)?;
-        let mut arguments =
-            BumpVec::with_capacity_in(spec.arguments.len().checked_add(2).unwrap(), bump);
-        arguments.push(Some(&program.to_bytes_with_nul()[0]));
-        for argument in &spec.arguments {
-            let argument_cstr = bump_c_str(bump, argument.as_str()).map_err(syserr)?;
-            arguments.push(Some(&argument_cstr.to_bytes_with_nul()[0]));
-        }
-        arguments.push(None);
-        let mut environment =
-            BumpVec::with_capacity_in(spec.environment.len().checked_add(1).unwrap(), bump);
-        for var in &spec.environment {
-            let var_cstr = bump_c_str(bump, var.as_str()).map_err(syserr)?;
-            environment.push(Some(&var_cstr.to_bytes_with_nul()[0]));
+    ) -> JobResult<BumpVec<'bump, FdSlot<'bump>>, Error> {
+        let mut root_fds = BumpVec::new_in(bump);
+        let mut stack_index = 1 + spec.num_layer_mounts();
+        for (index, sidecar) in spec.sidecars.iter().enumerate() {
+            let mut lowers = BumpVec::new_in(bump);
+            for layer_index in 0..sidecar.layers.len() {
+                let lower = self.sidecar_dir(bump, index, format_args!("lower-{layer_index}"))?;
+                builder.push(
+                    Syscall::Mkdir {
+                        path: lower,
+                        mode: FileMode::RWXU,
+                    },
+                    &|err| syserr(anyhow!("making lowerdir for sidecar: {err}")),
+                );
+                self.set_up_layer_stack_mount(spec, stack_index, lower, bump, builder)?;
+                stack_index += 1;
+                lowers.push(lower);
+            }
+
+            // Overlay file systems want their lower directories listed from the top down.
+            let mut lowerdir = BumpVec::new_in(bump);
+            for lower in lowers.iter().rev() {
+                if !lowerdir.is_empty() {
+                    lowerdir.push(b':');
+                }
+                lowerdir.extend_from_slice(lower.to_bytes());
+            }
+            let lowerdir = bump_c_str_from_bytes(bump, &lowerdir).map_err(syserr)?;
+
+            let upper = self.sidecar_dir(bump, index, "upper")?;
+            let work = self.sidecar_dir(bump, index, "work")?;
+            builder.push(
+                Syscall::Mkdir {
+                    path: upper,
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making upperdir for sidecar: {err}")),
+            );
+            builder.push(
+                Syscall::Mkdir {
+                    path: work,
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making workdir for sidecar: {err}")),
+            );
+            root_fds.push(self.open_overlay_mount_fd(lowerdir, upper, work, bump, builder));
         }
-        environment.push(None);
-        builder.push(
-            match (
-                spec.environment.iter().find(|var| var.starts_with("PATH=")),
-                spec.program.as_str(),
-            ) {
-                (Some(path), program_str) if !program_str.contains('/') => Syscall::ExecveList {
-                    paths: path
-                        .strip_prefix("PATH=")
-                        .unwrap()
-                        .split(':')
-                        .filter(|dir| !dir.is_empty())
-                        .map(|dir| {
-                            let mut bump_string = BumpString::from_str_in(dir, bump);
-                            bump_string.push('/');
-                            bump_string.push_str(program_str);
-                            bump_c_str(bump, bump_string.as_str())
-                        })
-                        .collect_in::<result::Result<BumpVec<_>, _>>(bump)
-                        .map_err(syserr)?
-                        .into_bump_slice(),
-                    fallback: program,
-                    argv: arguments.into_bump_slice(),
-                    envp: environment.into_bump_slice(),
+        Ok(root_fds)
+    }
+
+    /// Start each sidecar in its own mount namespace, and have it attach its root in our scratch
+    /// tmpfs and then `pivot_root` into it, just like we do below. This has to happen before our
+    /// `pivot_root`, since the scratch tmpfs is outside of the container's root. The sidecars are
+    /// children of the job's main process, which is the init process of the PID namespace, so the
+    /// kernel kills them when the main process exits.
+    ///
+    /// Sidecars get the same capabilities as the job. Returns each sidecar's pidfd.
+    fn start_sidecars_pre_pivot_root<'bump>(
+        &'bump self,
+        spec: &'bump JobSpec,
+        root_fds: BumpVec<'bump, FdSlot<'bump>>,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<BumpVec<'bump, FdSlot<'bump>>, Error> {
+        let mut pidfds = BumpVec::new_in(bump);
+        for (index, (sidecar, root_fd)) in spec.sidecars.iter().zip(root_fds).enumerate() {
+            let program = &sidecar.program;
+            let root = self.sidecar_dir(bump, index, "root")?;
+            builder.push(
+                Syscall::Mkdir {
+                    path: root,
+                    mode: FileMode::RWXU,
+                },
+                &|err| syserr(anyhow!("making root for sidecar: {err}")),
+            );
+
+            let mut script = ScriptBuilder::new(bump);
+
+            // Send the sidecar's output to the job's stderr so that it doesn't get mixed in with
+            // the job's stdout.
+            script.push(
+                Syscall::Dup2 {
+                    from: Fd::STDERR,
+                    to: Fd::STDOUT,
+                },
+                &|err| syserr(anyhow!("dup2-ing sidecar's stdout: {err}")),
+            );
+            script.push(
+                Syscall::MoveMount {
+                    from_dirfd: root_fd,
+                    from_path: c"",
+                    to_dirfd: Fd::AT_FDCWD,
+                    to_path: root,
+                    flags: MoveMountFlags::F_EMPTY_PATH,
                 },
-                _ => Syscall::Execve {
-                    path: program,
-                    argv: arguments.into_bump_slice(),
-                    envp: environment.into_bump_slice(),
+                &|err| syserr(anyhow!("move_mount for sidecar's root: {err}")),
+            );
+            self.do_pivot_root(root, &mut script);
+            if let Some(working_directory) = &sidecar.working_directory {
+                script.push(
+                    Syscall::Chdir {
+                        path: bump_c_str(bump, working_directory.as_str()).map_err(syserr)?,
+                    },
+                    bump.alloc(move |err| {
+                        execerr(anyhow!(
+                            "chdir to sidecar {program}'s working directory: {err}"
+                        ))
+                    }),
+                );
+            }
+            self.set_up_capabilities(spec, bump, &mut script);
+            self.do_close_range(&mut script);
+            script.push(
+                exec_syscall(
+                    bump,
+                    program,
+                    &sidecar.arguments,
+                    &sidecar.environment,
+                    &[],
+                    None,
+                )?,
+                bump.alloc(move |err| execerr(anyhow!("execvc of sidecar {program}: {err}"))),
+            );
+
+            let pidfd = new_fd_slot(bump);
+            builder.push_spawn(
+                CloneFlags::NEWNS,
+                script,
+                pidfd,
+                bump.alloc(move |err| syserr(anyhow!("starting sidecar {program}: {err}"))),
+            );
+            pidfds.push(pidfd);
+        }
+        Ok(pidfds)
+    }
+
+    /// Wait for the sidecars with a ready port to accept connections.
+    fn wait_for_sidecars<'bump>(
+        &'bump self,
+        spec: &'bump JobSpec,
+        pidfds: BumpVec<'bump, FdSlot<'bump>>,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) {
+        for (sidecar, pidfd) in spec.sidecars.iter().zip(pidfds) {
+            let Some(port) = sidecar.ready_port else {
+                continue;
+            };
+            let addr = bump.alloc(SockaddrIn::loopback(port));
+            let program = &sidecar.program;
+            builder.push(
+                Syscall::WaitForListener {
+                    addr,
+                    pidfd,
+                    timeout: sidecar
+                        .ready_timeout
+                        .unwrap_or(JobSidecar::DEFAULT_READY_TIMEOUT)
+                        .into(),
                 },
+                bump.alloc(move |err| {
+                    execerr(anyhow!(
+                        "sidecar {program} exited or timed out before listening on port {port}: {err}"
+                    ))
+                }),
+            );
+        }
+    }
+
+    /// Returns the read and write sides of the pipe the init process uses to tell us which signal
+    /// killed the job, if there is an init process.
+    fn set_up_init<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<Option<(OwnedFd, OwnedFd)>, Error> {
+        if !spec.init {
+            return Ok(None);
+        }
+        let (signal_read, signal_write) = linux::pipe().map_err(syserr)?;
+        linux::fcntl_setfl(&signal_read, OpenFlags::NONBLOCK).map_err(syserr)?;
+        // Fork, leaving the current process, which is PID 1 of the PID namespace, behind as an
+        // init process. The child goes on to exec the job's program.
+        builder.push(
+            Syscall::Init {
+                tty: spec.allocate_tty.is_some(),
+                signal_fd: signal_write.as_fd(),
             },
+            &|err| syserr(anyhow!("starting init process: {err}")),
+        );
+        Ok(Some((signal_read, signal_write)))
+    }
+
+    fn do_exec<'bump>(
+        &'bump self,
+        spec: &'bump JobSpec,
+        secrets: &'bump [JobSecret],
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        builder.push(
+            exec_syscall(
+                bump,
+                &spec.program,
+                &spec.arguments,
+                &spec.environment,
+                secrets,
+                spec.home(),
+            )?,
             &|err| execerr(anyhow!("execvc: {err}")),
         );
         Ok(())
     }
 
+    /// Fetch the values of the job's secrets from the client, and pair them up with their
+    /// destinations. The client sends them as soon as we connect.
+    fn fetch_secrets(&self, spec: &JobSpec) -> JobResult<Vec<JobSecret>, Error> {
+        let Some(JobSecrets {
+            address,
+            destinations,
+        }) = &spec.secrets
+        else {
+            return Ok(vec![]);
+        };
+        // The client is waiting for us by the time the job starts, so this only guards against it
+        // going away without closing its end.
+        const SECRETS_TIMEOUT: Duration = Duration::from_secs(30);
+        let log = Logger::root(Discard, o!());
+        let values = match *address {
+            JobSecretsAddress::Local(socket_address) => {
+                let socket =
+                    linux::socket(SocketDomain::UNIX, SocketType::STREAM, Default::default())
+                        .map_err(syserr)?;
+                let sockaddr = SockaddrUnStorage::new(socket_address.as_slice()).map_err(syserr)?;
+                linux::connect(&socket, &sockaddr)
+                    .map_err(|err| execerr(anyhow!("connecting to client for secrets: {err}")))?;
+                let mut socket = std::os::unix::net::UnixStream::from(socket);
+                socket
+                    .set_read_timeout(Some(SECRETS_TIMEOUT))
+                    .map_err(syserr)?;
+                net::read_message_from_socket::<Vec<JobSecretValue>>(&mut socket, &log)
+            }
+            JobSecretsAddress::Relay(key) => {
+                let broker = self.broker.ok_or_else(|| {
+                    execerr(anyhow!("secrets relay requested but there is no broker"))
+                })?;
+                let mut socket = std::net::TcpStream::connect(broker.inner()).map_err(syserr)?;
+                net::write_message_to_socket(
+                    &mut socket,
+                    Hello::Relay {
+                        key,
+                        side: RelaySide::Job,
+                    },
+                    &log,
+                )
+                .map_err(syserr)?;
+                socket
+                    .set_read_timeout(Some(SECRETS_TIMEOUT))
+                    .map_err(syserr)?;
+                net::read_message_from_socket::<Vec<JobSecretValue>>(&mut socket, &log)
+            }
+        }
+        .map_err(|err| execerr(anyhow!("fetching secrets from client: {err}")))?;
+        if values.len() != destinations.len() {
+            return Err(execerr(anyhow!(
+                "client sent {} secret values for {} secrets",
+                values.len(),
+                destinations.len()
+            )));
+        }
+        Ok(destinations
+            .iter()
+            .cloned()
+            .zip(values)
+            .map(|(destination, value)| JobSecret { destination, value })
+            .collect())
+    }
+
+    /// Compute the gid mapping for a child that has supplementary groups. The primary group is
+    /// mapped to our group, like in the normal case, and the supplementary groups are mapped into
+    /// our range of subordinate gids. They're never mapped to the same gids outside of the
+    /// container, since that would give the job the privileges of those groups on the host.
+    fn supplementary_gid_map(&self, spec: &JobSpec) -> JobResult<Option<String>, Error> {
+        let groups: Vec<_> = spec.supplementary_groups().collect();
+        if groups.is_empty() {
+            return Ok(None);
+        }
+        let Some(subordinate_gids) = &self.subordinate_gids else {
+            return Err(execerr(anyhow!(
+                "supplementary groups require the worker's user to have subordinate gids in \
+                /etc/subgid"
+            )));
+        };
+        subordinate_gids
+            .gid_map(spec.group.unwrap_or(0.into()), self.group, &groups)
+            .map(Some)
+            .map_err(execerr)
+    }
+
+    /// Clone a child and have it set up everything that a job with the key `key` needs before it
+    /// knows anything else about the job. Return once the child is waiting to be woken up.
+    fn start_warm_sandbox(
+        &self,
+        spec: &JobSpec,
+        key: &SandboxKey,
+        fuse_spawn: impl FnOnce(OwnedFd),
+    ) -> JobResult<WarmSandbox, Error> {
+        let (read_sock, write_sock) = linux::UnixStream::pair().map_err(syserr)?;
+
+        // This is the same as the beginning of the script built by run_job_inner, with the parts
+        // that depend on the rest of the job left out.
+        let bump = Bump::new();
+        let mut builder = ScriptBuilder::new(&bump);
+        self.set_up_user_namespace(spec, write_sock.as_fd(), &bump, &mut builder)?;
+        let new_root_path = self.mount_dir.as_c_str();
+        self.set_up_layer_stack_mount(spec, 0, new_root_path, &bump, &mut builder)?;
+        self.set_up_tmpfs(spec, &[], false, &bump, &mut builder);
+        self.set_up_root_overlay(spec, new_root_path, &bump, &mut builder)?;
+
+        let mut clone_flags = CloneFlags::CLEAR_SIGHAND
+            | CloneFlags::NEWCGROUP
+            | CloneFlags::NEWIPC
+            | CloneFlags::NEWNS
+            | CloneFlags::NEWPID
+            | CloneFlags::NEWUSER
+            | CloneFlags::NEWUTS
+            | CloneFlags::VM;
+        if key.new_network_namespace {
+            clone_flags |= CloneFlags::NEWNET;
+        }
+        let continuation = NonNull::from(&*bump.alloc(ScriptSlot::default()));
+        let args = bump.alloc(WarmChildArgs {
+            write_sock: write_sock.as_fd(),
+            syscalls: builder.syscalls.as_mut_slice(),
+            continuation: unsafe { continuation.as_ref() },
+        });
+        let child_process = ChildProcess::new(
+            &bump,
+            clone_flags,
+            maelstrom_worker_child::start_warm_and_exec_in_child_trampoline,
+            args,
+        )
+        .map_err(syserr)?;
+        drop(write_sock);
+
+        // Wait for the child to tell us it's ready, passing along the FUSE file descriptor on the
+        // way. If we return early, dropping child_process will kill the child.
+        let mut fuse_spawn = Some(fuse_spawn);
+        let mut buf = [0; mem::size_of::<u64>()];
+        loop {
+            match read_sock.recv_with_fd(&mut buf).map_err(syserr)? {
+                (_, Some(fd)) => {
+                    let fuse_spawn = fuse_spawn
+                        .take()
+                        .ok_or(syserr(anyhow!("too many FUSE fds")))?;
+                    fuse_spawn(fd);
+                }
+                (0, None) => {
+                    return Err(syserr(anyhow!("warm sandbox exited before it was ready")));
+                }
+                (1, None) => break,
+                (count, None) if count == buf.len() => {
+                    let result = u64::from_ne_bytes(buf);
+                    let index = (result >> 32) as usize;
+                    let errno = result & 0xffffffff;
+                    return Err(builder.error_transformers[index](
+                        Errno::from_u64(errno).desc().unwrap_or("Unknown error"),
+                    ));
+                }
+                (count, None) => {
+                    return Err(syserr(anyhow!(
+                        "couldn't parse warm sandbox socket's contents: {:?}",
+                        &buf[..count]
+                    )));
+                }
+            }
+        }
+
+        // The child is now blocked waiting for us, and won't touch anything in bump other than its
+        // stack and the continuation slot until we wake it up. Moving bump doesn't move the memory
+        // it has handed out.
+        let child_pidfd = child_process.into_child_pidfd();
+        drop(builder);
+        Ok(WarmSandbox {
+            child_pidfd: Some(child_pidfd),
+            read_sock,
+            continuation,
+            _bump: bump,
+        })
+    }
+
     fn run_job_inner(
         &self,
         spec: &JobSpec,
+        warm_sandbox: Option<WarmSandbox>,
         inline_limit: InlineLimit,
         kill_event_receiver: EventReceiver,
-        fuse_spawn: impl FnOnce(OwnedFd),
+        mut fuse_spawn: impl FnMut(OwnedFd),
         runtime: runtime::Handle,
     ) -> JobResult<JobCompleted, Error> {
+        // Sidecars are only useful if the job can talk to them.
+        if !spec.sidecars.is_empty() && spec.network == JobNetwork::Disabled {
+            return Err(execerr(anyhow!(
+                "a job with sidecars can't have a network of disabled"
+            )));
+        }
+
+        // Get the secrets' values from the client before doing anything else. They're never part
+        // of the spec.
+        let secrets = self.fetch_secrets(spec)?;
+
         // We're going to need three channels between the parent and child: one for stdout, one for
         // stderr, and one to tranfer back the fuse file descriptor from the child and to convey
         // back any error that occurs in the child before it execs. The first two can be regular
@@ -1275,18 +2947,42 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 }
             }
             Some(JobTty {
-                socket_address,
+                address,
                 window_size,
             }) => {
                 // Open and connect the socket.
-                let socket = linux::socket(
-                    SocketDomain::UNIX,
-                    SocketType::STREAM | SocketType::NONBLOCK,
-                    Default::default(),
-                )
-                .map_err(syserr)?;
-                let sockaddr = SockaddrUnStorage::new(socket_address.as_slice()).map_err(syserr)?;
-                linux::connect(&socket, &sockaddr).map_err(syserr)?;
+                let socket = match address {
+                    JobTtyAddress::Local(socket_address) => {
+                        let socket = linux::socket(
+                            SocketDomain::UNIX,
+                            SocketType::STREAM | SocketType::NONBLOCK,
+                            Default::default(),
+                        )
+                        .map_err(syserr)?;
+                        let sockaddr =
+                            SockaddrUnStorage::new(socket_address.as_slice()).map_err(syserr)?;
+                        linux::connect(&socket, &sockaddr).map_err(syserr)?;
+                        TtySocket::Unix(socket)
+                    }
+                    JobTtyAddress::Relay(key) => {
+                        let broker = self.broker.ok_or_else(|| {
+                            execerr(anyhow!("TTY relay requested but there is no broker"))
+                        })?;
+                        let mut socket =
+                            std::net::TcpStream::connect(broker.inner()).map_err(syserr)?;
+                        net::write_message_to_socket(
+                            &mut socket,
+                            Hello::Relay {
+                                key,
+                                side: RelaySide::Job,
+                            },
+                            &Logger::root(Discard, o!()),
+                        )
+                        .map_err(syserr)?;
+                        socket.set_nonblocking(true).map_err(syserr)?;
+                        TtySocket::Tcp(socket)
+                    }
+                };
 
                 // Open pseudoterminal device
                 let (master, slave) =
@@ -1300,7 +2996,13 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
             }
         };
 
-        let (read_sock, write_sock) = linux::UnixStream::pair().map_err(syserr)?;
+        // A warm sandbox already has its exec result socket.
+        let warm = warm_sandbox.is_some();
+        let exec_result_sock = if warm {
+            None
+        } else {
+            Some(linux::UnixStream::pair().map_err(syserr)?)
+        };
 
         // At a high level, the approach we're going to take is to build a "script" here in the
         // parent, and then pass the script to the child for execution. The script will consist of
@@ -1344,23 +3046,42 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
 
         // Set up stdio to either be pipes or a PTY. This has to happen after setting up the
         // session if we're allocating a PTY.
-        self.set_up_stdio(&stdio, &mut builder);
+        self.set_up_stdio(&stdio, warm, &mut builder);
 
         // Set up the network namespace, returning true iff we should actually create a new network
         // namespace. If `newnet` is false, we should share the parent's network namespace.
         let newnet = self.set_up_network(spec, &bump, &mut builder);
-        self.set_up_user_namespace(spec, &bump, &mut builder)?;
 
-        // Set up the fuse mount and send back the open fuse fd.
+        // A warm sandbox has already set up its user namespace and its root FUSE mount.
+        if let Some((_, write_sock)) = &exec_result_sock {
+            self.set_up_user_namespace(spec, write_sock.as_fd(), &bump, &mut builder)?;
+        }
+
+        // Set the hostname. This has to happen after we've set up our user namespace.
+        self.set_up_hostname(spec, &mut builder);
+
+        // Mount the root's layer stack. For FUSE, this sends back the open fuse fd.
         let new_root_path = self.mount_dir.as_c_str();
-        self.set_up_fuse_root(spec, new_root_path, &bump, &mut builder);
+        if !warm {
+            self.set_up_layer_stack_mount(spec, 0, new_root_path, &bump, &mut builder)?;
+        }
+
+        // Mount our scratch tmpfs if we need it for the root overlay or synthesized files.
+        let files = self.synthesize_files(spec, &secrets, &bump)?;
+        self.set_up_tmpfs(spec, &files, warm, &bump, &mut builder);
 
         // We need to resolve any local paths before we pivot_root, and we want to do the
         // move_mount before we complete the move_mounts below. We could split this up into two
         // functions like we do before, but there's no need to do so, since we don't need to
         // evaluate a client-provided mount point path. So, we do the whole thing, move_mount
-        // included, here before the pivot_root.
-        self.set_up_root_overlay(spec, new_root_path, &bump, &mut builder)?;
+        // included, here before the pivot_root. A warm sandbox has already done this.
+        if !warm {
+            self.set_up_root_overlay(spec, new_root_path, &bump, &mut builder)?;
+        }
+
+        // The home directory has to be made in the new root, before we mount anything on top of
+        // it.
+        self.set_up_home_pre_pivot_root(spec, new_root_path, &bump, &mut builder)?;
 
         // Prepare all of the mounts before we pivot_root. This way we can evaluate local
         // paths for bind  mounts before we go into the container's root. Also, Linux won't let us
@@ -1369,6 +3090,23 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         let mut mount_fds = BumpVec::new_in(&bump);
         self.open_mount_fds_for_mounts_pre_pivot_root(spec, &bump, &mut builder, &mut mount_fds)?;
 
+        // Mount the sidecars' layers and stack them into a root for each sidecar. Their FUSE file
+        // systems have to come after the ones for layer mounts, since that's the order the parent
+        // serves them in.
+        let sidecar_root_fds =
+            self.open_sidecar_root_fds_pre_pivot_root(spec, &bump, &mut builder)?;
+        self.open_mount_fds_for_synthesized_files_pre_pivot_root(
+            &files,
+            &bump,
+            &mut builder,
+            &mut mount_fds,
+        );
+
+        // Start the sidecars. Each does its own pivot_root into its own root, so they have to be
+        // started while their roots are still reachable.
+        let sidecar_pidfds =
+            self.start_sidecars_pre_pivot_root(spec, sidecar_root_fds, &bump, &mut builder)?;
+
         // Pivot root and unmount the old root. This places us in the new /.
         self.do_pivot_root(new_root_path, &mut builder);
 
@@ -1376,54 +3114,106 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         // the mounting of file systems.
         let mut mount_fds = mount_fds.into_iter();
         self.complete_mounts_post_pivot_root(spec, &bump, &mut builder, &mut mount_fds)?;
+        self.complete_synthesized_files_post_pivot_root(
+            &files,
+            &bump,
+            &mut builder,
+            &mut mount_fds,
+        );
+        self.set_up_home(spec, &mut builder);
 
         // We don't want to chdir until we've completed mounting, since we want clients to be able
         // to specify relative paths, and have them be relative to /.
         self.do_chdir(spec, &bump, &mut builder)?;
 
-        // This needs to happen last, right before the exec, so we don't leak any file descriptors.
+        // Adjust the capabilities the job will have after the exec. This only affects the
+        // inheritable, ambient, and bounding sets, so we can still do everything below.
+        self.set_up_capabilities(spec, &bump, &mut builder);
+
+        // This needs to happen after we've opened all of our file descriptors, so we don't leak
+        // any of them.
         self.do_close_range(&mut builder);
 
+        // Wait for the sidecars to be ready as late as possible, so they have time to start up
+        // while we finish setting up.
+        self.wait_for_sidecars(spec, sidecar_pidfds, &bump, &mut builder);
+
+        // Start the init process, if requested. This comes after starting the sidecars so that
+        // the init process will reap them too.
+        let (init_signal_read, init_signal_write) = self.set_up_init(spec, &mut builder)?.unzip();
+
         // This has to come last.
-        self.do_exec(spec, &bump, &mut builder)?;
+        self.do_exec(spec, &secrets, &bump, &mut builder)?;
 
         // Start timing the job now.
         let start = self.clock.now();
 
-        // We're finally ready to actually clone the child.
-        let mut clone_flags = CloneFlags::CLEAR_SIGHAND
-            | CloneFlags::NEWCGROUP
-            | CloneFlags::NEWIPC
-            | CloneFlags::NEWNS
-            | CloneFlags::NEWPID
-            | CloneFlags::NEWUSER
-            | CloneFlags::VM;
-        if newnet {
-            clone_flags |= CloneFlags::NEWNET;
-        }
-        let mut args = maelstrom_worker_child::ChildArgs {
-            write_sock: write_sock.as_fd(),
-            syscalls: builder.syscalls.as_mut_slice(),
+        let child = match warm_sandbox {
+            Some(sandbox) => {
+                // The sandbox has already been cloned, so we just have to wake it up. Like below,
+                // if we encounter an error after this, dropping the sandbox will kill the child
+                // before we destroy bump.
+                JobChild::Warm(sandbox.wake(builder.syscalls.as_mut_slice(), &stdio)?)
+            }
+            None => {
+                let (read_sock, write_sock) =
+                    exec_result_sock.expect("cold jobs should have an exec result socket");
+                let gid_map = self.supplementary_gid_map(spec)?;
+
+                // We're finally ready to actually clone the child.
+                let mut clone_flags = CloneFlags::CLEAR_SIGHAND
+                    | CloneFlags::NEWCGROUP
+                    | CloneFlags::NEWIPC
+                    | CloneFlags::NEWNS
+                    | CloneFlags::NEWPID
+                    | CloneFlags::NEWUSER
+                    | CloneFlags::NEWUTS;
+                // The init process never execs, so it can't share our address space: if it did, it
+                // would keep using our memory after we return. Without CLONE_VM, the child gets a
+                // copy-on-write copy of our address space instead, just like with fork.
+                if !spec.init {
+                    clone_flags |= CloneFlags::VM;
+                }
+                if newnet {
+                    clone_flags |= CloneFlags::NEWNET;
+                }
+                let args = bump.alloc(maelstrom_worker_child::ChildArgs {
+                    write_sock: write_sock.as_fd(),
+                    syscalls: builder.syscalls.as_mut_slice(),
+                });
+                let child_process = ChildProcess::new(
+                    &bump,
+                    clone_flags,
+                    maelstrom_worker_child::start_and_exec_in_child_trampoline,
+                    args,
+                )
+                .map_err(syserr)?;
+
+                // If the child has supplementary groups, we have to write its gid mapping for it.
+                // The child will wait for us to do so before continuing.
+                if let Some(gid_map) = gid_map {
+                    subgid::write_gid_map(child_process.child_pid, &gid_map)
+                        .map_err(|err| execerr(anyhow!("mapping supplementary groups: {err:#}")))?;
+                    read_sock.send(&[0]).map_err(syserr)?;
+                }
+
+                // It's important to drop our copy of the write side of the socket before reading
+                // from the read side below. Otherwise, we'd deadlock on ourselves!
+                drop(write_sock);
+                JobChild::Cold(child_process, read_sock)
+            }
         };
-        let child_process = ChildProcess::new(
-            &bump,
-            clone_flags,
-            maelstrom_worker_child::start_and_exec_in_child_trampoline,
-            &mut args,
-        )
-        .map_err(syserr)?;
+        drop(init_signal_write);
 
         // Read (in a blocking manner) from the exec result socket. The child will write to the
         // socket if it has an error exec-ing. The child will mark the write side of the socket
         // exec-on-close, so we'll read an immediate EOF if the exec is successful.
         //
-        // It's important to drop our copy of the write side of the socket first. Otherwise, we'd
-        // deadlock on ourselves!
-        //
-        // If we encounter an error here, we will run Drop on the ChildProcess. This will guarantee
+        // If we encounter an error here, we will run Drop on the JobChild. This will guarantee
         // that our child is dead before we return from this function and destroy bump.
-        drop(write_sock);
-        let mut fuse_spawn = Some(fuse_spawn);
+        let read_sock = child.read_sock();
+        let mut fuse_fds_remaining =
+            usize::from(!warm) + spec.num_layer_mounts() + spec.num_sidecar_layers();
         let mut exec_result_buf = [0; mem::size_of::<u64>()];
         loop {
             let (count, fd) = read_sock
@@ -1444,9 +3234,9 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
 
             // If we get a file descriptor, we pass it to the FUSE callback.
             if let Some(fd) = fd {
-                let fuse_spawn = fuse_spawn
-                    .take()
-                    .ok_or(syserr(anyhow!("multiple FUSE fds")))?;
+                fuse_fds_remaining = fuse_fds_remaining
+                    .checked_sub(1)
+                    .ok_or(syserr(anyhow!("too many FUSE fds")))?;
                 fuse_spawn(fd);
                 continue;
             }
@@ -1460,20 +3250,21 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
             ));
         }
 
-        // At this point it's safe to destructure the ChildProcess, since we know it has exec-ed,
-        // and therefore isn't sharing our virtual memory anymore (so we can safely return without
-        // waiting for the process).
+        // At this point it's safe to destructure the JobChild, since we know it has exec-ed (or
+        // is an init process with its own copy of our address space), and therefore isn't sharing
+        // our virtual memory anymore (so we can safely return without waiting for the process).
         //
         // However, we want to make sure that we always wait on the child somehow, even if there is
         // an error, so that we don't end up accumlating zombie children. That's why we don't put
         // the following task into the JoinSet: we want it to run eve if we ignore its results.
-        let child_pidfd = child_process.into_child_pidfd();
+        let child_pidfd = child.into_child_pidfd();
         let (status_sender, status_receiver) = oneshot::channel();
         runtime.spawn(async move {
             // It's not clear what to do if we get an error waiting, which, in theory, should never
             // happen. What we do is return the error so that the client can get back a system
             // error. An alternative would be to panic and send a plain JobTerminationStatus back.
-            let _ = status_sender.send(wait_for_child(child_pidfd, kill_event_receiver).await);
+            let _ = status_sender
+                .send(wait_for_child(child_pidfd, init_signal_read, kill_event_receiver).await);
         });
 
         let (stdout_sender, stdout_receiver) = oneshot::channel();
@@ -1522,8 +3313,21 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 let master_fd = master.as_fd();
                 let (mut master_read, mut master_write) =
                     AsyncFile::new(master).map_err(syserr)?.into_split();
-                let (mut socket_read, mut socket_write) =
-                    UnixStream::try_from(socket).map_err(syserr)?.into_split();
+                let (mut socket_read, mut socket_write): (
+                    Box<dyn AsyncRead + Send + Unpin>,
+                    Box<dyn AsyncWrite + Send + Unpin>,
+                ) = match socket {
+                    TtySocket::Unix(socket) => {
+                        let (read, write) =
+                            UnixStream::try_from(socket).map_err(syserr)?.into_split();
+                        (Box::new(read), Box::new(write))
+                    }
+                    TtySocket::Tcp(socket) => {
+                        let (read, write) =
+                            TcpStream::from_std(socket).map_err(syserr)?.into_split();
+                        (Box::new(read), Box::new(write))
+                    }
+                };
 
                 // Spawn two tasks to proxy between the master and the socket.
                 let master_to_socket_handle = joinset.spawn_on(
@@ -1622,13 +3426,22 @@ mod tests {
     use bytesize::ByteSize;
     use indoc::indoc;
     use maelstrom_base::{
-        enum_set, nonempty, ArtifactType, EnumSet, JobTerminationStatus, Utf8Path, WindowSize,
+        enum_set,
+        manifest::{Mode, Owner},
+        nonempty, ArtifactType, EnumSet, JobTerminationStatus, Timeout, Utf8Path, WindowSize,
     };
-    use maelstrom_layer_fs::{BlobDir, BottomLayerBuilder, LayerFs, ReaderCache};
+    use maelstrom_layer_fs::{BlobDir, BottomLayerBuilder, FileAttributes, LayerFs, ReaderCache};
     use maelstrom_test::{boxed_u8, digest, utf8_path_buf};
     use maelstrom_util::{async_fs, log::test_logger, sync, time::TickingClock};
     use std::{
-        ascii, collections::HashSet, env, fs, path::PathBuf, str, sync::Arc, time::Duration,
+        ascii,
+        collections::HashSet,
+        env, fs,
+        net::{Ipv4Addr, Ipv6Addr},
+        path::PathBuf,
+        str,
+        sync::Arc,
+        time::{Duration, Instant},
     };
     use tempfile::{NamedTempFile, TempDir};
     use tokio::{
@@ -1650,7 +3463,9 @@ mod tests {
     }
 
     impl TarMount {
-        async fn new() -> Self {
+        /// Build a layer from the test dependencies, with an empty directory added at each of the
+        /// paths in `owned_dirs`, owned by the given owner.
+        async fn new(owned_dirs: &[(&str, Owner)]) -> Self {
             let fs = async_fs::Fs::new();
             let temp_dir = TempDir::new().unwrap();
             let data_path = temp_dir.path().join("data");
@@ -1669,6 +3484,19 @@ mod tests {
                 .add_from_tar(digest!(42), fs.open_file(&tar_path).await.unwrap())
                 .await
                 .unwrap();
+            for (path, owner) in owned_dirs {
+                let attrs = FileAttributes {
+                    size: 0,
+                    mode: Mode(0o40755),
+                    mtime: ARBITRARY_TIME,
+                    xattrs: Default::default(),
+                    owner: Some(*owner),
+                };
+                builder
+                    .add_dir_path(Utf8Path::new(path), attrs)
+                    .await
+                    .unwrap();
+            }
             let _ = builder.finish().await.unwrap();
             let cache = Arc::new(Mutex::new(ReaderCache::new()));
             Self {
@@ -1686,23 +3514,52 @@ mod tests {
         }
     }
 
-    async fn run(
+    async fn run(
+        spec: maelstrom_base::JobSpec,
+        inline_limit: InlineLimit,
+    ) -> JobResult<JobCompleted, Error> {
+        run_with_options(
+            spec,
+            inline_limit,
+            None,
+            None,
+            Some(TEST_SUBORDINATE_GIDS),
+            &[],
+        )
+        .await
+    }
+
+    /// The tests run as root, so we can map supplementary groups to any host gids.
+    const TEST_SUBORDINATE_GIDS: SubordinateGids = SubordinateGids {
+        start: 100000,
+        count: 65536,
+    };
+
+    async fn run_with_options(
         spec: maelstrom_base::JobSpec,
         inline_limit: InlineLimit,
+        broker: Option<BrokerAddr>,
+        allowed_devices: Option<BTreeSet<Utf8PathBuf>>,
+        subordinate_gids: Option<SubordinateGids>,
+        owned_dirs: &[(&str, Owner)],
     ) -> JobResult<JobCompleted, Error> {
         let clock = TickingClock::new();
-        let mount = TarMount::new().await;
+        let mount = TarMount::new(owned_dirs).await;
         let spec = JobSpec::from_spec(spec);
         let (_kill_event_sender, kill_event_receiver) = sync::event();
         task::spawn_blocking(move || {
             Executor::new(
                 RootBuf::new(tempfile::tempdir().unwrap().into_path()),
                 RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                broker,
+                allowed_devices,
+                subordinate_gids,
                 &clock,
             )
             .unwrap()
             .run_job(
                 &spec,
+                None,
                 inline_limit,
                 kill_event_receiver,
                 |fd| mount.spawn(fd),
@@ -1713,8 +3570,66 @@ mod tests {
         .unwrap()
     }
 
+    /// Run the job with its root stacked with overlayfs from the test dependencies unpacked into a
+    /// directory, instead of with LayerFS. The files in `removed_image_files` are removed from the
+    /// unpacked test dependencies first.
+    async fn run_with_overlay_layers(
+        spec: maelstrom_base::JobSpec,
+        inline_limit: InlineLimit,
+        removed_image_files: &[&str],
+    ) -> JobResult<JobCompleted, Error> {
+        let clock = TickingClock::new();
+        let temp_dir = TempDir::new().unwrap();
+        let tar_path = temp_dir.path().join("deps.tar");
+        let layer_path = temp_dir.path().join("layer");
+        fs::write(&tar_path, include_bytes!("executor-test-deps.tar")).unwrap();
+        fs::create_dir(&layer_path).unwrap();
+        crate::overlay_layer::unpack_bottom_layer(
+            test_logger(),
+            &layer_path,
+            ArtifactType::Tar,
+            &tar_path,
+            |_| Ok(std::io::empty()),
+        )
+        .unwrap();
+        for path in removed_image_files {
+            fs::remove_file(layer_path.join("root").join(path)).unwrap();
+        }
+        let mut spec = JobSpec::from_spec(spec);
+        spec.layer_stacks =
+            LayerStacks::Overlay(vec![
+                crate::overlay_layer::overlay_lower_dirs(&layer_path).unwrap()
+            ]);
+        let (_kill_event_sender, kill_event_receiver) = sync::event();
+        task::spawn_blocking(move || {
+            let _temp_dir = temp_dir;
+            Executor::new(
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                None,
+                None,
+                None,
+                &clock,
+            )
+            .unwrap()
+            .run_job(
+                &spec,
+                None,
+                inline_limit,
+                kill_event_receiver,
+                |_| panic!("unexpected FUSE file descriptor"),
+                runtime::Handle::current(),
+            )
+        })
+        .await
+        .unwrap()
+    }
+
     struct Test {
         spec: maelstrom_base::JobSpec,
+        overlay_layers: bool,
+        removed_image_files: &'static [&'static str],
+        owned_dirs: Vec<(&'static str, Owner)>,
         inline_limit: InlineLimit,
         expected_status: JobTerminationStatus,
         expected_stdout: JobOutputResult,
@@ -1726,6 +3641,9 @@ mod tests {
         fn new(spec: maelstrom_base::JobSpec) -> Self {
             Test {
                 spec,
+                overlay_layers: false,
+                removed_image_files: &[],
+                owned_dirs: vec![],
                 inline_limit: InlineLimit::from(ByteSize::b(1000)),
                 expected_status: JobTerminationStatus::Exited(0),
                 expected_stdout: JobOutputResult::None,
@@ -1734,6 +3652,27 @@ mod tests {
             }
         }
 
+        fn overlay_layers(mut self) -> Self {
+            self.overlay_layers = true;
+            self
+        }
+
+        /// Run the job with overlay layers, with the given files removed from the image.
+        fn without_image_files(mut self, paths: &'static [&'static str]) -> Self {
+            self.overlay_layers = true;
+            self.removed_image_files = paths;
+            self
+        }
+
+        /// Add empty directories, owned by the given owners, to the job's layer.
+        fn owned_dirs(
+            mut self,
+            owned_dirs: impl IntoIterator<Item = (&'static str, Owner)>,
+        ) -> Self {
+            self.owned_dirs = owned_dirs.into_iter().collect();
+            self
+        }
+
         fn inline_limit(mut self, inline_limit: impl Into<InlineLimit>) -> Self {
             self.inline_limit = inline_limit.into();
             self
@@ -1763,7 +3702,21 @@ mod tests {
                         stderr,
                         duration,
                     },
-            } = run(self.spec, self.inline_limit).await.unwrap();
+            } = if self.overlay_layers {
+                run_with_overlay_layers(self.spec, self.inline_limit, self.removed_image_files)
+                    .await
+            } else {
+                run_with_options(
+                    self.spec,
+                    self.inline_limit,
+                    None,
+                    None,
+                    Some(TEST_SUBORDINATE_GIDS),
+                    &self.owned_dirs,
+                )
+                .await
+            }
+            .unwrap();
 
             assert_eq!(stderr, self.expected_stderr);
             assert_eq!(status, self.expected_status);
@@ -1910,6 +3863,82 @@ mod tests {
             .await;
     }
 
+    /// Serve the given secret values to the job from a local socket, the way the client does.
+    fn serve_secrets<const N: usize>(
+        destinations: [JobSecretDestination; N],
+        values: &[&str],
+    ) -> Option<JobSecrets> {
+        let (listener, path) = unix_listener();
+        let values = Vec::from_iter(values.iter().copied().map(JobSecretValue::new));
+        task::spawn(async move {
+            let mut socket = listener.accept().await.unwrap().0;
+            net::write_message_to_async_socket(&mut socket, values, &Logger::root(Discard, o!()))
+                .await
+                .unwrap();
+        });
+        Some(JobSecrets::new(
+            JobSecretsAddress::Local(path),
+            destinations,
+        ))
+    }
+
+    #[tokio::test]
+    async fn secret_environment_variable() {
+        Test::new(
+            bash_spec("echo -n $FOO - $BAR")
+                .environment(["FOO=3", "BAR=4"])
+                .secrets(serve_secrets(
+                    [JobSecretDestination::Environment("FOO".into())],
+                    &["hunter2"],
+                )),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"hunter2 - 4")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn secret_file() {
+        Test::new(
+            bash_spec("ls -l /etc/hosts | awk '{print $1}' && cat /etc/hosts").secrets(
+                serve_secrets(
+                    [JobSecretDestination::File("/etc/hosts".into())],
+                    &["swordfish"],
+                ),
+            ),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"-r--------\nswordfish")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn secrets_with_missing_value() {
+        let secrets = serve_secrets(
+            [
+                JobSecretDestination::Environment("FOO".into()),
+                JobSecretDestination::Environment("BAR".into()),
+            ],
+            &["hunter2"],
+        );
+        assert_matches!(
+            run(bash_spec("true").secrets(secrets), 0.into()).await,
+            Err(JobError::Execution(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn secrets_relay_without_broker() {
+        let secrets = JobSecrets::new(
+            JobSecretsAddress::Relay([42; 16]),
+            [JobSecretDestination::Environment("FOO".into())],
+        );
+        assert_matches!(
+            run(bash_spec("true").secrets(Some(secrets)), 0.into()).await,
+            Err(JobError::Execution(_))
+        );
+    }
+
     #[tokio::test]
     async fn stdin_empty() {
         Test::new(test_spec("/bin/cat")).run().await;
@@ -1938,6 +3967,75 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn init_pid_ppid_pgid_and_sid() {
+        // The init process is pid 1 and the session leader. We should be its child, in our own
+        // process group.
+        Test::new(
+            python_spec(indoc! {r#"
+                import os
+                print('pid:', os.getpid())
+                print('ppid:', os.getppid())
+                print('pgid:', os.getpgid(0))
+                print('sid:', os.getsid(0))
+            "#})
+            .init(true),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            pid: 2
+            ppid: 1
+            pgid: 2
+            sid: 1
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn init_exits_with_child_status() {
+        Test::new(python_spec("import sys; sys.exit(3)").init(true))
+            .expected_status(JobTerminationStatus::Exited(3))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn init_reports_signal_that_killed_child() {
+        Test::new(python_spec("import os; os.abort()").init(true))
+            .expected_status(JobTerminationStatus::Signaled(6))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn init_reaps_orphans() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import os
+                import time
+                if os.fork() == 0:
+                    if os.fork() == 0:
+                        os._exit(0)
+                    os._exit(0)
+                os.wait()
+                time.sleep(0.1)
+                zombies = 0
+                for pid in filter(str.isdigit, os.listdir('/proc')):
+                    with open(f'/proc/{pid}/stat') as f:
+                        if f.read().rsplit(')', 1)[1].split()[0] == 'Z':
+                            zombies += 1
+                print('zombies:', zombies)
+            "#})
+            .mounts([JobMount::Proc {
+                mount_point: utf8_path_buf!("/proc"),
+            }])
+            .init(true),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"zombies: 0\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn no_loopback() {
         Test::new(
@@ -2021,6 +4119,34 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn owned_files_nonzero_user_and_group() {
+        Test::new(
+            bash_spec("/usr/bin/stat -c '%u %g' /mine /roots")
+                .user(Some(43))
+                .group(Some(100)),
+        )
+        .owned_dirs([
+            (
+                "/mine",
+                Owner {
+                    user: UserId::new(43),
+                    group: GroupId::new(100),
+                },
+            ),
+            (
+                "/roots",
+                Owner {
+                    user: UserId::new(0),
+                    group: GroupId::new(0),
+                },
+            ),
+        ])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"43 100\n65534 65534\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn user_and_group_nonzero() {
         Test::new(
@@ -2040,6 +4166,238 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn synthesize_user_files() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import grp
+                import os
+                import pwd
+                print(pwd.getpwuid(os.getuid()).pw_name)
+                print(grp.getgrgid(os.getgid()).gr_name)
+                print(os.environ['HOME'])
+                open(os.path.join(os.environ['HOME'], 'foo'), 'w').write('bar')
+            "#})
+            .user(Some(1234))
+            .group(Some(4321))
+            .synthesize_user_files(true),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            maelstrom
+            maelstrom-4321
+            /home/maelstrom
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn synthesize_user_files_keeps_other_home_directories() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import os
+                print(sorted(os.listdir('/home')))
+                print(os.listdir(os.environ['HOME']))
+                open(os.path.join(os.environ['HOME'], 'foo'), 'w').write('bar')
+            "#})
+            .user(Some(1234))
+            .group(Some(4321))
+            .synthesize_user_files(true),
+        )
+        .owned_dirs([(
+            "/home/other",
+            Owner {
+                user: UserId::new(1234),
+                group: GroupId::new(4321),
+            },
+        )])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            ['maelstrom', 'other']
+            []
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn synthesize_user_files_keeps_image_entries() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import os
+                import pwd
+                print(pwd.getpwuid(os.getuid()).pw_name)
+                print(os.environ['HOME'])
+            "#})
+            .synthesize_user_files(true),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            root
+            /root
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn synthesize_user_files_without_image_files() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import grp
+                import os
+                import pwd
+                print(pwd.getpwuid(os.getuid()).pw_name)
+                print(grp.getgrgid(os.getgid()).gr_name)
+            "#})
+            .user(Some(1234))
+            .group(Some(4321))
+            .synthesize_user_files(true)
+            .root_overlay(JobRootOverlay::Tmp { size: None }),
+        )
+        .without_image_files(&["etc/passwd", "etc/group"])
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            maelstrom
+            maelstrom-4321
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn supplementary_groups_mapped_to_subordinate_gids() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import os
+                print(sorted(os.getgroups()))
+                print([line.split() for line in open('/proc/self/gid_map')])
+            "#})
+            .user(Some(43))
+            .group(Some(100))
+            .groups([100, 6, 27])
+            .mounts([JobMount::Proc {
+                mount_point: utf8_path_buf!("/proc"),
+            }]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            [6, 27]
+            [['100', '0', '1'], ['6', '100000', '1'], ['27', '100001', '1']]
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn supplementary_groups_without_subordinate_gids() {
+        assert_matches!(
+            run_with_options(
+                test_spec("/bin/true").groups([6]),
+                0.into(),
+                None,
+                None,
+                None,
+                &[],
+            )
+            .await,
+            Err(JobError::Execution(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn capabilities_added_for_non_root_user() {
+        Test::new(
+            bash_spec("grep CapEff /proc/self/status")
+                .user(Some(43))
+                .capabilities(JobCapabilities {
+                    add: enum_set!(JobCapability::NetRaw | JobCapability::Kill),
+                    drop: EnumSet::empty(),
+                })
+                .mounts([JobMount::Proc {
+                    mount_point: utf8_path_buf!("/proc"),
+                }]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(
+            b"CapEff:\t0000000000002020\n"
+        )))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn capabilities_dropped_for_root_user() {
+        Test::new(
+            python_spec(indoc! {r#"
+                for line in open('/proc/self/status'):
+                    if line.startswith('CapEff:') or line.startswith('CapBnd:'):
+                        caps = int(line.split()[1], 16)
+                        print(caps & (1 << 21) == 0, caps & (1 << 13) != 0)
+            "#})
+            .capabilities(JobCapabilities {
+                add: EnumSet::empty(),
+                drop: enum_set!(JobCapability::SysAdmin),
+            })
+            .mounts([JobMount::Proc {
+                mount_point: utf8_path_buf!("/proc"),
+            }]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            True True
+            True True
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn hostname() {
+        Test::new(
+            python_spec(indoc! {r#"
+                import socket
+                print(socket.gethostname())
+            "#})
+            .hostname(Some("maelstrom-test")),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"maelstrom-test\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn hosts() {
+        Test::new(
+            bash_spec("cat /etc/hosts")
+                .hostname(Some("maelstrom-test"))
+                .hosts([
+                    JobHostsEntry::new(Ipv4Addr::new(127, 0, 0, 2), ["db.local", "db"]),
+                    JobHostsEntry::new(Ipv6Addr::LOCALHOST, ["cache.local"]),
+                ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(indoc! {b"
+            127.0.0.1\tlocalhost
+            ::1\tlocalhost ip6-localhost ip6-loopback
+            127.0.1.1\tmaelstrom-test
+            127.0.0.2\tdb.local db
+            ::1\tcache.local
+        "})))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn hosts_with_tmp_root_overlay() {
+        Test::new(
+            bash_spec("grep db.local /etc/hosts && echo bar > /foo && cat /foo")
+                .root_overlay(JobRootOverlay::Tmp { size: None })
+                .hosts([JobHostsEntry::new(
+                    Ipv4Addr::new(127, 0, 0, 2),
+                    ["db.local"],
+                )]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(
+            b"127.0.0.2\tdb.local\nbar\n"
+        )))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn close_range() {
         // Throw the kitchen sink in the spec: we want an example of anything that opens a file
@@ -2094,12 +4452,15 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn one_layer_with_tmp_root_overlay_is_writable() {
-        Test::new(bash_spec("echo bar > /foo && cat /foo").root_overlay(JobRootOverlay::Tmp))
-            .expected_status(JobTerminationStatus::Exited(0))
-            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"bar\n")))
-            .run()
-            .await;
+    async fn one_layer_with_tmp_root_overlay_is_writable() {
+        Test::new(
+            bash_spec("echo bar > /foo && cat /foo")
+                .root_overlay(JobRootOverlay::Tmp { size: None }),
+        )
+        .expected_status(JobTerminationStatus::Exited(0))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"bar\n")))
+        .run()
+        .await;
 
         // Run another job to ensure that the file doesn't persist.
         Test::new(bash_spec("test -e /foo"))
@@ -2110,7 +4471,8 @@ mod tests {
 
     #[tokio::test]
     async fn multiple_layers_with_tmp_root_overlay_is_writable() {
-        let spec = bash_spec("echo bar > /foo && cat /foo").root_overlay(JobRootOverlay::Tmp);
+        let spec = bash_spec("echo bar > /foo && cat /foo")
+            .root_overlay(JobRootOverlay::Tmp { size: None });
         Test::new(spec)
             .expected_status(JobTerminationStatus::Exited(0))
             .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"bar\n")))
@@ -2118,13 +4480,47 @@ mod tests {
             .await;
 
         // Run another job to ensure that the file doesn't persist.
-        let spec = bash_spec("test -e /foo").root_overlay(JobRootOverlay::Tmp);
+        let spec = bash_spec("test -e /foo").root_overlay(JobRootOverlay::Tmp { size: None });
         Test::new(spec)
             .expected_status(JobTerminationStatus::Exited(1))
             .run()
             .await;
     }
 
+    #[tokio::test]
+    async fn tmp_root_overlay_size_is_enforced() {
+        Test::new(
+            bash_spec("printf '%*s' 2097152 '' > /foo 2>&-").root_overlay(JobRootOverlay::Tmp {
+                size: Some(1024 * 1024),
+            }),
+        )
+        .expected_status(JobTerminationStatus::Exited(1))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn overlay_layer_stack() {
+        Test::new(bash_spec("echo hello; test -w /usr/bin/bash"))
+            .overlay_layers()
+            .expected_status(JobTerminationStatus::Exited(1))
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"hello\n")))
+            .run()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn overlay_layer_stack_with_tmp_root_overlay() {
+        Test::new(
+            bash_spec("echo bar > /foo && cat /foo")
+                .root_overlay(JobRootOverlay::Tmp { size: None }),
+        )
+        .overlay_layers()
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"bar\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn local_root_overlay_is_writable_and_output_is_captured() {
         let temp_dir = TempDir::new().unwrap();
@@ -2454,6 +4850,36 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn host_device() {
+        Test::new(
+            bash_spec("/bin/ls -l /dev/zero | awk '{print $5, $6}'").mounts([JobMount::Device {
+                path: utf8_path_buf!("/dev/zero"),
+            }]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"1, 5\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn host_device_not_allowed() {
+        assert_matches!(
+            run_with_options(
+                test_spec("/bin/true").mounts([JobMount::Device {
+                    path: utf8_path_buf!("/dev/zero"),
+                }]),
+                0.into(),
+                None,
+                Some(BTreeSet::from([utf8_path_buf!("/dev/null")])),
+                None,
+                &[],
+            )
+            .await,
+            Err(JobError::Execution(_))
+        );
+    }
+
     #[tokio::test]
     async fn no_tmpfs() {
         Test::new(
@@ -2479,6 +4905,11 @@ mod tests {
                     },
                     JobMount::Tmp {
                         mount_point: utf8_path_buf!("/tmp"),
+                        size: None,
+                        mode: None,
+                        uid: None,
+                        gid: None,
+                        nr_inodes: None,
                     },
                 ]),
         )
@@ -2487,6 +4918,34 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn tmpfs_with_options() {
+        Test::new(
+            test_spec("/bin/grep")
+                .arguments([
+                    "-c",
+                    "^none /tmp tmpfs rw,.*size=1024k,nr_inodes=16,mode=700 ",
+                    "/proc/self/mounts",
+                ])
+                .mounts([
+                    JobMount::Proc {
+                        mount_point: utf8_path_buf!("/proc"),
+                    },
+                    JobMount::Tmp {
+                        mount_point: utf8_path_buf!("/tmp"),
+                        size: Some(1024 * 1024),
+                        mode: Some(0o700),
+                        uid: None,
+                        gid: None,
+                        nr_inodes: Some(16),
+                    },
+                ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"1\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn no_sysfs() {
         Test::new(
@@ -2685,6 +5144,167 @@ mod tests {
         assert_eq!(contents, "hello\n");
     }
 
+    #[tokio::test]
+    async fn layer_mount_writable() {
+        Test::new(
+            bash_spec("echo hello > /mnt/foo && cat /mnt/foo && ls -d /mnt/mnt && ls /foo").mounts(
+                [
+                    JobMount::Layer {
+                        mount_point: utf8_path_buf!("/mnt"),
+                        layer: (digest!(42), ArtifactType::Tar),
+                        read_only: false,
+                    },
+                    JobMount::Devices {
+                        devices: enum_set!(JobDevice::Null),
+                    },
+                ],
+            ),
+        )
+        .expected_status(JobTerminationStatus::Exited(2))
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"hello\n/mnt/mnt\n")))
+        .expected_stderr(JobOutputResult::Inline(boxed_u8!(
+            b"ls: cannot access '/foo': No such file or directory\n"
+        )))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn layer_mount_read_only() {
+        Test::new(
+            bash_spec("ls -d /mnt/mnt && (echo hello > /mnt/foo) 2>/dev/null || echo failed")
+                .mounts([
+                    JobMount::Layer {
+                        mount_point: utf8_path_buf!("/mnt"),
+                        layer: (digest!(42), ArtifactType::Tar),
+                        read_only: true,
+                    },
+                    JobMount::Devices {
+                        devices: enum_set!(JobDevice::Null),
+                    },
+                ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"/mnt/mnt\nfailed\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_with_ready_port() {
+        let sidecar = JobSidecar::new(
+            "/usr/bin/python3",
+            nonempty![(digest!(42), ArtifactType::Tar)],
+        )
+        .arguments([
+            "-c",
+            indoc! {r#"
+                    import socket
+                    s = socket.socket()
+                    s.bind(("127.0.0.1", 8080))
+                    s.listen()
+                    while True:
+                        c, _ = s.accept()
+                        c.sendall(b"hello from sidecar")
+                        c.close()
+                "#},
+        ])
+        .ready_port(Some(8080));
+        Test::new(
+            python_spec(indoc! {r#"
+                import socket
+                s = socket.create_connection(("127.0.0.1", 8080))
+                print(s.recv(100).decode())
+            "#})
+            .network(JobNetwork::Loopback)
+            .sidecars([sidecar]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"hello from sidecar\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_output_goes_to_stderr() {
+        let sidecar = JobSidecar::new("/usr/bin/bash", nonempty![(digest!(42), ArtifactType::Tar)])
+            .arguments(["-c", "echo sidecar"]);
+        Test::new(
+            bash_spec("sleep 1")
+                .network(JobNetwork::Loopback)
+                .sidecars([sidecar]),
+        )
+        .expected_stderr(JobOutputResult::Inline(boxed_u8!(b"sidecar\n")))
+        .run()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_exits_before_ready() {
+        let sidecar = JobSidecar::new("/usr/bin/bash", nonempty![(digest!(42), ArtifactType::Tar)])
+            .arguments(["-c", "exit 1"])
+            .ready_port(Some(8080));
+        assert_execution_error(
+            bash_spec("exit 0")
+                .network(JobNetwork::Loopback)
+                .sidecars([sidecar]),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_ready_timeout() {
+        let sidecar = JobSidecar::new("/usr/bin/bash", nonempty![(digest!(42), ArtifactType::Tar)])
+            .arguments(["-c", "sleep 10"])
+            .ready_port(Some(8080))
+            .ready_timeout(Timeout::new(1));
+        let start = Instant::now();
+        assert_execution_error(
+            bash_spec("exit 0")
+                .network(JobNetwork::Loopback)
+                .sidecars([sidecar]),
+        )
+        .await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn sidecar_program_not_found_is_an_execution_error() {
+        let sidecar = JobSidecar::new(
+            "a_program_that_does_not_exist",
+            nonempty![(digest!(42), ArtifactType::Tar)],
+        );
+        assert_execution_error(
+            bash_spec("sleep 1")
+                .network(JobNetwork::Loopback)
+                .sidecars([sidecar]),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_bad_working_directory_is_an_execution_error() {
+        let sidecar = JobSidecar::new("/usr/bin/bash", nonempty![(digest!(42), ArtifactType::Tar)])
+            .arguments(["-c", "sleep 1"])
+            .working_directory(Some("/dev/null"));
+        assert_execution_error(
+            bash_spec("sleep 1")
+                .network(JobNetwork::Loopback)
+                .sidecars([sidecar]),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sidecar_with_disabled_network_is_an_execution_error() {
+        let sidecar = JobSidecar::new("/usr/bin/bash", nonempty![(digest!(42), ArtifactType::Tar)])
+            .arguments(["-c", "sleep 1"]);
+        assert_execution_error(
+            bash_spec("sleep 1")
+                .network(JobNetwork::Disabled)
+                .sidecars([sidecar]),
+        )
+        .await;
+    }
+
     #[tokio::test]
     async fn bind_mount_path_is_relative_to_pwd() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -2858,6 +5478,53 @@ mod tests {
         assert_job_exit(job_handle.await.unwrap(), 0);
     }
 
+    #[tokio::test]
+    async fn tty_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let broker = BrokerAddr::new(listener.local_addr().unwrap());
+        let job_handle = task::spawn(async move {
+            run_with_options(
+                test_spec("/bin/cat")
+                    .allocate_tty(Some(JobTty::relay([42; 16], WindowSize::new(24, 80)))),
+                0.into(),
+                Some(broker),
+                None,
+                None,
+                &[],
+            )
+            .await
+            .unwrap()
+        });
+        let mut socket = listener.accept().await.unwrap().0;
+        let log = Logger::root(Discard, o!());
+        assert_matches!(
+            net::read_message_from_async_socket(&mut socket, &log).await,
+            Ok(Hello::Relay {
+                key,
+                side: RelaySide::Job
+            }) if key == [42; 16]
+        );
+
+        socket.write_all(b"dog\n").await.unwrap();
+        expect(&mut socket, b"dog\r\ndog\r\n").await;
+
+        socket.write_all(b"\x04").await.unwrap();
+        assert_job_exit(job_handle.await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn tty_relay_without_broker() {
+        assert_matches!(
+            run(
+                test_spec("/bin/cat")
+                    .allocate_tty(Some(JobTty::relay([42; 16], WindowSize::new(24, 80)))),
+                0.into(),
+            )
+            .await,
+            Err(JobError::Execution(_))
+        );
+    }
+
     #[tokio::test]
     async fn tty_ctrl_c() {
         let (job_handle, mut socket) = start_tty_job("/exitsig", WindowSize::new(24, 80)).await;
@@ -2925,4 +5592,191 @@ mod tests {
         drop(socket);
         assert_job_exit(job_handle.await.unwrap(), 0);
     }
+
+    /// Start a warm sandbox for `spec`, then run `spec` in it.
+    async fn run_warm(
+        spec: maelstrom_base::JobSpec,
+        inline_limit: InlineLimit,
+    ) -> JobResult<JobCompleted, Error> {
+        let clock = TickingClock::new();
+        let mount = TarMount::new(&[]).await;
+        let key = SandboxKey::new(&spec, mount.data_path.clone()).unwrap();
+        let spec = JobSpec::from_spec(spec);
+        let (_kill_event_sender, kill_event_receiver) = sync::event();
+        task::spawn_blocking(move || {
+            let executor = Executor::new(
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                None,
+                None,
+                None,
+                &clock,
+            )
+            .unwrap();
+            let sandbox = executor
+                .warm_sandbox(&spec, &key, |fd| mount.spawn(fd))
+                .unwrap();
+            executor.run_job(
+                &spec,
+                Some(sandbox),
+                inline_limit,
+                kill_event_receiver,
+                |_| panic!("a warm sandbox's root should already be mounted"),
+                runtime::Handle::current(),
+            )
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn warm_sandbox() {
+        let JobCompleted {
+            status,
+            effects: JobEffects { stdout, stderr, .. },
+        } = run_warm(
+            bash_spec("echo a; echo b >&2; hostname; ls /proc/self/fd")
+                .hostname(Some("warm"))
+                .mounts([JobMount::Proc {
+                    mount_point: utf8_path_buf!("/proc"),
+                }]),
+            1000.into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stderr, JobOutputResult::Inline(boxed_u8!(b"b\n")));
+        assert_eq!(
+            stdout,
+            JobOutputResult::Inline(boxed_u8!(b"a\nwarm\n0\n1\n2\n3\n"))
+        );
+        assert_eq!(status, JobTerminationStatus::Exited(0));
+    }
+
+    #[tokio::test]
+    async fn warm_sandbox_with_tmp_root_overlay() {
+        let JobCompleted {
+            status,
+            effects: JobEffects { stdout, .. },
+        } = run_warm(
+            bash_spec("echo bar > /foo && cat /foo")
+                .root_overlay(JobRootOverlay::Tmp { size: None })
+                .user(Some(UserId::new(1000)))
+                .group(Some(GroupId::new(1000))),
+            1000.into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stdout, JobOutputResult::Inline(boxed_u8!(b"bar\n")));
+        assert_eq!(status, JobTerminationStatus::Exited(0));
+    }
+
+    #[tokio::test]
+    async fn warm_sandbox_tty() {
+        let (listener, path) = unix_listener();
+        let job_handle = task::spawn(async move {
+            run_warm(
+                test_spec("/bin/cat")
+                    .allocate_tty(Some(JobTty::new(&path, WindowSize::new(24, 80)))),
+                0.into(),
+            )
+            .await
+            .unwrap()
+        });
+        let mut socket = listener.accept().await.unwrap().0;
+
+        socket.write_all(b"dog\n").await.unwrap();
+        expect(&mut socket, b"dog\r\ndog\r\n").await;
+
+        socket.write_all(b"\x04").await.unwrap();
+        assert_job_exit(job_handle.await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn warm_sandbox_execution_error() {
+        assert_matches!(
+            run_warm(test_spec("/foo"), 0.into()).await,
+            Err(JobError::Execution(_))
+        );
+    }
+
+    #[test]
+    fn sandbox_key() {
+        let key = |spec: &maelstrom_base::JobSpec| SandboxKey::new(spec, "layers".into());
+
+        let base = test_spec("/bin/true");
+        assert_eq!(key(&base), key(&test_spec("/bin/false")));
+        assert_ne!(
+            key(&base),
+            key(&test_spec("/bin/true").network(JobNetwork::Local))
+        );
+        assert_ne!(
+            key(&base),
+            key(&test_spec("/bin/true").root_overlay(JobRootOverlay::Tmp { size: None }))
+        );
+        assert_ne!(key(&base), SandboxKey::new(&base, "other-layers".into()));
+
+        assert_eq!(key(&test_spec("/bin/true").init(true)), None);
+        assert_eq!(
+            key(&test_spec("/bin/true").root_overlay(JobRootOverlay::Local {
+                upper: "upper".into(),
+                work: "work".into(),
+            })),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn sandbox_pool_startup_latency() {
+        const ITERATIONS: u32 = 10;
+        let clock = TickingClock::new();
+        let mount = TarMount::new(&[]).await;
+        let spec = test_spec("/bin/true").root_overlay(JobRootOverlay::Tmp { size: None });
+        let key = SandboxKey::new(&spec, mount.data_path.clone()).unwrap();
+        let spec = JobSpec::from_spec(spec);
+        let (cold, warm) = task::spawn_blocking(move || {
+            let executor = Executor::new(
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                RootBuf::new(tempfile::tempdir().unwrap().into_path()),
+                None,
+                None,
+                None,
+                &clock,
+            )
+            .unwrap();
+            let run_job = |sandbox| {
+                let (_kill_event_sender, kill_event_receiver) = sync::event();
+                let start = Instant::now();
+                let job_completed = executor
+                    .run_job(
+                        &spec,
+                        sandbox,
+                        0.into(),
+                        kill_event_receiver,
+                        |fd| mount.spawn(fd),
+                        runtime::Handle::current(),
+                    )
+                    .unwrap();
+                let elapsed = start.elapsed();
+                assert_job_exit(job_completed, 0);
+                elapsed
+            };
+
+            let mut cold = Duration::ZERO;
+            let mut warm = Duration::ZERO;
+            for _ in 0..ITERATIONS {
+                cold += run_job(None);
+                let sandbox = executor
+                    .warm_sandbox(&spec, &key, |fd| mount.spawn(fd))
+                    .unwrap();
+                warm += run_job(Some(sandbox));
+            }
+            (cold / ITERATIONS, warm / ITERATIONS)
+        })
+        .await
+        .unwrap();
+        assert!(
+            warm < cold,
+            "warm sandboxes should start jobs faster: cold {cold:?}, warm {warm:?}"
+        );
+    }
 }
@@ -1,7 +1,15 @@
-use anyhow::Result;
+use crate::{
+    dispatcher::Message,
+    types::{CacheKeyKind, DispatcherSender},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
 use futures::StreamExt as _;
 use maelstrom_base::{manifest::UnixTimestamp, ArtifactType, Sha256Digest};
-use maelstrom_layer_fs::{BlobDir, BottomLayerBuilder, LayerFs, UpperLayerBuilder};
+use maelstrom_layer_fs::{
+    verify_layer, BlobDir, BlobFetcher, BottomLayerBuilder, CompactedLayerBuilder, LayerFs,
+    UpperLayerBuilder,
+};
 use maelstrom_util::{
     async_fs::Fs,
     cache::{
@@ -9,13 +17,17 @@ use maelstrom_util::{
             std::{Fs as StdFs, TempDir as StdTempDir},
             TempDir as _,
         },
-        TempFileFactory,
+        CacheDir, TempFileFactory,
     },
-    root::Root,
+    root::{Root, RootBuf},
+};
+use slog::{info, warn, Logger};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
-use slog::Logger;
-use std::path::{Path, PathBuf};
-use tokio::io::BufReader;
+use tokio::{io::BufReader, sync::oneshot};
 
 async fn dir_size(fs: &Fs, path: &Path) -> Result<u64> {
     let mut total = 0;
@@ -71,3 +83,199 @@ pub async fn build_upper_layer(
     let size = dir_size(&fs, layer_path).await?;
     Ok((layer_temp_dir, size))
 }
+
+pub async fn build_compacted_layer(
+    log: Logger,
+    temp_file_factory: TempFileFactory<StdFs>,
+    blob_dir: &Root<BlobDir>,
+    stack_path: PathBuf,
+) -> Result<(StdTempDir, u64)> {
+    let layer_temp_dir = temp_file_factory.temp_dir()?;
+    let layer_path = layer_temp_dir.path();
+    let fs = Fs::new();
+    let stack = LayerFs::from_path(&stack_path, blob_dir)?;
+    let mut builder = CompactedLayerBuilder::new(log, &fs, layer_path, blob_dir).await?;
+    builder.fill_from_stack(&stack).await?;
+    builder.finish().await?;
+
+    let size = dir_size(&fs, layer_path).await?;
+    Ok((layer_temp_dir, size))
+}
+
+/// Remove the LayerFS layers left in the cache from a previous run that can't be served, either
+/// because they were written by an incompatible version or because they're corrupt, like when the
+/// worker crashed while building one. This should be called before the cache is opened. The cache
+/// then forgets about the removed layers.
+///
+/// Layers are checked from the bottom of their stacks up, so a layer stacked on top of a removed
+/// layer is removed too.
+pub async fn discard_unusable_layers(
+    log: &Logger,
+    cache_root: &Root<CacheDir>,
+    blob_dir: &Root<BlobDir>,
+) -> Result<()> {
+    let fs = Fs::new();
+    let mut layers = vec![];
+    for kind in [
+        CacheKeyKind::BottomFsLayer,
+        CacheKeyKind::UpperFsLayer,
+        CacheKeyKind::CompactedFsLayer,
+    ] {
+        let kind_dir = cache_root.join::<()>(format!("sha256/{kind}"));
+        if !fs.exists(&kind_dir).await {
+            continue;
+        }
+        let mut entries = fs.read_dir(&kind_dir).await?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            let layer_id = LayerFs::from_path(&path, blob_dir)?.layer_id().await;
+            layers.push((layer_id, path));
+        }
+    }
+    layers.sort_by_key(|(layer_id, _)| layer_id.as_ref().ok().map(|id| id.as_u32()));
+
+    let mut discarded = 0;
+    for (layer_id, path) in layers {
+        let result = match layer_id {
+            Ok(_) => verify_layer(&LayerFs::from_path(&path, blob_dir)?).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!(log, "discarding unusable cached layer";
+                "path" => ?path,
+                "error" => %err.root_cause(),
+            );
+            fs.remove_dir_all(&path).await?;
+            discarded += 1;
+        }
+    }
+    if discarded > 0 {
+        info!(log, "discarded unusable cached layers"; "count" => discarded);
+    }
+    Ok(())
+}
+
+/// Something waiting for a blob fetch to finish. It is sent the fetch's error, if there was one.
+type BlobWaiter = oneshot::Sender<Result<(), String>>;
+
+/// Gets blobs for LayerFS on demand by asking the dispatcher for them. The dispatcher says when
+/// each fetch is finished by way of [`Self::blob_fetch_finished`].
+#[derive(Clone)]
+pub struct DispatcherBlobFetcher {
+    blob_dir: RootBuf<BlobDir>,
+    dispatcher_sender: DispatcherSender,
+    waiters: Arc<Mutex<HashMap<Sha256Digest, Vec<BlobWaiter>>>>,
+}
+
+impl DispatcherBlobFetcher {
+    pub fn new(blob_dir: RootBuf<BlobDir>, dispatcher_sender: DispatcherSender) -> Self {
+        Self {
+            blob_dir,
+            dispatcher_sender,
+            waiters: Default::default(),
+        }
+    }
+
+    /// Wake up everything waiting for the given blob, giving each of them `result`.
+    pub fn blob_fetch_finished(&self, digest: &Sha256Digest, result: Result<(), String>) {
+        for waiter in self
+            .waiters
+            .lock()
+            .unwrap()
+            .remove(digest)
+            .unwrap_or_default()
+        {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl BlobFetcher for DispatcherBlobFetcher {
+    async fn fetch_blob(&self, digest: &Sha256Digest) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut waiters = self.waiters.lock().unwrap();
+            let waiters = waiters.entry(digest.clone()).or_default();
+            waiters.retain(|waiter| !waiter.is_closed());
+            waiters.push(sender);
+        }
+
+        // We registered before looking, so we can't miss the fetch finishing.
+        let path = self.blob_dir.join::<BlobDir>(digest.to_string());
+        if !Fs::new().exists(&path).await {
+            self.dispatcher_sender
+                .send(Message::BlobNeeded(digest.clone()))?;
+            if let Ok(Err(err)) = receiver.await {
+                bail!(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_test::digest;
+    use tempfile::TempDir;
+
+    async fn build_layer(blob_dir: &Root<BlobDir>, path: &Path, lower: Option<&Path>) {
+        let log = maelstrom_util::log::test_logger();
+        let fs = Fs::new();
+        fs.create_dir(path).await.unwrap();
+        match lower {
+            None => {
+                let builder =
+                    BottomLayerBuilder::new(log, &fs, path, blob_dir, UnixTimestamp::EPOCH)
+                        .await
+                        .unwrap();
+                builder.finish().await.unwrap();
+            }
+            Some(lower) => {
+                let lower = LayerFs::from_path(lower, blob_dir).unwrap();
+                let mut builder = UpperLayerBuilder::new(log, path, blob_dir, &lower)
+                    .await
+                    .unwrap();
+                builder.fill_from_bottom_layer(&lower).await.unwrap();
+                builder.finish().await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn discard_unusable_layers_removes_layers_stacked_on_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_root = RootBuf::<CacheDir>::new(temp_dir.path().to_owned());
+        let blob_dir = cache_root.join::<BlobDir>("sha256/blob");
+        let fs = Fs::new();
+        let bottom_dir = temp_dir.path().join("sha256/bottom_fs_layer");
+        let upper_dir = temp_dir.path().join("sha256/upper_fs_layer");
+        fs.create_dir_all(&bottom_dir).await.unwrap();
+        fs.create_dir_all(&upper_dir).await.unwrap();
+
+        let good = bottom_dir.join(digest!(1).to_string());
+        let bad = bottom_dir.join(digest!(2).to_string());
+        let on_good = upper_dir.join(digest!(3).to_string());
+        let on_bad = upper_dir.join(digest!(4).to_string());
+        build_layer(&blob_dir, &good, None).await;
+        build_layer(&blob_dir, &bad, None).await;
+        build_layer(&blob_dir, &on_good, Some(&good)).await;
+        build_layer(&blob_dir, &on_bad, Some(&bad)).await;
+        fs.remove_file(bad.join("file_table.bin")).await.unwrap();
+
+        let log = maelstrom_util::log::test_logger();
+        discard_unusable_layers(&log, &cache_root, &blob_dir)
+            .await
+            .unwrap();
+
+        assert!(fs.exists(&good).await);
+        assert!(fs.exists(&on_good).await);
+        assert!(!fs.exists(&bad).await);
+        assert!(!fs.exists(&on_bad).await);
+    }
+}
@@ -9,15 +9,18 @@ mod dispatcher_adapter;
 mod executor;
 mod layer_fs;
 mod manifest_digest_cache;
+mod overlay_layer;
+mod subgid;
 mod types;
+mod volume;
 
 use anyhow::{anyhow, bail, Context as _, Result};
 use artifact_fetcher::ArtifactFetcher;
-use config::Config;
+use config::{Config, LayerStacking};
 use dispatcher::Message;
 use dispatcher_adapter::DispatcherAdapter;
 use executor::{MountDir, TmpfsDir};
-use maelstrom_base::proto::Hello;
+use maelstrom_base::{proto::Hello, Utf8PathBuf};
 use maelstrom_layer_fs::BlobDir;
 use maelstrom_linux::{self as linux};
 use maelstrom_util::{
@@ -27,7 +30,7 @@ use maelstrom_util::{
     signal,
 };
 use slog::{debug, error, info, Logger};
-use std::{future::Future, process};
+use std::{collections::BTreeSet, future::Future, process};
 use tokio::{io::BufReader, net::TcpStream, sync::mpsc};
 use types::{
     BrokerSender, BrokerSocketIncomingReceiver, BrokerSocketOutgoingSender, Cache, Dispatcher,
@@ -65,6 +68,7 @@ pub async fn main_inner(config: Config, log: Logger) -> Result<()> {
         &mut write_stream,
         Hello::Worker {
             slots: (*config.slots.inner()).into(),
+            devices: allowed_devices(&config),
         },
         &log,
     )
@@ -121,6 +125,20 @@ pub async fn main_inner(config: Config, log: Logger) -> Result<()> {
     Ok(())
 }
 
+fn allowed_devices(config: &Config) -> BTreeSet<Utf8PathBuf> {
+    config
+        .allowed_devices
+        .iter()
+        .map(Utf8PathBuf::from)
+        .collect()
+}
+
+fn sandbox_pool_size(config: &Config) -> usize {
+    config
+        .sandbox_pool_size
+        .unwrap_or(u16::from(config.slots).into())
+}
+
 /// Check if the open file limit is high enough to fit our estimate of how many files we need.
 pub fn check_open_file_limit(log: &Logger, slots: Slots, extra: u64) -> Result<()> {
     let limit = linux::getrlimit(linux::RlimitResource::NoFile)?;
@@ -178,18 +196,30 @@ async fn dispatcher_main(
 ) {
     let mount_dir = config.cache_root.join::<MountDir>("mount");
     let tmpfs_dir = config.cache_root.join::<TmpfsDir>("upper");
+    let volume_cache_root = config.cache_root.join::<CacheDir>("volumes");
     let cache_root = config.cache_root.join::<CacheDir>("artifacts");
     let blob_dir = cache_root.join::<BlobDir>("sha256/blob");
 
     let broker_sender = BrokerSender::new(broker_socket_outgoing_sender);
-    let (cache, temp_file_factory) =
-        match Cache::new(StdFs, cache_root, config.cache_size, log.clone()) {
-            Err(err) => {
-                error!(log, "could not start cache"; "error" => %err);
-                return;
-            }
-            Ok((cache, temp_file_factory)) => (cache, temp_file_factory),
-        };
+    if config.layer_stacking == LayerStacking::Fuse {
+        if let Err(err) = layer_fs::discard_unusable_layers(&log, &cache_root, &blob_dir).await {
+            error!(log, "could not check cached layers"; "error" => %err);
+            return;
+        }
+    }
+    let (cache, temp_file_factory, volume_temp_file_factory) = match Cache::new(
+        cache_root,
+        config.cache_size,
+        volume_cache_root,
+        config.volume_cache_size,
+        log.clone(),
+    ) {
+        Err(err) => {
+            error!(log, "could not start cache"; "error" => %err);
+            return;
+        }
+        Ok(caches) => caches,
+    };
     let artifact_fetcher = ArtifactFetcher::new(
         u32::try_from(MAX_ARTIFACT_FETCHES)
             .unwrap()
@@ -208,6 +238,10 @@ async fn dispatcher_main(
         tmpfs_dir,
         blob_dir,
         temp_file_factory,
+        volume_temp_file_factory,
+        Some(config.broker),
+        Some(allowed_devices(&config)),
+        config.layer_stacking,
     ) {
         Err(err) => {
             error!(log, "could not start executor"; "error" => %err);
@@ -219,6 +253,7 @@ async fn dispatcher_main(
                 broker_sender,
                 cache,
                 config.slots,
+                sandbox_pool_size(&config),
             );
             handle_incoming_messages(
                 log,
@@ -245,7 +280,7 @@ async fn handle_incoming_messages(
             },
             msg = broker_socket_incoming_recevier.recv() => {
                 let Some(msg) = msg else { continue };
-                handle_dispatcher_message(Message::Broker(msg), &mut dispatcher)
+                handle_dispatcher_message(Message::Broker(Box::new(msg)), &mut dispatcher)
             },
         };
         if let Err(err) = res {
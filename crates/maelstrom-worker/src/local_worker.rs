@@ -1,13 +1,14 @@
 pub use crate::{
     check_open_file_limit,
+    config::LayerStacking,
     dispatcher::{ArtifactFetcher, BrokerSender, Cache as CacheTrait, Deps, Dispatcher, Message},
     dispatcher_adapter::DispatcherAdapter,
     executor::{MountDir, TmpfsDir},
-    types::DispatcherSender,
+    types::{Cache, DispatcherSender},
     WorkerCacheDir,
 };
 pub use maelstrom_layer_fs::BlobDir;
 pub use maelstrom_util::cache::{
     fs::std::{Fs, TempFile},
-    Cache, CacheDir, GetArtifact, GotArtifact,
+    CacheDir, GetArtifact, GotArtifact,
 };
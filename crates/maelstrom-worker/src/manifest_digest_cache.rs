@@ -1,10 +1,7 @@
 use crate::{dispatcher::Message, types::DispatcherSender};
 use anyhow::Result;
 use lru::LruCache;
-use maelstrom_base::{
-    manifest::{ManifestEntryData, ManifestFileData},
-    JobId, Sha256Digest,
-};
+use maelstrom_base::{manifest::ManifestEntryData, JobId, Sha256Digest};
 use maelstrom_util::{async_fs, manifest::AsyncManifestReader};
 use slog::debug;
 use std::{
@@ -21,8 +18,8 @@ async fn read_manifest(path: &Path) -> Result<HashSet<Sha256Digest>> {
     let mut reader = AsyncManifestReader::new(fs.open_file(path).await?).await?;
     let mut digests = HashSet::new();
     while let Some(entry) = reader.next().await? {
-        if let ManifestEntryData::File(ManifestFileData::Digest(digest)) = entry.data {
-            digests.insert(digest);
+        if let ManifestEntryData::File(data) = entry.data {
+            digests.extend(data.digests().cloned());
         }
     }
     Ok(digests)
@@ -0,0 +1,840 @@
+//! Layers for workers that stack them with overlayfs instead of serving them with LayerFS.
+//!
+//! A bottom layer is a directory with the layer's files unpacked into `root`, next to an empty
+//! directory, `empty`. An upper layer is a directory with just a `lowers` file, which lists the
+//! `root` directories of the bottom layers in its stack, one per line, from the top down.
+//!
+//! Directories are always unpacked with read, write, and search permission for their owner, no
+//! matter what the layer says. The worker needs these to fill the directories in, and to later
+//! remove them from the cache.
+//!
+//! Extended attributes are set on a best-effort basis, since the worker usually can't set `trusted`
+//! ones, for example. The exception is `security` ones, which jobs may depend on, so unpacking
+//! fails if one of those can't be set. Attributes in the `user.overlay` namespace are refused, since
+//! overlayfs would interpret them.
+//!
+//! The worker doesn't have the privileges to give files other owners, so manifests with entries
+//! that have an owner are refused.
+
+use anyhow::{anyhow, bail, Result};
+use maelstrom_base::{
+    manifest::{ManifestEntryData, ManifestFileData, UnixTimestamp, Xattrs},
+    ArtifactType, Sha256Digest, Utf8Component, Utf8Path,
+};
+use maelstrom_layer_fs::{BlobDir, BlobFetcher};
+use maelstrom_linux as linux;
+use maelstrom_util::{
+    cache::{
+        fs::{
+            std::{Fs as StdFs, TempDir as StdTempDir},
+            TempDir as _,
+        },
+        TempFileFactory,
+    },
+    fs::Fs,
+    manifest::{tar::entry_xattrs, ManifestReader},
+    root::Root,
+};
+use slog::{warn, Logger};
+use std::{
+    ffi::{CString, OsStr},
+    fs::{File, Permissions},
+    io::{self, BufReader, Read},
+    os::unix::{ffi::OsStrExt as _, fs::PermissionsExt as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tar::{Archive, EntryType};
+use tokio::{runtime, task};
+
+const ROOT: &str = "root";
+const EMPTY: &str = "empty";
+const LOWERS: &str = "lowers";
+
+/// Unpack the given artifact into a new bottom layer. File contents that a manifest refers to
+/// that aren't in `blob_dir` yet are fetched with `blob_fetcher`.
+pub async fn build_bottom_layer(
+    log: Logger,
+    temp_file_factory: TempFileFactory<StdFs>,
+    blob_dir: &Root<BlobDir>,
+    blob_fetcher: Arc<dyn BlobFetcher>,
+    artifact_type: ArtifactType,
+    artifact_path: PathBuf,
+) -> Result<(StdTempDir, u64)> {
+    let layer_temp_dir = temp_file_factory.temp_dir()?;
+    let layer_path = layer_temp_dir.path().to_owned();
+    let blob_dir = blob_dir.to_owned();
+    let runtime = runtime::Handle::current();
+    let size = task::spawn_blocking(move || {
+        unpack_bottom_layer(log, &layer_path, artifact_type, &artifact_path, |digest| {
+            let path = blob_dir.join::<BlobDir>(digest.to_string());
+            if !Fs::new().exists(&path) {
+                runtime.block_on(blob_fetcher.fetch_blob(digest))?;
+            }
+            Ok(File::open(path)?)
+        })
+    })
+    .await??;
+    Ok((layer_temp_dir, size))
+}
+
+/// Unpack the given artifact into a bottom layer in the empty directory `layer_path`, returning
+/// the number of bytes of file contents written. File contents that a manifest refers to are
+/// gotten from `open_blob`.
+pub fn unpack_bottom_layer<BlobT: Read>(
+    log: Logger,
+    layer_path: &Path,
+    artifact_type: ArtifactType,
+    artifact_path: &Path,
+    open_blob: impl FnMut(&Sha256Digest) -> Result<BlobT>,
+) -> Result<u64> {
+    let fs = Fs::new();
+    fs.create_dir(layer_path.join(EMPTY))?;
+    let mut unpacker = Unpacker::new(log, fs, layer_path.join(ROOT))?;
+    let artifact_file = BufReader::new(File::open(artifact_path)?);
+    match artifact_type {
+        ArtifactType::Tar => unpacker.unpack_tar(artifact_file)?,
+        ArtifactType::Manifest => unpacker.unpack_manifest(artifact_file, open_blob)?,
+    }
+    Ok(unpacker.size)
+}
+
+/// Create a new upper layer for the stack of `upper_layer_path` on top of `lower_layer_path`.
+pub async fn build_upper_layer(
+    temp_file_factory: TempFileFactory<StdFs>,
+    lower_layer_path: PathBuf,
+    upper_layer_path: PathBuf,
+) -> Result<(StdTempDir, u64)> {
+    let layer_temp_dir = temp_file_factory.temp_dir()?;
+    let layer_path = layer_temp_dir.path().to_owned();
+    let size = task::spawn_blocking(move || {
+        write_upper_layer(&layer_path, &lower_layer_path, &upper_layer_path)
+    })
+    .await??;
+    Ok((layer_temp_dir, size))
+}
+
+/// Create a new layer with the same contents as the stack at `stack_path`. Overlayfs looks through
+/// all of a stack's directories at once, so this is just a copy of the stack's list of directories.
+pub async fn build_compacted_layer(
+    temp_file_factory: TempFileFactory<StdFs>,
+    stack_path: PathBuf,
+) -> Result<(StdTempDir, u64)> {
+    let layer_temp_dir = temp_file_factory.temp_dir()?;
+    let layer_path = layer_temp_dir.path().to_owned();
+    let size =
+        task::spawn_blocking(move || write_lowers(&layer_path, stack_dirs(&stack_path)?)).await??;
+    Ok((layer_temp_dir, size))
+}
+
+/// Write an upper layer for the stack of `upper_layer_path` on top of `lower_layer_path` into the
+/// empty directory `layer_path`, returning the number of bytes written.
+fn write_upper_layer(
+    layer_path: &Path,
+    lower_layer_path: &Path,
+    upper_layer_path: &Path,
+) -> Result<u64> {
+    let mut dirs = stack_dirs(upper_layer_path)?;
+    dirs.extend(stack_dirs(lower_layer_path)?);
+    write_lowers(layer_path, dirs)
+}
+
+/// Write the `lowers` file listing `dirs` into `layer_path`, returning the number of bytes written.
+fn write_lowers(layer_path: &Path, dirs: Vec<PathBuf>) -> Result<u64> {
+    let mut lowers = String::new();
+    for dir in dirs {
+        let dir = dir
+            .to_str()
+            .ok_or_else(|| anyhow!("non-UTF-8 layer path {dir:?}"))?;
+        lowers.push_str(dir);
+        lowers.push('\n');
+    }
+    Fs::new().write(layer_path.join(LOWERS), &lowers)?;
+    Ok(lowers.len() as u64)
+}
+
+/// The unpacked directories of the layer stack at `layer_path`, from the top down.
+fn stack_dirs(layer_path: &Path) -> Result<Vec<PathBuf>> {
+    match Fs::new().read_to_string_if_exists(layer_path.join(LOWERS))? {
+        Some(lowers) => Ok(lowers.lines().map(PathBuf::from).collect()),
+        None => Ok(vec![layer_path.join(ROOT)]),
+    }
+}
+
+/// The lower directories of a read-only overlay file system for the layer stack at `layer_path`,
+/// from the top down.
+pub fn overlay_lower_dirs(layer_path: &Path) -> Result<Vec<CString>> {
+    let mut dirs = stack_dirs(layer_path)?;
+
+    // Without an upper directory, overlayfs wants at least two lower directories.
+    if let [dir] = &dirs[..] {
+        dirs.push(dir.with_file_name(EMPTY));
+    }
+    dirs.iter().map(|dir| c_path(dir)).collect()
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+fn system_time(timestamp: UnixTimestamp) -> Option<SystemTime> {
+    if timestamp.0 >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(timestamp.0 as u64))
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(Duration::from_secs(timestamp.0.unsigned_abs()))
+    }
+}
+
+struct Unpacker {
+    log: Logger,
+    fs: Fs,
+    root: PathBuf,
+    size: u64,
+}
+
+impl Unpacker {
+    fn new(log: Logger, fs: Fs, root: PathBuf) -> Result<Self> {
+        fs.create_dir(&root)?;
+        Ok(Self {
+            log,
+            fs,
+            root,
+            size: 0,
+        })
+    }
+
+    fn unpack_tar(&mut self, tar_stream: impl Read) -> Result<()> {
+        let mut ar = Archive::new(tar_stream);
+        for entry in ar.entries()? {
+            let mut entry = entry?;
+            let xattrs = entry_xattrs(&mut entry)?;
+            let header = entry.header().clone();
+            let entry_path = entry.path()?;
+            let path: &Utf8Path = entry_path
+                .to_str()
+                .ok_or_else(|| anyhow!("non-UTF8 path in tar"))?
+                .as_ref();
+            let path = path.to_owned();
+            match header.entry_type() {
+                EntryType::Regular => {
+                    let file_name = path.file_name().unwrap_or_default();
+                    if let Some(stripped_name) = file_name.strip_prefix(".wh.") {
+                        if file_name == ".wh..wh..opq" {
+                            self.set_opaque_dir(path.parent().unwrap_or(Utf8Path::new("")))?;
+                        } else {
+                            self.add_whiteout(&path.with_file_name(stripped_name))?;
+                        }
+                        continue;
+                    }
+                    self.add_file(
+                        &path,
+                        header.mode()?,
+                        UnixTimestamp(header.mtime()?.try_into()?),
+                        &xattrs,
+                        [Ok(entry)],
+                    )?;
+                }
+                EntryType::Directory => {
+                    self.add_dir(&path, header.mode()?, &xattrs)?;
+                }
+                EntryType::Symlink => {
+                    let link_name = entry
+                        .link_name_bytes()
+                        .ok_or_else(|| anyhow!("empty symlink in tar"))?;
+                    self.add_symlink(&path, &link_name, &xattrs)?;
+                }
+                EntryType::Link => {
+                    let link_name = entry
+                        .link_name_bytes()
+                        .ok_or_else(|| anyhow!("empty hardlink in tar"))?;
+                    self.add_hardlink(&path, std::str::from_utf8(&link_name)?.into())?;
+                }
+                other => {
+                    bail!("unsupported tar entry type {other:?}")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unpack_manifest<BlobT: Read>(
+        &mut self,
+        manifest_stream: impl Read + io::Seek,
+        mut open_blob: impl FnMut(&Sha256Digest) -> Result<BlobT>,
+    ) -> Result<()> {
+        for entry in ManifestReader::new(manifest_stream)? {
+            let entry = entry?;
+            if entry.metadata.owner.is_some() {
+                bail!(
+                    "can't unpack {:?} with an owner on a worker that stacks layers with overlayfs",
+                    entry.path
+                );
+            }
+            let mode = entry.metadata.mode.into();
+            let mtime = entry.metadata.mtime;
+            let xattrs = &entry.metadata.xattrs;
+            match entry.data {
+                ManifestEntryData::Directory { opaque } => {
+                    self.add_dir(&entry.path, mode, xattrs)?;
+                    if opaque {
+                        self.set_opaque_dir(&entry.path)?;
+                    }
+                }
+                ManifestEntryData::File(ManifestFileData::Digest(digest)) => {
+                    self.add_file(&entry.path, mode, mtime, xattrs, [open_blob(&digest)])?;
+                }
+                ManifestEntryData::File(ManifestFileData::Inline(data)) => {
+                    self.add_file(&entry.path, mode, mtime, xattrs, [Ok(&data[..])])?;
+                }
+                ManifestEntryData::File(ManifestFileData::Empty) => {
+                    self.add_file(&entry.path, mode, mtime, xattrs, [Ok(io::empty())])?;
+                }
+                ManifestEntryData::File(ManifestFileData::Chunked(chunks)) => {
+                    let blobs = chunks.iter().map(|chunk| open_blob(&chunk.digest));
+                    self.add_file(&entry.path, mode, mtime, xattrs, blobs)?;
+                }
+                ManifestEntryData::Symlink(link_target) => {
+                    self.add_symlink(&entry.path, &link_target, xattrs)?;
+                }
+                ManifestEntryData::Hardlink(link_target) => {
+                    self.add_hardlink(&entry.path, &link_target)?;
+                }
+                ManifestEntryData::Whiteout => {
+                    self.add_whiteout(&entry.path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return where the layer's `path` is unpacked to.
+    fn resolve(&self, path: &Utf8Path) -> Result<PathBuf> {
+        let mut resolved = self.root.clone();
+        for component in path.components() {
+            match component {
+                Utf8Component::RootDir | Utf8Component::CurDir => {}
+                Utf8Component::Normal(name) => resolved.push(name),
+                _ => bail!("invalid path {path:?} in layer"),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Return where the layer's `path` is unpacked to, creating its parent directories if they
+    /// don't exist yet, and removing anything other than a directory that's already there.
+    fn target(&self, path: &Utf8Path) -> Result<PathBuf> {
+        let target = self.resolve(path)?;
+        if target != self.root {
+            if let Some(parent) = target.parent() {
+                self.fs.create_dir_all(parent)?;
+            }
+            if let Ok(metadata) = self.fs.symlink_metadata(&target) {
+                if !metadata.is_dir() {
+                    self.fs.remove_file(&target)?;
+                }
+            }
+        }
+        Ok(target)
+    }
+
+    /// Set the extended attributes `xattrs` on `target`. This has to happen while the owner can
+    /// still write to it, since that's needed for `user` ones. Only failing to set `security` ones
+    /// is an error. Others, like `trusted` ones, can't be set without privileges, so they are
+    /// skipped with a warning.
+    fn set_xattrs(&self, target: &Path, xattrs: &Xattrs) -> Result<()> {
+        for (name, value) in xattrs {
+            if name.starts_with("user.overlay.") {
+                bail!("can't unpack overlayfs extended attribute {name} of {target:?}");
+            }
+            let result = linux::setxattr(&c_path(target)?, &CString::new(name.as_str())?, value);
+            if let Err(err) = result {
+                if name.starts_with("security.") {
+                    bail!("couldn't set extended attribute {name} of {target:?}: {err}");
+                }
+                warn!(self.log, "couldn't set extended attribute, skipping it";
+                    "name" => name, "path" => ?target, "error" => %err);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_dir(&mut self, path: &Utf8Path, mode: u32, xattrs: &Xattrs) -> Result<()> {
+        let target = self.target(path)?;
+        if !self.fs.exists(&target) {
+            self.fs.create_dir(&target)?;
+        }
+        self.fs
+            .set_permissions(&target, Permissions::from_mode((mode & 0o7777) | 0o700))?;
+        self.set_xattrs(&target, xattrs)?;
+        Ok(())
+    }
+
+    fn set_opaque_dir(&mut self, path: &Utf8Path) -> Result<()> {
+        let target = self.target(path)?;
+        if !self.fs.exists(&target) {
+            self.fs.create_dir(&target)?;
+        }
+        linux::setxattr(&c_path(&target)?, c"user.overlay.opaque", b"y")?;
+        Ok(())
+    }
+
+    /// Add a regular file whose contents are the concatenation of `contents`. Each part is only
+    /// opened once the ones before it have been copied.
+    fn add_file<ReadT: Read>(
+        &mut self,
+        path: &Utf8Path,
+        mode: u32,
+        mtime: UnixTimestamp,
+        xattrs: &Xattrs,
+        contents: impl IntoIterator<Item = Result<ReadT>>,
+    ) -> Result<()> {
+        let target = self.target(path)?;
+        let mut file = File::create(&target)?;
+        for part in contents {
+            self.size += io::copy(&mut part?, &mut file)?;
+        }
+        self.set_xattrs(&target, xattrs)?;
+        if let Some(mtime) = system_time(mtime) {
+            file.set_modified(mtime)?;
+        }
+        file.set_permissions(Permissions::from_mode(mode & 0o7777))?;
+        Ok(())
+    }
+
+    /// Add a symbolic link. Its extended attributes can't be set, since that would follow the link,
+    /// so this fails if it has any that have to be.
+    fn add_symlink(&mut self, path: &Utf8Path, link_target: &[u8], xattrs: &Xattrs) -> Result<()> {
+        if let Some(name) = xattrs.keys().find(|name| name.starts_with("security.")) {
+            bail!("can't set extended attribute {name} of symlink {path}");
+        }
+        let target = self.target(path)?;
+        self.fs.symlink(OsStr::from_bytes(link_target), target)?;
+        Ok(())
+    }
+
+    fn add_hardlink(&mut self, path: &Utf8Path, link_target: &Utf8Path) -> Result<()> {
+        let link_target = self.resolve(link_target)?;
+        let target = self.target(path)?;
+        self.fs.hard_link(link_target, target)?;
+        Ok(())
+    }
+
+    fn add_whiteout(&mut self, path: &Utf8Path) -> Result<()> {
+        let target = self.target(path)?;
+        if self.fs.exists(&target) {
+            self.fs.remove_dir_all(&target)?;
+        }
+        linux::mknod_whiteout(&c_path(&target)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maelstrom_base::{
+        manifest::{ManifestChunk, ManifestEntry, ManifestEntryMetadata, Mode, Owner},
+        GroupId, UserId,
+    };
+    use maelstrom_test::digest;
+    use maelstrom_util::{
+        log::test_logger,
+        manifest::{tar::pax_xattr_records, ManifestWriter},
+    };
+    use std::{
+        collections::HashMap,
+        ffi::CStr,
+        os::unix::fs::{FileTypeExt as _, MetadataExt as _},
+    };
+    use tar::{Builder, Header};
+    use tempfile::TempDir;
+
+    fn tar_header(entry_type: EntryType, mode: u32, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_mtime(1705000271);
+        header
+    }
+
+    fn build_tar(path: &Path, build: impl FnOnce(&mut Builder<File>)) {
+        let mut builder = Builder::new(File::create(path).unwrap());
+        build(&mut builder);
+        builder.finish().unwrap();
+    }
+
+    fn unpack_tar(build: impl FnOnce(&mut Builder<File>)) -> (TempDir, PathBuf, Result<u64>) {
+        let temp_dir = TempDir::new().unwrap();
+        let tar_path = temp_dir.path().join("layer.tar");
+        build_tar(&tar_path, build);
+        let layer_path = temp_dir.path().join("layer");
+        Fs::new().create_dir(&layer_path).unwrap();
+        let result = unpack_bottom_layer(
+            test_logger(),
+            &layer_path,
+            ArtifactType::Tar,
+            &tar_path,
+            |_| Ok::<_, anyhow::Error>(io::empty()),
+        );
+        let root = layer_path.join(ROOT);
+        (temp_dir, root, result)
+    }
+
+    fn mode(path: &Path) -> u32 {
+        Fs::new()
+            .symlink_metadata(path)
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o7777
+    }
+
+    #[test]
+    fn unpack_tar_files_dirs_and_links() {
+        let (_temp_dir, root, result) = unpack_tar(|builder| {
+            let mut header = tar_header(EntryType::Directory, 0o555, 0);
+            builder
+                .append_data(&mut header, "dir", io::empty())
+                .unwrap();
+            let mut header = tar_header(EntryType::Regular, 0o444, 5);
+            builder
+                .append_data(&mut header, "dir/file", &b"hello"[..])
+                .unwrap();
+            let mut header = tar_header(EntryType::Symlink, 0o777, 0);
+            builder
+                .append_link(&mut header, "symlink", "dir/file")
+                .unwrap();
+            let mut header = tar_header(EntryType::Link, 0o444, 0);
+            builder
+                .append_link(&mut header, "hardlink", "dir/file")
+                .unwrap();
+            let mut header = tar_header(EntryType::Regular, 0o644, 3);
+            builder
+                .append_data(&mut header, "a/b/c", &b"abc"[..])
+                .unwrap();
+        });
+        assert_eq!(result.unwrap(), 8);
+
+        let fs = Fs::new();
+        assert_eq!(mode(&root.join("dir")), 0o755);
+        assert_eq!(mode(&root.join("dir/file")), 0o444);
+        assert_eq!(fs.read_to_string(root.join("dir/file")).unwrap(), "hello");
+        assert_eq!(
+            fs.metadata(root.join("dir/file"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1705000271)
+        );
+        assert_eq!(
+            fs.read_link(root.join("symlink")).unwrap(),
+            Path::new("dir/file")
+        );
+        assert_eq!(
+            fs.metadata(root.join("hardlink"))
+                .unwrap()
+                .into_inner()
+                .ino(),
+            fs.metadata(root.join("dir/file"))
+                .unwrap()
+                .into_inner()
+                .ino()
+        );
+        assert_eq!(fs.read_to_string(root.join("a/b/c")).unwrap(), "abc");
+    }
+
+    #[test]
+    fn unpack_tar_whiteouts() {
+        let (_temp_dir, root, result) = unpack_tar(|builder| {
+            let mut header = tar_header(EntryType::Regular, 0o644, 0);
+            builder
+                .append_data(&mut header, "dir/.wh.gone", io::empty())
+                .unwrap();
+            let mut header = tar_header(EntryType::Regular, 0o644, 0);
+            builder
+                .append_data(&mut header, "opaque/.wh..wh..opq", io::empty())
+                .unwrap();
+        });
+        result.unwrap();
+
+        let fs = Fs::new();
+        let metadata = fs
+            .symlink_metadata(root.join("dir/gone"))
+            .unwrap()
+            .into_inner();
+        assert!(metadata.file_type().is_char_device());
+        assert_eq!(metadata.rdev(), 0);
+        assert!(!fs.exists(root.join("dir/.wh.gone")));
+        assert!(fs.metadata(root.join("opaque")).unwrap().is_dir());
+        assert!(!fs.exists(root.join("opaque/.wh..wh..opq")));
+    }
+
+    fn xattr(path: &Path, name: &CStr) -> Vec<u8> {
+        let mut value = vec![0; 64];
+        let len = linux::getxattr(&c_path(path).unwrap(), name, &mut value).unwrap();
+        value.truncate(len);
+        value
+    }
+
+    fn append_xattrs(builder: &mut Builder<File>, xattrs: &Xattrs) {
+        let records = pax_xattr_records(xattrs);
+        let mut header = tar_header(EntryType::XHeader, 0o644, records.len() as u64);
+        builder
+            .append_data(&mut header, "././@PaxHeader", &records[..])
+            .unwrap();
+    }
+
+    #[test]
+    fn unpack_tar_xattrs() {
+        let (_temp_dir, root, result) = unpack_tar(|builder| {
+            append_xattrs(builder, &Xattrs::from([("user.a".into(), b"1".to_vec())]));
+            let mut header = tar_header(EntryType::Directory, 0o555, 0);
+            builder
+                .append_data(&mut header, "dir", io::empty())
+                .unwrap();
+            append_xattrs(builder, &Xattrs::from([("user.b".into(), b"2".to_vec())]));
+            let mut header = tar_header(EntryType::Regular, 0o444, 5);
+            builder
+                .append_data(&mut header, "dir/file", &b"hello"[..])
+                .unwrap();
+        });
+        result.unwrap();
+
+        assert_eq!(xattr(&root.join("dir"), c"user.a"), b"1");
+        assert_eq!(xattr(&root.join("dir/file"), c"user.b"), b"2");
+        assert_eq!(mode(&root.join("dir/file")), 0o444);
+    }
+
+    #[test]
+    fn unpack_tar_rejects_overlay_xattrs() {
+        let (_temp_dir, _root, result) = unpack_tar(|builder| {
+            append_xattrs(
+                builder,
+                &Xattrs::from([("user.overlay.opaque".into(), b"y".to_vec())]),
+            );
+            let mut header = tar_header(EntryType::Directory, 0o755, 0);
+            builder
+                .append_data(&mut header, "dir", io::empty())
+                .unwrap();
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unpack_tar_rejects_parent_dir_paths() {
+        let (_temp_dir, _root, result) = unpack_tar(|builder| {
+            let mut header = tar_header(EntryType::Regular, 0o644, 3);
+            header.as_gnu_mut().unwrap().name[..6].copy_from_slice(b"../foo");
+            header.set_cksum();
+            builder.append(&header, &b"foo"[..]).unwrap();
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unpack_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join("layer.manifest");
+        let metadata = |size, mode| ManifestEntryMetadata {
+            size,
+            mode: Mode(mode),
+            mtime: UnixTimestamp(1705000271),
+            xattrs: Default::default(),
+            owner: None,
+        };
+        let mut writer = ManifestWriter::new(File::create(&manifest_path).unwrap()).unwrap();
+        writer
+            .write_entries(&[
+                ManifestEntry {
+                    path: "dir".into(),
+                    metadata: metadata(0, 0o755),
+                    data: ManifestEntryData::Directory { opaque: false },
+                },
+                ManifestEntry {
+                    path: "dir/digest".into(),
+                    metadata: metadata(6, 0o755),
+                    data: ManifestEntryData::File(ManifestFileData::Digest(digest!(1))),
+                },
+                ManifestEntry {
+                    path: "dir/inline".into(),
+                    metadata: metadata(6, 0o644),
+                    data: ManifestEntryData::File(ManifestFileData::Inline(b"inline".to_vec())),
+                },
+                ManifestEntry {
+                    path: "dir/empty".into(),
+                    metadata: metadata(0, 0o600),
+                    data: ManifestEntryData::File(ManifestFileData::Empty),
+                },
+                ManifestEntry {
+                    path: "symlink".into(),
+                    metadata: metadata(0, 0o777),
+                    data: ManifestEntryData::Symlink(b"dir/digest".to_vec()),
+                },
+                ManifestEntry {
+                    path: "gone".into(),
+                    metadata: metadata(0, 0o644),
+                    data: ManifestEntryData::Whiteout,
+                },
+            ])
+            .unwrap();
+        drop(writer);
+
+        let blobs = HashMap::from([(digest!(1), b"digest".to_vec())]);
+        let layer_path = temp_dir.path().join("layer");
+        let fs = Fs::new();
+        fs.create_dir(&layer_path).unwrap();
+        let size = unpack_bottom_layer(
+            test_logger(),
+            &layer_path,
+            ArtifactType::Manifest,
+            &manifest_path,
+            |digest| Ok(&blobs[digest][..]),
+        )
+        .unwrap();
+        assert_eq!(size, 12);
+
+        let root = layer_path.join(ROOT);
+        assert_eq!(
+            fs.read_to_string(root.join("dir/digest")).unwrap(),
+            "digest"
+        );
+        assert_eq!(mode(&root.join("dir/digest")), 0o755);
+        assert_eq!(
+            fs.read_to_string(root.join("dir/inline")).unwrap(),
+            "inline"
+        );
+        assert_eq!(fs.read_to_string(root.join("dir/empty")).unwrap(), "");
+        assert_eq!(mode(&root.join("dir/empty")), 0o600);
+        assert_eq!(
+            fs.read_link(root.join("symlink")).unwrap(),
+            Path::new("dir/digest")
+        );
+        assert!(fs
+            .symlink_metadata(root.join("gone"))
+            .unwrap()
+            .file_type()
+            .is_char_device());
+        assert!(fs.exists(layer_path.join(EMPTY)));
+    }
+
+    #[test]
+    fn unpack_chunked_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join("layer.manifest");
+        let mut writer = ManifestWriter::new(File::create(&manifest_path).unwrap()).unwrap();
+        writer
+            .write_entry(&ManifestEntry {
+                path: "chunked".into(),
+                metadata: ManifestEntryMetadata {
+                    size: 11,
+                    mode: Mode(0o644),
+                    mtime: UnixTimestamp(1705000271),
+                    xattrs: Default::default(),
+                    owner: None,
+                },
+                data: ManifestEntryData::File(ManifestFileData::Chunked(vec![
+                    ManifestChunk {
+                        digest: digest!(1),
+                        size: 6,
+                    },
+                    ManifestChunk {
+                        digest: digest!(2),
+                        size: 5,
+                    },
+                ])),
+            })
+            .unwrap();
+        drop(writer);
+
+        let blobs = HashMap::from([
+            (digest!(1), b"hello ".to_vec()),
+            (digest!(2), b"world".to_vec()),
+        ]);
+        let layer_path = temp_dir.path().join("layer");
+        let fs = Fs::new();
+        fs.create_dir(&layer_path).unwrap();
+        let size = unpack_bottom_layer(
+            test_logger(),
+            &layer_path,
+            ArtifactType::Manifest,
+            &manifest_path,
+            |digest| Ok(&blobs[digest][..]),
+        )
+        .unwrap();
+        assert_eq!(size, 11);
+        assert_eq!(
+            fs.read_to_string(layer_path.join(ROOT).join("chunked"))
+                .unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn unpack_manifest_rejects_owned_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join("layer.manifest");
+        let mut writer = ManifestWriter::new(File::create(&manifest_path).unwrap()).unwrap();
+        writer
+            .write_entry(&ManifestEntry {
+                path: "owned".into(),
+                metadata: ManifestEntryMetadata {
+                    size: 0,
+                    mode: Mode(0o644),
+                    mtime: UnixTimestamp(1705000271),
+                    xattrs: Default::default(),
+                    owner: Some(Owner {
+                        user: UserId::new(0),
+                        group: GroupId::new(0),
+                    }),
+                },
+                data: ManifestEntryData::File(ManifestFileData::Empty),
+            })
+            .unwrap();
+        drop(writer);
+
+        let layer_path = temp_dir.path().join("layer");
+        Fs::new().create_dir(&layer_path).unwrap();
+        let result = unpack_bottom_layer(
+            test_logger(),
+            &layer_path,
+            ArtifactType::Manifest,
+            &manifest_path,
+            |_| Ok::<_, anyhow::Error>(io::empty()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn upper_layer_lowers() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Fs::new();
+        let layer = |name| {
+            let path = temp_dir.path().join(name);
+            fs.create_dir(&path).unwrap();
+            path
+        };
+        let (a, b, c) = (layer("a"), layer("b"), layer("c"));
+        let (ab, abc) = (layer("ab"), layer("abc"));
+
+        assert_eq!(
+            overlay_lower_dirs(&a).unwrap(),
+            [
+                c_path(&a.join(ROOT)).unwrap(),
+                c_path(&a.join(EMPTY)).unwrap()
+            ]
+        );
+
+        write_upper_layer(&ab, &a, &b).unwrap();
+        write_upper_layer(&abc, &ab, &c).unwrap();
+        assert_eq!(
+            overlay_lower_dirs(&abc).unwrap(),
+            [
+                c_path(&c.join(ROOT)).unwrap(),
+                c_path(&b.join(ROOT)).unwrap(),
+                c_path(&a.join(ROOT)).unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Finding the range of subordinate gids that the worker's user owns, and mapping jobs'
+//! supplementary groups into it.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use maelstrom_base::GroupId;
+use maelstrom_linux::Pid;
+use std::{fmt::Write as _, fs, io, process::Command};
+
+/// A range of host gids, from `/etc/subgid`, that belong to the worker's user. Jobs' supplementary
+/// groups are mapped into this range so that they never pick up the privileges of real host
+/// groups.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SubordinateGids {
+    pub start: u32,
+    pub count: u32,
+}
+
+impl SubordinateGids {
+    /// Return the first range in `/etc/subgid` for the user with the given uid, if there is one.
+    pub fn for_user(uid: u32) -> Result<Option<Self>> {
+        let subgid = match fs::read_to_string("/etc/subgid") {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            subgid => subgid.context("reading /etc/subgid")?,
+        };
+        let passwd = match fs::read_to_string("/etc/passwd") {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            passwd => passwd.context("reading /etc/passwd")?,
+        };
+        let name = user_name(&passwd, uid);
+        parse(&subgid, uid, name.as_deref()).context("parsing /etc/subgid")
+    }
+
+    /// Map a job's primary group to `group`, the worker's gid, and its supplementary groups to the
+    /// start of this range. Returns the contents to write to the child's `gid_map`.
+    pub fn gid_map(
+        &self,
+        primary: GroupId,
+        group: GroupId,
+        supplementary: &[GroupId],
+    ) -> Result<String> {
+        if supplementary.len() > self.count as usize {
+            bail!(
+                "job has {} supplementary groups, but the worker only has {} subordinate gids",
+                supplementary.len(),
+                self.count
+            );
+        }
+        let mut gid_map = String::new();
+        writeln!(gid_map, "{primary} {group} 1").unwrap();
+        for (outside, inside) in (self.start..).zip(supplementary) {
+            writeln!(gid_map, "{inside} {outside} 1").unwrap();
+        }
+        Ok(gid_map)
+    }
+}
+
+/// Write `gid_map` for the child. If we don't have CAP_SETGID, we have `newgidmap` do it for us.
+/// It's setuid root, and checks that the gids we map belong to us.
+pub fn write_gid_map(child_pid: Pid, gid_map: &str) -> Result<()> {
+    match fs::write(format!("/proc/{child_pid}/gid_map"), gid_map) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {}
+        result => return result.context("writing gid_map"),
+    }
+    let output = Command::new("newgidmap")
+        .arg(child_pid.as_i32().to_string())
+        .args(gid_map.split_whitespace())
+        .output()
+        .context("running newgidmap")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "newgidmap failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn user_name(passwd: &str, uid: u32) -> Option<String> {
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        (fields.nth(1)?.parse() == Ok(uid)).then(|| name.to_owned())
+    })
+}
+
+fn parse(subgid: &str, uid: u32, name: Option<&str>) -> Result<Option<SubordinateGids>> {
+    for line in subgid.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let [owner, start, count] = line.split(':').collect::<Vec<_>>()[..] else {
+            bail!("malformed line {line:?}");
+        };
+        if Some(owner) == name || owner.parse() == Ok(uid) {
+            return Ok(Some(SubordinateGids {
+                start: start.parse().with_context(|| format!("in line {line:?}"))?,
+                count: count.parse().with_context(|| format!("in line {line:?}"))?,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_by_name_and_uid() {
+        let subgid = "alice:100000:65536\n# comment\n\n1001:165536:1000\n";
+        assert_eq!(
+            parse(subgid, 1000, Some("alice")).unwrap(),
+            Some(SubordinateGids {
+                start: 100000,
+                count: 65536
+            })
+        );
+        assert_eq!(
+            parse(subgid, 1001, Some("bob")).unwrap(),
+            Some(SubordinateGids {
+                start: 165536,
+                count: 1000
+            })
+        );
+        assert_eq!(parse(subgid, 1002, Some("carol")).unwrap(), None);
+        assert_eq!(parse(subgid, 1002, None).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        parse("alice:100000\n", 1000, Some("alice")).unwrap_err();
+        parse("alice:x:1\n", 1000, Some("alice")).unwrap_err();
+    }
+
+    #[test]
+    fn user_name_from_passwd() {
+        let passwd = "root:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n";
+        assert_eq!(user_name(passwd, 1000).as_deref(), Some("alice"));
+        assert_eq!(user_name(passwd, 0).as_deref(), Some("root"));
+        assert_eq!(user_name(passwd, 1), None);
+    }
+
+    #[test]
+    fn gid_map() {
+        let range = SubordinateGids {
+            start: 100000,
+            count: 2,
+        };
+        assert_eq!(
+            range
+                .gid_map(
+                    GroupId::new(0),
+                    GroupId::new(1000),
+                    &[GroupId::new(6), GroupId::new(27)]
+                )
+                .unwrap(),
+            "0 1000 1\n6 100000 1\n27 100001 1\n"
+        );
+        range
+            .gid_map(
+                GroupId::new(0),
+                GroupId::new(1000),
+                &[GroupId::new(1), GroupId::new(2), GroupId::new(3)],
+            )
+            .unwrap_err();
+    }
+}
@@ -1,9 +1,17 @@
-use crate::{artifact_fetcher::ArtifactFetcher, dispatcher, dispatcher_adapter::DispatcherAdapter};
+use crate::{
+    artifact_fetcher::ArtifactFetcher, dispatcher, dispatcher_adapter::DispatcherAdapter, volume,
+};
 use maelstrom_base::{
     proto::{BrokerToWorker, WorkerToBroker},
     JobId, Sha256Digest,
 };
-use maelstrom_util::cache::{self, fs::std::Fs as StdFs, GotArtifact};
+use maelstrom_util::{
+    cache::{self, fs::std::Fs as StdFs, CacheDir, GotArtifact},
+    config::common::CacheSize,
+    fs::Fs,
+    root::RootBuf,
+};
+use slog::Logger;
 use std::path::PathBuf;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
@@ -15,6 +23,8 @@ pub enum CacheKeyKind {
     Blob,
     BottomFsLayer,
     UpperFsLayer,
+    CompactedFsLayer,
+    Volume,
 }
 
 impl cache::KeyKind for CacheKeyKind {
@@ -33,11 +43,52 @@ impl cache::GetStrategy for CacheGetStrategy {
 }
 
 pub type CacheKey = cache::Key<CacheKeyKind>;
-pub type Cache = cache::Cache<StdFs, CacheKeyKind, CacheGetStrategy>;
 pub type TempFile = cache::fs::std::TempFile;
 pub type TempFileFactory = cache::TempFileFactory<StdFs>;
 
-/// The standard implementation of [`Cache`] that just calls into [`cache::Cache`].
+pub type VolumeTempFileFactory = cache::TempFileFactory<volume::Fs>;
+
+type ArtifactCache = cache::Cache<StdFs, CacheKeyKind, CacheGetStrategy>;
+type VolumeCache = cache::Cache<volume::Fs, CacheKeyKind, CacheGetStrategy>;
+
+/// The standard implementation of [`dispatcher::Cache`]. Job volumes are kept in a separate
+/// [`cache::Cache`] from artifacts and FS layers so that they can be given their own size budget.
+/// Calls for [`CacheKeyKind::Volume`] are routed to the volume cache, and all others are routed to
+/// the artifact cache.
+pub struct Cache {
+    artifacts: ArtifactCache,
+    volumes: VolumeCache,
+}
+
+impl Cache {
+    /// Create a new [`Cache`]. Returns the cache, along with a [`TempFileFactory`] for artifacts
+    /// and a [`VolumeTempFileFactory`] for volumes. Temporary files and directories must be
+    /// created with the factory for the cache they will be inserted into.
+    ///
+    /// The contents of volumes are lost when the process exits, so the volume cache is cleared.
+    pub fn new(
+        artifacts_root: RootBuf<CacheDir>,
+        artifacts_size: CacheSize,
+        volumes_root: RootBuf<CacheDir>,
+        volumes_size: CacheSize,
+        log: Logger,
+    ) -> anyhow::Result<(Self, TempFileFactory, VolumeTempFileFactory)> {
+        let (artifacts, artifacts_temp_file_factory) =
+            ArtifactCache::new(StdFs, artifacts_root, artifacts_size, log.clone())?;
+        let fs = Fs::new();
+        if fs.exists(&volumes_root) {
+            fs.remove_dir_all(&volumes_root)?;
+        }
+        let (volumes, volumes_temp_file_factory) =
+            VolumeCache::new(volume::Fs, volumes_root, volumes_size, log)?;
+        Ok((
+            Self { artifacts, volumes },
+            artifacts_temp_file_factory,
+            volumes_temp_file_factory,
+        ))
+    }
+}
+
 impl dispatcher::Cache for Cache {
     type Fs = StdFs;
 
@@ -47,11 +98,24 @@ impl dispatcher::Cache for Cache {
         artifact: Sha256Digest,
         jid: JobId,
     ) -> cache::GetArtifact {
-        self.get_artifact(kind, artifact, jid)
+        match kind {
+            CacheKeyKind::Volume => self.volumes.get_artifact(kind, artifact, jid),
+            _ => self.artifacts.get_artifact(kind, artifact, jid),
+        }
+    }
+
+    fn get_artifact_if_present(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) -> bool {
+        match kind {
+            CacheKeyKind::Volume => self.volumes.get_artifact_if_present(kind, digest),
+            _ => self.artifacts.get_artifact_if_present(kind, digest),
+        }
     }
 
     fn got_artifact_failure(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) -> Vec<JobId> {
-        self.got_artifact_failure(kind, digest)
+        match kind {
+            CacheKeyKind::Volume => self.volumes.got_artifact_failure(kind, digest),
+            _ => self.artifacts.got_artifact_failure(kind, digest),
+        }
     }
 
     fn got_artifact_success(
@@ -60,15 +124,42 @@ impl dispatcher::Cache for Cache {
         digest: &Sha256Digest,
         artifact: GotArtifact<StdFs>,
     ) -> Result<Vec<JobId>, (anyhow::Error, Vec<JobId>)> {
-        self.got_artifact_success(kind, digest, artifact)
+        match kind {
+            CacheKeyKind::Volume => {
+                // The two file systems use the same temporary files and directories.
+                let artifact = match artifact {
+                    GotArtifact::Symlink { target } => GotArtifact::Symlink { target },
+                    GotArtifact::File { source } => GotArtifact::File { source },
+                    GotArtifact::Directory { source, size } => {
+                        GotArtifact::Directory { source, size }
+                    }
+                };
+                self.volumes.got_artifact_success(kind, digest, artifact)
+            }
+            _ => self.artifacts.got_artifact_success(kind, digest, artifact),
+        }
     }
 
     fn decrement_ref_count(&mut self, kind: CacheKeyKind, digest: &Sha256Digest) {
-        self.decrement_ref_count(kind, digest)
+        match kind {
+            CacheKeyKind::Volume => self.volumes.decrement_ref_count(kind, digest),
+            _ => self.artifacts.decrement_ref_count(kind, digest),
+        }
     }
 
     fn cache_path(&self, kind: CacheKeyKind, digest: &Sha256Digest) -> PathBuf {
-        self.cache_path(kind, digest).into_path_buf()
+        match kind {
+            CacheKeyKind::Volume => self.volumes.cache_path(kind, digest),
+            _ => self.artifacts.cache_path(kind, digest),
+        }
+        .into_path_buf()
+    }
+
+    fn is_over_target(&self, kind: CacheKeyKind) -> bool {
+        match kind {
+            CacheKeyKind::Volume => self.volumes.is_over_target(),
+            _ => self.artifacts.is_over_target(),
+        }
     }
 }
 
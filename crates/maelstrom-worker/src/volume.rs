@@ -0,0 +1,120 @@
+//! Job volumes.
+//!
+//! A volume is a directory in the volume cache with a tmpfs mounted on its `data` subdirectory.
+//! The tmpfs is created with the volume's size limit, so jobs writing to the volume get `ENOSPC`
+//! once it's full. Jobs bind mount the `data` subdirectory.
+//!
+//! The tmpfs is mounted in the worker's own mount namespace, so volumes don't outlive the worker.
+//! The volume cache is cleared when the worker starts, and the volume cache's [`Fs`] unmounts a
+//! volume's tmpfs before removing its directory.
+
+use anyhow::Result;
+use maelstrom_linux::{self as linux, UmountFlags};
+use maelstrom_util::cache::fs::{self, std::Fs as StdFs, Metadata};
+use std::{
+    ffi::{CString, OsString},
+    io,
+    os::unix::ffi::OsStrExt as _,
+    path::{Path, PathBuf},
+};
+
+/// The subdirectory of a volume's directory that holds its contents.
+pub const DATA_DIR: &str = "data";
+
+/// Create the `data` subdirectory in the new volume directory `path`, and mount a tmpfs limited to
+/// `size` bytes on it.
+pub fn mount_data_dir(path: &Path, size: u64) -> Result<()> {
+    let data_dir = path.join(DATA_DIR);
+    std::fs::create_dir(&data_dir)?;
+    let data_dir = CString::new(data_dir.as_os_str().as_bytes())?;
+    let options = format!("size={size},mode=0700");
+    linux::mount(
+        Some(c"tmpfs"),
+        &data_dir,
+        Some(c"tmpfs"),
+        linux::MountFlags::default(),
+        Some(CString::new(options)?.as_bytes_with_nul()),
+    )?;
+    Ok(())
+}
+
+/// The [`fs::Fs`] used by the volume cache. It's the same as the standard one, except that it
+/// unmounts a volume's tmpfs before removing the volume's directory.
+#[derive(Clone)]
+pub struct Fs;
+
+impl fs::Fs for Fs {
+    type Error = io::Error;
+
+    fn rand_u64(&self) -> u64 {
+        StdFs.rand_u64()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Option<Metadata>> {
+        StdFs.metadata(path)
+    }
+
+    fn read_file(&self, path: &Path, contents: &mut [u8]) -> io::Result<usize> {
+        StdFs.read_file(path, contents)
+    }
+
+    fn read_dir(
+        &self,
+        path: &Path,
+    ) -> io::Result<impl Iterator<Item = io::Result<(OsString, Metadata)>>> {
+        StdFs.read_dir(path)
+    }
+
+    fn create_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        StdFs.create_file(path, contents)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        StdFs.symlink(target, link)
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        StdFs.mkdir(path)
+    }
+
+    fn mkdir_recursively(&self, path: &Path) -> io::Result<()> {
+        StdFs.mkdir_recursively(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        StdFs.remove(path)
+    }
+
+    fn rmdir_recursively_on_thread(&self, path: PathBuf) -> io::Result<()> {
+        // Jobs that still have the tmpfs bind mounted keep it alive, so it's fine to detach it.
+        // If there is no tmpfs, because the directory isn't a volume or its creation failed
+        // part way through, this fails, and we can just remove the directory.
+        let data_dir = CString::new(path.join(DATA_DIR).as_os_str().as_bytes())?;
+        let _ = linux::umount2(&data_dir, UmountFlags::DETACH);
+        StdFs.rmdir_recursively_on_thread(path)
+    }
+
+    fn rename(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        StdFs.rename(source, destination)
+    }
+
+    type TempFile = <StdFs as fs::Fs>::TempFile;
+
+    fn temp_file(&self, parent: &Path) -> io::Result<Self::TempFile> {
+        StdFs.temp_file(parent)
+    }
+
+    fn persist_temp_file(&self, temp_file: Self::TempFile, target: &Path) -> io::Result<()> {
+        StdFs.persist_temp_file(temp_file, target)
+    }
+
+    type TempDir = <StdFs as fs::Fs>::TempDir;
+
+    fn temp_dir(&self, parent: &Path) -> io::Result<Self::TempDir> {
+        StdFs.temp_dir(parent)
+    }
+
+    fn persist_temp_dir(&self, temp_dir: Self::TempDir, target: &Path) -> io::Result<()> {
+        StdFs.persist_temp_dir(temp_dir, target)
+    }
+}